@@ -1,11 +1,43 @@
+//! OAuth token handling and storage.
+//!
+//! With the `keyring` feature the token goes to the OS keyring (Secret
+//! Service, macOS Keychain, Windows Credential Manager). Without it, when
+//! the keyring is unavailable, or with [`TOKEN_STORAGE_ENV`] set to
+//! `file`, it goes to `token.json` in the config directory, readable by
+//! the owner only. A token file left from before is moved to the keyring.
+//!
+//! Instead of the user pasting the redirect URL, a [`RedirectListener`]
+//! on a local port can catch it.
+
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::VkClient;
+use crate::error::{ERROR_ACCESS_DENIED, ERROR_PERMISSION_DENIED, Error};
 
 const VK_APP_ID: &str = "6287487"; // Standalone app ID (Kate Mobile)
 const VK_AUTH_URL: &str = "https://oauth.vk.com/authorize";
 const VK_API_VERSION: &str = "5.199";
 
+/// Permission bits of `account.getAppPermissions`.
+pub const SCOPE_FRIENDS: i64 = 1 << 1;
+pub const SCOPE_PHOTOS: i64 = 1 << 2;
+pub const SCOPE_MESSAGES: i64 = 1 << 12;
+pub const SCOPE_OFFLINE: i64 = 1 << 16;
+
+/// Scopes the clients cannot work without, with their names in the auth
+/// URL.
+pub const REQUIRED_SCOPES: &[(i64, &str)] = &[(SCOPE_MESSAGES, "messages")];
+
+/// Port of the local redirect catcher unless configured otherwise.
+pub const DEFAULT_REDIRECT_PORT: u16 = 8910;
+
+/// Set to `file` to keep the token in the token file even when a keyring
+/// is available, e.g. on headless machines without a Secret Service.
+pub const TOKEN_STORAGE_ENV: &str = "VK_TUI_TOKEN_STORAGE";
+
 /// Token data stored on disk
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TokenData {
@@ -14,9 +46,95 @@ pub struct TokenData {
     pub expires_at: Option<i64>,
 }
 
+/// Names of the [`REQUIRED_SCOPES`] missing from permission `mask`.
+pub fn missing_scopes(mask: i64) -> Vec<&'static str> {
+    REQUIRED_SCOPES
+        .iter()
+        .filter(|(bit, _)| mask & bit == 0)
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+/// Check that the token of `client` has the [`REQUIRED_SCOPES`], failing
+/// with [`Error::MissingScopes`] otherwise. Besides the permission mask,
+/// an empty `messages.getConversations` confirms VK lets the token read
+/// messages; that is what fails later with "Access denied".
+pub async fn validate_scopes(client: &VkClient) -> crate::Result<()> {
+    let missing = missing_scopes(client.account().get_app_permissions().await?);
+    if !missing.is_empty() {
+        return Err(Error::MissingScopes(missing));
+    }
+
+    match client.messages().get_conversations(0, 0).await {
+        Ok(_) => Ok(()),
+        Err(e)
+            if matches!(
+                e.code(),
+                Some(ERROR_PERMISSION_DENIED | ERROR_ACCESS_DENIED)
+            ) =>
+        {
+            Err(Error::MissingScopes(vec!["messages"]))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Secure storage for the serialized token.
+pub trait SecretStore: Send + Sync {
+    /// Stored secret, `None` if there is none
+    fn get(&self) -> Result<Option<String>>;
+    fn set(&self, secret: &str) -> Result<()>;
+    /// Remove the secret; removing a missing one is not an error
+    fn delete(&self) -> Result<()>;
+}
+
+/// Token entry in the OS keyring.
+#[cfg(feature = "keyring")]
+struct KeyringStore(keyring::Entry);
+
+#[cfg(feature = "keyring")]
+impl SecretStore for KeyringStore {
+    fn get(&self) -> Result<Option<String>> {
+        match self.0.get_password() {
+            Ok(secret) => Ok(Some(secret)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn set(&self, secret: &str) -> Result<()> {
+        Ok(self.0.set_password(secret)?)
+    }
+
+    fn delete(&self) -> Result<()> {
+        match self.0.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// The OS keyring, unless disabled with [`TOKEN_STORAGE_ENV`] or built
+/// without the `keyring` feature.
+fn default_secret_store() -> Option<Box<dyn SecretStore>> {
+    if std::env::var(TOKEN_STORAGE_ENV).is_ok_and(|v| v.eq_ignore_ascii_case("file")) {
+        return None;
+    }
+    #[cfg(feature = "keyring")]
+    {
+        match keyring::Entry::new("vk_tui", "access_token") {
+            Ok(entry) => return Some(Box::new(KeyringStore(entry))),
+            Err(e) => tracing::warn!("Keyring unavailable: {}", e),
+        }
+    }
+    None
+}
+
 /// Authentication manager
 pub struct AuthManager {
     config_path: PathBuf,
+    /// Keyring the token is kept in; `None` keeps it in the token file
+    secrets: Option<Box<dyn SecretStore>>,
     token: Option<TokenData>,
 }
 
@@ -30,16 +148,63 @@ impl AuthManager {
 
         std::fs::create_dir_all(&config_dir)?;
 
-        let config_path = config_dir.join("token.json");
+        Ok(Self::with_store(
+            config_dir.join("token.json"),
+            default_secret_store(),
+        ))
+    }
 
-        let token = if config_path.exists() {
-            let data = std::fs::read_to_string(&config_path)?;
-            serde_json::from_str(&data).ok()
-        } else {
-            None
+    /// Auth manager with the token file at `config_path` and the token in
+    /// `secrets` when it works. Loads the saved token, moving one found in
+    /// the file into `secrets`.
+    pub fn with_store(config_path: PathBuf, secrets: Option<Box<dyn SecretStore>>) -> Self {
+        let mut auth = Self {
+            config_path,
+            secrets,
+            token: None,
         };
+        auth.load();
+        auth
+    }
+
+    fn load(&mut self) {
+        if let Some(store) = &self.secrets {
+            match store.get() {
+                Ok(Some(data)) => {
+                    self.token = serde_json::from_str(&data).ok();
+                    return;
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!("Keyring unavailable, using the token file: {:#}", e);
+                    self.secrets = None;
+                }
+            }
+        }
 
-        Ok(Self { config_path, token })
+        let Ok(data) = std::fs::read_to_string(&self.config_path) else {
+            return;
+        };
+        self.token = serde_json::from_str(&data).ok();
+
+        // Migrate a plaintext token left from before the keyring
+        if self.token.is_some()
+            && let Some(store) = &self.secrets
+        {
+            match store.set(&data) {
+                Ok(()) => {
+                    if let Err(e) = std::fs::remove_file(&self.config_path) {
+                        tracing::warn!("Failed to remove the migrated token file: {}", e);
+                    }
+                }
+                Err(e) => tracing::warn!("Failed to move the token to the keyring: {:#}", e),
+            }
+        }
+    }
+
+    /// Whether the token is kept in the keyring rather than the file
+    pub fn uses_keyring(&self) -> bool {
+        self.secrets.is_some()
     }
 
     /// Check if we have a valid token
@@ -89,6 +254,24 @@ impl AuthManager {
         )
     }
 
+    /// Listen on 127.0.0.1:`port` for the OAuth redirect, so the user
+    /// does not have to paste it. Open [`RedirectListener::auth_url`] in
+    /// the browser, then save the URL [`RedirectListener::wait`] returns
+    /// with [`save_token_from_url`](Self::save_token_from_url).
+    pub async fn start_redirect_listener(port: u16) -> Result<RedirectListener> {
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", port))
+            .await
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::AddrInUse => anyhow::anyhow!(
+                    "Port {} is already in use; close the program using it or pick another port",
+                    port
+                ),
+                _ => anyhow::anyhow!("Cannot listen on 127.0.0.1:{}: {}", port, e),
+            })?;
+        let port = listener.local_addr()?.port();
+        Ok(RedirectListener { listener, port })
+    }
+
     /// Save token from redirect URL
     pub fn save_token_from_url(&mut self, url: &str) -> Result<()> {
         // Normalize URL: users sometimes paste //oauth.vk.com/blank.html#...
@@ -140,32 +323,47 @@ impl AuthManager {
             }
         });
 
-        let token = TokenData {
+        self.save_token(TokenData {
             access_token,
             user_id,
             expires_at,
-        };
+        })
+    }
 
+    /// Save `token` to the keyring, or to the token file when there is no
+    /// keyring or it fails.
+    pub fn save_token(&mut self, token: TokenData) -> Result<()> {
         let data = serde_json::to_string_pretty(&token)?;
 
-        if let Some(parent) = self.config_path.parent() {
-            std::fs::create_dir_all(parent)?;
+        let in_keyring = match &self.secrets {
+            Some(store) => match store.set(&data) {
+                Ok(()) => true,
+                Err(e) => {
+                    tracing::warn!("Keyring unavailable, using the token file: {:#}", e);
+                    false
+                }
+            },
+            None => false,
+        };
+        if in_keyring {
+            remove_if_exists(&self.config_path)?;
+        } else {
+            write_private(&self.config_path, &data)?;
         }
 
-        std::fs::write(&self.config_path, data)?;
-
         self.token = Some(token);
         Ok(())
     }
 
-    /// Clear saved token
-    #[allow(dead_code)]
+    /// Clear saved token, from both the keyring and the file
     pub fn logout(&mut self) -> Result<()> {
-        if self.config_path.exists() {
-            std::fs::remove_file(&self.config_path)?;
-        }
         self.token = None;
-        Ok(())
+        let keyring = match &self.secrets {
+            Some(store) => store.delete(),
+            None => Ok(()),
+        };
+        remove_if_exists(&self.config_path)?;
+        keyring
     }
 }
 
@@ -173,7 +371,205 @@ impl Default for AuthManager {
     fn default() -> Self {
         Self::new().unwrap_or_else(|_| Self {
             config_path: PathBuf::from("token.json"),
+            secrets: None,
             token: None,
         })
     }
 }
+
+/// Local HTTP server catching the OAuth redirect.
+///
+/// VK puts the token in the URL fragment, which browsers do not send, so
+/// the redirect gets a small page that sends the fragment back to the
+/// server as a query.
+pub struct RedirectListener {
+    listener: tokio::net::TcpListener,
+    port: u16,
+}
+
+/// Page served at the redirect URI; passes the fragment on to `/token`.
+const REDIRECT_PAGE: &str = "<!doctype html><meta charset=\"utf-8\"><title>VK login</title>\
+<body>Logging in…<script>\
+fetch('/token?' + location.hash.slice(1)).then(function () {\
+document.body.textContent = 'Logged in. You can close this tab.';\
+});</script>";
+
+impl RedirectListener {
+    /// Port the server listens on
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Redirect URI VK sends the browser to
+    pub fn redirect_uri(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+
+    /// Auth URL redirecting to this server
+    pub fn auth_url(&self) -> String {
+        AuthManager::get_auth_url_with_redirect(&self.redirect_uri())
+    }
+
+    /// Wait up to `timeout` for the browser to bring the token. Returns
+    /// the redirect URL with the token in its fragment.
+    pub async fn wait(self, timeout: Duration) -> Result<String> {
+        let minutes = timeout.as_secs().div_ceil(60);
+        tokio::time::timeout(timeout, self.serve())
+            .await
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "No redirect from the browser within {} min; try again",
+                    minutes
+                )
+            })?
+    }
+
+    async fn serve(&self) -> Result<String> {
+        // Connections are read side by side, so one that sends nothing (a
+        // browser preconnect, say) does not hold up the redirect
+        let mut reads = tokio::task::JoinSet::new();
+        loop {
+            let (mut socket, target) = tokio::select! {
+                accepted = self.listener.accept() => {
+                    let (mut socket, _) = accepted?;
+                    reads.spawn(async move {
+                        let read = read_request_target(&mut socket);
+                        let target = tokio::time::timeout(REQUEST_READ_TIMEOUT, read).await;
+                        (socket, target)
+                    });
+                    continue;
+                }
+                Some(read) = reads.join_next() => match read {
+                    Ok((socket, Ok(Ok(target)))) => (socket, target),
+                    // A broken or silent connection is not fatal
+                    _ => continue,
+                },
+            };
+            let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+            let params = parse_query(query);
+            let param = |key: &str| {
+                params
+                    .iter()
+                    .find(|(k, _)| k == key)
+                    .map(|(_, v)| v.as_str())
+            };
+
+            if let Some(error) = param("error") {
+                let reason = param("error_description").unwrap_or(error).to_string();
+                respond(&mut socket, "text/plain; charset=utf-8", &reason).await;
+                anyhow::bail!("Authorization failed: {}", reason);
+            }
+            if path == "/token" && param("access_token").is_some() {
+                respond(&mut socket, "text/plain; charset=utf-8", "OK").await;
+                return Ok(format!("{}/#{}", self.redirect_uri(), query));
+            }
+            respond(&mut socket, "text/html; charset=utf-8", REDIRECT_PAGE).await;
+        }
+    }
+}
+
+/// How long a connection may take to send its request head.
+const REQUEST_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Read an HTTP request head and return its target (`/token?...`).
+async fn read_request_target(socket: &mut tokio::net::TcpStream) -> Result<String> {
+    use tokio::io::AsyncReadExt;
+
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = socket.read(&mut buf).await?;
+        anyhow::ensure!(n > 0, "connection closed");
+        head.extend_from_slice(&buf[..n]);
+        anyhow::ensure!(head.len() <= 16 * 1024, "request too large");
+    }
+    let head = String::from_utf8_lossy(&head);
+    let target = head
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .context("malformed request")?;
+    Ok(target.to_string())
+}
+
+async fn respond(socket: &mut tokio::net::TcpStream, content_type: &str, body: &str) {
+    use tokio::io::AsyncWriteExt;
+
+    let response = format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        content_type,
+        body.len(),
+        body
+    );
+    let _ = socket.write_all(response.as_bytes()).await;
+    let _ = socket.shutdown().await;
+}
+
+/// Decoded `key=value` pairs of a query string.
+fn parse_query(query: &str) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+/// `%XX` escapes and `+` for spaces decoded; invalid escapes kept as is.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' => match bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                Some(byte) => {
+                    out.push(byte);
+                    i += 2;
+                }
+                None => out.push(b'%'),
+            },
+            byte => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Write `data` to `path`, readable and writable by the owner only.
+fn write_private(path: &Path, data: &str) -> Result<()> {
+    use std::io::Write;
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+        options.mode(0o600);
+        // The mode only applies to new files; tighten an older one too
+        if path.exists() {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    options.open(path)?.write_all(data.as_bytes())?;
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
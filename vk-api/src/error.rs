@@ -0,0 +1,196 @@
+//! Error type returned by the VK API client.
+
+/// VK error code: user authorization failed (invalid or expired token).
+pub const ERROR_AUTH_FAILED: i64 = 5;
+
+/// VK error code: too many requests per second.
+pub const ERROR_TOO_MANY_REQUESTS: i64 = 6;
+
+/// VK error code: permission to perform this action is denied.
+pub const ERROR_PERMISSION_DENIED: i64 = 7;
+
+/// VK error code: internal server error.
+pub const ERROR_INTERNAL: i64 = 10;
+
+/// VK error code: VK wants a captcha solved before the request.
+pub const ERROR_CAPTCHA_NEEDED: i64 = 14;
+
+/// VK error code: access to the object or action denied.
+pub const ERROR_ACCESS_DENIED: i64 = 15;
+
+/// VK error code: a parameter is missing or invalid.
+pub const ERROR_INVALID_PARAM: i64 = 100;
+
+/// VK error code: token is bound to another IP / device and was revoked.
+pub const ERROR_TOKEN_REVOKED: i64 = 179;
+
+/// VK error code: the message is too old to be edited.
+pub const ERROR_EDIT_EXPIRED: i64 = 909;
+
+/// VK error code: the message is too big (too long or too many attachments).
+pub const ERROR_MESSAGE_TOO_BIG: i64 = 910;
+
+/// VK error code: the message can no longer be deleted for everybody.
+pub const ERROR_DELETE_FOR_ALL_EXPIRED: i64 = 924;
+
+/// Errors returned by [`VkClient`](crate::VkClient) and the namespace APIs.
+///
+/// Implements `std::error::Error`, so `?` still converts it into
+/// `anyhow::Error` for callers that don't care about the variant.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// VK returned an `error` object in the response.
+    #[error("VK API error {code}: {message}")]
+    Api { code: i64, message: String },
+
+    /// Network or HTTP-level failure.
+    #[error("HTTP error: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// Response body could not be decoded.
+    #[error("Failed to parse response: {0}")]
+    Parse(#[from] serde_json::Error),
+
+    /// Access token is invalid, expired or revoked.
+    #[error("VK API error 5: user authorization failed")]
+    Auth,
+
+    /// The message was sent more than 24 hours ago and can no longer be
+    /// edited (VK error 909).
+    #[error("VK does not allow editing messages older than 24 hours")]
+    EditExpired,
+
+    /// The message is too long or has too many attachments (VK error 910).
+    #[error("Message is too big: shorten the text or remove attachments")]
+    MessageTooBig,
+
+    /// The message was sent more than 24 hours ago and can only be deleted
+    /// for the current user (VK error 924).
+    #[error("VK does not allow deleting messages older than 24 hours for everyone")]
+    DeleteForAllExpired,
+
+    /// VK wants a captcha solved before it accepts the request (VK error
+    /// 14). Answer it with
+    /// [`VkClient::answer_captcha`](crate::VkClient::answer_captcha) and
+    /// repeat the request.
+    #[error("VK asks to solve a captcha: {img_url}")]
+    Captcha { sid: String, img_url: String },
+
+    /// Local I/O failure (e.g. reading a file to upload).
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// File upload was rejected (by VK or by local limits).
+    #[error("Upload failed: {0}")]
+    Upload(String),
+
+    /// Response had an unexpected shape.
+    #[error("Unexpected response: {0}")]
+    UnexpectedResponse(String),
+
+    /// Token was issued without scopes the client needs; a new one must
+    /// be requested with the auth URL.
+    #[error("Token lacks {} — regenerate the auth URL and re-authorize", scope_list(.0))]
+    MissingScopes(Vec<&'static str>),
+
+    /// Response body is larger than
+    /// [`VkClientBuilder::max_response_bytes`](crate::VkClientBuilder::max_response_bytes);
+    /// reading stopped at the limit.
+    #[error("Response body exceeds the limit of {limit} bytes")]
+    ResponseTooLarge { limit: usize },
+
+    /// No part of the response body arrived within
+    /// [`VkClientBuilder::read_timeout`](crate::VkClientBuilder::read_timeout).
+    #[error("Response body stalled for {0:?}")]
+    ReadTimeout(std::time::Duration),
+
+    /// The proxy given to
+    /// [`VkClientBuilder::proxy`](crate::VkClientBuilder::proxy) is not a
+    /// usable URL.
+    #[error("Invalid proxy {url}: {reason}")]
+    InvalidProxy { url: String, reason: String },
+
+    /// [`VkClient::ping`](crate::VkClient::ping) got no answer from the API
+    /// server. `proxy` has no credentials in it.
+    #[error("Cannot reach {host}{}: {reason}", via_proxy(.proxy))]
+    Unreachable {
+        host: String,
+        proxy: Option<String>,
+        reason: String,
+    },
+}
+
+/// "'messages' scope", "'messages', 'photos' scopes".
+fn scope_list(scopes: &[&str]) -> String {
+    let names: Vec<String> = scopes.iter().map(|s| format!("'{}'", s)).collect();
+    let noun = if scopes.len() == 1 { "scope" } else { "scopes" };
+    format!("{} {}", names.join(", "), noun)
+}
+
+/// " via proxy http://proxy:3128", or nothing for a direct connection.
+fn via_proxy(proxy: &Option<String>) -> String {
+    proxy
+        .as_ref()
+        .map(|url| format!(" via proxy {}", url))
+        .unwrap_or_default()
+}
+
+/// Result alias for VK API calls.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Build an error from a VK `error` object, mapping auth failures to
+    /// [`Error::Auth`] and message limits to their own variants.
+    pub fn from_api(code: i64, message: impl Into<String>) -> Self {
+        match code {
+            ERROR_AUTH_FAILED => Self::Auth,
+            ERROR_EDIT_EXPIRED => Self::EditExpired,
+            ERROR_MESSAGE_TOO_BIG => Self::MessageTooBig,
+            ERROR_DELETE_FOR_ALL_EXPIRED => Self::DeleteForAllExpired,
+            _ => Self::Api {
+                code,
+                message: message.into(),
+            },
+        }
+    }
+
+    /// VK error code, if this is an API error.
+    pub fn code(&self) -> Option<i64> {
+        match self {
+            Self::Api { code, .. } => Some(*code),
+            Self::Auth => Some(ERROR_AUTH_FAILED),
+            Self::EditExpired => Some(ERROR_EDIT_EXPIRED),
+            Self::MessageTooBig => Some(ERROR_MESSAGE_TOO_BIG),
+            Self::DeleteForAllExpired => Some(ERROR_DELETE_FOR_ALL_EXPIRED),
+            Self::Captcha { .. } => Some(ERROR_CAPTCHA_NEEDED),
+            _ => None,
+        }
+    }
+
+    /// Whether the session is no longer usable and the user must log in again.
+    ///
+    /// Only codes 5 and 179 mean that; "permission denied" (7) is about the
+    /// action, not the token.
+    pub fn is_auth(&self) -> bool {
+        matches!(
+            self,
+            Self::Auth
+                | Self::Api {
+                    code: ERROR_TOKEN_REVOKED,
+                    ..
+                }
+        )
+    }
+
+    /// Whether the request may succeed if repeated later
+    /// (rate limiting or a transient server failure).
+    pub fn is_retriable(&self) -> bool {
+        matches!(
+            self,
+            Self::Api {
+                code: ERROR_TOO_MANY_REQUESTS | ERROR_INTERNAL,
+                ..
+            }
+        )
+    }
+}
@@ -0,0 +1,99 @@
+//! Batching of API calls with the `execute` method.
+//!
+//! `execute` runs a VKScript on the VK side, so up to
+//! [`EXECUTE_MAX_CALLS`] calls cost one request and one slot of the rate
+//! limit. [`ExecuteBatch`] builds the script for a list of calls whose
+//! results come back as an array, in call order.
+
+use std::collections::HashMap;
+
+use serde::de::DeserializeOwned;
+
+use crate::client::VkClient;
+use crate::error::Result;
+
+/// API calls one `execute` request may make
+pub const EXECUTE_MAX_CALLS: usize = 25;
+
+/// Calls to make in one `execute` request
+#[derive(Debug, Clone, Default)]
+pub struct ExecuteBatch {
+    calls: Vec<String>,
+}
+
+impl ExecuteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a call of `method` (like `messages.getById`) with `params`.
+    /// Returns false, adding nothing, when the batch is full.
+    pub fn push(&mut self, method: &str, params: &[(&str, String)]) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        let args: serde_json::Map<String, serde_json::Value> = params
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.clone().into()))
+            .collect();
+        self.calls.push(format!(
+            "API.{}({})",
+            method,
+            serde_json::Value::Object(args)
+        ));
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.calls.len() >= EXECUTE_MAX_CALLS
+    }
+
+    /// The VKScript returning the results of the calls as an array
+    pub fn code(&self) -> String {
+        format!("return [{}];", self.calls.join(","))
+    }
+}
+
+impl VkClient {
+    /// Run VKScript `code` as one request
+    ///
+    /// # VK API
+    /// Method: execute
+    /// https://dev.vk.com/method/execute
+    pub async fn execute<T: DeserializeOwned>(&self, code: &str) -> Result<T> {
+        let mut params = HashMap::new();
+        params.insert("code", code.to_string());
+        self.request("execute", params).await
+    }
+
+    /// Make the calls of `batch` in one request, returning their results
+    /// in call order
+    ///
+    /// A call that failed is `None`; its error is logged. The request
+    /// itself fails only if VK rejects the whole script.
+    pub async fn execute_batch<T: DeserializeOwned>(
+        &self,
+        batch: &ExecuteBatch,
+    ) -> Result<Vec<Option<T>>> {
+        let results: Vec<serde_json::Value> = self.execute(&batch.code()).await?;
+        Ok(results
+            .into_iter()
+            .map(|result| match result {
+                serde_json::Value::Bool(false) => None,
+                value => serde_json::from_value(value)
+                    .inspect_err(
+                        |e| tracing::warn!(target: "vk_api::http", "Bad execute result: {}", e),
+                    )
+                    .ok(),
+            })
+            .collect())
+    }
+}
@@ -8,7 +8,7 @@
 //! ## Basic Usage
 //!
 //! ```rust,no_run
-//! use vk_api::{VkClient, auth::AuthManager};
+//! use vk_api::{BASIC_USER_FIELDS, VkClient, auth::AuthManager};
 //!
 //! #[tokio::main]
 //! async fn main() -> anyhow::Result<()> {
@@ -27,7 +27,7 @@
 //!     let msg_id = client.messages().send(12345, "Hello!").await?;
 //!
 //!     // Get user info
-//!     let users = client.users().get(&[12345]).await?;
+//!     let users = client.users().get(&[12345], BASIC_USER_FIELDS).await?;
 //!
 //!     Ok(())
 //! }
@@ -57,15 +57,51 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! # Response limits
+//!
+//! API, Long Poll and upload responses are read in parts, at most
+//! [`VkClientBuilder::max_response_bytes`] (16 MB by default) with no pause
+//! longer than [`VkClientBuilder::read_timeout`]. Past either limit the call
+//! fails with [`Error::ResponseTooLarge`] or [`Error::ReadTimeout`] instead
+//! of buffering the rest.
+//!
+//! # Integer types
+//!
+//! All identifiers (user, community, peer, message, owner ids) are `i64`:
+//! community ids are negative and chat peer ids are
+//! [`CHAT_PEER_OFFSET`] + chat id, which leaves i32 for large chats. Use
+//! [`chat_peer_id`] instead of adding the offset by hand. Counts are `u32`.
+//!
+//! ## Migration
+//!
+//! `count` in [`MessagesHistoryResponse`], [`ConversationsResponse`],
+//! [`SearchResponse`] and [`LongPollHistoryMessages`], and
+//! `ChatSettings::members_count`, changed from `i32` to `u32`. Drop
+//! `as u32` casts on these fields; code that stored them in `i32` should
+//! switch to `u32`.
+//!
+//! [`UsersApi::get`] takes the profile fields to request. Pass
+//! [`BASIC_USER_FIELDS`] for names, avatars and online status, or `&[]`
+//! when only names are needed.
 
 pub mod auth;
 pub mod client;
+pub mod error;
+pub mod execute;
 pub mod methods;
+pub mod schema;
 pub mod types;
 
 // Re-exports for convenience
-pub use client::VkClient;
-pub use methods::{AccountApi, FriendsApi, LongPollApi, MessagesApi, UsersApi};
+pub use client::{MethodMetrics, VkClient, VkClientBuilder};
+pub use error::{Error, Result};
+pub use execute::{EXECUTE_MAX_CALLS, ExecuteBatch};
+pub use methods::{
+    AccountApi, FriendsApi, GET_BY_ID_MAX, GroupsApi, LONG_POLL_MAX_WAIT, LONG_POLL_WAIT,
+    LongPollApi, MAX_ATTACHMENTS, MessagesApi, UploadProgress, UsersApi, new_random_id,
+};
+pub use schema::SchemaMode;
 pub use types::*;
 
 /// VK API version used by this library
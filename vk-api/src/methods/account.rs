@@ -3,7 +3,7 @@
 //! Provides methods for working with account settings and counters.
 //! References: https://dev.vk.com/method/account
 
-use anyhow::Result;
+use crate::error::Result;
 use std::collections::HashMap;
 
 use crate::client::VkClient;
@@ -50,6 +50,21 @@ impl<'a> AccountApi<'a> {
         self.client.request("account.getProfileInfo", params).await
     }
 
+    /// Get the permission mask of the current token
+    ///
+    /// Bits are the `SCOPE_*` constants in [`crate::auth`].
+    ///
+    /// # VK API
+    /// Method: account.getAppPermissions
+    /// https://dev.vk.com/method/account.getAppPermissions
+    pub async fn get_app_permissions(&self) -> Result<i64> {
+        let params = HashMap::new();
+
+        self.client
+            .request("account.getAppPermissions", params)
+            .await
+    }
+
     /// Set online status
     ///
     /// Marks the user as online. The online status is automatically
@@ -65,6 +80,28 @@ impl<'a> AccountApi<'a> {
         Ok(())
     }
 
+    /// Turn notifications of a conversation off or back on
+    ///
+    /// # Arguments
+    /// * `peer_id` - Conversation to silence
+    /// * `time` - Seconds to stay silent: -1 for ever, 0 to turn
+    ///   notifications back on
+    ///
+    /// # VK API
+    /// Method: account.setSilenceMode
+    /// https://dev.vk.com/method/account.setSilenceMode
+    pub async fn set_silence_mode(&self, peer_id: i64, time: i64) -> Result<()> {
+        let mut params = HashMap::new();
+        params.insert("peer_id", peer_id.to_string());
+        params.insert("time", time.to_string());
+
+        let _: i32 = self
+            .client
+            .request("account.setSilenceMode", params)
+            .await?;
+        Ok(())
+    }
+
     /// Set offline status
     ///
     /// Marks the user as offline.
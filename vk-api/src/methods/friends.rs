@@ -3,7 +3,7 @@
 //! Provides methods for working with VK friends.
 //! References: https://dev.vk.com/method/friends
 
-use anyhow::Result;
+use crate::error::Result;
 use std::collections::HashMap;
 
 use crate::client::VkClient;
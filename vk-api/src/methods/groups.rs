@@ -0,0 +1,47 @@
+//! Groups API implementation
+//!
+//! Provides methods for working with VK communities.
+//! References: https://dev.vk.com/method/groups
+
+use crate::error::Result;
+use std::collections::HashMap;
+
+use crate::client::VkClient;
+use crate::types::*;
+
+/// Groups API namespace
+pub struct GroupsApi<'a> {
+    client: &'a VkClient,
+}
+
+impl<'a> GroupsApi<'a> {
+    pub(crate) fn new(client: &'a VkClient) -> Self {
+        Self { client }
+    }
+
+    /// Get community info by IDs
+    ///
+    /// # Arguments
+    /// * `group_ids` - Community IDs, positive (max: 500)
+    /// * `fields` - Extra fields, e.g. `&["status", "members_count"]`
+    ///
+    /// # VK API
+    /// Method: groups.getById
+    /// https://dev.vk.com/method/groups.getById
+    pub async fn get_by_id(&self, group_ids: &[i64], fields: &[&str]) -> Result<Vec<Group>> {
+        let mut params = HashMap::new();
+        let ids: Vec<String> = group_ids.iter().map(|id| id.to_string()).collect();
+        params.insert("group_ids", ids.join(","));
+        if !fields.is_empty() {
+            params.insert("fields", fields.join(","));
+        }
+
+        #[derive(Debug, serde::Deserialize)]
+        struct Response {
+            groups: Vec<Group>,
+        }
+
+        let response: Response = self.client.request("groups.getById", params).await?;
+        Ok(response.groups)
+    }
+}
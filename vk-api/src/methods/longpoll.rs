@@ -3,12 +3,21 @@
 //! Provides methods for working with VK Long Poll server for real-time updates.
 //! References: https://dev.vk.com/api/user-long-poll/getting-started
 
-use anyhow::{Context, Result};
 use std::{collections::HashMap, time::Duration};
 
 use crate::client::VkClient;
+use crate::error::Result;
 use crate::types::*;
 
+/// How long the server holds a poll open without events.
+pub const LONG_POLL_WAIT: Duration = Duration::from_secs(25);
+
+/// Longest wait the Long Poll server accepts.
+pub const LONG_POLL_MAX_WAIT: Duration = Duration::from_secs(90);
+
+/// Extra time over the wait before the poll request is abandoned.
+const POLL_TIMEOUT_MARGIN: Duration = Duration::from_secs(10);
+
 /// Long Poll API namespace
 pub struct LongPollApi<'a> {
     client: &'a VkClient,
@@ -30,6 +39,7 @@ impl<'a> LongPollApi<'a> {
     pub async fn get_server(&self) -> Result<LongPollServer> {
         let mut params = HashMap::new();
         params.insert("lp_version", "3".to_string());
+        params.insert("need_pts", "1".to_string());
 
         self.client
             .request("messages.getLongPollServer", params)
@@ -59,25 +69,46 @@ impl<'a> LongPollApi<'a> {
     /// # VK API
     /// https://dev.vk.com/api/user-long-poll/getting-started
     pub async fn poll(&self, server: &LongPollServer) -> Result<LongPollResponse> {
+        self.poll_with_wait(server, LONG_POLL_WAIT).await
+    }
+
+    /// Like [`poll`](Self::poll), but the server holds the request open for
+    /// up to `wait` (at most [`LONG_POLL_MAX_WAIT`]) when nothing happens.
+    /// Events still arrive as soon as they occur; a longer wait only means
+    /// fewer requests while idle.
+    pub async fn poll_with_wait(
+        &self,
+        server: &LongPollServer,
+        wait: Duration,
+    ) -> Result<LongPollResponse> {
+        let wait = wait.clamp(Duration::from_secs(1), LONG_POLL_MAX_WAIT);
         // mode=234: attachments(2) + extended_events(8) + pts(32) + random_id(64) + extra_fields(128)
+        // VK gives the server without a scheme; one that has it is used as is
+        let scheme = if server.server.contains("://") {
+            ""
+        } else {
+            "https://"
+        };
         let url = format!(
-            "https://{}?act=a_check&key={}&ts={}&wait=25&mode=234&version=3",
-            server.server, server.key, server.ts
+            "{}{}?act=a_check&key={}&ts={}&wait={}&mode=234&version=3",
+            scheme,
+            server.server,
+            server.key,
+            server.ts,
+            wait.as_secs()
         );
 
         let response = self
             .client
             .http_client()
             .get(&url)
-            .timeout(Duration::from_secs(35))
+            .timeout(wait + POLL_TIMEOUT_MARGIN)
             .send()
-            .await
-            .context("Long poll request failed")?;
+            .await?;
 
-        response
-            .json()
-            .await
-            .context("Failed to parse long poll response")
+        let text = self.client.read_body(response).await?;
+        self.client
+            .parse_response("longpoll", &text, |r: &LongPollResponse| r.failed.is_none())
     }
 
     /// Get history of missed events
@@ -92,7 +123,11 @@ impl<'a> LongPollApi<'a> {
     /// # VK API
     /// Method: messages.getLongPollHistory
     /// https://dev.vk.com/method/messages.getLongPollHistory
-    pub async fn get_history(&self, ts: &str, pts: Option<i64>) -> Result<LongPollHistory> {
+    pub async fn get_long_poll_history(
+        &self,
+        ts: &str,
+        pts: Option<i64>,
+    ) -> Result<LongPollHistory> {
         let mut params = HashMap::new();
         params.insert("ts", ts.to_string());
 
@@ -107,15 +142,3 @@ impl<'a> LongPollApi<'a> {
             .await
     }
 }
-
-/// Long Poll history response
-#[derive(Debug, serde::Deserialize)]
-pub struct LongPollHistory {
-    pub messages: Vec<Message>,
-
-    #[serde(default)]
-    pub profiles: Vec<User>,
-
-    #[serde(default)]
-    pub new_pts: Option<i64>,
-}
@@ -3,15 +3,31 @@
 //! Provides methods for working with VK messages, conversations, and related functionality.
 //! References: https://dev.vk.com/method/messages
 
-use anyhow::{Context, Result};
+use futures_util::{StreamExt, stream};
 use rand::Rng;
 use std::collections::HashMap;
 use std::path::Path;
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
 
 use crate::client::VkClient;
+use crate::error::{Error, Result};
+use crate::execute::{EXECUTE_MAX_CALLS, ExecuteBatch};
 use crate::types::*;
 use serde_json::Value;
 
+/// Called while a file uploads, with bytes sent and total bytes.
+pub type UploadProgress = Arc<dyn Fn(u64, u64) + Send + Sync>;
+
+/// Attachments VK accepts in one message.
+pub const MAX_ATTACHMENTS: usize = 10;
+
+/// Message ids one `messages.getById` call takes.
+pub const GET_BY_ID_MAX: usize = 100;
+
+/// Size of the file chunks streamed to upload servers.
+const UPLOAD_CHUNK: usize = 64 * 1024;
+
 /// Messages API namespace
 pub struct MessagesApi<'a> {
     client: &'a VkClient,
@@ -45,6 +61,7 @@ impl<'a> MessagesApi<'a> {
         params.insert("offset", offset.to_string());
         params.insert("count", count.to_string());
         params.insert("extended", "1".to_string());
+        params.insert("fields", CONVERSATION_USER_FIELDS.join(","));
 
         self.client
             .request("messages.getConversations", params)
@@ -61,7 +78,7 @@ impl<'a> MessagesApi<'a> {
         conversations
             .into_iter()
             .next()
-            .context("Conversation not found")
+            .ok_or_else(|| Error::UnexpectedResponse("conversation not found".into()))
     }
 
     /// Get conversations by multiple IDs
@@ -85,7 +102,7 @@ impl<'a> MessagesApi<'a> {
         #[derive(Debug, serde::Deserialize)]
         struct Response {
             #[allow(dead_code)]
-            count: i32,
+            count: u32,
             items: Vec<Conversation>,
         }
 
@@ -97,12 +114,150 @@ impl<'a> MessagesApi<'a> {
         Ok(response.items)
     }
 
+    // ========== Chat Members ==========
+
+    /// Get members of a group chat
+    ///
+    /// # Arguments
+    /// * `peer_id` - Chat peer ID ([`chat_peer_id`](crate::chat_peer_id))
+    /// * `offset` - Offset for pagination
+    /// * `count` - Number of members (max: 200)
+    ///
+    /// # Returns
+    /// ConversationMembersResponse with members and their profiles
+    ///
+    /// # VK API
+    /// Method: messages.getConversationMembers
+    /// https://dev.vk.com/method/messages.getConversationMembers
+    pub async fn get_conversation_members(
+        &self,
+        peer_id: i64,
+        offset: u32,
+        count: u32,
+    ) -> Result<ConversationMembersResponse> {
+        let mut params = HashMap::new();
+        params.insert("peer_id", peer_id.to_string());
+        params.insert("offset", offset.to_string());
+        params.insert("count", count.to_string());
+        params.insert("fields", BASIC_USER_FIELDS.join(","));
+
+        self.client
+            .request("messages.getConversationMembers", params)
+            .await
+    }
+
+    /// Get who read a message in a group chat
+    ///
+    /// # Arguments
+    /// * `peer_id` - Chat peer ID ([`chat_peer_id`](crate::chat_peer_id))
+    /// * `cmid` - Conversation message ID
+    ///
+    /// # Returns
+    /// ReadPeersResponse with reader ids and their profiles. VK refuses the
+    /// request for old messages.
+    ///
+    /// # VK API
+    /// Method: messages.getMessageReadPeers
+    /// https://dev.vk.com/method/messages.getMessageReadPeers
+    pub async fn get_message_read_peers(
+        &self,
+        peer_id: i64,
+        cmid: i64,
+    ) -> Result<ReadPeersResponse> {
+        let mut params = HashMap::new();
+        params.insert("peer_id", peer_id.to_string());
+        params.insert("cmid", cmid.to_string());
+        params.insert("extended", "1".to_string());
+        params.insert("fields", BASIC_USER_FIELDS.join(","));
+
+        self.client
+            .request("messages.getMessageReadPeers", params)
+            .await
+    }
+
+    /// Remove a member from a group chat; pass your own id to leave it
+    ///
+    /// # Arguments
+    /// * `chat_id` - Chat ID (peer ID minus [`CHAT_PEER_OFFSET`](crate::CHAT_PEER_OFFSET))
+    /// * `user_id` - User ID, or negative community ID
+    ///
+    /// # VK API
+    /// Method: messages.removeChatUser
+    /// https://dev.vk.com/method/messages.removeChatUser
+    pub async fn remove_chat_user(&self, chat_id: i64, user_id: i64) -> Result<()> {
+        let mut params = HashMap::new();
+        params.insert("chat_id", chat_id.to_string());
+        params.insert("member_id", user_id.to_string());
+
+        let _: serde_json::Value = self
+            .client
+            .request("messages.removeChatUser", params)
+            .await?;
+        Ok(())
+    }
+
+    /// Rename a group chat; only its admins may do so
+    ///
+    /// # Arguments
+    /// * `chat_id` - Chat ID (peer ID minus [`CHAT_PEER_OFFSET`](crate::CHAT_PEER_OFFSET))
+    /// * `title` - New title
+    ///
+    /// # VK API
+    /// Method: messages.editChat
+    /// https://dev.vk.com/method/messages.editChat
+    pub async fn edit_chat(&self, chat_id: i64, title: &str) -> Result<()> {
+        let mut params = HashMap::new();
+        params.insert("chat_id", chat_id.to_string());
+        params.insert("title", title.to_string());
+
+        let _: serde_json::Value = self.client.request("messages.editChat", params).await?;
+        Ok(())
+    }
+
+    /// Create a group chat with the given users
+    ///
+    /// # Arguments
+    /// * `user_ids` - Users to add besides yourself (at least one)
+    /// * `title` - Chat title
+    ///
+    /// # Returns
+    /// Chat ID of the new chat; its peer ID is [`chat_peer_id`](crate::chat_peer_id)
+    ///
+    /// # VK API
+    /// Method: messages.createChat
+    /// https://dev.vk.com/method/messages.createChat
+    pub async fn create_chat(&self, user_ids: &[i64], title: &str) -> Result<i64> {
+        let mut params = HashMap::new();
+        params.insert(
+            "user_ids",
+            user_ids
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+        params.insert("title", title.to_string());
+
+        // Newer API versions wrap the id in an object
+        #[derive(Debug, serde::Deserialize)]
+        #[serde(untagged)]
+        enum Response {
+            Id(i64),
+            Chat { chat_id: i64 },
+        }
+
+        let response: Response = self.client.request("messages.createChat", params).await?;
+        Ok(match response {
+            Response::Id(chat_id) | Response::Chat { chat_id } => chat_id,
+        })
+    }
+
     // ========== Messages ==========
 
     /// Get message history for a conversation
     ///
     /// # Arguments
-    /// * `peer_id` - Peer ID (user_id for DM, 2000000000+chat_id for group chats)
+    /// * `peer_id` - Peer ID (user_id for DM, [`chat_peer_id`](crate::chat_peer_id) for group chats)
     /// * `offset` - Offset for pagination
     /// * `count` - Number of messages (max: 200)
     ///
@@ -123,6 +278,7 @@ impl<'a> MessagesApi<'a> {
         params.insert("offset", offset.to_string());
         params.insert("count", count.to_string());
         params.insert("extended", "1".to_string());
+        params.insert("fields", BASIC_USER_FIELDS.join(","));
 
         self.client.request("messages.getHistory", params).await
     }
@@ -277,7 +433,7 @@ impl<'a> MessagesApi<'a> {
     /// Method: messages.send
     /// https://dev.vk.com/method/messages.send
     pub async fn send(&self, peer_id: i64, message: &str) -> Result<SentMessage> {
-        self.send_with_params(peer_id, message, None, None, None)
+        self.send_with_params(peer_id, message, None, None, None, new_random_id())
             .await
     }
 
@@ -291,8 +447,15 @@ impl<'a> MessagesApi<'a> {
         message: &str,
         reply_to: i64,
     ) -> Result<SentMessage> {
-        self.send_with_params(peer_id, message, Some(reply_to), None, None)
-            .await
+        self.send_with_params(
+            peer_id,
+            message,
+            Some(reply_to),
+            None,
+            None,
+            new_random_id(),
+        )
+        .await
     }
 
     /// Send message with forward
@@ -305,8 +468,15 @@ impl<'a> MessagesApi<'a> {
         message: &str,
         forward_messages: &[i64],
     ) -> Result<SentMessage> {
-        self.send_with_params(peer_id, message, None, Some(forward_messages), None)
-            .await
+        self.send_with_params(
+            peer_id,
+            message,
+            None,
+            Some(forward_messages),
+            None,
+            new_random_id(),
+        )
+        .await
     }
 
     /// Send message with attachment
@@ -322,8 +492,71 @@ impl<'a> MessagesApi<'a> {
         message: &str,
         attachment: &str,
     ) -> Result<SentMessage> {
-        self.send_with_params(peer_id, message, None, None, Some(attachment))
-            .await
+        self.send_with_params(
+            peer_id,
+            message,
+            None,
+            None,
+            Some(attachment),
+            new_random_id(),
+        )
+        .await
+    }
+
+    /// Send message with several attachments
+    ///
+    /// # Arguments
+    /// * `attachments` - Attachment strings, at most [`MAX_ATTACHMENTS`]
+    ///
+    /// # VK API
+    /// Method: messages.send (with comma-separated attachment parameter)
+    pub async fn send_with_attachments(
+        &self,
+        peer_id: i64,
+        message: &str,
+        attachments: &[String],
+    ) -> Result<SentMessage> {
+        self.send_with_params(
+            peer_id,
+            message,
+            None,
+            None,
+            Some(&attachments.join(",")),
+            new_random_id(),
+        )
+        .await
+    }
+
+    /// Send message with a `random_id` picked by the caller
+    ///
+    /// Lets a frontend store the id on its optimistic entry before sending,
+    /// so the result and the Long Poll echo can be matched to that entry.
+    /// VK also ignores a repeated send with the same `random_id`.
+    ///
+    /// # Arguments
+    /// * `random_id` - From [`new_random_id`]
+    /// * `attachments` - Attachment strings, at most [`MAX_ATTACHMENTS`]
+    ///
+    /// # VK API
+    /// Method: messages.send
+    pub async fn send_with_random_id(
+        &self,
+        peer_id: i64,
+        random_id: i64,
+        message: &str,
+        reply_to: Option<i64>,
+        attachments: &[String],
+    ) -> Result<SentMessage> {
+        let attachment = (!attachments.is_empty()).then(|| attachments.join(","));
+        self.send_with_params(
+            peer_id,
+            message,
+            reply_to,
+            None,
+            attachment.as_deref(),
+            random_id,
+        )
+        .await
     }
 
     /// Internal method to send message with various parameters
@@ -334,6 +567,7 @@ impl<'a> MessagesApi<'a> {
         reply_to: Option<i64>,
         forward_messages: Option<&[i64]>,
         attachment: Option<&str>,
+        random_id: i64,
     ) -> Result<SentMessage> {
         let mut params = HashMap::new();
         // Use peer_id as in web version
@@ -356,7 +590,7 @@ impl<'a> MessagesApi<'a> {
             params.insert("attachment", att.to_string());
         }
 
-        params.insert("random_id", generate_random_id().to_string());
+        params.insert("random_id", random_id.to_string());
 
         // Parse response as object with cmid and message_id
         // VK can return either an object with {message_id, cmid} or a plain integer (message_id)
@@ -366,20 +600,27 @@ impl<'a> MessagesApi<'a> {
             let message_id = obj
                 .get("message_id")
                 .and_then(|v| v.as_i64())
-                .context("messages.send response missing message_id")?;
+                .ok_or_else(|| {
+                    Error::UnexpectedResponse("messages.send response missing message_id".into())
+                })?;
             let cmid = obj.get("cmid").and_then(|v| v.as_i64()).unwrap_or(0);
 
             Ok(SentMessage {
                 message_id,
                 conversation_message_id: cmid,
+                random_id,
             })
         } else if let Some(message_id) = response.as_i64() {
             Ok(SentMessage {
                 message_id,
                 conversation_message_id: 0,
+                random_id,
             })
         } else {
-            anyhow::bail!("Unexpected messages.send response shape: {}", response);
+            Err(Error::UnexpectedResponse(format!(
+                "messages.send response shape: {}",
+                response
+            )))
         }
     }
 
@@ -392,6 +633,8 @@ impl<'a> MessagesApi<'a> {
     /// * `message_id` - Global message ID
     /// * `cmid` - Optional conversation message ID (for chats)
     /// * `message` - New message text
+    /// * `attachments` - Attachments the message keeps, as from
+    ///   [`Attachment::api_ref`]; VK removes any not listed
     ///
     /// # VK API
     /// Method: messages.edit
@@ -402,6 +645,7 @@ impl<'a> MessagesApi<'a> {
         message_id: i64,
         cmid: Option<i64>,
         message: &str,
+        attachments: &[String],
     ) -> Result<()> {
         let mut params = HashMap::new();
         params.insert("peer_id", peer_id.to_string());
@@ -415,6 +659,9 @@ impl<'a> MessagesApi<'a> {
             }
         };
         params.insert("message", message.to_string());
+        if !attachments.is_empty() {
+            params.insert("attachment", attachments.join(","));
+        }
         // Preserve forwards/snippets to mirror web behavior
         params.insert("keep_forward_messages", "1".into());
         params.insert("keep_snippets", "1".into());
@@ -445,10 +692,92 @@ impl<'a> MessagesApi<'a> {
         Ok(())
     }
 
+    /// Delete messages in one call, reporting which of them were deleted
+    ///
+    /// # Arguments
+    /// * `message_ids` - IDs of messages to delete
+    /// * `delete_for_all` - Delete for all participants (only for own messages)
+    ///
+    /// # VK API
+    /// Method: messages.delete
+    /// https://dev.vk.com/method/messages.delete
+    pub async fn delete_each(&self, message_ids: &[i64], delete_for_all: bool) -> Result<Vec<i64>> {
+        let mut params = HashMap::new();
+        let ids: Vec<String> = message_ids.iter().map(|id| id.to_string()).collect();
+        params.insert("message_ids", ids.join(","));
+
+        if delete_for_all {
+            params.insert("delete_for_all", "1".to_string());
+        }
+
+        let response: DeleteMessagesResponse =
+            self.client.request("messages.delete", params).await?;
+        Ok(response.deleted())
+    }
+
+    /// Delete a whole conversation for the current user
+    ///
+    /// The other participants keep their copy of the history.
+    ///
+    /// # Arguments
+    /// * `peer_id` - Peer ID of the conversation
+    ///
+    /// # VK API
+    /// Method: messages.deleteConversation
+    /// https://dev.vk.com/method/messages.deleteConversation
+    pub async fn delete_conversation(&self, peer_id: i64) -> Result<()> {
+        let mut params = HashMap::new();
+        params.insert("peer_id", peer_id.to_string());
+
+        let _: serde_json::Value = self
+            .client
+            .request("messages.deleteConversation", params)
+            .await?;
+        Ok(())
+    }
+
+    /// Pin a conversation to the top of the conversation list
+    ///
+    /// # Arguments
+    /// * `peer_id` - Peer ID of the conversation
+    ///
+    /// # VK API
+    /// Method: messages.pinConversation
+    /// https://dev.vk.com/method/messages.pinConversation
+    pub async fn pin_conversation(&self, peer_id: i64) -> Result<()> {
+        let mut params = HashMap::new();
+        params.insert("peer_id", peer_id.to_string());
+
+        let _: serde_json::Value = self
+            .client
+            .request("messages.pinConversation", params)
+            .await?;
+        Ok(())
+    }
+
+    /// Unpin a conversation pinned with [`pin_conversation`](Self::pin_conversation)
+    ///
+    /// # Arguments
+    /// * `peer_id` - Peer ID of the conversation
+    ///
+    /// # VK API
+    /// Method: messages.unpinConversation
+    /// https://dev.vk.com/method/messages.unpinConversation
+    pub async fn unpin_conversation(&self, peer_id: i64) -> Result<()> {
+        let mut params = HashMap::new();
+        params.insert("peer_id", peer_id.to_string());
+
+        let _: serde_json::Value = self
+            .client
+            .request("messages.unpinConversation", params)
+            .await?;
+        Ok(())
+    }
+
     /// Get messages by their IDs
     ///
     /// # Arguments
-    /// * `message_ids` - Array of message IDs (up to 100)
+    /// * `message_ids` - Array of message IDs (up to [`GET_BY_ID_MAX`])
     ///
     /// # VK API
     /// Method: messages.getById
@@ -460,6 +789,8 @@ impl<'a> MessagesApi<'a> {
 
         #[derive(Debug, serde::Deserialize)]
         struct Response {
+            #[allow(dead_code)]
+            count: u32,
             items: Vec<Message>,
         }
 
@@ -468,6 +799,37 @@ impl<'a> MessagesApi<'a> {
         Ok(response.items)
     }
 
+    /// Get any number of messages by their IDs in as few requests as
+    /// possible
+    ///
+    /// Up to [`GET_BY_ID_MAX`] ids are one `messages.getById` call; more
+    /// are split into several calls made together through `execute`.
+    /// Messages of a call that failed are missing from the result.
+    pub async fn get_by_id_batched(&self, message_ids: &[i64]) -> Result<Vec<Message>> {
+        if message_ids.len() <= GET_BY_ID_MAX {
+            return self.get_by_id(message_ids).await;
+        }
+
+        #[derive(Debug, serde::Deserialize)]
+        struct Response {
+            #[allow(dead_code)]
+            count: u32,
+            items: Vec<Message>,
+        }
+
+        let mut messages = Vec::new();
+        for ids in message_ids.chunks(GET_BY_ID_MAX * EXECUTE_MAX_CALLS) {
+            let mut batch = ExecuteBatch::new();
+            for chunk in ids.chunks(GET_BY_ID_MAX) {
+                let ids: Vec<String> = chunk.iter().map(|id| id.to_string()).collect();
+                batch.push("messages.getById", &[("message_ids", ids.join(","))]);
+            }
+            let results: Vec<Option<Response>> = self.client.execute_batch(&batch).await?;
+            messages.extend(results.into_iter().flatten().flat_map(|r| r.items));
+        }
+        Ok(messages)
+    }
+
     // ========== Search ==========
 
     /// Search messages
@@ -508,16 +870,16 @@ impl<'a> MessagesApi<'a> {
     /// * `count` - Number of results (max: 255, default: 20)
     ///
     /// # Returns
-    /// ConversationsResponse with matching conversations and profiles
+    /// Matching conversations
     ///
     /// # Example
     /// ```no_run
     /// # use vk_api::VkClient;
     /// # async fn example(client: VkClient) -> anyhow::Result<()> {
     /// // Find all chats with "Ivan" in the name
-    /// let result = client.messages().search_conversations("Ivan", 20).await?;
-    /// for item in result.items {
-    ///     println!("Found chat: {:?}", item.conversation.peer.id);
+    /// let conversations = client.messages().search_conversations("Ivan", 20).await?;
+    /// for conversation in conversations {
+    ///     println!("Found chat: {:?}", conversation.peer.id);
     /// }
     /// # Ok(())
     /// # }
@@ -536,7 +898,7 @@ impl<'a> MessagesApi<'a> {
         #[derive(Debug, serde::Deserialize)]
         struct Response {
             #[allow(dead_code)]
-            count: i32,
+            count: u32,
             items: Vec<Conversation>,
         }
 
@@ -548,6 +910,37 @@ impl<'a> MessagesApi<'a> {
         Ok(response.items)
     }
 
+    /// Get attachments of one type from a conversation, newest first
+    ///
+    /// # Arguments
+    /// * `peer_id` - Peer ID
+    /// * `media_type` - `photo`, `video`, `audio`, `doc`, `link`, `audio_message`...
+    /// * `start_from` - `next_from` of the previous page
+    /// * `count` - Number of attachments (max: 200)
+    ///
+    /// # VK API
+    /// Method: messages.getHistoryAttachments
+    /// https://dev.vk.com/method/messages.getHistoryAttachments
+    pub async fn get_history_attachments(
+        &self,
+        peer_id: i64,
+        media_type: &str,
+        start_from: Option<&str>,
+        count: u32,
+    ) -> Result<HistoryAttachmentsResponse> {
+        let mut params = HashMap::new();
+        params.insert("peer_id", peer_id.to_string());
+        params.insert("media_type", media_type.to_string());
+        params.insert("count", count.to_string());
+        if let Some(start_from) = start_from {
+            params.insert("start_from", start_from.to_string());
+        }
+
+        self.client
+            .request("messages.getHistoryAttachments", params)
+            .await
+    }
+
     // ========== Pin/Unpin ==========
 
     /// Pin message in conversation
@@ -624,6 +1017,23 @@ impl<'a> MessagesApi<'a> {
         Ok(())
     }
 
+    /// Remove your reaction from a message
+    ///
+    /// # VK API
+    /// Method: messages.deleteReaction
+    /// https://dev.vk.com/method/messages.deleteReaction
+    pub async fn delete_reaction(&self, peer_id: i64, cmid: i64) -> Result<()> {
+        let mut params = HashMap::new();
+        params.insert("peer_id", peer_id.to_string());
+        params.insert("cmid", cmid.to_string());
+
+        let _: i32 = self
+            .client
+            .request("messages.deleteReaction", params)
+            .await?;
+        Ok(())
+    }
+
     /// Get available reaction assets
     ///
     /// # VK API
@@ -654,6 +1064,23 @@ impl<'a> MessagesApi<'a> {
     /// 3. Saves photo
     /// 4. Sends message with photo attachment
     pub async fn send_photo(&self, peer_id: i64, photo_path: &Path) -> Result<SentMessage> {
+        let attachment = self.upload_photo(peer_id, photo_path, None).await?;
+        self.send_with_attachment(peer_id, "", &attachment).await
+    }
+
+    /// Upload and save a photo for a message, without sending it
+    ///
+    /// `progress` is called as the file is streamed to the upload server.
+    ///
+    /// # Returns
+    /// Attachment string (`photo<owner_id>_<id>`) for
+    /// [`send_with_attachment`](Self::send_with_attachment)
+    pub async fn upload_photo(
+        &self,
+        peer_id: i64,
+        photo_path: &Path,
+        progress: Option<UploadProgress>,
+    ) -> Result<String> {
         // Get upload server
         let mut server_params = HashMap::new();
         server_params.insert("peer_id", peer_id.to_string());
@@ -663,25 +1090,12 @@ impl<'a> MessagesApi<'a> {
             .await?;
 
         // Upload photo
-        let (boundary, body) = build_multipart_body(photo_path, "photo")?;
-        let response = self
-            .client
-            .http_client()
-            .post(&upload_server.upload_url)
-            .header(
-                reqwest::header::CONTENT_TYPE,
-                format!("multipart/form-data; boundary={}", boundary),
-            )
-            .body(body)
-            .send()
-            .await
-            .context("Photo upload failed")?;
-
-        let response_text = response.text().await?;
+        let response_text = self
+            .upload_file(&upload_server.upload_url, photo_path, "photo", progress)
+            .await?;
 
         // Parse upload response
-        let upload_json: serde_json::Value = serde_json::from_str(&response_text)
-            .context("Failed to parse photo upload response")?;
+        let upload_json: serde_json::Value = serde_json::from_str(&response_text)?;
 
         // Save photo
         let mut save_params: HashMap<&str, String> = HashMap::new();
@@ -701,13 +1115,10 @@ impl<'a> MessagesApi<'a> {
             .request("photos.saveMessagesPhoto", save_params)
             .await?;
 
-        let attachment = saved
+        saved
             .first()
             .map(|p| format!("photo{}_{}", p.owner_id, p.id))
-            .context("No saved photo returned")?;
-
-        // Send message with attachment
-        self.send_with_attachment(peer_id, "", &attachment).await
+            .ok_or_else(|| Error::UnexpectedResponse("no saved photo returned".into()))
     }
 
     /// Send document to peer (combines upload + save + send)
@@ -718,6 +1129,23 @@ impl<'a> MessagesApi<'a> {
     /// 3. Saves document
     /// 4. Sends message with document attachment
     pub async fn send_doc(&self, peer_id: i64, doc_path: &Path) -> Result<SentMessage> {
+        let attachment = self.upload_doc(peer_id, doc_path, None).await?;
+        self.send_with_attachment(peer_id, "", &attachment).await
+    }
+
+    /// Upload and save a document for a message, without sending it
+    ///
+    /// `progress` is called as the file is streamed to the upload server.
+    ///
+    /// # Returns
+    /// Attachment string (`doc<owner_id>_<id>`) for
+    /// [`send_with_attachment`](Self::send_with_attachment)
+    pub async fn upload_doc(
+        &self,
+        peer_id: i64,
+        doc_path: &Path,
+        progress: Option<UploadProgress>,
+    ) -> Result<String> {
         // Get upload server
         let mut params = HashMap::new();
         params.insert("type", "doc".to_string());
@@ -728,54 +1156,33 @@ impl<'a> MessagesApi<'a> {
             .await?;
 
         // Upload doc
-        let (boundary, body) = build_multipart_body(doc_path, "file")?;
-        let response = self
-            .client
-            .http_client()
-            .post(&upload_server.upload_url)
-            .header(
-                reqwest::header::CONTENT_TYPE,
-                format!("multipart/form-data; boundary={}", boundary),
-            )
-            .body(body)
-            .send()
-            .await
-            .context("Doc upload failed")?;
-
-        let response_text = response.text().await?;
-        let upload_json: serde_json::Value = serde_json::from_str(&response_text).map_err(|e| {
-            anyhow::anyhow!(
-                "Failed to parse doc upload response: {}; body: {}",
-                e,
-                response_text
-            )
-        })?;
+        let response_text = self
+            .upload_file(&upload_server.upload_url, doc_path, "file", progress)
+            .await?;
+        let upload_json: serde_json::Value =
+            serde_json::from_str(&response_text).inspect_err(|e| {
+                tracing::error!(
+                    "Failed to parse doc upload response: {}; body: {}",
+                    e,
+                    response_text
+                );
+            })?;
 
         // VK may return {file}, or {error, error_descr}
-        if let Some(err) = upload_json.get("error").and_then(|v| v.as_str()) {
-            let descr = upload_json
-                .get("error_descr")
-                .and_then(|v| v.as_str())
-                .unwrap_or_default();
-            anyhow::bail!(
-                "Upload error from VK: {}{}",
-                err,
-                if descr.is_empty() {
-                    "".to_string()
-                } else {
-                    format!(" ({})", descr)
-                }
-            );
+        if let Some(err) = upload_error(&upload_json) {
+            return Err(err);
         }
 
         let file_id = upload_json
             .get("file")
             .and_then(|v| v.as_str())
             .map(str::to_string)
-            .context(format!(
-                "Upload response missing file id; body: {}",
-                response_text
-            ))?;
+            .ok_or_else(|| {
+                Error::UnexpectedResponse(format!(
+                    "upload response missing file id; body: {}",
+                    response_text
+                ))
+            })?;
 
         // Save doc
         let mut save_params: HashMap<&str, String> = HashMap::new();
@@ -791,11 +1198,106 @@ impl<'a> MessagesApi<'a> {
         }
 
         let saved: Value = self.client.request("docs.save", save_params).await?;
-        let attachment = extract_doc_attachment(&saved)?;
+        extract_doc_attachment(&saved)
+    }
 
-        // Send message with attachment
-        self.send_with_attachment(peer_id, "", &attachment).await
+    /// Set the photo of a group chat from a local image
+    ///
+    /// Uploads the file to the chat photo upload server, then applies it.
+    ///
+    /// # Returns
+    /// URL of the new chat photo, if VK returned one
+    ///
+    /// # VK API
+    /// Methods: photos.getChatUploadServer, messages.setChatPhoto
+    /// https://dev.vk.com/method/messages.setChatPhoto
+    pub async fn set_chat_photo(&self, chat_id: i64, photo_path: &Path) -> Result<Option<String>> {
+        // Get upload server
+        let mut server_params = HashMap::new();
+        server_params.insert("chat_id", chat_id.to_string());
+        let upload_server: UploadServer = self
+            .client
+            .request("photos.getChatUploadServer", server_params)
+            .await?;
+
+        // Upload photo
+        let response_text = self
+            .upload_file(&upload_server.upload_url, photo_path, "file", None)
+            .await?;
+        let upload_json: serde_json::Value = serde_json::from_str(&response_text)?;
+        if let Some(err) = upload_error(&upload_json) {
+            return Err(err);
+        }
+
+        // The upload server answers {response: "<file>"}
+        let file = upload_json
+            .get("response")
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .ok_or_else(|| {
+                Error::UnexpectedResponse(format!(
+                    "chat photo upload response missing file; body: {}",
+                    response_text
+                ))
+            })?;
+
+        // Apply photo
+        let mut params = HashMap::new();
+        params.insert("file", file);
+        let applied: Value = self.client.request("messages.setChatPhoto", params).await?;
+
+        let chat = applied.get("chat");
+        Ok(["photo_50", "photo_100"]
+            .iter()
+            .find_map(|size| chat?.get(size)?.as_str())
+            .map(str::to_string))
     }
+
+    /// POST a file to an upload server as multipart form data and read the
+    /// reply. The file is streamed in chunks; a failed transfer is
+    /// [`Error::Upload`].
+    async fn upload_file(
+        &self,
+        upload_url: &str,
+        path: &Path,
+        field_name: &str,
+        progress: Option<UploadProgress>,
+    ) -> Result<String> {
+        let (boundary, body, len) = multipart_body(path, field_name, progress).await?;
+        let response = self
+            .client
+            .http_client()
+            .post(upload_url)
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .header(reqwest::header::CONTENT_LENGTH, len)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| Error::Upload(e.to_string()))?;
+
+        self.client.read_body(response).await
+    }
+}
+
+/// Error reported by an upload server as `{error, error_descr}`
+fn upload_error(upload_json: &Value) -> Option<Error> {
+    let err = upload_json.get("error").and_then(|v| v.as_str())?;
+    let descr = upload_json
+        .get("error_descr")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+    Some(Error::Upload(format!(
+        "{}{}",
+        err,
+        if descr.is_empty() {
+            "".to_string()
+        } else {
+            format!(" ({})", descr)
+        }
+    )))
 }
 
 /// Activity types for setActivity
@@ -814,8 +1316,8 @@ impl ActivityType {
     }
 }
 
-/// Generate random message ID for VK API
-fn generate_random_id() -> i64 {
+/// Random `random_id` for `messages.send`, never 0
+pub fn new_random_id() -> i64 {
     let mut rng = rand::thread_rng();
     let mut id: i64 = rng.r#gen::<u32>() as i64;
     if id == 0 {
@@ -824,44 +1326,71 @@ fn generate_random_id() -> i64 {
     id
 }
 
-/// Build a simple multipart/form-data body with a single file part
-fn build_multipart_body(path: &Path, field_name: &str) -> Result<(String, Vec<u8>)> {
-    use std::io::Write;
-
+/// Multipart/form-data body with a single file part, streamed from disk.
+///
+/// Returns the boundary, the body and its total length.
+async fn multipart_body(
+    path: &Path,
+    field_name: &str,
+    progress: Option<UploadProgress>,
+) -> Result<(String, reqwest::Body, u64)> {
     const MAX_UPLOAD_BYTES: u64 = 50 * 1024 * 1024; // 50 MB soft limit for TUI
 
-    let metadata = std::fs::metadata(path)?;
+    let metadata = tokio::fs::metadata(path).await?;
     if metadata.len() > MAX_UPLOAD_BYTES {
-        anyhow::bail!(
-            "File is too large ({} bytes, limit {} bytes)",
+        return Err(Error::Upload(format!(
+            "file is too large ({} bytes, limit {} bytes)",
             metadata.len(),
             MAX_UPLOAD_BYTES
-        );
+        )));
     }
 
-    let boundary = format!("vk_api_boundary_{}", generate_random_id());
-    let mut body = Vec::new();
+    let boundary = format!("vk_api_boundary_{}", new_random_id());
     let filename = path
         .file_name()
         .and_then(|n| n.to_str())
         .unwrap_or("file.bin");
-    let data = std::fs::read(path)?;
     let content_type = mime_guess::from_path(path)
         .first_or_octet_stream()
         .essence_str()
         .to_string();
 
-    write!(body, "--{}\r\n", boundary)?;
-    write!(
-        body,
-        "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n",
-        field_name, filename
-    )?;
-    write!(body, "Content-Type: {}\r\n\r\n", content_type)?;
-    body.extend_from_slice(&data);
-    write!(body, "\r\n--{}--\r\n", boundary)?;
-
-    Ok((boundary, body))
+    let head = format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+        boundary, field_name, filename, content_type
+    )
+    .into_bytes();
+    let tail = format!("\r\n--{}--\r\n", boundary).into_bytes();
+    let total = head.len() as u64 + metadata.len() + tail.len() as u64;
+
+    let file = tokio::fs::File::open(path).await?;
+    let chunks = stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let mut buf = vec![0; UPLOAD_CHUNK];
+        match file.read(&mut buf).await {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some((Ok(buf), Some(file)))
+            }
+            // End the stream after the error
+            Err(e) => Some((Err(e), None)),
+        }
+    });
+
+    let mut sent = 0;
+    let body = stream::once(async { Ok(head) })
+        .chain(chunks)
+        .chain(stream::once(async { Ok(tail) }))
+        .map(move |chunk: std::io::Result<Vec<u8>>| {
+            if let (Ok(bytes), Some(progress)) = (&chunk, &progress) {
+                sent += bytes.len() as u64;
+                progress(sent, total);
+            }
+            chunk
+        });
+
+    Ok((boundary, reqwest::Body::wrap_stream(body), total))
 }
 
 fn extract_doc_attachment(value: &Value) -> Result<String> {
@@ -894,10 +1423,10 @@ fn extract_doc_attachment(value: &Value) -> Result<String> {
         return Ok(format!("doc{}_{}", owner_id, id));
     }
 
-    Err(anyhow::anyhow!(
-        "Could not find doc id in docs.save response: {}",
+    Err(Error::UnexpectedResponse(format!(
+        "could not find doc id in docs.save response: {}",
         value
-    ))
+    )))
 }
 
 /// Reaction type
@@ -2,12 +2,14 @@
 
 pub mod account;
 pub mod friends;
+pub mod groups;
 pub mod longpoll;
 pub mod messages;
 pub mod users;
 
 pub use account::AccountApi;
 pub use friends::FriendsApi;
-pub use longpoll::LongPollApi;
-pub use messages::MessagesApi;
+pub use groups::GroupsApi;
+pub use longpoll::{LONG_POLL_MAX_WAIT, LONG_POLL_WAIT, LongPollApi};
+pub use messages::{GET_BY_ID_MAX, MAX_ATTACHMENTS, MessagesApi, UploadProgress, new_random_id};
 pub use users::UsersApi;
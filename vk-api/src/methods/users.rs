@@ -3,7 +3,7 @@
 //! Provides methods for working with VK users.
 //! References: https://dev.vk.com/method/users
 
-use anyhow::Result;
+use crate::error::Result;
 use std::collections::HashMap;
 
 use crate::client::VkClient;
@@ -23,18 +23,19 @@ impl<'a> UsersApi<'a> {
     ///
     /// # Arguments
     /// * `user_ids` - User IDs (max: 1000)
+    /// * `fields` - Extra profile fields, e.g. [`BASIC_USER_FIELDS`] or
+    ///   `&["city", "last_seen", "status"]`; empty for names only
     ///
     /// # VK API
     /// Method: users.get
     /// https://dev.vk.com/method/users.get
-    pub async fn get(&self, user_ids: &[i64]) -> Result<Vec<User>> {
+    pub async fn get(&self, user_ids: &[i64], fields: &[&str]) -> Result<Vec<User>> {
         let mut params = HashMap::new();
         let ids: Vec<String> = user_ids.iter().map(|id| id.to_string()).collect();
         params.insert("user_ids", ids.join(","));
-        params.insert(
-            "fields",
-            "photo_50,photo_100,online,last_seen,screen_name,verified".to_string(),
-        );
+        if !fields.is_empty() {
+            params.insert("fields", fields.join(","));
+        }
 
         self.client.request("users.get", params).await
     }
@@ -0,0 +1,133 @@
+//! Detection of response fields the types do not model.
+//!
+//! VK keeps adding fields to its responses. By default the client drops
+//! them silently ([`SchemaMode::Ignore`]), which hides schema drift such as
+//! new attachment kinds. [`SchemaMode::Log`] reports every unknown field
+//! once per method under the `vk_api::schema` tracing target, and
+//! [`SchemaMode::Strict`] rejects such responses so that test fixtures stay
+//! in sync with the types.
+//!
+//! Fields collected by an explicit catch-all map (like
+//! [`Attachment::other`](crate::Attachment::other)) are not unknown.
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use serde::de::DeserializeOwned;
+use serde_ignored::Path;
+
+use crate::error::{Error, Result};
+
+/// How the client treats response fields missing from the types.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchemaMode {
+    /// Drop unknown fields silently
+    #[default]
+    Ignore,
+    /// Drop unknown fields, logging each one once per method
+    Log,
+    /// Fail with [`Error::UnexpectedResponse`] listing the unknown fields
+    Strict,
+}
+
+/// Deserialize `T` from JSON text, also returning the paths of fields `T`
+/// ignored, e.g. `items[].last_message.reactions`.
+pub fn from_str_reporting<T: DeserializeOwned>(text: &str) -> Result<(T, Vec<String>)> {
+    let mut unknown = Vec::new();
+    let mut deserializer = serde_json::Deserializer::from_str(text);
+    let value =
+        serde_ignored::deserialize(&mut deserializer, |path| unknown.push(format_path(&path)))?;
+    deserializer.end()?;
+    Ok((value, unknown))
+}
+
+/// Deserialize `T`, failing if the value has fields `T` does not model.
+///
+/// Meant for test fixtures.
+pub fn from_value_strict<T: DeserializeOwned>(value: serde_json::Value) -> Result<T> {
+    let mut unknown = Vec::new();
+    let parsed = serde_ignored::deserialize(value, |path| unknown.push(format_path(&path)))?;
+    if unknown.is_empty() {
+        Ok(parsed)
+    } else {
+        Err(unknown_fields_error("value", &unknown))
+    }
+}
+
+/// Applies a [`SchemaMode`] to parsed responses, remembering what was logged.
+#[derive(Debug, Default)]
+pub(crate) struct SchemaCheck {
+    mode: SchemaMode,
+    /// (method, field path) pairs already logged
+    reported: Mutex<HashSet<(String, String)>>,
+}
+
+impl SchemaCheck {
+    pub(crate) fn new(mode: SchemaMode) -> Self {
+        Self {
+            mode,
+            reported: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Parse a response of `method`, reporting unknown fields per the mode.
+    ///
+    /// `reject` is false for error responses: the VK error should win over
+    /// the schema complaint.
+    pub(crate) fn parse<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        text: &str,
+        reject: impl FnOnce(&T) -> bool,
+    ) -> Result<T> {
+        if self.mode == SchemaMode::Ignore {
+            return Ok(serde_json::from_str(text)?);
+        }
+
+        let (value, unknown) = from_str_reporting::<T>(text)?;
+        if unknown.is_empty() {
+            return Ok(value);
+        }
+        if self.mode == SchemaMode::Strict && reject(&value) {
+            return Err(unknown_fields_error(method, &unknown));
+        }
+
+        if let Ok(mut reported) = self.reported.lock() {
+            for path in unknown {
+                if reported.insert((method.to_string(), path.clone())) {
+                    tracing::warn!(
+                        target: "vk_api::schema",
+                        "VK {}: unknown field {}",
+                        method,
+                        path
+                    );
+                }
+            }
+        }
+        Ok(value)
+    }
+}
+
+fn unknown_fields_error(what: &str, unknown: &[String]) -> Error {
+    Error::UnexpectedResponse(format!(
+        "unknown fields in {}: {}",
+        what,
+        unknown.join(", ")
+    ))
+}
+
+/// `items[].last_message.reactions`: array indices are dropped so that the
+/// same field in every element is reported once.
+fn format_path(path: &Path) -> String {
+    match path {
+        Path::Root => String::new(),
+        Path::Seq { parent, .. } => format!("{}[]", format_path(parent)),
+        Path::Map { parent, key } => match format_path(parent) {
+            parent if parent.is_empty() => key.clone(),
+            parent => format!("{}.{}", parent, key),
+        },
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => format_path(parent),
+    }
+}
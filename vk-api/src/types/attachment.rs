@@ -17,6 +17,101 @@ pub struct Attachment {
     pub other: std::collections::HashMap<String, serde_json::Value>,
 }
 
+impl Attachment {
+    /// Page of the attachment on vk.com, for kinds that have one.
+    ///
+    /// Wall posts, articles, market items, polls and the like cannot be
+    /// downloaded, but their page opens in a browser. Links give their
+    /// target URL.
+    pub fn web_url(&self) -> Option<String> {
+        let kind = self.attachment_type.as_str();
+        match kind {
+            "photo" => {
+                let photo = self.photo.as_ref()?;
+                Some(object_url(
+                    "photo",
+                    photo.owner_id,
+                    photo.id,
+                    photo.access_key.as_deref(),
+                ))
+            }
+            "doc" => {
+                let doc = self.doc.as_ref()?;
+                Some(object_url(
+                    "doc",
+                    doc.owner_id,
+                    doc.id,
+                    doc.access_key.as_deref(),
+                ))
+            }
+            "link" | "article" => {
+                let url = self.payload()?.get("url")?.as_str()?;
+                Some(url.to_string())
+            }
+            "market" => self.payload_url("product"),
+            "wall" | "poll" | "narrative" | "video" | "audio" | "story" => self.payload_url(kind),
+            _ => None,
+        }
+    }
+
+    /// The attachment as the `attachment` parameter of messages.send and
+    /// messages.edit takes it, e.g. `photo2_1_key`, for kinds a message can
+    /// carry again. Links, stickers and voice messages cannot.
+    pub fn api_ref(&self) -> Option<String> {
+        let kind = self.attachment_type.as_str();
+        let (owner_id, id, access_key) = match kind {
+            "photo" => {
+                let photo = self.photo.as_ref()?;
+                (photo.owner_id, photo.id, photo.access_key.as_deref())
+            }
+            "doc" => {
+                let doc = self.doc.as_ref()?;
+                (doc.owner_id, doc.id, doc.access_key.as_deref())
+            }
+            "video" | "audio" | "wall" | "market" | "poll" => self.payload_object()?,
+            _ => return None,
+        };
+        Some(match access_key.filter(|key| !key.is_empty()) {
+            Some(key) => format!("{}{}_{}_{}", kind, owner_id, id, key),
+            None => format!("{}{}_{}", kind, owner_id, id),
+        })
+    }
+
+    /// Object of an untyped attachment kind, e.g. `wall` for a wall post.
+    fn payload(&self) -> Option<&serde_json::Value> {
+        self.other.get(&self.attachment_type)
+    }
+
+    /// Owner, id and access key of the untyped payload.
+    fn payload_object(&self) -> Option<(i64, i64, Option<&str>)> {
+        let payload = self.payload()?;
+        let id = payload.get("id")?.as_i64()?;
+        // Wall posts of older API versions only name the wall as `to_id`
+        let owner_id = ["owner_id", "to_id", "from_id"]
+            .iter()
+            .find_map(|field| payload.get(field)?.as_i64())?;
+        let access_key = payload.get("access_key").and_then(|v| v.as_str());
+        Some((owner_id, id, access_key))
+    }
+
+    /// `https://vk.com/{prefix}{owner}_{id}` from the untyped payload.
+    fn payload_url(&self, prefix: &str) -> Option<String> {
+        let (owner_id, id, access_key) = self.payload_object()?;
+        Some(object_url(prefix, owner_id, id, access_key))
+    }
+}
+
+/// `https://vk.com/wall-1_2?access_key=...`; an empty key is left out.
+fn object_url(prefix: &str, owner_id: i64, id: i64, access_key: Option<&str>) -> String {
+    match access_key.filter(|key| !key.is_empty()) {
+        Some(key) => format!(
+            "https://vk.com/{}{}_{}?access_key={}",
+            prefix, owner_id, id, key
+        ),
+        None => format!("https://vk.com/{}{}_{}", prefix, owner_id, id),
+    }
+}
+
 /// Photo attachment
 #[derive(Debug, Clone, Deserialize, Serialize, Default)]
 pub struct Photo {
@@ -25,6 +120,10 @@ pub struct Photo {
 
     #[serde(default)]
     pub sizes: Vec<PhotoSize>,
+
+    /// Needed to view a photo that is not public
+    #[serde(default)]
+    pub access_key: Option<String>,
 }
 
 /// Photo size info
@@ -57,4 +156,8 @@ pub struct Doc {
 
     #[serde(default, rename = "ext")]
     pub extension: Option<String>,
+
+    /// Needed to view a document that is not public
+    #[serde(default)]
+    pub access_key: Option<String>,
 }
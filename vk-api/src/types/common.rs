@@ -5,6 +5,9 @@ use serde::{Deserialize, Serialize};
 pub struct VkResponse<T> {
     pub response: Option<T>,
     pub error: Option<VkError>,
+    /// Failed calls of an `execute` script; their results are `false`
+    #[serde(default)]
+    pub execute_errors: Vec<ExecuteError>,
 }
 
 /// VK API error
@@ -12,6 +15,40 @@ pub struct VkResponse<T> {
 pub struct VkError {
     pub error_code: i32,
     pub error_msg: String,
+
+    /// Captcha to solve, with error 14
+    #[serde(default)]
+    pub captcha_sid: Option<String>,
+
+    #[serde(default)]
+    pub captcha_img: Option<String>,
+}
+
+/// A call that failed inside an `execute` script
+#[derive(Debug, Deserialize)]
+pub struct ExecuteError {
+    pub method: String,
+    pub error_code: i32,
+    pub error_msg: String,
+}
+
+/// Offset added to a chat id to get its peer id
+pub const CHAT_PEER_OFFSET: i64 = 2_000_000_000;
+
+// Peer ids of chats past 147_483_647 no longer fit in i32, so ids stay i64
+const _: () = assert!(CHAT_PEER_OFFSET + 147_483_648 == i32::MAX as i64 + 1);
+
+/// Peer id of a group chat, or `None` if `chat_id` is not a valid chat id
+pub fn chat_peer_id(chat_id: i64) -> Option<i64> {
+    if chat_id <= 0 {
+        return None;
+    }
+    CHAT_PEER_OFFSET.checked_add(chat_id)
+}
+
+/// Whether `peer_id` refers to a group chat
+pub fn is_chat_peer(peer_id: i64) -> bool {
+    peer_id > CHAT_PEER_OFFSET
 }
 
 /// Peer info
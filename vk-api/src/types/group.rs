@@ -24,4 +24,10 @@ pub struct Group {
 
     #[serde(default, rename = "type")]
     pub group_type: Option<String>,
+
+    #[serde(default)]
+    pub status: Option<String>,
+
+    #[serde(default)]
+    pub members_count: Option<u32>,
 }
@@ -1,6 +1,8 @@
 use serde::Deserialize;
 
 use super::common::{deserialize_ts, deserialize_ts_option};
+use super::message::Message;
+use super::user::User;
 
 /// Long Poll server info
 #[derive(Debug, Clone, Deserialize)]
@@ -10,6 +12,10 @@ pub struct LongPollServer {
 
     #[serde(deserialize_with = "deserialize_ts")]
     pub ts: String,
+
+    /// Persistent event counter for messages.getLongPollHistory
+    #[serde(default)]
+    pub pts: Option<i64>,
 }
 
 /// Long Poll response
@@ -20,4 +26,33 @@ pub struct LongPollResponse {
 
     pub updates: Option<Vec<serde_json::Value>>,
     pub failed: Option<i32>,
+
+    /// Current pts (returned with mode flag 32)
+    #[serde(default)]
+    pub pts: Option<i64>,
+}
+
+/// Long Poll history response (messages.getLongPollHistory)
+#[derive(Debug, Deserialize)]
+pub struct LongPollHistory {
+    /// Missed events in the short Long Poll format (no text or extra fields)
+    #[serde(default)]
+    pub history: Vec<serde_json::Value>,
+
+    /// Full objects of the messages mentioned in `history`
+    #[serde(default)]
+    pub messages: Option<LongPollHistoryMessages>,
+
+    #[serde(default)]
+    pub profiles: Vec<User>,
+
+    #[serde(default)]
+    pub new_pts: Option<i64>,
+}
+
+/// Messages block of [`LongPollHistory`]
+#[derive(Debug, Deserialize)]
+pub struct LongPollHistoryMessages {
+    pub count: u32,
+    pub items: Vec<Message>,
 }
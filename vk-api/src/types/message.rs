@@ -11,10 +11,12 @@ use super::user::User;
 pub struct SentMessage {
     pub message_id: i64,
     pub conversation_message_id: i64,
+    /// `random_id` the message was sent with; Long Poll echoes it back
+    pub random_id: i64,
 }
 
 /// Message
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Message {
     /// Message ID (may be absent in forwarded messages)
     #[serde(default)]
@@ -53,6 +55,63 @@ pub struct Message {
     /// Update timestamp (present if message was edited)
     #[serde(default)]
     pub update_time: Option<i64>,
+
+    /// Client-generated send id (0 for messages sent without one)
+    #[serde(default)]
+    pub random_id: Option<i64>,
+
+    /// Service action (member joined, chat renamed...); such messages
+    /// usually have no text
+    #[serde(default)]
+    pub action: Option<MessageAction>,
+
+    /// Reaction the current user put on the message
+    #[serde(default)]
+    pub reaction_id: Option<i64>,
+
+    /// Reaction counters, one per reaction used
+    #[serde(default)]
+    pub reactions: Vec<MessageReaction>,
+}
+
+/// Counter of one reaction on a message
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MessageReaction {
+    pub reaction_id: i64,
+
+    #[serde(default)]
+    pub count: u32,
+
+    /// Some of the users who reacted
+    #[serde(default)]
+    pub user_ids: Vec<i64>,
+}
+
+/// Service action of a message
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MessageAction {
+    /// e.g. `chat_invite_user`, `chat_kick_user`, `chat_title_update`
+    #[serde(rename = "type")]
+    pub action_type: String,
+
+    /// User or community the action is about
+    #[serde(default)]
+    pub member_id: Option<i64>,
+
+    /// Chat title for `chat_create` and `chat_title_update`
+    #[serde(default)]
+    pub text: Option<String>,
+
+    /// Email of the invited user when `member_id` is negative
+    #[serde(default)]
+    pub email: Option<String>,
+
+    /// Pinned message for `chat_pin_message`
+    #[serde(default)]
+    pub conversation_message_id: Option<i64>,
+
+    #[serde(default)]
+    pub message: Option<String>,
 }
 
 impl Message {
@@ -69,6 +128,9 @@ impl Message {
 #[derive(Debug, Clone, Deserialize)]
 pub struct ConversationItem {
     pub conversation: Conversation,
+
+    /// Empty for a conversation without messages
+    #[serde(default)]
     pub last_message: Message,
 }
 
@@ -93,6 +155,53 @@ pub struct Conversation {
     /// ID of last outgoing message read by opponent
     #[serde(default)]
     pub out_read: Option<i64>,
+
+    /// Notification settings; absent when notifications are on
+    #[serde(default)]
+    pub push_settings: Option<PushSettings>,
+
+    /// Place of the conversation in the list; pinned ones have a major id
+    #[serde(default)]
+    pub sort_id: Option<SortId>,
+}
+
+impl Conversation {
+    /// Whether the conversation is pinned to the top of the list
+    pub fn is_pinned(&self) -> bool {
+        self.sort_id.is_some_and(|s| s.major_id > 0)
+    }
+}
+
+/// Sort key of a conversation in the list
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct SortId {
+    /// Position among pinned conversations; 0 when not pinned
+    #[serde(default)]
+    pub major_id: i64,
+
+    #[serde(default)]
+    pub minor_id: i64,
+}
+
+/// Notification settings of a conversation
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PushSettings {
+    /// Unix time until which notifications are off
+    #[serde(default)]
+    pub disabled_until: Option<i64>,
+
+    #[serde(default)]
+    pub disabled_forever: bool,
+
+    #[serde(default)]
+    pub no_sound: bool,
+}
+
+impl PushSettings {
+    /// Whether notifications are off at unix time `now`
+    pub fn is_muted_at(&self, now: i64) -> bool {
+        self.disabled_forever || self.disabled_until.is_some_and(|until| until > now)
+    }
 }
 
 /// Chat settings for group chats
@@ -101,10 +210,77 @@ pub struct ChatSettings {
     pub title: String,
 
     #[serde(default)]
-    pub members_count: Option<i32>,
+    pub members_count: Option<u32>,
 
     #[serde(default)]
     pub photo: Option<ChatPhoto>,
+
+    /// What the current user may do in the chat
+    #[serde(default)]
+    pub acl: Option<ChatAcl>,
+}
+
+/// Permissions of the current user in a group chat
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatAcl {
+    /// May change the title and photo
+    #[serde(default)]
+    pub can_change_info: bool,
+
+    #[serde(default)]
+    pub can_change_pin: bool,
+
+    #[serde(default)]
+    pub can_invite: bool,
+}
+
+/// Member of a group chat
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConversationMember {
+    /// User id, or negative community id for bots
+    pub member_id: i64,
+
+    #[serde(default)]
+    pub invited_by: Option<i64>,
+
+    #[serde(default)]
+    pub join_date: Option<i64>,
+
+    #[serde(default)]
+    pub is_admin: bool,
+
+    #[serde(default)]
+    pub is_owner: bool,
+
+    #[serde(default)]
+    pub can_kick: bool,
+}
+
+/// Chat members response (messages.getConversationMembers)
+#[derive(Debug, Deserialize)]
+pub struct ConversationMembersResponse {
+    pub count: u32,
+    pub items: Vec<ConversationMember>,
+
+    #[serde(default)]
+    pub profiles: Vec<User>,
+
+    #[serde(default)]
+    pub groups: Vec<Group>,
+}
+
+/// Readers of a message (messages.getMessageReadPeers)
+#[derive(Debug, Deserialize)]
+pub struct ReadPeersResponse {
+    pub count: u32,
+    /// User ids, or negative community ids
+    pub items: Vec<i64>,
+
+    #[serde(default)]
+    pub profiles: Vec<User>,
+
+    #[serde(default)]
+    pub groups: Vec<Group>,
 }
 
 /// Chat photo
@@ -120,7 +296,7 @@ pub struct ChatPhoto {
 /// Messages history response
 #[derive(Debug, Deserialize)]
 pub struct MessagesHistoryResponse {
-    pub count: i32,
+    pub count: u32,
     pub items: Vec<Message>,
 
     #[serde(default)]
@@ -137,7 +313,7 @@ pub struct MessagesHistoryResponse {
 /// Conversations list response
 #[derive(Debug, Deserialize)]
 pub struct ConversationsResponse {
-    pub count: i32,
+    pub count: u32,
     pub items: Vec<ConversationItem>,
 
     #[serde(default)]
@@ -150,7 +326,7 @@ pub struct ConversationsResponse {
 /// Search messages response (extended)
 #[derive(Debug, Deserialize)]
 pub struct SearchResponse {
-    pub count: i32,
+    pub count: u32,
     pub items: Vec<Message>,
 
     #[serde(default)]
@@ -162,3 +338,80 @@ pub struct SearchResponse {
     #[serde(default)]
     pub conversations: Vec<Conversation>,
 }
+
+/// Attachment found by messages.getHistoryAttachments
+#[derive(Debug, Clone, Deserialize)]
+pub struct HistoryAttachment {
+    pub message_id: i64,
+
+    #[serde(default)]
+    pub from_id: i64,
+
+    #[serde(default)]
+    pub cmid: Option<i64>,
+
+    #[serde(default)]
+    pub date: Option<i64>,
+
+    pub attachment: Attachment,
+}
+
+/// Page of chat attachments (messages.getHistoryAttachments)
+///
+/// VK does not report a total; `next_from` is set while more pages remain.
+#[derive(Debug, Deserialize)]
+pub struct HistoryAttachmentsResponse {
+    pub items: Vec<HistoryAttachment>,
+
+    #[serde(default)]
+    pub next_from: Option<String>,
+}
+
+/// Outcome of messages.delete for each message
+///
+/// Older API versions answer with an object mapping ids to `1`; newer ones
+/// with a list holding `response: 1` or an `error` per message.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum DeleteMessagesResponse {
+    Items(Vec<DeletedMessage>),
+    ById(std::collections::HashMap<String, i64>),
+}
+
+impl DeleteMessagesResponse {
+    /// Ids of the messages that were deleted
+    pub fn deleted(&self) -> Vec<i64> {
+        match self {
+            Self::Items(items) => items
+                .iter()
+                .filter(|item| item.response == Some(1))
+                .map(|item| item.message_id)
+                .collect(),
+            Self::ById(by_id) => by_id
+                .iter()
+                .filter(|(_, ok)| **ok == 1)
+                .filter_map(|(id, _)| id.parse().ok())
+                .collect(),
+        }
+    }
+}
+
+/// One message in a [`DeleteMessagesResponse`]
+#[derive(Debug, Deserialize)]
+pub struct DeletedMessage {
+    #[serde(default)]
+    pub peer_id: i64,
+
+    #[serde(default)]
+    pub message_id: i64,
+
+    #[serde(default)]
+    pub conversation_message_id: Option<i64>,
+
+    /// `1` when deleted
+    #[serde(default)]
+    pub response: Option<i64>,
+
+    #[serde(default)]
+    pub error: Option<serde_json::Value>,
+}
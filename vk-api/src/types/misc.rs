@@ -33,6 +33,10 @@ pub struct Counters {
 /// Profile information
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ProfileInfo {
+    /// Account id; older API versions leave it out
+    #[serde(default)]
+    pub id: i64,
+
     pub first_name: String,
     pub last_name: String,
 
@@ -55,6 +59,12 @@ pub struct ProfileInfo {
     pub home_town: Option<String>,
 }
 
+impl ProfileInfo {
+    pub fn full_name(&self) -> String {
+        format!("{} {}", self.first_name, self.last_name)
+    }
+}
+
 /// Can write status for conversations
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CanWrite {
@@ -11,13 +11,17 @@ pub mod user;
 
 // Re-export commonly used types
 pub use attachment::{Attachment, Doc, Photo, PhotoSize};
-pub use common::{Peer, VkError, VkResponse};
+pub use common::{
+    CHAT_PEER_OFFSET, ExecuteError, Peer, VkError, VkResponse, chat_peer_id, is_chat_peer,
+};
 pub use group::Group;
-pub use longpoll::{LongPollResponse, LongPollServer};
+pub use longpoll::{LongPollHistory, LongPollHistoryMessages, LongPollResponse, LongPollServer};
 pub use message::{
-    ChatPhoto, ChatSettings, Conversation, ConversationItem, ConversationsResponse, Message,
-    MessagesHistoryResponse, SearchResponse, SentMessage,
+    ChatAcl, ChatPhoto, ChatSettings, Conversation, ConversationItem, ConversationMember,
+    ConversationMembersResponse, ConversationsResponse, DeleteMessagesResponse, DeletedMessage,
+    HistoryAttachment, HistoryAttachmentsResponse, Message, MessageAction, MessageReaction,
+    MessagesHistoryResponse, PushSettings, ReadPeersResponse, SearchResponse, SentMessage, SortId,
 };
 pub use misc::{CanWrite, City, Counters, Country, ProfileInfo};
 pub use upload::{DocInfo, SavedDoc, SavedPhoto, UploadDocResponse, UploadServer};
-pub use user::{LastSeen, User};
+pub use user::{BASIC_USER_FIELDS, CONVERSATION_USER_FIELDS, LastSeen, User};
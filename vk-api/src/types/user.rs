@@ -1,5 +1,13 @@
 use serde::{Deserialize, Serialize};
 
+use super::misc::City;
+
+/// Fields behind names and avatars, enough for chat lists and history
+pub const BASIC_USER_FIELDS: &[&str] = &["photo_50", "photo_100", "online"];
+
+/// Basic fields plus when the user was last online, for conversation headers
+pub const CONVERSATION_USER_FIELDS: &[&str] = &["photo_50", "photo_100", "online", "last_seen"];
+
 /// User info
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct User {
@@ -13,11 +21,26 @@ pub struct User {
     #[serde(default)]
     pub photo_100: Option<String>,
 
+    #[serde(default)]
+    pub photo_200: Option<String>,
+
     #[serde(default)]
     pub online: Option<i32>,
 
+    #[serde(default)]
+    pub last_seen: Option<LastSeen>,
+
     #[serde(default)]
     pub screen_name: Option<String>,
+
+    #[serde(default)]
+    pub status: Option<String>,
+
+    #[serde(default)]
+    pub bdate: Option<String>,
+
+    #[serde(default)]
+    pub city: Option<City>,
 }
 
 impl User {
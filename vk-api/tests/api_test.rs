@@ -0,0 +1,243 @@
+//! `MessagesApi` and `LongPollApi` against recorded VK responses
+//!
+//! Responses come from the fixtures of `vk-test-support`, served by its
+//! mock. The client checks them in strict schema mode.
+
+use vk_api::Error;
+use vk_api::error::{ERROR_CAPTCHA_NEEDED, ERROR_TOO_MANY_REQUESTS};
+use vk_test_support::{LONG_POLL_PATH, MockVk, fixture};
+
+#[tokio::test]
+async fn parses_conversations() {
+    let mock = MockVk::start().await;
+    mock.respond("messages.getConversations", fixture("get_conversations"));
+
+    let response = mock
+        .client()
+        .messages()
+        .get_conversations(0, 20)
+        .await
+        .unwrap();
+
+    assert_eq!(response.count, 2);
+    let chat = &response.items[0];
+    assert_eq!(chat.conversation.peer.id, 2000000001);
+    assert_eq!(chat.conversation.unread_count, Some(2));
+    assert!(chat.conversation.is_pinned());
+    assert_eq!(
+        chat.conversation.chat_settings.as_ref().unwrap().title,
+        "Weekend trip"
+    );
+    assert_eq!(chat.last_message.text, "See you at 9");
+
+    let dialog = &response.items[1];
+    assert!(!dialog.conversation.is_pinned());
+    assert!(!dialog.conversation.can_write.as_ref().unwrap().allowed);
+    assert!(dialog.last_message.is_outgoing());
+    assert_eq!(dialog.last_message.update_time, Some(1700000160));
+    assert_eq!(response.profiles[0].full_name(), "Anna Petrova");
+
+    let call = &mock.calls_to("messages.getConversations")[0];
+    assert_eq!(call.param("count"), Some("20"));
+    assert_eq!(call.param("access_token"), Some("test-token"));
+}
+
+#[tokio::test]
+async fn parses_history() {
+    let mock = MockVk::start().await;
+    mock.respond("messages.getHistory", fixture("get_history"));
+
+    let history = mock
+        .client()
+        .messages()
+        .get_history(2000000001, 0, 3)
+        .await
+        .unwrap();
+
+    assert_eq!(history.count, 58);
+    let [reply, photo, service] = &history.items[..] else {
+        panic!("expected 3 messages, got {}", history.items.len());
+    };
+    let quoted = reply.reply_message.as_ref().unwrap();
+    assert_eq!(quoted.conversation_message_id, Some(56));
+    assert_eq!(quoted.id, 0);
+    assert_eq!(reply.reactions[0].count, 2);
+
+    assert_eq!(photo.random_id, Some(987654));
+    assert_eq!(photo.attachments[0].attachment_type, "photo");
+    assert_eq!(photo.attachments[0].photo.as_ref().unwrap().sizes.len(), 1);
+
+    assert_eq!(
+        service.action.as_ref().unwrap().action_type,
+        "chat_invite_user"
+    );
+    assert_eq!(history.conversations[0].out_read, Some(121));
+    assert_eq!(history.profiles.len(), 3);
+}
+
+#[tokio::test]
+async fn send_accepts_id_and_object_responses() {
+    let mock = MockVk::start().await;
+    mock.respond("messages.send", r#"{"response":130}"#)
+        .respond(
+            "messages.send",
+            r#"{"response":{"message_id":131,"cmid":61}}"#,
+        );
+    let client = mock.client();
+
+    let plain = client.messages().send(2, "hi").await.unwrap();
+    let extended = client.messages().send(2, "hi again").await.unwrap();
+
+    assert_eq!(plain.message_id, 130);
+    assert_eq!(plain.conversation_message_id, 0);
+    assert_eq!(extended.message_id, 131);
+    assert_eq!(extended.conversation_message_id, 61);
+
+    // The random id Long Poll echoes back is the one sent
+    let calls = mock.calls_to("messages.send");
+    assert_eq!(
+        calls[0].param("random_id"),
+        Some(plain.random_id.to_string().as_str())
+    );
+    assert_eq!(calls[1].param("message"), Some("hi+again"));
+}
+
+#[tokio::test]
+async fn send_uses_the_given_random_id() {
+    let mock = MockVk::start().await;
+    mock.respond("messages.send", r#"{"response":130}"#);
+    let client = mock.client();
+
+    let sent = client
+        .messages()
+        .send_with_random_id(2, 4242, "hi", Some(120), &["photo1_2".into()])
+        .await
+        .unwrap();
+
+    assert_eq!(sent.random_id, 4242);
+    let calls = mock.calls_to("messages.send");
+    assert_eq!(calls[0].param("random_id"), Some("4242"));
+    assert_eq!(calls[0].param("reply_to"), Some("120"));
+    assert_eq!(calls[0].param("attachment"), Some("photo1_2"));
+}
+
+#[tokio::test]
+async fn long_poll_gets_server_and_polls() {
+    let mock = MockVk::start().await;
+    mock.respond(
+        "messages.getLongPollServer",
+        fixture("get_long_poll_server").replace("{server}", &mock.long_poll_server()),
+    );
+    mock.respond(LONG_POLL_PATH, fixture("long_poll_updates"))
+        .respond(LONG_POLL_PATH, r#"{"failed":2}"#);
+    let client = mock.client();
+
+    let server = client.longpoll().get_server().await.unwrap();
+    assert_eq!(server.ts, "1874635611");
+    assert_eq!(server.pts, Some(10443203));
+
+    let response = client.longpoll().poll(&server).await.unwrap();
+    assert_eq!(response.ts.as_deref(), Some("1874635613"));
+    let updates = response.updates.unwrap();
+    assert_eq!(updates.len(), 2);
+    assert_eq!(updates[0][5], "Boarding now");
+
+    let poll = &mock.calls_to(LONG_POLL_PATH)[0];
+    assert_eq!(poll.param("act"), Some("a_check"));
+    assert_eq!(poll.param("key"), Some("4e1b2a3c5d"));
+    assert_eq!(poll.param("ts"), Some("1874635611"));
+
+    // An expired key is a response to handle, not an error
+    let expired = client.longpoll().poll(&server).await.unwrap();
+    assert_eq!(expired.failed, Some(2));
+}
+
+#[tokio::test]
+async fn expired_token_is_auth_error_without_retry() {
+    let mock = MockVk::start().await;
+    mock.respond_error(
+        "messages.getConversations",
+        5,
+        "User authorization failed: invalid access_token (4).",
+    );
+
+    let err = mock
+        .client()
+        .messages()
+        .get_conversations(0, 20)
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, Error::Auth));
+    assert!(err.is_auth());
+    assert_eq!(mock.calls().len(), 1);
+}
+
+#[tokio::test]
+async fn rate_limit_is_retried() {
+    let mock = MockVk::start().await;
+    mock.respond_error(
+        "messages.getHistory",
+        ERROR_TOO_MANY_REQUESTS,
+        "Too many requests per second",
+    )
+    .respond("messages.getHistory", fixture("get_history"));
+
+    let history = mock
+        .client()
+        .messages()
+        .get_history(2000000001, 0, 3)
+        .await
+        .unwrap();
+
+    assert_eq!(history.items.len(), 3);
+    assert_eq!(mock.calls_to("messages.getHistory").len(), 2);
+}
+
+const CAPTCHA: &str = r#"{"error":{"error_code":14,"error_msg":"Captcha needed","captcha_sid":"548747100691","captcha_img":"https://api.vk.com/captcha.php?sid=548747100691"}}"#;
+
+#[tokio::test]
+async fn captcha_is_reported_without_retry() {
+    let mock = MockVk::start().await;
+    mock.respond("messages.send", CAPTCHA);
+
+    let err = mock.client().messages().send(2, "hi").await.unwrap_err();
+
+    match &err {
+        Error::Captcha { sid, img_url } => {
+            assert_eq!(sid, "548747100691");
+            assert_eq!(img_url, "https://api.vk.com/captcha.php?sid=548747100691");
+        }
+        other => panic!("expected Captcha, got {:?}", other),
+    }
+    assert_eq!(err.code(), Some(ERROR_CAPTCHA_NEEDED));
+    assert!(!err.is_retriable());
+    assert!(!err.is_auth());
+    assert_eq!(mock.calls().len(), 1);
+}
+
+#[tokio::test]
+async fn captcha_answer_goes_with_the_repeated_call() {
+    let mock = MockVk::start().await;
+    mock.respond("messages.send", CAPTCHA)
+        .respond("messages.send", r#"{"response":132}"#)
+        .respond("messages.getConversations", fixture("get_conversations"));
+    let client = mock.client();
+
+    assert!(!client.answer_captcha("548747100691", "x7k2q"));
+    client.messages().send(2, "hi").await.unwrap_err();
+    assert!(client.answer_captcha("548747100691", "x7k2q"));
+
+    // Other methods do not take the answer
+    client.messages().get_conversations(0, 20).await.unwrap();
+    let sent = client.messages().send(2, "hi").await.unwrap();
+    client.messages().send(2, "hi").await.unwrap();
+
+    assert_eq!(sent.message_id, 132);
+    let calls = mock.calls();
+    assert_eq!(calls[1].param("captcha_key"), None);
+    let sends = mock.calls_to("messages.send");
+    assert_eq!(sends[1].param("captcha_sid"), Some("548747100691"));
+    assert_eq!(sends[1].param("captcha_key"), Some("x7k2q"));
+    assert_eq!(sends[2].param("captcha_sid"), None);
+}
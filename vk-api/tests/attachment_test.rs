@@ -0,0 +1,119 @@
+//! Web links of attachments that cannot be downloaded, and the references
+//! that attach them to a message again
+//!
+//! The URL formats are what vk.com itself uses; community owners are
+//! negative and private objects need their access key.
+
+use serde_json::json;
+use vk_api::Attachment;
+
+fn web_url(value: serde_json::Value) -> Option<String> {
+    serde_json::from_value::<Attachment>(value)
+        .unwrap()
+        .web_url()
+}
+
+fn api_ref(value: serde_json::Value) -> Option<String> {
+    serde_json::from_value::<Attachment>(value)
+        .unwrap()
+        .api_ref()
+}
+
+#[test]
+fn wall_post() {
+    assert_eq!(
+        web_url(json!({"type": "wall", "wall": {"id": 42, "owner_id": -1234, "from_id": 5}})),
+        Some("https://vk.com/wall-1234_42".into())
+    );
+    // Older payloads name the wall only as `to_id`
+    assert_eq!(
+        web_url(json!({"type": "wall", "wall": {"id": 7, "to_id": 99, "from_id": 5}})),
+        Some("https://vk.com/wall99_7".into())
+    );
+}
+
+#[test]
+fn access_key_is_appended() {
+    assert_eq!(
+        web_url(json!({
+            "type": "video",
+            "video": {"id": 456239017, "owner_id": -22822305, "access_key": "a1b2c3"}
+        })),
+        Some("https://vk.com/video-22822305_456239017?access_key=a1b2c3".into())
+    );
+    assert_eq!(
+        web_url(json!({
+            "type": "photo",
+            "photo": {"id": 1, "owner_id": 2, "sizes": [], "access_key": "k"}
+        })),
+        Some("https://vk.com/photo2_1?access_key=k".into())
+    );
+    assert_eq!(
+        web_url(json!({"type": "poll", "poll": {"id": 3, "owner_id": -4, "access_key": ""}})),
+        Some("https://vk.com/poll-4_3".into())
+    );
+}
+
+#[test]
+fn market_article_and_narrative() {
+    assert_eq!(
+        web_url(json!({"type": "market", "market": {"id": 10, "owner_id": -20}})),
+        Some("https://vk.com/product-20_10".into())
+    );
+    assert_eq!(
+        web_url(json!({
+            "type": "article",
+            "article": {"id": 5, "owner_id": -6, "url": "https://m.vk.com/@club6-how-to"}
+        })),
+        Some("https://m.vk.com/@club6-how-to".into())
+    );
+    assert_eq!(
+        web_url(json!({"type": "narrative", "narrative": {"id": 8, "owner_id": -9}})),
+        Some("https://vk.com/narrative-9_8".into())
+    );
+}
+
+#[test]
+fn kinds_without_a_page() {
+    assert_eq!(
+        web_url(json!({"type": "sticker", "sticker": {"sticker_id": 1}})),
+        None
+    );
+    // Missing ids give no link rather than a broken one
+    assert_eq!(web_url(json!({"type": "wall", "wall": {"id": 1}})), None);
+}
+
+#[test]
+fn api_refs_keep_access_keys() {
+    assert_eq!(
+        api_ref(json!({
+            "type": "photo",
+            "photo": {"id": 1, "owner_id": 2, "sizes": [], "access_key": "k"}
+        })),
+        Some("photo2_1_k".into())
+    );
+    assert_eq!(
+        api_ref(json!({"type": "doc", "doc": {"id": 7, "owner_id": -3}})),
+        Some("doc-3_7".into())
+    );
+    assert_eq!(
+        api_ref(json!({
+            "type": "video",
+            "video": {"id": 456239017, "owner_id": -22822305, "access_key": "a1b2c3"}
+        })),
+        Some("video-22822305_456239017_a1b2c3".into())
+    );
+    assert_eq!(
+        api_ref(json!({"type": "wall", "wall": {"id": 7, "to_id": 99, "from_id": 5}})),
+        Some("wall99_7".into())
+    );
+    // Links come back as snippets; stickers cannot be edited in
+    assert_eq!(
+        api_ref(json!({"type": "link", "link": {"url": "https://example.com"}})),
+        None
+    );
+    assert_eq!(
+        api_ref(json!({"type": "sticker", "sticker": {"sticker_id": 1}})),
+        None
+    );
+}
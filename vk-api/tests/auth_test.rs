@@ -0,0 +1,243 @@
+//! Token storage, keyring first with the token file as fallback, the
+//! scope check at login and the local OAuth redirect catcher
+//!
+//! The keyring is an in-memory mock; the token file goes to a directory
+//! of its own under the system temp dir.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use vk_api::Error;
+use vk_api::auth::{
+    AuthManager, SCOPE_FRIENDS, SCOPE_MESSAGES, SCOPE_OFFLINE, SecretStore, missing_scopes,
+};
+
+const REDIRECT: &str =
+    "https://oauth.vk.com/blank.html#access_token=abc123&expires_in=0&user_id=42";
+
+/// Keyring kept in memory; `broken` makes every call fail.
+#[derive(Clone, Default)]
+struct MockKeyring {
+    secret: Arc<Mutex<Option<String>>>,
+    broken: bool,
+}
+
+impl MockKeyring {
+    fn broken() -> Self {
+        Self {
+            broken: true,
+            ..Self::default()
+        }
+    }
+
+    fn secret(&self) -> Option<String> {
+        self.secret.lock().unwrap().clone()
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.broken {
+            anyhow::bail!("no secret service");
+        }
+        Ok(())
+    }
+}
+
+impl SecretStore for MockKeyring {
+    fn get(&self) -> Result<Option<String>> {
+        self.check()?;
+        Ok(self.secret())
+    }
+
+    fn set(&self, secret: &str) -> Result<()> {
+        self.check()?;
+        *self.secret.lock().unwrap() = Some(secret.to_string());
+        Ok(())
+    }
+
+    fn delete(&self) -> Result<()> {
+        self.check()?;
+        *self.secret.lock().unwrap() = None;
+        Ok(())
+    }
+}
+
+/// Token file path in a fresh directory for the test `name`.
+fn token_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("vk_api_auth_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir.join("token.json")
+}
+
+fn with_keyring(path: &Path, keyring: &MockKeyring) -> AuthManager {
+    AuthManager::with_store(path.to_path_buf(), Some(Box::new(keyring.clone())))
+}
+
+#[test]
+fn token_goes_to_keyring() {
+    let path = token_path("keyring");
+    let keyring = MockKeyring::default();
+
+    let mut auth = with_keyring(&path, &keyring);
+    auth.save_token_from_url(REDIRECT).unwrap();
+    assert!(keyring.secret().unwrap().contains("abc123"));
+    assert!(!path.exists());
+
+    let auth = with_keyring(&path, &keyring);
+    assert_eq!(auth.access_token(), Some("abc123"));
+    assert_eq!(auth.user_id(), Some(42));
+}
+
+#[test]
+fn file_without_keyring() {
+    let path = token_path("file");
+
+    let mut auth = AuthManager::with_store(path.clone(), None);
+    auth.save_token_from_url(REDIRECT).unwrap();
+    assert!(!auth.uses_keyring());
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    let auth = AuthManager::with_store(path, None);
+    assert_eq!(auth.access_token(), Some("abc123"));
+}
+
+#[test]
+fn broken_keyring_falls_back_to_file() {
+    let path = token_path("broken");
+
+    let mut auth = with_keyring(&path, &MockKeyring::broken());
+    assert!(!auth.uses_keyring());
+    auth.save_token_from_url(REDIRECT).unwrap();
+    assert!(path.exists());
+
+    let auth = with_keyring(&path, &MockKeyring::broken());
+    assert_eq!(auth.access_token(), Some("abc123"));
+}
+
+#[test]
+fn plaintext_token_is_migrated() {
+    let path = token_path("migrate");
+    AuthManager::with_store(path.clone(), None)
+        .save_token_from_url(REDIRECT)
+        .unwrap();
+    assert!(path.exists());
+
+    let keyring = MockKeyring::default();
+    let auth = with_keyring(&path, &keyring);
+    assert_eq!(auth.access_token(), Some("abc123"));
+    assert!(keyring.secret().is_some());
+    assert!(!path.exists());
+}
+
+#[test]
+fn logout_clears_both() {
+    let path = token_path("logout");
+    let keyring = MockKeyring::default();
+    keyring.set("{}").unwrap();
+    AuthManager::with_store(path.clone(), None)
+        .save_token_from_url(REDIRECT)
+        .unwrap();
+
+    let mut auth = with_keyring(&path, &keyring);
+    auth.logout().unwrap();
+    assert!(!auth.is_authenticated());
+    assert_eq!(keyring.secret(), None);
+    assert!(!path.exists());
+}
+
+#[test]
+fn messages_scope_is_required() {
+    assert_eq!(
+        missing_scopes(SCOPE_MESSAGES | SCOPE_OFFLINE),
+        Vec::<&str>::new()
+    );
+    assert_eq!(missing_scopes(SCOPE_FRIENDS | SCOPE_OFFLINE), ["messages"]);
+    // Mask of the scopes in the auth URL
+    assert_eq!(missing_scopes(69638), Vec::<&str>::new());
+
+    assert_eq!(
+        Error::MissingScopes(vec!["messages"]).to_string(),
+        "Token lacks 'messages' scope — regenerate the auth URL and re-authorize"
+    );
+    assert_eq!(
+        Error::MissingScopes(vec!["messages", "photos"]).to_string(),
+        "Token lacks 'messages', 'photos' scopes — regenerate the auth URL and re-authorize"
+    );
+}
+
+/// Body of the response to `GET target` from the catcher on `port`.
+async fn get(port: u16, target: &str) -> String {
+    let mut socket = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target);
+    socket.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    socket.read_to_string(&mut response).await.unwrap();
+    response.split("\r\n\r\n").nth(1).unwrap_or("").to_string()
+}
+
+#[tokio::test]
+async fn redirect_listener_catches_token() {
+    let listener = AuthManager::start_redirect_listener(0).await.unwrap();
+    let port = listener.port();
+    assert!(
+        listener
+            .auth_url()
+            .contains(&format!("redirect_uri=http://127.0.0.1:{}", port))
+    );
+    let wait = tokio::spawn(listener.wait(Duration::from_secs(10)));
+
+    // A connection that never sends a request does not block the others
+    let _idle = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+
+    // The redirect itself gets the page that forwards the fragment
+    assert!(get(port, "/").await.contains("location.hash"));
+    assert_eq!(
+        get(port, "/token?access_token=abc123&expires_in=0&user_id=42").await,
+        "OK"
+    );
+
+    let url = wait.await.unwrap().unwrap();
+    let path = token_path("redirect");
+    let mut auth = AuthManager::with_store(path, None);
+    auth.save_token_from_url(&url).unwrap();
+    assert_eq!(auth.access_token(), Some("abc123"));
+}
+
+#[tokio::test]
+async fn redirect_listener_errors_are_readable() {
+    let first = AuthManager::start_redirect_listener(0).await.unwrap();
+    let Err(e) = AuthManager::start_redirect_listener(first.port()).await else {
+        panic!("second listener on the same port");
+    };
+    assert!(e.to_string().contains("already in use"), "{}", e);
+
+    let e = first.wait(Duration::from_millis(50)).await.unwrap_err();
+    assert!(
+        e.to_string().starts_with("No redirect from the browser"),
+        "{}",
+        e
+    );
+
+    let listener = AuthManager::start_redirect_listener(0).await.unwrap();
+    let port = listener.port();
+    let wait = tokio::spawn(listener.wait(Duration::from_secs(10)));
+    get(
+        port,
+        "/?error=access_denied&error_description=User+denied+your+request",
+    )
+    .await;
+    let e = wait.await.unwrap().unwrap_err();
+    assert_eq!(
+        e.to_string(),
+        "Authorization failed: User denied your request"
+    );
+}
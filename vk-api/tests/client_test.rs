@@ -0,0 +1,571 @@
+//! Tests for request throttling, retries, batching, response limits and proxies in `VkClient`
+//!
+//! A tiny HTTP server on localhost replays canned VK responses, so these
+//! tests run without network access or a real token.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use vk_api::{EXECUTE_MAX_CALLS, Error, ExecuteBatch, SchemaMode, VkClient};
+
+/// Start a mock server answering each request with the next body from `bodies`
+/// (the last one is repeated). Returns base URL and request counter.
+async fn mock_server(bodies: Vec<&'static str>) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = hits.clone();
+    let mut bodies: VecDeque<&'static str> = bodies.into();
+
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut socket, _)) = listener.accept().await else {
+                break;
+            };
+            read_request(&mut socket).await;
+            counter.fetch_add(1, Ordering::SeqCst);
+
+            let body = if bodies.len() > 1 {
+                bodies.pop_front().unwrap()
+            } else {
+                bodies[0]
+            };
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+            let _ = socket.shutdown().await;
+        }
+    });
+
+    (format!("http://{}/method", addr), hits)
+}
+
+/// Consume request headers and the form body
+async fn read_request(socket: &mut tokio::net::TcpStream) {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    loop {
+        let n = socket.read(&mut chunk).await.unwrap_or(0);
+        if n == 0 {
+            return;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            let headers = String::from_utf8_lossy(&buf[..pos]).to_lowercase();
+            let content_length = headers
+                .lines()
+                .find_map(|l| l.strip_prefix("content-length:"))
+                .and_then(|v| v.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            if buf.len() >= pos + 4 + content_length {
+                return;
+            }
+        }
+    }
+}
+
+fn test_client(url: &str, max_retries: u32) -> VkClient {
+    VkClient::builder("test-token")
+        .api_url(url)
+        .max_requests_per_second(0)
+        .max_retries(max_retries)
+        .retry_backoff(Duration::from_millis(1))
+        .schema_mode(SchemaMode::Strict)
+        .build()
+}
+
+const RATE_LIMITED: &str =
+    r#"{"error":{"error_code":6,"error_msg":"Too many requests per second"}}"#;
+
+#[tokio::test]
+async fn retries_after_rate_limit_error() {
+    let (url, hits) = mock_server(vec![
+        RATE_LIMITED,
+        RATE_LIMITED,
+        r#"{"response":{"messages":7}}"#,
+    ])
+    .await;
+    let client = test_client(&url, 3);
+
+    let counters = client.account().get_counters().await.unwrap();
+
+    assert_eq!(counters.messages, Some(7));
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn gives_up_after_max_retries() {
+    let (url, hits) = mock_server(vec![RATE_LIMITED]).await;
+    let client = test_client(&url, 2);
+
+    let err = client.account().get_counters().await.unwrap_err();
+
+    assert!(matches!(err, Error::Api { code: 6, .. }));
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn does_not_retry_other_errors() {
+    let (url, hits) = mock_server(vec![
+        r#"{"error":{"error_code":15,"error_msg":"Access denied"}}"#,
+    ])
+    .await;
+    let client = test_client(&url, 3);
+
+    let err = client.account().get_counters().await.unwrap_err();
+
+    assert_eq!(err.code(), Some(15));
+    assert!(!err.is_auth());
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn permission_denied_keeps_the_session() {
+    let (url, _) = mock_server(vec![
+        r#"{"error":{"error_code":7,"error_msg":"Permission to perform this action is denied"}}"#,
+    ])
+    .await;
+    let client = test_client(&url, 3);
+
+    let err = client.account().get_counters().await.unwrap_err();
+
+    assert_eq!(err.code(), Some(7));
+    assert!(!err.is_auth());
+}
+
+#[tokio::test]
+async fn maps_authorization_failure_to_auth_error() {
+    let (url, _) = mock_server(vec![
+        r#"{"error":{"error_code":5,"error_msg":"User authorization failed: invalid access_token"}}"#,
+    ])
+    .await;
+    let client = test_client(&url, 3);
+
+    let err = client.account().get_counters().await.unwrap_err();
+
+    assert!(matches!(err, Error::Auth));
+    assert!(err.is_auth());
+}
+
+#[tokio::test]
+async fn maps_message_limits_to_their_errors() {
+    let (url, _) = mock_server(vec![
+        r#"{"error":{"error_code":909,"error_msg":"Can't edit this message, because it's too old"}}"#,
+    ])
+    .await;
+    let client = test_client(&url, 3);
+
+    let err = client
+        .messages()
+        .edit(1, 2, None, "text", &[])
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, Error::EditExpired));
+    assert_eq!(err.code(), Some(909));
+    assert_eq!(
+        err.to_string(),
+        "VK does not allow editing messages older than 24 hours"
+    );
+    assert!(matches!(
+        Error::from_api(910, "too big"),
+        Error::MessageTooBig
+    ));
+}
+
+#[tokio::test]
+async fn maps_expired_delete_for_all() {
+    let (url, _) = mock_server(vec![
+        r#"{"error":{"error_code":924,"error_msg":"Can't delete this message for everybody"}}"#,
+    ])
+    .await;
+    let client = test_client(&url, 3);
+
+    let err = client.messages().delete(&[2], true).await.unwrap_err();
+
+    assert!(matches!(err, Error::DeleteForAllExpired));
+    assert_eq!(err.code(), Some(924));
+    assert_eq!(
+        err.to_string(),
+        "VK does not allow deleting messages older than 24 hours for everyone"
+    );
+}
+
+#[tokio::test]
+async fn reports_malformed_body_as_parse_error() {
+    let (url, _) = mock_server(vec!["<html>bad gateway</html>"]).await;
+    let client = test_client(&url, 3);
+
+    let err = client.account().get_counters().await.unwrap_err();
+
+    assert!(matches!(err, Error::Parse(_)));
+}
+
+#[tokio::test]
+async fn throttles_requests() {
+    let (url, hits) = mock_server(vec![r#"{"response":{}}"#]).await;
+    let client = VkClient::builder("test-token")
+        .api_url(url)
+        .max_requests_per_second(10)
+        .build();
+
+    let started = std::time::Instant::now();
+    for _ in 0..3 {
+        client.account().get_counters().await.unwrap();
+    }
+
+    // First request goes immediately, the next two wait ~100ms each
+    assert!(started.elapsed() >= Duration::from_millis(190));
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn counts_calls_and_errors_per_method() {
+    let (url, _) = mock_server(vec![
+        RATE_LIMITED,
+        r#"{"response":{}}"#,
+        r#"{"error":{"error_code":15,"error_msg":"Access denied"}}"#,
+    ])
+    .await;
+    let client = test_client(&url, 3);
+
+    client.account().get_counters().await.unwrap();
+    client.account().get_counters().await.unwrap_err();
+
+    let metrics = client.metrics();
+    assert_eq!(metrics.len(), 1);
+    // The retried call counts once
+    assert_eq!(
+        metrics["account.getCounters"],
+        vk_api::MethodMetrics {
+            calls: 2,
+            errors: 1
+        }
+    );
+}
+
+const COUNTERS_WITH_NEW_FIELD: &str = r#"{"response":{"messages":1,"memories":2}}"#;
+
+#[tokio::test]
+async fn strict_mode_rejects_unknown_fields() {
+    let (url, _) = mock_server(vec![COUNTERS_WITH_NEW_FIELD]).await;
+    let client = test_client(&url, 3);
+
+    let err = client.account().get_counters().await.unwrap_err();
+
+    assert!(matches!(err, Error::UnexpectedResponse(_)));
+    assert!(err.to_string().contains("response.memories"));
+}
+
+#[tokio::test]
+async fn strict_mode_keeps_api_errors() {
+    let (url, _) = mock_server(vec![
+        r#"{"error":{"error_code":15,"error_msg":"Access denied","request_params":[]}}"#,
+    ])
+    .await;
+    let client = test_client(&url, 3);
+
+    let err = client.account().get_counters().await.unwrap_err();
+
+    assert_eq!(err.code(), Some(15));
+}
+
+#[tokio::test]
+async fn log_mode_accepts_unknown_fields() {
+    let (url, _) = mock_server(vec![COUNTERS_WITH_NEW_FIELD]).await;
+    let client = VkClient::builder("test-token")
+        .api_url(url)
+        .max_requests_per_second(0)
+        .schema_mode(SchemaMode::Log)
+        .build();
+
+    let counters = client.account().get_counters().await.unwrap();
+
+    assert_eq!(counters.messages, Some(1));
+}
+
+#[tokio::test]
+async fn create_chat_accepts_both_response_shapes() {
+    let (url, _) = mock_server(vec![
+        r#"{"response":7}"#,
+        r#"{"response":{"chat_id":8,"peer_ids":[1,2]}}"#,
+    ])
+    .await;
+    let client = test_client(&url, 3);
+
+    assert_eq!(
+        client.messages().create_chat(&[1], "Trip").await.unwrap(),
+        7
+    );
+    assert_eq!(
+        client.messages().create_chat(&[1], "Trip").await.unwrap(),
+        8
+    );
+}
+
+/// How the body of [`raw_server`] is sent
+#[derive(Clone, Copy)]
+enum RawBody {
+    /// `Content-Length` of the given size, but no body bytes
+    Declared(usize),
+    /// Chunked body that never ends
+    Endless,
+}
+
+/// Start a server that answers once with `body` and then keeps the
+/// connection open. Returns base URL and the number of body bytes sent.
+async fn raw_server(body: RawBody) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let sent = Arc::new(AtomicUsize::new(0));
+    let counter = sent.clone();
+
+    tokio::spawn(async move {
+        let Ok((mut socket, _)) = listener.accept().await else {
+            return;
+        };
+        read_request(&mut socket).await;
+
+        let head = match body {
+            RawBody::Declared(len) => format!("Content-Length: {}", len),
+            RawBody::Endless => "Transfer-Encoding: chunked".to_string(),
+        };
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n{}\r\n\r\n",
+            head
+        );
+        if socket.write_all(head.as_bytes()).await.is_err() {
+            return;
+        }
+
+        if let RawBody::Endless = body {
+            let chunk = vec![b' '; 16 * 1024];
+            loop {
+                let frame = [format!("{:x}\r\n", chunk.len()).as_bytes(), &chunk, b"\r\n"].concat();
+                if socket.write_all(&frame).await.is_err() {
+                    return;
+                }
+                counter.fetch_add(chunk.len(), Ordering::SeqCst);
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(60)).await;
+    });
+
+    (format!("http://{}/method", addr), sent)
+}
+
+#[tokio::test]
+async fn rejects_declared_oversized_body_before_reading() {
+    let (url, _) = raw_server(RawBody::Declared(100 * 1024 * 1024)).await;
+    let client = VkClient::builder("test-token")
+        .api_url(url)
+        .max_requests_per_second(0)
+        .max_response_bytes(64 * 1024)
+        .build();
+
+    let err = tokio::time::timeout(Duration::from_secs(5), client.account().get_counters())
+        .await
+        .expect("limit check must not wait for the body")
+        .unwrap_err();
+
+    assert!(matches!(err, Error::ResponseTooLarge { limit: 65_536 }));
+}
+
+#[tokio::test]
+async fn stops_reading_endless_body_at_limit() {
+    let (url, sent) = raw_server(RawBody::Endless).await;
+    let client = VkClient::builder("test-token")
+        .api_url(url)
+        .max_requests_per_second(0)
+        .max_response_bytes(64 * 1024)
+        .build();
+
+    let err = tokio::time::timeout(Duration::from_secs(5), client.account().get_counters())
+        .await
+        .expect("reading must stop at the limit")
+        .unwrap_err();
+
+    assert!(matches!(err, Error::ResponseTooLarge { .. }));
+    assert!(err.to_string().contains("65536 bytes"));
+    // Socket buffers let the server run ahead a little, not without bound
+    assert!(sent.load(Ordering::SeqCst) < 64 * 1024 * 1024);
+}
+
+#[tokio::test]
+async fn stalled_body_hits_read_timeout() {
+    let (url, _) = raw_server(RawBody::Declared(64)).await;
+    let client = VkClient::builder("test-token")
+        .api_url(url)
+        .max_requests_per_second(0)
+        .read_timeout(Duration::from_millis(100))
+        .build();
+
+    let err = client.account().get_counters().await.unwrap_err();
+
+    assert!(matches!(err, Error::ReadTimeout(_)));
+}
+
+/// Temp file of `len` bytes for upload tests
+fn upload_file(name: &str, len: usize) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("vk_api_{}_{}", std::process::id(), name));
+    std::fs::write(&path, vec![b'x'; len]).unwrap();
+    path
+}
+
+#[tokio::test]
+async fn upload_reports_progress_up_to_total() {
+    let (upload_url, _) = mock_server(vec![r#"{"server":1,"photo":"[]","hash":"h"}"#]).await;
+    let server = format!(r#"{{"response":{{"upload_url":"{}"}}}}"#, upload_url);
+    let (url, _) = mock_server(vec![
+        Box::leak(server.into_boxed_str()),
+        r#"{"response":[{"id":5,"owner_id":7}]}"#,
+    ])
+    .await;
+    let client = VkClient::builder("test-token")
+        .api_url(url)
+        .max_requests_per_second(0)
+        .build();
+    let path = upload_file("progress.jpg", 200 * 1024);
+
+    let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let seen = calls.clone();
+    let progress: vk_api::UploadProgress =
+        Arc::new(move |sent, total| seen.lock().unwrap().push((sent, total)));
+    let attachment = client
+        .messages()
+        .upload_photo(1, &path, Some(progress))
+        .await
+        .unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(attachment, "photo7_5");
+    let calls = calls.lock().unwrap();
+    assert!(calls.len() > 2);
+    assert!(calls.windows(2).all(|w| w[0].0 < w[1].0));
+    let (sent, total) = *calls.last().unwrap();
+    assert_eq!(sent, total);
+    assert!(total > 200 * 1024);
+}
+
+#[tokio::test]
+async fn failed_transfer_is_upload_error() {
+    // Nothing listens on the upload URL
+    let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upload_url = format!("http://{}/upload", closed.local_addr().unwrap());
+    drop(closed);
+    let server = format!(r#"{{"response":{{"upload_url":"{}"}}}}"#, upload_url);
+    let (url, _) = mock_server(vec![Box::leak(server.into_boxed_str())]).await;
+    let client = test_client(&url, 0);
+    let path = upload_file("refused.txt", 16);
+
+    let err = client
+        .messages()
+        .upload_doc(1, &path, None)
+        .await
+        .unwrap_err();
+    let _ = std::fs::remove_file(&path);
+
+    assert!(matches!(err, Error::Upload(_)));
+}
+
+/// Address nothing listens on
+async fn closed_port() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+    addr.to_string()
+}
+
+#[tokio::test]
+async fn ping_reaches_api_server() {
+    let (url, hits) = mock_server(vec![r#"{"response":1700000000}"#]).await;
+    let client = test_client(&url, 0);
+
+    client.ping().await.unwrap();
+
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn ping_names_host_and_proxy() {
+    let client = VkClient::builder("test-token")
+        .api_url("https://api.vk.com/method")
+        .proxy(format!("http://user:secret@{}", closed_port().await))
+        .build();
+
+    let err = client.ping().await.unwrap_err();
+
+    assert!(
+        matches!(&err, Error::Unreachable { host, proxy: Some(_), .. } if host == "api.vk.com")
+    );
+    let message = err.to_string();
+    assert!(
+        message.starts_with("Cannot reach api.vk.com via proxy http://127.0.0.1:"),
+        "{}",
+        message
+    );
+    assert!(!message.contains("secret"), "{}", message);
+}
+
+#[tokio::test]
+async fn direct_ping_failure_has_no_proxy() {
+    let client = VkClient::builder("test-token")
+        .api_url(format!("http://{}/method", closed_port().await))
+        .no_proxy()
+        .build();
+
+    let err = client.ping().await.unwrap_err();
+
+    assert!(matches!(err, Error::Unreachable { proxy: None, .. }));
+    assert!(!err.to_string().contains("via proxy"));
+}
+
+#[test]
+fn invalid_proxy_fails_to_build() {
+    let result = VkClient::builder("test-token")
+        .proxy("http://[not a host")
+        .try_build();
+
+    assert!(matches!(result, Err(Error::InvalidProxy { .. })));
+}
+
+#[test]
+fn execute_batch_builds_script() {
+    let mut batch = ExecuteBatch::new();
+    assert!(batch.push("messages.getById", &[("message_ids", "1,2".into())]));
+    assert!(batch.push("users.get", &[("user_ids", "\"quoted\"".into())]));
+
+    assert_eq!(
+        batch.code(),
+        r#"return [API.messages.getById({"message_ids":"1,2"}),API.users.get({"user_ids":"\"quoted\""})];"#
+    );
+
+    while !batch.is_full() {
+        batch.push("users.get", &[]);
+    }
+    assert!(!batch.push("users.get", &[]));
+    assert_eq!(batch.len(), EXECUTE_MAX_CALLS);
+}
+
+#[tokio::test]
+async fn execute_batch_keeps_failed_calls_in_place() {
+    let (url, hits) = mock_server(vec![
+        r#"{"response":[{"count":1,"items":[{"id":1,"from_id":1,"peer_id":1,"date":0,"text":"a","out":0}]},false,{"count":1,"items":[{"id":201,"from_id":1,"peer_id":1,"date":0,"text":"b","out":0}]}],"execute_errors":[{"method":"messages.getById","error_code":15,"error_msg":"Access denied"}]}"#,
+    ])
+    .await;
+    let client = test_client(&url, 3);
+
+    let ids: Vec<i64> = (1..=250).collect();
+    let messages = client.messages().get_by_id_batched(&ids).await.unwrap();
+
+    let found: Vec<i64> = messages.iter().map(|m| m.id).collect();
+    assert_eq!(found, vec![1, 201]);
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
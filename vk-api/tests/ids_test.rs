@@ -0,0 +1,291 @@
+//! Identifiers must survive deserialization and request building unchanged
+//!
+//! Chat peer ids (2_000_000_000 + chat_id) and community ids (negative
+//! owner/from ids) are close to or beyond the i32 range, so any narrowing
+//! on the way in or out would corrupt them.
+
+use vk_api::schema::from_value_strict;
+use vk_api::{
+    CHAT_PEER_OFFSET, ConversationMembersResponse, ConversationsResponse, DeleteMessagesResponse,
+    HistoryAttachmentsResponse, Message, MessagesHistoryResponse, ReadPeersResponse, chat_peer_id,
+    is_chat_peer,
+};
+use vk_test_support::MockVk;
+
+const CHAT_PEER: i64 = 2_000_099_999;
+const COMMUNITY: i64 = -223_456_789;
+
+// Compile-time checks: these stop building if an id field is narrowed
+const _: fn(&Message) -> i64 = |m| m.id;
+const _: fn(&Message) -> i64 = |m| m.peer_id;
+const _: fn(&Message) -> i64 = |m| m.from_id;
+const _: fn(&vk_api::Photo) -> i64 = |p| p.owner_id;
+const _: fn(&vk_api::Doc) -> i64 = |d| d.owner_id;
+const _: fn(&vk_api::Peer) -> i64 = |p| p.id;
+const _: fn(&vk_api::Group) -> i64 = |g| g.id;
+const _: fn(&vk_api::User) -> i64 = |u| u.id;
+const _: fn(&MessagesHistoryResponse) -> u32 = |r| r.count;
+const _: fn(&ConversationsResponse) -> u32 = |r| r.count;
+
+#[test]
+fn chat_peer_id_is_checked() {
+    assert_eq!(chat_peer_id(99_999), Some(CHAT_PEER));
+    assert_eq!(chat_peer_id(0), None);
+    assert_eq!(chat_peer_id(-5), None);
+    assert_eq!(chat_peer_id(i64::MAX), None);
+    assert!(is_chat_peer(CHAT_PEER));
+    assert!(!is_chat_peer(CHAT_PEER_OFFSET));
+    assert!(!is_chat_peer(COMMUNITY));
+}
+
+#[test]
+fn history_keeps_large_ids() {
+    let history: MessagesHistoryResponse = from_value_strict(serde_json::json!({
+        "count": 3_000_000_000u32,
+        "items": [{
+            "id": 4_000_000_001i64,
+            "from_id": COMMUNITY,
+            "peer_id": CHAT_PEER,
+            "date": 1_700_000_000,
+            "text": "",
+            "conversation_message_id": 2_500_000_000i64,
+            "attachments": [
+                {"type": "photo", "photo": {"id": 457_239_017, "owner_id": COMMUNITY}},
+                {"type": "doc", "doc": {"id": 3_000_000_000i64, "owner_id": COMMUNITY}}
+            ]
+        }],
+        "groups": [{"id": 223_456_789, "name": "Community", "screen_name": "club223456789"}]
+    }))
+    .unwrap();
+
+    assert_eq!(history.count, 3_000_000_000);
+    let msg = &history.items[0];
+    assert_eq!(msg.id, 4_000_000_001);
+    assert_eq!(msg.peer_id, CHAT_PEER);
+    assert_eq!(msg.from_id, COMMUNITY);
+    assert_eq!(msg.conversation_message_id, Some(2_500_000_000));
+    assert_eq!(
+        msg.attachments[0].photo.as_ref().unwrap().owner_id,
+        COMMUNITY
+    );
+    assert_eq!(msg.attachments[1].doc.as_ref().unwrap().id, 3_000_000_000);
+    assert_eq!(history.groups[0].id, -COMMUNITY);
+}
+
+#[test]
+fn conversation_peer_keeps_large_id() {
+    let conversations: ConversationsResponse = from_value_strict(serde_json::json!({
+        "count": 1,
+        "items": [{
+            "conversation": {
+                "peer": {"id": CHAT_PEER, "type": "chat", "local_id": 99_999},
+                "chat_settings": {"title": "Work chat", "members_count": 3_000_000_000u32}
+            },
+            "last_message": {"id": 1, "from_id": COMMUNITY, "peer_id": CHAT_PEER, "date": 0}
+        }]
+    }))
+    .unwrap();
+
+    let conversation = &conversations.items[0].conversation;
+    assert_eq!(conversation.peer.id, CHAT_PEER);
+    assert_eq!(
+        conversation.chat_settings.as_ref().unwrap().members_count,
+        Some(3_000_000_000)
+    );
+}
+
+#[test]
+fn history_attachments_keep_ids() {
+    let page: HistoryAttachmentsResponse = from_value_strict(serde_json::json!({
+        "items": [{
+            "message_id": 3_000_000_000u32,
+            "from_id": COMMUNITY,
+            "cmid": 7,
+            "date": 1_700_000_000,
+            "attachment": {"type": "photo", "photo": {"id": 3_000_000_000u32, "owner_id": COMMUNITY}}
+        }],
+        "next_from": "3000000000/1"
+    }))
+    .unwrap();
+
+    assert_eq!(page.items[0].message_id, 3_000_000_000);
+    assert_eq!(page.items[0].from_id, COMMUNITY);
+    assert_eq!(
+        page.items[0].attachment.photo.as_ref().unwrap().owner_id,
+        COMMUNITY
+    );
+    assert_eq!(page.next_from.as_deref(), Some("3000000000/1"));
+}
+
+#[test]
+fn conversation_members_keep_ids() {
+    let members: ConversationMembersResponse = from_value_strict(serde_json::json!({
+        "count": 2,
+        "items": [
+            {"member_id": 3_000_000_001i64, "invited_by": 3_000_000_001i64, "join_date": 1_700_000_000, "is_owner": true, "is_admin": true},
+            {"member_id": COMMUNITY, "invited_by": 3_000_000_001i64, "join_date": 1_700_000_000, "can_kick": true}
+        ],
+        "profiles": [{"id": 3_000_000_001i64, "first_name": "Ann", "last_name": "Lee"}],
+        "groups": [{"id": 223_456_789, "name": "Bot", "screen_name": "club223456789"}]
+    }))
+    .unwrap();
+
+    assert_eq!(members.items[0].member_id, 3_000_000_001);
+    assert!(members.items[0].is_owner);
+    assert_eq!(members.items[1].member_id, COMMUNITY);
+    assert!(!members.items[1].is_admin);
+}
+
+#[test]
+fn read_peers_keep_ids() {
+    let readers: ReadPeersResponse = from_value_strict(serde_json::json!({
+        "count": 2,
+        "items": [3_000_000_001i64, COMMUNITY],
+        "profiles": [{"id": 3_000_000_001i64, "first_name": "Ann", "last_name": "Lee"}]
+    }))
+    .unwrap();
+
+    assert_eq!(readers.items, vec![3_000_000_001, COMMUNITY]);
+    assert!(readers.groups.is_empty());
+}
+
+#[test]
+fn reactions_keep_user_ids() {
+    let history: MessagesHistoryResponse = from_value_strict(serde_json::json!({
+        "count": 1,
+        "items": [{
+            "id": 1, "date": 0, "peer_id": CHAT_PEER, "reaction_id": 2,
+            "reactions": [
+                {"reaction_id": 2, "count": 3, "user_ids": [3_000_000_001i64, COMMUNITY]},
+                {"reaction_id": 1, "count": 1, "user_ids": []}
+            ]
+        }]
+    }))
+    .unwrap();
+
+    let message = &history.items[0];
+    assert_eq!(message.reaction_id, Some(2));
+    assert_eq!(message.reactions.len(), 2);
+    assert_eq!(message.reactions[0].count, 3);
+    assert_eq!(
+        message.reactions[0].user_ids,
+        vec![3_000_000_001, COMMUNITY]
+    );
+}
+
+#[test]
+fn negative_count_is_rejected() {
+    let result =
+        from_value_strict::<MessagesHistoryResponse>(serde_json::json!({"count": -1, "items": []}));
+    assert!(result.is_err());
+}
+
+#[test]
+fn deleted_messages_are_reported_per_id() {
+    let items: DeleteMessagesResponse = from_value_strict(serde_json::json!([
+        {"peer_id": CHAT_PEER, "message_id": 3_000_000_001i64, "conversation_message_id": 7, "response": 1},
+        {"peer_id": CHAT_PEER, "message_id": 12, "error": {"code": 924, "description": "Can't delete this message for everybody"}}
+    ]))
+    .unwrap();
+    assert_eq!(items.deleted(), vec![3_000_000_001]);
+
+    let by_id: DeleteMessagesResponse =
+        from_value_strict(serde_json::json!({"3000000001": 1, "12": 0})).unwrap();
+    assert_eq!(by_id.deleted(), vec![3_000_000_001]);
+}
+
+/// Mock VK API answering each of `methods` with `{"response":1}`
+async fn accepting(methods: &[&str]) -> MockVk {
+    let mock = MockVk::start().await;
+    for method in methods {
+        mock.respond(method, r#"{"response":1}"#);
+    }
+    mock
+}
+
+#[tokio::test]
+async fn requests_carry_full_ids() {
+    let mock = accepting(&[
+        "messages.markAsRead",
+        "messages.send",
+        "messages.removeChatUser",
+        "messages.editChat",
+        "messages.deleteConversation",
+        "account.setSilenceMode",
+    ])
+    .await;
+    let client = mock.client();
+
+    client.messages().mark_as_read(CHAT_PEER).await.unwrap();
+    client.messages().send(COMMUNITY, "hi").await.unwrap();
+    client
+        .messages()
+        .remove_chat_user(99_999, COMMUNITY)
+        .await
+        .unwrap();
+    client
+        .messages()
+        .edit_chat(147_483_647, "Trip")
+        .await
+        .unwrap();
+    client
+        .messages()
+        .delete_conversation(CHAT_PEER)
+        .await
+        .unwrap();
+    client
+        .account()
+        .set_silence_mode(CHAT_PEER, -1)
+        .await
+        .unwrap();
+
+    let calls = mock.calls();
+    assert_eq!(calls[0].param("peer_id"), Some("2000099999"));
+    assert_eq!(calls[1].param("peer_id"), Some("-223456789"));
+    assert_eq!(calls[2].param("chat_id"), Some("99999"));
+    assert_eq!(calls[2].param("member_id"), Some("-223456789"));
+    assert_eq!(calls[3].param("chat_id"), Some("147483647"));
+    assert_eq!(calls[4].param("peer_id"), Some("2000099999"));
+    assert_eq!(calls[5].param("peer_id"), Some("2000099999"));
+    assert_eq!(calls[5].param("time"), Some("-1"));
+}
+
+#[tokio::test]
+async fn reactions_target_chat_messages() {
+    let mock = accepting(&["messages.sendReaction", "messages.deleteReaction"]).await;
+    let client = mock.client();
+
+    client
+        .messages()
+        .send_reaction(CHAT_PEER, 42, 3)
+        .await
+        .unwrap();
+    client
+        .messages()
+        .delete_reaction(CHAT_PEER, 42)
+        .await
+        .unwrap();
+
+    let calls = mock.calls();
+    assert_eq!(calls[0].param("peer_id"), Some("2000099999"));
+    assert_eq!(calls[0].param("cmid"), Some("42"));
+    assert_eq!(calls[0].param("reaction_id"), Some("3"));
+    assert_eq!(calls[1].param("peer_id"), Some("2000099999"));
+    assert_eq!(calls[1].param("reaction_id"), None);
+}
+
+#[tokio::test]
+async fn create_chat_sends_large_user_ids() {
+    let mock = accepting(&["messages.createChat"]).await;
+
+    let chat_id = mock
+        .client()
+        .messages()
+        .create_chat(&[3_000_000_000, 42], "Trip")
+        .await
+        .unwrap();
+
+    assert_eq!(chat_id, 1);
+    let calls = mock.calls();
+    assert_eq!(calls[0].param("user_ids"), Some("3000000000%2C42"));
+}
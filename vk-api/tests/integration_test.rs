@@ -5,7 +5,7 @@
 //!
 //! Run with: cargo test --test integration_test -- --test-threads=1 --nocapture
 
-use vk_api::VkClient;
+use vk_api::{BASIC_USER_FIELDS, VkClient};
 
 /// Load token from config file
 fn get_test_token() -> String {
@@ -96,7 +96,7 @@ async fn test_get_current_user() {
     let client = create_test_client();
     let user_id = get_test_user_id();
 
-    let result = client.users().get(&[user_id]).await;
+    let result = client.users().get(&[user_id], BASIC_USER_FIELDS).await;
 
     match result {
         Ok(users) => {
@@ -198,8 +198,6 @@ async fn test_get_history() {
                 };
                 println!("  {}. [{}] {}", i + 1, msg.id, preview);
             }
-
-            assert!(history.count >= 0, "Count should be non-negative");
         }
         Err(e) => {
             panic!("Failed to get history: {}", e);
@@ -264,7 +262,7 @@ async fn test_users_get() {
     let client = create_test_client();
     let user_id = get_current_user_id(&client).await;
 
-    let result = client.users().get(&[user_id]).await;
+    let result = client.users().get(&[user_id], BASIC_USER_FIELDS).await;
 
     match result {
         Ok(users) => {
@@ -337,7 +335,7 @@ async fn test_edit_message() {
     let cmid = (sent.conversation_message_id > 0).then_some(sent.conversation_message_id);
     let edit_result = client
         .messages()
-        .edit(user_id, sent.message_id, cmid, &new_text)
+        .edit(user_id, sent.message_id, cmid, &new_text, &[])
         .await;
 
     match edit_result {
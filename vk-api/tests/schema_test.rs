@@ -0,0 +1,46 @@
+//! Unknown-field detection used by `SchemaMode::Log` and `SchemaMode::Strict`
+
+use vk_api::schema::{from_str_reporting, from_value_strict};
+use vk_api::{City, MessagesHistoryResponse, VkResponse};
+
+const HISTORY: &str = r#"{"response":{"count":1,"items":[
+    {"id":1,"date":0,"was_listened":true},
+    {"id":2,"date":0,"was_listened":false,"reply_message":{"id":3,"is_hidden":false}}
+],"new_section":{}}}"#;
+
+#[test]
+fn reports_unknown_paths_without_indices() {
+    let (history, unknown) =
+        from_str_reporting::<VkResponse<MessagesHistoryResponse>>(HISTORY).unwrap();
+
+    assert_eq!(history.response.unwrap().items.len(), 2);
+    assert_eq!(
+        unknown,
+        vec![
+            "response.items[].was_listened",
+            "response.items[].was_listened",
+            "response.items[].reply_message.is_hidden",
+            "response.new_section",
+        ]
+    );
+}
+
+#[test]
+fn catch_all_attachment_fields_are_not_unknown() {
+    let (_, unknown) = from_str_reporting::<vk_api::Attachment>(
+        r#"{"type":"sticker","sticker":{"sticker_id":1}}"#,
+    )
+    .unwrap();
+
+    assert!(unknown.is_empty());
+}
+
+#[test]
+fn strict_value_rejects_unknown_fields() {
+    let city: City = from_value_strict(serde_json::json!({"id": 1, "title": "Moscow"})).unwrap();
+    assert_eq!(city.title, "Moscow");
+
+    let err = from_value_strict::<City>(serde_json::json!({"id": 1, "title": "Moscow", "area": 1}))
+        .unwrap_err();
+    assert!(err.to_string().contains("area"));
+}
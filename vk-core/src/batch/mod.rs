@@ -0,0 +1,131 @@
+//! Coalescing of requests that arrive close together.
+//!
+//! Opening a chat asks for the details of several messages one by one.
+//! A [`Coalescer`] collects such keys for a short window and hands them
+//! over at once, so they cost one API request; [`demux`] matches the
+//! results back to the keys that asked for them.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// How long message detail fetches wait for others to join them.
+pub const FETCH_WINDOW: Duration = Duration::from_millis(100);
+
+/// Collects keys pushed within a window and flushes them together.
+#[derive(Debug)]
+pub struct Coalescer<K> {
+    window: Duration,
+    pending: Arc<Mutex<Vec<K>>>,
+}
+
+impl<K: PartialEq + Send + 'static> Coalescer<K> {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: Arc::default(),
+        }
+    }
+
+    /// Queue `key`. The first key of a window spawns a task that waits the
+    /// window out and calls `flush` with every key queued meanwhile, in
+    /// order and without repeats; later keys join that call and their
+    /// `flush` is dropped.
+    pub fn push<F, Fut>(&self, key: K, flush: F)
+    where
+        F: FnOnce(Vec<K>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.contains(&key) {
+            return;
+        }
+        pending.push(key);
+        if pending.len() > 1 {
+            return;
+        }
+
+        let queued = self.pending.clone();
+        let window = self.window;
+        tokio::spawn(async move {
+            tokio::time::sleep(window).await;
+            let keys = std::mem::take(&mut *queued.lock().unwrap());
+            flush(keys).await;
+        });
+    }
+}
+
+/// Match `items` to the `keys` they answer, in key order. Keys without an
+/// item get `None`; items no key asked for are dropped.
+pub fn demux<K, T>(
+    keys: &[K],
+    items: impl IntoIterator<Item = T>,
+    key_of: impl Fn(&T) -> K,
+) -> Vec<(K, Option<T>)>
+where
+    K: Eq + Hash + Clone,
+{
+    let mut by_key: HashMap<K, T> = items
+        .into_iter()
+        .map(|item| (key_of(&item), item))
+        .collect();
+    keys.iter()
+        .map(|key| (key.clone(), by_key.remove(key)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    const WINDOW: Duration = Duration::from_millis(30);
+
+    fn push(coalescer: &Coalescer<i64>, key: i64, tx: &mpsc::UnboundedSender<Vec<i64>>) {
+        let tx = tx.clone();
+        coalescer.push(key, move |keys| async move {
+            let _ = tx.send(keys);
+        });
+    }
+
+    #[tokio::test]
+    async fn test_keys_in_window_flush_once() {
+        let coalescer = Coalescer::new(WINDOW);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        push(&coalescer, 3, &tx);
+        push(&coalescer, 1, &tx);
+        push(&coalescer, 3, &tx);
+
+        assert_eq!(rx.recv().await, Some(vec![3, 1]));
+        tokio::time::sleep(WINDOW * 2).await;
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_key_after_flush_opens_new_window() {
+        let coalescer = Coalescer::new(WINDOW);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        push(&coalescer, 1, &tx);
+        assert_eq!(rx.recv().await, Some(vec![1]));
+
+        push(&coalescer, 1, &tx);
+        push(&coalescer, 2, &tx);
+        assert_eq!(rx.recv().await, Some(vec![1, 2]));
+    }
+
+    #[test]
+    fn test_demux() {
+        let items = vec![(20, "b"), (99, "stray"), (10, "a")];
+
+        let results = demux(&[10, 20, 30], items, |item| item.0);
+
+        assert_eq!(
+            results,
+            vec![(10, Some((10, "a"))), (20, Some((20, "b"))), (30, None)]
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Local cache of conversations and messages.
+//!
+//! Opening a chat shows its cached messages at once; the network page that
+//! follows is reconciled into the cache (new and changed messages stored,
+//! ones gone from the server removed), and Long Poll events are applied
+//! as they arrive. At most [`MAX_CACHED_MESSAGES`] per chat are kept.
+//!
+//! [`CacheLayer`] is what frontends and the executor talk to. The SQLite
+//! implementation, [`SqliteCache`], needs the `cache` feature. A cache is
+//! best-effort: failures are logged and read back as an empty cache.
+
+use std::sync::Arc;
+
+use crate::edit::apply_remote_edit;
+use crate::events::VkEvent;
+use crate::models::{Chat, ChatMessage, DeliveryStatus, MessageKind};
+
+#[cfg(feature = "cache")]
+mod sqlite;
+#[cfg(feature = "cache")]
+pub use sqlite::SqliteCache;
+
+/// Messages kept per chat; older ones are dropped.
+pub const MAX_CACHED_MESSAGES: usize = 1000;
+
+/// Conversations returned by [`CacheLayer::chats`].
+pub const MAX_CACHED_CHATS: usize = 200;
+
+/// Open the message cache in the user cache directory, to hand to
+/// [`crate::CommandExecutor::new`]. Without the `cache` feature, or if it
+/// cannot be opened, there is none and the app works from the network alone.
+#[cfg(feature = "cache")]
+pub fn open_default() -> Option<Arc<dyn CacheLayer>> {
+    let path = SqliteCache::default_path()?;
+    match SqliteCache::open(&path) {
+        Ok(cache) => Some(Arc::new(cache)),
+        Err(e) => {
+            tracing::warn!("Message cache unavailable at {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "cache"))]
+pub fn open_default() -> Option<Arc<dyn CacheLayer>> {
+    None
+}
+
+/// Persistent store of conversations and messages.
+pub trait CacheLayer: Send + Sync {
+    /// Cached conversations, most recent first.
+    fn chats(&self) -> Vec<Chat>;
+
+    /// Store or update `chats`.
+    fn store_chats(&self, chats: &[Chat]);
+
+    /// Remove a conversation and its messages.
+    fn delete_chat(&self, peer_id: i64);
+
+    /// The newest `limit` messages of `peer_id`, oldest first.
+    fn messages(&self, peer_id: i64, limit: usize) -> Vec<ChatMessage>;
+
+    /// A cached message and the chat it is in.
+    fn message(&self, message_id: i64) -> Option<(i64, ChatMessage)>;
+
+    /// Store a page of `peer_id` fetched from the server. Cached messages
+    /// within the page's id range that it lacks were deleted; so were
+    /// those newer than the page when it is the `newest` one.
+    fn reconcile(&self, peer_id: i64, messages: &[ChatMessage], newest: bool);
+
+    /// Store or update one message.
+    fn upsert_message(&self, peer_id: i64, message: &ChatMessage);
+
+    fn delete_message(&self, message_id: i64);
+
+    /// Name of a user seen as the sender of a cached message.
+    fn user_name(&self, user_id: i64) -> Option<String>;
+
+    /// Forget everything.
+    fn clear(&self);
+
+    /// Apply a Long Poll event. Text-only edits are applied as they come;
+    /// other edits and attachments arrive without the content, so the
+    /// frontend fetches the message and stores it then. A new message
+    /// already cached (from a page loaded meanwhile) is kept.
+    fn apply_event(&self, event: &VkEvent) {
+        match event {
+            VkEvent::NewMessage {
+                message_id,
+                peer_id,
+                timestamp,
+                text,
+                from_id,
+                is_outgoing,
+                attachment_types,
+                action,
+                random_id,
+                ..
+            } if self.message(*message_id).is_none() => {
+                let from_name = self.user_name(*from_id).unwrap_or_default();
+                // Attachment-only messages keep their empty text; the
+                // renderer shows the placeholder of their kind
+                let text = match action {
+                    Some(action) => action.describe(*from_id, |id| {
+                        self.user_name(id).unwrap_or_else(|| format!("User {}", id))
+                    }),
+                    None => text.clone(),
+                };
+                let message = ChatMessage {
+                    id: *message_id,
+                    cmid: None,
+                    random_id: *random_id,
+                    from_id: *from_id,
+                    from_name,
+                    from_photo: None,
+                    text,
+                    kind: MessageKind::of(action.as_ref(), attachment_types),
+                    timestamp: *timestamp,
+                    is_outgoing: *is_outgoing,
+                    is_read: false,
+                    is_edited: false,
+                    is_pinned: false,
+                    delivery: DeliveryStatus::Sent,
+                    attachments: Vec::new(),
+                    reply: None,
+                    fwd_count: 0,
+                    forwards: Vec::new(),
+                    reactions: Vec::new(),
+                    upload: None,
+                };
+                self.upsert_message(*peer_id, &message);
+            }
+            VkEvent::MessageEditedFromLongPoll {
+                message_id,
+                new_text,
+                has_attachments,
+                ..
+            } => {
+                if let Some((peer_id, mut message)) = self.message(*message_id)
+                    && apply_remote_edit(&mut message, new_text.as_deref(), *has_attachments)
+                {
+                    self.upsert_message(peer_id, &message);
+                }
+            }
+            VkEvent::MessageDeletedFromLongPoll { message_id, .. } => {
+                self.delete_message(*message_id);
+            }
+            _ => {}
+        }
+    }
+}
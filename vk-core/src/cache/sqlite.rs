@@ -0,0 +1,406 @@
+//! [`CacheLayer`] in an SQLite file.
+//!
+//! Chats and messages are stored as JSON next to the columns they are
+//! looked up and ordered by, so model changes need no schema migration;
+//! rows that no longer deserialize are skipped.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use rusqlite::{Connection, OptionalExtension, params};
+
+use super::{CacheLayer, MAX_CACHED_CHATS, MAX_CACHED_MESSAGES};
+use crate::models::{Chat, ChatMessage, DeliveryStatus, sort_chats};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS chats (
+        id INTEGER PRIMARY KEY,
+        last_message_time INTEGER NOT NULL,
+        data TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS messages (
+        peer_id INTEGER NOT NULL,
+        id INTEGER NOT NULL,
+        cmid INTEGER,
+        data TEXT NOT NULL,
+        PRIMARY KEY (peer_id, id)
+    );
+    CREATE INDEX IF NOT EXISTS messages_by_id ON messages (id);
+    CREATE TABLE IF NOT EXISTS names (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL
+    );
+";
+
+/// Message cache in one SQLite database.
+pub struct SqliteCache {
+    conn: Mutex<Connection>,
+    max_messages: usize,
+}
+
+impl SqliteCache {
+    /// Open or create the cache at `path`.
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    /// A cache that lives as long as the value; for tests.
+    pub fn open_in_memory() -> anyhow::Result<Self> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    /// `messages.sqlite` in the app cache directory.
+    pub fn default_path() -> Option<PathBuf> {
+        directories::ProjectDirs::from("", "", "vk_tui")
+            .map(|dirs| dirs.cache_dir().join("messages.sqlite"))
+    }
+
+    /// Keep `max` messages per chat instead of [`MAX_CACHED_MESSAGES`].
+    pub fn with_max_messages(mut self, max: usize) -> Self {
+        self.max_messages = max;
+        self
+    }
+
+    fn with_connection(conn: Connection) -> anyhow::Result<Self> {
+        conn.execute_batch(SCHEMA)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            max_messages: MAX_CACHED_MESSAGES,
+        })
+    }
+
+    /// Run `f` on the connection; a failure is logged and gives `T::default()`.
+    fn run<T: Default>(
+        &self,
+        what: &str,
+        f: impl FnOnce(&mut Connection) -> rusqlite::Result<T>,
+    ) -> T {
+        let Ok(mut conn) = self.conn.lock() else {
+            return T::default();
+        };
+        f(&mut conn).unwrap_or_else(|e| {
+            tracing::warn!("Message cache: failed to {}: {}", what, e);
+            T::default()
+        })
+    }
+
+    /// Drop all but the newest `max_messages` of `peer_id`.
+    fn trim(&self, conn: &Connection, peer_id: i64) -> rusqlite::Result<()> {
+        conn.execute(
+            "DELETE FROM messages WHERE peer_id = ?1 AND id NOT IN (
+                SELECT id FROM messages WHERE peer_id = ?1 ORDER BY id DESC LIMIT ?2
+            )",
+            params![peer_id, self.max_messages as i64],
+        )?;
+        Ok(())
+    }
+}
+
+impl CacheLayer for SqliteCache {
+    fn chats(&self) -> Vec<Chat> {
+        self.run("read chats", |conn| {
+            let mut stmt =
+                conn.prepare("SELECT data FROM chats ORDER BY last_message_time DESC LIMIT ?1")?;
+            let rows = stmt.query_map([MAX_CACHED_CHATS as i64], |row| row.get::<_, String>(0))?;
+            let mut chats: Vec<Chat> = rows
+                .filter_map(|data| serde_json::from_str(&data.ok()?).ok())
+                .collect();
+            // Pinned chats go on top, as in the list loaded from VK
+            sort_chats(&mut chats);
+            Ok(chats)
+        })
+    }
+
+    fn store_chats(&self, chats: &[Chat]) {
+        self.run("store chats", |conn| {
+            let tx = conn.transaction()?;
+            for chat in chats {
+                let Ok(data) = serde_json::to_string(chat) else {
+                    continue;
+                };
+                tx.execute(
+                    "INSERT OR REPLACE INTO chats (id, last_message_time, data) VALUES (?1, ?2, ?3)",
+                    params![chat.id, chat.last_message_time, data],
+                )?;
+            }
+            tx.commit()
+        })
+    }
+
+    fn delete_chat(&self, peer_id: i64) {
+        self.run("delete chat", |conn| {
+            conn.execute("DELETE FROM chats WHERE id = ?1", [peer_id])?;
+            conn.execute("DELETE FROM messages WHERE peer_id = ?1", [peer_id])?;
+            Ok(())
+        })
+    }
+
+    fn messages(&self, peer_id: i64, limit: usize) -> Vec<ChatMessage> {
+        self.run("read messages", |conn| {
+            let mut stmt = conn.prepare(
+                "SELECT data FROM messages WHERE peer_id = ?1 ORDER BY id DESC LIMIT ?2",
+            )?;
+            let rows = stmt.query_map(params![peer_id, limit as i64], |row| {
+                row.get::<_, String>(0)
+            })?;
+            let mut messages: Vec<ChatMessage> = rows
+                .filter_map(|data| serde_json::from_str(&data.ok()?).ok())
+                .collect();
+            messages.reverse();
+            Ok(messages)
+        })
+    }
+
+    fn message(&self, message_id: i64) -> Option<(i64, ChatMessage)> {
+        self.run("read message", |conn| {
+            let row = conn
+                .query_row(
+                    "SELECT peer_id, data FROM messages WHERE id = ?1",
+                    [message_id],
+                    |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)),
+                )
+                .optional()?;
+            Ok(row.and_then(|(peer_id, data)| Some((peer_id, serde_json::from_str(&data).ok()?))))
+        })
+    }
+
+    fn reconcile(&self, peer_id: i64, messages: &[ChatMessage], newest: bool) {
+        let ids: Vec<i64> = messages.iter().map(|m| m.id).collect();
+        let (Some(&min), Some(&max)) = (ids.iter().min(), ids.iter().max()) else {
+            if newest {
+                // The server has no messages left in this chat
+                self.run("clear chat", |conn| {
+                    conn.execute("DELETE FROM messages WHERE peer_id = ?1", [peer_id])?;
+                    Ok(())
+                });
+            }
+            return;
+        };
+        let upper = if newest { i64::MAX } else { max };
+
+        self.run("reconcile messages", |conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut stale = tx.prepare(
+                    "SELECT id FROM messages WHERE peer_id = ?1 AND id BETWEEN ?2 AND ?3",
+                )?;
+                let cached: Vec<i64> = stale
+                    .query_map(params![peer_id, min, upper], |row| row.get(0))?
+                    .collect::<rusqlite::Result<_>>()?;
+                for id in cached.into_iter().filter(|id| !ids.contains(id)) {
+                    tx.execute(
+                        "DELETE FROM messages WHERE peer_id = ?1 AND id = ?2",
+                        params![peer_id, id],
+                    )?;
+                }
+            }
+            for message in messages {
+                insert(&tx, peer_id, message)?;
+            }
+            self.trim(&tx, peer_id)?;
+            tx.commit()
+        })
+    }
+
+    fn upsert_message(&self, peer_id: i64, message: &ChatMessage) {
+        self.run("store message", |conn| {
+            insert(conn, peer_id, message)?;
+            self.trim(conn, peer_id)
+        })
+    }
+
+    fn delete_message(&self, message_id: i64) {
+        self.run("delete message", |conn| {
+            conn.execute("DELETE FROM messages WHERE id = ?1", [message_id])?;
+            Ok(())
+        })
+    }
+
+    fn user_name(&self, user_id: i64) -> Option<String> {
+        self.run("read name", |conn| {
+            conn.query_row("SELECT name FROM names WHERE id = ?1", [user_id], |row| {
+                row.get(0)
+            })
+            .optional()
+        })
+    }
+
+    fn clear(&self) {
+        self.run("clear", |conn| {
+            conn.execute_batch("DELETE FROM chats; DELETE FROM messages; DELETE FROM names;")
+        })
+    }
+}
+
+/// Store `message` unless it is a pending one the server does not know.
+fn insert(conn: &Connection, peer_id: i64, message: &ChatMessage) -> rusqlite::Result<()> {
+    if message.id <= 0 || message.delivery != DeliveryStatus::Sent || message.upload.is_some() {
+        return Ok(());
+    }
+    let Ok(data) = serde_json::to_string(message) else {
+        return Ok(());
+    };
+    conn.execute(
+        "INSERT OR REPLACE INTO messages (peer_id, id, cmid, data) VALUES (?1, ?2, ?3, ?4)",
+        params![peer_id, message.id, message.cmid, data],
+    )?;
+    // Senders by id, to name the authors of Long Poll messages
+    if !message.from_name.is_empty() {
+        conn.execute(
+            "INSERT OR REPLACE INTO names (id, name) VALUES (?1, ?2)",
+            params![message.from_id, message.from_name],
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::VkEvent;
+    use crate::fixtures::{chat, message};
+
+    fn ids(cache: &SqliteCache, peer_id: i64) -> Vec<i64> {
+        cache.messages(peer_id, 100).iter().map(|m| m.id).collect()
+    }
+
+    #[test]
+    fn test_reconcile_updates_and_removes() {
+        let cache = SqliteCache::open_in_memory().unwrap();
+        cache.reconcile(
+            1,
+            &[message(10, "a"), message(11, "b"), message(12, "c")],
+            true,
+        );
+        cache.reconcile(2, &[message(20, "other chat")], true);
+        assert_eq!(ids(&cache, 1), [10, 11, 12]);
+
+        // 11 was deleted, 12 edited, 13 is new; 10 is outside the page
+        cache.reconcile(1, &[message(12, "c edited"), message(13, "d")], false);
+        assert_eq!(ids(&cache, 1), [10, 11, 12, 13]);
+        cache.reconcile(1, &[message(10, "a"), message(12, "c edited")], false);
+        assert_eq!(ids(&cache, 1), [10, 12, 13]);
+        assert_eq!(cache.message(12).unwrap().1.text, "c edited");
+
+        // The newest page also drops what is newer than it
+        cache.reconcile(1, &[message(10, "a"), message(12, "c edited")], true);
+        assert_eq!(ids(&cache, 1), [10, 12]);
+        assert_eq!(ids(&cache, 2), [20]);
+    }
+
+    #[test]
+    fn test_keeps_newest_messages_only() {
+        let cache = SqliteCache::open_in_memory().unwrap().with_max_messages(3);
+        let page: Vec<ChatMessage> = (1..=5).map(|id| message(id, "x")).collect();
+        cache.reconcile(1, &page, true);
+        assert_eq!(ids(&cache, 1), [3, 4, 5]);
+        cache.upsert_message(1, &message(6, "y"));
+        assert_eq!(ids(&cache, 1), [4, 5, 6]);
+    }
+
+    #[test]
+    fn test_pending_messages_are_not_stored() {
+        let cache = SqliteCache::open_in_memory().unwrap();
+        cache.upsert_message(1, &message(0, "local"));
+        let mut failed = message(7, "failed");
+        failed.delivery = DeliveryStatus::Failed;
+        cache.upsert_message(1, &failed);
+        assert!(cache.messages(1, 10).is_empty());
+    }
+
+    #[test]
+    fn test_long_poll_events() {
+        let cache = SqliteCache::open_in_memory().unwrap();
+        cache.reconcile(1, &[message(10, "hi")], true);
+
+        cache.apply_event(&VkEvent::NewMessage {
+            message_id: 11,
+            peer_id: 1,
+            timestamp: 1_700_000_100,
+            text: "hello".into(),
+            from_id: 5,
+            is_outgoing: false,
+            has_attachments: false,
+            attachment_types: Vec::new(),
+            action: None,
+            random_id: None,
+        });
+        let (peer_id, new) = cache.message(11).unwrap();
+        assert_eq!(peer_id, 1);
+        assert_eq!(new.text, "hello");
+        // Sender name from the cached messages of the same sender
+        assert_eq!(new.from_name, "Ann Lee");
+
+        cache.apply_event(&VkEvent::NewMessage {
+            message_id: 12,
+            peer_id: 1,
+            timestamp: 1_700_000_200,
+            text: String::new(),
+            from_id: 5,
+            is_outgoing: false,
+            has_attachments: true,
+            attachment_types: vec!["photo".into()],
+            action: None,
+            random_id: None,
+        });
+        let photo = cache.message(12).unwrap().1;
+        assert!(photo.text.is_empty());
+        assert_eq!(photo.display_text(), "📷 Photo");
+
+        cache.apply_event(&VkEvent::MessageEditedFromLongPoll {
+            peer_id: 1,
+            message_id: 11,
+            new_text: Some("hello there".into()),
+            has_attachments: false,
+        });
+        let edited = cache.message(11).unwrap().1;
+        assert_eq!(edited.text, "hello there");
+        assert!(edited.is_edited);
+
+        cache.apply_event(&VkEvent::MessageDeletedFromLongPoll {
+            peer_id: 1,
+            message_id: 10,
+        });
+        assert_eq!(ids(&cache, 1), [11, 12]);
+    }
+
+    #[test]
+    fn test_chats_and_clear() {
+        let cache = SqliteCache::open_in_memory().unwrap();
+        let chat_at = |id, last_message_time| Chat {
+            last_message_time,
+            ..chat(id)
+        };
+        cache.store_chats(&[chat_at(1, 100), chat_at(2, 300)]);
+        cache.store_chats(&[chat_at(1, 500)]);
+        let ids: Vec<i64> = cache.chats().iter().map(|c| c.id).collect();
+        assert_eq!(ids, [1, 2]);
+
+        cache.reconcile(2, &[message(20, "x")], true);
+        cache.delete_chat(2);
+        assert_eq!(cache.chats().len(), 1);
+        assert!(cache.messages(2, 10).is_empty());
+
+        cache.reconcile(1, &[message(10, "x")], true);
+        cache.clear();
+        assert!(cache.chats().is_empty());
+        assert!(cache.messages(1, 10).is_empty());
+    }
+
+    #[test]
+    fn test_reopens_from_file() {
+        let path =
+            std::env::temp_dir().join(format!("vk_core_cache_{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        SqliteCache::open(&path)
+            .unwrap()
+            .reconcile(1, &[message(10, "kept")], true);
+        let cache = SqliteCache::open(&path).unwrap();
+        assert_eq!(cache.messages(1, 10)[0].text, "kept");
+        drop(cache);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -5,7 +5,11 @@
 
 use std::path::PathBuf;
 
-use crate::models::AttachmentInfo;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Settings;
+use crate::models::{AttachmentInfo, ChatMessage, MuteDuration};
+use crate::outbox::OutboxCommand;
 
 /// Synchronous commands (immediate state changes).
 #[derive(Debug, Clone)]
@@ -21,7 +25,10 @@ pub enum Command {
 }
 
 /// Async commands that require API calls.
-#[derive(Debug, Clone)]
+///
+/// Serializable, so a frontend can hand back the command of
+/// [`CoreEvent::CaptchaRequired`](crate::CoreEvent::CaptchaRequired).
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AsyncCommand {
     // === Loading ===
     /// Load conversations list.
@@ -61,6 +68,10 @@ pub enum AsyncCommand {
         text: String,
     },
 
+    /// Send or manage a text through the outbox; answered with
+    /// `CoreEvent::Outbox`.
+    Outbox(OutboxCommand),
+
     /// Forward messages.
     SendForward {
         peer_id: i64,
@@ -69,11 +80,15 @@ pub enum AsyncCommand {
     },
 
     /// Edit a message.
+    ///
+    /// If `base_hash` is set, the edit is only submitted when the server text
+    /// still matches it; otherwise `CoreEvent::EditConflict` is emitted.
     EditMessage {
         peer_id: i64,
         message_id: i64,
         cmid: Option<i64>,
         text: String,
+        base_hash: Option<u64>,
     },
 
     /// Delete a message.
@@ -83,20 +98,83 @@ pub enum AsyncCommand {
         for_all: bool,
     },
 
+    /// Put a reaction on a message, or remove yours with `None`.
+    /// On failure the message is fetched again to undo optimistic counters.
+    SetReaction {
+        peer_id: i64,
+        message_id: i64,
+        cmid: i64,
+        reaction_id: Option<i64>,
+    },
+
     // === Attachments ===
-    /// Send a photo.
-    SendPhoto { peer_id: i64, path: PathBuf },
+    /// Send photos in one message. Upload progress of the whole batch is
+    /// reported with `local_id`.
+    SendPhoto {
+        peer_id: i64,
+        paths: Vec<PathBuf>,
+        local_id: u64,
+    },
 
-    /// Send a document.
-    SendDoc { peer_id: i64, path: PathBuf },
+    /// Send documents in one message. Upload progress of the whole batch
+    /// is reported with `local_id`.
+    SendDoc {
+        peer_id: i64,
+        paths: Vec<PathBuf>,
+        local_id: u64,
+    },
 
-    /// Download attachments.
+    /// Download attachments into the download folder, or each to its
+    /// `target_path` if set.
     DownloadAttachments { attachments: Vec<AttachmentInfo> },
 
+    // === Chat Members ===
+    /// Load a page of group chat members.
+    LoadChatMembers { peer_id: i64, offset: u32 },
+
+    /// Remove a member from a group chat; removing yourself leaves it.
+    RemoveChatUser { peer_id: i64, user_id: i64 },
+
+    /// Load who read one of your messages in a group chat.
+    FetchReadPeers { peer_id: i64, cmid: i64 },
+
+    /// Count the media of a chat for the chat info popup.
+    LoadChatInfo { peer_id: i64 },
+
+    /// Rename a group chat (admins only).
+    RenameChat { peer_id: i64, title: String },
+
+    /// Set the photo of a group chat from a local image (admins only).
+    SetChatPhoto { peer_id: i64, path: PathBuf },
+
+    /// Delete a conversation with its whole history (for the current user).
+    DeleteConversation { peer_id: i64 },
+
+    /// Turn notifications of a chat off for `duration`, or back on for `None`.
+    SetChatMuted {
+        peer_id: i64,
+        duration: Option<MuteDuration>,
+    },
+
+    /// Pin a chat to the top of the conversation list, or unpin it.
+    SetChatPinned { peer_id: i64, pinned: bool },
+
+    /// Create a group chat with the given users.
+    CreateChat { user_ids: Vec<i64>, title: String },
+
     // === Search ===
     /// Search messages globally.
     SearchMessages { query: String, peer_id: Option<i64> },
 
+    /// Search the history of `peer_id` on the device: `loaded` messages
+    /// and the message cache. Results arrive as `GrepResults`.
+    GrepHistory {
+        peer_id: i64,
+        chat_title: String,
+        query: String,
+        loaded: Vec<ChatMessage>,
+    },
+
     // === Other ===
     /// Start LongPoll listener.
     StartLongPoll,
@@ -106,4 +184,25 @@ pub enum AsyncCommand {
 
     /// Fetch message details by ID.
     FetchMessageById { message_id: i64 },
+
+    /// Load profiles of users that are not known yet, in one request.
+    LoadUsers { user_ids: Vec<i64> },
+
+    /// Fetch profile details of a user, or of a community if negative.
+    FetchUserProfile { user_id: i64 },
+
+    /// Load the profile of the logged-in account.
+    LoadProfile,
+
+    /// Save settings to the settings file and use them from now on.
+    SaveSettings(Box<Settings>),
+
+    // === Captcha ===
+    /// Answer the captcha of [`CoreEvent::CaptchaRequired`](crate::CoreEvent::CaptchaRequired)
+    /// with `key` and run the command that hit it again.
+    AnswerCaptcha {
+        sid: String,
+        key: String,
+        retry: Box<AsyncCommand>,
+    },
 }
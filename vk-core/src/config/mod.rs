@@ -0,0 +1,400 @@
+//! Settings shared by all frontends.
+//!
+//! They live in `settings.toml` in the config directory. Core settings sit
+//! at the top level; each frontend keeps its own options in a section of
+//! its name (`[tui]`, `[gui]`, `[tauri]`) and reads it with
+//! [`Settings::section`].
+//!
+//! A mistake in the file never stops the app: the broken value keeps its
+//! default and the problem is returned from [`Settings::load`] to be shown.
+
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use vk_api::VkClient;
+
+use crate::{download, persist};
+
+/// Messages and chats fetched per request unless configured otherwise.
+pub const DEFAULT_MESSAGES_PAGE_SIZE: u32 = 50;
+
+/// Page sizes accepted; VK returns at most 200 items per request.
+pub const MESSAGES_PAGE_SIZE_RANGE: RangeInclusive<u32> = 1..=200;
+
+/// Request timeouts accepted, in seconds.
+pub const REQUEST_TIMEOUT_RANGE: RangeInclusive<u64> = 1..=300;
+
+/// A frontend with a section of its own in the settings file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frontend {
+    Tui,
+    Gui,
+    Tauri,
+}
+
+impl Frontend {
+    /// Name of the section in the file.
+    pub fn key(self) -> &'static str {
+        match self {
+            Frontend::Tui => "tui",
+            Frontend::Gui => "gui",
+            Frontend::Tauri => "tauri",
+        }
+    }
+}
+
+/// Contents of `settings.toml`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Where attachments are saved; `None` is the user's download folder
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_dir: Option<PathBuf>,
+    /// Messages and chats fetched per request
+    pub messages_page_size: u32,
+    /// Proxy for VK requests; `HTTPS_PROXY` or `ALL_PROXY` when unset, and
+    /// an empty one connects directly
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// API base URL, for a mirror of api.vk.com
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+    /// Seconds an API request may take
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_timeout: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// Send text over VK's length limit as several messages instead of
+    /// refusing it
+    pub split_long_messages: bool,
+    #[serde(skip_serializing_if = "toml::Table::is_empty")]
+    pub tui: toml::Table,
+    #[serde(skip_serializing_if = "toml::Table::is_empty")]
+    pub gui: toml::Table,
+    #[serde(skip_serializing_if = "toml::Table::is_empty")]
+    pub tauri: toml::Table,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            download_dir: None,
+            messages_page_size: DEFAULT_MESSAGES_PAGE_SIZE,
+            proxy: None,
+            api_url: None,
+            request_timeout: None,
+            user_agent: None,
+            split_long_messages: false,
+            tui: toml::Table::new(),
+            gui: toml::Table::new(),
+            tauri: toml::Table::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Load the settings file, with the problems found in it. A missing
+    /// file yields the defaults and no problems.
+    pub fn load() -> (Self, Vec<String>) {
+        let Some(path) = Self::path() else {
+            return (Self::default(), Vec::new());
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                let (settings, problems) = Self::parse(&text);
+                let problems = problems
+                    .into_iter()
+                    .map(|p| format!("{}: {}", path.display(), p))
+                    .collect();
+                (settings, problems)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (Self::default(), Vec::new()),
+            Err(e) => (
+                Self::default(),
+                vec![format!("Cannot read {}: {}", path.display(), e)],
+            ),
+        }
+    }
+
+    /// Read settings from TOML. Each value that is missing or wrong keeps
+    /// its default; the wrong ones are described in the returned problems.
+    pub fn parse(text: &str) -> (Self, Vec<String>) {
+        let mut settings = Self::default();
+        let mut problems = Vec::new();
+        let mut table = match text.parse::<toml::Table>() {
+            Ok(table) => table,
+            Err(e) => {
+                problems.push(format!("not valid TOML, using defaults: {}", e.message()));
+                return (settings, problems);
+            }
+        };
+
+        if let Some(dir) = take::<PathBuf>(&mut table, "download_dir", &mut problems) {
+            settings.download_dir = Some(dir);
+        }
+        if let Some(size) = take::<u32>(&mut table, "messages_page_size", &mut problems) {
+            if MESSAGES_PAGE_SIZE_RANGE.contains(&size) {
+                settings.messages_page_size = size;
+            } else {
+                problems.push(format!(
+                    "messages_page_size must be {} to {}, got {}",
+                    MESSAGES_PAGE_SIZE_RANGE.start(),
+                    MESSAGES_PAGE_SIZE_RANGE.end(),
+                    size
+                ));
+            }
+        }
+        settings.proxy = take(&mut table, "proxy", &mut problems);
+        settings.api_url = take(&mut table, "api_url", &mut problems);
+        settings.user_agent = take(&mut table, "user_agent", &mut problems);
+        if let Some(split) = take(&mut table, "split_long_messages", &mut problems) {
+            settings.split_long_messages = split;
+        }
+        if let Some(secs) = take::<u64>(&mut table, "request_timeout", &mut problems) {
+            if REQUEST_TIMEOUT_RANGE.contains(&secs) {
+                settings.request_timeout = Some(secs);
+            } else {
+                problems.push(format!(
+                    "request_timeout must be {} to {} seconds, got {}",
+                    REQUEST_TIMEOUT_RANGE.start(),
+                    REQUEST_TIMEOUT_RANGE.end(),
+                    secs
+                ));
+            }
+        }
+        for frontend in [Frontend::Tui, Frontend::Gui, Frontend::Tauri] {
+            if let Some(section) = take::<toml::Table>(&mut table, frontend.key(), &mut problems) {
+                *settings.section_mut(frontend) = section;
+            }
+        }
+        problems.extend(table.keys().map(|key| format!("unknown setting {}", key)));
+
+        (settings, problems)
+    }
+
+    /// Write the settings file, replacing it atomically so a crash while
+    /// saving never leaves half of it behind.
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+        let text = toml::to_string_pretty(self).map_err(io::Error::other)?;
+        persist::write_atomic(&path, &text)
+    }
+
+    /// Where the settings file is.
+    pub fn path() -> Option<PathBuf> {
+        directories::ProjectDirs::from("", "", "vk_tui")
+            .map(|dirs| dirs.config_dir().join("settings.toml"))
+    }
+
+    /// Where attachments are saved.
+    pub fn download_dir(&self) -> PathBuf {
+        self.download_dir
+            .clone()
+            .unwrap_or_else(download::download_dir)
+    }
+
+    /// A VK client for `token` that goes through the configured proxy to
+    /// the configured API URL. Fails only on a proxy URL that cannot work.
+    pub fn client(&self, token: impl Into<String>) -> vk_api::Result<VkClient> {
+        let mut builder = VkClient::builder(token);
+        match self.proxy.as_deref() {
+            Some("") => builder = builder.no_proxy(),
+            Some(proxy) => builder = builder.proxy(proxy),
+            None => {}
+        }
+        if let Some(url) = &self.api_url {
+            builder = builder.api_url(url.as_str());
+        }
+        if let Some(secs) = self.request_timeout {
+            builder = builder.timeout(Duration::from_secs(secs));
+        }
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent.as_str());
+        }
+        builder.try_build()
+    }
+
+    /// The section of `frontend` as its own options type. An empty section
+    /// is `None`, so a frontend can fall back to an older config file.
+    pub fn section<T: DeserializeOwned>(&self, frontend: Frontend) -> Option<Result<T, String>> {
+        let section = self.section_ref(frontend);
+        if section.is_empty() {
+            return None;
+        }
+        Some(
+            section
+                .clone()
+                .try_into()
+                .map_err(|e: toml::de::Error| format!("[{}]: {}", frontend.key(), e.message())),
+        )
+    }
+
+    /// Replace the section of `frontend` with `options`.
+    pub fn set_section<T: Serialize>(
+        &mut self,
+        frontend: Frontend,
+        options: &T,
+    ) -> Result<(), String> {
+        let table = toml::Table::try_from(options).map_err(|e| e.to_string())?;
+        *self.section_mut(frontend) = table;
+        Ok(())
+    }
+
+    fn section_ref(&self, frontend: Frontend) -> &toml::Table {
+        match frontend {
+            Frontend::Tui => &self.tui,
+            Frontend::Gui => &self.gui,
+            Frontend::Tauri => &self.tauri,
+        }
+    }
+
+    fn section_mut(&mut self, frontend: Frontend) -> &mut toml::Table {
+        match frontend {
+            Frontend::Tui => &mut self.tui,
+            Frontend::Gui => &mut self.gui,
+            Frontend::Tauri => &mut self.tauri,
+        }
+    }
+}
+
+/// Remove `key` from `table` and convert it, noting a wrong type.
+fn take<T: DeserializeOwned>(
+    table: &mut toml::Table,
+    key: &str,
+    problems: &mut Vec<String>,
+) -> Option<T> {
+    let value = table.remove(key)?;
+    match value.try_into() {
+        Ok(value) => Some(value),
+        Err(e) => {
+            let e: toml::de::Error = e;
+            problems.push(format!("{}: {}", key, e.message()));
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    #[serde(default)]
+    struct TuiOptions {
+        disable_mouse: bool,
+        sidebar_width: Option<u16>,
+    }
+
+    #[test]
+    fn test_defaults() {
+        let (settings, problems) = Settings::parse("");
+        assert!(problems.is_empty());
+        assert_eq!(settings, Settings::default());
+        assert_eq!(settings.messages_page_size, DEFAULT_MESSAGES_PAGE_SIZE);
+        assert_eq!(settings.download_dir(), download::download_dir());
+    }
+
+    #[test]
+    fn test_core_values() {
+        let (settings, problems) =
+            Settings::parse("download_dir = \"/data/vk\"\nmessages_page_size = 100\n");
+        assert!(problems.is_empty());
+        assert_eq!(settings.download_dir(), PathBuf::from("/data/vk"));
+        assert_eq!(settings.messages_page_size, 100);
+    }
+
+    #[test]
+    fn test_split_long_messages() {
+        assert!(!Settings::default().split_long_messages);
+        let (settings, problems) = Settings::parse("split_long_messages = true\n");
+        assert!(problems.is_empty());
+        assert!(settings.split_long_messages);
+    }
+
+    #[test]
+    fn test_bad_values_keep_defaults() {
+        let (settings, problems) = Settings::parse(
+            "download_dir = 5\nmessages_page_size = 1000\ncolour = \"red\"\n[tui]\nx = 1\n",
+        );
+        assert_eq!(settings.download_dir, None);
+        assert_eq!(settings.messages_page_size, DEFAULT_MESSAGES_PAGE_SIZE);
+        // The good section is still read
+        assert_eq!(settings.tui.get("x"), Some(&toml::Value::Integer(1)));
+        assert_eq!(problems.len(), 3);
+        assert!(problems[0].starts_with("download_dir: "));
+        assert_eq!(problems[1], "messages_page_size must be 1 to 200, got 1000");
+        assert_eq!(problems[2], "unknown setting colour");
+    }
+
+    #[test]
+    fn test_network_values() {
+        let (settings, problems) = Settings::parse(
+            "proxy = \"socks5://127.0.0.1:1080\"\napi_url = \"https://vk.mirror.test/method\"\nrequest_timeout = 60\n",
+        );
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(settings.proxy.as_deref(), Some("socks5://127.0.0.1:1080"));
+        assert_eq!(settings.request_timeout, Some(60));
+        assert!(settings.client("token").is_ok());
+
+        let (settings, problems) = Settings::parse("request_timeout = 0\n");
+        assert_eq!(settings.request_timeout, None);
+        assert_eq!(
+            problems,
+            ["request_timeout must be 1 to 300 seconds, got 0"]
+        );
+
+        let settings = Settings {
+            proxy: Some("http://[broken".into()),
+            ..Settings::default()
+        };
+        assert!(matches!(
+            settings.client("token"),
+            Err(vk_api::Error::InvalidProxy { .. })
+        ));
+    }
+
+    #[test]
+    fn test_broken_file() {
+        let (settings, problems) = Settings::parse("messages_page_size = [");
+        assert_eq!(settings, Settings::default());
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("not valid TOML"));
+    }
+
+    #[test]
+    fn test_sections() {
+        let (settings, _) = Settings::parse("[tui]\nsidebar_width = 40\n");
+        let tui: TuiOptions = settings.section(Frontend::Tui).unwrap().unwrap();
+        assert_eq!(tui.sidebar_width, Some(40));
+        assert!(settings.section::<TuiOptions>(Frontend::Gui).is_none());
+
+        let (settings, _) = Settings::parse("[tui]\ndisable_mouse = \"yes\"\n");
+        let error = settings.section::<TuiOptions>(Frontend::Tui).unwrap();
+        assert!(error.unwrap_err().starts_with("[tui]: "));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut settings = Settings {
+            download_dir: Some(PathBuf::from("/data/vk")),
+            messages_page_size: 80,
+            ..Settings::default()
+        };
+        let tui = TuiOptions {
+            disable_mouse: true,
+            sidebar_width: Some(25),
+        };
+        settings.set_section(Frontend::Tui, &tui).unwrap();
+
+        let text = toml::to_string_pretty(&settings).unwrap();
+        let (loaded, problems) = Settings::parse(&text);
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(loaded, settings);
+        assert_eq!(loaded.section(Frontend::Tui), Some(Ok(tui)));
+    }
+}
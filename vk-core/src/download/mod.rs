@@ -0,0 +1,377 @@
+//! Saving attachments to disk.
+//!
+//! Bodies are streamed straight into the file, so large documents never
+//! sit in memory. Titles are turned into file names that are valid on
+//! every platform, and an existing file is never overwritten: the new one
+//! gets a ` (1)`, ` (2)`, ... suffix instead. Only a path the user chose
+//! ([`download_to`]) is written as given.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use futures_util::StreamExt;
+use tokio::io::AsyncWriteExt;
+
+/// Progress step when the server does not send a length.
+const UNKNOWN_TOTAL_STEP: u64 = 1024 * 1024;
+
+/// Longest file name made from a title, in bytes; most file systems allow
+/// 255, and the rest is room for a ` (n)` suffix.
+pub const MAX_NAME_BYTES: usize = 200;
+
+/// Longest extension kept when a name is shortened.
+const MAX_EXTENSION_BYTES: usize = 16;
+
+/// The user's download folder, or the temp dir if there is none.
+pub fn download_dir() -> PathBuf {
+    directories::UserDirs::new()
+        .and_then(|u| u.download_dir().map(|p| p.to_path_buf()))
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// `dir/name`, or `dir/name (1).ext`, `dir/name (2).ext`, ... if taken.
+pub fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let name = sanitize_file_name(name);
+    let path = dir.join(&name);
+    if !path.exists() {
+        return path;
+    }
+
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{}", ext)),
+        _ => (name.as_str(), String::new()),
+    };
+    (1..)
+        .map(|n| dir.join(format!("{} ({}){}", stem, n, ext)))
+        .find(|path| !path.exists())
+        .expect("some suffix is free")
+}
+
+/// "photo.jpg 45%", or "photo.jpg 1.2 MB" without a known total.
+pub fn progress_text(title: &str, received: u64, total: Option<u64>) -> String {
+    match total {
+        Some(total) => format!("{} {}%", title, received.min(total) * 100 / total.max(1)),
+        None => format!("{} {:.1} MB", title, received as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+/// `path` with the home directory shown as `~`.
+pub fn display_path(path: &Path) -> String {
+    directories::BaseDirs::new()
+        .and_then(|dirs| {
+            let rest = path.strip_prefix(dirs.home_dir()).ok()?;
+            Some(Path::new("~").join(rest).display().to_string())
+        })
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// Attachment title as a file name valid on Linux, macOS and Windows:
+/// separators and the characters Windows forbids become `_`, trailing dots
+/// and spaces are dropped, and a long name is cut to [`MAX_NAME_BYTES`]
+/// keeping its extension. Separators cannot leave the target folder.
+pub fn sanitize_file_name(title: &str) -> String {
+    let name: String = title
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    // Windows drops them itself; this also turns "." and ".." into ""
+    match name.trim_end_matches(['.', ' ']) {
+        "" => "attachment".to_string(),
+        name => shorten(name, MAX_NAME_BYTES),
+    }
+}
+
+/// `name` cut to at most `max` bytes on a char boundary, keeping a short
+/// extension.
+fn shorten(name: &str, max: usize) -> String {
+    if name.len() <= max {
+        return name.to_string();
+    }
+    let ext = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && ext.len() < MAX_EXTENSION_BYTES => {
+            &name[name.len() - ext.len() - 1..]
+        }
+        _ => "",
+    };
+    let mut end = max - ext.len();
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", name[..end].trim_end_matches(['.', ' ']), ext)
+}
+
+/// Decides when a download reports progress: at every new percent, or
+/// every [`UNKNOWN_TOTAL_STEP`] bytes when the length is unknown.
+#[derive(Debug, Default)]
+struct ProgressGate {
+    last_step: Option<u64>,
+}
+
+impl ProgressGate {
+    fn should_report(&mut self, received: u64, total: Option<u64>) -> bool {
+        let step = match total {
+            Some(total) => received * 100 / total.max(1),
+            None => received / UNKNOWN_TOTAL_STEP,
+        };
+        if self.last_step == Some(step) {
+            return false;
+        }
+        self.last_step = Some(step);
+        true
+    }
+}
+
+/// Stream `url` into a new file in `dir` named after `title` and return
+/// its path. `progress` gets the bytes received and the total, if the
+/// server sent one. A partly written file is removed on failure.
+pub async fn download(
+    client: &reqwest::Client,
+    url: &str,
+    dir: &Path,
+    title: &str,
+    progress: impl FnMut(u64, Option<u64>),
+) -> io::Result<PathBuf> {
+    let response = fetch(client, url).await?;
+    tokio::fs::create_dir_all(dir).await?;
+    let path = unique_path(dir, title);
+    write_body(response, &path, progress).await?;
+    Ok(path)
+}
+
+/// Stream `url` into `path`, replacing the file there: the user chose it
+/// in a Save As dialog, which already asked about overwriting.
+pub async fn download_to(
+    client: &reqwest::Client,
+    url: &str,
+    path: &Path,
+    progress: impl FnMut(u64, Option<u64>),
+) -> io::Result<PathBuf> {
+    let response = fetch(client, url).await?;
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    write_body(response, path, progress).await?;
+    Ok(path.to_path_buf())
+}
+
+async fn fetch(client: &reqwest::Client, url: &str) -> io::Result<reqwest::Response> {
+    client
+        .get(url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(io::Error::other)
+}
+
+/// Write the body of `response` to `path`, removing the file on failure.
+async fn write_body(
+    response: reqwest::Response,
+    path: &Path,
+    mut progress: impl FnMut(u64, Option<u64>),
+) -> io::Result<()> {
+    let total = response.content_length();
+    let mut file = tokio::fs::File::create(path).await?;
+
+    let result = async {
+        let mut gate = ProgressGate::default();
+        let mut received = 0;
+        let mut body = response.bytes_stream();
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(io::Error::other)?;
+            file.write_all(&chunk).await?;
+            received += chunk.len() as u64;
+            if gate.should_report(received, total) {
+                progress(received, total);
+            }
+        }
+        file.flush().await
+    }
+    .await;
+
+    if let Err(e) = result {
+        drop(file);
+        let _ = tokio::fs::remove_file(path).await;
+        return Err(e);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("vk_core_download_{}_{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Serve `body` with a Content-Length to every connection.
+    async fn serve(body: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut buf = [0u8; 1024];
+                let _ = socket.read(&mut buf).await;
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = socket.write_all(head.as_bytes()).await;
+                let _ = socket.write_all(&body).await;
+            }
+        });
+        format!("http://{}/file", addr)
+    }
+
+    #[test]
+    fn test_unique_path_adds_suffix() {
+        let dir = temp_dir("unique");
+        assert_eq!(unique_path(&dir, "photo.jpg"), dir.join("photo.jpg"));
+
+        std::fs::write(dir.join("photo.jpg"), b"").unwrap();
+        assert_eq!(unique_path(&dir, "photo.jpg"), dir.join("photo (1).jpg"));
+        std::fs::write(dir.join("photo (1).jpg"), b"").unwrap();
+        assert_eq!(unique_path(&dir, "photo.jpg"), dir.join("photo (2).jpg"));
+
+        std::fs::write(dir.join("README"), b"").unwrap();
+        assert_eq!(unique_path(&dir, "README"), dir.join("README (1)"));
+        std::fs::write(dir.join(".env"), b"").unwrap();
+        assert_eq!(unique_path(&dir, ".env"), dir.join(".env (1)"));
+    }
+
+    #[test]
+    fn test_title_cannot_escape_dir() {
+        let dir = temp_dir("escape");
+        assert_eq!(unique_path(&dir, "../x.txt"), dir.join(".._x.txt"));
+        assert_eq!(unique_path(&dir, ".."), dir.join("attachment"));
+    }
+
+    #[test]
+    fn test_sanitize_file_name() {
+        assert_eq!(sanitize_file_name("report.pdf"), "report.pdf");
+        assert_eq!(
+            sanitize_file_name("a/b\\c:d*e?f\"g<h>i|j.txt"),
+            "a_b_c_d_e_f_g_h_i_j.txt"
+        );
+        assert_eq!(sanitize_file_name("line\nbreak"), "line_break");
+        assert_eq!(sanitize_file_name("  notes. . "), "notes");
+        assert_eq!(sanitize_file_name("Фото 2025.jpg"), "Фото 2025.jpg");
+        assert_eq!(sanitize_file_name(""), "attachment");
+        assert_eq!(sanitize_file_name("."), "attachment");
+        assert_eq!(sanitize_file_name("?"), "_");
+    }
+
+    #[test]
+    fn test_sanitize_shortens_long_names() {
+        let long = format!("{}.pdf", "x".repeat(300));
+        let name = sanitize_file_name(&long);
+        assert_eq!(name.len(), MAX_NAME_BYTES);
+        assert!(name.ends_with("xx.pdf"));
+
+        // Two bytes per char: the cut does not split one
+        let name = sanitize_file_name(&"я".repeat(150));
+        assert!(name.len() <= MAX_NAME_BYTES);
+        assert_eq!(name.chars().count(), MAX_NAME_BYTES / 2);
+
+        // A long "extension" is not one
+        let name = sanitize_file_name(&format!("a.{}", "b".repeat(300)));
+        assert_eq!(name.len(), MAX_NAME_BYTES);
+    }
+
+    #[test]
+    fn test_long_names_get_suffix() {
+        let dir = temp_dir("long");
+        let long = format!("{}.jpg", "x".repeat(300));
+        let first = unique_path(&dir, &long);
+        std::fs::write(&first, b"").unwrap();
+        let second = unique_path(&dir, &long);
+        let name = second.file_name().unwrap().to_str().unwrap();
+        assert!(name.ends_with("x (1).jpg"));
+        assert_eq!(name.len(), MAX_NAME_BYTES + " (1)".len());
+    }
+
+    #[test]
+    fn test_progress_text() {
+        assert_eq!(progress_text("photo.jpg", 450, Some(1000)), "photo.jpg 45%");
+        assert_eq!(progress_text("a.bin", 1536 * 1024, None), "a.bin 1.5 MB");
+        assert_eq!(progress_text("empty", 0, Some(0)), "empty 0%");
+    }
+
+    #[test]
+    fn test_display_path_shortens_home() {
+        let Some(home) = directories::BaseDirs::new().map(|d| d.home_dir().to_path_buf()) else {
+            return;
+        };
+        assert_eq!(
+            display_path(&home.join("Downloads").join("photo.jpg")),
+            "~/Downloads/photo.jpg"
+        );
+        assert_eq!(display_path(Path::new("/elsewhere/x")), "/elsewhere/x");
+    }
+
+    #[test]
+    fn test_progress_gate() {
+        let mut gate = ProgressGate::default();
+        assert!(gate.should_report(1, Some(1000)));
+        assert!(!gate.should_report(9, Some(1000)));
+        assert!(gate.should_report(10, Some(1000)));
+        assert!(gate.should_report(1000, Some(1000)));
+
+        let mut gate = ProgressGate::default();
+        assert!(gate.should_report(10, None));
+        assert!(!gate.should_report(UNKNOWN_TOTAL_STEP - 1, None));
+        assert!(gate.should_report(UNKNOWN_TOTAL_STEP, None));
+    }
+
+    #[tokio::test]
+    async fn test_download_streams_to_new_file() {
+        let dir = temp_dir("stream");
+        let body = vec![b'x'; 300 * 1024];
+        let url = serve(body.clone()).await;
+        let client = reqwest::Client::new();
+
+        let mut reports = Vec::new();
+        let first = download(&client, &url, &dir, "doc.pdf", |r, t| reports.push((r, t)))
+            .await
+            .unwrap();
+        let second = download(&client, &url, &dir, "doc.pdf", |_, _| {})
+            .await
+            .unwrap();
+
+        assert_eq!(first, dir.join("doc.pdf"));
+        assert_eq!(second, dir.join("doc (1).pdf"));
+        assert_eq!(std::fs::read(&first).unwrap(), body);
+        let total = body.len() as u64;
+        assert_eq!(reports.last(), Some(&(total, Some(total))));
+    }
+
+    #[tokio::test]
+    async fn test_download_to_chosen_path() {
+        let dir = temp_dir("chosen");
+        let url = serve(b"new".to_vec()).await;
+        let client = reqwest::Client::new();
+
+        let target = dir.join("sub").join("saved as.bin");
+        std::fs::create_dir_all(target.parent().unwrap()).unwrap();
+        std::fs::write(&target, b"old").unwrap();
+        let path = download_to(&client, &url, &target, |_, _| {})
+            .await
+            .unwrap();
+
+        assert_eq!(path, target);
+        assert_eq!(std::fs::read(&target).unwrap(), b"new");
+    }
+}
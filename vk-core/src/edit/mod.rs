@@ -0,0 +1,154 @@
+//! Edit-conflict detection and VK's limits on edits.
+//!
+//! When the user starts editing a message we remember a hash of its text
+//! (the *base*). Right before submitting, the current server text is fetched
+//! and compared against the base: if somebody edited the message from another
+//! device in the meantime, the edit is not sent and the frontend asks the user
+//! how to resolve the conflict.
+//!
+//! The same fetch yields the attachments of the message: `messages.edit`
+//! drops every attachment that is not sent again with the new text.
+
+use vk_api::VkClient;
+
+use crate::models::ChatMessage;
+
+/// VK refuses to edit messages older than this.
+pub const EDIT_WINDOW_SECS: i64 = 24 * 60 * 60;
+
+/// Why `e` does nothing on a message older than [`EDIT_WINDOW_SECS`].
+pub const EDIT_TOO_OLD: &str = "VK does not allow editing messages older than 24 hours";
+
+/// Whether VK still accepts edits of a message sent at `timestamp`.
+pub fn is_editable(timestamp: i64, now: i64) -> bool {
+    now - timestamp < EDIT_WINDOW_SECS
+}
+
+/// Stable 64-bit hash of message text (FNV-1a).
+///
+/// Unlike `DefaultHasher`, the value does not depend on the Rust version or
+/// process, so frontends can keep it across restarts.
+pub fn content_hash(text: &str) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    text.bytes().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(PRIME)
+    })
+}
+
+/// Whether `server_text` differs from the text the edit was based on.
+pub fn is_conflict(base_hash: u64, server_text: &str) -> bool {
+    content_hash(server_text) != base_hash
+}
+
+/// Apply an edit made elsewhere, as Long Poll reported it, to `msg` without
+/// fetching it again. Returns `false` when it has to be fetched instead:
+/// the update carried no text, or the message has attachments that the
+/// edit may have changed.
+pub fn apply_remote_edit(
+    msg: &mut ChatMessage,
+    new_text: Option<&str>,
+    has_attachments: bool,
+) -> bool {
+    let Some(text) = new_text else {
+        return false;
+    };
+    if has_attachments || !msg.attachments.is_empty() {
+        return false;
+    }
+    msg.text = text.to_string();
+    msg.is_edited = true;
+    true
+}
+
+/// What the server copy of a message says about an edit of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditCheck {
+    /// Changed elsewhere since the edit began: the server text.
+    Conflict(String),
+    /// Safe to submit, sending these attachments again to keep them.
+    Ready { attachments: Vec<String> },
+}
+
+/// Fetch the message about to be edited, comparing its text with the edit
+/// base if there is one.
+pub async fn check_edit(
+    client: &VkClient,
+    message_id: i64,
+    base_hash: Option<u64>,
+) -> vk_api::Result<EditCheck> {
+    let messages = client.messages().get_by_id(&[message_id]).await?;
+    let Some(msg) = messages.into_iter().next() else {
+        // Message is gone; let messages.edit report the failure
+        return Ok(EditCheck::Ready {
+            attachments: Vec::new(),
+        });
+    };
+
+    if base_hash.is_some_and(|base_hash| is_conflict(base_hash, &msg.text)) {
+        return Ok(EditCheck::Conflict(msg.text));
+    }
+    Ok(EditCheck::Ready {
+        attachments: msg.attachments.iter().filter_map(|a| a.api_ref()).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::message;
+    use crate::models::{AttachmentInfo, AttachmentKind};
+
+    #[test]
+    fn test_content_hash_is_stable() {
+        assert_eq!(content_hash(""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(content_hash("hello"), content_hash("hello"));
+        assert_ne!(content_hash("hello"), content_hash("hello!"));
+    }
+
+    #[test]
+    fn test_is_editable() {
+        let now = 1_700_000_000;
+        assert!(is_editable(now - 60, now));
+        assert!(is_editable(now - EDIT_WINDOW_SECS + 1, now));
+        assert!(!is_editable(now - EDIT_WINDOW_SECS, now));
+        assert!(!is_editable(now - 3 * EDIT_WINDOW_SECS, now));
+    }
+
+    #[test]
+    fn test_is_conflict() {
+        let base = content_hash("original");
+        assert!(!is_conflict(base, "original"));
+        assert!(is_conflict(base, "edited on phone"));
+    }
+
+    #[test]
+    fn test_apply_remote_edit() {
+        let mut msg = message(42, "see you at 9");
+        assert!(apply_remote_edit(&mut msg, Some("see you at 10"), false));
+        assert_eq!(msg.text, "see you at 10");
+        assert!(msg.is_edited);
+
+        // No text in the update: fetch
+        let mut msg = message(42, "see you at 9");
+        assert!(!apply_remote_edit(&mut msg, None, false));
+        assert_eq!(msg.text, "see you at 9");
+        assert!(!msg.is_edited);
+
+        // The edit lists attachments, or the message had some: fetch
+        assert!(!apply_remote_edit(&mut msg, Some("photo"), true));
+        msg.attachments.push(AttachmentInfo {
+            kind: AttachmentKind::Photo,
+            title: "Photo".into(),
+            url: None,
+            thumbnail_url: None,
+            size: None,
+            subtitle: None,
+            description: None,
+            target_path: None,
+        });
+        assert!(!apply_remote_edit(&mut msg, Some("caption"), false));
+        assert_eq!(msg.text, "see you at 9");
+    }
+}
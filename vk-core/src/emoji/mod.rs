@@ -0,0 +1,146 @@
+//! Emoji dataset for pickers and `:shortcode:` lookup.
+//!
+//! The dataset is `emoji.tsv`, embedded at compile time: one emoji per line
+//! with its shortcode and English name, in the order pickers list them.
+
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+const DATASET: &str = include_str!("emoji.tsv");
+
+/// Recently used emoji kept by [`RecentEmoji`].
+pub const RECENT_LIMIT: usize = 16;
+
+/// One emoji of the dataset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Emoji {
+    pub emoji: &'static str,
+    /// GitHub-style shortcode without colons, e.g. `joy`
+    pub shortcode: &'static str,
+    pub name: &'static str,
+}
+
+/// Every emoji of the dataset, in picker order.
+pub fn all() -> &'static [Emoji] {
+    static EMOJI: OnceLock<Vec<Emoji>> = OnceLock::new();
+    EMOJI.get_or_init(|| {
+        DATASET
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let mut fields = line.split('\t');
+                Some(Emoji {
+                    emoji: fields.next()?,
+                    shortcode: fields.next()?,
+                    name: fields.next()?,
+                })
+            })
+            .collect()
+    })
+}
+
+/// Emoji of a shortcode, with or without the surrounding colons.
+pub fn by_shortcode(code: &str) -> Option<&'static Emoji> {
+    let code = code.trim_matches(':');
+    all().iter().find(|e| e.shortcode == code)
+}
+
+/// Emoji matching `query`, best first: shortcode prefix, then a word of
+/// the name starting with it, then the query anywhere in either. An empty
+/// query lists the whole dataset.
+pub fn search(query: &str) -> Vec<&'static Emoji> {
+    let query = query.trim().trim_matches(':').to_lowercase();
+    if query.is_empty() {
+        return all().iter().collect();
+    }
+
+    let mut ranked: Vec<(u8, &'static Emoji)> = all()
+        .iter()
+        .filter_map(|e| {
+            let name = e.name.to_lowercase();
+            let rank = if e.shortcode.starts_with(&query) {
+                0
+            } else if name
+                .split(|c: char| !c.is_alphanumeric())
+                .any(|word| word.starts_with(&query))
+            {
+                1
+            } else if e.shortcode.contains(&query) || name.contains(&query) {
+                2
+            } else {
+                return None;
+            };
+            Some((rank, e))
+        })
+        .collect();
+    // Stable, so equal ranks keep picker order
+    ranked.sort_by_key(|(rank, _)| *rank);
+    ranked.into_iter().map(|(_, e)| e).collect()
+}
+
+/// Recently used emoji, most recent first.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecentEmoji {
+    emoji: Vec<String>,
+}
+
+impl RecentEmoji {
+    /// Move `emoji` to the front, dropping the oldest past [`RECENT_LIMIT`].
+    pub fn push(&mut self, emoji: &str) {
+        self.emoji.retain(|e| e != emoji);
+        self.emoji.insert(0, emoji.to_string());
+        self.emoji.truncate(RECENT_LIMIT);
+    }
+
+    pub fn list(&self) -> &[String] {
+        &self.emoji
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shortcodes(query: &str) -> Vec<&'static str> {
+        search(query).iter().map(|e| e.shortcode).collect()
+    }
+
+    #[test]
+    fn test_dataset_parses() {
+        assert!(all().len() > 200);
+        assert!(
+            all()
+                .iter()
+                .all(|e| !e.emoji.is_empty() && !e.name.is_empty())
+        );
+        assert_eq!(by_shortcode(":joy:").map(|e| e.emoji), Some("😂"));
+        assert_eq!(by_shortcode("+1").map(|e| e.emoji), Some("👍"));
+        assert!(by_shortcode("no_such_emoji").is_none());
+    }
+
+    #[test]
+    fn test_search_ranks_shortcode_prefix_first() {
+        let hearts = shortcodes("heart");
+        assert_eq!(hearts[0], "heart_eyes");
+        assert!(hearts.contains(&"broken_heart"));
+        // Name word match: "cat with tears of joy"
+        assert!(shortcodes("tears").contains(&"joy_cat"));
+        assert_eq!(search("").len(), all().len());
+        assert!(search("zzzz_nothing").is_empty());
+    }
+
+    #[test]
+    fn test_recent_moves_to_front_and_caps() {
+        let mut recent = RecentEmoji::default();
+        recent.push("😂");
+        recent.push("👍");
+        recent.push("😂");
+        assert_eq!(recent.list(), ["😂", "👍"]);
+
+        for e in all().iter().take(RECENT_LIMIT + 4) {
+            recent.push(e.emoji);
+        }
+        assert_eq!(recent.list().len(), RECENT_LIMIT);
+    }
+}
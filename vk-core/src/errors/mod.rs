@@ -0,0 +1,226 @@
+//! Errors shown to the user.
+//!
+//! [`CoreEvent::Error`] carries a category, so frontends can filter, and an
+//! occurrence count. The same error repeated within [`DEDUP_WINDOW`] (a
+//! Long Poll outage, say) is sent again with the count raised; frontends
+//! update the entry they already show instead of adding another one.
+
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use vk_api::error::ERROR_TOO_MANY_REQUESTS;
+
+use crate::events::CoreEvent;
+
+/// How long after an error the same one counts as a repeat.
+pub const DEDUP_WINDOW: Duration = Duration::from_secs(60);
+
+/// What kind of failure an error is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ErrorCategory {
+    /// Connection failed or timed out
+    Network,
+    /// Session is no longer valid
+    Auth,
+    /// VK throttled the requests or asked for a captcha
+    RateLimit,
+    /// VK rejected the request or answered with something unexpected
+    Api,
+    /// Local failure or a check in the client itself
+    Internal,
+}
+
+impl ErrorCategory {
+    /// Lowercase name for filters and logs.
+    pub fn label(self) -> &'static str {
+        match self {
+            ErrorCategory::Network => "network",
+            ErrorCategory::Auth => "auth",
+            ErrorCategory::RateLimit => "rate limit",
+            ErrorCategory::Api => "api",
+            ErrorCategory::Internal => "internal",
+        }
+    }
+}
+
+impl From<&vk_api::Error> for ErrorCategory {
+    fn from(e: &vk_api::Error) -> Self {
+        use vk_api::Error;
+
+        match e {
+            _ if e.is_auth() => ErrorCategory::Auth,
+            Error::Api {
+                code: ERROR_TOO_MANY_REQUESTS,
+                ..
+            }
+            | Error::Captcha { .. } => ErrorCategory::RateLimit,
+            Error::Http(_) | Error::ReadTimeout(_) | Error::Unreachable { .. } => {
+                ErrorCategory::Network
+            }
+            Error::Api { .. }
+            | Error::Parse(_)
+            | Error::UnexpectedResponse(_)
+            | Error::ResponseTooLarge { .. }
+            | Error::Upload(_)
+            | Error::EditExpired
+            | Error::MessageTooBig
+            | Error::DeleteForAllExpired => ErrorCategory::Api,
+            Error::Auth | Error::MissingScopes(_) => ErrorCategory::Auth,
+            Error::Io(_) | Error::InvalidProxy { .. } => ErrorCategory::Internal,
+        }
+    }
+}
+
+/// `text` with its repeat count: "Long Poll error: timeout ×5".
+pub fn error_text(text: &str, occurrence: u32) -> String {
+    if occurrence > 1 {
+        format!("{} ×{}", text, occurrence)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Counts repeats of the last error a producer sent.
+#[derive(Debug, Default)]
+pub struct ErrorDedup {
+    /// Last error's code, category and text, when it was last seen and
+    /// how many times in a row
+    last: Option<(Option<i64>, ErrorCategory, String, Instant, u32)>,
+}
+
+impl ErrorDedup {
+    /// Set the `occurrence` of an error event seen at `now`: 1 for a new
+    /// error, one more than last time for a repeat within
+    /// [`DEDUP_WINDOW`]. Other events pass unchanged.
+    pub fn stamp(&mut self, event: CoreEvent, now: Instant) -> CoreEvent {
+        let CoreEvent::Error {
+            code,
+            category,
+            text,
+            ..
+        } = event
+        else {
+            return event;
+        };
+
+        let occurrence = match &mut self.last {
+            Some((last_code, last_category, last_text, seen, count))
+                if *last_code == code
+                    && *last_category == category
+                    && *last_text == text
+                    && now.duration_since(*seen) <= DEDUP_WINDOW =>
+            {
+                *seen = now;
+                *count += 1;
+                *count
+            }
+            last => {
+                *last = Some((code, category, text.clone(), now, 1));
+                1
+            }
+        };
+        CoreEvent::Error {
+            code,
+            category,
+            text,
+            occurrence,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn occurrence(event: CoreEvent) -> u32 {
+        match event {
+            CoreEvent::Error { occurrence, .. } => occurrence,
+            other => panic!("not an error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_repeats_within_window_are_counted() {
+        let start = Instant::now();
+        let mut dedup = ErrorDedup::default();
+        let timeout = || CoreEvent::error("Long Poll error: timeout");
+
+        assert_eq!(occurrence(dedup.stamp(timeout(), start)), 1);
+        assert_eq!(
+            occurrence(dedup.stamp(timeout(), start + Duration::from_secs(30))),
+            2
+        );
+        // The window runs from the last repeat
+        assert_eq!(
+            occurrence(dedup.stamp(timeout(), start + Duration::from_secs(80))),
+            3
+        );
+        assert_eq!(
+            occurrence(dedup.stamp(timeout(), start + Duration::from_secs(200))),
+            1
+        );
+    }
+
+    #[test]
+    fn test_different_error_starts_over() {
+        let now = Instant::now();
+        let mut dedup = ErrorDedup::default();
+        dedup.stamp(CoreEvent::error("a"), now);
+        dedup.stamp(CoreEvent::error("a"), now);
+        assert_eq!(occurrence(dedup.stamp(CoreEvent::error("b"), now)), 1);
+        assert_eq!(occurrence(dedup.stamp(CoreEvent::error("a"), now)), 1);
+
+        let api = vk_api::Error::from_api(9, "Flood control");
+        assert_eq!(
+            occurrence(dedup.stamp(CoreEvent::api_error("a", &api), now)),
+            1
+        );
+        assert!(matches!(
+            dedup.stamp(CoreEvent::AuthExpired, now),
+            CoreEvent::AuthExpired
+        ));
+    }
+
+    #[test]
+    fn test_categories() {
+        let category = |e: vk_api::Error| ErrorCategory::from(&e);
+        assert_eq!(category(vk_api::Error::Auth), ErrorCategory::Auth);
+        assert_eq!(
+            category(vk_api::Error::from_api(ERROR_TOO_MANY_REQUESTS, "Too many")),
+            ErrorCategory::RateLimit
+        );
+        assert_eq!(
+            category(vk_api::Error::from_api(100, "Invalid param")),
+            ErrorCategory::Api
+        );
+        assert_eq!(
+            category(vk_api::Error::ReadTimeout(Duration::from_secs(5))),
+            ErrorCategory::Network
+        );
+        assert_eq!(
+            category(vk_api::Error::Io(std::io::Error::other("disk full"))),
+            ErrorCategory::Internal
+        );
+    }
+
+    #[test]
+    fn test_api_error_event() {
+        let e = vk_api::Error::from_api(100, "Invalid param");
+        match CoreEvent::api_error("Failed to load", &e) {
+            CoreEvent::Error {
+                code,
+                category,
+                text,
+                occurrence,
+            } => {
+                assert_eq!(code, Some(100));
+                assert_eq!(category, ErrorCategory::Api);
+                assert_eq!(text, "Failed to load: VK API error 100: Invalid param");
+                assert_eq!(occurrence, 1);
+            }
+            other => panic!("not an error: {:?}", other),
+        }
+        assert_eq!(error_text("x", 1), "x");
+        assert_eq!(error_text("x", 5), "x ×5");
+    }
+}
@@ -3,9 +3,19 @@
 //! These events represent state changes and async operation results
 //! that frontends need to react to.
 
-use crate::models::{AttachmentInfo, Chat, ChatMessage, ForwardItem, ReplyPreview, SearchResult};
-use vk_api::User;
-use serde::{Serialize, Deserialize};
+use std::path::PathBuf;
+
+use crate::commands::AsyncCommand;
+use crate::config::Settings;
+use crate::errors::ErrorCategory;
+use crate::media::ChatInfo;
+use crate::models::{
+    AttachmentInfo, Chat, ChatMember, ChatMessage, ForwardItem, MessageReaders, ProfileDetails,
+    ReactionCount, ReplyPreview, SearchResult, ServiceAction,
+};
+use crate::outbox::OutboxEvent;
+use serde::{Deserialize, Serialize};
+use vk_api::{ProfileInfo, User};
 
 /// Events from VK LongPoll API.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,13 +28,44 @@ pub enum VkEvent {
         text: String,
         from_id: i64,
         is_outgoing: bool,
+        /// Long Poll only says that attachments, forwards or a reply exist;
+        /// fetch the message by id to get them.
+        has_attachments: bool,
+        /// Attachment types (`photo`, `audio_message`, `fwd` for forwards)
+        /// for the chat list preview of a message without text.
+        #[serde(default)]
+        attachment_types: Vec<String>,
+        /// Service action: someone joined or left, the chat was renamed...
+        #[serde(default)]
+        action: Option<ServiceAction>,
+        /// `random_id` of our own sent messages, used to match the echo
+        /// with the optimistic entry.
+        random_id: Option<i64>,
+    },
+    /// Message flags set (`set == true`) or reset, e.g. marked important
+    /// or restored after deletion. `flags` holds only the changed bits.
+    MessageFlagsChanged {
+        message_id: i64,
+        peer_id: i64,
+        flags: i64,
+        set: bool,
     },
     /// Message read.
     MessageRead { peer_id: i64, message_id: i64 },
-    /// Message edited (from Long Poll).
-    MessageEditedFromLongPoll { peer_id: i64, message_id: i64 },
+    /// Message edited (from Long Poll). `new_text` is the text after the
+    /// edit, absent from getLongPollHistory entries; `has_attachments` says
+    /// the message lists attachments, forwards or a reply, which Long Poll
+    /// does not describe.
+    MessageEditedFromLongPoll {
+        peer_id: i64,
+        message_id: i64,
+        new_text: Option<String>,
+        has_attachments: bool,
+    },
     /// Message deleted (from Long Poll).
     MessageDeletedFromLongPoll { peer_id: i64, message_id: i64 },
+    /// Reactions on a message changed; fetch the message to get the counters.
+    ReactionsChanged { peer_id: i64, cmid: i64 },
     /// User typing.
     UserTyping { peer_id: i64, user_id: i64 },
     /// Connection status changed.
@@ -46,6 +87,10 @@ pub enum CoreEvent {
         has_more: bool,
     },
 
+    /// Conversations from the message cache, sent before the first page
+    /// arrives from the API.
+    CachedConversations { chats: Vec<Chat> },
+
     /// Messages loaded from API.
     MessagesLoaded {
         peer_id: i64,
@@ -53,17 +98,137 @@ pub enum CoreEvent {
         profiles: Vec<User>,
         total_count: u32,
         has_more: bool,
+        /// Last incoming message the user has read, if VK sent it
+        in_read: Option<i64>,
+    },
+
+    /// Newest cached messages of a chat, oldest first, sent before its
+    /// first page arrives from the API; that page replaces them.
+    CachedMessages {
+        peer_id: i64,
+        messages: Vec<ChatMessage>,
     },
 
     /// Search results loaded.
     SearchResultsLoaded {
         results: Vec<SearchResult>,
-        total_count: i32,
+        total_count: u32,
+    },
+
+    /// A batch of `GrepHistory` results for `query`, newest first; `done`
+    /// on the last one.
+    GrepResults {
+        peer_id: i64,
+        query: String,
+        results: Vec<SearchResult>,
+        done: bool,
+    },
+
+    /// Profiles requested with `LoadUsers` loaded. `requested` is always
+    /// the full request, `users` is empty if loading failed.
+    UsersLoaded {
+        requested: Vec<i64>,
+        users: Vec<User>,
+    },
+
+    /// Profile details requested with `FetchUserProfile` loaded.
+    UserProfileLoaded { user: ProfileDetails },
+
+    /// Profile of the logged-in account, requested with `LoadProfile`.
+    ProfileLoaded { user: ProfileInfo },
+
+    /// A page of chat members requested with `LoadChatMembers` loaded.
+    ChatMembersLoaded {
+        peer_id: i64,
+        offset: u32,
+        members: Vec<ChatMember>,
+        total_count: u32,
+    },
+
+    /// Member removed from a group chat.
+    ChatUserRemoved { peer_id: i64, user_id: i64 },
+
+    /// Readers requested with `FetchReadPeers` loaded.
+    ReadPeersLoaded { readers: MessageReaders },
+
+    /// Media counts requested with `LoadChatInfo` loaded.
+    ChatInfoLoaded { info: ChatInfo },
+
+    /// Title or photo of a group chat changed with `RenameChat` or
+    /// `SetChatPhoto`. Fields that did not change are `None`; so is
+    /// `photo_url` when VK did not return the new photo.
+    ChatSettingsChanged {
+        peer_id: i64,
+        title: Option<String>,
+        photo_url: Option<String>,
     },
 
+    /// Notifications of a chat turned off or on with `SetChatMuted`.
+    ChatMuteChanged { peer_id: i64, muted: bool },
+
+    /// Chat pinned or unpinned with `SetChatPinned`.
+    ChatPinChanged { peer_id: i64, pinned: bool },
+
+    /// Conversation deleted with `DeleteConversation`.
+    ConversationDeleted { peer_id: i64 },
+
+    /// Group chat created with `CreateChat`; its peer id is
+    /// [`vk_api::chat_peer_id`] of `chat_id`.
+    ChatCreated { chat_id: i64, title: String },
+
     // === Message Actions ===
     /// Message sent successfully.
-    MessageSent { message_id: i64, cmid: i64 },
+    MessageSent {
+        message_id: i64,
+        cmid: i64,
+        random_id: i64,
+    },
+
+    /// Bytes of a `SendPhoto`/`SendDoc` file sent to the upload server.
+    UploadProgress {
+        peer_id: i64,
+        local_id: u64,
+        bytes_sent: u64,
+        total: u64,
+    },
+
+    /// No file of `SendPhoto`/`SendDoc` could be uploaded; nothing was
+    /// sent. Failures of the send itself are `SendFailed`.
+    UploadFailed {
+        peer_id: i64,
+        local_id: u64,
+        error: String,
+    },
+
+    /// Some files of `SendPhoto`/`SendDoc` could not be uploaded; the
+    /// message is sent with the rest. `failed` holds their file names.
+    UploadPartlyFailed {
+        peer_id: i64,
+        local_id: u64,
+        failed: Vec<String>,
+        error: String,
+    },
+
+    /// Bytes of an attachment saved so far by `DownloadAttachments`;
+    /// `total` is `None` if the server did not send a length.
+    DownloadProgress {
+        title: String,
+        received: u64,
+        total: Option<u64>,
+    },
+
+    /// Attachment saved to `path`, with any ` (n)` suffix it got.
+    DownloadFinished { title: String, path: PathBuf },
+
+    /// Messages fetched so far by a history export of `peer_id`.
+    ExportProgress {
+        peer_id: i64,
+        done: usize,
+        total: usize,
+    },
+
+    /// Settings were saved; frontends apply them at once.
+    SettingsChanged(Box<Settings>),
 
     /// Message edited successfully.
     MessageEdited { message_id: i64 },
@@ -71,6 +236,13 @@ pub enum CoreEvent {
     /// Message deleted successfully.
     MessageDeleted { message_id: i64 },
 
+    /// Edit was not submitted because the message changed on the server
+    /// since editing started.
+    EditConflict {
+        message_id: i64,
+        server_text: String,
+    },
+
     /// Message details fetched (for updating cmid, attachments, etc).
     MessageDetailsFetched {
         message_id: i64,
@@ -81,16 +253,87 @@ pub enum CoreEvent {
         reply: Option<ReplyPreview>,
         fwd_count: Option<usize>,
         forwards: Option<Vec<ForwardItem>>,
+        reactions: Option<Vec<ReactionCount>>,
     },
 
     // === Real-time Events ===
     /// VK LongPoll event.
     VkEvent(VkEvent),
 
+    /// The Long Poll key expired or user info was lost (`failed: 2/3`) and
+    /// the runner requested a new server. Counted for diagnostics.
+    LongPollKeyExpired,
+
+    /// The connection was lost (or a reconnect was requested) and the
+    /// runner is getting a new server; `attempt` counts from 1.
+    LongPollReconnecting { attempt: u32 },
+
+    /// Long Poll keeps failing, so recent conversations are polled instead
+    /// until it answers again (then `ConnectionStatus(true)` follows).
+    LongPollDegraded,
+
+    /// Long Poll keys are invalidated so often that another client is
+    /// probably using the same token. Sent at most once per hour.
+    PossibleConcurrentSession { recent_failures: u32 },
+
     // === Errors ===
-    /// Error occurred.
-    Error(String),
+    /// Access token is no longer valid; frontends should return to the auth screen.
+    AuthExpired,
+
+    /// Error occurred. `occurrence` counts repeats of the same error;
+    /// above 1 it replaces the previous report instead of adding one.
+    Error {
+        code: Option<i64>,
+        category: ErrorCategory,
+        text: String,
+        occurrence: u32,
+    },
 
     /// Send operation failed.
     SendFailed(String),
+
+    /// VK wants the captcha at `img_url` solved before it accepts `retry`.
+    /// Answer with [`AsyncCommand::AnswerCaptcha`], handing `retry` back.
+    CaptchaRequired {
+        sid: String,
+        img_url: String,
+        retry: Box<AsyncCommand>,
+    },
+
+    /// An outbox entry was added, changed state, went out or was dropped.
+    Outbox(OutboxEvent),
+}
+
+impl CoreEvent {
+    /// Error without a typed cause, for call sites that only have a
+    /// message (local checks, older code paths).
+    pub fn error(text: impl Into<String>) -> Self {
+        CoreEvent::Error {
+            code: None,
+            category: ErrorCategory::Internal,
+            text: text.into(),
+            occurrence: 1,
+        }
+    }
+
+    /// Error for a failed API call: "context: error".
+    pub fn api_error(context: &str, e: &vk_api::Error) -> Self {
+        CoreEvent::Error {
+            code: e.code(),
+            category: e.into(),
+            text: format!("{}: {}", context, e),
+            occurrence: 1,
+        }
+    }
+}
+
+impl From<vk_api::Error> for CoreEvent {
+    fn from(e: vk_api::Error) -> Self {
+        CoreEvent::Error {
+            code: e.code(),
+            category: (&e).into(),
+            text: e.to_string(),
+            occurrence: 1,
+        }
+    }
 }
@@ -3,31 +3,110 @@
 //! This module handles all async operations and sends results
 //! back to frontends via events.
 
-use std::path::Path;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use tokio::sync::mpsc;
-use vk_api::VkClient;
+use vk_api::{BASIC_USER_FIELDS, CHAT_PEER_OFFSET, UploadProgress, VkClient, is_chat_peer};
 
+use crate::batch::{Coalescer, FETCH_WINDOW, demux};
+use crate::cache::CacheLayer;
 use crate::commands::AsyncCommand;
+use crate::config::Settings;
+use crate::download;
+use crate::edit::{EditCheck, check_edit};
+use crate::errors::ErrorDedup;
 use crate::events::CoreEvent;
-use crate::mapper::{map_attachment, map_forward_tree, map_history_message, map_reply};
-use crate::models::{AttachmentInfo, Chat, SearchResult};
+use crate::grep::{GrepPattern, grep_messages, searchable_history};
+use crate::mapper::{
+    conversation_kind, conversation_last_seen, conversation_muted, conversation_photo,
+    conversation_write_access, map_attachment, map_chat_members, map_forward_tree,
+    map_group_profile, map_history_message, map_reactions, map_read_peers, map_reply,
+    map_user_profile, message_kind, message_preview, message_sender,
+};
+use crate::media::load_chat_info;
+use crate::models::{
+    AttachmentInfo, CHAT_MEMBERS_PAGE, Chat, ChatMessage, MuteDuration, SearchResult,
+    change_chat_info_denied, create_chat_error, fill_missing_times, read_peers_error,
+    rename_chat_error, sort_chats,
+};
+use crate::outbox::{Outbox, OutboxCommand, OutboxEntry, OutboxEvent, OutboxState, failure_state};
+use crate::profiles::{DETAIL_FIELDS, GROUP_DETAIL_FIELDS};
+use crate::upload::{self, UploadKind};
 
 /// Executes async commands and sends events to frontends.
 pub struct CommandExecutor {
     client: Arc<VkClient>,
     event_tx: mpsc::UnboundedSender<CoreEvent>,
+    errors: Mutex<ErrorDedup>,
+    cache: Option<Arc<dyn CacheLayer>>,
+    settings: Mutex<Settings>,
+    /// Message ids waiting for their details to be fetched
+    details: Coalescer<i64>,
+    /// Captcha the running command hit: sid and image URL
+    captcha: Mutex<Option<(String, String)>>,
+    /// Texts sent through `AsyncCommand::Outbox` that VK has not taken yet
+    outbox: Mutex<Outbox>,
 }
 
 impl CommandExecutor {
-    /// Create a new command executor.
-    pub fn new(client: Arc<VkClient>, event_tx: mpsc::UnboundedSender<CoreEvent>) -> Self {
-        Self { client, event_tx }
+    /// Create a new command executor. With a `cache`, first pages of chats
+    /// and messages are sent from it before the API answers, and what the
+    /// API returns is stored back.
+    pub fn new(
+        client: Arc<VkClient>,
+        event_tx: mpsc::UnboundedSender<CoreEvent>,
+        cache: Option<Arc<dyn CacheLayer>>,
+    ) -> Self {
+        Self {
+            client,
+            event_tx,
+            errors: Mutex::default(),
+            cache,
+            settings: Mutex::default(),
+            details: Coalescer::new(FETCH_WINDOW),
+            captcha: Mutex::default(),
+            outbox: Mutex::default(),
+        }
+    }
+
+    /// Use `settings` (page size, download folder) instead of the defaults.
+    pub fn with_settings(self, settings: Settings) -> Self {
+        *self.settings.lock().unwrap() = settings;
+        self
+    }
+
+    /// Messages and chats fetched per request.
+    fn page_size(&self) -> u32 {
+        self.settings.lock().unwrap().messages_page_size
     }
 
     /// Execute an async command.
+    ///
+    /// A command VK answers with a captcha emits
+    /// [`CoreEvent::CaptchaRequired`] instead of an error.
     pub async fn execute(&self, cmd: AsyncCommand) {
+        // An answered captcha runs its command again, so that is what to
+        // repeat if the answer is wrong
+        let retry = match &cmd {
+            AsyncCommand::AnswerCaptcha { retry, .. } => (**retry).clone(),
+            cmd => cmd.clone(),
+        };
+        self.run(cmd).await;
+
+        let captcha = self.captcha.lock().unwrap().take();
+        if let Some((sid, img_url)) = captcha {
+            self.send_event(CoreEvent::CaptchaRequired {
+                sid,
+                img_url,
+                retry: Box::new(retry),
+            });
+        }
+    }
+
+    async fn run(&self, cmd: AsyncCommand) {
         match cmd {
             AsyncCommand::LoadConversations { offset } => {
                 self.load_conversations(offset).await;
@@ -56,13 +135,8 @@ impl CommandExecutor {
                 offset,
                 count,
             } => {
-                self.load_messages_with_start_message_id(
-                    peer_id,
-                    start_message_id,
-                    offset,
-                    count,
-                )
-                .await;
+                self.load_messages_with_start_message_id(peer_id, start_message_id, offset, count)
+                    .await;
             }
             AsyncCommand::SendMessage { peer_id, text } => {
                 self.send_message(peer_id, text).await;
@@ -74,6 +148,9 @@ impl CommandExecutor {
             } => {
                 self.send_reply(peer_id, reply_to, text).await;
             }
+            AsyncCommand::Outbox(cmd) => {
+                self.outbox(cmd).await;
+            }
             AsyncCommand::SendForward {
                 peer_id,
                 message_ids,
@@ -86,8 +163,10 @@ impl CommandExecutor {
                 message_id,
                 cmid,
                 text,
+                base_hash,
             } => {
-                self.edit_message(peer_id, message_id, cmid, text).await;
+                self.edit_message(peer_id, message_id, cmid, text, base_hash)
+                    .await;
             }
             AsyncCommand::DeleteMessage {
                 message_id,
@@ -96,24 +175,100 @@ impl CommandExecutor {
             } => {
                 self.delete_message(message_id, for_all).await;
             }
-            AsyncCommand::SendPhoto { peer_id, path } => {
-                self.send_photo(peer_id, &path).await;
+            AsyncCommand::SetReaction {
+                peer_id,
+                message_id,
+                cmid,
+                reaction_id,
+            } => {
+                self.set_reaction(peer_id, message_id, cmid, reaction_id)
+                    .await;
+            }
+            AsyncCommand::SendPhoto {
+                peer_id,
+                paths,
+                local_id,
+            } => {
+                self.send_files(peer_id, UploadKind::Photo, &paths, local_id)
+                    .await;
             }
-            AsyncCommand::SendDoc { peer_id, path } => {
-                self.send_doc(peer_id, &path).await;
+            AsyncCommand::SendDoc {
+                peer_id,
+                paths,
+                local_id,
+            } => {
+                self.send_files(peer_id, UploadKind::Doc, &paths, local_id)
+                    .await;
             }
             AsyncCommand::DownloadAttachments { attachments } => {
                 self.download_attachments(attachments).await;
             }
+            AsyncCommand::LoadChatMembers { peer_id, offset } => {
+                self.load_chat_members(peer_id, offset).await;
+            }
+            AsyncCommand::RemoveChatUser { peer_id, user_id } => {
+                self.remove_chat_user(peer_id, user_id).await;
+            }
+            AsyncCommand::FetchReadPeers { peer_id, cmid } => {
+                self.fetch_read_peers(peer_id, cmid).await;
+            }
+            AsyncCommand::LoadChatInfo { peer_id } => {
+                match load_chat_info(&self.client, peer_id).await {
+                    Ok(info) => self.send_event(CoreEvent::ChatInfoLoaded { info }),
+                    Err(e) => self.send_error("Failed to load chat info", e),
+                }
+            }
+            AsyncCommand::RenameChat { peer_id, title } => {
+                self.rename_chat(peer_id, title).await;
+            }
+            AsyncCommand::SetChatPhoto { peer_id, path } => {
+                self.set_chat_photo(peer_id, &path).await;
+            }
+            AsyncCommand::SetChatMuted { peer_id, duration } => {
+                self.set_chat_muted(peer_id, duration).await;
+            }
+            AsyncCommand::SetChatPinned { peer_id, pinned } => {
+                self.set_chat_pinned(peer_id, pinned).await;
+            }
+            AsyncCommand::DeleteConversation { peer_id } => {
+                self.delete_conversation(peer_id).await;
+            }
+            AsyncCommand::CreateChat { user_ids, title } => {
+                self.create_chat(user_ids, title).await;
+            }
             AsyncCommand::SearchMessages { query, peer_id } => {
                 self.search_messages(query, peer_id).await;
             }
+            AsyncCommand::GrepHistory {
+                peer_id,
+                chat_title,
+                query,
+                loaded,
+            } => {
+                self.grep_history(peer_id, chat_title, query, loaded).await;
+            }
             AsyncCommand::FetchMessageById { message_id } => {
-                self.fetch_message_by_id(message_id).await;
+                self.fetch_message_by_id(message_id);
             }
             AsyncCommand::MarkAsRead { peer_id } => {
                 self.mark_as_read(peer_id).await;
             }
+            AsyncCommand::LoadUsers { user_ids } => {
+                self.load_users(user_ids).await;
+            }
+            AsyncCommand::FetchUserProfile { user_id } => {
+                self.fetch_user_profile(user_id).await;
+            }
+            AsyncCommand::LoadProfile => {
+                self.load_profile().await;
+            }
+            AsyncCommand::SaveSettings(settings) => {
+                self.save_settings(*settings);
+            }
+            AsyncCommand::AnswerCaptcha { sid, key, retry } => {
+                self.client.answer_captcha(&sid, &key);
+                Box::pin(self.run(*retry)).await;
+            }
             AsyncCommand::StartLongPoll => {
                 // Handled elsewhere or no-op for now
             }
@@ -121,41 +276,103 @@ impl CommandExecutor {
     }
 
     fn send_event(&self, event: CoreEvent) {
+        let event = match self.errors.lock() {
+            Ok(mut errors) => errors.stamp(event, Instant::now()),
+            Err(_) => event,
+        };
         let _ = self.event_tx.send(event);
     }
 
+    /// Report a failed API call, turning auth failures into `AuthExpired`.
+    fn send_error(&self, context: &str, e: vk_api::Error) {
+        if e.is_auth() {
+            self.send_event(CoreEvent::AuthExpired);
+        } else if let vk_api::Error::Captcha { sid, img_url } = e {
+            *self.captcha.lock().unwrap() = Some((sid, img_url));
+        } else {
+            self.send_event(CoreEvent::api_error(context, &e));
+        }
+    }
+
+    /// Like [`Self::send_error`], but for send/edit/delete operations.
+    fn send_failed(&self, context: &str, e: vk_api::Error) {
+        if e.is_auth() {
+            self.send_event(CoreEvent::AuthExpired);
+        } else if let vk_api::Error::Captcha { sid, img_url } = e {
+            *self.captcha.lock().unwrap() = Some((sid, img_url));
+        } else {
+            self.send_event(CoreEvent::SendFailed(format!("{}: {}", context, e)));
+        }
+    }
+
     async fn load_conversations(&self, offset: u32) {
-        const COUNT: u32 = 50;
+        let count = self.page_size();
+
+        if offset == 0
+            && let Some(cache) = &self.cache
+        {
+            let chats = cache.chats();
+            if !chats.is_empty() {
+                self.send_event(CoreEvent::CachedConversations { chats });
+            }
+        }
 
         match self
             .client
             .messages()
-            .get_conversations(offset, COUNT)
+            .get_conversations(offset, count)
             .await
         {
             Ok(response) => {
-                let total_count = response.count as u32;
+                let total_count = response.count;
                 let loaded_count = response.items.len() as u32;
                 let has_more = offset + loaded_count < total_count;
 
-                let chats: Vec<Chat> = response
+                let mut chats: Vec<Chat> = response
                     .items
                     .into_iter()
                     .map(|item| {
                         let title = get_conversation_title(&item, &response.profiles);
                         let is_online =
                             get_user_online(&item.conversation.peer.id, &response.profiles);
+                        let (can_write, cannot_write_reason) = conversation_write_access(&item);
 
                         Chat {
                             id: item.conversation.peer.id,
                             title,
-                            last_message: item.last_message.text.clone(),
+                            last_message: message_preview(
+                                &item.last_message,
+                                &response.profiles,
+                                &response.groups,
+                            ),
+                            last_message_from: message_sender(
+                                &item.last_message,
+                                &response.profiles,
+                                &response.groups,
+                            ),
+                            last_message_kind: message_kind(&item.last_message),
                             last_message_time: item.last_message.date,
                             unread_count: item.conversation.unread_count.unwrap_or(0),
                             is_online,
+                            photo_url: conversation_photo(
+                                &item,
+                                &response.profiles,
+                                &response.groups,
+                            ),
+                            is_muted: conversation_muted(&item),
+                            is_pinned: item.conversation.is_pinned(),
+                            can_write,
+                            cannot_write_reason,
+                            kind: conversation_kind(&item),
+                            last_seen: conversation_last_seen(&item, &response.profiles),
                         }
                     })
                     .collect();
+                fill_missing_times(&mut chats);
+                sort_chats(&mut chats);
+                if let Some(cache) = &self.cache {
+                    cache.store_chats(&chats);
+                }
 
                 self.send_event(CoreEvent::ConversationsLoaded {
                     chats,
@@ -165,37 +382,47 @@ impl CommandExecutor {
                 });
             }
             Err(e) => {
-                self.send_event(CoreEvent::Error(format!("Failed to load chats: {}", e)));
+                self.send_error("Failed to load chats", e);
             }
         }
     }
 
     async fn load_messages(&self, peer_id: i64, offset: u32) {
-        const COUNT: u32 = 50;
+        let count = self.page_size();
+
+        if offset == 0
+            && let Some(cache) = &self.cache
+        {
+            let messages = cache.messages(peer_id, count as usize);
+            if !messages.is_empty() {
+                self.send_event(CoreEvent::CachedMessages { peer_id, messages });
+            }
+        }
 
         match self
             .client
             .messages()
-            .get_history(peer_id, offset, COUNT)
+            .get_history(peer_id, offset, count)
             .await
         {
             Ok(response) => {
-                let total_count = response.count as u32;
+                let total_count = response.count;
                 let loaded_count = response.items.len() as u32;
                 let has_more = offset + loaded_count < total_count;
 
-                let out_read = response
-                    .conversations
-                    .first()
-                    .and_then(|c| c.out_read)
-                    .unwrap_or(0);
+                let conversation = response.conversations.first();
+                let out_read = conversation.and_then(|c| c.out_read).unwrap_or(0);
+                let in_read = conversation.and_then(|c| c.in_read);
 
-                let messages = response
+                let messages: Vec<_> = response
                     .items
                     .into_iter()
                     .rev()
                     .map(|msg| map_history_message(&response.profiles, &msg, out_read))
                     .collect();
+                if let Some(cache) = &self.cache {
+                    cache.reconcile(peer_id, &messages, offset == 0);
+                }
 
                 self.send_event(CoreEvent::MessagesLoaded {
                     peer_id,
@@ -203,32 +430,31 @@ impl CommandExecutor {
                     profiles: response.profiles,
                     total_count,
                     has_more,
+                    in_read,
                 });
             }
             Err(e) => {
-                self.send_event(CoreEvent::Error(format!("Failed to load messages: {}", e)));
+                self.send_error("Failed to load messages", e);
             }
         }
     }
 
     async fn load_messages_around(&self, peer_id: i64, message_id: i64) {
-        const COUNT: u32 = 50;
+        let count = self.page_size();
 
         match self
             .client
             .messages()
-            .get_history_around(peer_id, message_id, COUNT)
+            .get_history_around(peer_id, message_id, count)
             .await
         {
             Ok(response) => {
-                let total_count = response.count as u32;
+                let total_count = response.count;
                 let has_more = true;
 
-                let out_read = response
-                    .conversations
-                    .first()
-                    .and_then(|c| c.out_read)
-                    .unwrap_or(0);
+                let conversation = response.conversations.first();
+                let out_read = conversation.and_then(|c| c.out_read).unwrap_or(0);
+                let in_read = conversation.and_then(|c| c.in_read);
 
                 let messages = response
                     .items
@@ -243,13 +469,11 @@ impl CommandExecutor {
                     profiles: response.profiles,
                     total_count,
                     has_more,
+                    in_read,
                 });
             }
             Err(e) => {
-                self.send_event(CoreEvent::Error(format!(
-                    "Failed to load messages around target: {}",
-                    e
-                )));
+                self.send_error("Failed to load messages around target", e);
             }
         }
     }
@@ -268,15 +492,13 @@ impl CommandExecutor {
             .await
         {
             Ok(response) => {
-                let total_count = response.count as u32;
+                let total_count = response.count;
                 let loaded_count = response.items.len() as u32;
                 let has_more = loaded_count == count;
 
-                let out_read = response
-                    .conversations
-                    .first()
-                    .and_then(|c| c.out_read)
-                    .unwrap_or(0);
+                let conversation = response.conversations.first();
+                let out_read = conversation.and_then(|c| c.out_read).unwrap_or(0);
+                let in_read = conversation.and_then(|c| c.in_read);
 
                 let messages = response
                     .items
@@ -291,10 +513,11 @@ impl CommandExecutor {
                     profiles: response.profiles,
                     total_count,
                     has_more,
+                    in_read,
                 });
             }
             Err(e) => {
-                self.send_event(CoreEvent::Error(format!("Failed to load messages: {}", e)));
+                self.send_error("Failed to load messages", e);
             }
         }
     }
@@ -313,15 +536,13 @@ impl CommandExecutor {
             .await
         {
             Ok(response) => {
-                let total_count = response.count as u32;
+                let total_count = response.count;
                 let loaded_count = response.items.len() as u32;
                 let has_more = loaded_count == count;
 
-                let out_read = response
-                    .conversations
-                    .first()
-                    .and_then(|c| c.out_read)
-                    .unwrap_or(0);
+                let conversation = response.conversations.first();
+                let out_read = conversation.and_then(|c| c.out_read).unwrap_or(0);
+                let in_read = conversation.and_then(|c| c.in_read);
 
                 let messages = response
                     .items
@@ -336,10 +557,11 @@ impl CommandExecutor {
                     profiles: response.profiles,
                     total_count,
                     has_more,
+                    in_read,
                 });
             }
             Err(e) => {
-                self.send_event(CoreEvent::Error(format!("Failed to load messages: {}", e)));
+                self.send_error("Failed to load messages", e);
             }
         }
     }
@@ -350,13 +572,11 @@ impl CommandExecutor {
                 self.send_event(CoreEvent::MessageSent {
                     message_id: sent.message_id,
                     cmid: sent.conversation_message_id,
+                    random_id: sent.random_id,
                 });
             }
             Err(e) => {
-                self.send_event(CoreEvent::SendFailed(format!(
-                    "Failed to send message: {}",
-                    e
-                )));
+                self.send_failed("Failed to send message", e);
             }
         }
     }
@@ -372,13 +592,88 @@ impl CommandExecutor {
                 self.send_event(CoreEvent::MessageSent {
                     message_id: sent.message_id,
                     cmid: sent.conversation_message_id,
+                    random_id: sent.random_id,
                 });
             }
             Err(e) => {
-                self.send_event(CoreEvent::SendFailed(format!(
-                    "Failed to send reply: {}",
-                    e
-                )));
+                self.send_failed("Failed to send reply", e);
+            }
+        }
+    }
+
+    async fn outbox(&self, cmd: OutboxCommand) {
+        let (to_send, dropped) = {
+            let mut outbox = self.outbox.lock().unwrap();
+            match cmd {
+                OutboxCommand::Send {
+                    peer_id,
+                    text,
+                    reply_to,
+                } => (vec![outbox.push(peer_id, text, reply_to)], Vec::new()),
+                OutboxCommand::Retry(id) => (outbox.resend(|e| e.id == id), Vec::new()),
+                OutboxCommand::RetryAll => (outbox.resend(|_| true), Vec::new()),
+                OutboxCommand::Flush => (
+                    outbox.resend(|e| e.state == OutboxState::Queued),
+                    Vec::new(),
+                ),
+                OutboxCommand::Discard(id) => (Vec::new(), outbox.discard(|e| e.id == id)),
+                OutboxCommand::DiscardAll => (Vec::new(), outbox.discard(|_| true)),
+            }
+        };
+
+        for id in dropped {
+            self.send_event(CoreEvent::Outbox(OutboxEvent::Discarded { id }));
+        }
+        for entry in &to_send {
+            self.send_event(CoreEvent::Outbox(OutboxEvent::Updated(entry.clone())));
+        }
+        for entry in to_send {
+            self.send_outbox_entry(entry).await;
+        }
+    }
+
+    async fn send_outbox_entry(&self, entry: OutboxEntry) {
+        let messages = self.client.messages();
+        let result = match entry.reply_to {
+            Some(reply_to) => {
+                messages
+                    .send_with_reply(entry.peer_id, &entry.text, reply_to)
+                    .await
+            }
+            None => messages.send(entry.peer_id, &entry.text).await,
+        };
+
+        match result {
+            Ok(sent) => {
+                self.outbox.lock().unwrap().remove(entry.id);
+                self.send_event(CoreEvent::Outbox(OutboxEvent::Sent {
+                    id: entry.id,
+                    peer_id: entry.peer_id,
+                    message_id: sent.message_id,
+                    cmid: sent.conversation_message_id,
+                    random_id: sent.random_id,
+                }));
+            }
+            Err(e) => {
+                let updated = self
+                    .outbox
+                    .lock()
+                    .unwrap()
+                    .set_state(entry.id, failure_state(&e));
+                if let Some(updated) = updated {
+                    self.send_event(CoreEvent::Outbox(OutboxEvent::Updated(updated)));
+                }
+                if e.is_auth() {
+                    self.send_event(CoreEvent::AuthExpired);
+                } else if let vk_api::Error::Captcha { sid, img_url } = e {
+                    // Answering sends this entry again instead of adding
+                    // the text to the outbox once more
+                    self.send_event(CoreEvent::CaptchaRequired {
+                        sid,
+                        img_url,
+                        retry: Box::new(AsyncCommand::Outbox(OutboxCommand::Retry(entry.id))),
+                    });
+                }
             }
         }
     }
@@ -394,32 +689,49 @@ impl CommandExecutor {
                 self.send_event(CoreEvent::MessageSent {
                     message_id: sent.message_id,
                     cmid: sent.conversation_message_id,
+                    random_id: sent.random_id,
                 });
             }
             Err(e) => {
-                self.send_event(CoreEvent::SendFailed(format!(
-                    "Failed to forward message: {}",
-                    e
-                )));
+                self.send_failed("Failed to forward message", e);
             }
         }
     }
 
-    async fn edit_message(&self, peer_id: i64, message_id: i64, cmid: Option<i64>, text: String) {
+    async fn edit_message(
+        &self,
+        peer_id: i64,
+        message_id: i64,
+        cmid: Option<i64>,
+        text: String,
+        base_hash: Option<u64>,
+    ) {
+        let attachments = match check_edit(&self.client, message_id, base_hash).await {
+            Ok(EditCheck::Ready { attachments }) => attachments,
+            Ok(EditCheck::Conflict(server_text)) => {
+                self.send_event(CoreEvent::EditConflict {
+                    message_id,
+                    server_text,
+                });
+                return;
+            }
+            Err(e) => {
+                self.send_failed("Failed to edit message", e);
+                return;
+            }
+        };
+
         match self
             .client
             .messages()
-            .edit(peer_id, message_id, cmid, &text)
+            .edit(peer_id, message_id, cmid, &text, &attachments)
             .await
         {
             Ok(()) => {
                 self.send_event(CoreEvent::MessageEdited { message_id });
             }
             Err(e) => {
-                self.send_event(CoreEvent::SendFailed(format!(
-                    "Failed to edit message: {}",
-                    e
-                )));
+                self.send_failed("Failed to edit message", e);
             }
         }
     }
@@ -427,96 +739,145 @@ impl CommandExecutor {
     async fn delete_message(&self, message_id: i64, for_all: bool) {
         match self.client.messages().delete(&[message_id], for_all).await {
             Ok(()) => {
+                if let Some(cache) = &self.cache {
+                    cache.delete_message(message_id);
+                }
                 self.send_event(CoreEvent::MessageDeleted { message_id });
             }
             Err(e) => {
-                self.send_event(CoreEvent::SendFailed(format!(
-                    "Failed to delete message: {}",
-                    e
-                )));
+                self.send_failed("Failed to delete message", e);
             }
         }
     }
 
-    async fn send_photo(&self, peer_id: i64, path: &Path) {
-        match self.client.messages().send_photo(peer_id, path).await {
-            Ok(sent) => {
-                self.send_event(CoreEvent::MessageSent {
-                    message_id: sent.message_id,
-                    cmid: sent.conversation_message_id,
-                });
-            }
-            Err(e) => {
-                self.send_event(CoreEvent::SendFailed(format!(
-                    "Failed to send photo: {}",
-                    e
-                )));
-            }
+    async fn set_reaction(
+        &self,
+        peer_id: i64,
+        message_id: i64,
+        cmid: i64,
+        reaction_id: Option<i64>,
+    ) {
+        let messages = self.client.messages();
+        let result = match reaction_id {
+            Some(reaction_id) => messages.send_reaction(peer_id, cmid, reaction_id).await,
+            None => messages.delete_reaction(peer_id, cmid).await,
+        };
+        if let Err(e) = result {
+            self.send_failed("Failed to set reaction", e);
+            self.fetch_message_by_id(message_id);
         }
     }
 
-    async fn send_doc(&self, peer_id: i64, path: &Path) {
-        match self.client.messages().send_doc(peer_id, path).await {
+    /// Upload `paths` and send whatever uploaded in one message.
+    async fn send_files(&self, peer_id: i64, kind: UploadKind, paths: &[PathBuf], local_id: u64) {
+        let progress = self.upload_progress(peer_id, local_id);
+        let batch = upload::upload_files(&self.client, peer_id, kind, paths, progress).await;
+
+        if batch.auth_failed() {
+            self.send_event(CoreEvent::AuthExpired);
+            return;
+        }
+        if batch.attachments.is_empty() {
+            self.send_event(CoreEvent::UploadFailed {
+                peer_id,
+                local_id,
+                error: batch.failure_text(),
+            });
+            return;
+        }
+        if !batch.failed.is_empty() {
+            self.send_event(CoreEvent::UploadPartlyFailed {
+                peer_id,
+                local_id,
+                failed: batch.failed_names(),
+                error: batch.failure_text(),
+            });
+        }
+
+        let context = match kind {
+            UploadKind::Photo => "Failed to send photo",
+            UploadKind::Doc => "Failed to send file",
+        };
+        match self
+            .client
+            .messages()
+            .send_with_attachments(peer_id, "", &batch.attachments)
+            .await
+        {
             Ok(sent) => {
                 self.send_event(CoreEvent::MessageSent {
                     message_id: sent.message_id,
                     cmid: sent.conversation_message_id,
+                    random_id: sent.random_id,
                 });
             }
-            Err(e) => {
-                self.send_event(CoreEvent::SendFailed(format!("Failed to send file: {}", e)));
-            }
+            Err(e) => self.send_failed(context, e),
         }
     }
 
-    async fn download_attachments(&self, attachments: Vec<AttachmentInfo>) {
-        let Some(base_dir) = directories::UserDirs::new()
-            .and_then(|u| u.download_dir().map(|p| p.to_path_buf()))
-            .or_else(|| Some(std::env::temp_dir()))
-        else {
-            self.send_event(CoreEvent::Error("No download directory available".into()));
-            return;
-        };
+    /// Progress callback sending `UploadProgress` at most once per percent.
+    fn upload_progress(&self, peer_id: i64, local_id: u64) -> UploadProgress {
+        let event_tx = self.event_tx.clone();
+        let last_percent = AtomicU64::new(u64::MAX);
+        Arc::new(move |bytes_sent, total| {
+            let percent = bytes_sent * 100 / total.max(1);
+            if last_percent.swap(percent, Ordering::Relaxed) != percent {
+                let _ = event_tx.send(CoreEvent::UploadProgress {
+                    peer_id,
+                    local_id,
+                    bytes_sent,
+                    total,
+                });
+            }
+        })
+    }
 
-        if std::fs::create_dir_all(&base_dir).is_err() {
-            self.send_event(CoreEvent::Error(
-                "Failed to create download directory".into(),
-            ));
+    /// Save `settings`, use them from now on and tell the frontends.
+    fn save_settings(&self, settings: Settings) {
+        if let Err(e) = settings.save() {
+            self.send_event(CoreEvent::error(format!("Failed to save settings: {}", e)));
             return;
         }
+        *self.settings.lock().unwrap() = settings.clone();
+        self.send_event(CoreEvent::SettingsChanged(Box::new(settings)));
+    }
 
+    async fn download_attachments(&self, attachments: Vec<AttachmentInfo>) {
+        let dir = self.settings.lock().unwrap().download_dir();
         let client = reqwest::Client::new();
 
         for (idx, att) in attachments.into_iter().enumerate() {
-            let Some(url) = att.url.clone() else {
+            if !att.is_downloadable() {
+                continue;
+            }
+            let Some(url) = att.url else {
                 continue;
             };
 
-            let name = if !att.title.is_empty() {
-                att.title.clone()
+            let title = if !att.title.is_empty() {
+                att.title
             } else {
                 format!("attachment_{}", idx)
             };
 
-            let path = base_dir.join(name);
-
-            match client.get(&url).send().await {
-                Ok(resp) => match resp.bytes().await {
-                    Ok(bytes) => {
-                        if let Err(e) = std::fs::write(&path, &bytes) {
-                            self.send_event(CoreEvent::Error(format!(
-                                "Failed to save {}: {}",
-                                path.display(),
-                                e
-                            )));
-                        }
-                    }
-                    Err(e) => {
-                        self.send_event(CoreEvent::Error(format!("Download failed: {}", e)));
-                    }
-                },
+            let progress = |received, total| {
+                self.send_event(CoreEvent::DownloadProgress {
+                    title: title.clone(),
+                    received,
+                    total,
+                });
+            };
+            let saved = match &att.target_path {
+                Some(path) => download::download_to(&client, &url, path, progress).await,
+                None => download::download(&client, &url, &dir, &title, progress).await,
+            };
+            match saved {
+                Ok(path) => self.send_event(CoreEvent::DownloadFinished { title, path }),
                 Err(e) => {
-                    self.send_event(CoreEvent::Error(format!("Download failed: {}", e)));
+                    self.send_event(CoreEvent::error(format!(
+                        "Download of {} failed: {}",
+                        title, e
+                    )));
                 }
             }
         }
@@ -566,6 +927,7 @@ impl CommandExecutor {
                         chat_title,
                         text: msg.text,
                         timestamp: msg.date,
+                        highlight: None,
                     });
                 }
 
@@ -575,56 +937,347 @@ impl CommandExecutor {
                 });
             }
             Err(e) => {
-                self.send_event(CoreEvent::Error(format!("Search failed: {}", e)));
-            }
-        }
-    }
-
-    async fn fetch_message_by_id(&self, message_id: i64) {
-        match self.client.messages().get_by_id(&[message_id]).await {
-            Ok(messages) => {
-                if let Some(msg) = messages.first() {
-                    let attachments = msg
-                        .attachments
-                        .clone()
-                        .into_iter()
-                        .map(map_attachment)
-                        .collect::<Vec<_>>();
-                    let reply = msg.reply_message.as_ref().map(|r| map_reply(&[], r));
-                    let forwards = msg
-                        .fwd_messages
-                        .iter()
-                        .map(|m| map_forward_tree(&[], m))
-                        .collect::<Vec<_>>();
-                    let fwd_count = forwards.len();
-
-                    self.send_event(CoreEvent::MessageDetailsFetched {
-                        message_id: msg.id,
-                        cmid: msg.conversation_message_id,
-                        text: Some(msg.text.clone()),
-                        is_edited: msg.update_time.is_some(),
-                        attachments: Some(attachments),
-                        reply,
-                        fwd_count: Some(fwd_count),
-                        forwards: Some(forwards),
-                    });
-                }
-            }
-            Err(e) => {
-                tracing::warn!("Failed to fetch message details: {}", e);
+                self.send_error("Search failed", e);
             }
         }
     }
 
+    async fn grep_history(
+        &self,
+        peer_id: i64,
+        chat_title: String,
+        query: String,
+        loaded: Vec<ChatMessage>,
+    ) {
+        let pattern = match GrepPattern::parse(&query) {
+            Ok(pattern) => pattern,
+            Err(e) => return self.send_event(CoreEvent::error(e)),
+        };
+        let cache = self.cache.clone();
+        let event_tx = self.event_tx.clone();
+        let scan = tokio::task::spawn_blocking(move || {
+            let messages = searchable_history(peer_id, loaded, cache.as_deref());
+            grep_messages(
+                &messages,
+                &pattern,
+                peer_id,
+                &chat_title,
+                |results, done| {
+                    event_tx
+                        .send(CoreEvent::GrepResults {
+                            peer_id,
+                            query: query.clone(),
+                            results,
+                            done,
+                        })
+                        .is_ok()
+                },
+            );
+        });
+        if let Err(e) = scan.await {
+            self.send_event(CoreEvent::error(format!("Search failed: {}", e)));
+        }
+    }
+
+    /// Queue a details fetch; fetches within [`FETCH_WINDOW`] of each
+    /// other are made in one request.
+    fn fetch_message_by_id(&self, message_id: i64) {
+        let client = self.client.clone();
+        let cache = self.cache.clone();
+        let event_tx = self.event_tx.clone();
+        self.details.push(message_id, move |message_ids| {
+            fetch_message_details(client, cache, event_tx, message_ids)
+        });
+    }
+
     async fn mark_as_read(&self, peer_id: i64) {
         if let Err(e) = self.client.messages().mark_as_read(peer_id).await {
             tracing::warn!("Failed to mark as read: {}", e);
         }
     }
+
+    async fn load_chat_members(&self, peer_id: i64, offset: u32) {
+        match self
+            .client
+            .messages()
+            .get_conversation_members(peer_id, offset, CHAT_MEMBERS_PAGE)
+            .await
+        {
+            Ok(response) => self.send_event(CoreEvent::ChatMembersLoaded {
+                peer_id,
+                offset,
+                members: map_chat_members(&response),
+                total_count: response.count,
+            }),
+            Err(e) => self.send_error("Failed to load chat members", e),
+        }
+    }
+
+    async fn remove_chat_user(&self, peer_id: i64, user_id: i64) {
+        if !is_chat_peer(peer_id) {
+            self.send_event(CoreEvent::error(format!("{} is not a group chat", peer_id)));
+            return;
+        }
+
+        match self
+            .client
+            .messages()
+            .remove_chat_user(peer_id - CHAT_PEER_OFFSET, user_id)
+            .await
+        {
+            Ok(()) => self.send_event(CoreEvent::ChatUserRemoved { peer_id, user_id }),
+            Err(e) => self.send_failed("Failed to remove chat member", e),
+        }
+    }
+
+    async fn fetch_read_peers(&self, peer_id: i64, cmid: i64) {
+        match self
+            .client
+            .messages()
+            .get_message_read_peers(peer_id, cmid)
+            .await
+        {
+            Ok(response) => self.send_event(CoreEvent::ReadPeersLoaded {
+                readers: map_read_peers(peer_id, cmid, &response),
+            }),
+            Err(e) => match read_peers_error(&e) {
+                Some(reason) => self.send_event(CoreEvent::error(reason)),
+                None => self.send_error("Failed to load read receipts", e),
+            },
+        }
+    }
+
+    /// Check that `peer_id` is a group chat whose title and photo the user
+    /// may change, reporting why not otherwise.
+    async fn may_change_chat_info(&self, peer_id: i64) -> bool {
+        if !is_chat_peer(peer_id) {
+            self.send_event(CoreEvent::error(format!("{} is not a group chat", peer_id)));
+            return false;
+        }
+
+        // Without the settings, let the API call decide
+        match self.client.messages().get_conversation_by_id(peer_id).await {
+            Ok(conversation) => match change_chat_info_denied(&conversation) {
+                Some(reason) => {
+                    self.send_event(CoreEvent::error(reason));
+                    false
+                }
+                None => true,
+            },
+            Err(e) => {
+                tracing::debug!("Failed to load chat settings of {}: {}", peer_id, e);
+                true
+            }
+        }
+    }
+
+    async fn rename_chat(&self, peer_id: i64, title: String) {
+        if !self.may_change_chat_info(peer_id).await {
+            return;
+        }
+
+        match self
+            .client
+            .messages()
+            .edit_chat(peer_id - CHAT_PEER_OFFSET, &title)
+            .await
+        {
+            Ok(()) => self.send_event(CoreEvent::ChatSettingsChanged {
+                peer_id,
+                title: Some(title),
+                photo_url: None,
+            }),
+            Err(e) => match rename_chat_error(&e) {
+                Some(reason) => self.send_event(CoreEvent::error(reason)),
+                None => self.send_error("Failed to rename chat", e),
+            },
+        }
+    }
+
+    async fn set_chat_photo(&self, peer_id: i64, path: &Path) {
+        if !self.may_change_chat_info(peer_id).await {
+            return;
+        }
+
+        match self
+            .client
+            .messages()
+            .set_chat_photo(peer_id - CHAT_PEER_OFFSET, path)
+            .await
+        {
+            Ok(photo_url) => self.send_event(CoreEvent::ChatSettingsChanged {
+                peer_id,
+                title: None,
+                photo_url,
+            }),
+            Err(e) => match rename_chat_error(&e) {
+                Some(reason) => self.send_event(CoreEvent::error(reason)),
+                None => self.send_error("Failed to set chat photo", e),
+            },
+        }
+    }
+
+    async fn set_chat_muted(&self, peer_id: i64, duration: Option<MuteDuration>) {
+        let time = duration.map_or(0, MuteDuration::api_time);
+        match self.client.account().set_silence_mode(peer_id, time).await {
+            Ok(()) => self.send_event(CoreEvent::ChatMuteChanged {
+                peer_id,
+                muted: duration.is_some(),
+            }),
+            Err(e) => self.send_failed("Failed to change notifications", e),
+        }
+    }
+
+    async fn set_chat_pinned(&self, peer_id: i64, pinned: bool) {
+        let messages = self.client.messages();
+        let result = if pinned {
+            messages.pin_conversation(peer_id).await
+        } else {
+            messages.unpin_conversation(peer_id).await
+        };
+        match result {
+            Ok(()) => self.send_event(CoreEvent::ChatPinChanged { peer_id, pinned }),
+            Err(e) => self.send_failed("Failed to pin chat", e),
+        }
+    }
+
+    async fn delete_conversation(&self, peer_id: i64) {
+        match self.client.messages().delete_conversation(peer_id).await {
+            Ok(()) => {
+                if let Some(cache) = &self.cache {
+                    cache.delete_chat(peer_id);
+                }
+                self.send_event(CoreEvent::ConversationDeleted { peer_id });
+            }
+            Err(e) => self.send_failed("Failed to delete conversation", e),
+        }
+    }
+
+    async fn create_chat(&self, user_ids: Vec<i64>, title: String) {
+        if user_ids.is_empty() {
+            self.send_event(CoreEvent::error("Select at least one user for the chat"));
+            return;
+        }
+
+        match self.client.messages().create_chat(&user_ids, &title).await {
+            Ok(chat_id) => self.send_event(CoreEvent::ChatCreated { chat_id, title }),
+            Err(e) => match create_chat_error(&e) {
+                Some(reason) => self.send_event(CoreEvent::error(reason)),
+                None => self.send_error("Failed to create chat", e),
+            },
+        }
+    }
+
+    async fn load_users(&self, user_ids: Vec<i64>) {
+        let users = match self.client.users().get(&user_ids, BASIC_USER_FIELDS).await {
+            Ok(users) => users,
+            Err(e) => {
+                tracing::warn!("Failed to load users: {}", e);
+                Vec::new()
+            }
+        };
+        self.send_event(CoreEvent::UsersLoaded {
+            requested: user_ids,
+            users,
+        });
+    }
+
+    async fn fetch_user_profile(&self, user_id: i64) {
+        let profile = if user_id < 0 {
+            self.client
+                .groups()
+                .get_by_id(&[-user_id], GROUP_DETAIL_FIELDS)
+                .await
+                .map(|groups| groups.first().map(map_group_profile))
+        } else {
+            self.client
+                .users()
+                .get(&[user_id], DETAIL_FIELDS)
+                .await
+                .map(|users| users.first().map(map_user_profile))
+        };
+
+        match profile {
+            Ok(Some(user)) => self.send_event(CoreEvent::UserProfileLoaded { user }),
+            Ok(None) => self.send_event(CoreEvent::error(format!("Profile {} not found", user_id))),
+            Err(e) => self.send_error("Failed to load profile", e),
+        }
+    }
+
+    async fn load_profile(&self) {
+        match self.client.account().get_profile_info().await {
+            Ok(user) => self.send_event(CoreEvent::ProfileLoaded { user }),
+            Err(e) => self.send_error("Failed to load account profile", e),
+        }
+    }
 }
 
 // === Helper functions ===
 
+/// Fetch the details of `message_ids` in one request and send an event
+/// for each message found.
+async fn fetch_message_details(
+    client: Arc<VkClient>,
+    cache: Option<Arc<dyn CacheLayer>>,
+    event_tx: mpsc::UnboundedSender<CoreEvent>,
+    message_ids: Vec<i64>,
+) {
+    let messages = match client.messages().get_by_id_batched(&message_ids).await {
+        Ok(messages) => messages,
+        Err(e) => {
+            tracing::warn!("Failed to fetch message details: {}", e);
+            return;
+        }
+    };
+
+    for (message_id, msg) in demux(&message_ids, messages, |m| m.id) {
+        let Some(msg) = msg else {
+            tracing::debug!("No details returned for message {}", message_id);
+            continue;
+        };
+        let attachments = msg
+            .attachments
+            .clone()
+            .into_iter()
+            .map(map_attachment)
+            .collect::<Vec<_>>();
+        let reply = msg.reply_message.as_ref().map(|r| map_reply(&[], r));
+        let forwards = msg
+            .fwd_messages
+            .iter()
+            .map(|m| map_forward_tree(&[], m))
+            .collect::<Vec<_>>();
+        let fwd_count = forwards.len();
+        let reactions = map_reactions(&msg);
+
+        if let Some(cache) = &cache
+            && let Some((peer_id, mut cached)) = cache.message(msg.id)
+        {
+            cached.cmid = msg.conversation_message_id.or(cached.cmid);
+            cached.text = msg.text.clone();
+            cached.is_edited = msg.update_time.is_some();
+            cached.attachments = attachments.clone();
+            cached.reply = reply.clone();
+            cached.fwd_count = fwd_count;
+            cached.forwards = forwards.clone();
+            cached.reactions = reactions.clone();
+            cache.upsert_message(peer_id, &cached);
+        }
+
+        let _ = event_tx.send(CoreEvent::MessageDetailsFetched {
+            message_id: msg.id,
+            cmid: msg.conversation_message_id,
+            text: Some(msg.text),
+            is_edited: msg.update_time.is_some(),
+            attachments: Some(attachments),
+            reply,
+            fwd_count: Some(fwd_count),
+            forwards: Some(forwards),
+            reactions: Some(reactions),
+        });
+    }
+}
+
 /// Get conversation title from response.
 fn get_conversation_title(item: &vk_api::ConversationItem, profiles: &[vk_api::User]) -> String {
     if let Some(settings) = &item.conversation.chat_settings {
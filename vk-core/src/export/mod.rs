@@ -0,0 +1,330 @@
+//! Exporting the history of a conversation to a file.
+//!
+//! The history is paged through `messages.getHistory`, newest page first,
+//! at the client's request rate, and written once complete: oldest first,
+//! as a JSON array or a self-contained HTML transcript. A cancelled export
+//! writes nothing.
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tokio::sync::watch;
+use vk_api::VkClient;
+
+use crate::download;
+use crate::mapper::map_history_message;
+use crate::models::{AttachmentInfo, ChatMessage, ForwardItem, ReplyPreview};
+
+/// Messages per request, the most `messages.getHistory` returns.
+const PAGE_SIZE: u32 = 200;
+
+/// File format of an export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    Json,
+    Html,
+}
+
+impl ExportFormat {
+    /// `json` or `html`, ignoring case.
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "json" => Some(Self::Json),
+            "html" | "htm" => Some(Self::Html),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Html => "html",
+        }
+    }
+}
+
+/// A new file in `dir` named after the chat.
+pub fn default_path(dir: &Path, title: &str, format: ExportFormat) -> PathBuf {
+    download::unique_path(dir, &format!("{}.{}", title, format.extension()))
+}
+
+/// "Exported 450/2300 messages"
+pub fn progress_text(done: usize, total: usize) -> String {
+    format!("Exported {}/{} messages", done, total)
+}
+
+/// Whole history of `peer_id`, oldest first. `progress` gets the messages
+/// fetched so far and the total after each page. Returns `None` once
+/// `cancel` turns true or its sender is dropped.
+pub async fn fetch_history(
+    client: &VkClient,
+    peer_id: i64,
+    cancel: &watch::Receiver<bool>,
+    mut progress: impl FnMut(usize, usize),
+) -> vk_api::Result<Option<Vec<ChatMessage>>> {
+    let mut messages = Vec::new();
+    let mut offset = 0;
+    loop {
+        if *cancel.borrow() || cancel.has_changed().is_err() {
+            return Ok(None);
+        }
+        let page = client
+            .messages()
+            .get_history(peer_id, offset, PAGE_SIZE)
+            .await?;
+        let fetched = page.items.len() as u32;
+        messages.extend(
+            page.items
+                .iter()
+                .map(|msg| map_history_message(&page.profiles, msg, 0)),
+        );
+        offset += fetched;
+        let total = (page.count as usize).max(messages.len());
+        progress(messages.len(), total);
+        if fetched == 0 || offset >= page.count {
+            break;
+        }
+    }
+
+    // Messages arriving meanwhile shift the offsets, so a page can repeat
+    // the end of the previous one
+    messages.sort_by_key(|m| m.id);
+    messages.dedup_by_key(|m| m.id);
+    Ok(Some(messages))
+}
+
+/// Fetch the history of `peer_id` and write it to `path`. Returns the
+/// number of messages written, or `None` if cancelled.
+pub async fn export_history(
+    client: &VkClient,
+    peer_id: i64,
+    title: &str,
+    format: ExportFormat,
+    path: &Path,
+    cancel: &watch::Receiver<bool>,
+    progress: impl FnMut(usize, usize),
+) -> anyhow::Result<Option<usize>> {
+    let Some(messages) = fetch_history(client, peer_id, cancel, progress).await? else {
+        return Ok(None);
+    };
+    let contents = match format {
+        ExportFormat::Json => to_json(&messages)?,
+        ExportFormat::Html => to_html(title, &messages),
+    };
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::write(path, contents).await?;
+    Ok(Some(messages.len()))
+}
+
+/// One message in a JSON export.
+#[derive(Serialize)]
+struct ExportedMessage<'a> {
+    id: i64,
+    from_id: i64,
+    from: &'a str,
+    /// Unix time
+    date: i64,
+    text: &'a str,
+    attachments: &'a [AttachmentInfo],
+    reply: Option<&'a ReplyPreview>,
+    forwards: &'a [ForwardItem],
+}
+
+/// `messages` as a pretty-printed JSON array.
+pub fn to_json(messages: &[ChatMessage]) -> serde_json::Result<String> {
+    let exported: Vec<ExportedMessage> = messages
+        .iter()
+        .map(|m| ExportedMessage {
+            id: m.id,
+            from_id: m.from_id,
+            from: &m.from_name,
+            date: m.timestamp,
+            text: &m.text,
+            attachments: &m.attachments,
+            reply: m.reply.as_ref(),
+            forwards: &m.forwards,
+        })
+        .collect();
+    serde_json::to_string_pretty(&exported)
+}
+
+const HTML_STYLE: &str = "body{font-family:sans-serif;max-width:50em;margin:2em auto;padding:0 1em}\
+.msg{margin:.8em 0}.meta{color:#777;font-size:.85em}.text{white-space:pre-wrap}\
+blockquote{margin:.3em 0;padding-left:.8em;border-left:3px solid #ccc;color:#444}";
+
+/// `messages` as a standalone HTML page titled after the chat.
+pub fn to_html(title: &str, messages: &[ChatMessage]) -> String {
+    let title = escape_html(title);
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        title, HTML_STYLE, title
+    );
+    for msg in messages {
+        html.push_str(&format!(
+            "<div class=\"msg\">\n<div class=\"meta\"><b>{}</b> {}</div>\n",
+            escape_html(&msg.from_name),
+            format_date(msg.timestamp)
+        ));
+        if let Some(reply) = &msg.reply {
+            html.push_str(&format!(
+                "<blockquote>Reply to <b>{}</b>: {}</blockquote>\n",
+                escape_html(&reply.from),
+                escape_html(&reply.text)
+            ));
+        }
+        html.push_str(&format!(
+            "<div class=\"text\">{}</div>\n",
+            escape_html(&msg.text)
+        ));
+        push_attachments(&mut html, &msg.attachments);
+        for fwd in &msg.forwards {
+            push_forward(&mut html, fwd);
+        }
+        html.push_str("</div>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// A forwarded message and the ones nested in it, as blockquotes.
+fn push_forward(html: &mut String, fwd: &ForwardItem) {
+    html.push_str(&format!(
+        "<blockquote>Forwarded from <b>{}</b>\n<div class=\"text\">{}</div>\n",
+        escape_html(&fwd.from),
+        escape_html(&fwd.text)
+    ));
+    push_attachments(html, &fwd.attachments);
+    for nested in &fwd.nested {
+        push_forward(html, nested);
+    }
+    html.push_str("</blockquote>\n");
+}
+
+/// Attachment titles, linked when they have a URL.
+fn push_attachments(html: &mut String, attachments: &[AttachmentInfo]) {
+    for att in attachments {
+        let title = escape_html(&att.title);
+        match &att.url {
+            Some(url) => html.push_str(&format!(
+                "<div>📎 <a href=\"{}\">{}</a></div>\n",
+                escape_html(url),
+                title
+            )),
+            None => html.push_str(&format!("<div>📎 {}</div>\n", title)),
+        }
+    }
+}
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// "2023-11-14 22:13 UTC"
+fn format_date(ts: i64) -> String {
+    use time::OffsetDateTime;
+    use time::macros::format_description;
+
+    OffsetDateTime::from_unix_timestamp(ts)
+        .ok()
+        .and_then(|dt| {
+            dt.format(&format_description!(
+                "[year]-[month]-[day] [hour]:[minute] UTC"
+            ))
+            .ok()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::message;
+    use crate::models::AttachmentKind;
+
+    #[test]
+    fn test_format_parse() {
+        assert_eq!(ExportFormat::parse("JSON"), Some(ExportFormat::Json));
+        assert_eq!(ExportFormat::parse("html"), Some(ExportFormat::Html));
+        assert_eq!(ExportFormat::parse("pdf"), None);
+        assert_eq!(ExportFormat::Html.extension(), "html");
+    }
+
+    #[test]
+    fn test_json_keeps_structure() {
+        let mut msg = message(7, "see this");
+        msg.attachments.push(AttachmentInfo {
+            kind: AttachmentKind::Doc,
+            title: "report.pdf".into(),
+            url: Some("https://vk.com/doc1_2".into()),
+            thumbnail_url: None,
+            size: Some(10),
+            subtitle: None,
+            description: None,
+            target_path: None,
+        });
+        msg.reply = Some(ReplyPreview {
+            from: "Bob".into(),
+            text: "where?".into(),
+            attachments: Vec::new(),
+            message_id: Some(6),
+            cmid: Some(6),
+        });
+
+        let json: serde_json::Value = serde_json::from_str(&to_json(&[msg]).unwrap()).unwrap();
+        let exported = &json[0];
+        assert_eq!(exported["id"], 7);
+        assert_eq!(exported["from"], "Ann Lee");
+        assert_eq!(exported["date"], 1_700_000_007);
+        assert_eq!(exported["attachments"][0]["url"], "https://vk.com/doc1_2");
+        assert_eq!(exported["reply"]["from"], "Bob");
+        assert_eq!(exported["forwards"], serde_json::json!([]));
+    }
+
+    #[test]
+    fn test_html_escapes_and_nests_forwards() {
+        let mut msg = ChatMessage {
+            from_name: "Ann <Lee>".into(),
+            ..message(1, "<script>alert(1)</script>")
+        };
+        msg.forwards.push(ForwardItem {
+            message_id: 2,
+            peer_id: 3,
+            from: "Bob".into(),
+            text: "outer".into(),
+            attachments: Vec::new(),
+            nested: vec![ForwardItem {
+                message_id: 4,
+                peer_id: 3,
+                from: "Carol".into(),
+                text: "inner".into(),
+                attachments: Vec::new(),
+                nested: Vec::new(),
+            }],
+        });
+
+        let html = to_html("Chat & co", &[msg]);
+        assert!(html.contains("<title>Chat &amp; co</title>"));
+        assert!(html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("<b>Ann &lt;Lee&gt;</b> 2023-11-14 22:13 UTC"));
+        let outer = html.find("Forwarded from <b>Bob</b>").unwrap();
+        let inner = html.find("Forwarded from <b>Carol</b>").unwrap();
+        assert!(outer < inner);
+        assert_eq!(html.matches("</blockquote>").count(), 2);
+    }
+}
@@ -0,0 +1,55 @@
+//! Messages and chats for tests.
+//!
+//! Tests take these and change only the fields they are about, with
+//! struct update syntax: `ChatMessage { is_outgoing: true, ..message(1, "hi") }`.
+//! Other crates get the module with the `test-support` feature.
+
+use crate::models::{Chat, ChatKind, ChatMessage, DeliveryStatus, MessageKind};
+
+/// Incoming text message `id` from Ann Lee (user 5), read and sent,
+/// `id` seconds after 14 Nov 2023 22:13 UTC.
+pub fn message(id: i64, text: &str) -> ChatMessage {
+    ChatMessage {
+        id,
+        cmid: Some(id),
+        random_id: None,
+        from_id: 5,
+        from_name: "Ann Lee".into(),
+        from_photo: None,
+        text: text.into(),
+        kind: MessageKind::Text,
+        timestamp: 1_700_000_000 + id,
+        is_outgoing: false,
+        is_read: true,
+        is_edited: false,
+        is_pinned: false,
+        delivery: DeliveryStatus::Sent,
+        attachments: Vec::new(),
+        reply: None,
+        fwd_count: 0,
+        forwards: Vec::new(),
+        reactions: Vec::new(),
+        upload: None,
+    }
+}
+
+/// Dialog `id` titled "Chat `id`", with no messages and nothing unread.
+pub fn chat(id: i64) -> Chat {
+    Chat {
+        id,
+        title: format!("Chat {}", id),
+        last_message: String::new(),
+        last_message_from: None,
+        last_message_kind: MessageKind::Text,
+        last_message_time: 0,
+        unread_count: 0,
+        is_online: false,
+        photo_url: None,
+        is_muted: false,
+        is_pinned: false,
+        can_write: true,
+        cannot_write_reason: None,
+        kind: ChatKind::User,
+        last_seen: None,
+    }
+}
@@ -0,0 +1,248 @@
+//! Fuzzy matching for chat filters and pickers.
+//!
+//! Matching ignores case (Cyrillic included) and treats `ё` as `е`. Text
+//! is also compared transliterated, so "ivn ptr" finds "Иван Петров" and
+//! "петр" finds "Petr". Matches rank by [`MatchKind`] first, then by a
+//! score favouring consecutive characters and word starts.
+
+use std::cmp::Reverse;
+
+use crate::models::Chat;
+
+/// How a query matched, best last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MatchKind {
+    /// Characters of the query appear in order
+    Fuzzy,
+    /// Every word of the query starts a word of the text, in order
+    WordStart,
+    /// The text starts with the query
+    Prefix,
+}
+
+/// Result of [`fuzzy_match`]; compares by kind, then score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Match {
+    pub kind: MatchKind,
+    pub score: i32,
+}
+
+/// Match `query` against `text`. An empty query matches everything.
+pub fn fuzzy_match(text: &str, query: &str) -> Option<Match> {
+    let words: Vec<String> = query.split_whitespace().map(normalize).collect();
+    if words.is_empty() {
+        return Some(Match {
+            kind: MatchKind::Prefix,
+            score: 0,
+        });
+    }
+    let text = normalize(text);
+
+    let plain = match_words(&text, &words);
+    let translit_words: Vec<String> = words.iter().map(|w| transliterate(w)).collect();
+    let translit = match_words(&transliterate(&text), &translit_words);
+    plain.max(translit)
+}
+
+/// Indices of the chats matching `query`, best first. Titles match
+/// fuzzily; the last message only on word starts, since a short query is
+/// a subsequence of almost any long text. Equal matches keep the order of
+/// `chats`, so the list does not reshuffle while typing.
+pub fn filter_chats(chats: &[Chat], query: &str) -> Vec<usize> {
+    if query.trim().is_empty() {
+        return (0..chats.len()).collect();
+    }
+
+    let mut matches: Vec<(usize, (MatchKind, bool, i32))> = chats
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, chat)| chat_match(chat, query).map(|key| (idx, key)))
+        .collect();
+    matches.sort_by_key(|(_, key)| Reverse(*key));
+    matches.into_iter().map(|(idx, _)| idx).collect()
+}
+
+/// Rank of a chat: match kind, whether the title matched (it beats the
+/// last message at the same kind), score.
+fn chat_match(chat: &Chat, query: &str) -> Option<(MatchKind, bool, i32)> {
+    let title = fuzzy_match(&chat.title, query).map(|m| (m.kind, true, m.score));
+    let preview = fuzzy_match(&chat.last_message, query)
+        .filter(|m| m.kind != MatchKind::Fuzzy)
+        .map(|m| (m.kind, false, m.score));
+    title.max(preview)
+}
+
+/// Match normalized query `words` against normalized `text`.
+fn match_words(text: &str, words: &[String]) -> Option<Match> {
+    let chars: Vec<char> = text.chars().collect();
+    let needle: Vec<char> = words.iter().flat_map(|w| w.chars()).collect();
+    let score = subsequence_score(&chars, &needle)?;
+
+    let kind = if text.starts_with(&words.join(" ")) {
+        MatchKind::Prefix
+    } else if words_start(&chars, words) {
+        MatchKind::WordStart
+    } else {
+        MatchKind::Fuzzy
+    };
+    Some(Match { kind, score })
+}
+
+/// Score of `needle` as a subsequence of `text`, `None` if it is not one.
+fn subsequence_score(text: &[char], needle: &[char]) -> Option<i32> {
+    let mut score = 0;
+    let mut next = 0;
+    let mut last: Option<usize> = None;
+
+    for &c in needle {
+        let pos = next + text[next..].iter().position(|&t| t == c)?;
+        // Bonus for consecutive matches
+        if last.is_some_and(|last| pos == last + 1) {
+            score += 10;
+        }
+        // Bonus for matching at word boundaries
+        if pos == 0 || !text[pos - 1].is_alphanumeric() {
+            score += 15;
+        }
+        score += 1;
+        last = Some(pos);
+        next = pos + 1;
+    }
+    Some(score)
+}
+
+/// Whether each of `words` starts a word of `text`, in order.
+fn words_start(text: &[char], words: &[String]) -> bool {
+    let mut starts = (0..text.len())
+        .filter(|&i| text[i].is_alphanumeric() && (i == 0 || !text[i - 1].is_alphanumeric()));
+    words.iter().all(|word| {
+        let word: Vec<char> = word.chars().collect();
+        starts.any(|i| text[i..].starts_with(&word))
+    })
+}
+
+/// Lowercase, with `ё` folded into `е`.
+fn normalize(s: &str) -> String {
+    s.to_lowercase().replace('ё', "е")
+}
+
+/// Russian letters of lowercase `s` in Latin.
+fn transliterate(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        let latin = match c {
+            'а' => "a",
+            'б' => "b",
+            'в' => "v",
+            'г' => "g",
+            'д' => "d",
+            'е' | 'э' => "e",
+            'ж' => "zh",
+            'з' => "z",
+            'и' => "i",
+            'й' | 'ы' => "y",
+            'к' => "k",
+            'л' => "l",
+            'м' => "m",
+            'н' => "n",
+            'о' => "o",
+            'п' => "p",
+            'р' => "r",
+            'с' => "s",
+            'т' => "t",
+            'у' => "u",
+            'ф' => "f",
+            'х' => "kh",
+            'ц' => "ts",
+            'ч' => "ch",
+            'ш' => "sh",
+            'щ' => "shch",
+            'ъ' | 'ь' => "",
+            'ю' => "yu",
+            'я' => "ya",
+            _ => {
+                out.push(c);
+                continue;
+            }
+        };
+        out.push_str(latin);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn kind(text: &str, query: &str) -> Option<MatchKind> {
+        fuzzy_match(text, query).map(|m| m.kind)
+    }
+
+    fn chat(title: &str, last_message: &str) -> Chat {
+        Chat {
+            title: title.into(),
+            last_message: last_message.into(),
+            ..fixtures::chat(0)
+        }
+    }
+
+    #[test]
+    fn test_fuzzy_match_basic() {
+        assert!(fuzzy_match("hello world", "hlo").is_some());
+        assert!(fuzzy_match("hello world", "hw").is_some());
+        assert!(fuzzy_match("hello world", "hew").is_some());
+        assert!(fuzzy_match("hello world", "xyz").is_none());
+        assert_eq!(kind("hello", ""), Some(MatchKind::Prefix));
+    }
+
+    #[test]
+    fn test_fuzzy_match_scoring() {
+        let score1 = fuzzy_match("hello world", "hel").unwrap().score;
+        let score2 = fuzzy_match("hello world", "hw").unwrap().score;
+        // Consecutive matches should score higher
+        assert!(score1 > score2);
+    }
+
+    #[test]
+    fn test_cyrillic_ignores_case() {
+        assert_eq!(kind("Иван Петров", "иван"), Some(MatchKind::Prefix));
+        assert_eq!(kind("иван петров", "ИВАН"), Some(MatchKind::Prefix));
+        assert_eq!(kind("Иван Петров", "ПЕТ"), Some(MatchKind::WordStart));
+        assert_eq!(kind("Алёна", "алена"), Some(MatchKind::Prefix));
+        assert_eq!(kind("HELLO", "hel"), Some(MatchKind::Prefix));
+    }
+
+    #[test]
+    fn test_transliterated() {
+        assert_eq!(kind("Иван Петров", "ivn ptr"), Some(MatchKind::Fuzzy));
+        assert_eq!(kind("Иван Петров", "ivan pet"), Some(MatchKind::Prefix));
+        assert_eq!(kind("Petr", "петр"), Some(MatchKind::Prefix));
+        assert_eq!(kind("Иван Петров", "xyz"), None);
+    }
+
+    #[test]
+    fn test_kinds_rank() {
+        assert_eq!(kind("Иван Петров", "ив пет"), Some(MatchKind::WordStart));
+        assert_eq!(kind("Работа: Иван", "иван"), Some(MatchKind::WordStart));
+        assert_eq!(kind("Диван", "иван"), Some(MatchKind::Fuzzy));
+        assert!(MatchKind::Prefix > MatchKind::WordStart);
+        assert!(MatchKind::WordStart > MatchKind::Fuzzy);
+    }
+
+    #[test]
+    fn test_filter_chats_ranks_and_keeps_order() {
+        let chats = vec![
+            chat("Диван", ""),
+            chat("Работа", "Иван прислал отчёт"),
+            chat("Иван Петров", ""),
+            chat("Семья", "завтра приедет иван"),
+            chat("Иванов", ""),
+            chat("Новости", "и вообще, а нас"),
+        ];
+        // Title prefixes in list order, then the last messages by kind,
+        // then the fuzzy title; a fuzzy last message does not count
+        assert_eq!(filter_chats(&chats, "иван"), [2, 4, 1, 3, 0]);
+        assert_eq!(filter_chats(&chats, " "), [0, 1, 2, 3, 4, 5]);
+    }
+}
@@ -0,0 +1,179 @@
+//! Offline search in the history of one chat.
+//!
+//! `:grep` scans messages already on the device — those loaded in the
+//! open chat and those in the [message cache](crate::cache) — without a
+//! request to the server. A plain query matches as a substring ignoring
+//! case; `/…/` is a regular expression. Histories can be long, so the scan
+//! is meant for a blocking task and hands results over in batches.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::cache::{CacheLayer, MAX_CACHED_MESSAGES};
+use crate::models::{ChatMessage, SearchResult};
+
+/// Results a scan stops at.
+pub const MAX_GREP_HITS: usize = 1000;
+
+/// Results handed over at a time.
+pub const GREP_BATCH: usize = 100;
+
+/// A parsed `:grep` query.
+#[derive(Debug, Clone)]
+pub struct GrepPattern(Regex);
+
+impl GrepPattern {
+    /// `/regex/` or a substring; both ignore case. Fails on an empty
+    /// query or an invalid regex.
+    pub fn parse(query: &str) -> Result<Self, String> {
+        let source = match query.strip_prefix('/').and_then(|q| q.strip_suffix('/')) {
+            Some(re) if !re.is_empty() => re.to_string(),
+            _ if query.is_empty() => return Err("Empty pattern".into()),
+            _ => regex::escape(query),
+        };
+        RegexBuilder::new(&source)
+            .case_insensitive(true)
+            .build()
+            .map(Self)
+            .map_err(|e| format!("Invalid pattern: {}", e))
+    }
+
+    /// Byte range of the first match in `text`.
+    pub fn find(&self, text: &str) -> Option<Range<usize>> {
+        self.0
+            .find_iter(text)
+            .find(|m| !m.is_empty())
+            .map(|m| m.range())
+    }
+}
+
+/// Messages of `peer_id` to search: `loaded` and the cached ones, each
+/// once, oldest first.
+pub fn searchable_history(
+    peer_id: i64,
+    loaded: Vec<ChatMessage>,
+    cache: Option<&dyn CacheLayer>,
+) -> Vec<ChatMessage> {
+    let mut by_id: BTreeMap<i64, ChatMessage> = cache
+        .map(|cache| cache.messages(peer_id, MAX_CACHED_MESSAGES))
+        .unwrap_or_default()
+        .into_iter()
+        .map(|m| (m.id, m))
+        .collect();
+    // Loaded messages are newer than what the cache read back
+    by_id.extend(loaded.into_iter().filter(|m| m.id > 0).map(|m| (m.id, m)));
+    by_id.into_values().collect()
+}
+
+/// Find `pattern` in `messages` of the chat `peer_id`, newest first.
+/// `on_batch` gets up to [`GREP_BATCH`] results at a time, `done` on the
+/// last call (made even with no results), and stops the scan by returning
+/// `false`. At most [`MAX_GREP_HITS`] are found; returns how many.
+pub fn grep_messages(
+    messages: &[ChatMessage],
+    pattern: &GrepPattern,
+    peer_id: i64,
+    chat_title: &str,
+    mut on_batch: impl FnMut(Vec<SearchResult>, bool) -> bool,
+) -> usize {
+    let mut found = 0;
+    let mut batch = Vec::new();
+    for msg in messages.iter().rev() {
+        let Some(range) = pattern.find(&msg.text) else {
+            continue;
+        };
+        batch.push(SearchResult {
+            message_id: msg.id,
+            peer_id,
+            from_id: msg.from_id,
+            from_name: msg.from_name.clone(),
+            chat_title: chat_title.to_string(),
+            text: msg.text.clone(),
+            timestamp: msg.timestamp,
+            highlight: Some(range),
+        });
+        found += 1;
+        if found == MAX_GREP_HITS {
+            break;
+        }
+        if batch.len() == GREP_BATCH && !on_batch(std::mem::take(&mut batch), false) {
+            return found;
+        }
+    }
+    on_batch(batch, true);
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::message;
+
+    fn find(query: &str, text: &str) -> Option<Range<usize>> {
+        GrepPattern::parse(query).unwrap().find(text)
+    }
+
+    #[test]
+    fn test_substring_ignores_case() {
+        assert_eq!(find("lo w", "Hello World"), Some(3..7));
+        assert_eq!(find("ПРИВЕТ", "ну привет"), Some(5..17));
+        // Regex characters are literal outside slashes
+        assert_eq!(find("a.b", "axb a.b"), Some(4..7));
+        assert_eq!(find("/", "a/b"), Some(1..2));
+        assert!(find("xyz", "hello").is_none());
+    }
+
+    #[test]
+    fn test_regex() {
+        assert_eq!(find(r"/\d{3}/", "code 12 and 4567"), Some(12..15));
+        assert_eq!(find("/^hi/", "HI there"), Some(0..2));
+        // Empty matches are skipped
+        assert_eq!(find("/x*/", "abx"), Some(2..3));
+        assert!(GrepPattern::parse("/(/").is_err());
+        assert!(GrepPattern::parse("").is_err());
+    }
+
+    #[test]
+    fn test_grep_newest_first_in_batches() {
+        let messages: Vec<ChatMessage> = (1..=250)
+            .map(|id| message(id, if id % 2 == 0 { "even" } else { "odd" }))
+            .collect();
+        let pattern = GrepPattern::parse("EVEN").unwrap();
+        let mut batches = Vec::new();
+        let found = grep_messages(&messages, &pattern, 1, "Chat", |batch, done| {
+            batches.push((batch, done));
+            true
+        });
+        assert_eq!(found, 125);
+        let sizes: Vec<(usize, bool)> = batches.iter().map(|(b, d)| (b.len(), *d)).collect();
+        assert_eq!(sizes, [(100, false), (25, true)]);
+        assert_eq!(batches[0].0[0].message_id, 250);
+        assert_eq!(batches[0].0[0].highlight, Some(0..4));
+    }
+
+    #[test]
+    fn test_grep_stops_when_asked() {
+        let messages: Vec<ChatMessage> = (1..=500).map(|id| message(id, "x")).collect();
+        let pattern = GrepPattern::parse("x").unwrap();
+        let mut calls = 0;
+        grep_messages(&messages, &pattern, 1, "Chat", |_, _| {
+            calls += 1;
+            false
+        });
+        assert_eq!(calls, 1);
+    }
+
+    #[test]
+    fn test_searchable_history_merges() {
+        let loaded = vec![
+            message(3, "new text"),
+            message(4, "d"),
+            message(0, "pending"),
+        ];
+        let merged = searchable_history(1, loaded, None);
+        let ids: Vec<i64> = merged.iter().map(|m| m.id).collect();
+        assert_eq!(ids, [3, 4]);
+    }
+}
@@ -3,20 +3,43 @@
 //! This crate provides UI-agnostic core functionality that can be used
 //! by both TUI (ratatui) and GUI (Iced) frontends.
 
+pub mod batch;
+pub mod cache;
 pub mod commands;
+pub mod config;
+pub mod download;
+pub mod edit;
+pub mod emoji;
+pub mod errors;
 pub mod events;
 pub mod executor;
+pub mod export;
+#[cfg(any(test, feature = "test-support"))]
+pub mod fixtures;
+pub mod fuzzy;
+pub mod grep;
+pub mod logging;
 pub mod longpoll;
 pub mod mapper;
+pub mod media;
 pub mod models;
+pub mod outbox;
+pub mod outgoing;
+pub mod persist;
+pub mod profiles;
 pub mod state;
+pub mod stats;
+pub mod timeline;
+pub mod upload;
+pub mod window;
 
 // Re-export commonly used types
 pub use commands::{AsyncCommand, Command};
+pub use errors::ErrorCategory;
 pub use events::{CoreEvent, VkEvent};
 pub use executor::CommandExecutor;
 pub use models::*;
 pub use state::{ChatsPagination, CoreState, MessagesPagination};
 
 // Re-export vk-api types that frontends might need
-pub use vk_api::{User, VkClient};
+pub use vk_api::{ProfileInfo, User, VkClient};
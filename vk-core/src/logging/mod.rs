@@ -0,0 +1,134 @@
+//! Log files shared by the frontends.
+//!
+//! Logs are written to a daily file in the state directory
+//! (`~/.local/state/vk_tui/logs` on Linux); the newest [`KEPT_LOG_FILES`]
+//! are kept. The TUI logs only there, since anything written to the
+//! terminal would corrupt the screen; graphical frontends log to the
+//! console as well.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+
+/// Daily log files kept before the oldest is removed.
+pub const KEPT_LOG_FILES: usize = 7;
+
+const FILE_PREFIX: &str = "vk_tui";
+const FILE_SUFFIX: &str = "log";
+
+/// Directory the log files go to.
+pub fn log_dir() -> Option<PathBuf> {
+    let dirs = directories::ProjectDirs::from("", "", "vk_tui")?;
+    // Only Linux has a state directory
+    let base = dirs.state_dir().unwrap_or_else(|| dirs.data_local_dir());
+    Some(base.join("logs"))
+}
+
+/// Writer appending to today's file in `dir`, on a background thread.
+/// Lines are lost once the guard is dropped, so keep it until exit.
+pub fn file_writer(dir: &Path) -> anyhow::Result<(NonBlocking, WorkerGuard)> {
+    std::fs::create_dir_all(dir)?;
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(FILE_PREFIX)
+        .filename_suffix(FILE_SUFFIX)
+        .max_log_files(KEPT_LOG_FILES)
+        .build(dir)?;
+    Ok(tracing_appender::non_blocking(appender))
+}
+
+/// The file in `dir` being written to: the newest one.
+pub fn current_log_file(dir: &Path) -> Option<PathBuf> {
+    let suffix = format!(".{}", FILE_SUFFIX);
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(FILE_PREFIX) && name.ends_with(&suffix))
+        })
+        // Dated names sort by day
+        .max()
+}
+
+/// The last `count` lines of the file at `path`, read from its end.
+pub fn tail(path: &Path, count: usize) -> io::Result<Vec<String>> {
+    const CHUNK: u64 = 64 * 1024;
+
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut read = CHUNK.min(len);
+    loop {
+        file.seek(SeekFrom::Start(len - read))?;
+        let mut buf = Vec::with_capacity(read as usize);
+        (&mut file).take(read).read_to_end(&mut buf)?;
+        let text = String::from_utf8_lossy(&buf);
+        let lines: Vec<&str> = text.lines().collect();
+        // The first line may be cut unless the whole file was read
+        if lines.len() > count || read == len {
+            let skip = lines.len().saturating_sub(count);
+            return Ok(lines[skip..].iter().map(|l| l.to_string()).collect());
+        }
+        read = (read * 2).min(len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("vk_core_logging_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_tail() {
+        let dir = temp_dir("tail");
+        let path = dir.join("vk_tui.2026-01-01.log");
+        let text: String = (1..=20_000).map(|i| format!("line {}\n", i)).collect();
+        std::fs::write(&path, text).unwrap();
+
+        assert_eq!(
+            tail(&path, 3).unwrap(),
+            ["line 19998", "line 19999", "line 20000"]
+        );
+        // More than one chunk back
+        let lines = tail(&path, 15_000).unwrap();
+        assert_eq!(lines.len(), 15_000);
+        assert_eq!(lines[0], "line 5001");
+        // Fewer lines than asked for
+        assert_eq!(tail(&path, 50_000).unwrap().len(), 20_000);
+
+        std::fs::write(&path, "").unwrap();
+        assert!(tail(&path, 10).unwrap().is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_current_log_file_is_newest() {
+        let dir = temp_dir("current");
+        assert_eq!(current_log_file(&dir), None);
+        for name in [
+            "vk_tui.2026-01-02.log",
+            "vk_tui.2026-01-10.log",
+            "notes.txt",
+            "vk_tui.2026-01-09.log",
+        ] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        assert_eq!(
+            current_log_file(&dir),
+            Some(dir.join("vk_tui.2026-01-10.log"))
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
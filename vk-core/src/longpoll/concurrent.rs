@@ -0,0 +1,114 @@
+//! Detecting another client polling with the same token.
+//!
+//! VK keeps one Long Poll key per token, so two running clients keep
+//! invalidating each other's key (`failed: 2/3` over and over). A burst of
+//! such failures is the only sign of it the API gives.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Key failures within [`FAILURE_WINDOW`] that trigger the warning.
+pub const FAILURE_THRESHOLD: usize = 3;
+
+/// How far back key failures are counted.
+pub const FAILURE_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// Minimum time between two warnings.
+pub const WARNING_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Text frontends show for [`CoreEvent::PossibleConcurrentSession`].
+///
+/// [`CoreEvent::PossibleConcurrentSession`]: crate::CoreEvent::PossibleConcurrentSession
+pub const CONCURRENT_SESSION_WARNING: &str =
+    "Another client appears to be using this account — real-time updates may be unreliable";
+
+/// Rate of Long Poll key failures, fed by the runner.
+#[derive(Debug, Default)]
+pub struct ConcurrentSessionDetector {
+    /// Failures within the window, oldest first
+    recent: VecDeque<Instant>,
+    last_warning: Option<Instant>,
+}
+
+impl ConcurrentSessionDetector {
+    /// Record a key failure at `now`. Returns the number of failures in
+    /// the window when it is time to warn, at most once per
+    /// [`WARNING_INTERVAL`].
+    pub fn record_failure(&mut self, now: Instant) -> Option<u32> {
+        while self
+            .recent
+            .front()
+            .is_some_and(|&at| now.duration_since(at) > FAILURE_WINDOW)
+        {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(now);
+
+        let warned_recently = self
+            .last_warning
+            .is_some_and(|at| now.duration_since(at) < WARNING_INTERVAL);
+        if self.recent.len() < FAILURE_THRESHOLD || warned_recently {
+            return None;
+        }
+        self.last_warning = Some(now);
+        Some(self.recent.len() as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed failures at the given offsets (seconds from start) and return
+    /// the offsets that produced a warning.
+    fn warnings_at(offsets: &[u64]) -> Vec<u64> {
+        let start = Instant::now();
+        let mut detector = ConcurrentSessionDetector::default();
+        offsets
+            .iter()
+            .filter(|&&s| {
+                detector
+                    .record_failure(start + Duration::from_secs(s))
+                    .is_some()
+            })
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn test_burst_warns_once() {
+        assert_eq!(warnings_at(&[0, 10, 20]), vec![20]);
+        // The storm goes on, but the warning is not repeated
+        assert_eq!(warnings_at(&[0, 10, 20, 30, 40, 50]), vec![20]);
+    }
+
+    #[test]
+    fn test_spread_out_failures_do_not_warn() {
+        // Occasional key expiry is normal: one every ten minutes
+        assert!(warnings_at(&[0, 600, 1200, 1800, 2400]).is_empty());
+        // Two close together, the third after the first left the window
+        assert!(warnings_at(&[0, 200, 301]).is_empty());
+    }
+
+    #[test]
+    fn test_warns_again_after_an_hour() {
+        assert_eq!(
+            warnings_at(&[0, 1, 2, 1800, 1801, 1802, 3601, 3602, 3603]),
+            vec![2, 3603]
+        );
+    }
+
+    #[test]
+    fn test_reports_failures_in_window() {
+        let start = Instant::now();
+        let mut detector = ConcurrentSessionDetector::default();
+        detector.record_failure(start);
+        detector.record_failure(start + Duration::from_secs(400));
+        detector.record_failure(start + Duration::from_secs(401));
+        // The first one is out of the window by now
+        assert_eq!(
+            detector.record_failure(start + Duration::from_secs(402)),
+            Some(3)
+        );
+    }
+}
@@ -0,0 +1,80 @@
+//! Connection state shown by the frontends.
+
+use crate::events::{CoreEvent, VkEvent};
+
+/// State of the Long Poll connection, as shown to the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConnectionState {
+    Connected,
+    /// Getting a new Long Poll server; `attempt` counts from 1 since the
+    /// connection was lost.
+    Reconnecting {
+        attempt: u32,
+    },
+    /// Long Poll cannot connect; recent conversations are polled instead.
+    Polling,
+    /// Not started yet, lost and waiting to retry, or stopped.
+    #[default]
+    Offline,
+}
+
+impl ConnectionState {
+    /// Follow a Long Poll event; other events leave the state as is.
+    pub fn apply(&mut self, event: &CoreEvent) {
+        match event {
+            CoreEvent::VkEvent(VkEvent::ConnectionStatus(true)) => *self = Self::Connected,
+            CoreEvent::VkEvent(VkEvent::ConnectionStatus(false)) | CoreEvent::AuthExpired => {
+                *self = Self::Offline;
+            }
+            CoreEvent::LongPollReconnecting { attempt } => {
+                *self = Self::Reconnecting { attempt: *attempt };
+            }
+            CoreEvent::LongPollDegraded => *self = Self::Polling,
+            _ => {}
+        }
+    }
+
+    /// "● online", "⟳ reconnecting (2)", "◌ polling (degraded)" or
+    /// "○ offline".
+    pub fn label(&self) -> String {
+        match self {
+            Self::Connected => "● online".into(),
+            Self::Reconnecting { attempt: 1 } => "⟳ reconnecting".into(),
+            Self::Reconnecting { attempt } => format!("⟳ reconnecting ({})", attempt),
+            Self::Polling => "◌ polling (degraded)".into(),
+            Self::Offline => "○ offline".into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_follows_long_poll_events() {
+        let mut state = ConnectionState::default();
+        assert_eq!(state, ConnectionState::Offline);
+
+        state.apply(&CoreEvent::VkEvent(VkEvent::ConnectionStatus(true)));
+        assert_eq!(state, ConnectionState::Connected);
+        state.apply(&CoreEvent::LongPollKeyExpired);
+        assert_eq!(state, ConnectionState::Connected);
+
+        state.apply(&CoreEvent::VkEvent(VkEvent::ConnectionStatus(false)));
+        assert_eq!(state.label(), "○ offline");
+        state.apply(&CoreEvent::LongPollReconnecting { attempt: 1 });
+        assert_eq!(state.label(), "⟳ reconnecting");
+        state.apply(&CoreEvent::LongPollReconnecting { attempt: 3 });
+        assert_eq!(state, ConnectionState::Reconnecting { attempt: 3 });
+        assert_eq!(state.label(), "⟳ reconnecting (3)");
+
+        state.apply(&CoreEvent::LongPollDegraded);
+        assert_eq!(state.label(), "◌ polling (degraded)");
+        state.apply(&CoreEvent::VkEvent(VkEvent::ConnectionStatus(true)));
+        assert_eq!(state, ConnectionState::Connected);
+
+        state.apply(&CoreEvent::AuthExpired);
+        assert_eq!(state, ConnectionState::Offline);
+    }
+}
@@ -0,0 +1,185 @@
+//! Polling used while Long Poll cannot connect.
+//!
+//! Some networks (corporate proxies) block the Long Poll server while the
+//! API itself works. After [`RetryPolicy::fallback_after`] failed polls in
+//! a row the runner fetches the recent conversations every
+//! [`RetryPolicy::poll_interval`] instead, and [`RecentConversations`]
+//! turns what changed into the events Long Poll would have sent.
+//!
+//! Only the last message of each conversation is visible here, so several
+//! messages arriving in one chat between two polls show up as one.
+//!
+//! [`RetryPolicy::fallback_after`]: super::RetryPolicy::fallback_after
+//! [`RetryPolicy::poll_interval`]: super::RetryPolicy::poll_interval
+
+use std::collections::HashMap;
+
+use vk_api::ConversationItem;
+
+use super::message_event;
+use crate::events::VkEvent;
+
+/// Conversations fetched on every poll.
+pub const RECENT_CONVERSATIONS: u32 = 20;
+
+/// Read marks of a conversation at the last poll.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ReadMarks {
+    /// Last incoming message the user has read
+    in_read: i64,
+    /// Last outgoing message the other side has read
+    out_read: i64,
+}
+
+/// Recent conversations as of the last poll.
+#[derive(Debug, Default)]
+pub struct RecentConversations {
+    /// Newest message seen; `None` until the first poll
+    newest_message_id: Option<i64>,
+    read: HashMap<i64, ReadMarks>,
+}
+
+impl RecentConversations {
+    /// Remember `items` and return the events for what changed since the
+    /// previous poll. The first poll only takes a baseline.
+    ///
+    /// Read events come before new messages, so a read mark does not clear
+    /// the unread count of a message that arrived after it.
+    pub fn update(&mut self, items: &[ConversationItem]) -> Vec<VkEvent> {
+        let mut events = Vec::new();
+
+        for item in items {
+            let peer_id = item.conversation.peer.id;
+            let marks = ReadMarks {
+                in_read: item.conversation.in_read.unwrap_or(0),
+                out_read: item.conversation.out_read.unwrap_or(0),
+            };
+            if let Some(previous) = self.read.insert(peer_id, marks) {
+                if marks.out_read > previous.out_read {
+                    events.push(VkEvent::MessageRead {
+                        peer_id,
+                        message_id: marks.out_read,
+                    });
+                } else if marks.in_read > previous.in_read
+                    && item.conversation.unread_count.unwrap_or(0) == 0
+                {
+                    // Read on another device
+                    events.push(VkEvent::MessageRead {
+                        peer_id,
+                        message_id: marks.in_read,
+                    });
+                }
+            }
+        }
+
+        if let Some(seen) = self.newest_message_id {
+            // Oldest first, the order Long Poll delivers them in
+            let mut new: Vec<_> = items
+                .iter()
+                .map(|item| &item.last_message)
+                .filter(|msg| msg.id > seen)
+                .collect();
+            new.sort_by_key(|msg| msg.id);
+            events.extend(new.into_iter().map(message_event));
+        }
+
+        let newest = items.iter().map(|item| item.last_message.id).max();
+        self.newest_message_id = self.newest_message_id.max(newest);
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(peer_id: i64, message_id: i64, in_read: i64, out_read: i64) -> ConversationItem {
+        serde_json::from_value(serde_json::json!({
+            "conversation": {
+                "peer": { "id": peer_id, "type": "user" },
+                "in_read": in_read,
+                "out_read": out_read,
+                "unread_count": (message_id - in_read).max(0),
+            },
+            "last_message": {
+                "id": message_id,
+                "peer_id": peer_id,
+                "from_id": peer_id,
+                "date": 1700000000 + message_id,
+                "text": format!("message {}", message_id),
+                "out": 0,
+            },
+        }))
+        .unwrap()
+    }
+
+    fn new_message_ids(events: &[VkEvent]) -> Vec<i64> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                VkEvent::NewMessage { message_id, .. } => Some(*message_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_first_poll_is_baseline() {
+        let items = [item(1, 10, 10, 9), item(2, 8, 7, 8)];
+        let mut recent = RecentConversations::default();
+        assert!(recent.update(&items).is_empty());
+        assert!(recent.update(&items).is_empty());
+    }
+
+    #[test]
+    fn test_new_messages_oldest_first() {
+        let mut recent = RecentConversations::default();
+        recent.update(&[item(1, 10, 10, 10), item(2, 8, 8, 8)]);
+
+        // Chat 3 was below the fetched page before its new message
+        let events = recent.update(&[item(3, 12, 0, 0), item(2, 11, 8, 8), item(1, 10, 10, 10)]);
+
+        assert_eq!(new_message_ids(&events), vec![11, 12]);
+        assert!(matches!(
+            &events[0],
+            VkEvent::NewMessage { peer_id: 2, text, .. } if text == "message 11"
+        ));
+        assert!(recent.update(&[item(3, 12, 0, 0)]).is_empty());
+    }
+
+    #[test]
+    fn test_read_marks() {
+        let mut recent = RecentConversations::default();
+        recent.update(&[item(1, 10, 10, 9), item(2, 8, 6, 8)]);
+
+        // Chat 1 read up to message 10; we read chat 2 on another device
+        let events = recent.update(&[item(1, 10, 10, 10), item(2, 8, 8, 8)]);
+
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            events[0],
+            VkEvent::MessageRead {
+                peer_id: 1,
+                message_id: 10
+            }
+        ));
+        assert!(matches!(
+            events[1],
+            VkEvent::MessageRead {
+                peer_id: 2,
+                message_id: 8
+            }
+        ));
+    }
+
+    #[test]
+    fn test_read_comes_before_new_message() {
+        let mut recent = RecentConversations::default();
+        recent.update(&[item(1, 10, 10, 9)]);
+
+        let events = recent.update(&[item(1, 11, 10, 10)]);
+
+        assert!(matches!(events[0], VkEvent::MessageRead { .. }));
+        assert_eq!(new_message_ids(&events), vec![11]);
+    }
+}
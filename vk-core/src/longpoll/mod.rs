@@ -1,7 +1,29 @@
 //! VK LongPoll event handling.
 
+mod concurrent;
+mod connection;
+mod fallback;
+mod runner;
+
+pub use concurrent::{
+    CONCURRENT_SESSION_WARNING, ConcurrentSessionDetector, FAILURE_THRESHOLD, FAILURE_WINDOW,
+    WARNING_INTERVAL,
+};
+pub use connection::ConnectionState;
+pub use fallback::{RECENT_CONVERSATIONS, RecentConversations};
+pub use runner::{LongPollSource, RetryPolicy, reconnect, run, run_with};
+
 use crate::events::VkEvent;
+use crate::models::ServiceAction;
 use serde_json::Value;
+use vk_api::{LongPollHistory, LongPollServer, Message, VkClient};
+
+/// Message flag: outgoing message.
+pub const FLAG_OUTBOX: i64 = 2;
+/// Message flag: marked as important.
+pub const FLAG_IMPORTANT: i64 = 8;
+/// Message flag: deleted.
+pub const FLAG_DELETED: i64 = 128;
 
 /// Parse a single longpoll update into VkEvent, if applicable.
 pub fn handle_update(update: &Value) -> Option<VkEvent> {
@@ -9,51 +31,37 @@ pub fn handle_update(update: &Value) -> Option<VkEvent> {
     let event_type = arr.first().and_then(|v| v.as_i64())?;
 
     match event_type {
-        2 => {
-            // Message deleted: [2, message_id, flags, peer_id]
-            let message_id = arr.get(1).and_then(|v| v.as_i64())?;
-            let peer_id = arr.get(3).and_then(|v| v.as_i64())?;
-            Some(VkEvent::MessageDeletedFromLongPoll {
-                peer_id,
-                message_id,
-            })
-        }
-        4 => {
-            // New message: [4, message_id, flags, peer_id, timestamp, text, extra, attachments]
+        2 | 3 => {
+            // Flags set (2) or reset (3): [2/3, message_id, flags, peer_id]
             let message_id = arr.get(1).and_then(|v| v.as_i64())?;
             let flags = arr.get(2).and_then(|v| v.as_i64()).unwrap_or(0);
             let peer_id = arr.get(3).and_then(|v| v.as_i64())?;
-            let timestamp = arr.get(4).and_then(|v| v.as_i64()).unwrap_or(0);
-            let text = arr
-                .get(5)
-                .and_then(|v| v.as_str())
-                .unwrap_or("")
-                .to_string();
-            let extra = arr.get(6);
-            let from_id = extra
-                .and_then(|v| v.as_object())
-                .and_then(|obj| obj.get("from"))
-                .and_then(|v| v.as_str())
-                .and_then(|s| s.parse::<i64>().ok())
-                .or(Some(peer_id))?;
-            // Bit 1 (value 2) in flags means OUTBOX (message is outgoing)
-            let is_outgoing = (flags & 2) != 0;
-            Some(VkEvent::NewMessage {
+            let set = event_type == 2;
+            if set && flags & FLAG_DELETED != 0 {
+                return Some(VkEvent::MessageDeletedFromLongPoll {
+                    peer_id,
+                    message_id,
+                });
+            }
+            Some(VkEvent::MessageFlagsChanged {
                 message_id,
                 peer_id,
-                timestamp,
-                text,
-                from_id,
-                is_outgoing,
+                flags,
+                set,
             })
         }
+        4 => parse_new_message(arr),
         5 => {
-            // Message flags changed (used as edit marker): [5, message_id, flags, peer_id, ...]
+            // Message edited, laid out like a new message:
+            // [5, message_id, flags, peer_id, timestamp, text, extra, attachments, ...]
             let message_id = arr.get(1).and_then(|v| v.as_i64())?;
             let peer_id = arr.get(3).and_then(|v| v.as_i64())?;
+            let new_text = arr.get(5).and_then(|v| v.as_str()).map(str::to_string);
             Some(VkEvent::MessageEditedFromLongPoll {
                 peer_id,
                 message_id,
+                new_text,
+                has_attachments: lists_attachments(arr.get(7)),
             })
         }
         61 => {
@@ -68,9 +76,15 @@ pub fn handle_update(update: &Value) -> Option<VkEvent> {
             // User typing in chat: [62, user_id, chat_id]
             let user_id = arr.get(1).and_then(|v| v.as_i64())?;
             let chat_id = arr.get(2).and_then(|v| v.as_i64())?;
-            let peer_id = 2000000000 + chat_id;
+            let peer_id = vk_api::chat_peer_id(chat_id)?;
             Some(VkEvent::UserTyping { peer_id, user_id })
         }
+        601 => {
+            // Reactions on a message changed: [601, peer_id, cmid, ...]
+            let peer_id = arr.get(1).and_then(|v| v.as_i64())?;
+            let cmid = arr.get(2).and_then(|v| v.as_i64())?;
+            Some(VkEvent::ReactionsChanged { peer_id, cmid })
+        }
         6 | 7 => {
             // Message read events: [6/7, peer_id, message_id, ...]
             let peer_id = arr.get(1).and_then(|v| v.as_i64())?;
@@ -83,3 +97,402 @@ pub fn handle_update(update: &Value) -> Option<VkEvent> {
         _ => None,
     }
 }
+
+/// New message: [4, message_id, flags, peer_id, timestamp, text, extra, attachments, random_id, ...]
+///
+/// `extra` carries `from` (the sender) in group chats and `source_act` for
+/// service messages; `attachments` holds `attachN_type`/`attachN` pairs plus
+/// `fwd`/`reply`, which frontends hydrate with messages.getById.
+fn parse_new_message(arr: &[Value]) -> Option<VkEvent> {
+    let message_id = arr.get(1).and_then(|v| v.as_i64())?;
+    let flags = arr.get(2).and_then(|v| v.as_i64()).unwrap_or(0);
+    let peer_id = arr.get(3).and_then(|v| v.as_i64())?;
+    let timestamp = arr.get(4).and_then(|v| v.as_i64()).unwrap_or(0);
+    let text = arr
+        .get(5)
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .to_string();
+    let extra = arr.get(6);
+    let from_id = extra
+        .and_then(|v| v.get("from"))
+        .and_then(as_id)
+        .unwrap_or(peer_id);
+    let action = extra.and_then(parse_service_action);
+    let attachments = arr.get(7).and_then(|v| v.as_object());
+    let has_attachments = lists_attachments(arr.get(7));
+    let mut attachment_types: Vec<String> = (1..)
+        .map_while(|n| {
+            let kind = attachments?.get(&format!("attach{}_type", n))?;
+            Some(kind.as_str().unwrap_or_default().to_string())
+        })
+        .collect();
+    if attachments.is_some_and(|obj| obj.contains_key("fwd")) {
+        attachment_types.push("fwd".to_string());
+    }
+    let random_id = arr.get(8).and_then(|v| v.as_i64()).filter(|id| *id != 0);
+
+    Some(VkEvent::NewMessage {
+        message_id,
+        peer_id,
+        timestamp,
+        text,
+        from_id,
+        is_outgoing: flags & FLAG_OUTBOX != 0,
+        has_attachments,
+        attachment_types,
+        action,
+        random_id,
+    })
+}
+
+/// Whether the `attachments` object of an update lists any attachment,
+/// forwarded messages or a reply.
+fn lists_attachments(attachments: Option<&Value>) -> bool {
+    attachments.and_then(|v| v.as_object()).is_some_and(|obj| {
+        obj.keys()
+            .any(|key| key.starts_with("attach") || key == "fwd" || key == "reply")
+    })
+}
+
+/// `source_act`, `source_mid` and `source_text` of a service message.
+fn parse_service_action(extra: &Value) -> Option<ServiceAction> {
+    let kind = extra.get("source_act")?.as_str()?;
+    Some(ServiceAction {
+        kind: kind.to_string(),
+        member_id: extra.get("source_mid").and_then(as_id),
+        text: extra
+            .get("source_text")
+            .and_then(|v| v.as_str())
+            .map(str::to_string),
+    })
+}
+
+/// Ids in `extra` come as strings or numbers.
+fn as_id(value: &Value) -> Option<i64> {
+    value.as_i64().or_else(|| value.as_str()?.parse().ok())
+}
+
+/// Fetch events missed since `server.ts`/`server.pts` (e.g. while the
+/// network was down) via messages.getLongPollHistory.
+///
+/// Call with the *old* server before switching to a new one.
+pub async fn catch_up(client: &VkClient, server: &LongPollServer) -> vk_api::Result<Vec<VkEvent>> {
+    let history = client
+        .longpoll()
+        .get_long_poll_history(&server.ts, server.pts)
+        .await?;
+    Ok(history_events(&history))
+}
+
+/// Convert a getLongPollHistory response into events.
+///
+/// History entries are short (`[4, message_id, flags, peer_id]`), so new
+/// messages are filled in from the accompanying message objects.
+pub fn history_events(history: &LongPollHistory) -> Vec<VkEvent> {
+    let messages = history
+        .messages
+        .as_ref()
+        .map(|m| m.items.as_slice())
+        .unwrap_or_default();
+
+    history
+        .history
+        .iter()
+        .filter_map(|update| {
+            let event = handle_update(update)?;
+            let VkEvent::NewMessage { message_id, .. } = event else {
+                return Some(event);
+            };
+            let Some(msg) = messages.iter().find(|m| m.id == message_id) else {
+                return Some(event);
+            };
+            Some(message_event(msg))
+        })
+        .collect()
+}
+
+/// The `NewMessage` event Long Poll sends for `msg`.
+fn message_event(msg: &Message) -> VkEvent {
+    VkEvent::NewMessage {
+        message_id: msg.id,
+        peer_id: msg.peer_id,
+        timestamp: msg.date,
+        text: msg.text.clone(),
+        from_id: msg.from_id,
+        is_outgoing: msg.out == Some(1),
+        has_attachments: !msg.attachments.is_empty()
+            || !msg.fwd_messages.is_empty()
+            || msg.reply_message.is_some(),
+        attachment_types: msg
+            .attachments
+            .iter()
+            .map(|a| a.attachment_type.clone())
+            .chain((!msg.fwd_messages.is_empty()).then(|| "fwd".to_string()))
+            .collect(),
+        action: msg.action.as_ref().map(crate::mapper::map_service_action),
+        random_id: msg.random_id.filter(|id| *id != 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_message_in_group_chat() {
+        let update = serde_json::json!([
+            4, 531, 532481, 2000000012, 1700000123, "look at this",
+            {"title": "", "from": "215837"},
+            {"attach1_type": "photo", "attach1": "215837_457240012"},
+            0, 1204, 0
+        ]);
+
+        match handle_update(&update) {
+            Some(VkEvent::NewMessage {
+                message_id,
+                peer_id,
+                timestamp,
+                text,
+                from_id,
+                is_outgoing,
+                has_attachments,
+                attachment_types,
+                action,
+                random_id,
+            }) => {
+                assert_eq!(message_id, 531);
+                assert_eq!(peer_id, 2000000012);
+                assert_eq!(timestamp, 1700000123);
+                assert_eq!(text, "look at this");
+                assert_eq!(from_id, 215837);
+                assert!(!is_outgoing);
+                assert!(has_attachments);
+                assert_eq!(attachment_types, vec!["photo"]);
+                assert_eq!(action, None);
+                assert_eq!(random_id, None);
+            }
+            other => panic!("expected NewMessage, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_new_outgoing_message_in_dialog() {
+        let update = serde_json::json!([
+            4, 77, 35, 1001, 1700000456, "hi", {"title": " ... "}, {}, 1948302211, 12, 0
+        ]);
+
+        match handle_update(&update) {
+            Some(VkEvent::NewMessage {
+                from_id,
+                is_outgoing,
+                has_attachments,
+                random_id,
+                ..
+            }) => {
+                assert_eq!(from_id, 1001);
+                assert!(is_outgoing);
+                assert!(!has_attachments);
+                assert_eq!(random_id, Some(1948302211));
+            }
+            other => panic!("expected NewMessage, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_text_edit() {
+        let update = serde_json::json!([
+            5, 533, 1, 2000000012, 1700000123, "see you at 10",
+            {"title": "", "from": "215837"},
+            {},
+            0, 1206, 1700000200
+        ]);
+
+        match handle_update(&update) {
+            Some(VkEvent::MessageEditedFromLongPoll {
+                peer_id,
+                message_id,
+                new_text,
+                has_attachments,
+            }) => {
+                assert_eq!(peer_id, 2000000012);
+                assert_eq!(message_id, 533);
+                assert_eq!(new_text.as_deref(), Some("see you at 10"));
+                assert!(!has_attachments);
+            }
+            other => panic!("expected MessageEditedFromLongPoll, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_edit_with_attachments() {
+        let update = serde_json::json!([
+            5, 534, 35, 1001, 1700000456, "new caption", {"title": " ... "},
+            {"attach1_type": "photo", "attach1": "1001_457240013"},
+            0, 13, 1700000500
+        ]);
+
+        assert!(matches!(
+            handle_update(&update),
+            Some(VkEvent::MessageEditedFromLongPoll {
+                message_id: 534,
+                has_attachments: true,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_edit_without_text() {
+        // getLongPollHistory entries stop after the peer id
+        let update = serde_json::json!([5, 535, 1, 1001]);
+
+        assert!(matches!(
+            handle_update(&update),
+            Some(VkEvent::MessageEditedFromLongPoll {
+                message_id: 535,
+                peer_id: 1001,
+                new_text: None,
+                has_attachments: false,
+            })
+        ));
+    }
+
+    #[test]
+    fn test_reactions_changed() {
+        let update = serde_json::json!([601, 2000000012, 345, 1]);
+
+        match handle_update(&update) {
+            Some(VkEvent::ReactionsChanged { peer_id, cmid }) => {
+                assert_eq!(peer_id, 2000000012);
+                assert_eq!(cmid, 345);
+            }
+            other => panic!("expected ReactionsChanged, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_new_message_keeps_server_timestamp() {
+        let update = serde_json::json!([4, 79, 1, 1001, 1699990000, "sent during outage"]);
+
+        match handle_update(&update) {
+            Some(VkEvent::NewMessage { timestamp, .. }) => {
+                assert_eq!(Some(timestamp), update[4].as_i64());
+            }
+            other => panic!("expected NewMessage, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_forwarded_message_has_attachments() {
+        let update = serde_json::json!([
+            4, 78, 1, 1001, 1700000456, "", {}, {"fwd": "0_0"}
+        ]);
+
+        assert!(matches!(
+            handle_update(&update),
+            Some(VkEvent::NewMessage {
+                has_attachments: true,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_service_message() {
+        let update = serde_json::json!([
+            4, 532, 1, 2000000012, 1700000124, "",
+            {"from": "215837", "source_act": "chat_kick_user", "source_mid": "215837"},
+            {}
+        ]);
+
+        match handle_update(&update) {
+            Some(VkEvent::NewMessage {
+                from_id,
+                action: Some(action),
+                ..
+            }) => {
+                assert_eq!(action.kind, "chat_kick_user");
+                assert_eq!(action.member_id, Some(215837));
+                let preview = crate::models::preview_text("", Some(&action), &[], from_id, |_| {
+                    "Ann Lee".to_string()
+                });
+                assert_eq!(preview, "Ann Lee left the chat");
+            }
+            other => panic!("expected service NewMessage, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_flag_changes() {
+        let important = serde_json::json!([2, 531, 8, 2000000012]);
+        assert!(matches!(
+            handle_update(&important),
+            Some(VkEvent::MessageFlagsChanged {
+                message_id: 531,
+                peer_id: 2000000012,
+                flags: FLAG_IMPORTANT,
+                set: true,
+            })
+        ));
+
+        let deleted = serde_json::json!([2, 531, 128, 2000000012]);
+        assert!(matches!(
+            handle_update(&deleted),
+            Some(VkEvent::MessageDeletedFromLongPoll {
+                message_id: 531,
+                ..
+            })
+        ));
+
+        let restored = serde_json::json!([3, 531, 128, 2000000012]);
+        assert!(matches!(
+            handle_update(&restored),
+            Some(VkEvent::MessageFlagsChanged {
+                flags: FLAG_DELETED,
+                set: false,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_history_events_fill_in_messages() {
+        let history: LongPollHistory = vk_api::schema::from_value_strict(serde_json::json!({
+            "history": [[4, 10, 1, 42], [6, 42, 9], [2, 7, 128, 42]],
+            "messages": {
+                "count": 1,
+                "items": [{"id": 10, "from_id": 42, "peer_id": 42, "date": 1700000000, "text": "missed", "out": 0}]
+            },
+            "new_pts": 100
+        }))
+        .unwrap();
+
+        let events = history_events(&history);
+
+        assert_eq!(events.len(), 3);
+        match &events[0] {
+            VkEvent::NewMessage {
+                message_id,
+                text,
+                timestamp,
+                is_outgoing,
+                ..
+            } => {
+                assert_eq!(*message_id, 10);
+                assert_eq!(text, "missed");
+                assert_eq!(*timestamp, 1700000000);
+                assert!(!is_outgoing);
+            }
+            other => panic!("expected NewMessage, got {:?}", other),
+        }
+        assert!(matches!(
+            events[1],
+            VkEvent::MessageRead { peer_id: 42, .. }
+        ));
+        assert!(matches!(
+            events[2],
+            VkEvent::MessageDeletedFromLongPoll { message_id: 7, .. }
+        ));
+    }
+}
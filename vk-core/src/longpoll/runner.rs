@@ -0,0 +1,784 @@
+//! Long Poll loop shared by all frontends.
+//!
+//! Keeps a single connection to the Long Poll server, handles `failed`
+//! codes, reconnects with exponential backoff and replays events missed
+//! while disconnected. Everything is reported as [`CoreEvent`]s.
+//!
+//! Notifying the `reconnect` handle makes the runner get a new server at
+//! once: the poll in flight, or the wait before the next retry, is cut
+//! short.
+//!
+//! When polls keep failing, the runner falls back to polling the recent
+//! conversations (see [`super::RecentConversations`]) and tries Long Poll
+//! again between those polls.
+
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::{Notify, mpsc, watch};
+use vk_api::{ConversationsResponse, LongPollResponse, LongPollServer, VkClient};
+
+use super::{
+    ConcurrentSessionDetector, RECENT_CONVERSATIONS, RecentConversations, catch_up, handle_update,
+};
+use crate::errors::ErrorDedup;
+use crate::events::{CoreEvent, VkEvent};
+
+/// Where the runner gets Long Poll data from.
+///
+/// Implemented for [`VkClient`]. Frontends may wrap it (e.g. to track
+/// liveness); tests use a scripted mock.
+pub trait LongPollSource: Send + Sync {
+    /// Request a new Long Poll server (messages.getLongPollServer).
+    fn get_server(&self) -> impl Future<Output = vk_api::Result<LongPollServer>> + Send;
+
+    /// Wait for updates on `server`.
+    fn poll(
+        &self,
+        server: &LongPollServer,
+    ) -> impl Future<Output = vk_api::Result<LongPollResponse>> + Send;
+
+    /// Events that happened since `server.ts`/`server.pts`.
+    fn missed_events(
+        &self,
+        server: &LongPollServer,
+    ) -> impl Future<Output = vk_api::Result<Vec<VkEvent>>> + Send;
+
+    /// The `count` most recent conversations (messages.getConversations),
+    /// polled while Long Poll cannot connect.
+    fn recent_conversations(
+        &self,
+        count: u32,
+    ) -> impl Future<Output = vk_api::Result<ConversationsResponse>> + Send;
+}
+
+impl LongPollSource for VkClient {
+    async fn get_server(&self) -> vk_api::Result<LongPollServer> {
+        self.longpoll().get_server().await
+    }
+
+    async fn poll(&self, server: &LongPollServer) -> vk_api::Result<LongPollResponse> {
+        self.longpoll().poll(server).await
+    }
+
+    async fn missed_events(&self, server: &LongPollServer) -> vk_api::Result<Vec<VkEvent>> {
+        catch_up(self, server).await
+    }
+
+    async fn recent_conversations(&self, count: u32) -> vk_api::Result<ConversationsResponse> {
+        self.messages().get_conversations(0, count).await
+    }
+}
+
+impl<S: LongPollSource + ?Sized> LongPollSource for Arc<S> {
+    fn get_server(&self) -> impl Future<Output = vk_api::Result<LongPollServer>> + Send {
+        (**self).get_server()
+    }
+
+    fn poll(
+        &self,
+        server: &LongPollServer,
+    ) -> impl Future<Output = vk_api::Result<LongPollResponse>> + Send {
+        (**self).poll(server)
+    }
+
+    fn missed_events(
+        &self,
+        server: &LongPollServer,
+    ) -> impl Future<Output = vk_api::Result<Vec<VkEvent>>> + Send {
+        (**self).missed_events(server)
+    }
+
+    fn recent_conversations(
+        &self,
+        count: u32,
+    ) -> impl Future<Output = vk_api::Result<ConversationsResponse>> + Send {
+        (**self).recent_conversations(count)
+    }
+}
+
+/// Reconnect timings used by [`run_with`].
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    /// First delay after a poll error, doubled on every further failure
+    pub initial_backoff: Duration,
+    /// Upper bound for the backoff
+    pub max_backoff: Duration,
+    /// Pause after failing to get a new server on `failed: 2..=4`
+    pub reconnect_delay: Duration,
+    /// Failed polls in a row after which recent conversations are polled
+    pub fallback_after: u32,
+    /// Time between those polls, each followed by a Long Poll attempt
+    pub poll_interval: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            reconnect_delay: Duration::from_secs(5),
+            fallback_after: 5,
+            poll_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Run the Long Poll loop for `client` with default timings.
+///
+/// Stops when `shutdown` is set to `true` or its sender is dropped.
+pub async fn run(
+    client: Arc<VkClient>,
+    event_tx: mpsc::UnboundedSender<CoreEvent>,
+    shutdown: watch::Receiver<bool>,
+    reconnect: Arc<Notify>,
+) {
+    run_with(
+        client,
+        RetryPolicy::default(),
+        event_tx,
+        shutdown,
+        reconnect,
+    )
+    .await;
+}
+
+/// Run the Long Poll loop on an arbitrary [`LongPollSource`].
+pub async fn run_with<S: LongPollSource>(
+    source: S,
+    policy: RetryPolicy,
+    event_tx: mpsc::UnboundedSender<CoreEvent>,
+    mut shutdown: watch::Receiver<bool>,
+    reconnect_now: Arc<Notify>,
+) {
+    tracing::info!("Starting Long Poll...");
+    let mut errors = ErrorDedup::default();
+    let mut emit = |event: CoreEvent| {
+        let _ = event_tx.send(errors.stamp(event, Instant::now()));
+    };
+
+    let Some(result) = until_shutdown(&mut shutdown, source.get_server()).await else {
+        return;
+    };
+    let mut server = match result {
+        Ok(server) => {
+            tracing::info!("Got Long Poll server: {}", server.server);
+            server
+        }
+        Err(e) => {
+            tracing::error!("Failed to get Long Poll server: {}", e);
+            emit(error_event("Long Poll error", e));
+            return;
+        }
+    };
+    emit(CoreEvent::VkEvent(VkEvent::ConnectionStatus(true)));
+
+    let mut backoff = policy.initial_backoff;
+    // Reconnect attempts since the connection was lost
+    let mut attempt = 0;
+    // Polls failed in a row; a new server does not reset it
+    let mut poll_failures = 0;
+    let mut recent = RecentConversations::default();
+    let mut key_failures = ConcurrentSessionDetector::default();
+    loop {
+        let polled = until_shutdown(
+            &mut shutdown,
+            unless_notified(&reconnect_now, source.poll(&server)),
+        );
+        let Some(polled) = polled.await else {
+            break;
+        };
+        let Some(result) = polled else {
+            tracing::info!("Long Poll reconnect requested");
+            attempt += 1;
+            // Give Long Poll a fresh start before falling back again
+            poll_failures = 0;
+            emit(CoreEvent::LongPollReconnecting { attempt });
+            match until_shutdown(&mut shutdown, reconnect(&source, &mut server)).await {
+                None => break,
+                Some(Ok(missed)) => {
+                    missed.into_iter().for_each(|e| emit(CoreEvent::VkEvent(e)));
+                    emit(CoreEvent::VkEvent(VkEvent::ConnectionStatus(true)));
+                    backoff = policy.initial_backoff;
+                    attempt = 0;
+                }
+                Some(Err(e)) if e.is_auth() => {
+                    emit(CoreEvent::AuthExpired);
+                    break;
+                }
+                Some(Err(e)) => {
+                    emit(CoreEvent::VkEvent(VkEvent::ConnectionStatus(false)));
+                    emit(error_event("Long Poll reconnect error", e));
+                }
+            }
+            continue;
+        };
+
+        match result {
+            Ok(response) => {
+                if poll_failures >= policy.fallback_after {
+                    tracing::info!("Long Poll is reachable again");
+                    emit(CoreEvent::VkEvent(VkEvent::ConnectionStatus(true)));
+                    attempt = 0;
+                }
+                poll_failures = 0;
+
+                if let Some(failed) = response.failed {
+                    match failed {
+                        1 => {
+                            // History is outdated, continue from the new ts
+                            if let Some(ts) = response.ts {
+                                server.ts = ts;
+                            }
+                        }
+                        2..=4 => {
+                            // Key expired or user info lost: need a new server
+                            if failed != 4 {
+                                emit(CoreEvent::LongPollKeyExpired);
+                                if let Some(recent_failures) =
+                                    key_failures.record_failure(Instant::now())
+                                {
+                                    tracing::warn!(
+                                        "{} Long Poll key failures in a row, another session?",
+                                        recent_failures
+                                    );
+                                    emit(CoreEvent::PossibleConcurrentSession { recent_failures });
+                                }
+                            }
+                            let reconnected =
+                                until_shutdown(&mut shutdown, reconnect(&source, &mut server));
+                            match reconnected.await {
+                                None => break,
+                                Some(Ok(missed)) => {
+                                    missed.into_iter().for_each(|e| emit(CoreEvent::VkEvent(e)));
+                                }
+                                Some(Err(e)) if e.is_auth() => {
+                                    emit(CoreEvent::AuthExpired);
+                                    break;
+                                }
+                                Some(Err(e)) => {
+                                    emit(error_event("Long Poll reconnect error", e));
+                                    let delay = tokio::time::sleep(policy.reconnect_delay);
+                                    if until_shutdown(&mut shutdown, delay).await.is_none() {
+                                        break;
+                                    }
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+
+                if let Some(ts) = response.ts {
+                    server.ts = ts;
+                }
+                if let Some(pts) = response.pts {
+                    server.pts = Some(pts);
+                }
+
+                if let Some(updates) = response.updates {
+                    tracing::debug!("Got {} updates", updates.len());
+                    for update in updates {
+                        tracing::trace!("Update: {:?}", update);
+                        if let Some(event) = handle_update(&update) {
+                            emit(CoreEvent::VkEvent(event));
+                        }
+                    }
+                }
+                if attempt > 0 {
+                    // The old server answered after a failed reconnect
+                    emit(CoreEvent::VkEvent(VkEvent::ConnectionStatus(true)));
+                    attempt = 0;
+                }
+                backoff = policy.initial_backoff;
+            }
+            Err(e) => {
+                poll_failures += 1;
+                if poll_failures >= policy.fallback_after {
+                    if poll_failures == policy.fallback_after {
+                        tracing::warn!(
+                            "Long Poll failed {} times in a row, polling conversations",
+                            poll_failures
+                        );
+                        emit(CoreEvent::LongPollDegraded);
+                        recent = RecentConversations::default();
+                    } else {
+                        tracing::debug!("Long Poll still unreachable: {}", e);
+                    }
+                    let polled = poll_recent(&source, &mut server, &mut recent);
+                    match until_shutdown(&mut shutdown, polled).await {
+                        None => break,
+                        Some(Ok(events)) => {
+                            events.into_iter().for_each(|e| emit(CoreEvent::VkEvent(e)))
+                        }
+                        Some(Err(e)) if e.is_auth() => {
+                            emit(CoreEvent::AuthExpired);
+                            break;
+                        }
+                        Some(Err(e)) => tracing::warn!("Conversation polling failed: {}", e),
+                    }
+
+                    // A requested reconnect tries Long Poll at once
+                    let delay =
+                        unless_notified(&reconnect_now, tokio::time::sleep(policy.poll_interval));
+                    if until_shutdown(&mut shutdown, delay).await.is_none() {
+                        break;
+                    }
+                    continue;
+                }
+
+                emit(CoreEvent::VkEvent(VkEvent::ConnectionStatus(false)));
+                emit(CoreEvent::api_error("Long Poll error", &e));
+
+                // A requested reconnect skips the rest of the wait
+                let delay = unless_notified(&reconnect_now, tokio::time::sleep(backoff));
+                if until_shutdown(&mut shutdown, delay).await.is_none() {
+                    break;
+                }
+                backoff = (backoff * 2).min(policy.max_backoff);
+
+                attempt += 1;
+                emit(CoreEvent::LongPollReconnecting { attempt });
+                match until_shutdown(&mut shutdown, reconnect(&source, &mut server)).await {
+                    None => break,
+                    Some(Ok(missed)) => {
+                        missed.into_iter().for_each(|e| emit(CoreEvent::VkEvent(e)));
+                        emit(CoreEvent::VkEvent(VkEvent::ConnectionStatus(true)));
+                        backoff = policy.initial_backoff;
+                        attempt = 0;
+                    }
+                    Some(Err(e)) if e.is_auth() => {
+                        emit(CoreEvent::AuthExpired);
+                        break;
+                    }
+                    Some(Err(_)) => continue,
+                }
+            }
+        }
+    }
+
+    tracing::info!("Long Poll stopped");
+}
+
+/// Switch `server` to a freshly requested Long Poll server and return the
+/// events missed while the old one was unusable.
+///
+/// A failed catch-up (history too old, network hiccup) is not fatal: polling
+/// simply continues from the new server.
+pub async fn reconnect<S: LongPollSource + ?Sized>(
+    source: &S,
+    server: &mut LongPollServer,
+) -> vk_api::Result<Vec<VkEvent>> {
+    let new_server = source.get_server().await?;
+
+    let events = match source.missed_events(server).await {
+        Ok(events) => {
+            tracing::info!("Long Poll catch-up: {} missed events", events.len());
+            events
+        }
+        Err(e) => {
+            tracing::warn!("Long Poll catch-up failed: {}", e);
+            Vec::new()
+        }
+    };
+
+    *server = new_server;
+    Ok(events)
+}
+
+/// One poll of the fallback mode: events for the recent conversations
+/// that changed since the last one.
+///
+/// `server` is replaced by a fresh one, so that Long Poll picks up from
+/// here once it answers instead of replaying what this poll has seen.
+async fn poll_recent<S: LongPollSource + ?Sized>(
+    source: &S,
+    server: &mut LongPollServer,
+    recent: &mut RecentConversations,
+) -> vk_api::Result<Vec<VkEvent>> {
+    *server = source.get_server().await?;
+    let response = source.recent_conversations(RECENT_CONVERSATIONS).await?;
+    Ok(recent.update(&response.items))
+}
+
+/// Drive `fut` unless shutdown is requested first (`None` then).
+async fn until_shutdown<F: Future>(
+    shutdown: &mut watch::Receiver<bool>,
+    fut: F,
+) -> Option<F::Output> {
+    tokio::select! {
+        // Err means the sender is gone, which also stops the loop
+        _ = shutdown.wait_for(|&stop| stop) => None,
+        output = fut => Some(output),
+    }
+}
+
+/// Drive `fut` unless `reconnect` is notified first (`None` then).
+async fn unless_notified<F: Future>(reconnect: &Notify, fut: F) -> Option<F::Output> {
+    tokio::select! {
+        _ = reconnect.notified() => None,
+        output = fut => Some(output),
+    }
+}
+
+fn error_event(context: &str, e: vk_api::Error) -> CoreEvent {
+    if e.is_auth() {
+        CoreEvent::AuthExpired
+    } else {
+        CoreEvent::api_error(context, &e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
+    /// Scripted source: each `poll` pops the next result, an empty script
+    /// blocks forever (like an idle Long Poll).
+    #[derive(Default)]
+    struct MockSource {
+        servers: Mutex<VecDeque<vk_api::Result<LongPollServer>>>,
+        polls: Mutex<VecDeque<vk_api::Result<LongPollResponse>>>,
+        missed: Mutex<Vec<VkEvent>>,
+        conversations: Mutex<VecDeque<vk_api::Result<ConversationsResponse>>>,
+        polled_ts: Mutex<Vec<String>>,
+    }
+
+    impl MockSource {
+        fn with_servers(self, count: usize) -> Self {
+            self.servers
+                .lock()
+                .unwrap()
+                .extend((0..count).map(|i| Ok(server(&format!("{}", i * 100)))));
+            self
+        }
+
+        fn then_poll(self, response: vk_api::Result<LongPollResponse>) -> Self {
+            self.polls.lock().unwrap().push_back(response);
+            self
+        }
+
+        fn then_conversations(self, json: serde_json::Value) -> Self {
+            let response = serde_json::from_value(json).unwrap();
+            self.conversations.lock().unwrap().push_back(Ok(response));
+            self
+        }
+    }
+
+    impl LongPollSource for MockSource {
+        async fn get_server(&self) -> vk_api::Result<LongPollServer> {
+            self.servers
+                .lock()
+                .unwrap()
+                .pop_front()
+                .unwrap_or_else(|| Err(vk_api::Error::UnexpectedResponse("no server".into())))
+        }
+
+        async fn poll(&self, server: &LongPollServer) -> vk_api::Result<LongPollResponse> {
+            self.polled_ts.lock().unwrap().push(server.ts.clone());
+            let next = self.polls.lock().unwrap().pop_front();
+            match next {
+                Some(result) => result,
+                None => std::future::pending().await,
+            }
+        }
+
+        async fn missed_events(&self, _server: &LongPollServer) -> vk_api::Result<Vec<VkEvent>> {
+            Ok(std::mem::take(&mut *self.missed.lock().unwrap()))
+        }
+
+        async fn recent_conversations(&self, _count: u32) -> vk_api::Result<ConversationsResponse> {
+            self.conversations
+                .lock()
+                .unwrap()
+                .pop_front()
+                .unwrap_or_else(|| {
+                    Err(vk_api::Error::UnexpectedResponse("no conversations".into()))
+                })
+        }
+    }
+
+    fn server(ts: &str) -> LongPollServer {
+        LongPollServer {
+            key: "key".into(),
+            server: "lp.vk.test".into(),
+            ts: ts.into(),
+            pts: Some(1),
+        }
+    }
+
+    fn response(json: serde_json::Value) -> vk_api::Result<LongPollResponse> {
+        Ok(vk_api::schema::from_value_strict(json).unwrap())
+    }
+
+    fn fast_policy() -> RetryPolicy {
+        RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(4),
+            reconnect_delay: Duration::from_millis(1),
+            fallback_after: 2,
+            poll_interval: Duration::from_millis(1),
+        }
+    }
+
+    /// Run until the script is exhausted, then shut down and collect events.
+    async fn run_script(source: Arc<MockSource>, expected_polls: usize) -> Vec<CoreEvent> {
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let task = tokio::spawn(run_with(
+            source.clone(),
+            fast_policy(),
+            event_tx,
+            shutdown_rx,
+            Arc::default(),
+        ));
+
+        while source.polled_ts.lock().unwrap().len() < expected_polls {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        shutdown_tx.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(1), task)
+            .await
+            .expect("runner stops on shutdown")
+            .unwrap();
+
+        let mut events = Vec::new();
+        while let Ok(event) = event_rx.try_recv() {
+            events.push(event);
+        }
+        events
+    }
+
+    #[tokio::test]
+    async fn test_forwards_updates_and_advances_ts() {
+        let source = Arc::new(MockSource::default().with_servers(1).then_poll(response(
+            serde_json::json!({
+                "ts": 5,
+                "updates": [[4, 10, 1, 42, 1700000000, "hi", {}]]
+            }),
+        )));
+
+        let events = run_script(source.clone(), 2).await;
+
+        assert!(matches!(
+            events[0],
+            CoreEvent::VkEvent(VkEvent::ConnectionStatus(true))
+        ));
+        assert!(matches!(
+            events[1],
+            CoreEvent::VkEvent(VkEvent::NewMessage { message_id: 10, .. })
+        ));
+        assert_eq!(*source.polled_ts.lock().unwrap(), vec!["0", "5"]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_key_reconnects_and_replays_missed() {
+        let source = Arc::new(
+            MockSource::default()
+                .with_servers(2)
+                .then_poll(response(serde_json::json!({ "failed": 2 }))),
+        );
+        source.missed.lock().unwrap().push(VkEvent::MessageRead {
+            peer_id: 42,
+            message_id: 7,
+        });
+
+        let events = run_script(source.clone(), 2).await;
+
+        assert!(matches!(events[1], CoreEvent::LongPollKeyExpired));
+        assert!(matches!(
+            events[2],
+            CoreEvent::VkEvent(VkEvent::MessageRead { message_id: 7, .. })
+        ));
+        assert_eq!(*source.polled_ts.lock().unwrap(), vec!["0", "100"]);
+    }
+
+    #[tokio::test]
+    async fn test_key_failure_storm_warns_about_concurrent_session() {
+        let source = MockSource::default().with_servers(5);
+        let source = (0..4).fold(source, |source, i| {
+            // Lost user info counts the same as an expired key
+            let failed = if i == 1 { 3 } else { 2 };
+            source.then_poll(response(serde_json::json!({ "failed": failed })))
+        });
+
+        let events = run_script(Arc::new(source), 5).await;
+
+        let expired = events
+            .iter()
+            .filter(|e| matches!(e, CoreEvent::LongPollKeyExpired))
+            .count();
+        let warnings: Vec<u32> = events
+            .iter()
+            .filter_map(|e| match e {
+                CoreEvent::PossibleConcurrentSession { recent_failures } => Some(*recent_failures),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(expired, 4);
+        assert_eq!(warnings, vec![3]);
+    }
+
+    #[tokio::test]
+    async fn test_single_key_failure_does_not_warn() {
+        let source = Arc::new(
+            MockSource::default()
+                .with_servers(3)
+                .then_poll(response(serde_json::json!({ "failed": 2 })))
+                .then_poll(response(serde_json::json!({ "failed": 1, "ts": 7 })))
+                .then_poll(response(serde_json::json!({ "failed": 4 }))),
+        );
+
+        let events = run_script(source, 4).await;
+
+        assert!(
+            !events
+                .iter()
+                .any(|e| matches!(e, CoreEvent::PossibleConcurrentSession { .. }))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_poll_error_backs_off_and_reconnects() {
+        let source = Arc::new(
+            MockSource::default()
+                .with_servers(2)
+                .then_poll(Err(vk_api::Error::UnexpectedResponse("boom".into()))),
+        );
+
+        let events = run_script(source.clone(), 2).await;
+
+        let statuses: Vec<bool> = events
+            .iter()
+            .filter_map(|e| match e {
+                CoreEvent::VkEvent(VkEvent::ConnectionStatus(up)) => Some(*up),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(statuses, vec![true, false, true]);
+        assert!(events.iter().any(|e| matches!(e, CoreEvent::Error { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_to_polling_conversations() {
+        let conversations = |message_id: i64| {
+            serde_json::json!({
+                "count": 1,
+                "items": [{
+                    "conversation": { "peer": { "id": 42, "type": "user" } },
+                    "last_message": { "id": message_id, "peer_id": 42, "from_id": 42 },
+                }],
+            })
+        };
+        let boom = || Err(vk_api::Error::UnexpectedResponse("boom".into()));
+        let source = Arc::new(
+            MockSource::default()
+                .with_servers(4)
+                .then_poll(boom())
+                .then_poll(boom())
+                .then_poll(boom())
+                .then_poll(response(serde_json::json!({ "ts": 9, "updates": [] })))
+                .then_conversations(conversations(10))
+                .then_conversations(conversations(11)),
+        );
+
+        let events = run_script(source, 5).await;
+
+        let degraded = events
+            .iter()
+            .position(|e| matches!(e, CoreEvent::LongPollDegraded))
+            .expect("switches to polling");
+        let after: Vec<_> = events[degraded + 1..].iter().collect();
+        assert_eq!(after.len(), 2, "{:?}", after);
+        assert!(matches!(
+            after[0],
+            CoreEvent::VkEvent(VkEvent::NewMessage { message_id: 11, .. })
+        ));
+        assert!(matches!(
+            after[1],
+            CoreEvent::VkEvent(VkEvent::ConnectionStatus(true))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_requested_reconnect_cuts_poll_short() {
+        let source = Arc::new(MockSource::default().with_servers(2));
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let reconnect = Arc::new(Notify::new());
+        let task = tokio::spawn(run_with(
+            source.clone(),
+            fast_policy(),
+            event_tx,
+            shutdown_rx,
+            reconnect.clone(),
+        ));
+        let wait_for_polls = |count: usize| {
+            let source = source.clone();
+            async move {
+                while source.polled_ts.lock().unwrap().len() < count {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+            }
+        };
+
+        // The first poll never answers
+        wait_for_polls(1).await;
+        reconnect.notify_one();
+        wait_for_polls(2).await;
+        shutdown_tx.send(true).unwrap();
+        task.await.unwrap();
+
+        assert_eq!(*source.polled_ts.lock().unwrap(), vec!["0", "100"]);
+        let mut states = Vec::new();
+        while let Ok(event) = event_rx.try_recv() {
+            match event {
+                CoreEvent::VkEvent(VkEvent::ConnectionStatus(up)) => states.push(up.to_string()),
+                CoreEvent::LongPollReconnecting { attempt } => states.push(attempt.to_string()),
+                _ => {}
+            }
+        }
+        assert_eq!(states, ["true", "1", "true"]);
+    }
+
+    #[tokio::test]
+    async fn test_stops_when_sender_dropped() {
+        let source = Arc::new(MockSource::default().with_servers(1));
+        let (event_tx, _event_rx) = mpsc::unbounded_channel();
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let task = tokio::spawn(run_with(
+            source,
+            fast_policy(),
+            event_tx,
+            shutdown_rx,
+            Arc::default(),
+        ));
+
+        drop(shutdown_tx);
+
+        tokio::time::timeout(Duration::from_secs(1), task)
+            .await
+            .expect("runner stops when the session is dropped")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_auth_failure_on_start() {
+        let source = MockSource::default();
+        source
+            .servers
+            .lock()
+            .unwrap()
+            .push_back(Err(vk_api::Error::Auth));
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        run_with(source, fast_policy(), event_tx, shutdown_rx, Arc::default()).await;
+
+        assert!(matches!(event_rx.try_recv(), Ok(CoreEvent::AuthExpired)));
+    }
+}
@@ -1,10 +1,14 @@
 //! Mappers to convert VK API types to domain models.
 
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use crate::models::{
-    AttachmentInfo, AttachmentKind, ChatMessage, DeliveryStatus, ForwardItem, ReplyPreview,
+    AttachmentInfo, AttachmentKind, ChatKind, ChatMember, ChatMessage, DeliveryStatus, ForwardItem,
+    MessageKind, MessageReaders, ProfileDetails, ReactionCount, ReplyPreview, ServiceAction,
+    preview_sender, preview_text,
 };
-use vk_api::Message;
-use vk_api::User;
+use vk_api::{ConversationItem, ConversationMembersResponse, Group, ReadPeersResponse, User};
+use vk_api::{Message, MessageAction};
 
 /// Map VK API attachment to domain model.
 pub fn map_attachment(att: vk_api::Attachment) -> AttachmentInfo {
@@ -27,28 +31,7 @@ pub fn map_attachment(att: vk_api::Attachment) -> AttachmentInfo {
                 })
                 .map(|(url, _)| url);
 
-            // Get medium quality for thumbnail (around 400-600px width)
-            let thumbnail = photo
-                .and_then(|p| {
-                    p.sizes
-                        .iter()
-                        .filter_map(|s| {
-                            s.url.as_ref().map(|url| {
-                                let width = s.width.unwrap_or(0);
-                                // Prefer images around 400-600px for thumbnail
-                                let score_diff = if width >= 400 && width <= 600 {
-                                    0 // Perfect match
-                                } else if width < 400 {
-                                    400 - width // Smaller is worse
-                                } else {
-                                    width - 600 // Larger is worse
-                                };
-                                (url.clone(), score_diff)
-                            })
-                        })
-                        .min_by_key(|(_, score)| *score)
-                        .map(|(url, _)| url)
-                });
+            let thumbnail = photo.and_then(|p| thumbnail_size(&p.sizes));
 
             AttachmentInfo {
                 kind: AttachmentKind::Photo,
@@ -57,6 +40,8 @@ pub fn map_attachment(att: vk_api::Attachment) -> AttachmentInfo {
                 thumbnail_url: thumbnail,
                 size: None,
                 subtitle: None,
+                description: None,
+                target_path: None,
             }
         }
         "doc" => {
@@ -68,6 +53,8 @@ pub fn map_attachment(att: vk_api::Attachment) -> AttachmentInfo {
                 thumbnail_url: None,
                 size: doc.size,
                 subtitle: doc.extension,
+                description: None,
+                target_path: None,
             }
         }
         "link" => {
@@ -77,17 +64,29 @@ pub fn map_attachment(att: vk_api::Attachment) -> AttachmentInfo {
                 .and_then(|v| v.as_str())
                 .unwrap_or("Link")
                 .to_string();
-            let url = link
-                .and_then(|o| o.get("url"))
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
+            let text = |key: &str| {
+                link.and_then(|o| o.get(key))
+                    .and_then(|v| v.as_str())
+                    .filter(|s| !s.trim().is_empty())
+                    .map(|s| s.to_string())
+            };
+            // The preview photo comes as a plain photo object
+            let preview = link
+                .and_then(|o| o.get("photo"))
+                .and_then(|p| p.get("sizes"))
+                .and_then(|sizes| {
+                    serde_json::from_value::<Vec<vk_api::PhotoSize>>(sizes.clone()).ok()
+                })
+                .and_then(|sizes| thumbnail_size(&sizes));
             AttachmentInfo {
                 kind: AttachmentKind::Link,
                 title,
-                url,
-                thumbnail_url: None,
+                url: text("url"),
+                thumbnail_url: preview,
                 size: None,
-                subtitle: None,
+                subtitle: text("caption"),
+                description: text("description"),
+                target_path: None,
             }
         }
         "audio" => {
@@ -108,10 +107,12 @@ pub fn map_attachment(att: vk_api::Attachment) -> AttachmentInfo {
             AttachmentInfo {
                 kind: AttachmentKind::Audio,
                 title: full_title,
-                url: None,
+                url: att.web_url(),
                 thumbnail_url: None,
                 size: None,
                 subtitle: None,
+                description: None,
+                target_path: None,
             }
         }
         "sticker" => AttachmentInfo {
@@ -136,18 +137,43 @@ pub fn map_attachment(att: vk_api::Attachment) -> AttachmentInfo {
             thumbnail_url: None,
             size: None,
             subtitle: None,
+            description: None,
+            target_path: None,
         },
         other => AttachmentInfo {
             kind: AttachmentKind::Other(other.to_string()),
             title: other.to_string(),
-            url: None,
+            url: att.web_url(),
             thumbnail_url: None,
             size: None,
             subtitle: None,
+            description: None,
+            target_path: None,
         },
     }
 }
 
+/// URL of the size closest to 400-600px wide, for thumbnails and previews.
+fn thumbnail_size(sizes: &[vk_api::PhotoSize]) -> Option<String> {
+    sizes
+        .iter()
+        .filter_map(|s| {
+            s.url.as_ref().map(|url| {
+                let width = s.width.unwrap_or(0);
+                let score_diff = if (400..=600).contains(&width) {
+                    0 // Perfect match
+                } else if width < 400 {
+                    400 - width // Smaller is worse
+                } else {
+                    width - 600 // Larger is worse
+                };
+                (url.clone(), score_diff)
+            })
+        })
+        .min_by_key(|(_, score)| *score)
+        .map(|(url, _)| url)
+}
+
 /// Map VK API reply message to domain model.
 pub fn map_reply(profiles: &[User], r: &Message) -> ReplyPreview {
     let attachments = r
@@ -164,6 +190,8 @@ pub fn map_reply(profiles: &[User], r: &Message) -> ReplyPreview {
             r.text.clone()
         },
         attachments,
+        message_id: Some(r.id).filter(|&id| id > 0),
+        cmid: r.conversation_message_id,
     }
 }
 
@@ -205,10 +233,12 @@ pub fn map_history_message(profiles: &[User], msg: &Message, out_read: i64) -> C
     } else {
         msg.is_read()
     };
-    let text = if msg.text.is_empty() {
-        "[attachment]".to_string()
-    } else {
-        msg.text.clone()
+    let action = msg.action.as_ref().map(map_service_action);
+    let kind = MessageKind::of(action.as_ref(), &attachment_types(msg));
+    let text = match &action {
+        Some(action) => action.describe(msg.from_id, |id| get_name(profiles, id)),
+        None if msg.text.is_empty() => "[attachment]".to_string(),
+        None => msg.text.clone(),
     };
     let attachments = msg
         .attachments
@@ -227,9 +257,12 @@ pub fn map_history_message(profiles: &[User], msg: &Message, out_read: i64) -> C
     ChatMessage {
         id: msg.id,
         cmid: msg.conversation_message_id,
+        random_id: msg.random_id.filter(|id| *id != 0),
         from_id: msg.from_id,
         from_name,
+        from_photo: get_photo(profiles, msg.from_id),
         text,
+        kind,
         timestamp: msg.date,
         is_outgoing,
         is_read,
@@ -240,9 +273,219 @@ pub fn map_history_message(profiles: &[User], msg: &Message, out_read: i64) -> C
         reply,
         fwd_count,
         forwards,
+        reactions: map_reactions(msg),
+        upload: None,
+    }
+}
+
+/// Map the reaction counters of a message, marking the current user's one.
+pub fn map_reactions(msg: &Message) -> Vec<ReactionCount> {
+    msg.reactions
+        .iter()
+        .map(|r| ReactionCount {
+            reaction_id: r.reaction_id,
+            count: r.count,
+            mine: msg.reaction_id == Some(r.reaction_id),
+        })
+        .collect()
+}
+
+/// Avatar of a conversation: the group chat photo, or the peer's own photo.
+pub fn conversation_photo(
+    item: &ConversationItem,
+    profiles: &[User],
+    groups: &[Group],
+) -> Option<String> {
+    let peer_id = item.conversation.peer.id;
+    if let Some(settings) = &item.conversation.chat_settings {
+        return settings
+            .photo
+            .as_ref()
+            .and_then(|p| p.photo_50.clone().or_else(|| p.photo_100.clone()));
+    }
+    if peer_id < 0 {
+        return groups
+            .iter()
+            .find(|g| g.id == -peer_id)
+            .and_then(|g| g.photo_50.clone());
+    }
+    get_photo(profiles, peer_id)
+}
+
+/// Whether notifications of a conversation are off right now.
+pub fn conversation_muted(item: &ConversationItem) -> bool {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    item.conversation
+        .push_settings
+        .as_ref()
+        .is_some_and(|p| p.is_muted_at(now))
+}
+
+/// Whether messages can be sent to a conversation, and VK's reason code
+/// when they cannot.
+pub fn conversation_write_access(item: &ConversationItem) -> (bool, Option<i32>) {
+    match &item.conversation.can_write {
+        Some(can_write) if !can_write.allowed => (false, can_write.reason),
+        _ => (true, None),
     }
 }
 
+/// Whether a conversation is a DM, a community or a group chat, with the
+/// member count of a group chat.
+pub fn conversation_kind(item: &ConversationItem) -> ChatKind {
+    let conversation = &item.conversation;
+    match conversation.peer.peer_type.as_str() {
+        "chat" => ChatKind::Chat {
+            member_count: conversation
+                .chat_settings
+                .as_ref()
+                .and_then(|s| s.members_count),
+        },
+        "group" => ChatKind::Group,
+        "user" => ChatKind::User,
+        _ => ChatKind::of_peer(conversation.peer.id),
+    }
+}
+
+/// When the user of a DM was last online, if `profiles` has it.
+pub fn conversation_last_seen(item: &ConversationItem, profiles: &[User]) -> Option<i64> {
+    let peer_id = item.conversation.peer.id;
+    profiles
+        .iter()
+        .find(|u| u.id == peer_id)
+        .and_then(|u| u.last_seen.as_ref())
+        .map(|l| l.time)
+}
+
+/// Map a user fetched with profile fields to a profile card.
+pub fn map_user_profile(user: &User) -> ProfileDetails {
+    ProfileDetails {
+        id: user.id,
+        name: user.full_name(),
+        screen_name: user.screen_name.clone(),
+        photo_url: user.photo_200.clone().or_else(|| user.photo_100.clone()),
+        is_online: user.is_online(),
+        last_seen: user.last_seen.as_ref().map(|l| l.time),
+        city: user.city.as_ref().map(|c| c.title.clone()),
+        status: user.status.clone().filter(|s| !s.is_empty()),
+        bdate: user.bdate.clone(),
+        members_count: None,
+    }
+}
+
+/// Map a community to a profile card; its id becomes negative like a peer id.
+pub fn map_group_profile(group: &Group) -> ProfileDetails {
+    ProfileDetails {
+        id: -group.id,
+        name: group.name.clone(),
+        screen_name: Some(group.screen_name.clone()),
+        photo_url: group.photo_200.clone().or_else(|| group.photo_100.clone()),
+        is_online: false,
+        last_seen: None,
+        city: None,
+        status: group.status.clone().filter(|s| !s.is_empty()),
+        bdate: None,
+        members_count: group.members_count,
+    }
+}
+
+/// Map a page of chat members, taking names from the response profiles.
+pub fn map_chat_members(response: &ConversationMembersResponse) -> Vec<ChatMember> {
+    response
+        .items
+        .iter()
+        .map(|member| ChatMember {
+            id: member.member_id,
+            name: member_name(&response.profiles, &response.groups, member.member_id),
+            is_admin: member.is_admin || member.is_owner,
+            is_owner: member.is_owner,
+        })
+        .collect()
+}
+
+/// Map the readers of a message, taking names from the response profiles.
+pub fn map_read_peers(peer_id: i64, cmid: i64, response: &ReadPeersResponse) -> MessageReaders {
+    MessageReaders {
+        peer_id,
+        cmid,
+        count: response.count,
+        readers: response
+            .items
+            .iter()
+            .map(|id| (*id, member_name(&response.profiles, &response.groups, *id)))
+            .collect(),
+    }
+}
+
+/// Map the service action of a message.
+pub fn map_service_action(action: &MessageAction) -> ServiceAction {
+    ServiceAction {
+        kind: action.action_type.clone(),
+        member_id: action.member_id,
+        text: action.text.clone(),
+    }
+}
+
+/// Chat list preview of a conversation's last message.
+///
+/// Service messages and messages with only attachments have no text; they
+/// are described instead ("Ann Lee joined the chat", "[photo]").
+pub fn message_preview(msg: &Message, profiles: &[User], groups: &[Group]) -> String {
+    let action = msg.action.as_ref().map(map_service_action);
+    preview_text(
+        &msg.text,
+        action.as_ref(),
+        &attachment_types(msg),
+        msg.from_id,
+        |id| member_name(profiles, groups, id),
+    )
+}
+
+/// Sender named in the chat list preview of a conversation's last message.
+pub fn message_sender(msg: &Message, profiles: &[User], groups: &[Group]) -> Option<String> {
+    preview_sender(msg.peer_id, msg.out == Some(1), msg.from_id, |id| {
+        member_name(profiles, groups, id)
+    })
+}
+
+/// What a conversation's last message holds, for its preview.
+pub fn message_kind(msg: &Message) -> MessageKind {
+    let action = msg.action.as_ref().map(map_service_action);
+    MessageKind::of(action.as_ref(), &attachment_types(msg))
+}
+
+/// Attachment types of a message; `fwd` for forwarded messages.
+fn attachment_types(msg: &Message) -> Vec<String> {
+    let mut types: Vec<String> = msg
+        .attachments
+        .iter()
+        .map(|a| a.attachment_type.clone())
+        .collect();
+    if !msg.fwd_messages.is_empty() {
+        types.push("fwd".to_string());
+    }
+    types
+}
+
+/// Name of a user, or of a community for negative ids.
+fn member_name(profiles: &[User], groups: &[Group], id: i64) -> String {
+    groups
+        .iter()
+        .find(|g| id < 0 && g.id == -id)
+        .map(|g| g.name.clone())
+        .unwrap_or_else(|| get_name(profiles, id))
+}
+
+/// Get user avatar URL from profiles.
+fn get_photo(profiles: &[User], user_id: i64) -> Option<String> {
+    profiles
+        .iter()
+        .find(|u| u.id == user_id)
+        .and_then(|u| u.photo_50.clone())
+}
+
 /// Get user name from profiles or generate placeholder.
 fn get_name(profiles: &[User], user_id: i64) -> String {
     profiles
@@ -257,3 +500,325 @@ fn get_name(profiles: &[User], user_id: i64) -> String {
             }
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Chat, fill_missing_times};
+    use vk_api::ConversationsResponse;
+    use vk_api::schema::from_value_strict;
+
+    const CHAT_PEER: i64 = 2_000_000_001;
+
+    fn conversations(last_messages: serde_json::Value) -> ConversationsResponse {
+        let items: Vec<_> = last_messages
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|last| {
+                serde_json::json!({
+                    "conversation": {
+                        "peer": {"id": last["peer_id"], "type": "chat", "local_id": 1},
+                        "chat_settings": {"title": "Trip"}
+                    },
+                    "last_message": last
+                })
+            })
+            .collect();
+        from_value_strict(serde_json::json!({
+            "count": items.len(),
+            "items": items,
+            "profiles": [
+                {"id": 1, "first_name": "Ann", "last_name": "Lee"},
+                {"id": 2, "first_name": "Bob", "last_name": "Ray"}
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn previews(response: &ConversationsResponse) -> Vec<String> {
+        response
+            .items
+            .iter()
+            .map(|item| message_preview(&item.last_message, &response.profiles, &response.groups))
+            .collect()
+    }
+
+    #[test]
+    fn test_service_message_last() {
+        let response = conversations(serde_json::json!([
+            {
+                "id": 10, "from_id": 2, "peer_id": CHAT_PEER, "date": 1_700_000_000, "text": "",
+                "action": {"type": "chat_invite_user", "member_id": 2}
+            },
+            {
+                "id": 11, "from_id": 1, "peer_id": CHAT_PEER + 1, "date": 1_700_000_000,
+                "action": {"type": "chat_invite_user", "member_id": 2}
+            }
+        ]));
+
+        assert_eq!(
+            previews(&response),
+            vec!["Bob Ray joined the chat", "Ann Lee invited Bob Ray"]
+        );
+    }
+
+    #[test]
+    fn test_service_message_in_history() {
+        let response = conversations(serde_json::json!([{
+            "id": 10, "from_id": 1, "peer_id": CHAT_PEER, "date": 1_700_000_000, "text": "",
+            "action": {"type": "chat_kick_user", "member_id": 2}
+        }]));
+
+        let msg = map_history_message(&response.profiles, &response.items[0].last_message, 0);
+        assert_eq!(msg.kind, MessageKind::Service);
+        assert!(msg.is_service());
+        assert_eq!(msg.text, "Ann Lee removed Bob Ray");
+    }
+
+    #[test]
+    fn test_photo_only_last() {
+        let response = conversations(serde_json::json!([{
+            "id": 12, "from_id": 1, "peer_id": CHAT_PEER, "date": 1_700_000_000, "text": "",
+            "attachments": [
+                {"type": "photo", "photo": {"id": 1, "owner_id": 1, "sizes": []}},
+                {"type": "photo", "photo": {"id": 2, "owner_id": 1, "sizes": []}}
+            ]
+        }]));
+
+        assert_eq!(previews(&response), vec!["📷 Photo"]);
+        let msg = &response.items[0].last_message;
+        assert_eq!(message_kind(msg), MessageKind::Photo);
+        assert_eq!(
+            message_sender(msg, &response.profiles, &response.groups).as_deref(),
+            Some("Ann Lee")
+        );
+    }
+
+    #[test]
+    fn test_wall_post_opens_but_does_not_download() {
+        let att: vk_api::Attachment = serde_json::from_value(serde_json::json!({
+            "type": "wall", "wall": {"id": 42, "owner_id": -1234}
+        }))
+        .unwrap();
+        let info = map_attachment(att);
+        assert_eq!(info.url.as_deref(), Some("https://vk.com/wall-1234_42"));
+        assert!(!info.is_downloadable());
+    }
+
+    #[test]
+    fn test_link_keeps_preview() {
+        let att: vk_api::Attachment = serde_json::from_value(serde_json::json!({
+            "type": "link",
+            "link": {
+                "url": "https://example.com/post",
+                "title": "A post",
+                "caption": "example.com",
+                "description": "What the post is about",
+                "photo": {"id": 1, "owner_id": 2, "sizes": [
+                    {"url": "https://img/s", "width": 130, "height": 80},
+                    {"url": "https://img/m", "width": 510, "height": 300},
+                    {"url": "https://img/l", "width": 1080, "height": 640}
+                ]}
+            }
+        }))
+        .unwrap();
+        let info = map_attachment(att);
+        assert_eq!(info.title, "A post");
+        assert_eq!(info.url.as_deref(), Some("https://example.com/post"));
+        assert_eq!(info.subtitle.as_deref(), Some("example.com"));
+        assert_eq!(info.description.as_deref(), Some("What the post is about"));
+        assert_eq!(info.thumbnail_url.as_deref(), Some("https://img/m"));
+
+        let bare: vk_api::Attachment = serde_json::from_value(serde_json::json!({
+            "type": "link", "link": {"url": "https://example.com", "description": " "}
+        }))
+        .unwrap();
+        let info = map_attachment(bare);
+        assert_eq!(info.title, "Link");
+        assert_eq!(info.description, None);
+        assert_eq!(info.thumbnail_url, None);
+    }
+
+    #[test]
+    fn test_push_settings_mute() {
+        let response: ConversationsResponse = from_value_strict(serde_json::json!({
+            "count": 3,
+            "items": [
+                {"conversation": {
+                    "peer": {"id": 1, "type": "user", "local_id": 1},
+                    "push_settings": {"disabled_forever": true, "no_sound": true}
+                }},
+                {"conversation": {
+                    "peer": {"id": 2, "type": "user", "local_id": 2},
+                    "push_settings": {"disabled_until": 1_000, "disabled_forever": false}
+                }},
+                {"conversation": {"peer": {"id": 3, "type": "user", "local_id": 3}}}
+            ]
+        }))
+        .unwrap();
+
+        let muted: Vec<bool> = response.items.iter().map(conversation_muted).collect();
+        assert_eq!(muted, vec![true, false, false]);
+    }
+
+    #[test]
+    fn test_write_access() {
+        let response: ConversationsResponse = from_value_strict(serde_json::json!({
+            "count": 3,
+            "items": [
+                {"conversation": {
+                    "peer": {"id": 1, "type": "user", "local_id": 1},
+                    "can_write": {"allowed": false, "reason": 918}
+                }},
+                {"conversation": {
+                    "peer": {"id": 2, "type": "user", "local_id": 2},
+                    "can_write": {"allowed": true}
+                }},
+                {"conversation": {"peer": {"id": 3, "type": "user", "local_id": 3}}}
+            ]
+        }))
+        .unwrap();
+
+        let access: Vec<_> = response
+            .items
+            .iter()
+            .map(conversation_write_access)
+            .collect();
+        assert_eq!(access, vec![(false, Some(918)), (true, None), (true, None)]);
+    }
+
+    #[test]
+    fn test_conversation_kind_and_last_seen() {
+        let response: ConversationsResponse = from_value_strict(serde_json::json!({
+            "count": 3,
+            "items": [
+                {"conversation": {
+                    "peer": {"id": CHAT_PEER, "type": "chat", "local_id": 1},
+                    "chat_settings": {"title": "Weekend trip", "members_count": 23}
+                }},
+                {"conversation": {"peer": {"id": -5, "type": "group", "local_id": 5}}},
+                {"conversation": {"peer": {"id": 7, "type": "user", "local_id": 7}}}
+            ],
+            "profiles": [{
+                "id": 7, "first_name": "Ann", "last_name": "Lee",
+                "last_seen": {"time": 1_700_000_000, "platform": 7}
+            }]
+        }))
+        .unwrap();
+
+        let kinds: Vec<ChatKind> = response.items.iter().map(conversation_kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ChatKind::Chat {
+                    member_count: Some(23)
+                },
+                ChatKind::Group,
+                ChatKind::User
+            ]
+        );
+        let last_seen: Vec<Option<i64>> = response
+            .items
+            .iter()
+            .map(|item| conversation_last_seen(item, &response.profiles))
+            .collect();
+        assert_eq!(last_seen, vec![None, None, Some(1_700_000_000)]);
+    }
+
+    #[test]
+    fn test_read_peers_names() {
+        let response: ReadPeersResponse = from_value_strict(serde_json::json!({
+            "count": 3,
+            "items": [1, -5, 7],
+            "profiles": [{"id": 1, "first_name": "Ann", "last_name": "Lee"}],
+            "groups": [{"id": 5, "name": "Bot", "screen_name": "club5"}]
+        }))
+        .unwrap();
+
+        let readers = map_read_peers(CHAT_PEER, 10, &response);
+        let names: Vec<&str> = readers.readers.iter().map(|(_, n)| n.as_str()).collect();
+        assert_eq!(names, vec!["Ann Lee", "Bot", "User 7"]);
+        assert_eq!(readers.summary(), "Read by Ann Lee, Bot and 1 other");
+    }
+
+    #[test]
+    fn test_reactions_mark_mine() {
+        let msg: Message = from_value_strict(serde_json::json!({
+            "id": 5, "date": 0, "peer_id": CHAT_PEER, "reaction_id": 4,
+            "reactions": [
+                {"reaction_id": 1, "count": 2, "user_ids": [1, 2]},
+                {"reaction_id": 4, "count": 1, "user_ids": [7]}
+            ]
+        }))
+        .unwrap();
+
+        let message = map_history_message(&[], &msg, 0);
+        let mine: Vec<bool> = message.reactions.iter().map(|r| r.mine).collect();
+        assert_eq!(mine, vec![false, true]);
+        assert_eq!(
+            crate::models::format_reactions(&message.reactions),
+            "❤ 2  👍 1"
+        );
+    }
+
+    #[test]
+    fn test_reply_keeps_target_ids() {
+        let msg: Message = from_value_strict(serde_json::json!({
+            "id": 9, "date": 0, "peer_id": CHAT_PEER,
+            "reply_message": {"from_id": 1, "date": 0, "text": "hi", "conversation_message_id": 4}
+        }))
+        .unwrap();
+
+        let reply = map_history_message(&[], &msg, 0).reply.unwrap();
+        assert_eq!(reply.message_id, None);
+        assert_eq!(reply.cmid, Some(4));
+    }
+
+    #[test]
+    fn test_empty_conversation_keeps_vk_order() {
+        let response: ConversationsResponse = from_value_strict(serde_json::json!({
+            "count": 3,
+            "items": [
+                {
+                    "conversation": {"peer": {"id": 1, "type": "user", "local_id": 1}},
+                    "last_message": {"id": 1, "from_id": 1, "peer_id": 1, "date": 300}
+                },
+                {"conversation": {"peer": {"id": CHAT_PEER, "type": "chat", "local_id": 1}}},
+                {
+                    "conversation": {"peer": {"id": 2, "type": "user", "local_id": 2}},
+                    "last_message": {"id": 2, "from_id": 2, "peer_id": 2, "date": 200}
+                }
+            ]
+        }))
+        .unwrap();
+
+        let mut chats: Vec<Chat> = response
+            .items
+            .iter()
+            .map(|item| Chat {
+                id: item.conversation.peer.id,
+                title: String::new(),
+                last_message: message_preview(&item.last_message, &[], &[]),
+                last_message_from: None,
+                last_message_kind: MessageKind::Text,
+                last_message_time: item.last_message.date,
+                unread_count: 0,
+                is_online: false,
+                photo_url: None,
+                is_muted: false,
+                is_pinned: false,
+                can_write: true,
+                cannot_write_reason: None,
+                kind: ChatKind::User,
+                last_seen: None,
+            })
+            .collect();
+        fill_missing_times(&mut chats);
+        crate::models::sort_chats(&mut chats);
+
+        let ids: Vec<i64> = chats.iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![1, CHAT_PEER, 2]);
+        assert_eq!(chats[1].last_message, "");
+    }
+}
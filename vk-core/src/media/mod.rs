@@ -0,0 +1,152 @@
+//! Attachment counts of a chat for the chat info popup.
+//!
+//! VK reports no totals for conversation media, so attachments are counted
+//! by paging messages.getHistoryAttachments, at most [`COUNT_PAGES`] pages
+//! per kind. Larger counts are shown as "600+".
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use vk_api::VkClient;
+
+/// Attachments requested per page (VK maximum).
+pub const PAGE_SIZE: u32 = 200;
+
+/// Pages fetched per kind before the count is reported as a lower bound.
+pub const COUNT_PAGES: u32 = 3;
+
+/// Attachment kinds counted for a chat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MediaKind {
+    Photo,
+    Video,
+    Doc,
+    Link,
+}
+
+impl MediaKind {
+    pub const ALL: [MediaKind; 4] = [
+        MediaKind::Photo,
+        MediaKind::Video,
+        MediaKind::Doc,
+        MediaKind::Link,
+    ];
+
+    /// `media_type` of messages.getHistoryAttachments.
+    pub fn api_name(self) -> &'static str {
+        match self {
+            MediaKind::Photo => "photo",
+            MediaKind::Video => "video",
+            MediaKind::Doc => "doc",
+            MediaKind::Link => "link",
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MediaKind::Photo => "Photos",
+            MediaKind::Video => "Videos",
+            MediaKind::Doc => "Files",
+            MediaKind::Link => "Links",
+        }
+    }
+}
+
+/// Number of attachments of one kind; `more` when counting stopped early.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MediaCount {
+    pub count: u32,
+    pub more: bool,
+}
+
+impl fmt::Display for MediaCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.more {
+            write!(f, "{}+", self.count)
+        } else {
+            write!(f, "{}", self.count)
+        }
+    }
+}
+
+/// Media counts of a chat, in [`MediaKind::ALL`] order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatInfo {
+    pub peer_id: i64,
+    pub counts: Vec<(MediaKind, MediaCount)>,
+}
+
+impl ChatInfo {
+    pub fn count(&self, kind: MediaKind) -> MediaCount {
+        self.counts
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map(|(_, count)| *count)
+            .unwrap_or_default()
+    }
+
+    /// "Photos: 230, Videos: 0, Files: 12, Links: 95"
+    pub fn summary(&self) -> String {
+        self.counts
+            .iter()
+            .map(|(kind, count)| format!("{}: {}", kind.label(), count))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Count the attachments of every [`MediaKind`] in a chat.
+pub async fn load_chat_info(client: &VkClient, peer_id: i64) -> vk_api::Result<ChatInfo> {
+    let mut counts = Vec::with_capacity(MediaKind::ALL.len());
+    for kind in MediaKind::ALL {
+        counts.push((kind, count_media(client, peer_id, kind).await?));
+    }
+    Ok(ChatInfo { peer_id, counts })
+}
+
+async fn count_media(
+    client: &VkClient,
+    peer_id: i64,
+    kind: MediaKind,
+) -> vk_api::Result<MediaCount> {
+    let mut count = 0;
+    let mut start_from: Option<String> = None;
+    for _ in 0..COUNT_PAGES {
+        let page = client
+            .messages()
+            .get_history_attachments(peer_id, kind.api_name(), start_from.as_deref(), PAGE_SIZE)
+            .await?;
+        count += page.items.len() as u32;
+        start_from = page
+            .next_from
+            .filter(|next| !next.is_empty() && !page.items.is_empty());
+        if start_from.is_none() {
+            return Ok(MediaCount { count, more: false });
+        }
+    }
+    Ok(MediaCount { count, more: true })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(count: u32, more: bool) -> MediaCount {
+        MediaCount { count, more }
+    }
+
+    #[test]
+    fn test_summary() {
+        let info = ChatInfo {
+            peer_id: 2_000_000_001,
+            counts: vec![
+                (MediaKind::Photo, count(600, true)),
+                (MediaKind::Doc, count(12, false)),
+                (MediaKind::Link, count(95, false)),
+            ],
+        };
+
+        assert_eq!(info.summary(), "Photos: 600+, Files: 12, Links: 95");
+        assert_eq!(info.count(MediaKind::Video), MediaCount::default());
+    }
+}
@@ -1,6 +1,8 @@
 //! Attachment types for messages.
 
-use serde::{Serialize, Deserialize};
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
 
 /// Summary information about an attachment.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,6 +14,13 @@ pub struct AttachmentInfo {
     pub thumbnail_url: Option<String>,
     pub size: Option<u64>,
     pub subtitle: Option<String>,
+    /// Page description of a link preview
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Exact file to save it to, e.g. chosen in a Save As dialog; `None`
+    /// saves it to the download folder under its title
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_path: Option<PathBuf>,
 }
 
 /// Type of attachment.
@@ -24,3 +33,76 @@ pub enum AttachmentKind {
     Sticker,
     Other(String),
 }
+
+impl AttachmentInfo {
+    /// Whether `url` is the file itself. Audio and the kinds without a file
+    /// (wall posts, polls, market items...) link to their vk.com page,
+    /// which can be opened but not saved.
+    pub fn is_downloadable(&self) -> bool {
+        self.url.is_some() && !matches!(self.kind, AttachmentKind::Audio | AttachmentKind::Other(_))
+    }
+
+    /// Whether this link's URL is already written out in `text`, so it
+    /// need not be shown again under the message. Scheme, `www.` and a
+    /// trailing slash are ignored.
+    pub fn is_link_in(&self, text: &str) -> bool {
+        let Some(url) = self
+            .url
+            .as_deref()
+            .filter(|_| matches!(self.kind, AttachmentKind::Link))
+        else {
+            return false;
+        };
+        let url = bare_url(url);
+        text.split_whitespace()
+            .map(|word| word.trim_matches(LINK_PUNCTUATION))
+            .any(|word| bare_url(word) == url)
+    }
+}
+
+/// Brackets, quotes and punctuation around a link in message text.
+const LINK_PUNCTUATION: &[char] = &['(', ')', '<', '>', ',', '.', '!', '?', '"', '\''];
+
+/// `url` without scheme, `www.` and trailing slash.
+fn bare_url(url: &str) -> &str {
+    let url = url
+        .strip_prefix("https://")
+        .or_else(|| url.strip_prefix("http://"))
+        .unwrap_or(url);
+    let url = url.strip_prefix("www.").unwrap_or(url);
+    url.trim_end_matches('/')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(url: &str) -> AttachmentInfo {
+        AttachmentInfo {
+            kind: AttachmentKind::Link,
+            title: "Link".into(),
+            url: Some(url.into()),
+            thumbnail_url: None,
+            size: None,
+            subtitle: None,
+            description: None,
+            target_path: None,
+        }
+    }
+
+    #[test]
+    fn test_link_in_text() {
+        let att = link("https://www.example.com/post/");
+        assert!(att.is_link_in("look: https://www.example.com/post/"));
+        assert!(att.is_link_in("(example.com/post)."));
+        assert!(att.is_link_in("http://example.com/post"));
+        assert!(!att.is_link_in("https://example.com/post/2"));
+        assert!(!att.is_link_in("example.com"));
+
+        let doc = AttachmentInfo {
+            kind: AttachmentKind::Doc,
+            ..att
+        };
+        assert!(!doc.is_link_in("https://www.example.com/post/"));
+    }
+}
@@ -1,6 +1,8 @@
 //! Chat/conversation types.
 
-use serde::{Serialize, Deserialize};
+use serde::{Deserialize, Serialize};
+
+use super::{MessageKind, ServiceAction, presence};
 
 /// A chat/conversation in the list.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -8,7 +10,678 @@ pub struct Chat {
     pub id: i64,
     pub title: String,
     pub last_message: String,
+    /// Who wrote the last message: "You", or the sender in a group chat.
+    #[serde(default)]
+    pub last_message_from: Option<String>,
+    /// What the last message holds besides text.
+    #[serde(default)]
+    pub last_message_kind: MessageKind,
     pub last_message_time: i64,
     pub unread_count: u32,
     pub is_online: bool,
+    /// Avatar URL: the user's or community's photo, or the group chat photo.
+    #[serde(default)]
+    pub photo_url: Option<String>,
+    /// Notifications are off; muted chats do not count towards unread totals.
+    #[serde(default)]
+    pub is_muted: bool,
+    /// Pinned to the top of the list, above the chats sorted by activity.
+    #[serde(default)]
+    pub is_pinned: bool,
+    /// Messages can be sent here; VK says why not in `cannot_write_reason`.
+    #[serde(default = "can_write_by_default")]
+    pub can_write: bool,
+    /// VK's reason code when `can_write` is false, e.g. 918.
+    #[serde(default)]
+    pub cannot_write_reason: Option<i32>,
+    /// Whether this is a DM, a community or a group chat.
+    #[serde(default)]
+    pub kind: ChatKind,
+    /// When the user of a DM was last online (unix seconds), if shown.
+    #[serde(default)]
+    pub last_seen: Option<i64>,
+}
+
+fn can_write_by_default() -> bool {
+    true
+}
+
+/// Who is on the other side of a conversation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ChatKind {
+    /// Direct messages with a user.
+    #[default]
+    User,
+    /// Messages with a community.
+    Group,
+    /// Group chat; VK leaves out the member count for chats you left.
+    Chat { member_count: Option<u32> },
+}
+
+impl ChatKind {
+    /// Kind told by the peer id alone: group chats start past 2e9,
+    /// communities are negative.
+    pub fn of_peer(peer_id: i64) -> Self {
+        if vk_api::is_chat_peer(peer_id) {
+            Self::Chat { member_count: None }
+        } else if peer_id < 0 {
+            Self::Group
+        } else {
+            Self::User
+        }
+    }
+}
+
+impl Chat {
+    /// Chat list line for the last message: "You: ok", "Anna: 📷 Photo".
+    /// A service message names who did it already, so it gets no sender.
+    pub fn preview(&self) -> String {
+        match &self.last_message_from {
+            Some(from)
+                if self.last_message_kind != MessageKind::Service
+                    && !self.last_message.is_empty() =>
+            {
+                format!("{}: {}", from, self.last_message)
+            }
+            _ => self.last_message.clone(),
+        }
+    }
+
+    /// What the header says next to the title: "23 members", "online",
+    /// "last seen 5 min ago" or "community".
+    pub fn subtitle(&self, now: i64) -> Option<String> {
+        match self.kind {
+            ChatKind::User => presence(self.is_online, self.last_seen, now),
+            ChatKind::Group => Some("community".to_string()),
+            ChatKind::Chat {
+                member_count: Some(1),
+            } => Some("1 member".to_string()),
+            ChatKind::Chat {
+                member_count: Some(count),
+            } => Some(format!("{} members", count)),
+            ChatKind::Chat { member_count: None } => None,
+        }
+    }
+
+    /// Messages panel title: "Weekend trip — 23 members", or just the title
+    /// when there is nothing to add.
+    pub fn header(&self, now: i64) -> String {
+        match self.subtitle(now) {
+            Some(subtitle) => format!("{} — {}", self.title, subtitle),
+            None => self.title.clone(),
+        }
+    }
+
+    /// What to show instead of the input when nothing can be sent here.
+    pub fn write_denied(&self) -> Option<String> {
+        if self.can_write {
+            return None;
+        }
+        Some(match self.cannot_write_reason {
+            Some(reason) => format!(
+                "You can't send messages to this conversation (reason: {})",
+                reason
+            ),
+            None => "You can't send messages to this conversation".to_string(),
+        })
+    }
+}
+
+/// How long `:mute` silences a chat.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MuteDuration {
+    Seconds(u32),
+    Forever,
+}
+
+impl MuteDuration {
+    /// Parse `30m`, `1h`, `8h`, `2d` or `forever`.
+    pub fn parse(arg: &str) -> Result<Self, String> {
+        let arg = arg.trim().to_lowercase();
+        if arg == "forever" {
+            return Ok(MuteDuration::Forever);
+        }
+
+        let invalid = || {
+            format!(
+                "Invalid duration '{}': use e.g. 30m, 1h, 8h, 2d or forever",
+                arg
+            )
+        };
+        let unit = arg.chars().last().ok_or_else(invalid)?;
+        let scale = match unit {
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        let amount: u32 = arg[..arg.len() - 1].parse().map_err(|_| invalid())?;
+        match amount.checked_mul(scale) {
+            Some(0) => Err(invalid()),
+            Some(seconds) => Ok(MuteDuration::Seconds(seconds)),
+            None => Err(format!("Duration '{}' is too long, use forever", arg)),
+        }
+    }
+
+    /// `time` of account.setSilenceMode.
+    pub fn api_time(self) -> i64 {
+        match self {
+            MuteDuration::Seconds(seconds) => seconds as i64,
+            MuteDuration::Forever => -1,
+        }
+    }
+}
+
+/// Chat members loaded per request; VK returns at most 200.
+pub const CHAT_MEMBERS_PAGE: u32 = 200;
+
+/// Member of a group chat.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatMember {
+    /// User id, or negative community id for bots.
+    pub id: i64,
+    pub name: String,
+    /// Admins include the owner.
+    pub is_admin: bool,
+    pub is_owner: bool,
+}
+
+/// Sort chats by last activity, newest first, with pinned chats on top.
+///
+/// The sort is stable, so chats with the same time keep their order.
+pub fn sort_chats(chats: &mut [Chat]) {
+    use std::cmp::Reverse;
+    chats.sort_by_key(|c| (Reverse(c.is_pinned), Reverse(c.last_message_time)));
+}
+
+/// Readable reason messages.createChat failed, for the errors users can act on.
+///
+/// Permission errors are the token's missing rights here, not an expired
+/// session, so they must not send the user back to the login screen.
+pub fn create_chat_error(e: &vk_api::Error) -> Option<&'static str> {
+    use vk_api::error::{ERROR_ACCESS_DENIED, ERROR_INVALID_PARAM, ERROR_PERMISSION_DENIED};
+
+    match e.code()? {
+        ERROR_PERMISSION_DENIED | ERROR_ACCESS_DENIED => {
+            Some("Not allowed to create chats with this token")
+        }
+        ERROR_INVALID_PARAM => Some("Select at least one user for the chat"),
+        _ => None,
+    }
+}
+
+/// Why changing the title or photo of a chat is refused.
+const CHANGE_INFO_DENIED: &str = "Only chat admins can change the title and photo of this chat";
+
+/// Readable reason messages.editChat or messages.setChatPhoto failed when
+/// the user may not change the chat.
+pub fn rename_chat_error(e: &vk_api::Error) -> Option<&'static str> {
+    use vk_api::error::{ERROR_ACCESS_DENIED, ERROR_PERMISSION_DENIED};
+
+    match e.code()? {
+        ERROR_PERMISSION_DENIED | ERROR_ACCESS_DENIED => Some(CHANGE_INFO_DENIED),
+        _ => None,
+    }
+}
+
+/// Readable reason messages.removeChatUser failed when the user leaves a
+/// chat: VK refuses for chats the user can't leave, such as one they were
+/// already removed from.
+pub fn leave_chat_error(e: &vk_api::Error) -> Option<&'static str> {
+    use vk_api::error::{ERROR_ACCESS_DENIED, ERROR_PERMISSION_DENIED};
+
+    match e.code()? {
+        ERROR_PERMISSION_DENIED | ERROR_ACCESS_DENIED => Some("You can't leave this chat"),
+        _ => None,
+    }
+}
+
+/// Why the user may not change the title or photo of a group chat, judged
+/// by the `acl` of its settings. `None` when allowed, or when VK did not
+/// say; the API call then explains a refusal itself.
+pub fn change_chat_info_denied(conversation: &vk_api::Conversation) -> Option<&'static str> {
+    let acl = conversation.chat_settings.as_ref()?.acl.as_ref()?;
+    (!acl.can_change_info).then_some(CHANGE_INFO_DENIED)
+}
+
+/// Apply a service action that changes how a chat is listed, such as
+/// someone else renaming it. Returns whether a chat changed.
+pub fn apply_chat_action(chats: &mut [Chat], peer_id: i64, action: &ServiceAction) -> bool {
+    match (action.kind.as_str(), &action.text) {
+        ("chat_title_update", Some(title)) => rename_chat(chats, peer_id, title),
+        _ => false,
+    }
+}
+
+/// Set the title of a chat in the list. Returns false if it is not loaded.
+pub fn rename_chat(chats: &mut [Chat], peer_id: i64, title: &str) -> bool {
+    match chats.iter_mut().find(|c| c.id == peer_id) {
+        Some(chat) => {
+            chat.title = title.to_string();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Set the avatar of a chat in the list. Returns false if it is not loaded.
+pub fn set_chat_photo(chats: &mut [Chat], peer_id: i64, photo_url: &str) -> bool {
+    match chats.iter_mut().find(|c| c.id == peer_id) {
+        Some(chat) => {
+            chat.photo_url = Some(photo_url.to_string());
+            true
+        }
+        None => false,
+    }
+}
+
+/// Give chats without a last message date a place in VK's order.
+///
+/// Conversations arrive sorted by VK. An empty chat, or one whose last
+/// message has no date, takes the time of the chat before it, so that
+/// [`sort_chats`] keeps it where VK put it instead of moving it to the end.
+pub fn fill_missing_times(chats: &mut [Chat]) {
+    let mut previous = None;
+    for chat in chats.iter_mut() {
+        if chat.last_message_time == 0
+            && let Some(time) = previous
+        {
+            chat.last_message_time = time;
+        }
+        previous = Some(chat.last_message_time);
+    }
+}
+
+/// Show a new message in the chat list and move its chat to its place.
+///
+/// `text` is the preview from [`preview_text`](super::preview_text), with
+/// `from` and `kind` as [`Chat::preview`] takes them. `unread` bumps the
+/// unread counter (incoming message in a chat that is not open). Returns
+/// `false` if the chat is not loaded.
+pub fn record_new_message(
+    chats: &mut [Chat],
+    peer_id: i64,
+    text: &str,
+    from: Option<String>,
+    kind: MessageKind,
+    timestamp: i64,
+    unread: bool,
+) -> bool {
+    let Some(chat) = chats.iter_mut().find(|c| c.id == peer_id) else {
+        return false;
+    };
+
+    // An older message (e.g. replayed after a reconnect) keeps the preview
+    // of the newer one; one from the same second is the later to arrive
+    if timestamp >= chat.last_message_time {
+        chat.last_message = text.to_string();
+        chat.last_message_from = from;
+        chat.last_message_kind = kind;
+        chat.last_message_time = timestamp;
+    }
+    if unread {
+        chat.unread_count += 1;
+    }
+    sort_chats(chats);
+    true
+}
+
+/// Take a chat out of the list, e.g. after deleting the conversation.
+pub fn remove_chat(chats: &mut Vec<Chat>, peer_id: i64) -> Option<Chat> {
+    let index = chats.iter().position(|c| c.id == peer_id)?;
+    Some(chats.remove(index))
+}
+
+/// Put back a chat taken out by [`remove_chat`] when a new message arrives
+/// in it. Its history is gone, so the preview and unread count start empty.
+pub fn restore_chat(chats: &mut Vec<Chat>, mut chat: Chat) {
+    if chats.iter().any(|c| c.id == chat.id) {
+        return;
+    }
+    chat.last_message.clear();
+    chat.last_message_from = None;
+    chat.last_message_kind = MessageKind::Text;
+    chat.unread_count = 0;
+    chats.push(chat);
+    sort_chats(chats);
+}
+
+/// Mark a chat muted or unmuted. Returns false if it is not loaded.
+pub fn set_chat_muted(chats: &mut [Chat], peer_id: i64, muted: bool) -> bool {
+    match chats.iter_mut().find(|c| c.id == peer_id) {
+        Some(chat) => {
+            chat.is_muted = muted;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Pin a chat to the top or unpin it, moving it to its place. Returns
+/// false if it is not loaded.
+pub fn set_chat_pinned(chats: &mut [Chat], peer_id: i64, pinned: bool) -> bool {
+    let Some(chat) = chats.iter_mut().find(|c| c.id == peer_id) else {
+        return false;
+    };
+    chat.is_pinned = pinned;
+    sort_chats(chats);
+    true
+}
+
+/// Total number of unread messages across loaded chats, muted ones aside.
+pub fn total_unread(chats: &[Chat]) -> u32 {
+    chats
+        .iter()
+        .filter(|c| !c.is_muted)
+        .map(|c| c.unread_count)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn chat(id: i64, last_message_time: i64) -> Chat {
+        Chat {
+            last_message_time,
+            ..fixtures::chat(id)
+        }
+    }
+
+    fn ids(chats: &[Chat]) -> Vec<i64> {
+        chats.iter().map(|c| c.id).collect()
+    }
+
+    #[test]
+    fn test_new_message_moves_chat_to_top() {
+        let mut chats = vec![chat(1, 300), chat(2, 200), chat(3, 100)];
+
+        assert!(record_new_message(
+            &mut chats,
+            3,
+            "hey",
+            None,
+            MessageKind::Text,
+            400,
+            true
+        ));
+
+        assert_eq!(ids(&chats), vec![3, 1, 2]);
+        assert_eq!(chats[0].last_message, "hey");
+        assert_eq!(chats[0].unread_count, 1);
+        assert_eq!(total_unread(&chats), 1);
+    }
+
+    #[test]
+    fn test_preview_names_sender() {
+        let mut chats = vec![chat(1, 300)];
+        let you = || Some("You".to_string());
+
+        record_new_message(&mut chats, 1, "ok", you(), MessageKind::Text, 400, false);
+        assert_eq!(chats[0].preview(), "You: ok");
+
+        let photo = MessageKind::Photo;
+        record_new_message(
+            &mut chats,
+            1,
+            "📷 Photo",
+            Some("Anna".into()),
+            photo,
+            500,
+            true,
+        );
+        assert_eq!(chats[0].preview(), "Anna: 📷 Photo");
+
+        let voice = MessageKind::Voice;
+        record_new_message(&mut chats, 1, "🎤 Voice message", None, voice, 600, true);
+        assert_eq!(chats[0].preview(), "🎤 Voice message");
+
+        let service = MessageKind::Service;
+        record_new_message(
+            &mut chats,
+            1,
+            "Anna left the chat",
+            you(),
+            service,
+            700,
+            false,
+        );
+        assert_eq!(chats[0].preview(), "Anna left the chat");
+    }
+
+    #[test]
+    fn test_header() {
+        let now = 1_700_000_000;
+        let mut dm = chat(1, 100);
+        dm.title = "Ann Lee".into();
+        assert_eq!(dm.header(now), "Ann Lee");
+        dm.last_seen = Some(now - 300);
+        assert_eq!(dm.header(now), "Ann Lee — last seen 5 min ago");
+        dm.is_online = true;
+        assert_eq!(dm.header(now), "Ann Lee — online");
+
+        let mut community = chat(-5, 100);
+        community.kind = ChatKind::Group;
+        assert_eq!(community.header(now), "Chat -5 — community");
+
+        let mut group = chat(2_000_000_001, 100);
+        group.kind = ChatKind::Chat {
+            member_count: Some(23),
+        };
+        assert_eq!(group.header(now), "Chat 2000000001 — 23 members");
+        group.kind = ChatKind::Chat {
+            member_count: Some(1),
+        };
+        assert_eq!(group.header(now), "Chat 2000000001 — 1 member");
+        group.kind = ChatKind::Chat { member_count: None };
+        assert_eq!(group.header(now), "Chat 2000000001");
+    }
+
+    #[test]
+    fn test_kind_of_peer() {
+        assert_eq!(ChatKind::of_peer(1), ChatKind::User);
+        assert_eq!(ChatKind::of_peer(-5), ChatKind::Group);
+        assert_eq!(
+            ChatKind::of_peer(2_000_000_001),
+            ChatKind::Chat { member_count: None }
+        );
+    }
+
+    #[test]
+    fn test_write_denied() {
+        let mut read_only = chat(1, 100);
+        assert_eq!(read_only.write_denied(), None);
+
+        read_only.can_write = false;
+        read_only.cannot_write_reason = Some(918);
+        assert_eq!(
+            read_only.write_denied().as_deref(),
+            Some("You can't send messages to this conversation (reason: 918)")
+        );
+
+        // Cached before the field existed: writable
+        let mut value = serde_json::to_value(chat(2, 100)).unwrap();
+        let fields = value.as_object_mut().unwrap();
+        fields.remove("can_write");
+        fields.remove("cannot_write_reason");
+        let cached: Chat = serde_json::from_value(value).unwrap();
+        assert!(cached.can_write);
+    }
+
+    #[test]
+    fn test_old_message_does_not_move_chat_back() {
+        // e.g. history replayed after a reconnect
+        let mut chats = vec![chat(1, 300), chat(2, 200)];
+        chats[0].last_message = "newest".into();
+
+        record_new_message(
+            &mut chats,
+            1,
+            "late",
+            Some("Bob".into()),
+            MessageKind::Photo,
+            100,
+            false,
+        );
+
+        assert_eq!(ids(&chats), vec![1, 2]);
+        assert_eq!(chats[0].last_message_time, 300);
+        assert_eq!(chats[0].last_message, "newest");
+        assert_eq!(chats[0].last_message_from, None);
+        assert_eq!(chats[0].last_message_kind, MessageKind::Text);
+        assert_eq!(chats[0].unread_count, 0);
+    }
+
+    #[test]
+    fn test_unknown_chat_is_ignored() {
+        let mut chats = vec![chat(1, 300)];
+        assert!(!record_new_message(
+            &mut chats,
+            9,
+            "hi",
+            None,
+            MessageKind::Text,
+            400,
+            true
+        ));
+        assert_eq!(total_unread(&chats), 0);
+    }
+
+    #[test]
+    fn test_muted_chats_do_not_count_as_unread() {
+        let mut chats = vec![chat(1, 300), chat(2, 200)];
+        chats[0].unread_count = 2;
+        chats[1].unread_count = 5;
+
+        assert!(set_chat_muted(&mut chats, 2, true));
+        assert!(!set_chat_muted(&mut chats, 9, true));
+        assert_eq!(total_unread(&chats), 2);
+    }
+
+    #[test]
+    fn test_pinned_chats_stay_on_top() {
+        let mut chats = vec![chat(1, 300), chat(2, 200), chat(3, 100)];
+
+        assert!(set_chat_pinned(&mut chats, 3, true));
+        assert_eq!(ids(&chats), vec![3, 1, 2]);
+
+        // Newer activity elsewhere does not push a pinned chat down
+        record_new_message(&mut chats, 2, "hey", None, MessageKind::Text, 400, true);
+        assert_eq!(ids(&chats), vec![3, 2, 1]);
+
+        assert!(set_chat_pinned(&mut chats, 3, false));
+        assert_eq!(ids(&chats), vec![2, 1, 3]);
+        assert!(!set_chat_pinned(&mut chats, 9, true));
+    }
+
+    #[test]
+    fn test_parse_mute_duration() {
+        assert_eq!(MuteDuration::parse("1h"), Ok(MuteDuration::Seconds(3600)));
+        assert_eq!(MuteDuration::parse("8H"), Ok(MuteDuration::Seconds(28800)));
+        assert_eq!(MuteDuration::parse("30m"), Ok(MuteDuration::Seconds(1800)));
+        assert_eq!(MuteDuration::parse("forever"), Ok(MuteDuration::Forever));
+        assert_eq!(MuteDuration::Forever.api_time(), -1);
+
+        for bad in ["", "h", "0h", "1w", "1.5h", "-1h", "soon"] {
+            assert!(MuteDuration::parse(bad).is_err(), "{:?}", bad);
+        }
+        assert!(MuteDuration::parse("99999999d").is_err());
+    }
+
+    #[test]
+    fn test_deleted_chat_comes_back_on_new_message() {
+        let mut chats = vec![chat(1, 300), chat(2, 200)];
+        chats[1].unread_count = 4;
+
+        let removed = remove_chat(&mut chats, 2).unwrap();
+        assert!(remove_chat(&mut chats, 2).is_none());
+        assert!(!record_new_message(
+            &mut chats,
+            2,
+            "back",
+            None,
+            MessageKind::Text,
+            400,
+            true
+        ));
+
+        restore_chat(&mut chats, removed);
+        assert!(record_new_message(
+            &mut chats,
+            2,
+            "back",
+            None,
+            MessageKind::Text,
+            400,
+            true
+        ));
+        assert_eq!(ids(&chats), vec![2, 1]);
+        assert_eq!(chats[0].unread_count, 1);
+    }
+
+    #[test]
+    fn test_title_update_renames_chat() {
+        let mut chats = vec![chat(1, 300), chat(2, 200)];
+        let renamed = ServiceAction {
+            kind: "chat_title_update".into(),
+            member_id: None,
+            text: Some("Trip".into()),
+        };
+        let joined = ServiceAction {
+            kind: "chat_invite_user".into(),
+            member_id: Some(5),
+            text: None,
+        };
+
+        assert!(apply_chat_action(&mut chats, 2, &renamed));
+        assert!(!apply_chat_action(&mut chats, 1, &joined));
+        assert!(!apply_chat_action(&mut chats, 9, &renamed));
+        assert_eq!(chats[1].title, "Trip");
+    }
+
+    #[test]
+    fn test_change_info_follows_acl() {
+        let conversation = |settings: serde_json::Value| -> vk_api::Conversation {
+            serde_json::from_value(serde_json::json!({
+                "peer": {"id": 2_000_000_001, "type": "chat", "local_id": 1},
+                "chat_settings": settings
+            }))
+            .unwrap()
+        };
+
+        let member = conversation(serde_json::json!({
+            "title": "Trip",
+            "acl": {"can_change_info": false, "can_invite": true}
+        }));
+        let admin = conversation(serde_json::json!({
+            "title": "Trip",
+            "acl": {"can_change_info": true}
+        }));
+        let unknown = conversation(serde_json::json!({"title": "Trip"}));
+
+        assert_eq!(change_chat_info_denied(&member), Some(CHANGE_INFO_DENIED));
+        assert_eq!(change_chat_info_denied(&admin), None);
+        assert_eq!(change_chat_info_denied(&unknown), None);
+    }
+
+    #[test]
+    fn test_leave_chat_error() {
+        let api = |code| vk_api::Error::Api {
+            code,
+            message: String::new(),
+        };
+        assert_eq!(
+            leave_chat_error(&api(vk_api::error::ERROR_PERMISSION_DENIED)),
+            Some("You can't leave this chat")
+        );
+        assert_eq!(leave_chat_error(&api(6)), None);
+    }
+
+    #[test]
+    fn test_sort_is_stable() {
+        let mut chats = vec![chat(1, 100), chat(2, 200), chat(3, 100)];
+        sort_chats(&mut chats);
+        assert_eq!(ids(&chats), vec![2, 1, 3]);
+    }
 }
@@ -1,7 +1,16 @@
 //! Message types.
 
-use super::AttachmentInfo;
-use serde::{Serialize, Deserialize};
+use super::{AttachmentInfo, MessageKind, ReactionCount};
+use serde::{Deserialize, Serialize};
+
+/// VK lets the sender delete a message for everyone only this long after
+/// sending it; later it can only be deleted for themselves.
+pub const DELETE_FOR_ALL_WINDOW_SECS: i64 = 24 * 60 * 60;
+
+/// Status for replying to, editing, deleting or forwarding a service
+/// message, which VK does not allow.
+pub const SERVICE_MESSAGE_DENIED: &str =
+    "Service messages cannot be replied to, edited, deleted or forwarded";
 
 /// Delivery state for messages.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -11,12 +20,45 @@ pub enum DeliveryStatus {
     Failed,
 }
 
+/// Progress of the file upload behind a pending attachment message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadState {
+    /// Frontend-chosen id tying progress events to the message
+    pub local_id: u64,
+    pub bytes_sent: u64,
+    pub total: u64,
+}
+
+impl UploadState {
+    pub fn new(local_id: u64) -> Self {
+        Self {
+            local_id,
+            bytes_sent: 0,
+            total: 0,
+        }
+    }
+
+    /// Share of the file sent, 0 to 100.
+    pub fn percent(&self) -> u8 {
+        if self.total == 0 {
+            return 0;
+        }
+        (self.bytes_sent.min(self.total) * 100 / self.total) as u8
+    }
+}
+
 /// Preview of a reply message.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReplyPreview {
     pub from: String,
     pub text: String,
     pub attachments: Vec<AttachmentInfo>,
+    /// Id of the replied message; VK leaves it out in some chats
+    #[serde(default)]
+    pub message_id: Option<i64>,
+    /// Conversation message id of the replied message
+    #[serde(default)]
+    pub cmid: Option<i64>,
 }
 
 /// A forwarded message item (can be nested).
@@ -35,9 +77,19 @@ pub struct ForwardItem {
 pub struct ChatMessage {
     pub id: i64,
     pub cmid: Option<i64>,
+    /// `random_id` used when sending; set for our own messages once known.
+    #[serde(default)]
+    pub random_id: Option<i64>,
     pub from_id: i64,
     pub from_name: String,
+    /// Sender avatar URL, if the sender's profile was loaded.
+    #[serde(default)]
+    pub from_photo: Option<String>,
     pub text: String,
+    /// What the message holds. The `text` of a service message describes
+    /// what happened ("Ann Lee invited Bob Ray").
+    #[serde(default)]
+    pub kind: MessageKind,
     pub timestamp: i64,
     pub is_outgoing: bool,
     pub is_read: bool,
@@ -48,6 +100,11 @@ pub struct ChatMessage {
     pub reply: Option<ReplyPreview>,
     pub fwd_count: usize,
     pub forwards: Vec<ForwardItem>,
+    #[serde(default)]
+    pub reactions: Vec<ReactionCount>,
+    /// Set while the attachment of a pending message is uploading.
+    #[serde(default)]
+    pub upload: Option<UploadState>,
 }
 
 impl ChatMessage {
@@ -57,4 +114,81 @@ impl ChatMessage {
         // This is a simplified version; actual peer_id should be passed from context
         self.from_id
     }
+
+    /// Someone joined, left, renamed the chat...: shown apart from the
+    /// conversation and not open to message actions.
+    pub fn is_service(&self) -> bool {
+        self.kind == MessageKind::Service
+    }
+
+    /// Text to show: a message stored without text (one that arrived by
+    /// Long Poll with only attachments) shows the placeholder of its kind.
+    pub fn display_text(&self) -> &str {
+        if !self.text.is_empty() {
+            return &self.text;
+        }
+        self.kind.placeholder().unwrap_or("[attachment]")
+    }
+
+    /// Whether the message can still be deleted for everyone at `now`:
+    /// it is ours, sent and younger than [`DELETE_FOR_ALL_WINDOW_SECS`].
+    pub fn can_delete_for_all(&self, now: i64) -> bool {
+        self.is_outgoing && self.id != 0 && now - self.timestamp < DELETE_FOR_ALL_WINDOW_SECS
+    }
+}
+
+/// Id of the first incoming message after `in_read`, the last one the
+/// user has read: where the unread part of `messages` begins.
+pub fn first_unread(messages: &[ChatMessage], in_read: i64) -> Option<i64> {
+    messages
+        .iter()
+        .find(|m| !m.is_outgoing && m.id > in_read)
+        .map(|m| m.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn message(is_outgoing: bool, timestamp: i64) -> ChatMessage {
+        ChatMessage {
+            is_outgoing,
+            timestamp,
+            ..fixtures::message(7, "hi")
+        }
+    }
+
+    #[test]
+    fn test_can_delete_for_all() {
+        let now = 1_700_100_000;
+        assert!(message(true, now - 60).can_delete_for_all(now));
+        assert!(!message(true, now - DELETE_FOR_ALL_WINDOW_SECS).can_delete_for_all(now));
+        assert!(!message(false, now - 60).can_delete_for_all(now));
+
+        let mut pending = message(true, now);
+        pending.id = 0;
+        assert!(!pending.can_delete_for_all(now));
+    }
+
+    #[test]
+    fn test_display_text() {
+        let mut msg = message(false, 0);
+        assert_eq!(msg.display_text(), "hi");
+        msg.text.clear();
+        msg.kind = MessageKind::Photo;
+        assert_eq!(msg.display_text(), "📷 Photo");
+        msg.kind = MessageKind::Text;
+        assert_eq!(msg.display_text(), "[attachment]");
+    }
+
+    #[test]
+    fn test_first_unread_skips_outgoing() {
+        let mut messages: Vec<ChatMessage> = (1..=4).map(|id| fixtures::message(id, "")).collect();
+        messages[2].is_outgoing = true;
+
+        assert_eq!(first_unread(&messages, 1), Some(2));
+        assert_eq!(first_unread(&messages, 2), Some(4));
+        assert_eq!(first_unread(&messages, 4), None);
+    }
 }
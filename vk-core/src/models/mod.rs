@@ -6,9 +6,30 @@
 mod attachment;
 mod chat;
 mod message;
+mod preview;
+mod profile;
+mod reactions;
+mod receipts;
 mod search;
 
 pub use attachment::{AttachmentInfo, AttachmentKind};
-pub use chat::Chat;
-pub use message::{ChatMessage, DeliveryStatus, ForwardItem, ReplyPreview};
+pub use chat::{
+    CHAT_MEMBERS_PAGE, Chat, ChatKind, ChatMember, MuteDuration, apply_chat_action,
+    change_chat_info_denied, create_chat_error, fill_missing_times, leave_chat_error,
+    record_new_message, remove_chat, rename_chat, rename_chat_error, restore_chat, set_chat_muted,
+    set_chat_photo, set_chat_pinned, sort_chats, total_unread,
+};
+pub use message::{
+    ChatMessage, DELETE_FOR_ALL_WINDOW_SECS, DeliveryStatus, ForwardItem, ReplyPreview,
+    SERVICE_MESSAGE_DENIED, UploadState, first_unread,
+};
+pub use preview::{
+    KIND_PLACEHOLDERS, MessageKind, ServiceAction, attachment_label, preview_sender, preview_text,
+};
+pub use profile::{ProfileDetails, presence};
+pub use reactions::{
+    ReactionCount, format_reactions, my_reaction, reaction_emoji, reaction_emojis, reaction_id,
+    toggle_reaction,
+};
+pub use receipts::{MessageReaders, read_peers_error};
 pub use search::SearchResult;
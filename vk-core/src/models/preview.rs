@@ -0,0 +1,284 @@
+//! Chat list previews for messages without text.
+
+use serde::{Deserialize, Serialize};
+use vk_api::is_chat_peer;
+
+use super::{AttachmentInfo, AttachmentKind};
+
+/// What a message holds besides text, as far as its preview tells.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MessageKind {
+    #[default]
+    Text,
+    Photo,
+    Video,
+    Voice,
+    Sticker,
+    Audio,
+    File,
+    Link,
+    Post,
+    Gift,
+    Poll,
+    Forwarded,
+    /// Someone joined, left, renamed the chat...
+    Service,
+    /// An attachment type without a placeholder
+    Other,
+}
+
+/// Placeholders shown for a message without text, by what it holds.
+pub const KIND_PLACEHOLDERS: &[(MessageKind, &str)] = &[
+    (MessageKind::Photo, "📷 Photo"),
+    (MessageKind::Video, "🎬 Video"),
+    (MessageKind::Voice, "🎤 Voice message"),
+    (MessageKind::Sticker, "🙂 Sticker"),
+    (MessageKind::Audio, "🎵 Audio"),
+    (MessageKind::File, "📄 File"),
+    (MessageKind::Link, "🔗 Link"),
+    (MessageKind::Post, "📰 Post"),
+    (MessageKind::Gift, "🎁 Gift"),
+    (MessageKind::Poll, "📊 Poll"),
+    (MessageKind::Forwarded, "↪ Forwarded messages"),
+];
+
+impl MessageKind {
+    /// Kind of a message: its service action, else its first attachment.
+    pub fn of(action: Option<&ServiceAction>, attachment_types: &[String]) -> Self {
+        if action.is_some() {
+            return MessageKind::Service;
+        }
+        attachment_types
+            .first()
+            .map_or(MessageKind::Text, |kind| Self::of_attachment(kind))
+    }
+
+    /// Kind of a VK attachment type; `fwd` stands for forwarded messages.
+    pub fn of_attachment(kind: &str) -> Self {
+        match kind {
+            "photo" => MessageKind::Photo,
+            "video" => MessageKind::Video,
+            "audio_message" => MessageKind::Voice,
+            "sticker" => MessageKind::Sticker,
+            "audio" => MessageKind::Audio,
+            "doc" => MessageKind::File,
+            "link" => MessageKind::Link,
+            "wall" => MessageKind::Post,
+            "gift" => MessageKind::Gift,
+            "poll" => MessageKind::Poll,
+            "fwd" => MessageKind::Forwarded,
+            _ => MessageKind::Other,
+        }
+    }
+
+    /// Kind of a loaded message: its first attachment, else its forwards.
+    pub fn of_loaded(attachments: &[AttachmentInfo], has_forwards: bool) -> Self {
+        match attachments.first().map(|a| &a.kind) {
+            Some(AttachmentKind::Photo) => MessageKind::Photo,
+            Some(AttachmentKind::Doc) => MessageKind::File,
+            Some(AttachmentKind::Link) => MessageKind::Link,
+            Some(AttachmentKind::Audio) => MessageKind::Audio,
+            Some(AttachmentKind::Sticker) => MessageKind::Sticker,
+            Some(AttachmentKind::Other(kind)) => Self::of_attachment(kind),
+            None if has_forwards => MessageKind::Forwarded,
+            None => MessageKind::Text,
+        }
+    }
+
+    /// Placeholder from [`KIND_PLACEHOLDERS`], if the kind has one.
+    pub fn placeholder(self) -> Option<&'static str> {
+        KIND_PLACEHOLDERS
+            .iter()
+            .find(|(kind, _)| *kind == self)
+            .map(|(_, placeholder)| *placeholder)
+    }
+}
+
+/// Whom a chat list preview names as the sender: "You" for own messages,
+/// the sender in group chats, nobody in a dialog.
+pub fn preview_sender(
+    peer_id: i64,
+    is_outgoing: bool,
+    from_id: i64,
+    name: impl Fn(i64) -> String,
+) -> Option<String> {
+    if is_outgoing {
+        Some("You".to_string())
+    } else if is_chat_peer(peer_id) {
+        Some(name(from_id))
+    } else {
+        None
+    }
+}
+
+/// Service action of a message: someone joined, left, renamed the chat...
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServiceAction {
+    /// VK action type, e.g. `chat_invite_user`.
+    pub kind: String,
+    /// User the action is about (invited or removed member).
+    pub member_id: Option<i64>,
+    /// Chat title for `chat_create` and `chat_title_update`.
+    pub text: Option<String>,
+}
+
+impl ServiceAction {
+    /// "Ann Lee joined the chat". `actor_id` is the sender of the service
+    /// message, `name` resolves user ids to names.
+    pub fn describe(&self, actor_id: i64, name: impl Fn(i64) -> String) -> String {
+        let actor = name(actor_id);
+        let other = self.member_id.filter(|id| *id != actor_id);
+        let title = self.text.as_deref().unwrap_or_default();
+        match (self.kind.as_str(), other) {
+            ("chat_create", _) => format!("{} created the chat \"{}\"", actor, title),
+            ("chat_title_update", _) => format!("{} renamed the chat to \"{}\"", actor, title),
+            ("chat_photo_update", _) => format!("{} updated the chat photo", actor),
+            ("chat_photo_remove", _) => format!("{} removed the chat photo", actor),
+            ("chat_pin_message", _) => format!("{} pinned a message", actor),
+            ("chat_unpin_message", _) => format!("{} unpinned a message", actor),
+            ("chat_invite_user", Some(member)) => format!("{} invited {}", actor, name(member)),
+            ("chat_invite_user", None) => format!("{} joined the chat", actor),
+            ("chat_invite_user_by_link", _) => format!("{} joined the chat by link", actor),
+            ("chat_kick_user", Some(member)) => format!("{} removed {}", actor, name(member)),
+            ("chat_kick_user", None) => format!("{} left the chat", actor),
+            ("chat_screenshot", _) => format!("{} took a screenshot", actor),
+            (kind, _) => format!("[{}]", kind),
+        }
+    }
+}
+
+/// Label of an attachment type in previews, e.g. `[photo]`, `[voice]`.
+///
+/// `fwd` stands for forwarded messages.
+pub fn attachment_label(kind: &str) -> String {
+    let label = match kind {
+        "audio_message" => "voice",
+        "doc" => "file",
+        "wall" => "post",
+        "fwd" => "forwarded",
+        other => other,
+    };
+    format!("[{}]", label)
+}
+
+/// Chat list preview of a message: its text, else a description of the
+/// service action, else the placeholders of its attachments.
+pub fn preview_text(
+    text: &str,
+    action: Option<&ServiceAction>,
+    attachment_types: &[String],
+    actor_id: i64,
+    name: impl Fn(i64) -> String,
+) -> String {
+    if !text.is_empty() {
+        return text.to_string();
+    }
+    if let Some(action) = action {
+        return action.describe(actor_id, name);
+    }
+
+    let mut labels: Vec<String> = Vec::new();
+    for kind in attachment_types {
+        let label = match MessageKind::of_attachment(kind).placeholder() {
+            Some(placeholder) => placeholder.to_string(),
+            None => attachment_label(kind),
+        };
+        if !labels.contains(&label) {
+            labels.push(label);
+        }
+    }
+    labels.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(id: i64) -> String {
+        match id {
+            1 => "Ann".to_string(),
+            2 => "Bob".to_string(),
+            _ => format!("User {}", id),
+        }
+    }
+
+    fn action(kind: &str, member_id: Option<i64>) -> ServiceAction {
+        ServiceAction {
+            kind: kind.to_string(),
+            member_id,
+            text: None,
+        }
+    }
+
+    #[test]
+    fn test_text_wins() {
+        let join = action("chat_invite_user", Some(1));
+        assert_eq!(
+            preview_text("hi", Some(&join), &["photo".into()], 1, name),
+            "hi"
+        );
+    }
+
+    #[test]
+    fn test_service_actions() {
+        let joined = action("chat_invite_user", Some(1));
+        assert_eq!(joined.describe(1, name), "Ann joined the chat");
+
+        let invited = action("chat_invite_user", Some(2));
+        assert_eq!(invited.describe(1, name), "Ann invited Bob");
+
+        let left = action("chat_kick_user", Some(2));
+        assert_eq!(left.describe(2, name), "Bob left the chat");
+
+        let renamed = ServiceAction {
+            text: Some("Trip".into()),
+            ..action("chat_title_update", None)
+        };
+        assert_eq!(
+            renamed.describe(1, name),
+            "Ann renamed the chat to \"Trip\""
+        );
+
+        assert_eq!(
+            action("chat_new_thing", None).describe(1, name),
+            "[chat_new_thing]"
+        );
+    }
+
+    #[test]
+    fn test_attachment_labels_are_deduplicated() {
+        let types = vec![
+            "photo".to_string(),
+            "photo".to_string(),
+            "audio_message".to_string(),
+            "fwd".to_string(),
+            "graffiti".to_string(),
+        ];
+        assert_eq!(
+            preview_text("", None, &types, 1, name),
+            "📷 Photo, 🎤 Voice message, ↪ Forwarded messages, [graffiti]"
+        );
+        assert_eq!(preview_text("", None, &[], 1, name), "");
+    }
+
+    #[test]
+    fn test_message_kind() {
+        let join = action("chat_invite_user", Some(1));
+        let types = vec!["audio_message".to_string(), "photo".to_string()];
+        assert_eq!(MessageKind::of(None, &types), MessageKind::Voice);
+        assert_eq!(MessageKind::of(Some(&join), &types), MessageKind::Service);
+        assert_eq!(MessageKind::of(None, &[]), MessageKind::Text);
+        assert_eq!(MessageKind::Voice.placeholder(), Some("🎤 Voice message"));
+        assert_eq!(MessageKind::Text.placeholder(), None);
+    }
+
+    #[test]
+    fn test_preview_sender() {
+        assert_eq!(preview_sender(5, true, 1, name).as_deref(), Some("You"));
+        assert_eq!(
+            preview_sender(2_000_000_001, false, 2, name).as_deref(),
+            Some("Bob")
+        );
+        assert_eq!(preview_sender(5, false, 5, name), None);
+    }
+}
@@ -0,0 +1,122 @@
+//! Profile card types.
+
+use serde::{Deserialize, Serialize};
+
+/// Details shown when looking up who a user or community is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileDetails {
+    /// User id, or negative community id.
+    pub id: i64,
+    pub name: String,
+    pub screen_name: Option<String>,
+    pub photo_url: Option<String>,
+    pub is_online: bool,
+    /// Last time the user was online (unix seconds).
+    pub last_seen: Option<i64>,
+    pub city: Option<String>,
+    pub status: Option<String>,
+    pub bdate: Option<String>,
+    /// Number of community members.
+    pub members_count: Option<u32>,
+}
+
+impl ProfileDetails {
+    /// "online", "last seen 5 min ago" etc. `None` for communities and
+    /// users that hide it.
+    pub fn presence(&self, now: i64) -> Option<String> {
+        presence(self.is_online, self.last_seen, now)
+    }
+}
+
+/// "online", or how long ago a user was last seen at `now`: "last seen
+/// just now", "last seen 5 min ago", "2 h", "3 d". `None` when offline and
+/// the time is hidden.
+pub fn presence(is_online: bool, last_seen: Option<i64>, now: i64) -> Option<String> {
+    if is_online {
+        return Some("online".to_string());
+    }
+    let ago = (now - last_seen?).max(0);
+    Some(match ago {
+        0..60 => "last seen just now".to_string(),
+        60..3600 => format!("last seen {} min ago", ago / 60),
+        3600..86400 => format!("last seen {} h ago", ago / 3600),
+        _ => format!("last seen {} d ago", ago / 86400),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(is_online: bool, last_seen: Option<i64>) -> ProfileDetails {
+        ProfileDetails {
+            id: 1,
+            name: "Ann Lee".into(),
+            screen_name: None,
+            photo_url: None,
+            is_online,
+            last_seen,
+            city: None,
+            status: None,
+            bdate: None,
+            members_count: None,
+        }
+    }
+
+    #[test]
+    fn test_presence() {
+        let now = 1_700_000_000;
+        assert_eq!(
+            profile(true, Some(now - 500)).presence(now).unwrap(),
+            "online"
+        );
+        assert_eq!(
+            profile(false, Some(now - 30)).presence(now).unwrap(),
+            "last seen just now"
+        );
+        assert_eq!(
+            profile(false, Some(now - 300)).presence(now).unwrap(),
+            "last seen 5 min ago"
+        );
+        assert_eq!(
+            profile(false, Some(now - 7200)).presence(now).unwrap(),
+            "last seen 2 h ago"
+        );
+        assert_eq!(
+            profile(false, Some(now - 3 * 86400)).presence(now).unwrap(),
+            "last seen 3 d ago"
+        );
+        assert_eq!(profile(false, None).presence(now), None);
+    }
+
+    #[test]
+    fn test_presence_boundaries() {
+        let now = 1_700_000_000;
+        assert_eq!(presence(true, None, now).unwrap(), "online");
+        assert_eq!(
+            presence(false, Some(now - 59), now).unwrap(),
+            "last seen just now"
+        );
+        assert_eq!(
+            presence(false, Some(now - 60), now).unwrap(),
+            "last seen 1 min ago"
+        );
+        assert_eq!(
+            presence(false, Some(now - 3599), now).unwrap(),
+            "last seen 59 min ago"
+        );
+        assert_eq!(
+            presence(false, Some(now - 86399), now).unwrap(),
+            "last seen 23 h ago"
+        );
+        assert_eq!(
+            presence(false, Some(now - 86400), now).unwrap(),
+            "last seen 1 d ago"
+        );
+        // A clock slightly behind the server's
+        assert_eq!(
+            presence(false, Some(now + 5), now).unwrap(),
+            "last seen just now"
+        );
+    }
+}
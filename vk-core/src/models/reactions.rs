@@ -0,0 +1,150 @@
+//! Reactions on messages.
+
+use serde::{Deserialize, Serialize};
+
+/// Emoji of the VK reaction ids, as listed by messages.getReactionsAssets.
+const REACTIONS: [(i64, &str); 16] = [
+    (1, "❤"),
+    (2, "🔥"),
+    (3, "😂"),
+    (4, "👍"),
+    (5, "💩"),
+    (6, "❓"),
+    (7, "😭"),
+    (8, "👎"),
+    (9, "👌"),
+    (10, "😡"),
+    (11, "😱"),
+    (12, "🤔"),
+    (13, "👏"),
+    (14, "🎉"),
+    (15, "🙏"),
+    (16, "😮"),
+];
+
+/// Counter of one reaction on a message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReactionCount {
+    pub reaction_id: i64,
+    pub count: u32,
+    /// The current user put this reaction
+    pub mine: bool,
+}
+
+/// Emoji of a reaction id, `None` for ids this client does not know.
+pub fn reaction_emoji(reaction_id: i64) -> Option<&'static str> {
+    REACTIONS
+        .iter()
+        .find(|(id, _)| *id == reaction_id)
+        .map(|(_, emoji)| *emoji)
+}
+
+/// Reaction id of an emoji; a trailing variation selector is ignored.
+pub fn reaction_id(emoji: &str) -> Option<i64> {
+    let emoji = emoji.trim().trim_end_matches('\u{fe0f}');
+    REACTIONS
+        .iter()
+        .find(|(_, e)| *e == emoji)
+        .map(|(id, _)| *id)
+}
+
+/// All known reaction emoji, in VK order.
+pub fn reaction_emojis() -> impl Iterator<Item = &'static str> {
+    REACTIONS.iter().map(|(_, emoji)| *emoji)
+}
+
+/// Reaction the current user put on the message.
+pub fn my_reaction(reactions: &[ReactionCount]) -> Option<i64> {
+    reactions.iter().find(|r| r.mine).map(|r| r.reaction_id)
+}
+
+/// "❤ 2  👍 1"; unknown ids are shown as `#id`.
+pub fn format_reactions(reactions: &[ReactionCount]) -> String {
+    reactions
+        .iter()
+        .filter(|r| r.count > 0)
+        .map(|r| match reaction_emoji(r.reaction_id) {
+            Some(emoji) => format!("{} {}", emoji, r.count),
+            None => format!("#{} {}", r.reaction_id, r.count),
+        })
+        .collect::<Vec<_>>()
+        .join("  ")
+}
+
+/// Put `reaction_id` on a message locally, or take it back if it is
+/// already ours. VK allows one reaction per user, so a previous one is
+/// replaced. Returns the reaction to send, `None` to delete ours.
+pub fn toggle_reaction(reactions: &mut Vec<ReactionCount>, reaction_id: i64) -> Option<i64> {
+    let previous = my_reaction(reactions);
+    if let Some(previous) = previous
+        && let Some(r) = reactions.iter_mut().find(|r| r.reaction_id == previous)
+    {
+        r.count = r.count.saturating_sub(1);
+        r.mine = false;
+    }
+
+    let result = if previous == Some(reaction_id) {
+        None
+    } else {
+        match reactions.iter_mut().find(|r| r.reaction_id == reaction_id) {
+            Some(r) => {
+                r.count += 1;
+                r.mine = true;
+            }
+            None => reactions.push(ReactionCount {
+                reaction_id,
+                count: 1,
+                mine: true,
+            }),
+        }
+        Some(reaction_id)
+    };
+
+    reactions.retain(|r| r.count > 0);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(reaction_id: i64, count: u32, mine: bool) -> ReactionCount {
+        ReactionCount {
+            reaction_id,
+            count,
+            mine,
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(reaction_emoji(4), Some("👍"));
+        assert_eq!(reaction_emoji(99), None);
+        assert_eq!(reaction_id("❤️"), Some(1));
+        assert_eq!(reaction_id("👍"), Some(4));
+        assert_eq!(reaction_id("x"), None);
+    }
+
+    #[test]
+    fn test_format_reactions() {
+        let reactions = vec![count(1, 2, false), count(4, 1, true), count(42, 3, false)];
+        assert_eq!(format_reactions(&reactions), "❤ 2  👍 1  #42 3");
+        assert_eq!(format_reactions(&[]), "");
+    }
+
+    #[test]
+    fn test_toggle_reaction() {
+        let mut reactions = vec![count(1, 2, false)];
+
+        assert_eq!(toggle_reaction(&mut reactions, 1), Some(1));
+        assert_eq!(reactions, vec![count(1, 3, true)]);
+
+        // Switching moves our vote
+        assert_eq!(toggle_reaction(&mut reactions, 4), Some(4));
+        assert_eq!(reactions, vec![count(1, 2, false), count(4, 1, true)]);
+
+        // Same reaction again takes it back
+        assert_eq!(toggle_reaction(&mut reactions, 4), None);
+        assert_eq!(reactions, vec![count(1, 2, false)]);
+    }
+}
@@ -0,0 +1,93 @@
+//! Read receipts of messages in group chats.
+
+use serde::{Deserialize, Serialize};
+
+/// Names listed before "and N others" in [`MessageReaders::summary`].
+const SUMMARY_NAMES: usize = 2;
+
+/// Who read a message in a group chat.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageReaders {
+    pub peer_id: i64,
+    pub cmid: i64,
+    /// Total readers; VK may list fewer in `readers`
+    pub count: u32,
+    /// (user id, name), negative ids for communities
+    pub readers: Vec<(i64, String)>,
+}
+
+impl MessageReaders {
+    /// "Read by Alice, Bob and 3 others"
+    pub fn summary(&self) -> String {
+        let names: Vec<&str> = self
+            .readers
+            .iter()
+            .take(SUMMARY_NAMES)
+            .map(|(_, name)| name.as_str())
+            .collect();
+        let others = (self.count as usize).max(self.readers.len()) - names.len();
+
+        match (names.as_slice(), others) {
+            ([], 0) => "Nobody has read it yet".to_string(),
+            ([], n) => format!("Read by {} {}", n, plural(n)),
+            ([only], 0) => format!("Read by {}", only),
+            ([rest @ .., last], 0) => format!("Read by {} and {}", rest.join(", "), last),
+            (names, 1) => format!("Read by {} and 1 other", names.join(", ")),
+            (names, n) => format!("Read by {} and {} others", names.join(", "), n),
+        }
+    }
+}
+
+fn plural(n: usize) -> &'static str {
+    if n == 1 { "member" } else { "members" }
+}
+
+/// Readable reason messages.getMessageReadPeers failed. VK refuses the
+/// request for old messages and for chats that hide read receipts.
+pub fn read_peers_error(e: &vk_api::Error) -> Option<&'static str> {
+    use vk_api::error::{ERROR_ACCESS_DENIED, ERROR_INVALID_PARAM, ERROR_PERMISSION_DENIED};
+
+    match e.code()? {
+        ERROR_PERMISSION_DENIED | ERROR_ACCESS_DENIED | ERROR_INVALID_PARAM => {
+            Some("Read receipts are not available for this message")
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn readers(names: &[&str], count: u32) -> MessageReaders {
+        MessageReaders {
+            peer_id: 2_000_000_001,
+            cmid: 10,
+            count,
+            readers: names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| (i as i64 + 1, name.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(readers(&[], 0).summary(), "Nobody has read it yet");
+        assert_eq!(readers(&["Alice"], 1).summary(), "Read by Alice");
+        assert_eq!(
+            readers(&["Alice", "Bob"], 2).summary(),
+            "Read by Alice and Bob"
+        );
+        assert_eq!(
+            readers(&["Alice", "Bob", "Carol"], 3).summary(),
+            "Read by Alice, Bob and 1 other"
+        );
+        assert_eq!(
+            readers(&["Alice", "Bob", "Carol"], 5).summary(),
+            "Read by Alice, Bob and 3 others"
+        );
+        assert_eq!(readers(&[], 4).summary(), "Read by 4 members");
+    }
+}
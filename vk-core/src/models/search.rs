@@ -12,4 +12,7 @@ pub struct SearchResult {
     pub chat_title: String,
     pub text: String,
     pub timestamp: i64,
+    /// Byte range of the match in `text`, for offline (`:grep`) results.
+    #[serde(default)]
+    pub highlight: Option<std::ops::Range<usize>>,
 }
@@ -0,0 +1,296 @@
+//! Text messages that VK has not accepted yet.
+//!
+//! Frontends send texts through [`OutboxCommand::Send`] instead of calling
+//! `messages.send` directly. The executor keeps each one here until the
+//! API takes it and reports every change as an [`OutboxEvent`], so a
+//! frontend only mirrors the entries with [`Outbox::apply`].
+//!
+//! A send that fails for lack of a connection waits as `Queued` and goes
+//! out again on [`OutboxCommand::Flush`]; any other failure leaves it
+//! `Failed` until it is retried or discarded. Discarding never calls the
+//! API: the message was not sent.
+
+use serde::{Deserialize, Serialize};
+
+/// Where an unsent message is.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutboxState {
+    /// Handed to the API, no answer yet
+    Sending,
+    /// Could not reach VK; sent again when the connection is back
+    Queued,
+    /// VK refused it, with the error
+    Failed(String),
+}
+
+/// An unsent message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    /// Local id, unique within the session
+    pub id: u64,
+    pub peer_id: i64,
+    pub text: String,
+    pub reply_to: Option<i64>,
+    pub state: OutboxState,
+}
+
+impl OutboxEntry {
+    /// "Sending…", "Waiting for connection" or "Not sent: <error>".
+    pub fn state_text(&self) -> String {
+        match &self.state {
+            OutboxState::Sending => "Sending…".into(),
+            OutboxState::Queued => "Waiting for connection".into(),
+            OutboxState::Failed(error) => format!("Not sent: {}", error),
+        }
+    }
+
+    /// Whether it may be discarded: it is not on its way to VK.
+    pub fn is_idle(&self) -> bool {
+        self.state != OutboxState::Sending
+    }
+}
+
+/// What frontends ask of the outbox.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum OutboxCommand {
+    /// Send a text, answering `reply_to` if set.
+    Send {
+        peer_id: i64,
+        text: String,
+        reply_to: Option<i64>,
+    },
+    /// Send a failed or queued message again.
+    Retry(u64),
+    /// Send every failed and queued message again.
+    RetryAll,
+    /// Drop a failed or queued message.
+    Discard(u64),
+    /// Drop every failed and queued message.
+    DiscardAll,
+    /// The connection is back: send the queued messages.
+    Flush,
+}
+
+/// Changes of the outbox, in the order they happen.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum OutboxEvent {
+    /// An entry was added or changed state.
+    Updated(OutboxEntry),
+    /// VK accepted the entry; it left the outbox.
+    Sent {
+        id: u64,
+        peer_id: i64,
+        message_id: i64,
+        cmid: i64,
+        random_id: i64,
+    },
+    /// The entry was dropped without being sent.
+    Discarded { id: u64 },
+}
+
+/// Unsent messages of all chats, oldest first.
+#[derive(Debug, Default)]
+pub struct Outbox {
+    entries: Vec<OutboxEntry>,
+    next_id: u64,
+}
+
+impl Outbox {
+    pub fn entries(&self) -> &[OutboxEntry] {
+        &self.entries
+    }
+
+    pub fn get(&self, id: u64) -> Option<&OutboxEntry> {
+        self.entries.iter().find(|e| e.id == id)
+    }
+
+    /// Unsent messages of one chat.
+    pub fn for_peer(&self, peer_id: i64) -> impl Iterator<Item = &OutboxEntry> {
+        self.entries.iter().filter(move |e| e.peer_id == peer_id)
+    }
+
+    /// "N unsent" for the header, or `None` when everything went out.
+    pub fn label(&self) -> Option<String> {
+        (!self.entries.is_empty()).then(|| format!("{} unsent", self.entries.len()))
+    }
+
+    /// Add a message about to be sent.
+    pub fn push(&mut self, peer_id: i64, text: String, reply_to: Option<i64>) -> OutboxEntry {
+        self.next_id += 1;
+        let entry = OutboxEntry {
+            id: self.next_id,
+            peer_id,
+            text,
+            reply_to,
+            state: OutboxState::Sending,
+        };
+        self.entries.push(entry.clone());
+        entry
+    }
+
+    /// Change the state of an entry; returns it as it is now.
+    pub fn set_state(&mut self, id: u64, state: OutboxState) -> Option<OutboxEntry> {
+        let entry = self.entries.iter_mut().find(|e| e.id == id)?;
+        entry.state = state;
+        Some(entry.clone())
+    }
+
+    /// Mark the idle entries `pick` selects as sending again and return them.
+    pub fn resend(&mut self, pick: impl Fn(&OutboxEntry) -> bool) -> Vec<OutboxEntry> {
+        self.entries
+            .iter_mut()
+            .filter(|e| e.is_idle() && pick(e))
+            .map(|e| {
+                e.state = OutboxState::Sending;
+                e.clone()
+            })
+            .collect()
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<OutboxEntry> {
+        let idx = self.entries.iter().position(|e| e.id == id)?;
+        Some(self.entries.remove(idx))
+    }
+
+    /// Drop the idle entries `pick` selects; returns their ids.
+    pub fn discard(&mut self, pick: impl Fn(&OutboxEntry) -> bool) -> Vec<u64> {
+        let mut dropped = Vec::new();
+        self.entries.retain(|e| {
+            let drop = e.is_idle() && pick(e);
+            if drop {
+                dropped.push(e.id);
+            }
+            !drop
+        });
+        dropped
+    }
+
+    /// Follow an event of the executor's outbox.
+    pub fn apply(&mut self, event: &OutboxEvent) {
+        match event {
+            OutboxEvent::Updated(entry) => {
+                match self.entries.iter_mut().find(|e| e.id == entry.id) {
+                    Some(existing) => *existing = entry.clone(),
+                    None => self.entries.push(entry.clone()),
+                }
+            }
+            OutboxEvent::Sent { id, .. } | OutboxEvent::Discarded { id } => {
+                self.remove(*id);
+            }
+        }
+    }
+}
+
+/// State a send that failed with `e` leaves its entry in.
+pub fn failure_state(e: &vk_api::Error) -> OutboxState {
+    if matches!(e, vk_api::Error::Http(_)) || e.is_retriable() {
+        OutboxState::Queued
+    } else {
+        OutboxState::Failed(e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn failed(outbox: &mut Outbox, id: u64) {
+        outbox.set_state(id, OutboxState::Failed("Flood control".into()));
+    }
+
+    #[test]
+    fn test_retry_resends_only_idle_entries() {
+        let mut outbox = Outbox::default();
+        let a = outbox.push(1, "a".into(), None);
+        let b = outbox.push(2, "b".into(), Some(7));
+        let c = outbox.push(2, "c".into(), None);
+        failed(&mut outbox, a.id);
+        outbox.set_state(b.id, OutboxState::Queued);
+
+        let again = outbox.resend(|_| true);
+        let ids: Vec<u64> = again.iter().map(|e| e.id).collect();
+        assert_eq!(ids, vec![a.id, b.id]);
+        assert_eq!(again[1].reply_to, Some(7));
+        assert!(
+            outbox
+                .entries()
+                .iter()
+                .all(|e| e.state == OutboxState::Sending)
+        );
+        assert!(outbox.resend(|e| e.id == c.id).is_empty());
+    }
+
+    #[test]
+    fn test_flush_picks_queued() {
+        let mut outbox = Outbox::default();
+        let a = outbox.push(1, "a".into(), None);
+        let b = outbox.push(1, "b".into(), None);
+        failed(&mut outbox, a.id);
+        outbox.set_state(b.id, OutboxState::Queued);
+
+        let flushed = outbox.resend(|e| e.state == OutboxState::Queued);
+        assert_eq!(flushed.len(), 1);
+        assert_eq!(flushed[0].id, b.id);
+        assert!(matches!(
+            outbox.get(a.id).unwrap().state,
+            OutboxState::Failed(_)
+        ));
+    }
+
+    #[test]
+    fn test_discard_keeps_entries_on_their_way() {
+        let mut outbox = Outbox::default();
+        let a = outbox.push(1, "a".into(), None);
+        let b = outbox.push(1, "b".into(), None);
+        outbox.set_state(b.id, OutboxState::Queued);
+
+        assert_eq!(outbox.discard(|_| true), vec![b.id]);
+        assert_eq!(outbox.entries().len(), 1);
+        assert_eq!(outbox.label().as_deref(), Some("1 unsent"));
+        assert!(outbox.discard(|e| e.id == a.id).is_empty());
+    }
+
+    #[test]
+    fn test_mirror_follows_events() {
+        let mut core = Outbox::default();
+        let mut mirror = Outbox::default();
+        let a = core.push(5, "hi".into(), None);
+        let b = core.push(6, "there".into(), None);
+        mirror.apply(&OutboxEvent::Updated(a.clone()));
+        mirror.apply(&OutboxEvent::Updated(b.clone()));
+        assert_eq!(mirror.label().as_deref(), Some("2 unsent"));
+
+        let queued = core.set_state(a.id, OutboxState::Queued).unwrap();
+        mirror.apply(&OutboxEvent::Updated(queued));
+        assert_eq!(
+            mirror.get(a.id).unwrap().state_text(),
+            "Waiting for connection"
+        );
+        assert_eq!(mirror.for_peer(6).count(), 1);
+
+        mirror.apply(&OutboxEvent::Sent {
+            id: a.id,
+            peer_id: 5,
+            message_id: 100,
+            cmid: 10,
+            random_id: 42,
+        });
+        mirror.apply(&OutboxEvent::Discarded { id: b.id });
+        assert!(mirror.entries().is_empty());
+        assert_eq!(mirror.label(), None);
+    }
+
+    #[test]
+    fn test_failure_state() {
+        let flood = vk_api::Error::Api {
+            code: 9,
+            message: "Flood control".into(),
+        };
+        assert!(matches!(failure_state(&flood), OutboxState::Failed(_)));
+        let busy = vk_api::Error::Api {
+            code: 6,
+            message: "Too many requests per second".into(),
+        };
+        assert_eq!(failure_state(&busy), OutboxState::Queued);
+    }
+}
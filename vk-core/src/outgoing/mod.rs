@@ -0,0 +1,339 @@
+//! Reconciling optimistic outgoing messages with the server.
+//!
+//! Frontends show a sent message immediately as a `Pending` entry with
+//! `id == 0` and the `random_id` it is sent with. The same message later comes back twice: as the
+//! `messages.send` result (`MessageSent`) and as a Long Poll `NewMessage`,
+//! in either order. These helpers merge both into the optimistic entry
+//! instead of adding a duplicate row.
+
+mod split;
+
+pub use split::{LENGTH_WARNING_CHARS, MAX_MESSAGE_CHARS, length_counter, split_message, too_long};
+
+use crate::models::{ChatMessage, DeliveryStatus};
+
+/// Maximum clock difference (seconds) between an optimistic entry and its
+/// Long Poll echo when they can only be matched by text.
+pub const ECHO_WINDOW_SECS: i64 = 120;
+
+/// Find the entry an outgoing Long Poll message should be merged into.
+///
+/// Matches by message id (send already confirmed), then by `random_id`,
+/// then falls back to an unconfirmed pending entry with the same text sent
+/// within [`ECHO_WINDOW_SECS`].
+pub fn find_echo_target(messages: &[ChatMessage], incoming: &ChatMessage) -> Option<usize> {
+    if let Some(idx) = messages.iter().rposition(|m| m.id == incoming.id) {
+        return Some(idx);
+    }
+    if let Some(random_id) = incoming.random_id
+        && let Some(idx) = messages
+            .iter()
+            .rposition(|m| m.random_id == Some(random_id))
+    {
+        return Some(idx);
+    }
+    messages.iter().rposition(|m| {
+        is_unconfirmed(m)
+            && m.text == incoming.text
+            && (m.timestamp - incoming.timestamp).abs() <= ECHO_WINDOW_SECS
+    })
+}
+
+/// Add a message received from Long Poll, merging it into a matching
+/// optimistic entry if it is our own echo. Returns the index of the entry.
+pub fn merge_incoming(messages: &mut Vec<ChatMessage>, incoming: ChatMessage) -> usize {
+    let target = if incoming.is_outgoing {
+        find_echo_target(messages, &incoming)
+    } else {
+        messages.iter().rposition(|m| m.id == incoming.id)
+    };
+
+    let Some(idx) = target else {
+        messages.push(incoming);
+        return messages.len() - 1;
+    };
+
+    let msg = &mut messages[idx];
+    msg.id = incoming.id;
+    msg.timestamp = incoming.timestamp;
+    if incoming.cmid.is_some() {
+        msg.cmid = incoming.cmid;
+    }
+    if incoming.random_id.is_some() {
+        msg.random_id = incoming.random_id;
+    }
+    if msg.delivery == DeliveryStatus::Pending {
+        msg.delivery = DeliveryStatus::Sent;
+    }
+    idx
+}
+
+/// Apply a `messages.send` result to the optimistic entry sent with
+/// `random_id`.
+///
+/// If the Long Poll echo already arrived as a separate entry, the optimistic
+/// one is dropped. Returns `false` if there was nothing to update.
+pub fn confirm_sent(
+    messages: &mut Vec<ChatMessage>,
+    message_id: i64,
+    cmid: i64,
+    random_id: i64,
+) -> bool {
+    let pending = messages
+        .iter()
+        .rposition(|m| is_unconfirmed(m) && m.random_id == Some(random_id));
+    let echoed = messages.iter().rposition(|m| m.id == message_id);
+
+    let idx = match (pending, echoed) {
+        (Some(pending), Some(echoed)) => {
+            messages.remove(pending);
+            if echoed > pending { echoed - 1 } else { echoed }
+        }
+        (Some(idx), None) | (None, Some(idx)) => idx,
+        (None, None) => return false,
+    };
+
+    let msg = &mut messages[idx];
+    msg.id = message_id;
+    msg.cmid = Some(cmid);
+    msg.random_id = Some(random_id);
+    msg.upload = None;
+    if msg.delivery == DeliveryStatus::Pending {
+        msg.delivery = DeliveryStatus::Sent;
+    }
+    true
+}
+
+/// Record upload progress on the pending message with `local_id`.
+/// Returns `false` if no such message is uploading.
+pub fn set_upload_progress(
+    messages: &mut [ChatMessage],
+    local_id: u64,
+    bytes_sent: u64,
+    total: u64,
+) -> bool {
+    let Some(upload) = messages
+        .iter_mut()
+        .rev()
+        .find_map(|m| m.upload.as_mut().filter(|u| u.local_id == local_id))
+    else {
+        return false;
+    };
+    upload.bytes_sent = bytes_sent;
+    upload.total = total;
+    true
+}
+
+/// Mark the message whose upload `local_id` failed as `Failed`.
+pub fn fail_upload(messages: &mut [ChatMessage], local_id: u64) -> bool {
+    let Some(msg) = messages
+        .iter_mut()
+        .rev()
+        .find(|m| m.upload.is_some_and(|u| u.local_id == local_id))
+    else {
+        return false;
+    };
+    msg.upload = None;
+    msg.delivery = DeliveryStatus::Failed;
+    true
+}
+
+/// Remove the attachments named in `failed` from the message uploading
+/// `local_id`, after the rest was sent without them.
+pub fn drop_failed_uploads(messages: &mut [ChatMessage], local_id: u64, failed: &[String]) -> bool {
+    let Some(msg) = messages
+        .iter_mut()
+        .rev()
+        .find(|m| m.upload.is_some_and(|u| u.local_id == local_id))
+    else {
+        return false;
+    };
+    msg.attachments.retain(|a| !failed.contains(&a.title));
+    true
+}
+
+fn is_unconfirmed(msg: &ChatMessage) -> bool {
+    msg.is_outgoing && msg.id == 0 && msg.delivery == DeliveryStatus::Pending
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::models::{AttachmentInfo, AttachmentKind, UploadState};
+
+    /// Our own message, before the server gave it a `cmid`.
+    fn message(id: i64, text: &str, timestamp: i64, delivery: DeliveryStatus) -> ChatMessage {
+        ChatMessage {
+            cmid: None,
+            from_id: 1,
+            from_name: "You".into(),
+            timestamp,
+            is_outgoing: true,
+            is_read: false,
+            delivery,
+            ..fixtures::message(id, text)
+        }
+    }
+
+    fn pending(text: &str, timestamp: i64) -> ChatMessage {
+        message(0, text, timestamp, DeliveryStatus::Pending)
+    }
+
+    fn sending(text: &str, timestamp: i64, random_id: i64) -> ChatMessage {
+        ChatMessage {
+            random_id: Some(random_id),
+            ..pending(text, timestamp)
+        }
+    }
+
+    fn echo(id: i64, text: &str, timestamp: i64) -> ChatMessage {
+        message(id, text, timestamp, DeliveryStatus::Sent)
+    }
+
+    #[test]
+    fn test_echo_before_send_result_merges_by_text() {
+        let mut messages = vec![echo(5, "older", 900), sending("hi", 1000, 777)];
+
+        let idx = merge_incoming(&mut messages, echo(10, "hi", 1003));
+
+        assert_eq!(idx, 1);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].id, 10);
+        assert_eq!(messages[1].timestamp, 1003);
+        assert_eq!(messages[1].delivery, DeliveryStatus::Sent);
+
+        // The send result then just confirms the same entry
+        assert!(confirm_sent(&mut messages, 10, 3, 777));
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].cmid, Some(3));
+        assert_eq!(messages[1].random_id, Some(777));
+    }
+
+    #[test]
+    fn test_echo_after_send_result_merges_by_id() {
+        let mut messages = vec![sending("hi", 1000, 777)];
+        assert!(confirm_sent(&mut messages, 10, 3, 777));
+
+        let idx = merge_incoming(&mut messages, echo(10, "hi", 1001));
+
+        assert_eq!(idx, 0);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].timestamp, 1001);
+    }
+
+    #[test]
+    fn test_echo_merges_by_random_id() {
+        let mut messages = vec![echo(0, "edited locally", 1000)];
+        messages[0].random_id = Some(777);
+        let mut incoming = echo(10, "hi", 1001);
+        incoming.random_id = Some(777);
+
+        assert_eq!(merge_incoming(&mut messages, incoming), 0);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].id, 10);
+    }
+
+    #[test]
+    fn test_unmatched_echo_is_deduplicated_by_send_result() {
+        // Photo: optimistic text differs from what Long Poll reports
+        let mut messages = vec![sending("[image] cat.png", 1000, 777)];
+        merge_incoming(&mut messages, echo(10, "", 1001));
+        assert_eq!(messages.len(), 2);
+
+        assert!(confirm_sent(&mut messages, 10, 3, 777));
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].id, 10);
+        assert_eq!(messages[0].cmid, Some(3));
+    }
+
+    #[test]
+    fn test_send_results_confirm_their_own_entries() {
+        let mut messages = vec![sending("first", 1000, 1), sending("second", 1001, 2)];
+
+        // The first send answers after the second one went out
+        assert!(confirm_sent(&mut messages, 10, 3, 1));
+
+        assert_eq!(messages[0].id, 10);
+        assert_eq!(messages[0].delivery, DeliveryStatus::Sent);
+        assert_eq!(messages[1].id, 0);
+        assert_eq!(messages[1].delivery, DeliveryStatus::Pending);
+
+        assert!(confirm_sent(&mut messages, 11, 4, 2));
+        assert_eq!(messages[1].id, 11);
+        assert_eq!(messages[1].cmid, Some(4));
+        // A result for a send without an entry leaves both alone
+        assert!(!confirm_sent(&mut messages, 12, 5, 3));
+    }
+
+    #[test]
+    fn test_same_text_outside_window_is_not_merged() {
+        let mut messages = vec![pending("ok", 1000)];
+
+        merge_incoming(&mut messages, echo(10, "ok", 1000 + ECHO_WINDOW_SECS + 1));
+
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].id, 0);
+    }
+
+    #[test]
+    fn test_upload_progress_by_local_id() {
+        let mut messages = vec![
+            sending("[image] a.png", 1000, 777),
+            sending("[file] b.pdf", 1001, 778),
+        ];
+        messages[0].upload = Some(UploadState::new(1));
+        messages[1].upload = Some(UploadState::new(2));
+
+        assert!(set_upload_progress(&mut messages, 1, 50, 200));
+        assert_eq!(messages[0].upload.unwrap().percent(), 25);
+        assert_eq!(messages[1].upload.unwrap().percent(), 0);
+        assert!(!set_upload_progress(&mut messages, 3, 1, 1));
+
+        assert!(fail_upload(&mut messages, 2));
+        assert_eq!(messages[1].delivery, DeliveryStatus::Failed);
+        assert_eq!(messages[0].delivery, DeliveryStatus::Pending);
+
+        assert!(confirm_sent(&mut messages, 10, 3, 777));
+        assert_eq!(messages[0].upload, None);
+    }
+
+    #[test]
+    fn test_drop_failed_uploads() {
+        let doc = |title: &str| AttachmentInfo {
+            kind: AttachmentKind::Doc,
+            title: title.into(),
+            url: None,
+            thumbnail_url: None,
+            size: None,
+            subtitle: None,
+            description: None,
+            target_path: None,
+        };
+        let mut messages = vec![pending("[file] a.pdf, b.pdf", 1000)];
+        messages[0].attachments = vec![doc("a.pdf"), doc("b.pdf")];
+        messages[0].upload = Some(UploadState::new(1));
+
+        assert!(drop_failed_uploads(&mut messages, 1, &["b.pdf".into()]));
+        let titles: Vec<&str> = messages[0]
+            .attachments
+            .iter()
+            .map(|a| a.title.as_str())
+            .collect();
+        assert_eq!(titles, ["a.pdf"]);
+        assert!(!drop_failed_uploads(&mut messages, 2, &["a.pdf".into()]));
+    }
+
+    #[test]
+    fn test_incoming_message_is_appended() {
+        let mut messages = vec![pending("hi", 1000)];
+        let mut incoming = echo(10, "hi", 1001);
+        incoming.is_outgoing = false;
+
+        assert_eq!(merge_incoming(&mut messages, incoming.clone()), 1);
+        // A repeated delivery of the same message is not duplicated
+        assert_eq!(merge_incoming(&mut messages, incoming), 1);
+        assert_eq!(messages.len(), 2);
+    }
+}
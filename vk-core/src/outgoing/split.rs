@@ -0,0 +1,132 @@
+//! Length limit of outgoing text.
+//!
+//! VK rejects a message longer than [`MAX_MESSAGE_CHARS`]. Frontends check
+//! the input before sending: past [`LENGTH_WARNING_CHARS`] they show a
+//! counter, and a text over the limit is either refused or sent as the
+//! parts [`split_message`] cuts it into.
+
+/// Longest message VK accepts, in characters.
+pub const MAX_MESSAGE_CHARS: usize = 4096;
+
+/// Length from which the input shows a character counter.
+pub const LENGTH_WARNING_CHARS: usize = 3500;
+
+/// Whether `text` is over [`MAX_MESSAGE_CHARS`].
+pub fn too_long(text: &str) -> bool {
+    text.chars().nth(MAX_MESSAGE_CHARS).is_some()
+}
+
+/// "3712/4096" once `text` is past [`LENGTH_WARNING_CHARS`].
+pub fn length_counter(text: &str) -> Option<String> {
+    let count = text.chars().count();
+    (count > LENGTH_WARNING_CHARS).then(|| format!("{}/{}", count, MAX_MESSAGE_CHARS))
+}
+
+/// Cut `text` into parts of at most `max_chars` characters, in order.
+///
+/// A part ends at the last line break that keeps it at least half full,
+/// else at the last space; the break itself is dropped. A word longer
+/// than a part is cut between characters. Line breaks inside a part stay.
+pub fn split_message(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut parts = Vec::new();
+    let mut rest = text;
+
+    // Byte offset of the character past the limit, while there is one
+    while let Some((limit, _)) = rest.char_indices().nth(max_chars) {
+        // A break right after the limit still keeps the part full
+        let window_end = limit + rest[limit..].chars().next().map_or(0, char::len_utf8);
+        let min = rest
+            .char_indices()
+            .nth(max_chars / 2)
+            .map_or(0, |(idx, _)| idx);
+        let window = &rest[min..window_end];
+
+        let (end, skip) = match window.rfind('\n') {
+            Some(idx) => (min + idx, 1),
+            None => match window.char_indices().rev().find(|(_, c)| c.is_whitespace()) {
+                Some((idx, c)) => (min + idx, c.len_utf8()),
+                None => (limit, 0),
+            },
+        };
+
+        if end > 0 {
+            parts.push(rest[..end].to_string());
+        }
+        rest = &rest[end + skip..];
+    }
+    if !rest.is_empty() {
+        parts.push(rest.to_string());
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lengths(parts: &[String]) -> Vec<usize> {
+        parts.iter().map(|p| p.chars().count()).collect()
+    }
+
+    #[test]
+    fn test_short_text_is_one_part() {
+        assert_eq!(split_message("hello world", 20), vec!["hello world"]);
+        assert!(split_message("", 20).is_empty());
+    }
+
+    #[test]
+    fn test_splits_on_word_boundaries() {
+        let parts = split_message("one two three four five", 10);
+        assert_eq!(parts, vec!["one two", "three four", "five"]);
+    }
+
+    #[test]
+    fn test_prefers_line_breaks() {
+        let parts = split_message("first line\nsecond line goes on", 20);
+        assert_eq!(parts, vec!["first line", "second line goes on"]);
+
+        // Line breaks inside a part are kept
+        let parts = split_message("a\nb\nc d e f g h", 9);
+        assert_eq!(parts, vec!["a\nb\nc d e", "f g h"]);
+    }
+
+    #[test]
+    fn test_early_line_break_is_ignored() {
+        // Breaking at the newline would leave a one-word part
+        let parts = split_message("hi\nthere are many words here", 16);
+        assert_eq!(parts, vec!["hi\nthere are", "many words here"]);
+    }
+
+    #[test]
+    fn test_cuts_long_words_between_characters() {
+        let word = "привет".repeat(3);
+        let parts = split_message(&word, 7);
+        assert_eq!(lengths(&parts), vec![7, 7, 4]);
+        assert_eq!(parts.concat(), word);
+    }
+
+    #[test]
+    fn test_multibyte_text_keeps_the_limit() {
+        let text = "🙂🙂🙂 ёжик в тумане 🙂 ".repeat(50);
+        let parts = split_message(&text, 30);
+        assert!(lengths(&parts).iter().all(|&len| len <= 30));
+        assert_eq!(
+            parts.join(" ").split_whitespace().collect::<Vec<_>>(),
+            text.split_whitespace().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_length_limits() {
+        let limit = "a".repeat(MAX_MESSAGE_CHARS);
+        assert!(!too_long(&limit));
+        assert!(too_long(&format!("{}ы", limit)));
+
+        assert_eq!(length_counter("short"), None);
+        assert_eq!(
+            length_counter(&"ж".repeat(3712)).as_deref(),
+            Some("3712/4096")
+        );
+    }
+}
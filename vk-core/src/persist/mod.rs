@@ -0,0 +1,304 @@
+//! Versioned state files.
+//!
+//! A [`PersistedFile`] keeps a value as `{"version": N, "data": ...}` JSON.
+//! Loading a file written by an older version copies it to
+//! `<name>.v<N>.bak`, then runs the registered migrations one version at a
+//! time. Saving writes a temp file and renames it over the old one, so a
+//! crash never leaves half a file behind.
+//!
+//! Loading never fails: a file that cannot be read as any known version is
+//! renamed to `<name>.broken` and the default value is used instead.
+
+use std::fs;
+use std::io::{self, Write};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Turns the `data` of one version into the `data` of the next.
+pub type Migration = fn(Value) -> Result<Value, String>;
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<D> {
+    version: u32,
+    data: D,
+}
+
+/// A JSON file holding a `T` at schema version `version`.
+pub struct PersistedFile<T> {
+    path: PathBuf,
+    version: u32,
+    /// Migration from version `i + 1` to `i + 2`
+    migrations: Vec<Option<Migration>>,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned + Default> PersistedFile<T> {
+    /// File at `path` whose current schema is `version` (1 or higher).
+    pub fn new(path: impl Into<PathBuf>, version: u32) -> Self {
+        Self {
+            path: path.into(),
+            version: version.max(1),
+            migrations: Vec::new(),
+            _value: PhantomData,
+        }
+    }
+
+    /// Register the migration from version `from` to `from + 1`.
+    pub fn migration(mut self, from: u32, migrate: Migration) -> Self {
+        let index = from.saturating_sub(1) as usize;
+        if self.migrations.len() <= index {
+            self.migrations.resize(index + 1, None);
+        }
+        self.migrations[index] = Some(migrate);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Copy of the file taken before migrating it from `version`.
+    pub fn backup_path(&self, version: u32) -> PathBuf {
+        with_suffix(&self.path, &format!(".v{}.bak", version))
+    }
+
+    /// Where an unreadable file is moved.
+    pub fn broken_path(&self) -> PathBuf {
+        with_suffix(&self.path, ".broken")
+    }
+
+    /// Load the value, migrating old files and quarantining broken ones.
+    /// A missing file yields the default.
+    pub fn load(&self) -> T {
+        let text = match fs::read_to_string(&self.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return T::default(),
+            Err(e) => {
+                tracing::warn!("Failed to read {}: {}", self.path.display(), e);
+                return T::default();
+            }
+        };
+
+        match self.parse(&text) {
+            Ok(value) => value,
+            Err(reason) => {
+                self.quarantine(&reason);
+                T::default()
+            }
+        }
+    }
+
+    /// Write the value atomically at the current version.
+    pub fn save(&self, value: &T) -> io::Result<()> {
+        let envelope = Envelope {
+            version: self.version,
+            data: value,
+        };
+        let text = serde_json::to_string_pretty(&envelope).map_err(io::Error::other)?;
+        write_atomic(&self.path, &text)
+    }
+
+    fn parse(&self, text: &str) -> Result<T, String> {
+        let envelope: Envelope<Value> = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        let from = envelope.version;
+        if from == 0 || from > self.version {
+            return Err(format!(
+                "unsupported version {} (expected 1 to {})",
+                from, self.version
+            ));
+        }
+
+        if from == self.version {
+            return serde_json::from_value(envelope.data).map_err(|e| e.to_string());
+        }
+
+        fs::copy(&self.path, self.backup_path(from))
+            .map_err(|e| format!("cannot back up before migrating: {}", e))?;
+        let mut data = envelope.data;
+        for version in from..self.version {
+            let migrate = self
+                .migrations
+                .get(version as usize - 1)
+                .copied()
+                .flatten()
+                .ok_or_else(|| format!("no migration from version {}", version))?;
+            data = migrate(data).map_err(|e| format!("migration from {}: {}", version, e))?;
+        }
+        let value = serde_json::from_value(data).map_err(|e| e.to_string())?;
+
+        tracing::info!(
+            "Migrated {} from version {} to {}",
+            self.path.display(),
+            from,
+            self.version
+        );
+        if let Err(e) = self.save(&value) {
+            tracing::warn!("Failed to save migrated {}: {}", self.path.display(), e);
+        }
+        Ok(value)
+    }
+
+    fn quarantine(&self, reason: &str) {
+        let broken = self.broken_path();
+        tracing::warn!(
+            "Unreadable {} ({}), moved to {}",
+            self.path.display(),
+            reason,
+            broken.display()
+        );
+        if let Err(e) = fs::rename(&self.path, &broken) {
+            tracing::warn!("Failed to move {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Write `text` to a temp file next to `path` and rename it over `path`,
+/// creating the directory if needed. Readers see the old or the new
+/// contents, never a mix.
+pub fn write_atomic(path: &Path, text: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = with_suffix(path, ".tmp");
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(text.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)
+}
+
+/// `drafts.json` + `.tmp` = `drafts.json.tmp`
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// Fresh directory per test under the system temp dir
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("vk_core_persist_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Version 2 of a draft store: v1 kept only the text per peer.
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Drafts {
+        drafts: BTreeMap<i64, Draft>,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Draft {
+        text: String,
+        updated_at: i64,
+    }
+
+    fn v1_to_v2(data: Value) -> Result<Value, String> {
+        let Value::Object(texts) = data else {
+            return Err("expected an object".into());
+        };
+        let drafts: serde_json::Map<String, Value> = texts
+            .into_iter()
+            .map(|(peer, text)| (peer, json!({ "text": text, "updated_at": 0 })))
+            .collect();
+        Ok(json!({ "drafts": drafts }))
+    }
+
+    fn drafts_file(dir: &Path) -> PersistedFile<Drafts> {
+        PersistedFile::new(dir.join("drafts.json"), 2).migration(1, v1_to_v2)
+    }
+
+    #[test]
+    fn test_migrates_v1_and_keeps_backup() {
+        let dir = temp_dir("migrate");
+        let v1 = r#"{"version": 1, "data": {"2000000001": "see you", "42": "hi"}}"#;
+        fs::write(dir.join("drafts.json"), v1).unwrap();
+
+        let file = drafts_file(&dir);
+        let drafts = file.load();
+
+        assert_eq!(drafts.drafts.len(), 2);
+        assert_eq!(drafts.drafts[&42].text, "hi");
+        assert_eq!(fs::read_to_string(file.backup_path(1)).unwrap(), v1);
+
+        // Rewritten at v2, so the next load needs no migration
+        let saved: Value = serde_json::from_str(&fs::read_to_string(file.path()).unwrap()).unwrap();
+        assert_eq!(saved["version"], 2);
+        assert_eq!(file.load(), drafts);
+    }
+
+    #[test]
+    fn test_save_round_trip() {
+        let dir = temp_dir("round_trip");
+        let file = drafts_file(&dir);
+        let mut drafts = Drafts::default();
+        drafts.drafts.insert(
+            7,
+            Draft {
+                text: "later".into(),
+                updated_at: 1_700_000_000,
+            },
+        );
+
+        file.save(&drafts).unwrap();
+
+        assert_eq!(file.load(), drafts);
+        assert!(!dir.join("drafts.json.tmp").exists());
+    }
+
+    #[test]
+    fn test_missing_file_is_default() {
+        let dir = temp_dir("missing");
+        assert_eq!(drafts_file(&dir).load(), Drafts::default());
+    }
+
+    #[test]
+    fn test_corrupt_file_is_quarantined() {
+        let dir = temp_dir("corrupt");
+        let file = drafts_file(&dir);
+        fs::write(file.path(), "{\"version\": 2, \"data\": ").unwrap();
+
+        assert_eq!(file.load(), Drafts::default());
+        assert!(!file.path().exists());
+        assert!(file.broken_path().exists());
+    }
+
+    #[test]
+    fn test_newer_or_unmigratable_version_is_quarantined() {
+        let dir = temp_dir("versions");
+        let file = drafts_file(&dir);
+        fs::write(file.path(), r#"{"version": 3, "data": {}}"#).unwrap();
+        assert_eq!(file.load(), Drafts::default());
+        assert!(file.broken_path().exists());
+
+        let no_migrations = PersistedFile::<Drafts>::new(dir.join("other.json"), 2);
+        fs::write(no_migrations.path(), r#"{"version": 1, "data": {}}"#).unwrap();
+        assert_eq!(no_migrations.load(), Drafts::default());
+        assert!(no_migrations.broken_path().exists());
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_file() {
+        let dir = temp_dir("atomic");
+        let path = dir.join("nested").join("settings.toml");
+
+        write_atomic(&path, "a = 1\n").unwrap();
+        write_atomic(&path, "a = 2\n").unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a = 2\n");
+        assert!(!with_suffix(&path, ".tmp").exists());
+    }
+}
@@ -0,0 +1,211 @@
+//! Loading missing user profiles before they are shown.
+//!
+//! Pickers list people whose profiles may not have come with any response
+//! yet (chat members, direct chat peers). Instead of showing `User 123`,
+//! frontends ask [`ProfileWarmup`] which ids to fetch, send one
+//! [`AsyncCommand::LoadUsers`](crate::AsyncCommand::LoadUsers) for them and
+//! show [`LOADING_NAME`] until `CoreEvent::UsersLoaded` arrives.
+
+use std::collections::HashSet;
+
+use vk_api::{ProfileInfo, User, is_chat_peer};
+
+use crate::models::{Chat, ChatMessage};
+
+/// User fields requested for a profile card.
+pub const DETAIL_FIELDS: &[&str] = &[
+    "city",
+    "last_seen",
+    "online",
+    "status",
+    "bdate",
+    "photo_100",
+    "photo_200",
+    "screen_name",
+];
+
+/// Community fields requested for a profile card.
+pub const GROUP_DETAIL_FIELDS: &[&str] = &["status", "members_count"];
+
+/// Placeholder name for a profile that is being loaded.
+pub const LOADING_NAME: &str = "loading…";
+
+/// Tracks profile requests in flight so that reopening a picker does not
+/// request the same users again.
+#[derive(Debug, Clone, Default)]
+pub struct ProfileWarmup {
+    pending: HashSet<i64>,
+}
+
+impl ProfileWarmup {
+    /// Pick the ids that need loading: users that are not `known` and not
+    /// already requested. They are marked pending until [`finish`](Self::finish).
+    pub fn request(
+        &mut self,
+        ids: impl IntoIterator<Item = i64>,
+        known: impl Fn(i64) -> bool,
+    ) -> Vec<i64> {
+        let mut missing = Vec::new();
+        for id in ids {
+            if id > 0 && !is_chat_peer(id) && !known(id) && self.pending.insert(id) {
+                missing.push(id);
+            }
+        }
+        missing
+    }
+
+    /// Clear requested ids once the response (or an error) came back.
+    pub fn finish(&mut self, ids: &[i64]) {
+        for id in ids {
+            self.pending.remove(id);
+        }
+    }
+
+    /// Whether the profile of `user_id` is being loaded.
+    pub fn is_pending(&self, user_id: i64) -> bool {
+        self.pending.contains(&user_id)
+    }
+}
+
+/// Ids worth warming up for a picker: direct chat peers and, in a group
+/// chat, the senders of loaded messages.
+pub fn warmup_candidates(
+    chats: &[Chat],
+    messages: &[ChatMessage],
+    current_peer_id: Option<i64>,
+) -> Vec<i64> {
+    let mut ids: Vec<i64> = chats.iter().map(|c| c.id).collect();
+    if current_peer_id.is_some_and(is_chat_peer) {
+        ids.extend(messages.iter().map(|m| m.from_id));
+    }
+    ids
+}
+
+/// Update names and avatars in place after profiles were loaded.
+pub fn refresh_names(chats: &mut [Chat], messages: &mut [ChatMessage], users: &[User]) {
+    for user in users {
+        if let Some(chat) = chats.iter_mut().find(|c| c.id == user.id) {
+            chat.title = user.full_name();
+            if chat.photo_url.is_none() {
+                chat.photo_url = user.photo_50.clone();
+            }
+        }
+        for msg in messages.iter_mut().filter(|m| m.from_id == user.id) {
+            msg.from_name = user.full_name();
+            if msg.from_photo.is_none() {
+                msg.from_photo = user.photo_50.clone();
+            }
+        }
+    }
+}
+
+/// Id of the logged-in account: the loaded profile's, else the one saved
+/// with the token. Tokens pasted without a `user_id` store 0, which is
+/// no id at all.
+pub fn own_user_id(profile: Option<&ProfileInfo>, token_user_id: Option<i64>) -> Option<i64> {
+    profile
+        .map(|p| p.id)
+        .into_iter()
+        .chain(token_user_id)
+        .find(|&id| id != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, chat};
+
+    const GROUP_CHAT: i64 = 2_000_000_001;
+
+    fn user(id: i64) -> User {
+        User {
+            id,
+            first_name: "Ann".into(),
+            last_name: format!("#{}", id),
+            photo_50: Some(format!("https://example.com/{}.jpg", id)),
+            photo_100: None,
+            photo_200: None,
+            online: None,
+            last_seen: None,
+            screen_name: None,
+            status: None,
+            bdate: None,
+            city: None,
+        }
+    }
+
+    fn message(from_id: i64) -> ChatMessage {
+        ChatMessage {
+            from_id,
+            from_name: format!("User {}", from_id),
+            ..fixtures::message(1, "")
+        }
+    }
+
+    #[test]
+    fn test_request_skips_known_groups_and_chats() {
+        let mut warmup = ProfileWarmup::default();
+
+        let missing = warmup.request([1, 2, -3, GROUP_CHAT, 2], |id| id == 1);
+
+        assert_eq!(missing, vec![2]);
+        assert!(warmup.is_pending(2));
+        assert!(!warmup.is_pending(1));
+    }
+
+    #[test]
+    fn test_repeated_request_is_deduplicated_until_finished() {
+        let mut warmup = ProfileWarmup::default();
+        assert_eq!(warmup.request([5], |_| false), vec![5]);
+
+        // Picker closed and reopened before the response
+        assert!(warmup.request([5], |_| false).is_empty());
+
+        warmup.finish(&[5]);
+        assert!(!warmup.is_pending(5));
+        assert_eq!(warmup.request([5], |_| false), vec![5]);
+    }
+
+    #[test]
+    fn test_candidates_include_senders_only_in_group_chats() {
+        let chats = vec![chat(7), chat(GROUP_CHAT)];
+        let messages = vec![message(8), message(-9)];
+
+        assert_eq!(
+            warmup_candidates(&chats, &messages, Some(7)),
+            vec![7, GROUP_CHAT]
+        );
+        assert_eq!(
+            warmup_candidates(&chats, &messages, Some(GROUP_CHAT)),
+            vec![7, GROUP_CHAT, 8, -9]
+        );
+    }
+
+    #[test]
+    fn test_refresh_names_in_place() {
+        let mut chats = vec![chat(7), chat(GROUP_CHAT)];
+        let mut messages = vec![message(7), message(8)];
+
+        refresh_names(&mut chats, &mut messages, &[user(7)]);
+
+        assert_eq!(chats[0].title, "Ann #7");
+        assert!(chats[0].photo_url.is_some());
+        assert_eq!(chats[1].title, format!("Chat {}", GROUP_CHAT));
+        assert_eq!(messages[0].from_name, "Ann #7");
+        assert_eq!(messages[1].from_name, "User 8");
+    }
+
+    #[test]
+    fn test_own_user_id_prefers_profile() {
+        let profile: ProfileInfo = serde_json::from_value(serde_json::json!({
+            "id": 42, "first_name": "Ann", "last_name": "Lee"
+        }))
+        .unwrap();
+        assert_eq!(own_user_id(Some(&profile), Some(0)), Some(42));
+        assert_eq!(own_user_id(None, Some(7)), Some(7));
+        assert_eq!(own_user_id(None, Some(0)), None);
+
+        let without_id = ProfileInfo { id: 0, ..profile };
+        assert_eq!(own_user_id(Some(&without_id), Some(7)), Some(7));
+    }
+}
@@ -6,7 +6,7 @@ use std::collections::HashMap;
 use std::sync::Arc;
 
 use vk_api::auth::AuthManager;
-use vk_api::{User, VkClient};
+use vk_api::{ProfileInfo, User, VkClient};
 
 use crate::models::{Chat, ChatMessage, SearchResult};
 
@@ -22,6 +22,11 @@ pub struct MessagesPagination {
     pub first_cmid: Option<i64>,
     /// Last (newest loaded) conversation_message_id.
     pub last_cmid: Option<i64>,
+    /// Newer messages than `last_cmid` were dropped from memory and are
+    /// loaded again when scrolling down.
+    pub has_newer: bool,
+    /// Messages shown are from the cache; the first page replaces them.
+    pub from_cache: bool,
 }
 
 impl MessagesPagination {
@@ -34,6 +39,8 @@ impl MessagesPagination {
             has_more: true,
             first_cmid: None,
             last_cmid: None,
+            has_newer: false,
+            from_cache: false,
         }
     }
 }
@@ -59,7 +66,8 @@ pub struct CoreState {
 
     // User data
     pub users: HashMap<i64, User>,
-    pub current_user: Option<User>,
+    /// Logged-in account, once `CoreEvent::ProfileLoaded` arrived
+    pub current_user: Option<ProfileInfo>,
 
     // Chat data
     pub chats: Vec<Chat>,
@@ -115,6 +123,11 @@ impl CoreState {
         }
     }
 
+    /// Id of the logged-in account, if known.
+    pub fn current_user_id(&self) -> Option<i64> {
+        crate::profiles::own_user_id(self.current_user.as_ref(), self.auth.user_id())
+    }
+
     /// Check if authenticated.
     pub fn is_authenticated(&self) -> bool {
         self.vk_client.is_some()
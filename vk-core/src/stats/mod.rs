@@ -0,0 +1,199 @@
+//! Session counters for the stats popup.
+//!
+//! Frontends feed [`Stats`] from the events they already handle. API call
+//! counters are not kept here: they come from [`vk_api::VkClient::metrics`].
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Messages per chat and connection counters since startup or the last
+/// [`reset`](Stats::reset).
+#[derive(Debug, Clone)]
+pub struct Stats {
+    started: Instant,
+    /// Messages received and sent per peer
+    messages: HashMap<i64, MessageCounts>,
+    reconnects: u32,
+    /// Long Poll key expiries (`failed: 2/3`)
+    key_failures: u32,
+    /// Times another client was suspected of using the token
+    concurrent_session_warnings: u32,
+    /// Last reported connection state
+    connected: Option<bool>,
+    /// Reads of the local cache that found something, and that did not
+    cache: CacheCounts,
+}
+
+/// Reads of the local message cache.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheCounts {
+    pub hits: u32,
+    pub misses: u32,
+}
+
+/// Message counters of one chat.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MessageCounts {
+    pub received: u32,
+    pub sent: u32,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self {
+            started: Instant::now(),
+            messages: HashMap::new(),
+            reconnects: 0,
+            key_failures: 0,
+            concurrent_session_warnings: 0,
+            connected: None,
+            cache: CacheCounts::default(),
+        }
+    }
+}
+
+impl Stats {
+    /// Count a new message from Long Poll.
+    pub fn record_message(&mut self, peer_id: i64, is_outgoing: bool) {
+        let counts = self.messages.entry(peer_id).or_default();
+        if is_outgoing {
+            counts.sent += 1;
+        } else {
+            counts.received += 1;
+        }
+    }
+
+    /// Track Long Poll connection changes; coming back up after a loss
+    /// counts as a reconnect.
+    pub fn record_connection(&mut self, connected: bool) {
+        if connected && self.connected == Some(false) {
+            self.reconnects += 1;
+        }
+        self.connected = Some(connected);
+    }
+
+    pub fn record_key_failure(&mut self) {
+        self.key_failures += 1;
+    }
+
+    pub fn record_concurrent_session(&mut self) {
+        self.concurrent_session_warnings += 1;
+    }
+
+    /// Count a read of the local cache; `hit` if it had what was asked.
+    pub fn record_cache_read(&mut self, hit: bool) {
+        if hit {
+            self.cache.hits += 1;
+        } else {
+            self.cache.misses += 1;
+        }
+    }
+
+    /// Clear all counters and restart the uptime clock.
+    pub fn reset(&mut self) {
+        *self = Self {
+            connected: self.connected,
+            ..Self::default()
+        };
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn reconnects(&self) -> u32 {
+        self.reconnects
+    }
+
+    pub fn key_failures(&self) -> u32 {
+        self.key_failures
+    }
+
+    pub fn concurrent_session_warnings(&self) -> u32 {
+        self.concurrent_session_warnings
+    }
+
+    pub fn cache(&self) -> CacheCounts {
+        self.cache
+    }
+
+    /// Totals over all chats.
+    pub fn total(&self) -> MessageCounts {
+        self.messages
+            .values()
+            .fold(MessageCounts::default(), |acc, c| MessageCounts {
+                received: acc.received + c.received,
+                sent: acc.sent + c.sent,
+            })
+    }
+
+    /// Per-chat counters, busiest chat first.
+    pub fn by_chat(&self) -> Vec<(i64, MessageCounts)> {
+        let mut chats: Vec<_> = self.messages.iter().map(|(id, c)| (*id, *c)).collect();
+        chats.sort_by_key(|(id, c)| (std::cmp::Reverse(c.received + c.sent), *id));
+        chats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counts_messages_per_chat() {
+        let mut stats = Stats::default();
+        stats.record_message(1, false);
+        stats.record_message(2, false);
+        stats.record_message(2, true);
+        stats.record_message(2, false);
+
+        assert_eq!(
+            stats.total(),
+            MessageCounts {
+                received: 3,
+                sent: 1
+            }
+        );
+        let by_chat = stats.by_chat();
+        assert_eq!(by_chat[0].0, 2);
+        assert_eq!(
+            by_chat[0].1,
+            MessageCounts {
+                received: 2,
+                sent: 1
+            }
+        );
+        assert_eq!(by_chat[1].0, 1);
+    }
+
+    #[test]
+    fn test_reconnects_count_recoveries_only() {
+        let mut stats = Stats::default();
+        stats.record_connection(true);
+        stats.record_connection(true);
+        assert_eq!(stats.reconnects(), 0);
+
+        stats.record_connection(false);
+        stats.record_connection(false);
+        stats.record_connection(true);
+        assert_eq!(stats.reconnects(), 1);
+    }
+
+    #[test]
+    fn test_reset_keeps_connection_state() {
+        let mut stats = Stats::default();
+        stats.record_message(1, false);
+        stats.record_key_failure();
+        stats.record_cache_read(true);
+        stats.record_cache_read(false);
+        assert_eq!(stats.cache(), CacheCounts { hits: 1, misses: 1 });
+        stats.record_connection(false);
+        stats.reset();
+
+        assert_eq!(stats.total(), MessageCounts::default());
+        assert_eq!(stats.key_failures(), 0);
+        assert_eq!(stats.cache(), CacheCounts::default());
+        stats.record_connection(true);
+        assert_eq!(stats.reconnects(), 1);
+    }
+}
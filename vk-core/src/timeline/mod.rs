@@ -0,0 +1,230 @@
+//! Times and day separators in message lists.
+//!
+//! [`Clock`] holds how the user wants times shown: 24- or 12-hour, and the
+//! offset of their timezone from UTC. [`day_rows`] turns a run of messages
+//! into the rows a frontend draws, with a separator row wherever a new
+//! calendar day starts.
+
+use std::ops::Range;
+
+use serde::Deserialize;
+use time::macros::format_description;
+use time::{OffsetDateTime, UtcOffset};
+
+use crate::models::ChatMessage;
+
+/// "14:05" or "2:05 PM".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum HourFormat {
+    #[default]
+    #[serde(rename = "24h")]
+    H24,
+    #[serde(rename = "12h")]
+    H12,
+}
+
+/// How message times are shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Clock {
+    pub hours: HourFormat,
+    /// Offset of the user's timezone from UTC
+    pub offset: UtcOffset,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self {
+            hours: HourFormat::H24,
+            offset: UtcOffset::UTC,
+        }
+    }
+}
+
+impl Clock {
+    fn local(&self, ts: i64) -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(ts)
+            .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+            .to_offset(self.offset)
+    }
+
+    /// Time of day: "14:05" or "2:05 PM".
+    pub fn time(&self, ts: i64) -> String {
+        let dt = self.local(ts);
+        let formatted = match self.hours {
+            HourFormat::H24 => dt.format(&format_description!("[hour]:[minute]")),
+            HourFormat::H12 => dt.format(&format_description!(
+                "[hour repr:12 padding:none]:[minute] [period]"
+            )),
+        };
+        formatted.unwrap_or_else(|_| "--:--".into())
+    }
+
+    /// Time shown next to a message: the time of day if it was sent in
+    /// the last 24 hours, otherwise the date ("12.03.2025").
+    pub fn message_time(&self, ts: i64, now: i64) -> String {
+        if now - ts < 24 * 60 * 60 {
+            return self.time(ts);
+        }
+        self.local(ts)
+            .format(&format_description!("[day].[month].[year]"))
+            .unwrap_or_else(|_| "--.--.----".into())
+    }
+
+    /// Date of a day separator: "12 March 2025".
+    pub fn day_label(&self, ts: i64) -> String {
+        self.local(ts)
+            .format(&format_description!(
+                "[day padding:none] [month repr:long] [year]"
+            ))
+            .unwrap_or_default()
+    }
+
+    /// Whether both timestamps fall on the same calendar day.
+    pub fn same_day(&self, a: i64, b: i64) -> bool {
+        self.local(a).date() == self.local(b).date()
+    }
+}
+
+/// Parse a UTC offset: "+03:00", "-05:30", "+3" or "UTC".
+pub fn parse_utc_offset(s: &str) -> Option<UtcOffset> {
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("utc") || s.eq_ignore_ascii_case("z") {
+        return Some(UtcOffset::UTC);
+    }
+    let (sign, rest) = match s.as_bytes().first()? {
+        b'+' => (1, &s[1..]),
+        b'-' => (-1, &s[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((h, m)) => (h.parse::<i8>().ok()?, m.parse::<i8>().ok()?),
+        None => (rest.parse::<i8>().ok()?, 0),
+    };
+    if !(0..60).contains(&minutes) {
+        return None;
+    }
+    UtcOffset::from_hms(sign * hours, sign * minutes, 0).ok()
+}
+
+/// A row of a message list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Row {
+    /// Separator before the first message of a day: its date
+    Day(String),
+    /// Index of a message
+    Message(usize),
+}
+
+/// Whether the message at `idx` is the first of its day in the list.
+pub fn starts_day(messages: &[ChatMessage], idx: usize, clock: &Clock) -> bool {
+    idx == 0 || !clock.same_day(messages[idx - 1].timestamp, messages[idx].timestamp)
+}
+
+/// Rows for the messages in `range`, each new day preceded by its
+/// separator. Messages before `range` are looked at, so a range starting
+/// mid-day does not get a separator.
+pub fn day_rows(messages: &[ChatMessage], range: Range<usize>, clock: &Clock) -> Vec<Row> {
+    let mut rows = Vec::with_capacity(range.len() + 1);
+    for idx in range {
+        if starts_day(messages, idx, clock) {
+            rows.push(Row::Day(clock.day_label(messages[idx].timestamp)));
+        }
+        rows.push(Row::Message(idx));
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::message;
+
+    // 12 March 2025, 13:05 UTC
+    const MARCH_12: i64 = 1_741_784_700;
+
+    fn sent_at(id: i64, timestamp: i64) -> ChatMessage {
+        ChatMessage {
+            timestamp,
+            ..message(id, "")
+        }
+    }
+
+    fn clock(hours: HourFormat, offset: &str) -> Clock {
+        Clock {
+            hours,
+            offset: parse_utc_offset(offset).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_time_formats() {
+        let utc = Clock::default();
+        assert_eq!(utc.time(MARCH_12), "13:05");
+        assert_eq!(clock(HourFormat::H12, "UTC").time(MARCH_12), "1:05 PM");
+        assert_eq!(clock(HourFormat::H24, "+03:00").time(MARCH_12), "16:05");
+        assert_eq!(clock(HourFormat::H12, "-05:30").time(MARCH_12), "7:35 AM");
+
+        assert_eq!(utc.message_time(MARCH_12, MARCH_12 + 60), "13:05");
+        assert_eq!(
+            utc.message_time(MARCH_12, MARCH_12 + 3 * 86_400),
+            "12.03.2025"
+        );
+        assert_eq!(utc.day_label(MARCH_12), "12 March 2025");
+    }
+
+    #[test]
+    fn test_parse_utc_offset() {
+        assert_eq!(parse_utc_offset("UTC"), Some(UtcOffset::UTC));
+        assert_eq!(parse_utc_offset("+3"), UtcOffset::from_hms(3, 0, 0).ok());
+        assert_eq!(
+            parse_utc_offset("+05:45"),
+            UtcOffset::from_hms(5, 45, 0).ok()
+        );
+        assert_eq!(
+            parse_utc_offset("-03:30"),
+            UtcOffset::from_hms(-3, -30, 0).ok()
+        );
+        assert_eq!(parse_utc_offset("3"), None);
+        assert_eq!(parse_utc_offset("+03:75"), None);
+        assert_eq!(parse_utc_offset("Europe/Moscow"), None);
+    }
+
+    #[test]
+    fn test_day_rows() {
+        let messages = vec![
+            sent_at(1, MARCH_12),
+            sent_at(2, MARCH_12 + 3600),
+            sent_at(3, MARCH_12 + 86_400),
+            sent_at(4, MARCH_12 + 86_400 + 60),
+        ];
+        let utc = Clock::default();
+        assert_eq!(
+            day_rows(&messages, 0..4, &utc),
+            [
+                Row::Day("12 March 2025".into()),
+                Row::Message(0),
+                Row::Message(1),
+                Row::Day("13 March 2025".into()),
+                Row::Message(2),
+                Row::Message(3),
+            ]
+        );
+        // Starting mid-day: no separator until the next day
+        assert_eq!(
+            day_rows(&messages, 1..3, &utc),
+            [
+                Row::Message(1),
+                Row::Day("13 March 2025".into()),
+                Row::Message(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_days_follow_the_offset() {
+        // 23:30 and 00:30 the next day in UTC are the same day at UTC-3
+        let messages = vec![sent_at(1, MARCH_12 + 37_500), sent_at(2, MARCH_12 + 41_100)];
+        assert!(starts_day(&messages, 1, &Clock::default()));
+        assert!(!starts_day(&messages, 1, &clock(HourFormat::H24, "-03:00")));
+    }
+}
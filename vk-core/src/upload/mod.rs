@@ -0,0 +1,314 @@
+//! Sending several files in one message.
+//!
+//! Files are uploaded one after another and their attachment ids combined
+//! into one messages.send. A file that fails to upload is skipped and
+//! reported by name; the message still goes out with the rest, unless
+//! nothing uploaded at all.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use vk_api::{UploadProgress, VkClient};
+
+/// Largest document VK accepts.
+pub const MAX_DOC_SIZE: u64 = 200 * 1024 * 1024;
+
+/// Largest photo VK accepts.
+pub const MAX_PHOTO_SIZE: u64 = 50 * 1024 * 1024;
+
+/// Extensions VK takes as photos; other images go as documents.
+pub const PHOTO_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif"];
+
+/// What a batch of files is uploaded as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadKind {
+    Photo,
+    Doc,
+}
+
+impl UploadKind {
+    /// Largest file VK accepts as this kind.
+    pub fn max_size(self) -> u64 {
+        match self {
+            UploadKind::Photo => MAX_PHOTO_SIZE,
+            UploadKind::Doc => MAX_DOC_SIZE,
+        }
+    }
+}
+
+/// Why a file cannot be sent as the kind asked for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rejected {
+    /// The extension is not one of [`PHOTO_EXTENSIONS`]
+    NotAPhoto,
+    /// Larger than [`UploadKind::max_size`]
+    TooLarge { size: u64, max: u64 },
+}
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rejected::NotAPhoto => {
+                let extensions = PHOTO_EXTENSIONS.join(", ");
+                write!(f, "only {} files can be sent as photos", extensions)
+            }
+            Rejected::TooLarge { size, max } => write!(
+                f,
+                "{} MB is over the {} MB limit",
+                size.div_ceil(1024 * 1024),
+                max / (1024 * 1024)
+            ),
+        }
+    }
+}
+
+/// Check a file of `size` bytes before uploading it as `kind`.
+pub fn check_file(path: &Path, size: u64, kind: UploadKind) -> Result<(), Rejected> {
+    if kind == UploadKind::Photo && !is_photo(path) {
+        return Err(Rejected::NotAPhoto);
+    }
+    let max = kind.max_size();
+    if size > max {
+        return Err(Rejected::TooLarge { size, max });
+    }
+    Ok(())
+}
+
+/// Whether `path` has one of [`PHOTO_EXTENSIONS`], in any case.
+pub fn is_photo(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            PHOTO_EXTENSIONS
+                .iter()
+                .any(|photo| photo.eq_ignore_ascii_case(ext))
+        })
+}
+
+/// Result of [`upload_files`].
+#[derive(Debug, Default)]
+pub struct UploadBatch {
+    /// Attachment strings of the uploaded files, in order
+    pub attachments: Vec<String>,
+    /// Files that could not be uploaded
+    pub failed: Vec<(PathBuf, vk_api::Error)>,
+}
+
+impl UploadBatch {
+    /// The session expired during the batch; the rest was not tried.
+    pub fn auth_failed(&self) -> bool {
+        self.failed.iter().any(|(_, e)| e.is_auth())
+    }
+
+    /// File names of the failed uploads.
+    pub fn failed_names(&self) -> Vec<String> {
+        self.failed
+            .iter()
+            .map(|(path, _)| file_title(path))
+            .collect()
+    }
+
+    /// "Failed to upload a.pdf: <error>; b.pdf: <error>"
+    pub fn failure_text(&self) -> String {
+        let parts: Vec<String> = self
+            .failed
+            .iter()
+            .map(|(path, e)| format!("{}: {}", file_title(path), e))
+            .collect();
+        format!("Failed to upload {}", parts.join("; "))
+    }
+}
+
+/// Upload `paths` one at a time. `progress` sees the bytes of the whole
+/// batch, so a bar fills once across all files.
+pub async fn upload_files(
+    client: &VkClient,
+    peer_id: i64,
+    kind: UploadKind,
+    paths: &[PathBuf],
+    progress: UploadProgress,
+) -> UploadBatch {
+    let sizes: Vec<u64> = paths
+        .iter()
+        .map(|p| std::fs::metadata(p).map_or(0, |m| m.len()))
+        .collect();
+    let total = sizes.iter().sum();
+
+    let mut batch = UploadBatch::default();
+    let mut done = 0;
+    for (path, size) in paths.iter().zip(sizes) {
+        let file_progress = batch_progress(progress.clone(), done, size, total);
+        let messages = client.messages();
+        let result = match kind {
+            UploadKind::Photo => {
+                messages
+                    .upload_photo(peer_id, path, Some(file_progress))
+                    .await
+            }
+            UploadKind::Doc => {
+                messages
+                    .upload_doc(peer_id, path, Some(file_progress))
+                    .await
+            }
+        };
+        done += size;
+
+        match result {
+            Ok(attachment) => batch.attachments.push(attachment),
+            Err(e) => {
+                let auth = e.is_auth();
+                batch.failed.push((path.clone(), e));
+                if auth {
+                    break;
+                }
+            }
+        }
+    }
+    batch
+}
+
+/// Progress of one file, `size` bytes after `done`, as part of the batch.
+/// The upload body is slightly larger than the file, so it is scaled.
+fn batch_progress(progress: UploadProgress, done: u64, size: u64, total: u64) -> UploadProgress {
+    Arc::new(move |sent, body_len| {
+        let sent = sent.min(body_len) * size / body_len.max(1);
+        progress(done + sent, total);
+    })
+}
+
+/// Name a file is shown under while it uploads.
+pub fn file_title(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// Paths of a `/sendfile`-style argument. The whole argument is one path
+/// if such a file exists, so unquoted names with spaces keep working;
+/// otherwise it is split at spaces, and quotes group a name.
+pub fn split_paths(arg: &str) -> Vec<String> {
+    let arg = arg.trim();
+    if arg.is_empty() {
+        return Vec::new();
+    }
+    if Path::new(arg).exists() {
+        return vec![arg.to_string()];
+    }
+
+    let mut paths = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    for c in arg.chars() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if c == q => quote = None,
+            (None, c) if c.is_whitespace() => {
+                if !current.is_empty() {
+                    paths.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        paths.push(current);
+    }
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_split_paths() {
+        assert_eq!(split_paths("a.pdf b.pdf"), ["a.pdf", "b.pdf"]);
+        assert_eq!(
+            split_paths("  \"my notes.txt\"  'x y.png' z "),
+            ["my notes.txt", "x y.png", "z"]
+        );
+        assert!(split_paths("   ").is_empty());
+    }
+
+    #[test]
+    fn test_split_paths_keeps_existing_file_whole() {
+        let dir = std::env::temp_dir().join(format!("vk_core_upload_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("two words.txt");
+        std::fs::write(&path, b"x").unwrap();
+
+        let arg = path.to_str().unwrap();
+        assert_eq!(split_paths(arg), [arg]);
+    }
+
+    #[test]
+    fn test_check_file() {
+        let photo = Path::new("/tmp/cat.JPG");
+        assert_eq!(check_file(photo, 1024, UploadKind::Photo), Ok(()));
+        assert_eq!(
+            check_file(Path::new("cat.webp"), 1024, UploadKind::Photo),
+            Err(Rejected::NotAPhoto)
+        );
+        assert_eq!(
+            check_file(Path::new("noext"), 1024, UploadKind::Photo),
+            Err(Rejected::NotAPhoto)
+        );
+        // Anything goes as a document, up to its larger limit
+        assert_eq!(
+            check_file(Path::new("cat.webp"), MAX_PHOTO_SIZE + 1, UploadKind::Doc),
+            Ok(())
+        );
+        let too_large = check_file(photo, MAX_PHOTO_SIZE + 1, UploadKind::Photo);
+        assert_eq!(
+            too_large,
+            Err(Rejected::TooLarge {
+                size: MAX_PHOTO_SIZE + 1,
+                max: MAX_PHOTO_SIZE
+            })
+        );
+        assert_eq!(
+            too_large.unwrap_err().to_string(),
+            "51 MB is over the 50 MB limit"
+        );
+        assert!(check_file(Path::new("a.iso"), MAX_DOC_SIZE + 1, UploadKind::Doc).is_err());
+    }
+
+    #[test]
+    fn test_batch_progress_spans_files() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        let progress: UploadProgress = Arc::new(move |sent, total| {
+            sink.lock().unwrap().push((sent, total));
+        });
+
+        // Second of two 100-byte files, uploaded as a 120-byte body
+        let file = batch_progress(progress, 100, 100, 200);
+        file(0, 120);
+        file(60, 120);
+        file(120, 120);
+        assert_eq!(*seen.lock().unwrap(), [(100, 200), (150, 200), (200, 200)]);
+    }
+
+    #[test]
+    fn test_failure_text_names_files() {
+        let batch = UploadBatch {
+            attachments: vec!["doc1_2".into()],
+            failed: vec![
+                (
+                    PathBuf::from("/tmp/a.pdf"),
+                    vk_api::Error::Upload("timeout".into()),
+                ),
+                (
+                    PathBuf::from("b.pdf"),
+                    vk_api::Error::Upload("refused".into()),
+                ),
+            ],
+        };
+        assert_eq!(batch.failed_names(), ["a.pdf", "b.pdf"]);
+        assert!(!batch.auth_failed());
+        let text = batch.failure_text();
+        assert!(text.starts_with("Failed to upload a.pdf: "));
+        assert!(text.contains("; b.pdf: "));
+    }
+}
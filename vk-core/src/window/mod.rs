@@ -0,0 +1,275 @@
+//! Bounded list of messages for the open chat.
+//!
+//! Scrolling through a long history would otherwise keep every page in
+//! memory. After a page is added at one end, [`trim_window`] drops messages
+//! from the other end so at most a window of them stays loaded, and moves
+//! the pagination cursors so the dropped part is loaded again when the user
+//! scrolls back to it.
+//!
+//! [`anchor_insert`] keeps the cursor on the message the user is reading
+//! when messages are added above or below it.
+
+use crate::models::ChatMessage;
+use crate::state::MessagesPagination;
+
+/// Messages kept in memory for the open chat unless configured otherwise.
+pub const DEFAULT_MESSAGE_WINDOW: usize = 500;
+
+/// Smallest window accepted, so one page always fits.
+pub const MIN_MESSAGE_WINDOW: usize = 100;
+
+/// End of the list a page was just added to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grown {
+    /// Older messages were prepended.
+    Older,
+    /// Newer messages were appended.
+    Newer,
+}
+
+/// Keep at most `max` messages, dropping from the end opposite to `grown`.
+/// `scroll` is moved to stay on the same message, and `pagination` records
+/// that there is more to load past the dropped end. Returns how many
+/// messages were dropped.
+pub fn trim_window(
+    messages: &mut Vec<ChatMessage>,
+    scroll: &mut usize,
+    pagination: &mut MessagesPagination,
+    max: usize,
+    grown: Grown,
+) -> usize {
+    let max = max.max(MIN_MESSAGE_WINDOW);
+    let excess = messages.len().saturating_sub(max);
+    if excess == 0 {
+        return 0;
+    }
+
+    match grown {
+        Grown::Older => {
+            messages.truncate(max);
+            *scroll = (*scroll).min(max - 1);
+            pagination.has_newer = true;
+            pagination.last_cmid = messages.iter().rev().find_map(|m| m.cmid);
+        }
+        Grown::Newer => {
+            messages.drain(..excess);
+            *scroll = scroll.saturating_sub(excess);
+            pagination.has_more = true;
+            pagination.first_cmid = messages.iter().find_map(|m| m.cmid);
+        }
+    }
+    excess
+}
+
+/// Cursor position after messages were inserted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Anchored {
+    pub scroll: usize,
+    /// Messages that landed below a cursor that did not follow them
+    pub unseen: usize,
+}
+
+/// Move the cursor after `count` messages were inserted at index `at` into
+/// a list of `old_len`. A cursor on the last message follows the list to
+/// its new end; anywhere else it stays on the message it was on.
+pub fn anchor_insert(scroll: usize, old_len: usize, at: usize, count: usize) -> Anchored {
+    let len = old_len + count;
+    if scroll + 1 >= old_len {
+        return Anchored {
+            scroll: len.saturating_sub(1),
+            unseen: 0,
+        };
+    }
+    if at <= scroll {
+        Anchored {
+            scroll: scroll + count,
+            unseen: 0,
+        }
+    } else {
+        Anchored {
+            scroll,
+            unseen: count,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::message;
+
+    fn history(ids: std::ops::RangeInclusive<i64>) -> Vec<ChatMessage> {
+        ids.map(|id| message(id, "")).collect()
+    }
+
+    #[test]
+    fn test_prepend_drops_newest() {
+        // 50 older messages were prepended to 500; the user is at the top
+        let mut messages = history(1..=550);
+        let mut scroll = 50;
+        let mut pagination = MessagesPagination::new(1);
+        pagination.has_more = false;
+
+        let dropped = trim_window(
+            &mut messages,
+            &mut scroll,
+            &mut pagination,
+            500,
+            Grown::Older,
+        );
+        assert_eq!(dropped, 50);
+        assert_eq!(messages.len(), 500);
+        assert_eq!(messages[scroll].id, 51);
+        assert_eq!(messages.last().unwrap().id, 500);
+        // Newer messages are loaded again from the new last one
+        assert!(pagination.has_newer);
+        assert_eq!(pagination.last_cmid, Some(500));
+        assert!(!pagination.has_more);
+    }
+
+    #[test]
+    fn test_append_drops_oldest() {
+        let mut messages = history(1..=550);
+        let mut scroll = 520;
+        let mut pagination = MessagesPagination::new(1);
+        pagination.has_more = false;
+        pagination.first_cmid = Some(1);
+
+        let dropped = trim_window(
+            &mut messages,
+            &mut scroll,
+            &mut pagination,
+            500,
+            Grown::Newer,
+        );
+        assert_eq!(dropped, 50);
+        assert_eq!(messages.first().unwrap().id, 51);
+        assert_eq!(messages[scroll].id, 521);
+        // The dropped oldest messages are loaded again from the new first one
+        assert!(pagination.has_more);
+        assert_eq!(pagination.first_cmid, Some(51));
+        assert!(!pagination.has_newer);
+    }
+
+    #[test]
+    fn test_scroll_in_dropped_part_is_clamped() {
+        let mut messages = history(1..=550);
+        let mut pagination = MessagesPagination::new(1);
+
+        let mut scroll = 540;
+        trim_window(
+            &mut messages,
+            &mut scroll,
+            &mut pagination,
+            500,
+            Grown::Older,
+        );
+        assert_eq!(scroll, 499);
+
+        let mut messages = history(1..=550);
+        let mut scroll = 10;
+        trim_window(
+            &mut messages,
+            &mut scroll,
+            &mut pagination,
+            500,
+            Grown::Newer,
+        );
+        assert_eq!(scroll, 0);
+    }
+
+    #[test]
+    fn test_within_window_is_untouched() {
+        let mut messages = history(1..=500);
+        let mut scroll = 7;
+        let mut pagination = MessagesPagination::new(1);
+        pagination.has_more = false;
+
+        let dropped = trim_window(
+            &mut messages,
+            &mut scroll,
+            &mut pagination,
+            500,
+            Grown::Newer,
+        );
+        assert_eq!(dropped, 0);
+        assert_eq!(messages.len(), 500);
+        assert_eq!(scroll, 7);
+        assert!(!pagination.has_more);
+    }
+
+    #[test]
+    fn test_cursor_skips_messages_without_cmid() {
+        // A pending message at the end has no cmid yet
+        let mut messages = history(1..=550);
+        messages[499].cmid = None;
+        let mut scroll = 0;
+        let mut pagination = MessagesPagination::new(1);
+
+        trim_window(
+            &mut messages,
+            &mut scroll,
+            &mut pagination,
+            500,
+            Grown::Older,
+        );
+        assert_eq!(pagination.last_cmid, Some(499));
+    }
+
+    #[test]
+    fn test_tiny_window_is_raised() {
+        let mut messages = history(1..=150);
+        let mut scroll = 0;
+        let mut pagination = MessagesPagination::new(1);
+
+        trim_window(
+            &mut messages,
+            &mut scroll,
+            &mut pagination,
+            10,
+            Grown::Older,
+        );
+        assert_eq!(messages.len(), MIN_MESSAGE_WINDOW);
+    }
+
+    #[test]
+    fn test_anchor_follows_from_bottom() {
+        assert_eq!(
+            anchor_insert(9, 10, 10, 1),
+            Anchored {
+                scroll: 10,
+                unseen: 0
+            }
+        );
+        // First messages of an empty chat
+        assert_eq!(anchor_insert(0, 0, 0, 3).scroll, 2);
+    }
+
+    #[test]
+    fn test_anchor_stays_while_reading_history() {
+        assert_eq!(
+            anchor_insert(4, 10, 10, 3),
+            Anchored {
+                scroll: 4,
+                unseen: 3
+            }
+        );
+        // Arrived out of order, still below the cursor
+        assert_eq!(anchor_insert(4, 10, 7, 1).unseen, 1);
+    }
+
+    #[test]
+    fn test_anchor_shifts_on_prepend() {
+        // 50 older messages above the cursor: it stays on the same message
+        assert_eq!(
+            anchor_insert(0, 100, 0, 50),
+            Anchored {
+                scroll: 50,
+                unseen: 0
+            }
+        );
+        assert_eq!(anchor_insert(30, 100, 10, 2).scroll, 32);
+        // On the only message, which is also the last one
+        assert_eq!(anchor_insert(0, 1, 0, 50).scroll, 50);
+    }
+}
@@ -0,0 +1,74 @@
+//! Captcha round trip through `CommandExecutor`
+//!
+//! The mock VK API asks for a captcha on the first `messages.send`, then
+//! accepts the repeated one.
+
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+use vk_core::{AsyncCommand, CommandExecutor, CoreEvent};
+use vk_test_support::MockVk;
+
+const CAPTCHA: &str = r#"{"error":{"error_code":14,"error_msg":"Captcha needed","captcha_sid":"77","captcha_img":"https://api.vk.com/captcha.php?sid=77"}}"#;
+
+#[tokio::test]
+async fn captcha_is_asked_and_answered() {
+    let mock = MockVk::start().await;
+    mock.respond("messages.send", CAPTCHA)
+        .respond("messages.send", CAPTCHA)
+        .respond("messages.send", r#"{"response":5}"#);
+    let client = Arc::new(mock.client());
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+    let executor = CommandExecutor::new(client, event_tx, None);
+
+    executor
+        .execute(AsyncCommand::SendMessage {
+            peer_id: 1,
+            text: "hi".into(),
+        })
+        .await;
+    let Some(CoreEvent::CaptchaRequired {
+        sid,
+        img_url,
+        retry,
+    }) = event_rx.recv().await
+    else {
+        panic!("expected CaptchaRequired");
+    };
+    assert_eq!(sid, "77");
+    assert_eq!(img_url, "https://api.vk.com/captcha.php?sid=77");
+
+    // A wrong answer asks again for the same command
+    executor
+        .execute(AsyncCommand::AnswerCaptcha {
+            sid,
+            key: "wrong".into(),
+            retry,
+        })
+        .await;
+    let Some(CoreEvent::CaptchaRequired { sid, retry, .. }) = event_rx.recv().await else {
+        panic!("expected another CaptchaRequired");
+    };
+    assert!(matches!(
+        *retry,
+        AsyncCommand::SendMessage { peer_id: 1, .. }
+    ));
+
+    executor
+        .execute(AsyncCommand::AnswerCaptcha {
+            sid,
+            key: "x7k2q".into(),
+            retry,
+        })
+        .await;
+    assert!(matches!(
+        event_rx.recv().await,
+        Some(CoreEvent::MessageSent { message_id: 5, .. })
+    ));
+
+    let sends = mock.calls_to("messages.send");
+    assert_eq!(sends[0].param("captcha_key"), None);
+    assert_eq!(sends[1].param("captcha_key"), Some("wrong"));
+    assert_eq!(sends[2].param("captcha_sid"), Some("77"));
+    assert_eq!(sends[2].param("captcha_key"), Some("x7k2q"));
+}
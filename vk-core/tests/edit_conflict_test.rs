@@ -0,0 +1,87 @@
+//! Edit-conflict detection in `CommandExecutor`
+//!
+//! Simulates a message being edited on another device while the user is
+//! editing it locally, using a localhost mock of the VK API. The message
+//! has a photo, which the edit has to send again to keep.
+
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+use vk_core::edit::content_hash;
+use vk_core::{AsyncCommand, CommandExecutor, CoreEvent};
+use vk_test_support::{Call, MockVk};
+
+/// `messages.getById` answer: message 42 with `text` and a photo
+fn message_with_photo(text: &str) -> String {
+    format!(
+        r#"{{"response":{{"count":1,"items":[{{"id":42,"from_id":1,"peer_id":1,"date":0,"text":"{}","out":1,"attachments":[{{"type":"photo","photo":{{"id":7,"owner_id":1,"sizes":[],"access_key":"k"}}}}]}}]}}}}"#,
+        text
+    )
+}
+
+/// Run an edit against a server holding `server_text`, returning its
+/// event, the called methods and the `messages.edit` call if there was one
+async fn run_edit(
+    server_text: &'static str,
+    base_hash: Option<u64>,
+) -> (CoreEvent, Vec<String>, Option<Call>) {
+    let mock = MockVk::start().await;
+    mock.respond("messages.getById", message_with_photo(server_text))
+        .respond("messages.edit", r#"{"response":1}"#);
+    let client = Arc::new(mock.client());
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+    let executor = CommandExecutor::new(client, event_tx, None);
+
+    executor
+        .execute(AsyncCommand::EditMessage {
+            peer_id: 1,
+            message_id: 42,
+            cmid: None,
+            text: "my edit".into(),
+            base_hash,
+        })
+        .await;
+
+    let event = event_rx.recv().await.expect("executor emits an event");
+    let methods = mock.calls().into_iter().map(|call| call.method).collect();
+    let edit = mock.calls_to("messages.edit").into_iter().next();
+    (event, methods, edit)
+}
+
+#[tokio::test]
+async fn conflicting_edit_is_not_submitted() {
+    // User started editing "original", meanwhile it was changed on the phone
+    let (event, calls, _) = run_edit("edited on phone", Some(content_hash("original"))).await;
+
+    match event {
+        CoreEvent::EditConflict {
+            message_id,
+            server_text,
+        } => {
+            assert_eq!(message_id, 42);
+            assert_eq!(server_text, "edited on phone");
+        }
+        other => panic!("expected EditConflict, got {:?}", other),
+    }
+    assert_eq!(calls, vec!["messages.getById"]);
+}
+
+#[tokio::test]
+async fn unchanged_message_is_edited() {
+    let (event, calls, edit) = run_edit("original", Some(content_hash("original"))).await;
+
+    assert!(matches!(event, CoreEvent::MessageEdited { message_id: 42 }));
+    assert_eq!(calls, vec!["messages.getById", "messages.edit"]);
+    // The photo is sent again, or VK would remove it
+    assert_eq!(edit.unwrap().param("attachment"), Some("photo1_7_k"));
+}
+
+#[tokio::test]
+async fn overwrite_skips_the_check() {
+    let (event, calls, edit) = run_edit("edited on phone", None).await;
+
+    assert!(matches!(event, CoreEvent::MessageEdited { message_id: 42 }));
+    // Still fetched for its attachments, but not compared
+    assert_eq!(calls, vec!["messages.getById", "messages.edit"]);
+    assert_eq!(edit.unwrap().param("attachment"), Some("photo1_7_k"));
+}
@@ -1,34 +1,67 @@
 //! Main application state and logic.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
-use iced::widget::{Column, button, column, container, row, scrollable, text, text_input};
+use iced::widget::{
+    Column, Row, button, column, container, horizontal_space, image, mouse_area, progress_bar, row,
+    scrollable, slider, stack, text, text_input, tooltip,
+};
 use iced::{
-    Border, Color, Element, Font, Length, Shadow, Subscription, Task, Theme, Vector, font,
+    Alignment, Color, Element, Font, Length, Padding, Subscription, Task, Theme, event, font,
     font::{Family, Stretch, Style, Weight},
-    widget::{button as button_widget, container as container_widget, text_input as input_widget},
+    keyboard, window,
 };
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use vk_api::auth::AuthManager;
 use vk_api::{User, VkClient};
+use vk_core::cache::{self, CacheLayer};
+use vk_core::config::Settings;
+use vk_core::download;
+use vk_core::errors::error_text;
+use vk_core::fuzzy::filter_chats;
+use vk_core::longpoll::{
+    CONCURRENT_SESSION_WARNING, ConnectionState as LongPollState, FLAG_DELETED,
+};
+use vk_core::media::ChatInfo;
+use vk_core::outbox::{Outbox, OutboxCommand, OutboxEntry, OutboxEvent, OutboxState};
+use vk_core::outgoing::{
+    MAX_MESSAGE_CHARS, drop_failed_uploads, fail_upload, length_counter, merge_incoming,
+    set_upload_progress, split_message, too_long,
+};
+use vk_core::profiles::{
+    LOADING_NAME, ProfileWarmup, own_user_id, refresh_names, warmup_candidates,
+};
+use vk_core::timeline::{Clock, starts_day};
 use vk_core::{
-    AsyncCommand, Chat, ChatMessage, ChatsPagination, CommandExecutor, CoreEvent, DeliveryStatus,
-    MessagesPagination, VkEvent,
+    AsyncCommand, AttachmentInfo, AttachmentKind, Chat, ChatMessage, ChatsPagination,
+    CommandExecutor, CoreEvent, DeliveryStatus, ForwardItem, MessageKind, MessagesPagination,
+    ProfileDetails, ProfileInfo, SERVICE_MESSAGE_DENIED, VkEvent, apply_chat_action, first_unread,
+    preview_sender, preview_text, record_new_message, remove_chat, rename_chat, restore_chat,
+    set_chat_muted, set_chat_photo, set_chat_pinned, total_unread,
 };
 
 use crate::message::Message;
 
-const COSMIC_BG: Color = rgb8(12, 14, 20);
-const COSMIC_SURFACE: Color = rgb8(18, 22, 32);
-const COSMIC_SURFACE_ALT: Color = rgb8(26, 31, 44);
-const COSMIC_BORDER: Color = rgb8(42, 50, 67);
-const COSMIC_TEXT: Color = rgb8(231, 235, 242);
-const COSMIC_MUTED: Color = rgb8(151, 160, 178);
-const COSMIC_ACCENT: Color = rgb8(88, 170, 255);
-const COSMIC_SUCCESS: Color = rgb8(92, 209, 147);
-const COSMIC_DANGER: Color = rgb8(255, 122, 122);
-const COSMIC_SELECTION: Color = rgb8(65, 92, 140);
+mod avatars;
+mod bubble;
+mod captcha;
+mod chat_switcher;
+mod emoji_picker;
+mod image_viewer;
+mod message_scroll;
+mod sounds;
+mod styles;
+
+use avatars::AvatarCache;
+use bubble::{chip_label, forwards_title, reply_snippet};
+use captcha::CaptchaPrompt;
+use chat_switcher::{ChatSwitcher, SwitcherKey};
+use emoji_picker::{EmojiPicker, PickerKey, caret_after_edit, insert_at};
+use image_viewer::{ImageViewer, THUMBNAIL_HEIGHT, ViewerKey};
+use message_scroll::{Change, Ends, MessageScroll, merge_page};
+use sounds::{Notifier, SoundKind, SoundSettings};
+use styles::{ACCENT_PRESETS, Styles, ThemeMode};
 
 const JETBRAINS_FONT_NAME: &str = "JetBrainsMono Nerd Font";
 const JETBRAINS_BYTES: &[u8] = include_bytes!("../assets/JetBrainsMono.ttf");
@@ -56,11 +89,22 @@ pub enum ConnectionState {
     Connected,
 }
 
+/// Why a saved or entered token could not be used.
+#[derive(Debug, Clone)]
+pub enum SessionError {
+    /// VK rejected the token; the user has to log in again.
+    Expired,
+    /// Anything else, as the status to show.
+    Failed(String),
+}
+
 /// Main application state.
 pub struct VkApp {
     // View state
     view: View,
     connection: ConnectionState,
+    /// Long Poll link shown in the header
+    long_poll: LongPollState,
 
     // Auth
     auth: AuthManager,
@@ -68,17 +112,53 @@ pub struct VkApp {
 
     // VK state
     vk_client: Option<Arc<VkClient>>,
+    /// Chats and messages kept between runs, if the cache is built in
+    cache: Option<Arc<dyn CacheLayer>>,
+    /// Stops the Long Poll loop when dropped
+    long_poll_shutdown: Option<watch::Sender<bool>>,
     users: HashMap<i64, User>,
+    /// Logged-in account, once its profile is loaded
+    current_user: Option<ProfileInfo>,
+    /// Profiles requested for the forward picker and not loaded yet
+    profile_warmup: ProfileWarmup,
+    avatars: AvatarCache,
 
     // Chat data
     chats: Vec<Chat>,
+    /// Chats deleted this session, listed again if a message arrives
+    deleted_chats: HashMap<i64, Chat>,
     selected_chat: usize,
     current_peer_id: Option<i64>,
+    /// Query of the search field above the chat list
+    chat_filter: String,
+    /// Ctrl+K overlay, drawn over the main view
+    chat_switcher: Option<ChatSwitcher>,
+    /// Peer whose profile panel is open, and the profile once loaded
+    profile_panel: Option<(i64, Option<ProfileDetails>)>,
+    /// Media counts shown under the chat header after clicking it
+    chat_info_open: bool,
+    /// Media counts per chat, kept for the session
+    chat_infos: HashMap<i64, ChatInfo>,
 
     // Messages
     messages: Vec<ChatMessage>,
+    /// `messages` came from the cache; the first page from VK replaces them
+    messages_cached: bool,
     selected_message: usize,
+    message_scroll: MessageScroll,
+    /// First message that was unread when the chat was opened; the
+    /// "New messages" divider goes above it until the chat is read
+    unread_divider: Option<i64>,
+    /// Messages whose forwarded messages are unfolded
+    expanded_forwards: HashSet<i64>,
     message_input: String,
+    /// Caret in `message_input` (chars), as far as edits tell
+    input_caret: usize,
+    emoji_picker: EmojiPicker,
+    /// Full-window photo viewer, drawn over the main view
+    image_viewer: Option<ImageViewer>,
+    /// Captcha VK wants solved before it repeats a request
+    captcha: Option<CaptchaPrompt>,
 
     // Pagination
     chats_pagination: ChatsPagination,
@@ -87,16 +167,39 @@ pub struct VkApp {
     // Reply state
     reply_to: Option<i64>,
     editing_message: Option<i64>,
+    /// Hash of the message text when editing started (for conflict detection)
+    edit_base_hash: Option<u64>,
+    /// Text of the edit in flight, restored into the input on conflict
+    pending_edit: Option<String>,
     forward_source: Option<i64>,
     forward_target: Option<i64>,
     forward_stage: Option<ForwardStage>,
     forward_comment: String,
     delete_prompt: Option<i64>,
+    /// Texts of all chats VK has not taken yet, as the executor reports them
+    outbox: Outbox,
+    /// List of the outbox under the header
+    show_outbox: bool,
     font_loaded: bool,
 
+    // Appearance
+    styles: Styles,
+    /// Accent as picked by the user (`styles.accent` may be adjusted for contrast)
+    accent: Color,
+    accent_input: String,
+    show_settings: bool,
+
+    // Notification sounds
+    sound: SoundSettings,
+    sound_path_input: String,
+    notifier: Notifier,
+
     // Status
     status: Option<String>,
 
+    /// Settings shared with the other frontends
+    settings: Settings,
+
     // Command channel
     command_tx: Option<mpsc::UnboundedSender<AsyncCommand>>,
     event_rx: Option<mpsc::UnboundedReceiver<CoreEvent>>,
@@ -107,27 +210,59 @@ impl Default for VkApp {
         Self {
             view: View::Auth,
             connection: ConnectionState::Disconnected,
+            long_poll: LongPollState::default(),
             auth: AuthManager::default(),
             token_input: String::new(),
             vk_client: None,
+            cache: None,
+            long_poll_shutdown: None,
             users: HashMap::new(),
+            current_user: None,
+            profile_warmup: ProfileWarmup::default(),
+            avatars: AvatarCache::default(),
             chats: Vec::new(),
+            deleted_chats: HashMap::new(),
             selected_chat: 0,
             current_peer_id: None,
+            chat_filter: String::new(),
+            chat_switcher: None,
+            profile_panel: None,
+            chat_info_open: false,
+            chat_infos: HashMap::new(),
             messages: Vec::new(),
+            messages_cached: false,
             selected_message: 0,
+            message_scroll: MessageScroll::default(),
+            unread_divider: None,
+            expanded_forwards: HashSet::new(),
             message_input: String::new(),
+            input_caret: 0,
+            emoji_picker: EmojiPicker::default(),
+            image_viewer: None,
+            captcha: None,
             chats_pagination: ChatsPagination::default(),
             messages_pagination: None,
             reply_to: None,
             editing_message: None,
+            edit_base_hash: None,
+            pending_edit: None,
             forward_source: None,
             forward_target: None,
             forward_stage: None,
             forward_comment: String::new(),
             delete_prompt: None,
+            outbox: Outbox::default(),
+            show_outbox: false,
             font_loaded: false,
+            styles: Styles::default(),
+            accent: ACCENT_PRESETS[0],
+            accent_input: String::new(),
+            show_settings: false,
+            sound: SoundSettings::default(),
+            sound_path_input: String::new(),
+            notifier: Notifier::default(),
             status: None,
+            settings: Settings::default(),
             command_tx: None,
             event_rx: None,
         }
@@ -137,7 +272,18 @@ impl Default for VkApp {
 impl VkApp {
     /// Create new application with initial command.
     pub fn new() -> (Self, Task<Message>) {
-        let mut app = Self::default();
+        let (settings, problems) = Settings::load();
+        let mut app = Self {
+            emoji_picker: EmojiPicker::load(),
+            settings,
+            ..Self::default()
+        };
+        if !problems.is_empty() {
+            for problem in &problems {
+                tracing::warn!("Settings: {}", problem);
+            }
+            app.status = Some(format!("Settings: {}", problems.join("; ")));
+        }
         let font_task = font::load(JETBRAINS_BYTES)
             .map(|res: Result<(), font::Error>| Message::FontLoaded(res.is_ok()));
         let mut tasks = vec![font_task];
@@ -152,11 +298,10 @@ impl VkApp {
                 app.connection = ConnectionState::Connecting;
                 app.status = Some("Validating session...".into());
                 tasks.push(Task::perform(
-                    Self::validate_token(token.clone()),
+                    Self::validate_token(app.settings.clone(), token.clone()),
                     move |result| Message::SessionValidated {
                         token: token.clone(),
-                        valid: result.is_ok(),
-                        error: result.err(),
+                        result,
                     },
                 ));
             }
@@ -211,30 +356,38 @@ impl VkApp {
                 tracing::info!("Login pressed, token length: {}", token.len());
                 self.connection = ConnectionState::Connecting;
                 self.status = Some("Validating session...".into());
-                Task::perform(Self::validate_token(token.clone()), move |result| {
-                    Message::SessionValidated {
+                Task::perform(
+                    Self::validate_token(self.settings.clone(), token.clone()),
+                    move |result| Message::SessionValidated {
                         token: token.clone(),
-                        valid: result.is_ok(),
-                        error: result.err(),
-                    }
-                })
+                        result,
+                    },
+                )
             }
 
             // === Core Events ===
             Message::CoreEvent(event) => {
                 tracing::debug!("Received core event: {:?}", std::mem::discriminant(&event));
+                let before = Ends::of(&self.messages);
                 self.handle_core_event(event.clone());
-                Task::none()
+                let scroll = self.follow_messages(before);
+                Task::batch([scroll, self.load_avatars(), self.load_captcha_image()])
             }
 
             // === Chat Navigation ===
             Message::ChatSelected(idx) => {
+                self.chat_switcher = None;
                 self.selected_chat = idx;
                 if let Some(chat) = self.chats.get(idx) {
                     let peer_id = chat.id;
                     self.current_peer_id = Some(peer_id);
+                    self.profile_panel = None;
+                    self.chat_info_open = false;
                     self.messages.clear();
                     self.selected_message = 0;
+                    self.message_scroll = MessageScroll::default();
+                    self.expanded_forwards.clear();
+                    self.unread_divider = None;
                     self.messages_pagination = Some(MessagesPagination::new(peer_id));
                     self.send_command(AsyncCommand::LoadMessages { peer_id, offset: 0 });
 
@@ -251,37 +404,187 @@ impl VkApp {
                 Task::none()
             }
 
+            Message::ChatFilterChanged(query) => {
+                self.chat_filter = query;
+                Task::none()
+            }
+            Message::ChatFilterSubmit => {
+                match filter_chats(&self.chats, &self.chat_filter).first() {
+                    Some(&idx) => {
+                        self.chat_filter.clear();
+                        self.open_chat_from_keyboard(idx)
+                    }
+                    None => Task::none(),
+                }
+            }
+            Message::FocusChatFilter => text_input::focus(chat_filter_id()),
+            Message::SwitcherOpen => {
+                self.chat_switcher = Some(ChatSwitcher::open(&self.chats));
+                text_input::focus(switcher_input_id())
+            }
+            Message::SwitcherQueryChanged(query) => {
+                if let Some(switcher) = &mut self.chat_switcher {
+                    switcher.set_query(query, &self.chats);
+                }
+                Task::none()
+            }
+            Message::SwitcherKey(key) => {
+                if let Some(switcher) = &mut self.chat_switcher
+                    && !switcher.handle_key(key)
+                {
+                    self.chat_switcher = None;
+                    return text_input::focus(message_input_id());
+                }
+                Task::none()
+            }
+            Message::SwitcherSubmit => {
+                let picked = self.chat_switcher.take().and_then(|s| s.selected_chat());
+                match picked {
+                    Some(idx) => self.open_chat_from_keyboard(idx),
+                    None => Task::none(),
+                }
+            }
+
+            Message::ChatHeaderPressed => {
+                self.chat_info_open = !self.chat_info_open;
+                if let Some(peer_id) = self.current_peer_id
+                    && self.chat_info_open
+                    && !self.chat_infos.contains_key(&peer_id)
+                {
+                    self.send_command(AsyncCommand::LoadChatInfo { peer_id });
+                }
+
+                if self.profile_panel.is_some() {
+                    self.profile_panel = None;
+                } else if let Some(peer_id) = self.current_peer_id
+                    && !vk_api::is_chat_peer(peer_id)
+                {
+                    self.profile_panel = Some((peer_id, None));
+                    self.send_command(AsyncCommand::FetchUserProfile { user_id: peer_id });
+                }
+                Task::none()
+            }
+
             // === Messaging ===
             Message::MessageInputChanged(input) => {
+                self.input_caret = caret_after_edit(&self.message_input, &input);
                 self.message_input = input;
                 Task::none()
             }
+            Message::EmojiPickerToggle => {
+                self.emoji_picker.toggle();
+                if self.emoji_picker.open {
+                    text_input::focus(emoji_search_id())
+                } else {
+                    Task::none()
+                }
+            }
+            Message::EmojiQueryChanged(query) => {
+                self.emoji_picker.set_query(query);
+                Task::none()
+            }
+            Message::EmojiPickerKey(key) => {
+                if self.emoji_picker.handle_key(key) {
+                    Task::none()
+                } else {
+                    text_input::focus(message_input_id())
+                }
+            }
+            Message::EmojiSubmit => match self.emoji_picker.selected_emoji() {
+                Some(emoji) => self.update(Message::EmojiPicked(emoji.to_string())),
+                None => Task::none(),
+            },
+            Message::EmojiPicked(emoji) => {
+                self.input_caret = insert_at(&mut self.message_input, self.input_caret, &emoji);
+                self.emoji_picker.picked(&emoji);
+                Task::batch([
+                    text_input::focus(message_input_id()),
+                    text_input::move_cursor_to(message_input_id(), self.input_caret),
+                ])
+            }
             Message::MessageSelected(idx) => {
                 if idx < self.messages.len() {
                     self.selected_message = idx;
                 }
                 Task::none()
             }
+            Message::SelectAdjacentMessage(step) => {
+                if self.messages.is_empty() {
+                    return Task::none();
+                }
+                let last = self.messages.len() - 1;
+                self.selected_message = self.selected_message.saturating_add_signed(step).min(last);
+                // Rows differ in height; the share of the list is close enough
+                let y = self.selected_message as f32 / last.max(1) as f32;
+                scrollable::snap_to(
+                    messages_scroll_id(),
+                    scrollable::RelativeOffset { x: 0.0, y },
+                )
+            }
+            Message::MessagesScrolled(viewport) => {
+                let to_top = viewport.absolute_offset().y;
+                let to_bottom =
+                    viewport.content_bounds().height - viewport.bounds().height - to_top;
+                if self.message_scroll.scrolled(to_top, to_bottom) {
+                    self.load_older_messages();
+                }
+                Task::none()
+            }
+            Message::LoadOlderMessages => {
+                self.load_older_messages();
+                Task::none()
+            }
+            Message::ScrollToBottom => {
+                self.message_scroll.to_bottom();
+                scrollable::snap_to(messages_scroll_id(), scrollable::RelativeOffset::END)
+            }
+            Message::ReplyToSelected => match self.messages.get(self.selected_message) {
+                Some(msg) if msg.id != 0 => self.update(Message::ReplyPressed(msg.id)),
+                _ => Task::none(),
+            },
             Message::ReplyPressed(message_id) => {
+                if self.is_service_message(message_id) {
+                    self.status = Some(SERVICE_MESSAGE_DENIED.into());
+                    return Task::none();
+                }
                 self.reply_to = Some(message_id);
-                Task::none()
+                text_input::focus(message_input_id())
             }
             Message::ForwardPressed(message_id) => {
+                if self.is_service_message(message_id) {
+                    self.status = Some(SERVICE_MESSAGE_DENIED.into());
+                    return Task::none();
+                }
                 self.forward_source = Some(message_id);
                 self.forward_target = None;
                 self.forward_stage = Some(ForwardStage::SelectTarget);
                 self.forward_comment.clear();
                 self.status = Some("Select target chat to forward".into());
+                self.warm_up_profiles();
                 Task::none()
             }
             Message::EditPressed(message_id) => {
+                if self.is_service_message(message_id) {
+                    self.status = Some(SERVICE_MESSAGE_DENIED.into());
+                    return Task::none();
+                }
                 if let Some(msg) = self.messages.iter().find(|m| m.id == message_id) {
+                    if !vk_core::edit::is_editable(msg.timestamp, chrono_timestamp()) {
+                        self.status = Some(vk_core::edit::EDIT_TOO_OLD.into());
+                        return Task::none();
+                    }
                     self.editing_message = Some(message_id);
+                    self.edit_base_hash = Some(vk_core::edit::content_hash(&msg.text));
                     self.message_input = msg.text.clone();
+                    self.input_caret = self.message_input.chars().count();
                 }
                 Task::none()
             }
             Message::DeletePressed(message_id) => {
+                if self.is_service_message(message_id) {
+                    self.status = Some(SERVICE_MESSAGE_DENIED.into());
+                    return Task::none();
+                }
                 self.delete_prompt = Some(message_id);
                 Task::none()
             }
@@ -311,9 +614,45 @@ impl VkApp {
                 self.delete_prompt = None;
                 Task::none()
             }
+            Message::ReactionPressed(message_id, reaction_id) => {
+                if let Some(peer_id) = self.current_peer_id
+                    && let Some(msg) = self.messages.iter_mut().find(|m| m.id == message_id)
+                {
+                    match msg.cmid {
+                        Some(cmid) => {
+                            let reaction_id =
+                                vk_core::toggle_reaction(&mut msg.reactions, reaction_id);
+                            self.send_command(AsyncCommand::SetReaction {
+                                peer_id,
+                                message_id,
+                                cmid,
+                                reaction_id,
+                            });
+                        }
+                        None => self.status = Some("Message is not sent yet".into()),
+                    }
+                }
+                Task::none()
+            }
             Message::SendPressed => {
+                if let Some(denied) = self.write_denied() {
+                    self.status = Some(denied);
+                    return Task::none();
+                }
                 if let Some(peer_id) = self.current_peer_id {
+                    if self.editing_message.is_none()
+                        && too_long(&self.message_input)
+                        && !self.settings.split_long_messages
+                    {
+                        self.status = Some(format!(
+                            "Message is {} characters, VK takes {}; enable split_long_messages to send it in parts",
+                            self.message_input.chars().count(),
+                            MAX_MESSAGE_CHARS
+                        ));
+                        return Task::none();
+                    }
                     let input = std::mem::take(&mut self.message_input);
+                    self.input_caret = 0;
                     if !input.is_empty() {
                         if let Some(message_id) = self.editing_message.take() {
                             let cmid = self
@@ -321,23 +660,28 @@ impl VkApp {
                                 .iter()
                                 .find(|m| m.id == message_id)
                                 .and_then(|m| m.cmid);
+                            self.pending_edit = Some(input.clone());
                             self.send_command(AsyncCommand::EditMessage {
                                 peer_id,
                                 message_id,
                                 cmid,
                                 text: input,
-                            });
-                        } else if let Some(reply_to) = self.reply_to.take() {
-                            self.send_command(AsyncCommand::SendReply {
-                                peer_id,
-                                reply_to,
-                                text: input,
+                                base_hash: self.edit_base_hash.take(),
                             });
                         } else {
-                            self.send_command(AsyncCommand::SendMessage {
-                                peer_id,
-                                text: input,
-                            });
+                            // VK marks the chat read on sending
+                            self.unread_divider = None;
+                            self.message_scroll.to_bottom();
+                            // The executor runs commands in order, so the
+                            // parts arrive in order; a reply goes with the first
+                            let mut reply_to = self.reply_to.take();
+                            for text in split_message(&input, MAX_MESSAGE_CHARS) {
+                                self.send_command(AsyncCommand::Outbox(OutboxCommand::Send {
+                                    peer_id,
+                                    text,
+                                    reply_to: reply_to.take(),
+                                }));
+                            }
                         }
                     }
                 }
@@ -348,8 +692,86 @@ impl VkApp {
                 self.reply_to = None;
                 Task::none()
             }
+            Message::EscapePressed => {
+                // The innermost pending action goes first
+                if self.delete_prompt.is_some() {
+                    self.update(Message::CancelDelete)
+                } else if self.forward_stage.is_some() {
+                    self.status = None;
+                    self.update(Message::CancelForward)
+                } else if self.editing_message.is_some() {
+                    self.message_input.clear();
+                    self.input_caret = 0;
+                    self.update(Message::CancelEdit)
+                } else if self.reply_to.is_some() {
+                    self.update(Message::CancelReply)
+                } else if !self.chat_filter.is_empty() {
+                    self.chat_filter.clear();
+                    Task::none()
+                } else {
+                    Task::none()
+                }
+            }
             Message::CancelEdit => {
                 self.editing_message = None;
+                self.edit_base_hash = None;
+                Task::none()
+            }
+            Message::ToggleSettings => {
+                self.show_settings = !self.show_settings;
+                Task::none()
+            }
+
+            // === Outbox ===
+            Message::ToggleOutbox => {
+                self.show_outbox = !self.show_outbox;
+                Task::none()
+            }
+            Message::RetryOutgoing(id) => {
+                self.send_command(AsyncCommand::Outbox(OutboxCommand::Retry(id)));
+                Task::none()
+            }
+            Message::RetryAllOutgoing => {
+                self.send_command(AsyncCommand::Outbox(OutboxCommand::RetryAll));
+                Task::none()
+            }
+            Message::DiscardOutgoing(id) => {
+                // Never reached VK, so there is nothing to delete there
+                self.send_command(AsyncCommand::Outbox(OutboxCommand::Discard(id)));
+                Task::none()
+            }
+            Message::DiscardAllOutgoing => {
+                self.send_command(AsyncCommand::Outbox(OutboxCommand::DiscardAll));
+                Task::none()
+            }
+            Message::ToggleThemeMode => {
+                self.styles = Styles::new(self.styles.mode.toggled(), self.accent);
+                Task::none()
+            }
+            Message::AccentSelected(color) => {
+                self.set_accent(color);
+                self.accent_input.clear();
+                Task::none()
+            }
+            Message::AccentInputChanged(value) => {
+                if let Some(color) = styles::parse_hex(&value) {
+                    self.set_accent(color);
+                }
+                self.accent_input = value;
+                Task::none()
+            }
+            Message::ToggleSound => {
+                self.sound.enabled = !self.sound.enabled;
+                Task::none()
+            }
+            Message::SoundVolumeChanged(volume) => {
+                self.sound.volume = volume;
+                Task::none()
+            }
+            Message::SoundPathChanged(value) => {
+                let path = value.trim();
+                self.sound.custom_path = (!path.is_empty()).then(|| path.into());
+                self.sound_path_input = value;
                 Task::none()
             }
             Message::CancelForward => {
@@ -367,21 +789,14 @@ impl VkApp {
                 }
                 Task::none()
             }
-            Message::SessionValidated {
-                token,
-                valid,
-                error,
-            } => {
-                if valid {
-                    self.start_session(token);
-                } else if let Some(err) = error {
-                    if is_auth_error(&err) {
-                        let _ = self.auth.logout();
-                        self.status = Some("Session expired. Please login again.".into());
-                    } else {
+            Message::SessionValidated { token, result } => {
+                match result {
+                    Ok(()) => self.start_session(token),
+                    Err(SessionError::Expired) => self.handle_auth_expired(),
+                    Err(SessionError::Failed(err)) => {
                         self.status = Some(err);
+                        self.connection = ConnectionState::Disconnected;
                     }
-                    self.connection = ConnectionState::Disconnected;
                 }
                 Task::none()
             }
@@ -420,6 +835,103 @@ impl VkApp {
                         self.handle_core_event(event);
                     }
                 }
+                self.load_avatars()
+            }
+            Message::AttachmentPressed(url) => {
+                if let Err(e) = open::that(&url) {
+                    self.status = Some(format!("Failed to open {}: {}", url, e));
+                }
+                Task::none()
+            }
+            Message::DownloadAttachment(attachment) => {
+                self.send_command(AsyncCommand::DownloadAttachments {
+                    attachments: vec![attachment],
+                });
+                Task::none()
+            }
+            Message::ToggleForwards(message_id) => {
+                if !self.expanded_forwards.remove(&message_id) {
+                    self.expanded_forwards.insert(message_id);
+                }
+                Task::none()
+            }
+            Message::PhotoPressed(msg_idx, photo_idx) => {
+                self.image_viewer = self
+                    .messages
+                    .get(msg_idx)
+                    .and_then(|msg| ImageViewer::open(msg, photo_idx));
+                self.load_full_image()
+            }
+            Message::ViewerKey(key) => {
+                if let Some(viewer) = &mut self.image_viewer
+                    && !viewer.handle_key(key)
+                {
+                    self.image_viewer = None;
+                }
+                self.load_full_image()
+            }
+            Message::ViewerClosed => {
+                self.image_viewer = None;
+                Task::none()
+            }
+            Message::ViewerSave => {
+                if let Some(photo) = self.image_viewer.as_ref().and_then(|v| v.current()) {
+                    self.send_command(AsyncCommand::DownloadAttachments {
+                        attachments: vec![photo.clone()],
+                    });
+                }
+                Task::none()
+            }
+            Message::FullImageLoaded { url, result } => {
+                if let Some(viewer) = &mut self.image_viewer {
+                    match result {
+                        Ok(handle) => viewer.insert(url, handle),
+                        Err(e) => {
+                            tracing::debug!("Failed to load photo {}: {}", url, e);
+                            viewer.mark_failed(url);
+                        }
+                    }
+                }
+                Task::none()
+            }
+            Message::CaptchaImageLoaded { url, result } => {
+                if let Some(prompt) = &mut self.captcha
+                    && prompt.img_url == url
+                {
+                    match result {
+                        Ok(handle) => prompt.image = Some(handle),
+                        Err(e) => tracing::debug!("Failed to load captcha {}: {}", url, e),
+                    }
+                }
+                Task::none()
+            }
+            Message::CaptchaKeyChanged(key) => {
+                if let Some(prompt) = &mut self.captcha {
+                    prompt.key = key;
+                }
+                Task::none()
+            }
+            Message::CaptchaSubmit => {
+                if let Some(answer) = self.captcha.as_ref().and_then(|p| p.answer()) {
+                    self.captcha = None;
+                    self.send_command(answer);
+                }
+                Task::none()
+            }
+            Message::CaptchaCancel => {
+                if self.captcha.take().is_some() {
+                    self.status = Some("Send failed: captcha not solved".into());
+                }
+                Task::none()
+            }
+            Message::AvatarLoaded { url, result } => {
+                match result {
+                    Ok(bytes) => self.avatars.insert(url, bytes),
+                    Err(e) => {
+                        tracing::debug!("Failed to load avatar {}: {}", url, e);
+                        self.avatars.mark_failed(url);
+                    }
+                }
                 Task::none()
             }
 
@@ -435,82 +947,18 @@ impl VkApp {
         }
     }
 
-    /// Run command executor - this processes one command and returns the result.
-    async fn run_long_poll(client: Arc<VkClient>, event_tx: mpsc::UnboundedSender<CoreEvent>) {
-        tracing::info!("Starting Long Poll...");
-        let mut backoff = std::time::Duration::from_secs(1);
-
-        let mut server = match client.longpoll().get_server().await {
-            Ok(s) => {
-                tracing::info!("Got Long Poll server: {}", s.server);
-                s
-            }
-            Err(e) => {
-                let _ = event_tx.send(CoreEvent::Error(format!("Long Poll error: {}", e)));
-                return;
-            }
-        };
-
-        let _ = event_tx.send(CoreEvent::VkEvent(VkEvent::ConnectionStatus(true)));
-
-        loop {
-            match client.longpoll().poll(&server).await {
-                Ok(response) => {
-                    if let Some(failed) = response.failed {
-                        match failed {
-                            1 => {
-                                if let Some(ts) = response.ts {
-                                    server.ts = ts;
-                                }
-                            }
-                            2..=4 => match client.longpoll().get_server().await {
-                                Ok(new_server) => server = new_server,
-                                Err(e) => {
-                                    let _ = event_tx
-                                        .send(CoreEvent::Error(format!("Long Poll error: {}", e)));
-                                    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-                                }
-                            },
-                            _ => {}
-                        }
-                        continue;
-                    }
-
-                    if let Some(ts) = response.ts {
-                        server.ts = ts;
-                    }
-
-                    if let Some(updates) = response.updates {
-                        for update in updates {
-                            if let Some(event) = vk_core::longpoll::handle_update(&update) {
-                                let _ = event_tx.send(CoreEvent::VkEvent(event));
-                            }
-                        }
-                    }
-                    backoff = std::time::Duration::from_secs(1);
-                }
-                Err(e) => {
-                    let _ = event_tx.send(CoreEvent::VkEvent(VkEvent::ConnectionStatus(false)));
-                    let _ = event_tx.send(CoreEvent::Error(format!("Long Poll error: {}", e)));
-                    tokio::time::sleep(backoff).await;
-                    backoff = (backoff * 2).min(std::time::Duration::from_secs(30));
-
-                    match client.longpoll().get_server().await {
-                        Ok(new_server) => {
-                            server = new_server;
-                            let _ =
-                                event_tx.send(CoreEvent::VkEvent(VkEvent::ConnectionStatus(true)));
-                            backoff = std::time::Duration::from_secs(1);
-                        }
-                        Err(_) => continue,
-                    }
-                }
-            }
+    /// Handle events from vk-core.
+    /// Keep the open chat highlighted after the list is reordered.
+    fn sync_selected_chat(&mut self) {
+        if let Some(peer_id) = self.current_peer_id
+            && let Some(idx) = self.chats.iter().position(|c| c.id == peer_id)
+        {
+            self.selected_chat = idx;
         }
     }
 
-    /// Handle events from vk-core.
     fn handle_core_event(&mut self, event: CoreEvent) {
+        self.long_poll.apply(&event);
         match event {
             CoreEvent::ConversationsLoaded {
                 chats,
@@ -519,7 +967,11 @@ impl VkApp {
                 has_more,
             } => {
                 tracing::info!("Handling ConversationsLoaded: {} chats", chats.len());
+                for url in chats.iter().filter_map(|c| c.photo_url.as_deref()) {
+                    self.avatars.request(url);
+                }
                 self.chats = chats;
+                self.sync_selected_chat();
                 for profile in profiles {
                     self.users.insert(profile.id, profile);
                 }
@@ -530,15 +982,50 @@ impl VkApp {
                 self.connection = ConnectionState::Connected;
                 self.status = None;
             }
+            CoreEvent::CachedConversations { chats } => {
+                // Shown until the first page arrives from VK
+                if self.chats.is_empty() {
+                    self.chats = chats;
+                    self.sync_selected_chat();
+                    self.view = View::Main;
+                }
+            }
+            CoreEvent::CachedMessages { peer_id, messages } => {
+                if Some(peer_id) == self.current_peer_id && self.messages.is_empty() {
+                    self.messages = messages;
+                    self.messages_cached = true;
+                }
+            }
             CoreEvent::MessagesLoaded {
                 peer_id,
                 messages,
                 profiles,
                 total_count,
                 has_more,
+                in_read,
             } => {
                 if Some(peer_id) == self.current_peer_id {
-                    self.messages = messages;
+                    if std::mem::take(&mut self.messages_cached) {
+                        self.messages.clear();
+                    }
+                    let opening = self.messages.is_empty();
+                    for url in messages.iter().filter_map(|m| m.from_photo.as_deref()) {
+                        self.avatars.request(url);
+                    }
+                    // Photo thumbnails and link preview images
+                    for url in messages
+                        .iter()
+                        .flat_map(|m| &m.attachments)
+                        .filter(|a| matches!(a.kind, AttachmentKind::Photo | AttachmentKind::Link))
+                        .filter_map(|a| a.thumbnail_url.as_deref())
+                    {
+                        self.avatars.request(url);
+                    }
+                    merge_page(&mut self.messages, messages);
+                    if opening {
+                        self.unread_divider =
+                            in_read.and_then(|in_read| first_unread(&self.messages, in_read));
+                    }
                     for profile in profiles {
                         self.users.insert(profile.id, profile);
                     }
@@ -546,6 +1033,8 @@ impl VkApp {
                         pagination.total_count = Some(total_count);
                         pagination.has_more = has_more;
                         pagination.is_loading = false;
+                        pagination.first_cmid = self.messages.first().and_then(|m| m.cmid);
+                        pagination.last_cmid = self.messages.last().and_then(|m| m.cmid);
                     }
                 }
             }
@@ -555,7 +1044,48 @@ impl VkApp {
                     self.send_command(AsyncCommand::LoadMessages { peer_id, offset: 0 });
                 }
             }
+            CoreEvent::UploadProgress {
+                local_id,
+                bytes_sent,
+                total,
+                ..
+            } => {
+                set_upload_progress(&mut self.messages, local_id, bytes_sent, total);
+            }
+            CoreEvent::UploadFailed {
+                local_id, error, ..
+            } => {
+                fail_upload(&mut self.messages, local_id);
+                self.status = Some(format!("Upload failed: {}", error));
+            }
+            CoreEvent::UploadPartlyFailed {
+                local_id,
+                failed,
+                error,
+                ..
+            } => {
+                drop_failed_uploads(&mut self.messages, local_id, &failed);
+                self.status = Some(format!("{}; sending the rest", error));
+            }
+            CoreEvent::EditConflict {
+                message_id,
+                server_text,
+            } => {
+                // Show the newer text and let the user merge; sending again
+                // without a base overwrites it
+                if let Some(msg) = self.messages.iter_mut().find(|m| m.id == message_id) {
+                    msg.text = server_text;
+                }
+                self.editing_message = Some(message_id);
+                self.edit_base_hash = None;
+                self.message_input = self.pending_edit.take().unwrap_or_default();
+                self.input_caret = self.message_input.chars().count();
+                self.status = Some(
+                    "Message was edited elsewhere. Review it and send again to overwrite".into(),
+                );
+            }
             CoreEvent::MessageEdited { .. } | CoreEvent::MessageDeleted { .. } => {
+                self.pending_edit = None;
                 if let Some(peer_id) = self.current_peer_id {
                     self.send_command(AsyncCommand::LoadMessages { peer_id, offset: 0 });
                 }
@@ -568,6 +1098,7 @@ impl VkApp {
                 reply,
                 fwd_count,
                 forwards,
+                reactions,
                 ..
             } => {
                 if let Some(msg) = self.messages.iter_mut().find(|m| m.id == message_id) {
@@ -587,22 +1118,136 @@ impl VkApp {
                     if let Some(forwards) = forwards {
                         msg.forwards = forwards;
                     }
+                    if let Some(reactions) = reactions {
+                        msg.reactions = reactions;
+                    }
                 }
             }
-            CoreEvent::Error(msg) => {
-                self.status = Some(msg);
+            CoreEvent::AuthExpired => {
+                self.handle_auth_expired();
+            }
+            CoreEvent::Error {
+                text, occurrence, ..
+            } => {
+                self.status = Some(error_text(&text, occurrence));
             }
             CoreEvent::SendFailed(msg) => {
                 self.status = Some(format!("Send failed: {}", msg));
             }
+            CoreEvent::Outbox(event) => {
+                self.outbox.apply(&event);
+                match event {
+                    OutboxEvent::Sent { peer_id, .. } if self.current_peer_id == Some(peer_id) => {
+                        self.send_command(AsyncCommand::LoadMessages { peer_id, offset: 0 });
+                    }
+                    OutboxEvent::Updated(OutboxEntry {
+                        state: OutboxState::Failed(error),
+                        ..
+                    }) => {
+                        self.status = Some(format!("Send failed: {}", error));
+                    }
+                    _ => {}
+                }
+                if self.outbox.label().is_none() {
+                    self.show_outbox = false;
+                }
+            }
+            CoreEvent::CaptchaRequired {
+                sid,
+                img_url,
+                retry,
+            } => {
+                self.status = Some("VK asks to solve a captcha".into());
+                self.captcha = Some(CaptchaPrompt::new(sid, img_url, *retry));
+            }
+            CoreEvent::DownloadProgress {
+                title,
+                received,
+                total,
+            } => {
+                self.status = Some(format!(
+                    "Downloading {}",
+                    download::progress_text(&title, received, total)
+                ));
+            }
+            CoreEvent::DownloadFinished { path, .. } => {
+                self.status = Some(format!("Saved to {}", download::display_path(&path)));
+            }
+            CoreEvent::PossibleConcurrentSession { .. } => {
+                self.status = Some(CONCURRENT_SESSION_WARNING.into());
+            }
+            CoreEvent::ProfileLoaded { user } => {
+                self.status = Some(format!("Logged in as {}", user.full_name()));
+                self.current_user = Some(user);
+            }
+            CoreEvent::UserProfileLoaded { user } => {
+                if let Some((peer_id, profile)) = &mut self.profile_panel
+                    && *peer_id == user.id
+                {
+                    if let Some(url) = &user.photo_url {
+                        self.avatars.request(url);
+                    }
+                    *profile = Some(user);
+                }
+            }
+            CoreEvent::ChatInfoLoaded { info } => {
+                self.chat_infos.insert(info.peer_id, info);
+            }
+            CoreEvent::ChatSettingsChanged {
+                peer_id,
+                title,
+                photo_url,
+            } => {
+                if let Some(title) = title {
+                    rename_chat(&mut self.chats, peer_id, &title);
+                }
+                if let Some(url) = photo_url {
+                    self.avatars.request(&url);
+                    set_chat_photo(&mut self.chats, peer_id, &url);
+                }
+            }
+            CoreEvent::ChatMuteChanged { peer_id, muted } => {
+                set_chat_muted(&mut self.chats, peer_id, muted);
+            }
+            CoreEvent::ChatPinChanged { peer_id, pinned } => {
+                if set_chat_pinned(&mut self.chats, peer_id, pinned) {
+                    self.sync_selected_chat();
+                }
+            }
+            CoreEvent::ConversationDeleted { peer_id } => {
+                if let Some(chat) = remove_chat(&mut self.chats, peer_id) {
+                    self.deleted_chats.insert(peer_id, chat);
+                }
+                self.selected_chat = self.selected_chat.min(self.chats.len().saturating_sub(1));
+                if self.current_peer_id == Some(peer_id) {
+                    self.current_peer_id = None;
+                    self.messages.clear();
+                    self.messages_pagination = None;
+                }
+                self.sync_selected_chat();
+                self.status = Some("Conversation deleted".into());
+            }
+            CoreEvent::UsersLoaded { requested, users } => {
+                self.profile_warmup.finish(&requested);
+                refresh_names(&mut self.chats, &mut self.messages, &users);
+                for user in users {
+                    self.users.insert(user.id, user);
+                }
+            }
             CoreEvent::VkEvent(event) => {
                 self.handle_vk_event(event);
             }
+            CoreEvent::SettingsChanged(settings) => {
+                self.settings = *settings;
+            }
             _ => {}
         }
     }
 
     fn handle_vk_event(&mut self, event: VkEvent) {
+        if let Some(cache) = &self.cache {
+            cache.apply_event(&event);
+        }
         match event {
             VkEvent::NewMessage {
                 message_id,
@@ -610,17 +1255,62 @@ impl VkApp {
                 timestamp,
                 text,
                 from_id,
+                is_outgoing,
+                has_attachments,
+                attachment_types,
+                action,
+                random_id,
             } => {
-                if self.current_peer_id == Some(peer_id) {
-                    let from_name = self.get_user_name(from_id);
-                    self.messages.push(ChatMessage {
-                        id: message_id,
+                let unread = !is_outgoing && self.current_peer_id != Some(peer_id);
+                let preview =
+                    preview_text(&text, action.as_ref(), &attachment_types, from_id, |id| {
+                        self.get_user_name(id)
+                    });
+                if let Some(chat) = self.deleted_chats.remove(&peer_id) {
+                    restore_chat(&mut self.chats, chat);
+                }
+                let from =
+                    preview_sender(peer_id, is_outgoing, from_id, |id| self.get_user_name(id));
+                let kind = MessageKind::of(action.as_ref(), &attachment_types);
+                if record_new_message(
+                    &mut self.chats,
+                    peer_id,
+                    &preview,
+                    from,
+                    kind,
+                    timestamp,
+                    unread,
+                ) {
+                    self.sync_selected_chat();
+                }
+                if let Some(action) = &action {
+                    apply_chat_action(&mut self.chats, peer_id, action);
+                } else if unread && !self.chats.iter().any(|c| c.id == peer_id && c.is_muted) {
+                    let kind = match own_user_id(self.current_user.as_ref(), self.auth.user_id()) {
+                        Some(me) if sounds::is_mention(&text, me) => SoundKind::Mention,
+                        _ => SoundKind::Message,
+                    };
+                    self.notifier.play(kind, &self.sound);
+                }
+
+                if self.current_peer_id == Some(peer_id) {
+                    let from_name = self.get_user_name(from_id);
+                    let from_photo = self.users.get(&from_id).and_then(|u| u.photo_50.clone());
+                    if let Some(url) = &from_photo {
+                        self.avatars.request(url);
+                    }
+                    let message = ChatMessage {
+                        id: message_id,
                         cmid: None,
+                        random_id,
                         from_id,
                         from_name,
-                        text,
+                        from_photo,
+                        // A service message has no text of its own
+                        text: if action.is_some() { preview } else { text },
+                        kind,
                         timestamp,
-                        is_outgoing: from_id == self.auth.user_id().unwrap_or(0),
+                        is_outgoing,
                         is_read: true,
                         is_edited: false,
                         is_pinned: false,
@@ -629,10 +1319,26 @@ impl VkApp {
                         reply: None,
                         fwd_count: 0,
                         forwards: Vec::new(),
-                    });
-                    self.selected_message = self.messages.len().saturating_sub(1);
-                } else if let Some(chat) = self.chats.iter_mut().find(|c| c.id == peer_id) {
-                    chat.unread_count += 1;
+                        reactions: Vec::new(),
+                        upload: None,
+                    };
+                    // Sending reloads the chat, which may already include it
+                    self.selected_message = merge_incoming(&mut self.messages, message);
+                    if has_attachments {
+                        self.send_command(AsyncCommand::FetchMessageById { message_id });
+                    }
+                }
+            }
+            VkEvent::MessageFlagsChanged {
+                peer_id,
+                flags,
+                set,
+                ..
+            } => {
+                // Restored after deletion: it is no longer in the list, reload
+                if !set && flags & FLAG_DELETED != 0 && self.current_peer_id == Some(peer_id) {
+                    self.send_command(AsyncCommand::LoadMessages { peer_id, offset: 0 });
+                    self.status = Some("Message restored".into());
                 }
             }
             VkEvent::MessageRead {
@@ -643,6 +1349,11 @@ impl VkApp {
                     chat.unread_count = 0;
                 }
                 if self.current_peer_id == Some(peer_id) {
+                    if message_id == 0
+                        || self.unread_divider.is_some_and(|first| message_id >= first)
+                    {
+                        self.unread_divider = None;
+                    }
                     if message_id > 0 {
                         for msg in self.messages.iter_mut() {
                             if msg.is_outgoing && msg.id <= message_id {
@@ -658,12 +1369,35 @@ impl VkApp {
                     }
                 }
             }
+            VkEvent::ReactionsChanged { peer_id, cmid } => {
+                if self.current_peer_id == Some(peer_id)
+                    && let Some(msg) = self.messages.iter().find(|m| m.cmid == Some(cmid))
+                {
+                    self.send_command(AsyncCommand::FetchMessageById { message_id: msg.id });
+                }
+            }
             VkEvent::MessageEditedFromLongPoll {
                 peer_id,
                 message_id,
+                new_text,
+                has_attachments,
             } => {
                 if self.current_peer_id == Some(peer_id) {
-                    self.send_command(AsyncCommand::FetchMessageById { message_id });
+                    // Text-only edits carry the new text; fetch the rest
+                    let applied = self
+                        .messages
+                        .iter_mut()
+                        .find(|m| m.id == message_id)
+                        .is_some_and(|msg| {
+                            vk_core::edit::apply_remote_edit(
+                                msg,
+                                new_text.as_deref(),
+                                has_attachments,
+                            )
+                        });
+                    if !applied {
+                        self.send_command(AsyncCommand::FetchMessageById { message_id });
+                    }
                     self.status = Some("Message updated from web".into());
                 }
             }
@@ -688,6 +1422,9 @@ impl VkApp {
                 }
             }
             VkEvent::ConnectionStatus(connected) => {
+                if connected {
+                    self.send_command(AsyncCommand::Outbox(OutboxCommand::Flush));
+                }
                 self.status = Some(if connected {
                     "Connected to VK".into()
                 } else {
@@ -698,7 +1435,13 @@ impl VkApp {
     }
 
     fn start_session(&mut self, token: String) {
-        let client = Arc::new(VkClient::new(token));
+        let client = match self.settings.client(token) {
+            Ok(client) => Arc::new(client),
+            Err(e) => {
+                self.status = Some(e.to_string());
+                return;
+            }
+        };
         self.vk_client = Some(client.clone());
 
         let (cmd_tx, mut cmd_rx) = mpsc::unbounded_channel::<AsyncCommand>();
@@ -706,25 +1449,125 @@ impl VkApp {
         self.command_tx = Some(cmd_tx);
         self.event_rx = Some(event_rx);
 
-        let executor = CommandExecutor::new(client.clone(), event_tx.clone());
+        if self.cache.is_none() {
+            self.cache = cache::open_default();
+        }
+        let executor = CommandExecutor::new(client.clone(), event_tx.clone(), self.cache.clone())
+            .with_settings(self.settings.clone());
         tokio::spawn(async move {
             while let Some(cmd) = cmd_rx.recv().await {
                 executor.execute(cmd).await;
             }
         });
 
-        tokio::spawn(Self::run_long_poll(client, event_tx));
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        self.long_poll_shutdown = Some(shutdown_tx);
+        tokio::spawn(vk_core::longpoll::run(
+            client,
+            event_tx,
+            shutdown_rx,
+            Arc::default(),
+        ));
+        self.send_command(AsyncCommand::LoadProfile);
         self.send_command(AsyncCommand::LoadConversations { offset: 0 });
     }
 
-    async fn validate_token(token: String) -> Result<(), String> {
-        let client = VkClient::new(token);
+    /// Apply a new accent color; takes effect on the next redraw.
+    fn set_accent(&mut self, accent: Color) {
+        self.accent = accent;
+        self.styles = Styles::new(self.styles.mode, accent);
+    }
+
+    /// Drop the current session and return to the login screen.
+    fn handle_auth_expired(&mut self) {
+        let _ = self.auth.logout();
+        // The next account must not see this one's messages
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+        self.vk_client = None;
+        self.current_user = None;
+        self.long_poll_shutdown = None;
+        self.command_tx = None;
+        self.event_rx = None;
+        self.chats.clear();
+        self.messages.clear();
+        self.outbox = Outbox::default();
+        self.show_outbox = false;
+        self.current_peer_id = None;
+        self.token_input.clear();
+        self.view = View::Auth;
+        self.connection = ConnectionState::Disconnected;
+        self.status = Some("Session expired. Please login again.".into());
+    }
+
+    async fn validate_token(settings: Settings, token: String) -> Result<(), SessionError> {
+        let client = settings
+            .client(token)
+            .map_err(|e| SessionError::Failed(e.to_string()))?;
+        // "Cannot reach api.vk.com via proxy ..." rather than a failed call
         client
-            .account()
-            .get_profile_info()
+            .ping()
             .await
-            .map(|_| ())
-            .map_err(|e| format!("Session validation failed: {}", e))
+            .map_err(|e| SessionError::Failed(e.to_string()))?;
+        let result = match client.account().get_profile_info().await {
+            Ok(_) => vk_api::auth::validate_scopes(&client).await,
+            Err(e) => Err(e),
+        };
+        result.map_err(|e| match e {
+            e if e.is_auth() => SessionError::Expired,
+            vk_api::Error::MissingScopes(_) => SessionError::Failed(e.to_string()),
+            e => SessionError::Failed(format!("Session validation failed: {}", e)),
+        })
+    }
+
+    /// Request profiles the forward picker shows but that are not loaded yet.
+    fn warm_up_profiles(&mut self) {
+        let candidates = warmup_candidates(&self.chats, &self.messages, self.current_peer_id);
+        let missing = self
+            .profile_warmup
+            .request(candidates, |id| self.users.contains_key(&id));
+        if !missing.is_empty() {
+            self.send_command(AsyncCommand::LoadUsers { user_ids: missing });
+        }
+    }
+
+    /// Start loading the full-size image of the photo the viewer shows.
+    fn load_full_image(&mut self) -> Task<Message> {
+        match self.image_viewer.as_mut().and_then(|v| v.take_load()) {
+            Some(url) => Task::perform(image_viewer::fetch(url.clone()), move |result| {
+                Message::FullImageLoaded {
+                    url: url.clone(),
+                    result,
+                }
+            }),
+            None => Task::none(),
+        }
+    }
+
+    /// Start loading the image of a captcha that just arrived.
+    fn load_captcha_image(&mut self) -> Task<Message> {
+        match self.captcha.as_mut().and_then(|p| p.take_load()) {
+            Some(url) => Task::perform(image_viewer::fetch(url.clone()), move |result| {
+                Message::CaptchaImageLoaded {
+                    url: url.clone(),
+                    result,
+                }
+            }),
+            None => Task::none(),
+        }
+    }
+
+    /// Start downloading avatars requested since the last update.
+    fn load_avatars(&mut self) -> Task<Message> {
+        Task::batch(self.avatars.take_queue().into_iter().map(|url| {
+            Task::perform(avatars::fetch(url.clone()), move |result| {
+                Message::AvatarLoaded {
+                    url: url.clone(),
+                    result,
+                }
+            })
+        }))
     }
 
     /// Send command to executor.
@@ -734,23 +1577,82 @@ impl VkApp {
         }
     }
 
+    /// Open the chat at `idx` of the list and put the caret in the input,
+    /// so typing goes on without the mouse.
+    /// Ask for the page of history above the loaded messages, unless one
+    /// is on its way or the chat has no more.
+    fn load_older_messages(&mut self) {
+        let (Some(peer_id), Some(pagination)) =
+            (self.current_peer_id, self.messages_pagination.as_mut())
+        else {
+            return;
+        };
+        let Some(first_cmid) = pagination.first_cmid else {
+            return;
+        };
+        if !pagination.has_more || pagination.is_loading {
+            return;
+        }
+        pagination.is_loading = true;
+        // Offset -1 skips `first_cmid` itself, which is loaded already
+        self.send_command(AsyncCommand::LoadMessagesWithOffset {
+            peer_id,
+            start_cmid: first_cmid,
+            offset: -1,
+            count: self.settings.messages_page_size,
+        });
+    }
+
+    /// Keep the message list in place, or on the latest message, after
+    /// the messages changed from `before`.
+    fn follow_messages(&mut self, before: Ends) -> Task<Message> {
+        match Change::between(before, &self.messages) {
+            Change::Loaded => {
+                self.message_scroll.to_bottom();
+                scrollable::snap_to(messages_scroll_id(), scrollable::RelativeOffset::END)
+            }
+            Change::Appended(count) if self.message_scroll.appended(count) => {
+                scrollable::snap_to(messages_scroll_id(), scrollable::RelativeOffset::END)
+            }
+            Change::Prepended(count) => {
+                // Rows differ in height; the share of the list is close enough
+                let y = count as f32 / self.messages.len() as f32;
+                self.selected_message += count;
+                scrollable::snap_to(
+                    messages_scroll_id(),
+                    scrollable::RelativeOffset { x: 0.0, y },
+                )
+            }
+            _ => Task::none(),
+        }
+    }
+
+    fn open_chat_from_keyboard(&mut self, idx: usize) -> Task<Message> {
+        Task::batch([
+            self.update(Message::ChatSelected(idx)),
+            text_input::focus(message_input_id()),
+        ])
+    }
+
     /// Create subscription for periodic updates.
     pub fn subscription(&self) -> Subscription<Message> {
-        iced::time::every(std::time::Duration::from_millis(200)).map(|_| Message::Tick)
+        let tick = iced::time::every(std::time::Duration::from_millis(200)).map(|_| Message::Tick);
+        if self.image_viewer.is_some() {
+            Subscription::batch([tick, keyboard::on_key_press(image_viewer_key)])
+        } else if self.emoji_picker.open {
+            Subscription::batch([tick, keyboard::on_key_press(emoji_picker_key)])
+        } else if self.chat_switcher.is_some() {
+            Subscription::batch([tick, event::listen_with(chat_switcher_key)])
+        } else if matches!(self.view, View::Main) {
+            Subscription::batch([tick, event::listen_with(main_view_key)])
+        } else {
+            tick
+        }
     }
 
     /// Get theme.
     pub fn theme(&self) -> Theme {
-        Theme::custom(
-            "Cosmic Dark".to_string(),
-            iced::theme::Palette {
-                background: COSMIC_BG,
-                text: COSMIC_TEXT,
-                primary: COSMIC_ACCENT,
-                success: COSMIC_SUCCESS,
-                danger: COSMIC_DANGER,
-            },
-        )
+        self.styles.theme()
     }
 
     /// Render the view.
@@ -763,10 +1665,11 @@ impl VkApp {
 
     /// Render auth screen.
     fn view_auth(&self) -> Element<'_, Message> {
+        let styles = self.styles;
         let title = text("VK Client")
             .size(32)
             .font(self.font_ui_bold())
-            .color(COSMIC_TEXT);
+            .color(styles.palette.text);
 
         let status_text = match &self.connection {
             ConnectionState::Connecting => text("Connecting...").size(14).font(self.font_ui()),
@@ -785,24 +1688,24 @@ impl VkApp {
         let token_input = text_input("Paste redirect URL...", &self.token_input)
             .on_input(Message::TokenInputChanged)
             .on_submit(Message::LoginPressed)
-            .style(cosmic_text_input)
+            .style(move |theme, status| styles.text_input(theme, status))
             .padding(10)
             .width(Length::Fixed(400.0));
 
         let login_button = button(text("Login").font(self.font_ui_bold()))
             .on_press(Message::LoginPressed)
-            .style(cosmic_button_primary)
+            .style(move |theme, status| styles.button_primary(theme, status))
             .padding([10, 20]);
 
         let open_button = button(text("Open OAuth URL").font(self.font_ui_bold()))
             .on_press(Message::OpenAuthUrl)
-            .style(cosmic_button_secondary)
+            .style(move |theme, status| styles.button_secondary(theme, status))
             .padding([10, 20]);
 
         let help_text = text("Authorize in browser, then paste redirect URL here")
             .size(12)
             .font(self.font_ui())
-            .color(COSMIC_MUTED);
+            .color(styles.palette.muted);
 
         let content = column![
             title,
@@ -819,146 +1722,670 @@ impl VkApp {
             .height(Length::Fill)
             .center_x(Length::Fill)
             .center_y(Length::Fill)
-            .style(cosmic_root)
+            .style(move |theme| styles.root(theme))
             .into()
     }
 
     /// Render main screen.
     fn view_main(&self) -> Element<'_, Message> {
+        let styles = self.styles;
         let sidebar = self.view_chat_list();
         let content = self.view_conversation();
         let header = self.view_header();
+        let settings = if self.show_settings {
+            self.view_settings()
+        } else {
+            row![].into()
+        };
+        let outbox = if self.show_outbox {
+            self.view_outbox()
+        } else {
+            row![].into()
+        };
+
+        let main = container(column![
+            header,
+            settings,
+            outbox,
+            row![sidebar, content, self.view_profile_panel()].height(Length::Fill)
+        ])
+        .width(Length::Fill)
+        .height(Length::Fill)
+        .style(move |theme| styles.root(theme));
+
+        if let Some(captcha) = self.view_captcha() {
+            return stack![main, captcha].into();
+        }
+        match (self.view_image_viewer(), self.view_chat_switcher()) {
+            (Some(viewer), _) => stack![main, viewer].into(),
+            (None, Some(switcher)) => stack![main, switcher].into(),
+            (None, None) => main.into(),
+        }
+    }
+
+    /// Captcha overlay: the image, a field for its text, and buttons to
+    /// answer or give up.
+    fn view_captcha(&self) -> Option<Element<'_, Message>> {
+        let styles = self.styles;
+        let prompt = self.captcha.as_ref()?;
+
+        let picture: Element<'_, Message> = match &prompt.image {
+            Some(handle) => image(handle.clone()).height(Length::Fixed(60.0)).into(),
+            None => text("Loading...").font(self.font_ui()).into(),
+        };
+        let key = text_input("Text from the picture", &prompt.key)
+            .on_input(Message::CaptchaKeyChanged)
+            .on_submit(Message::CaptchaSubmit)
+            .style(move |theme, status| styles.text_input(theme, status))
+            .padding(10)
+            .width(Length::Fill);
+        let buttons = row![
+            horizontal_space(),
+            button(text("Cancel").font(self.font_ui_bold()))
+                .on_press(Message::CaptchaCancel)
+                .style(move |theme, status| styles.button_secondary(theme, status)),
+            button(text("Send").font(self.font_ui_bold()))
+                .on_press_maybe((!prompt.key.trim().is_empty()).then_some(Message::CaptchaSubmit))
+                .style(move |theme, status| styles.button_primary(theme, status)),
+        ]
+        .spacing(10);
+
+        let panel = container(
+            column![
+                text("VK asks to solve a captcha").font(self.font_ui_bold()),
+                picture,
+                key,
+                buttons
+            ]
+            .spacing(10),
+        )
+        .padding(12)
+        .width(Length::Fixed(360.0))
+        .style(move |theme| styles.panel(theme));
+
+        Some(
+            container(panel)
+                .padding(80)
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .center_x(Length::Fill)
+                .style(move |theme| styles.overlay(theme))
+                .into(),
+        )
+    }
+
+    /// Photo viewer overlay; a click outside the image closes it.
+    fn view_image_viewer(&self) -> Option<Element<'_, Message>> {
+        let styles = self.styles;
+        let viewer = self.image_viewer.as_ref()?;
+        let photo = viewer.current()?;
+
+        // The thumbnail stands in until the full image is decoded
+        let handle = viewer.full_image().or_else(|| {
+            photo
+                .thumbnail_url
+                .as_deref()
+                .and_then(|url| self.avatars.get(url))
+        });
+        let picture: Element<'_, Message> = match handle {
+            Some(handle) => image::viewer(handle.clone())
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .min_scale(0.25)
+                .max_scale(10.0)
+                .into(),
+            None => container(text("Loading...").font(self.font_ui()))
+                .center_x(Length::Fill)
+                .center_y(Length::Fill)
+                .into(),
+        };
 
-        container(column![header, row![sidebar, content].height(Length::Fill)])
+        let loading = if viewer.is_loading() {
+            "Loading full size..."
+        } else {
+            ""
+        };
+        let last = viewer.photos.len().saturating_sub(1);
+        let bar = row![
+            text(viewer.position()).font(self.font_ui_bold()),
+            text(loading)
+                .size(12)
+                .font(self.font_ui())
+                .color(styles.palette.muted),
+            horizontal_space(),
+            button(text("◀").font(self.font_ui_bold()))
+                .on_press_maybe(
+                    (viewer.index > 0).then_some(Message::ViewerKey(ViewerKey::Previous))
+                )
+                .style(move |theme, status| styles.button_secondary(theme, status)),
+            button(text("▶").font(self.font_ui_bold()))
+                .on_press_maybe(
+                    (viewer.index < last).then_some(Message::ViewerKey(ViewerKey::Next))
+                )
+                .style(move |theme, status| styles.button_secondary(theme, status)),
+            button(text("Save").font(self.font_ui_bold()))
+                .on_press(Message::ViewerSave)
+                .style(move |theme, status| styles.button_primary(theme, status)),
+            button(text("Close").font(self.font_ui_bold()))
+                .on_press(Message::ViewerClosed)
+                .style(move |theme, status| styles.button_secondary(theme, status)),
+        ]
+        .spacing(10)
+        .align_y(Alignment::Center);
+
+        let overlay = container(column![bar, picture].spacing(20))
+            .padding(40)
             .width(Length::Fill)
             .height(Length::Fill)
-            .style(cosmic_root)
-            .into()
+            .style(move |theme| styles.overlay(theme));
+        Some(mouse_area(overlay).on_press(Message::ViewerClosed).into())
     }
 
-    /// Render chat list sidebar.
-    fn view_chat_list(&self) -> Element<'_, Message> {
-        let chats: Vec<Element<'_, Message>> = self
-            .chats
+    /// Ctrl+K overlay: a query and the chats matching it. Enter or a click
+    /// opens a chat; a click elsewhere closes the overlay.
+    fn view_chat_switcher(&self) -> Option<Element<'_, Message>> {
+        let styles = self.styles;
+        let switcher = self.chat_switcher.as_ref()?;
+
+        let query = text_input("Go to chat...", &switcher.query)
+            .id(switcher_input_id())
+            .on_input(Message::SwitcherQueryChanged)
+            .on_submit(Message::SwitcherSubmit)
+            .style(move |theme, status| styles.text_input(theme, status))
+            .padding(10)
+            .width(Length::Fill);
+
+        let rows = switcher
+            .matches
             .iter()
             .enumerate()
+            .filter_map(|(pos, &idx)| {
+                let chat = self.chats.get(idx)?;
+                let is_selected = pos == switcher.selected;
+                let row = button(text(&chat.title).size(14).font(self.font_ui()))
+                    .on_press(Message::ChatSelected(idx))
+                    .width(Length::Fill)
+                    .padding(8)
+                    .style(move |theme, status| styles.chat_button(theme, status, is_selected));
+                Some(Element::from(row))
+            });
+        let results: Element<'_, Message> = if switcher.matches.is_empty() {
+            text("No chats found")
+                .size(12)
+                .font(self.font_ui())
+                .color(styles.palette.muted)
+                .into()
+        } else {
+            Column::with_children(rows).spacing(4).into()
+        };
+
+        let hint = text("↑↓ to choose, Enter to open, Esc to close")
+            .size(11)
+            .font(self.font_ui())
+            .color(styles.palette.muted);
+
+        let panel = container(column![query, results, hint].spacing(10))
+            .padding(12)
+            .width(Length::Fixed(420.0))
+            .style(move |theme| styles.panel(theme));
+
+        let overlay = container(panel)
+            .padding(80)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .center_x(Length::Fill)
+            .style(move |theme| styles.overlay(theme));
+        Some(
+            mouse_area(overlay)
+                .on_press(Message::SwitcherKey(SwitcherKey::Close))
+                .into(),
+        )
+    }
+
+    /// Render chat list sidebar.
+    fn view_chat_list(&self) -> Element<'_, Message> {
+        let styles = self.styles;
+        let chats: Vec<Element<'_, Message>> = filter_chats(&self.chats, &self.chat_filter)
+            .into_iter()
+            .filter_map(|idx| Some((idx, self.chats.get(idx)?)))
             .map(|(idx, chat)| {
                 let is_selected = idx == self.selected_chat;
 
-                let title_text = if chat.unread_count > 0 {
-                    format!("{} ({})", chat.title, chat.unread_count)
+                let chat_title = if self.profile_warmup.is_pending(chat.id) {
+                    LOADING_NAME
+                } else {
+                    chat.title.as_str()
+                };
+                let mut title_text = if chat.unread_count > 0 {
+                    format!("{} ({})", chat_title, chat.unread_count)
                 } else {
-                    chat.title.clone()
+                    chat_title.to_string()
                 };
+                if chat.is_pinned {
+                    title_text.insert_str(0, "📌 ");
+                }
 
                 let title = text(title_text).size(14).font(self.font_ui_bold());
 
-                let preview_text = truncate_text(&chat.last_message, 30);
+                let preview_text = truncate_text(&chat.preview(), 30);
                 let preview = text(preview_text)
                     .size(12)
                     .font(self.font_ui())
-                    .color(COSMIC_MUTED);
+                    .color(styles.palette.muted);
 
                 let online_indicator = if chat.is_online {
-                    text(" ●").size(12).color(COSMIC_SUCCESS)
+                    text(" ●").size(12).color(styles.palette.success)
                 } else {
                     text("").size(12)
                 };
 
-                let chat_row = column![row![title, online_indicator], preview].spacing(4);
+                let chat_row = row![
+                    self.view_avatar(chat.photo_url.as_deref(), &chat.title),
+                    column![row![title, online_indicator], preview].spacing(4)
+                ]
+                .spacing(10)
+                .align_y(Alignment::Center);
 
                 let btn = button(chat_row)
                     .on_press(Message::ChatSelected(idx))
                     .width(Length::Fill)
                     .padding(10)
-                    .style(move |theme, status| cosmic_chat_button(theme, status, is_selected));
+                    .style(move |theme, status| styles.chat_button(theme, status, is_selected));
 
                 btn.into()
             })
             .collect();
 
+        let unread_total = total_unread(&self.chats);
+        let heading = if unread_total > 0 {
+            format!("Chats ({} unread)", unread_total)
+        } else {
+            "Chats".to_string()
+        };
+        let heading = text(heading).size(13).font(self.font_ui_bold());
+
+        let filter = text_input("Search chats (Ctrl+F)", &self.chat_filter)
+            .id(chat_filter_id())
+            .on_input(Message::ChatFilterChanged)
+            .on_submit(Message::ChatFilterSubmit)
+            .style(move |theme, status| styles.text_input(theme, status))
+            .padding(6)
+            .width(Length::Fill);
+
         let chat_list = scrollable(Column::with_children(chats).spacing(6)).height(Length::Fill);
 
-        container(chat_list)
+        container(column![heading, filter, chat_list].spacing(6))
             .width(Length::Fixed(300.0))
             .height(Length::Fill)
             .padding(6)
-            .style(cosmic_sidebar)
+            .style(move |theme| styles.sidebar(theme))
             .into()
     }
 
     /// Render conversation view.
     fn view_conversation(&self) -> Element<'_, Message> {
+        let styles = self.styles;
         if self.current_peer_id.is_none() {
             return container(text("Select a chat").size(16).font(self.font_ui_bold()))
                 .width(Length::Fill)
                 .height(Length::Fill)
                 .center_x(Length::Fill)
                 .center_y(Length::Fill)
-                .style(cosmic_panel)
+                .style(move |theme| styles.panel(theme))
                 .into();
         }
 
-        let messages: Vec<Element<'_, Message>> = self
+        let clock = Clock::default();
+        let now = chrono_timestamp();
+        let mut messages: Vec<Element<'_, Message>> = self
             .messages
             .iter()
             .enumerate()
             .map(|(idx, msg)| {
+                // Joins, leaves, renames: a muted centered line, no bubble
+                if msg.is_service() {
+                    return container(
+                        text(&msg.text)
+                            .size(12)
+                            .font(self.font_ui())
+                            .color(styles.palette.muted),
+                    )
+                    .width(Length::Fill)
+                    .center_x(Length::Fill)
+                    .padding(4)
+                    .into();
+                }
                 let is_selected = idx == self.selected_message;
 
-                let from = text(&msg.from_name).size(12).font(self.font_ui_bold());
-                let content_text = text(&msg.text).size(14).font(self.font_ui());
+                let from_name = if self.profile_warmup.is_pending(msg.from_id) {
+                    LOADING_NAME
+                } else {
+                    msg.from_name.as_str()
+                };
+                let from = text(from_name).size(12).font(self.font_ui_bold());
+                let content_text = text(msg.display_text()).size(14).font(self.font_ui());
 
-                let time = format_timestamp(msg.timestamp);
-                let time_text = text(time).size(10).font(self.font_ui()).color(COSMIC_MUTED);
+                let time = clock.message_time(msg.timestamp, now);
+                let time_text = text(time)
+                    .size(10)
+                    .font(self.font_ui())
+                    .color(styles.palette.muted);
+                let edited = msg.is_edited.then(|| {
+                    text("(edited)")
+                        .size(10)
+                        .font(self.font_ui())
+                        .color(styles.palette.muted)
+                });
+                let pinned = msg
+                    .is_pinned
+                    .then(|| text("📌").size(10).font(self.font_ui()));
+                let reply = msg.reply.as_ref().map(|reply| {
+                    self.view_quote(
+                        column![
+                            text(&reply.from).size(12).font(self.font_ui_bold()),
+                            text(reply_snippet(&reply.text, &reply.attachments))
+                                .size(12)
+                                .font(self.font_ui())
+                                .color(styles.palette.muted)
+                        ]
+                        .spacing(2),
+                    )
+                });
 
                 let status = if msg.is_outgoing {
                     if msg.is_read {
-                        text("✓✓").size(10).font(self.font_ui()).color(COSMIC_MUTED)
+                        text("✓✓")
+                            .size(10)
+                            .font(self.font_ui())
+                            .color(styles.palette.muted)
                     } else {
-                        text("✓").size(10).font(self.font_ui()).color(COSMIC_MUTED)
+                        text("✓")
+                            .size(10)
+                            .font(self.font_ui())
+                            .color(styles.palette.muted)
                     }
                 } else {
                     text("").size(10)
                 };
 
-                let msg_content =
-                    column![row![from, time_text].spacing(10), content_text, status].spacing(4);
+                let reactions = Row::with_children(msg.reactions.iter().map(|r| {
+                    let mine = r.mine;
+                    button(
+                        text(vk_core::format_reactions(std::slice::from_ref(r)))
+                            .size(12)
+                            .font(self.font_ui()),
+                    )
+                    .on_press(Message::ReactionPressed(msg.id, r.reaction_id))
+                    .padding([2, 6])
+                    .style(move |theme, status| {
+                        if mine {
+                            styles.button_primary(theme, status)
+                        } else {
+                            styles.button_secondary(theme, status)
+                        }
+                    })
+                    .into()
+                }))
+                .spacing(6);
+
+                let upload = msg
+                    .upload
+                    .filter(|_| msg.delivery == DeliveryStatus::Pending)
+                    .map(|upload| {
+                        progress_bar(0.0..=100.0, f32::from(upload.percent()))
+                            .width(Length::Fixed(200.0))
+                            .height(Length::Fixed(6.0))
+                    });
+
+                let photos = Row::with_children(image_viewer::photos(msg).enumerate().map(
+                    |(photo_idx, photo)| {
+                        let thumbnail: Element<'_, Message> = match photo
+                            .thumbnail_url
+                            .as_deref()
+                            .and_then(|url| self.avatars.get(url))
+                        {
+                            Some(handle) => image(handle.clone())
+                                .height(Length::Fixed(THUMBNAIL_HEIGHT))
+                                .into(),
+                            None => text("[photo]").size(12).font(self.font_ui()).into(),
+                        };
+                        button(thumbnail)
+                            .on_press(Message::PhotoPressed(idx, photo_idx))
+                            .padding(0)
+                            .style(move |theme, status| styles.button_secondary(theme, status))
+                            .into()
+                    },
+                ))
+                .spacing(6);
+
+                // Link previews: page image, title and description
+                let links = Column::with_children(
+                    msg.attachments
+                        .iter()
+                        .filter(|a| matches!(a.kind, AttachmentKind::Link))
+                        .filter_map(|a| {
+                            let url = a.url.clone()?;
+                            let preview = a
+                                .thumbnail_url
+                                .as_deref()
+                                .and_then(|url| self.avatars.get(url))
+                                .map(|handle| image(handle.clone()).height(Length::Fixed(64.0)));
+                            let title = text(&a.title)
+                                .size(13)
+                                .font(self.font_ui())
+                                .color(styles.accent);
+                            let description = a.description.as_deref().map(|d| {
+                                text(d)
+                                    .size(12)
+                                    .font(self.font_ui())
+                                    .color(styles.palette.muted)
+                            });
+                            let card = row![]
+                                .push_maybe(preview)
+                                .push(column![title].push_maybe(description).spacing(2))
+                                .spacing(8);
+                            Some(
+                                button(card)
+                                    .on_press(Message::AttachmentPressed(url))
+                                    .padding(6)
+                                    .style(move |theme, status| {
+                                        styles.button_secondary(theme, status)
+                                    })
+                                    .into(),
+                            )
+                        }),
+                )
+                .spacing(4);
+
+                // Files open in the browser; wall posts, polls and the like
+                // open their vk.com page
+                let others = Column::with_children(
+                    msg.attachments
+                        .iter()
+                        .filter(|a| !matches!(a.kind, AttachmentKind::Photo | AttachmentKind::Link))
+                        .map(|a| self.view_attachment_chip(a)),
+                )
+                .spacing(4);
+
+                let forwards: Option<Element<'_, Message>> = if !msg.forwards.is_empty() {
+                    let expanded = self.expanded_forwards.contains(&msg.id);
+                    let title = format!(
+                        "{} {}",
+                        if expanded { "▾" } else { "▸" },
+                        forwards_title(&msg.forwards, msg.fwd_count)
+                    );
+                    let toggle = button(text(title).size(12).font(self.font_ui()))
+                        .on_press(Message::ToggleForwards(msg.id))
+                        .padding([2, 6])
+                        .style(move |theme, status| styles.button_secondary(theme, status));
+                    let mut section = column![toggle].spacing(4);
+                    if expanded {
+                        section =
+                            section.extend(msg.forwards.iter().map(|item| self.view_forward(item)));
+                    }
+                    Some(section.into())
+                } else if msg.fwd_count > 0 {
+                    Some(
+                        text(format!("↪ forwarded {}", msg.fwd_count))
+                            .size(12)
+                            .font(self.font_ui())
+                            .color(styles.palette.muted)
+                            .into(),
+                    )
+                } else {
+                    None
+                };
+
+                let msg_content = row![
+                    self.view_avatar(msg.from_photo.as_deref(), &msg.from_name),
+                    column![
+                        row![from, time_text]
+                            .push_maybe(edited)
+                            .push_maybe(pinned)
+                            .spacing(10)
+                    ]
+                    .push_maybe(reply)
+                    .push(content_text)
+                    .push(photos)
+                    .push(links)
+                    .push(others)
+                    .push_maybe(forwards)
+                    .push_maybe(upload)
+                    .push(reactions)
+                    .push(status)
+                    .spacing(4)
+                ]
+                .spacing(10);
 
                 let btn = button(msg_content)
                     .on_press(Message::MessageSelected(idx))
                     .width(Length::Fill)
                     .padding(10)
                     .style(move |theme, status| {
-                        cosmic_message_button(theme, status, is_selected, msg.is_outgoing)
+                        styles.message_button(theme, status, is_selected, msg.is_outgoing)
                     });
 
-                btn.into()
+                let day = starts_day(&self.messages, idx, &clock).then(|| {
+                    let day = text(clock.day_label(msg.timestamp))
+                        .size(11)
+                        .font(self.font_ui())
+                        .color(styles.palette.muted);
+                    container(day).center_x(Length::Fill)
+                });
+                let unread = (self.unread_divider == Some(msg.id)).then(|| {
+                    let label = text("— New messages —")
+                        .size(11)
+                        .font(self.font_ui_bold())
+                        .color(styles.accent);
+                    container(label).center_x(Length::Fill)
+                });
+                if day.is_none() && unread.is_none() {
+                    btn.into()
+                } else {
+                    column![]
+                        .push_maybe(day)
+                        .push_maybe(unread)
+                        .push(btn)
+                        .spacing(8)
+                        .into()
+                }
             })
             .collect();
+        // Unsent texts stay below the history until VK takes them
+        if let Some(peer_id) = self.current_peer_id {
+            messages.extend(self.outbox.for_peer(peer_id).map(|entry| {
+                container(column![
+                    text(&entry.text).size(14).font(self.font_ui()),
+                    self.view_unsent(entry)
+                ])
+                .padding(10)
+                .width(Length::Fill)
+                .style(move |theme| styles.panel(theme))
+                .into()
+            }));
+        }
 
-        let messages_view =
-            scrollable(Column::with_children(messages).spacing(8)).height(Length::Fill);
+        let load_older = self
+            .messages_pagination
+            .as_ref()
+            .filter(|p| p.has_more && p.first_cmid.is_some())
+            .map(|p| {
+                let label = if p.is_loading {
+                    "Loading…"
+                } else {
+                    "Load older messages"
+                };
+                let btn = button(text(label).size(12).font(self.font_ui()))
+                    .on_press_maybe((!p.is_loading).then_some(Message::LoadOlderMessages))
+                    .padding([4, 10])
+                    .style(move |theme, status| styles.button_secondary(theme, status));
+                container(btn).center_x(Length::Fill)
+            });
+
+        let messages_list =
+            scrollable(column![].push_maybe(load_older).extend(messages).spacing(8))
+                .id(messages_scroll_id())
+                .on_scroll(Message::MessagesScrolled)
+                .height(Length::Fill);
+        let messages_view: Element<'_, Message> = if self.message_scroll.at_bottom {
+            messages_list.into()
+        } else {
+            let label = match self.message_scroll.unseen {
+                0 => "Latest ↓".to_string(),
+                1 => "1 new message ↓".to_string(),
+                n => format!("{} new messages ↓", n),
+            };
+            let jump = button(text(label).size(12).font(self.font_ui_bold()))
+                .on_press(Message::ScrollToBottom)
+                .padding([6, 12])
+                .style(move |theme, status| styles.button_primary(theme, status));
+            stack![
+                messages_list,
+                container(jump)
+                    .align_right(Length::Fill)
+                    .align_bottom(Length::Fill)
+                    .padding(12)
+            ]
+            .into()
+        };
 
         let selected_msg = self.messages.get(self.selected_message);
         let action_row = if let Some(msg) = selected_msg {
             let reply_btn = button(text("Reply").font(self.font_ui_bold()))
                 .on_press(Message::ReplyPressed(msg.id))
-                .style(cosmic_button_secondary);
+                .style(move |theme, status| styles.button_secondary(theme, status));
             let forward_btn = button(text("Forward").font(self.font_ui_bold()))
                 .on_press(Message::ForwardPressed(msg.id))
-                .style(cosmic_button_secondary);
+                .style(move |theme, status| styles.button_secondary(theme, status));
             let delete_btn = button(text("Delete").font(self.font_ui_bold()))
                 .on_press(Message::DeletePressed(msg.id))
-                .style(cosmic_button_danger);
+                .style(move |theme, status| styles.button_danger(theme, status));
             let edit_btn = if msg.is_outgoing {
                 button(text("Edit").font(self.font_ui_bold()))
                     .on_press(Message::EditPressed(msg.id))
-                    .style(cosmic_button_secondary)
+                    .style(move |theme, status| styles.button_secondary(theme, status))
             } else {
-                button(text("Edit").font(self.font_ui_bold())).style(cosmic_button_secondary)
+                button(text("Edit").font(self.font_ui_bold()))
+                    .style(move |theme, status| styles.button_secondary(theme, status))
             };
-            row![reply_btn, forward_btn, edit_btn, delete_btn].spacing(10)
+            let react_btns = vk_core::reaction_emojis().filter_map(|emoji| {
+                let reaction_id = vk_core::reaction_id(emoji)?;
+                Some(
+                    button(text(emoji).size(12).font(self.font_ui()))
+                        .on_press(Message::ReactionPressed(msg.id, reaction_id))
+                        .padding([2, 6])
+                        .style(move |theme, status| styles.button_secondary(theme, status))
+                        .into(),
+                )
+            });
+            row![
+                reply_btn,
+                forward_btn,
+                edit_btn,
+                delete_btn,
+                Row::with_children(react_btns).spacing(4)
+            ]
+            .spacing(10)
         } else {
             row![]
         };
@@ -968,18 +2395,18 @@ impl VkApp {
                 text("Delete message?")
                     .size(12)
                     .font(self.font_ui())
-                    .color(COSMIC_MUTED),
+                    .color(styles.palette.muted),
                 button(text("For me").font(self.font_ui_bold()))
                     .on_press(Message::DeleteForMe(message_id))
-                    .style(cosmic_button_secondary)
+                    .style(move |theme, status| styles.button_secondary(theme, status))
                     .padding(6),
                 button(text("For all").font(self.font_ui_bold()))
                     .on_press(Message::DeleteForAll(message_id))
-                    .style(cosmic_button_primary)
+                    .style(move |theme, status| styles.button_primary(theme, status))
                     .padding(6),
                 button(text("Cancel").font(self.font_ui_bold()))
                     .on_press(Message::CancelDelete)
-                    .style(cosmic_button_secondary)
+                    .style(move |theme, status| styles.button_secondary(theme, status))
                     .padding(6),
             ]
             .spacing(10)
@@ -998,10 +2425,10 @@ impl VkApp {
                 text(reply_text)
                     .size(12)
                     .font(self.font_ui())
-                    .color(COSMIC_MUTED),
+                    .color(styles.palette.muted),
                 button(text("✕").size(12).font(self.font_ui_bold()))
                     .on_press(Message::CancelReply)
-                    .style(cosmic_button_secondary)
+                    .style(move |theme, status| styles.button_secondary(theme, status))
                     .padding(4),
             ]
             .spacing(10)
@@ -1018,10 +2445,10 @@ impl VkApp {
                 text(edit_text)
                     .size(12)
                     .font(self.font_ui())
-                    .color(COSMIC_MUTED),
+                    .color(styles.palette.muted),
                 button(text("✕").size(12).font(self.font_ui_bold()))
                     .on_press(Message::CancelEdit)
-                    .style(cosmic_button_secondary)
+                    .style(move |theme, status| styles.button_secondary(theme, status))
                     .padding(4),
             ]
             .spacing(10)
@@ -1034,10 +2461,10 @@ impl VkApp {
                 text("Select target chat to forward")
                     .size(12)
                     .font(self.font_ui())
-                    .color(COSMIC_MUTED),
+                    .color(styles.palette.muted),
                 button(text("✕").size(12).font(self.font_ui_bold()))
                     .on_press(Message::CancelForward)
-                    .style(cosmic_button_secondary)
+                    .style(move |theme, status| styles.button_secondary(theme, status))
                     .padding(4),
             ]
             .spacing(10),
@@ -1046,23 +2473,23 @@ impl VkApp {
                     text_input("Forward comment (required)...", &self.forward_comment)
                         .on_input(Message::ForwardCommentChanged)
                         .on_submit(Message::ForwardSubmit)
-                        .style(cosmic_text_input)
+                        .style(move |theme, status| styles.text_input(theme, status))
                         .padding(8)
                         .width(Length::Fill);
                 let send_btn = if self.forward_comment.trim().is_empty() {
                     button(text("Send forward").font(self.font_ui_bold()))
-                        .style(cosmic_button_secondary)
+                        .style(move |theme, status| styles.button_secondary(theme, status))
                 } else {
                     button(text("Send forward").font(self.font_ui_bold()))
                         .on_press(Message::ForwardSubmit)
-                        .style(cosmic_button_primary)
+                        .style(move |theme, status| styles.button_primary(theme, status))
                 };
                 row![
                     comment_input,
                     send_btn,
                     button(text("Cancel").font(self.font_ui_bold()))
                         .on_press(Message::CancelForward)
-                        .style(cosmic_button_secondary)
+                        .style(move |theme, status| styles.button_secondary(theme, status))
                         .padding(6),
                 ]
                 .spacing(10)
@@ -1070,28 +2497,101 @@ impl VkApp {
             None => row![],
         };
 
-        // Input area
-        let input = text_input("Type a message...", &self.message_input)
-            .on_input(Message::MessageInputChanged)
-            .on_submit(Message::SendPressed)
-            .style(cosmic_text_input)
+        // Input area; a read-only chat gets a disabled one that says why
+        let write_denied = self.write_denied();
+        let placeholder = write_denied.as_deref().unwrap_or("Type a message...");
+        let mut input = text_input(placeholder, &self.message_input)
+            .id(message_input_id())
+            .style(move |theme, status| styles.text_input(theme, status))
             .padding(10)
             .width(Length::Fill);
+        if write_denied.is_none() {
+            input = input
+                .on_input(Message::MessageInputChanged)
+                .on_submit(Message::SendPressed);
+        }
 
         let send_btn = button(text("Send").font(self.font_ui_bold()))
-            .on_press(Message::SendPressed)
-            .style(cosmic_button_primary)
+            .on_press_maybe(write_denied.is_none().then_some(Message::SendPressed))
+            .style(move |theme, status| styles.button_primary(theme, status))
             .padding([10, 20]);
+        let (input, send_btn): (Element<'_, Message>, Element<'_, Message>) = match &write_denied {
+            Some(denied) => {
+                let hint = |denied: &str| {
+                    container(text(denied.to_string()).size(12).font(self.font_ui()))
+                        .padding(6)
+                        .style(container::rounded_box)
+                };
+                (
+                    tooltip(input, hint(denied), tooltip::Position::Top).into(),
+                    tooltip(send_btn, hint(denied), tooltip::Position::Top).into(),
+                )
+            }
+            None => (input.into(), send_btn.into()),
+        };
+
+        let picker_open = self.emoji_picker.open;
+        let emoji_btn = button(text("☺").font(self.font_ui_bold()))
+            .on_press(Message::EmojiPickerToggle)
+            .style(move |theme, status| {
+                if picker_open {
+                    styles.button_primary(theme, status)
+                } else {
+                    styles.button_secondary(theme, status)
+                }
+            })
+            .padding([10, 14]);
+
+        let mut input_row = row![input].spacing(10).align_y(Alignment::Center);
+        if let Some(counter) = length_counter(&self.message_input) {
+            let color = if too_long(&self.message_input) {
+                styles.palette.danger
+            } else {
+                styles.palette.muted
+            };
+            input_row = input_row.push(text(counter).size(12).font(self.font_ui()).color(color));
+        }
+        let input_row = input_row.push(emoji_btn).push(send_btn);
 
-        let input_row = row![input, send_btn].spacing(10);
+        let chat_title = self
+            .chats
+            .iter()
+            .find(|c| Some(c.id) == self.current_peer_id)
+            .map(|c| c.header(chrono_timestamp()))
+            .unwrap_or_default();
+        let panel_open = self.profile_panel.is_some();
+        let chat_header = button(text(chat_title).size(16).font(self.font_ui_bold()))
+            .on_press(Message::ChatHeaderPressed)
+            .width(Length::Fill)
+            .padding(8)
+            .style(move |theme, status| styles.chat_button(theme, status, panel_open));
+
+        let chat_info: Element<'_, Message> = match self.current_peer_id {
+            Some(peer_id) if self.chat_info_open => {
+                let summary = self
+                    .chat_infos
+                    .get(&peer_id)
+                    .map(|info| info.summary())
+                    .unwrap_or_else(|| "Counting media...".to_string());
+                text(summary)
+                    .size(12)
+                    .font(self.font_ui())
+                    .color(styles.palette.muted)
+                    .into()
+            }
+            _ => row![].into(),
+        };
 
         let content = column![
+            chat_header,
+            chat_info,
             messages_view,
             action_row,
             delete_row,
             reply_row,
             edit_row,
             forward_row,
+            self.view_emoji_picker(),
             input_row
         ]
         .spacing(10)
@@ -1100,29 +2600,471 @@ impl VkApp {
         container(content)
             .width(Length::Fill)
             .height(Length::Fill)
-            .style(cosmic_panel)
+            .style(move |theme| styles.panel(theme))
+            .into()
+    }
+
+    /// Profile of the open chat's peer, shown after clicking the chat header.
+    fn view_profile_panel(&self) -> Element<'_, Message> {
+        let styles = self.styles;
+        let Some((_, profile)) = &self.profile_panel else {
+            return row![].into();
+        };
+
+        let body: Element<'_, Message> = match profile {
+            None => text("Loading...")
+                .size(12)
+                .font(self.font_ui())
+                .color(styles.palette.muted)
+                .into(),
+            Some(profile) => {
+                let field = |label: &str, value: String| {
+                    column![
+                        text(label.to_string())
+                            .size(11)
+                            .font(self.font_ui())
+                            .color(styles.palette.muted),
+                        text(value).size(13).font(self.font_ui()),
+                    ]
+                    .spacing(2)
+                };
+
+                let mut details = Column::new().spacing(10);
+                if let Some(presence) = profile.presence(chrono_timestamp()) {
+                    let color = if profile.is_online {
+                        styles.palette.success
+                    } else {
+                        styles.palette.muted
+                    };
+                    details = details.push(text(presence).size(12).color(color));
+                }
+                if let Some(city) = &profile.city {
+                    details = details.push(field("City", city.clone()));
+                }
+                if let Some(bdate) = &profile.bdate {
+                    details = details.push(field("Birthday", bdate.clone()));
+                }
+                if let Some(count) = profile.members_count {
+                    details = details.push(field("Members", count.to_string()));
+                }
+                if let Some(status) = &profile.status {
+                    details = details.push(field("Status", status.clone()));
+                }
+
+                let screen_name = profile
+                    .screen_name
+                    .as_ref()
+                    .map(|s| format!("@{}", s))
+                    .unwrap_or_default();
+                column![
+                    row![
+                        self.view_avatar(profile.photo_url.as_deref(), &profile.name),
+                        column![
+                            text(&profile.name).size(15).font(self.font_ui_bold()),
+                            text(screen_name)
+                                .size(11)
+                                .font(self.font_ui())
+                                .color(styles.palette.muted),
+                        ]
+                        .spacing(2)
+                    ]
+                    .spacing(10)
+                    .align_y(Alignment::Center),
+                    details
+                ]
+                .spacing(14)
+                .into()
+            }
+        };
+
+        container(body)
+            .width(Length::Fixed(260.0))
+            .height(Length::Fill)
+            .padding(12)
+            .style(move |theme| styles.sidebar(theme))
+            .into()
+    }
+
+    /// Avatar image, or the first letter of `name` until it is loaded.
+    /// Indented block for a quoted reply or a forwarded message.
+    fn view_quote<'a>(&self, content: impl Into<Element<'a, Message>>) -> Element<'a, Message> {
+        let styles = self.styles;
+        container(
+            container(content)
+                .padding([4, 10])
+                .style(move |theme| styles.quote(theme)),
+        )
+        .padding(Padding::ZERO.left(8.0))
+        .into()
+    }
+
+    /// Chip of a file or other attachment: icon, title and size, opened on
+    /// click, with a Download button if the file can be saved.
+    fn view_attachment_chip<'a>(&'a self, att: &'a AttachmentInfo) -> Element<'a, Message> {
+        let styles = self.styles;
+        let open = button(text(chip_label(att)).size(12).font(self.font_ui()))
+            .on_press_maybe(att.url.clone().map(Message::AttachmentPressed))
+            .padding([2, 6])
+            .style(move |theme, status| styles.button_secondary(theme, status));
+        let download = att.is_downloadable().then(|| {
+            button(text("Download").size(12).font(self.font_ui_bold()))
+                .on_press(Message::DownloadAttachment(att.clone()))
+                .padding([2, 6])
+                .style(move |theme, status| styles.button_secondary(theme, status))
+        });
+        row![open].push_maybe(download).spacing(4).into()
+    }
+
+    /// A forwarded message with its attachments and the messages it
+    /// forwards in turn.
+    fn view_forward<'a>(&'a self, item: &'a ForwardItem) -> Element<'a, Message> {
+        let body = (!item.text.is_empty()).then(|| text(&item.text).size(13).font(self.font_ui()));
+        self.view_quote(
+            column![text(&item.from).size(12).font(self.font_ui_bold())]
+                .push_maybe(body)
+                .extend(
+                    item.attachments
+                        .iter()
+                        .map(|a| self.view_attachment_chip(a)),
+                )
+                .extend(item.nested.iter().map(|nested| self.view_forward(nested)))
+                .spacing(4),
+        )
+    }
+
+    fn view_avatar(&self, url: Option<&str>, name: &str) -> Element<'_, Message> {
+        let styles = self.styles;
+        if let Some(handle) = url.and_then(|url| self.avatars.get(url)) {
+            return image(handle.clone())
+                .width(Length::Fixed(avatars::SIZE))
+                .height(Length::Fixed(avatars::SIZE))
+                .into();
+        }
+
+        let initial = name
+            .chars()
+            .next()
+            .map(|c| c.to_uppercase().to_string())
+            .unwrap_or_default();
+        container(text(initial).size(14).font(self.font_ui_bold()))
+            .width(Length::Fixed(avatars::SIZE))
+            .height(Length::Fixed(avatars::SIZE))
+            .center_x(Length::Fixed(avatars::SIZE))
+            .center_y(Length::Fixed(avatars::SIZE))
+            .style(move |theme| styles.avatar(theme))
             .into()
     }
 
     fn view_header(&self) -> Element<'_, Message> {
+        let styles = self.styles;
         let title = text("Messages")
             .size(18)
             .font(self.font_ui_bold())
-            .color(COSMIC_TEXT);
+            .color(styles.palette.text);
         let status = self.status.as_deref().unwrap_or("Ready");
         let status_text = text(status)
             .size(12)
             .font(self.font_ui())
-            .color(COSMIC_MUTED);
+            .color(styles.palette.muted);
+        let link = text(self.long_poll.label())
+            .size(12)
+            .font(self.font_ui())
+            .color(match self.long_poll {
+                LongPollState::Connected => styles.palette.success,
+                LongPollState::Reconnecting { .. } | LongPollState::Polling => styles.palette.muted,
+                LongPollState::Offline => styles.palette.danger,
+            });
+        let account = self.current_user.as_ref().map(|user| {
+            text(user.full_name())
+                .size(12)
+                .font(self.font_ui_bold())
+                .color(styles.palette.text)
+        });
+
+        let settings_btn = button(text("Settings").font(self.font_ui_bold()))
+            .on_press(Message::ToggleSettings)
+            .style(move |theme, status| styles.button_secondary(theme, status))
+            .padding([4, 12]);
 
-        let content = row![title, status_text]
+        let unsent = self.outbox.label().map(|label| {
+            button(text(label).size(12).font(self.font_ui_bold()))
+                .on_press(Message::ToggleOutbox)
+                .style(move |theme, status| styles.button_danger(theme, status))
+                .padding([4, 12])
+        });
+
+        let content = row![title, status_text, iced::widget::horizontal_space(), link]
+            .push_maybe(unsent)
+            .push_maybe(account)
+            .push(settings_btn)
             .spacing(16)
             .align_y(iced::Alignment::Center);
 
         container(content)
             .padding(12)
-            .style(cosmic_header)
+            .style(move |theme| styles.header(theme))
+            .width(Length::Fill)
+            .into()
+    }
+
+    /// Render appearance settings (theme and accent color) and sounds.
+    /// Emoji picker above the input: search box, recent row and a grid of
+    /// results. Empty while the picker is closed.
+    fn view_emoji_picker(&self) -> Element<'_, Message> {
+        let styles = self.styles;
+        let picker = &self.emoji_picker;
+        if !picker.open {
+            return row![].into();
+        }
+
+        let search = text_input("Search emoji (name or :shortcode:)", &picker.query)
+            .id(emoji_search_id())
+            .on_input(Message::EmojiQueryChanged)
+            .on_submit(Message::EmojiSubmit)
+            .style(move |theme, status| styles.text_input(theme, status))
+            .padding(6)
+            .width(Length::Fill);
+
+        let emoji_button = |emoji: &str, selected: bool| -> Element<'_, Message> {
+            button(text(emoji.to_string()).size(18).font(self.font_ui()))
+                .on_press(Message::EmojiPicked(emoji.to_string()))
+                .padding([2, 6])
+                .style(move |theme, status| {
+                    if selected {
+                        styles.button_primary(theme, status)
+                    } else {
+                        styles.button_secondary(theme, status)
+                    }
+                })
+                .into()
+        };
+
+        let recent: Element<'_, Message> = if picker.recent.list().is_empty() {
+            row![].into()
+        } else {
+            row![
+                text("Recent")
+                    .size(12)
+                    .font(self.font_ui())
+                    .color(styles.palette.muted),
+                Row::with_children(
+                    picker
+                        .recent
+                        .list()
+                        .iter()
+                        .map(|emoji| emoji_button(emoji, false))
+                )
+                .spacing(4)
+            ]
+            .spacing(10)
+            .align_y(Alignment::Center)
+            .into()
+        };
+
+        let results = picker.results();
+        let grid: Element<'_, Message> = if results.is_empty() {
+            text("No emoji found")
+                .size(12)
+                .font(self.font_ui())
+                .color(styles.palette.muted)
+                .into()
+        } else {
+            Column::with_children(results.chunks(emoji_picker::COLUMNS).enumerate().map(
+                |(row_idx, chunk)| {
+                    Row::with_children(chunk.iter().enumerate().map(|(col, e)| {
+                        let idx = row_idx * emoji_picker::COLUMNS + col;
+                        emoji_button(e.emoji, idx == picker.selected)
+                    }))
+                    .spacing(4)
+                    .into()
+                },
+            ))
+            .spacing(4)
+            .into()
+        };
+
+        let selected_name = picker
+            .results()
+            .get(picker.selected)
+            .map(|e| format!("{}  :{}:", e.name, e.shortcode))
+            .unwrap_or_default();
+
+        container(
+            column![
+                search,
+                recent,
+                scrollable(grid).height(Length::Fixed(180.0)),
+                text(selected_name)
+                    .size(11)
+                    .font(self.font_ui())
+                    .color(styles.palette.muted),
+            ]
+            .spacing(8),
+        )
+        .padding(8)
+        .style(move |theme| styles.panel(theme))
+        .into()
+    }
+
+    fn view_settings(&self) -> Element<'_, Message> {
+        let styles = self.styles;
+
+        let mode_label = match styles.mode {
+            ThemeMode::Dark => "Light theme",
+            ThemeMode::Light => "Dark theme",
+        };
+        let mode_btn = button(text(mode_label).font(self.font_ui_bold()))
+            .on_press(Message::ToggleThemeMode)
+            .style(move |theme, status| styles.button_secondary(theme, status))
+            .padding([6, 12]);
+
+        let swatches = ACCENT_PRESETS.iter().map(|&color| {
+            let selected = color == self.accent;
+            button(text("").width(Length::Fixed(20.0)))
+                .on_press(Message::AccentSelected(color))
+                .style(move |theme, status| styles.swatch(theme, status, color, selected))
+                .padding(6)
+                .into()
+        });
+
+        let hex_input = text_input("#58aaff", &self.accent_input)
+            .on_input(Message::AccentInputChanged)
+            .style(move |theme, status| styles.text_input(theme, status))
+            .padding(6)
+            .width(Length::Fixed(110.0));
+
+        let appearance = row![
+            mode_btn,
+            text("Accent")
+                .size(12)
+                .font(self.font_ui())
+                .color(styles.palette.muted),
+            row(swatches).spacing(6),
+            hex_input
+        ]
+        .spacing(12)
+        .align_y(iced::Alignment::Center);
+
+        let sound_label = if self.sound.enabled {
+            "Sound on"
+        } else {
+            "Sound off"
+        };
+        let sound_btn = button(text(sound_label).font(self.font_ui_bold()))
+            .on_press(Message::ToggleSound)
+            .style(move |theme, status| styles.button_secondary(theme, status))
+            .padding([6, 12]);
+
+        let volume = slider(0.0..=1.0, self.sound.volume, Message::SoundVolumeChanged)
+            .step(0.05)
+            .width(Length::Fixed(120.0));
+
+        let path_input = text_input("Custom sound file (optional)", &self.sound_path_input)
+            .on_input(Message::SoundPathChanged)
+            .style(move |theme, status| styles.text_input(theme, status))
+            .padding(6)
+            .width(Length::Fixed(260.0));
+
+        let sound = row![
+            sound_btn,
+            text("Volume")
+                .size(12)
+                .font(self.font_ui())
+                .color(styles.palette.muted),
+            volume,
+            path_input
+        ]
+        .spacing(12)
+        .align_y(iced::Alignment::Center);
+
+        let content = column![appearance, sound].spacing(8);
+
+        container(content)
+            .padding(10)
+            .width(Length::Fill)
+            .style(move |theme| styles.panel(theme))
+            .into()
+    }
+
+    /// State of an unsent message, with Retry/Delete once it failed or
+    /// Cancel while it waits for the connection.
+    fn view_unsent<'a>(&'a self, entry: &'a OutboxEntry) -> Element<'a, Message> {
+        let styles = self.styles;
+        let id = entry.id;
+        let color = match entry.state {
+            OutboxState::Failed(_) => styles.palette.danger,
+            OutboxState::Sending | OutboxState::Queued => styles.palette.muted,
+        };
+        let action = |label: &'static str, message: Message| {
+            button(text(label).size(12).font(self.font_ui()))
+                .on_press(message)
+                .padding([2, 8])
+                .style(move |theme, status| styles.button_secondary(theme, status))
+        };
+        let state = text(entry.state_text())
+            .size(12)
+            .font(self.font_ui())
+            .color(color);
+        let controls = match entry.state {
+            OutboxState::Failed(_) => row![
+                action("Retry", Message::RetryOutgoing(id)),
+                action("Delete", Message::DiscardOutgoing(id))
+            ],
+            OutboxState::Queued => row![action("Cancel", Message::DiscardOutgoing(id))],
+            OutboxState::Sending => row![],
+        };
+        row![state, controls.spacing(6)]
+            .spacing(10)
+            .align_y(iced::Alignment::Center)
+            .into()
+    }
+
+    /// Unsent messages of all chats under the header, opened from the
+    /// "N unsent" button, with bulk retry and cancel.
+    fn view_outbox(&self) -> Element<'_, Message> {
+        let styles = self.styles;
+        let entries = self.outbox.entries().iter().map(|entry| {
+            let chat = self
+                .chats
+                .iter()
+                .find(|c| c.id == entry.peer_id)
+                .map(|c| c.title.clone())
+                .unwrap_or_else(|| entry.peer_id.to_string());
+            row![
+                text(chat).size(12).font(self.font_ui_bold()),
+                text(truncate_text(&entry.text, 40))
+                    .size(12)
+                    .font(self.font_ui())
+                    .width(Length::Fill),
+                self.view_unsent(entry)
+            ]
+            .spacing(12)
+            .align_y(iced::Alignment::Center)
+            .into()
+        });
+
+        let bulk = row![
+            text("Unsent messages")
+                .size(12)
+                .font(self.font_ui_bold())
+                .color(styles.palette.muted),
+            iced::widget::horizontal_space(),
+            button(text("Retry all").size(12).font(self.font_ui_bold()))
+                .on_press(Message::RetryAllOutgoing)
+                .style(move |theme, status| styles.button_primary(theme, status))
+                .padding([4, 12]),
+            button(text("Cancel all").size(12).font(self.font_ui_bold()))
+                .on_press(Message::DiscardAllOutgoing)
+                .style(move |theme, status| styles.button_secondary(theme, status))
+                .padding([4, 12])
+        ]
+        .spacing(8)
+        .align_y(iced::Alignment::Center);
+
+        container(column![bulk, Column::with_children(entries).spacing(6)].spacing(8))
+            .padding(10)
             .width(Length::Fill)
+            .style(move |theme| styles.panel(theme))
             .into()
     }
 
@@ -1152,9 +3094,26 @@ impl VkApp {
         }
     }
 
+    /// Why nothing can be sent to the open chat, if it is read-only.
+    fn write_denied(&self) -> Option<String> {
+        self.chats
+            .iter()
+            .find(|c| Some(c.id) == self.current_peer_id)?
+            .write_denied()
+    }
+
+    /// Whether the loaded message `message_id` is a service message.
+    fn is_service_message(&self, message_id: i64) -> bool {
+        self.messages
+            .iter()
+            .any(|m| m.id == message_id && m.is_service())
+    }
+
     fn get_user_name(&self, user_id: i64) -> String {
         if let Some(user) = self.users.get(&user_id) {
             user.full_name()
+        } else if self.profile_warmup.is_pending(user_id) {
+            LOADING_NAME.to_string()
         } else if user_id < 0 {
             format!("Group {}", -user_id)
         } else {
@@ -1163,268 +3122,113 @@ impl VkApp {
     }
 }
 
-fn format_timestamp(timestamp: i64) -> String {
-    use std::time::{Duration, UNIX_EPOCH};
-
-    let datetime = UNIX_EPOCH + Duration::from_secs(timestamp as u64);
-    let now = std::time::SystemTime::now();
-
-    if let Ok(duration) = now.duration_since(datetime) {
-        let hours = duration.as_secs() / 3600;
-        if hours < 24 {
-            // Today - show time only
-            let secs = timestamp % 86400;
-            let h = (secs / 3600) % 24;
-            let m = (secs % 3600) / 60;
-            format!("{:02}:{:02}", h, m)
-        } else {
-            // Older - show date
-            let days = hours / 24;
-            format!("{}d ago", days)
-        }
-    } else {
-        "".to_string()
-    }
+fn message_input_id() -> text_input::Id {
+    text_input::Id::new("message-input")
 }
 
-fn chrono_timestamp() -> i64 {
-    std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap()
-        .as_secs() as i64
+fn emoji_search_id() -> text_input::Id {
+    text_input::Id::new("emoji-search")
 }
 
-fn is_auth_error(msg: &str) -> bool {
-    msg.contains("VK API error 5")
-        || msg.contains("VK API error 7")
-        || msg.contains("VK API error 179")
-        || msg.to_lowercase().contains("authorization failed")
+fn chat_filter_id() -> text_input::Id {
+    text_input::Id::new("chat-filter")
 }
 
-fn looks_like_oauth_url(input: &str) -> bool {
-    input.contains("access_token=")
-        || input.contains("oauth.vk.com/blank.html")
-        || input.starts_with("https://oauth.vk.com/blank.html#")
-        || input.starts_with("oauth.vk.com/blank.html#")
-        || input.starts_with("//oauth.vk.com/blank.html#")
+fn switcher_input_id() -> text_input::Id {
+    text_input::Id::new("chat-switcher")
 }
 
-const fn rgb8(r: u8, g: u8, b: u8) -> Color {
-    Color::from_rgb(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
+fn messages_scroll_id() -> scrollable::Id {
+    scrollable::Id::new("messages")
 }
 
-fn cosmic_root(_theme: &Theme) -> container_widget::Style {
-    container_widget::Style {
-        text_color: Some(COSMIC_TEXT),
-        background: Some(COSMIC_BG.into()),
-        ..container_widget::Style::default()
-    }
-}
-
-fn cosmic_header(_theme: &Theme) -> container_widget::Style {
-    container_widget::Style {
-        text_color: Some(COSMIC_TEXT),
-        background: Some(COSMIC_SURFACE_ALT.into()),
-        border: Border {
-            width: 1.0,
-            radius: 10.0.into(),
-            color: COSMIC_BORDER,
-        },
-        shadow: Shadow {
-            color: Color::from_rgba(0.0, 0.0, 0.0, 0.35),
-            offset: Vector::new(0.0, 4.0),
-            blur_radius: 12.0,
-        },
-    }
-}
-
-fn cosmic_panel(_theme: &Theme) -> container_widget::Style {
-    container_widget::Style {
-        text_color: Some(COSMIC_TEXT),
-        background: Some(COSMIC_SURFACE.into()),
-        border: Border {
-            width: 1.0,
-            radius: 12.0.into(),
-            color: COSMIC_BORDER,
-        },
-        ..container_widget::Style::default()
-    }
-}
-
-fn cosmic_sidebar(_theme: &Theme) -> container_widget::Style {
-    container_widget::Style {
-        text_color: Some(COSMIC_TEXT),
-        background: Some(COSMIC_SURFACE.into()),
-        border: Border {
-            width: 1.0,
-            radius: 12.0.into(),
-            color: COSMIC_BORDER,
-        },
-        ..container_widget::Style::default()
-    }
-}
-
-fn cosmic_button_primary(_theme: &Theme, status: button_widget::Status) -> button_widget::Style {
-    let bg = match status {
-        button_widget::Status::Hovered => Color::from_rgb8(109, 186, 255),
-        button_widget::Status::Pressed => Color::from_rgb8(70, 136, 210),
-        _ => COSMIC_ACCENT,
+/// Shortcuts of the main view. They are taken even while a text field has
+/// focus, since none of them is text: Ctrl+K switches chats, Ctrl+F
+/// searches them, Ctrl+R replies to the selected message, Esc cancels
+/// and the arrows select messages.
+fn main_view_key(
+    event: iced::Event,
+    _status: event::Status,
+    _window: window::Id,
+) -> Option<Message> {
+    use keyboard::key::Named;
+
+    let iced::Event::Keyboard(keyboard::Event::KeyPressed { key, modifiers, .. }) = event else {
+        return None;
     };
-
-    button_widget::Style {
-        background: Some(bg.into()),
-        text_color: COSMIC_BG,
-        border: Border {
-            width: 0.0,
-            radius: 10.0.into(),
-            color: Color::TRANSPARENT,
-        },
-        shadow: Shadow {
-            color: Color::from_rgba(0.0, 0.0, 0.0, 0.25),
-            offset: Vector::new(0.0, 2.0),
-            blur_radius: 8.0,
-        },
+    match key.as_ref() {
+        keyboard::Key::Character("k") if modifiers.command() => Some(Message::SwitcherOpen),
+        keyboard::Key::Character("f") if modifiers.command() => Some(Message::FocusChatFilter),
+        keyboard::Key::Character("r") if modifiers.command() => Some(Message::ReplyToSelected),
+        keyboard::Key::Named(Named::Escape) => Some(Message::EscapePressed),
+        keyboard::Key::Named(Named::ArrowUp) => Some(Message::SelectAdjacentMessage(-1)),
+        keyboard::Key::Named(Named::ArrowDown) => Some(Message::SelectAdjacentMessage(1)),
+        _ => None,
     }
 }
 
-fn cosmic_button_secondary(_theme: &Theme, status: button_widget::Status) -> button_widget::Style {
-    let bg = match status {
-        button_widget::Status::Hovered => COSMIC_SURFACE_ALT,
-        button_widget::Status::Pressed => Color::from_rgb8(32, 38, 54),
-        _ => COSMIC_SURFACE,
+/// Switcher navigation keys; Enter is the query's submit. Esc is taken
+/// from the focused query field, which would only drop its focus.
+fn chat_switcher_key(
+    event: iced::Event,
+    _status: event::Status,
+    _window: window::Id,
+) -> Option<Message> {
+    use keyboard::key::Named;
+
+    let iced::Event::Keyboard(keyboard::Event::KeyPressed { key, .. }) = event else {
+        return None;
     };
-
-    button_widget::Style {
-        background: Some(bg.into()),
-        text_color: COSMIC_TEXT,
-        border: Border {
-            width: 1.0,
-            radius: 10.0.into(),
-            color: COSMIC_BORDER,
-        },
-        shadow: Shadow::default(),
-    }
-}
-
-fn cosmic_button_danger(_theme: &Theme, status: button_widget::Status) -> button_widget::Style {
-    let bg = match status {
-        button_widget::Status::Hovered => Color::from_rgb8(255, 148, 148),
-        button_widget::Status::Pressed => Color::from_rgb8(200, 90, 90),
-        _ => COSMIC_DANGER,
+    let key = match key {
+        keyboard::Key::Named(Named::ArrowUp) => SwitcherKey::Up,
+        keyboard::Key::Named(Named::ArrowDown) => SwitcherKey::Down,
+        keyboard::Key::Named(Named::Escape) => SwitcherKey::Close,
+        _ => return None,
     };
-
-    button_widget::Style {
-        background: Some(bg.into()),
-        text_color: COSMIC_BG,
-        border: Border {
-            width: 0.0,
-            radius: 10.0.into(),
-            color: Color::TRANSPARENT,
-        },
-        shadow: Shadow::default(),
-    }
+    Some(Message::SwitcherKey(key))
 }
 
-fn cosmic_chat_button(
-    _theme: &Theme,
-    status: button_widget::Status,
-    selected: bool,
-) -> button_widget::Style {
-    let bg = if selected {
-        COSMIC_SURFACE_ALT
-    } else {
-        COSMIC_SURFACE
-    };
-    let hover = if selected {
-        COSMIC_SURFACE_ALT
-    } else {
-        Color::from_rgb8(30, 36, 50)
+/// Arrows switch photos, Esc closes the viewer.
+fn image_viewer_key(key: keyboard::Key, _modifiers: keyboard::Modifiers) -> Option<Message> {
+    use keyboard::key::Named;
+
+    let key = match key {
+        keyboard::Key::Named(Named::ArrowLeft) => ViewerKey::Previous,
+        keyboard::Key::Named(Named::ArrowRight) => ViewerKey::Next,
+        keyboard::Key::Named(Named::Escape) => ViewerKey::Close,
+        _ => return None,
     };
-    let pressed = Color::from_rgb8(24, 29, 41);
-
-    button_widget::Style {
-        background: Some(
-            match status {
-                button_widget::Status::Hovered => hover,
-                button_widget::Status::Pressed => pressed,
-                _ => bg,
-            }
-            .into(),
-        ),
-        text_color: COSMIC_TEXT,
-        border: Border {
-            width: if selected { 1.0 } else { 0.0 },
-            radius: 10.0.into(),
-            color: if selected {
-                COSMIC_ACCENT
-            } else {
-                Color::TRANSPARENT
-            },
-        },
-        shadow: Shadow::default(),
-    }
+    Some(Message::ViewerKey(key))
 }
 
-fn cosmic_message_button(
-    _theme: &Theme,
-    status: button_widget::Status,
-    selected: bool,
-    outgoing: bool,
-) -> button_widget::Style {
-    let base = if outgoing {
-        Color::from_rgb8(20, 32, 46)
-    } else {
-        COSMIC_SURFACE_ALT
-    };
-    let hover = if outgoing {
-        Color::from_rgb8(26, 38, 56)
-    } else {
-        Color::from_rgb8(32, 38, 54)
-    };
-    let border = if selected {
-        COSMIC_ACCENT
-    } else {
-        COSMIC_BORDER
+/// Picker navigation keys; Enter is the search box's submit.
+fn emoji_picker_key(key: keyboard::Key, _modifiers: keyboard::Modifiers) -> Option<Message> {
+    use keyboard::key::Named;
+
+    let key = match key {
+        keyboard::Key::Named(Named::ArrowUp) => PickerKey::Up,
+        keyboard::Key::Named(Named::ArrowDown) => PickerKey::Down,
+        keyboard::Key::Named(Named::ArrowLeft) => PickerKey::Left,
+        keyboard::Key::Named(Named::ArrowRight) => PickerKey::Right,
+        keyboard::Key::Named(Named::Escape) => PickerKey::Close,
+        _ => return None,
     };
-
-    button_widget::Style {
-        background: Some(
-            match status {
-                button_widget::Status::Hovered => hover,
-                button_widget::Status::Pressed => Color::from_rgb8(18, 26, 38),
-                _ => base,
-            }
-            .into(),
-        ),
-        text_color: COSMIC_TEXT,
-        border: Border {
-            width: 1.0,
-            radius: 12.0.into(),
-            color: border,
-        },
-        shadow: Shadow::default(),
-    }
+    Some(Message::EmojiPickerKey(key))
 }
 
-fn cosmic_text_input(_theme: &Theme, status: input_widget::Status) -> input_widget::Style {
-    let border = match status {
-        input_widget::Status::Focused => COSMIC_ACCENT,
-        input_widget::Status::Hovered => Color::from_rgb8(72, 82, 104),
-        _ => COSMIC_BORDER,
-    };
+fn chrono_timestamp() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
 
-    input_widget::Style {
-        background: COSMIC_SURFACE.into(),
-        border: Border {
-            radius: 10.0.into(),
-            width: 1.0,
-            color: border,
-        },
-        icon: COSMIC_MUTED,
-        placeholder: COSMIC_MUTED,
-        value: COSMIC_TEXT,
-        selection: COSMIC_SELECTION,
-    }
+fn looks_like_oauth_url(input: &str) -> bool {
+    input.contains("access_token=")
+        || input.contains("oauth.vk.com/blank.html")
+        || input.starts_with("https://oauth.vk.com/blank.html#")
+        || input.starts_with("oauth.vk.com/blank.html#")
+        || input.starts_with("//oauth.vk.com/blank.html#")
 }
 
 fn truncate_text(text: &str, max_chars: usize) -> String {
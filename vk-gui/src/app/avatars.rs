@@ -0,0 +1,255 @@
+//! Avatar images for chats and message senders, and photo thumbnails.
+//!
+//! Images are downloaded once per URL, saved to the cache directory and kept
+//! in memory as [`Handle`]s for at most [`CAPACITY`] URLs, least recently
+//! shown first out. The directory keeps at most [`DISK_CAPACITY`] files the
+//! same way. A failed download is not retried during the session; the view
+//! keeps showing the placeholder.
+
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use iced::widget::image::Handle;
+use vk_core::edit::content_hash;
+
+/// Maximum number of images kept in memory.
+pub const CAPACITY: usize = 200;
+
+/// Maximum number of images kept in the disk cache.
+pub const DISK_CAPACITY: usize = 2000;
+
+/// Avatar side in the chat list and next to message senders.
+pub const SIZE: f32 = 32.0;
+
+/// In-memory avatar cache.
+#[derive(Debug, Default)]
+pub struct AvatarCache {
+    /// Loaded images with the tick they were last shown at
+    images: HashMap<String, (Handle, Cell<u64>)>,
+    /// Downloads in flight
+    loading: HashSet<String>,
+    failed: HashSet<String>,
+    /// URLs to start downloading on the next update
+    queue: Vec<String>,
+    /// Bumped on every use; the view only borrows the cache
+    clock: Cell<u64>,
+}
+
+impl AvatarCache {
+    /// Image for `url`, if it is loaded. Called while drawing, so an image
+    /// on screen stays cached.
+    pub fn get(&self, url: &str) -> Option<&Handle> {
+        let (handle, used) = self.images.get(url)?;
+        used.set(self.tick());
+        Some(handle)
+    }
+
+    /// Mark `url` as shown; queues a download if it is not loaded yet.
+    pub fn request(&mut self, url: &str) {
+        if self.get(url).is_none() && !self.loading.contains(url) && !self.failed.contains(url) {
+            self.loading.insert(url.to_string());
+            self.queue.push(url.to_string());
+        }
+    }
+
+    /// URLs queued by [`request`](Self::request) since the last call.
+    pub fn take_queue(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.queue)
+    }
+
+    /// Store a finished download, evicting the least recently used image.
+    pub fn insert(&mut self, url: String, bytes: Vec<u8>) {
+        self.loading.remove(&url);
+        let used = Cell::new(self.tick());
+        self.images.insert(url, (Handle::from_bytes(bytes), used));
+
+        while self.images.len() > CAPACITY {
+            let Some(oldest) = self
+                .images
+                .iter()
+                .min_by_key(|(_, (_, used))| used.get())
+                .map(|(url, _)| url.clone())
+            else {
+                break;
+            };
+            self.images.remove(&oldest);
+        }
+    }
+
+    /// Record a failed download.
+    pub fn mark_failed(&mut self, url: String) {
+        self.loading.remove(&url);
+        self.failed.insert(url);
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.set(self.clock.get() + 1);
+        self.clock.get()
+    }
+}
+
+/// Load an avatar from the disk cache, or download and save it.
+pub async fn fetch(url: String) -> Result<Vec<u8>, String> {
+    let path = cache_path(&url);
+    if let Some(path) = &path
+        && let Ok(bytes) = tokio::fs::read(path).await
+    {
+        let path = path.clone();
+        tokio::task::spawn_blocking(move || touch(&path));
+        return Ok(bytes);
+    }
+
+    let response = reqwest::get(&url)
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())?;
+    let bytes = response.bytes().await.map_err(|e| e.to_string())?.to_vec();
+
+    if let Some(path) = path {
+        let saved = bytes.clone();
+        tokio::task::spawn_blocking(move || save(&path, &saved));
+    }
+    Ok(bytes)
+}
+
+/// File an avatar is cached in, named by a hash of its URL. The hash is
+/// stable across builds, so the files stay found after an update.
+fn cache_path(url: &str) -> Option<PathBuf> {
+    directories::ProjectDirs::from("", "", "vk_tui").map(|dirs| {
+        dirs.cache_dir()
+            .join("avatars")
+            .join(format!("{:016x}", content_hash(url)))
+    })
+}
+
+/// Write a downloaded avatar and trim the directory to [`DISK_CAPACITY`].
+fn save(path: &Path, bytes: &[u8]) {
+    let Some(dir) = path.parent() else {
+        return;
+    };
+    if let Err(e) = std::fs::create_dir_all(dir).and_then(|_| std::fs::write(path, bytes)) {
+        tracing::debug!("Failed to cache avatar {}: {}", path.display(), e);
+        return;
+    }
+    prune(dir, DISK_CAPACITY);
+}
+
+/// Mark a cached avatar as just used, so [`prune`] keeps it longer.
+fn touch(path: &Path) {
+    let touched = std::fs::File::options()
+        .write(true)
+        .open(path)
+        .and_then(|file| file.set_modified(SystemTime::now()));
+    if let Err(e) = touched {
+        tracing::debug!("Failed to touch avatar {}: {}", path.display(), e);
+    }
+}
+
+/// Delete the least recently used files of `dir` beyond `keep`.
+fn prune(dir: &Path, keep: usize) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut files: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let modified = entry.metadata().ok()?.modified().ok()?;
+            Some((modified, entry.path()))
+        })
+        .collect();
+    if files.len() <= keep {
+        return;
+    }
+    files.sort();
+    for (_, path) in &files[..files.len() - keep] {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_queues_once() {
+        let mut cache = AvatarCache::default();
+        cache.request("a");
+        cache.request("a");
+        assert_eq!(cache.take_queue(), vec!["a".to_string()]);
+        assert!(cache.take_queue().is_empty());
+
+        cache.insert("a".into(), Vec::new());
+        cache.request("a");
+        assert!(cache.take_queue().is_empty());
+        assert!(cache.get("a").is_some());
+    }
+
+    #[test]
+    fn test_failed_url_is_not_retried() {
+        let mut cache = AvatarCache::default();
+        cache.request("a");
+        cache.take_queue();
+        cache.mark_failed("a".into());
+
+        cache.request("a");
+        assert!(cache.take_queue().is_empty());
+        assert!(cache.get("a").is_none());
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let mut cache = AvatarCache::default();
+        for i in 0..CAPACITY {
+            cache.insert(format!("url{}", i), Vec::new());
+        }
+        // Touch the oldest so the next one is evicted instead
+        cache.request("url0");
+
+        cache.insert("new".into(), Vec::new());
+
+        assert_eq!(cache.images.len(), CAPACITY);
+        assert!(cache.get("url0").is_some());
+        assert!(cache.get("url1").is_none());
+        assert!(cache.get("new").is_some());
+    }
+
+    #[test]
+    fn test_drawing_keeps_image_cached() {
+        let mut cache = AvatarCache::default();
+        for i in 0..CAPACITY {
+            cache.insert(format!("url{}", i), Vec::new());
+        }
+        // Shown by the view, never requested again
+        assert!(cache.get("url0").is_some());
+
+        cache.insert("new".into(), Vec::new());
+
+        assert!(cache.get("url0").is_some());
+        assert!(cache.get("url1").is_none());
+    }
+
+    #[test]
+    fn test_prune_keeps_recently_used_files() {
+        let dir = std::env::temp_dir().join(format!("vk_gui_avatars_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let start = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        for (i, name) in ["a", "b", "c"].into_iter().enumerate() {
+            let path = dir.join(name);
+            std::fs::write(&path, name).unwrap();
+            let file = std::fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(start + std::time::Duration::from_secs(i as u64))
+                .unwrap();
+        }
+        touch(&dir.join("a"));
+
+        prune(&dir, 2);
+
+        assert!(dir.join("a").exists());
+        assert!(!dir.join("b").exists());
+        assert!(dir.join("c").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
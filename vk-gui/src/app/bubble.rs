@@ -0,0 +1,143 @@
+//! Text shown in message bubbles for attachments, replies and forwards.
+
+use vk_core::{AttachmentInfo, AttachmentKind, ForwardItem};
+
+/// Length of the reply snippet in the quote block.
+pub const REPLY_SNIPPET_CHARS: usize = 80;
+
+/// Icon in front of an attachment chip.
+pub fn kind_icon(kind: &AttachmentKind) -> &'static str {
+    match kind {
+        AttachmentKind::Photo => "🖼",
+        AttachmentKind::Doc => "📄",
+        AttachmentKind::Link => "🔗",
+        AttachmentKind::Audio => "🎵",
+        AttachmentKind::Sticker => "🙂",
+        AttachmentKind::Other(_) => "📎",
+    }
+}
+
+/// "12 KB", "3.4 MB": the size of a file for its chip.
+pub fn format_size(bytes: u64) -> String {
+    const KB: f64 = 1024.0;
+    const MB: f64 = KB * 1024.0;
+    let bytes = bytes as f64;
+    if bytes < KB {
+        format!("{} B", bytes)
+    } else if bytes < MB {
+        format!("{:.0} KB", bytes / KB)
+    } else {
+        format!("{:.1} MB", bytes / MB)
+    }
+}
+
+/// Title of an attachment chip: the title, then subtitle and size.
+pub fn chip_label(att: &AttachmentInfo) -> String {
+    let mut label = format!("{} {}", kind_icon(&att.kind), att.title);
+    if let Some(subtitle) = att.subtitle.as_deref().filter(|s| !s.is_empty()) {
+        label.push_str(" — ");
+        label.push_str(subtitle);
+    }
+    if let Some(size) = att.size {
+        label.push_str(&format!(" · {}", format_size(size)));
+    }
+    label
+}
+
+/// One line of text for the message a reply quotes. A quote without text
+/// names its attachments instead.
+pub fn reply_snippet(text: &str, attachments: &[AttachmentInfo]) -> String {
+    let line = text.lines().next().unwrap_or_default();
+    if !line.is_empty() {
+        return truncate(line, REPLY_SNIPPET_CHARS);
+    }
+    attachments
+        .iter()
+        .map(|a| kind_icon(&a.kind))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// "Forwarded messages (3)", counting nested ones.
+pub fn forwards_title(items: &[ForwardItem], fwd_count: usize) -> String {
+    let count = fwd_count.max(count_forwards(items));
+    if count == 1 {
+        "Forwarded message".to_string()
+    } else {
+        format!("Forwarded messages ({})", count)
+    }
+}
+
+fn count_forwards(items: &[ForwardItem]) -> usize {
+    items
+        .iter()
+        .map(|item| 1 + count_forwards(&item.nested))
+        .sum()
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(size: Option<u64>) -> AttachmentInfo {
+        AttachmentInfo {
+            kind: AttachmentKind::Doc,
+            title: "report.pdf".into(),
+            url: Some("https://vk.com/doc1_2".into()),
+            thumbnail_url: None,
+            size,
+            subtitle: None,
+            description: None,
+            target_path: None,
+        }
+    }
+
+    fn forward(nested: Vec<ForwardItem>) -> ForwardItem {
+        ForwardItem {
+            message_id: 1,
+            peer_id: 2,
+            from: "Anna".into(),
+            text: String::new(),
+            attachments: Vec::new(),
+            nested,
+        }
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(12 * 1024), "12 KB");
+        assert_eq!(format_size(3 * 1024 * 1024 + 400 * 1024), "3.4 MB");
+    }
+
+    #[test]
+    fn test_chip_label() {
+        assert_eq!(chip_label(&doc(None)), "📄 report.pdf");
+        assert_eq!(chip_label(&doc(Some(2048))), "📄 report.pdf · 2 KB");
+    }
+
+    #[test]
+    fn test_reply_snippet() {
+        assert_eq!(reply_snippet("first\nsecond", &[]), "first");
+        assert_eq!(reply_snippet("", &[doc(None)]), "📄");
+        let long = "a".repeat(REPLY_SNIPPET_CHARS + 5);
+        assert_eq!(
+            reply_snippet(&long, &[]).chars().count(),
+            REPLY_SNIPPET_CHARS + 1
+        );
+    }
+
+    #[test]
+    fn test_forwards_title_counts_nested() {
+        let items = vec![forward(vec![forward(Vec::new())]), forward(Vec::new())];
+        assert_eq!(forwards_title(&items, 2), "Forwarded messages (3)");
+        assert_eq!(forwards_title(&items[1..], 1), "Forwarded message");
+    }
+}
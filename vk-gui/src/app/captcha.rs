@@ -0,0 +1,94 @@
+//! Captcha VK asks for before it takes a request.
+//!
+//! The prompt shows the image and a field for its text. The answer goes
+//! back together with the command that ran into the captcha, so the
+//! executor repeats it.
+
+use iced::widget::image::Handle;
+use vk_core::AsyncCommand;
+
+/// A captcha waiting for the user.
+#[derive(Debug)]
+pub struct CaptchaPrompt {
+    pub sid: String,
+    pub img_url: String,
+    /// Command to run again once the captcha is answered
+    pub retry: AsyncCommand,
+    pub key: String,
+    pub image: Option<Handle>,
+    /// The image is still to be fetched
+    load: bool,
+}
+
+impl CaptchaPrompt {
+    pub fn new(sid: String, img_url: String, retry: AsyncCommand) -> Self {
+        Self {
+            sid,
+            img_url,
+            retry,
+            key: String::new(),
+            image: None,
+            load: true,
+        }
+    }
+
+    /// The image URL, the first time it is asked for.
+    pub fn take_load(&mut self) -> Option<String> {
+        std::mem::take(&mut self.load).then(|| self.img_url.clone())
+    }
+
+    /// Command answering with the typed text; `None` while it is empty.
+    pub fn answer(&self) -> Option<AsyncCommand> {
+        let key = self.key.trim();
+        (!key.is_empty()).then(|| AsyncCommand::AnswerCaptcha {
+            sid: self.sid.clone(),
+            key: key.to_string(),
+            retry: Box::new(self.retry.clone()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn prompt() -> CaptchaPrompt {
+        CaptchaPrompt::new(
+            "42".into(),
+            "https://api.vk.com/captcha.php?sid=42".into(),
+            AsyncCommand::SendMessage {
+                peer_id: 7,
+                text: "hi".into(),
+            },
+        )
+    }
+
+    #[test]
+    fn test_image_loads_once() {
+        let mut prompt = prompt();
+        assert_eq!(
+            prompt.take_load().as_deref(),
+            Some("https://api.vk.com/captcha.php?sid=42")
+        );
+        assert!(prompt.take_load().is_none());
+    }
+
+    #[test]
+    fn test_answer() {
+        let mut prompt = prompt();
+        assert!(prompt.answer().is_none());
+
+        prompt.key = " x7kq ".into();
+        match prompt.answer() {
+            Some(AsyncCommand::AnswerCaptcha { sid, key, retry }) => {
+                assert_eq!(sid, "42");
+                assert_eq!(key, "x7kq");
+                assert!(matches!(
+                    *retry,
+                    AsyncCommand::SendMessage { peer_id: 7, .. }
+                ));
+            }
+            other => panic!("unexpected answer: {:?}", other),
+        }
+    }
+}
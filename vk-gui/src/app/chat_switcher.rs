@@ -0,0 +1,60 @@
+//! Quick chat switcher opened with Ctrl+K.
+//!
+//! Chats are matched with [`vk_core::fuzzy::filter_chats`], the matcher of
+//! the TUI chat filter, so both frontends find the same chats for a query.
+
+use vk_core::Chat;
+use vk_core::fuzzy::filter_chats;
+
+/// Matches listed at once; refine the query to see others.
+pub const MAX_RESULTS: usize = 12;
+
+/// Keys the switcher handles while open; Enter is the query's submit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwitcherKey {
+    Up,
+    Down,
+    Close,
+}
+
+/// Open state of the switcher.
+#[derive(Debug, Default)]
+pub struct ChatSwitcher {
+    pub query: String,
+    /// Indices into the chat list, best match first
+    pub matches: Vec<usize>,
+    /// Index into [`matches`](Self::matches)
+    pub selected: usize,
+}
+
+impl ChatSwitcher {
+    /// Switcher listing every chat.
+    pub fn open(chats: &[Chat]) -> Self {
+        let mut switcher = Self::default();
+        switcher.set_query(String::new(), chats);
+        switcher
+    }
+
+    pub fn set_query(&mut self, query: String, chats: &[Chat]) {
+        self.matches = filter_chats(chats, &query);
+        self.matches.truncate(MAX_RESULTS);
+        self.query = query;
+        self.selected = 0;
+    }
+
+    /// Move the selection; returns false for [`SwitcherKey::Close`].
+    pub fn handle_key(&mut self, key: SwitcherKey) -> bool {
+        let last = self.matches.len().saturating_sub(1);
+        self.selected = match key {
+            SwitcherKey::Up => self.selected.saturating_sub(1),
+            SwitcherKey::Down => (self.selected + 1).min(last),
+            SwitcherKey::Close => return false,
+        };
+        true
+    }
+
+    /// Index in the chat list of the selected match.
+    pub fn selected_chat(&self) -> Option<usize> {
+        self.matches.get(self.selected).copied()
+    }
+}
@@ -0,0 +1,183 @@
+//! Emoji picker attached to the message input.
+//!
+//! The picker searches the [`vk_core::emoji`] dataset and keeps a row of
+//! recently used emoji in `recent_emoji.json` next to the other settings.
+//! iced does not report where the caret of a `text_input` is, so the caret
+//! is tracked from the edits themselves: after typing it sits right after
+//! the changed text, which is where a picked emoji goes.
+
+use std::path::PathBuf;
+
+use vk_core::emoji::{self, Emoji, RecentEmoji};
+use vk_core::persist::PersistedFile;
+
+/// Emoji per row of the result grid.
+pub const COLUMNS: usize = 8;
+
+/// Results shown at once; refine the search to see others.
+pub const MAX_RESULTS: usize = 64;
+
+/// Schema version of `recent_emoji.json`.
+const RECENT_VERSION: u32 = 1;
+
+/// Keys the picker handles while open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PickerKey {
+    Up,
+    Down,
+    Left,
+    Right,
+    Close,
+}
+
+/// Open state of the picker.
+#[derive(Debug, Default)]
+pub struct EmojiPicker {
+    pub open: bool,
+    pub query: String,
+    /// Index into [`results`](Self::results)
+    pub selected: usize,
+    pub recent: RecentEmoji,
+}
+
+impl EmojiPicker {
+    /// Picker with the recent row loaded from disk.
+    pub fn load() -> Self {
+        Self {
+            recent: recent_file().map(|file| file.load()).unwrap_or_default(),
+            ..Self::default()
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.query.clear();
+        self.selected = 0;
+    }
+
+    pub fn set_query(&mut self, query: String) {
+        self.query = query;
+        self.selected = 0;
+    }
+
+    pub fn results(&self) -> Vec<&'static Emoji> {
+        let mut results = emoji::search(&self.query);
+        results.truncate(MAX_RESULTS);
+        results
+    }
+
+    /// Move the grid selection; returns false for [`PickerKey::Close`].
+    pub fn handle_key(&mut self, key: PickerKey) -> bool {
+        let last = self.results().len().saturating_sub(1);
+        self.selected = match key {
+            PickerKey::Left => self.selected.saturating_sub(1),
+            PickerKey::Right => (self.selected + 1).min(last),
+            PickerKey::Up => self.selected.saturating_sub(COLUMNS),
+            PickerKey::Down => (self.selected + COLUMNS).min(last),
+            PickerKey::Close => {
+                self.open = false;
+                return false;
+            }
+        };
+        true
+    }
+
+    pub fn selected_emoji(&self) -> Option<&'static str> {
+        self.results().get(self.selected).map(|e| e.emoji)
+    }
+
+    /// Remember a picked emoji and close the picker.
+    pub fn picked(&mut self, emoji: &str) {
+        self.recent.push(emoji);
+        self.open = false;
+        if let Some(file) = recent_file()
+            && let Err(e) = file.save(&self.recent)
+        {
+            tracing::debug!("Failed to save recent emoji: {}", e);
+        }
+    }
+}
+
+fn recent_file() -> Option<PersistedFile<RecentEmoji>> {
+    let path: PathBuf = directories::ProjectDirs::from("", "", "vk_tui")?
+        .config_dir()
+        .join("recent_emoji.json");
+    Some(PersistedFile::new(path, RECENT_VERSION))
+}
+
+/// Caret position (in chars) after `old` was edited into `new`: the end
+/// of the part that changed.
+pub fn caret_after_edit(old: &str, new: &str) -> usize {
+    let prefix = old
+        .chars()
+        .zip(new.chars())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let max_suffix = old.chars().count().min(new.chars().count()) - prefix;
+    let suffix = old
+        .chars()
+        .rev()
+        .zip(new.chars().rev())
+        .take(max_suffix)
+        .take_while(|(a, b)| a == b)
+        .count();
+    new.chars().count() - suffix
+}
+
+/// Insert `emoji` at char position `caret`; returns the caret after it.
+pub fn insert_at(text: &mut String, caret: usize, emoji: &str) -> usize {
+    let caret = caret.min(text.chars().count());
+    let byte = text
+        .char_indices()
+        .nth(caret)
+        .map_or(text.len(), |(i, _)| i);
+    text.insert_str(byte, emoji);
+    caret + emoji.chars().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_caret_after_edit() {
+        assert_eq!(caret_after_edit("", "h"), 1);
+        assert_eq!(caret_after_edit("hllo", "hello"), 2);
+        assert_eq!(caret_after_edit("привет мир", "привет, мир"), 7);
+        // Deleting mid-string leaves the caret at the deletion
+        assert_eq!(caret_after_edit("hello", "helo"), 3);
+        // Typing a letter equal to the next one
+        assert_eq!(caret_after_edit("aa", "aaa"), 3);
+    }
+
+    #[test]
+    fn test_insert_at_mid_string() {
+        let mut text = "привет мир".to_string();
+        let caret = insert_at(&mut text, 7, "👋");
+        assert_eq!(text, "привет 👋мир");
+        assert_eq!(caret, 8);
+
+        let mut text = "end".to_string();
+        assert_eq!(insert_at(&mut text, 99, "🎉"), 4);
+        assert_eq!(text, "end🎉");
+    }
+
+    #[test]
+    fn test_grid_navigation_stays_in_results() {
+        let mut picker = EmojiPicker::default();
+        picker.handle_key(PickerKey::Up);
+        assert_eq!(picker.selected, 0);
+        picker.handle_key(PickerKey::Down);
+        picker.handle_key(PickerKey::Right);
+        assert_eq!(picker.selected, COLUMNS + 1);
+
+        picker.set_query("joy".into());
+        assert_eq!(picker.selected, 0);
+        for _ in 0..10 {
+            picker.handle_key(PickerKey::Down);
+        }
+        assert_eq!(picker.selected, picker.results().len() - 1);
+        assert!(!picker.handle_key(PickerKey::Close));
+        assert!(!picker.open);
+    }
+}
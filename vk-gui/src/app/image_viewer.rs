@@ -0,0 +1,210 @@
+//! Full-window viewer for the photos of a message.
+//!
+//! Opened by clicking a photo thumbnail. Until the full-size image is
+//! downloaded and decoded the thumbnail is shown scaled up. Decoding runs
+//! on a blocking task, so core events keep being handled underneath.
+//! Zoom (scroll wheel) and pan (drag) come from iced's image viewer.
+
+use std::collections::{HashMap, HashSet};
+
+use iced::widget::image::Handle;
+use vk_core::{AttachmentInfo, AttachmentKind, ChatMessage};
+
+/// Full-size images kept in memory while the viewer is open.
+pub const CAPACITY: usize = 16;
+
+/// Height of photo thumbnails in the message list.
+pub const THUMBNAIL_HEIGHT: f32 = 120.0;
+
+/// Keys the viewer handles while open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewerKey {
+    Previous,
+    Next,
+    Close,
+}
+
+/// Open state of the viewer.
+#[derive(Debug, Default)]
+pub struct ImageViewer {
+    /// Photos of the message the viewer was opened on
+    pub photos: Vec<AttachmentInfo>,
+    pub index: usize,
+    /// Decoded full-size images by URL, in load order
+    full: HashMap<String, Handle>,
+    order: Vec<String>,
+    loading: HashSet<String>,
+    failed: HashSet<String>,
+}
+
+impl ImageViewer {
+    /// Viewer on the `index`-th photo of `msg`, `None` if it has none.
+    pub fn open(msg: &ChatMessage, index: usize) -> Option<Self> {
+        let photos: Vec<AttachmentInfo> = photos(msg).cloned().collect();
+        if photos.is_empty() {
+            return None;
+        }
+        Some(Self {
+            index: index.min(photos.len() - 1),
+            photos,
+            ..Self::default()
+        })
+    }
+
+    pub fn current(&self) -> Option<&AttachmentInfo> {
+        self.photos.get(self.index)
+    }
+
+    /// Move to the neighbouring photo; returns false for [`ViewerKey::Close`].
+    pub fn handle_key(&mut self, key: ViewerKey) -> bool {
+        match key {
+            ViewerKey::Previous => self.index = self.index.saturating_sub(1),
+            ViewerKey::Next => {
+                self.index = (self.index + 1).min(self.photos.len().saturating_sub(1))
+            }
+            ViewerKey::Close => return false,
+        }
+        true
+    }
+
+    /// "2 / 5"
+    pub fn position(&self) -> String {
+        format!("{} / {}", self.index + 1, self.photos.len())
+    }
+
+    /// Full-size image of the current photo, if decoded.
+    pub fn full_image(&self) -> Option<&Handle> {
+        let url = self.current()?.url.as_deref()?;
+        self.full.get(url)
+    }
+
+    /// URL of the current photo to start loading, once per URL.
+    pub fn take_load(&mut self) -> Option<String> {
+        let url = self.current()?.url.clone()?;
+        if self.full.contains_key(&url) || self.loading.contains(&url) || self.failed.contains(&url)
+        {
+            return None;
+        }
+        self.loading.insert(url.clone());
+        Some(url)
+    }
+
+    /// Store a decoded image, dropping the oldest past [`CAPACITY`].
+    pub fn insert(&mut self, url: String, handle: Handle) {
+        self.loading.remove(&url);
+        self.order.retain(|u| *u != url);
+        self.order.push(url.clone());
+        self.full.insert(url, handle);
+        while self.order.len() > CAPACITY {
+            let oldest = self.order.remove(0);
+            self.full.remove(&oldest);
+        }
+    }
+
+    /// Record a failed load; the thumbnail stays on screen.
+    pub fn mark_failed(&mut self, url: String) {
+        self.loading.remove(&url);
+        self.failed.insert(url);
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.current()
+            .and_then(|p| p.url.as_deref())
+            .is_some_and(|url| self.loading.contains(url))
+    }
+}
+
+/// Photo attachments of a message, in order.
+pub fn photos(msg: &ChatMessage) -> impl Iterator<Item = &AttachmentInfo> {
+    msg.attachments
+        .iter()
+        .filter(|a| matches!(a.kind, AttachmentKind::Photo))
+}
+
+/// Download and decode a full-size photo off the UI thread.
+pub async fn fetch(url: String) -> Result<Handle, String> {
+    let response = reqwest::get(&url)
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| e.to_string())?;
+    let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+
+    tokio::task::spawn_blocking(move || {
+        let decoded = ::image::load_from_memory(&bytes)
+            .map_err(|e| e.to_string())?
+            .into_rgba8();
+        let (width, height) = decoded.dimensions();
+        Ok(Handle::from_rgba(width, height, decoded.into_raw()))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attachment(kind: AttachmentKind, url: &str) -> AttachmentInfo {
+        AttachmentInfo {
+            kind,
+            title: url.into(),
+            url: Some(url.into()),
+            thumbnail_url: None,
+            size: None,
+            subtitle: None,
+            description: None,
+            target_path: None,
+        }
+    }
+
+    fn viewer(urls: &[&str]) -> ImageViewer {
+        ImageViewer {
+            photos: urls
+                .iter()
+                .map(|url| attachment(AttachmentKind::Photo, url))
+                .collect(),
+            ..ImageViewer::default()
+        }
+    }
+
+    #[test]
+    fn test_navigation_stays_in_photos() {
+        let mut viewer = viewer(&["a", "b", "c"]);
+        viewer.handle_key(ViewerKey::Previous);
+        assert_eq!(viewer.index, 0);
+        viewer.handle_key(ViewerKey::Next);
+        viewer.handle_key(ViewerKey::Next);
+        viewer.handle_key(ViewerKey::Next);
+        assert_eq!(viewer.index, 2);
+        assert_eq!(viewer.position(), "3 / 3");
+        assert!(!viewer.handle_key(ViewerKey::Close));
+    }
+
+    #[test]
+    fn test_loads_each_photo_once() {
+        let mut viewer = viewer(&["a", "b"]);
+        assert_eq!(viewer.take_load().as_deref(), Some("a"));
+        assert!(viewer.take_load().is_none());
+        assert!(viewer.is_loading());
+
+        viewer.insert("a".into(), Handle::from_bytes(Vec::new()));
+        assert!(viewer.full_image().is_some());
+        assert!(viewer.take_load().is_none());
+
+        viewer.handle_key(ViewerKey::Next);
+        assert_eq!(viewer.take_load().as_deref(), Some("b"));
+        viewer.mark_failed("b".into());
+        assert!(viewer.take_load().is_none());
+        assert!(viewer.full_image().is_none());
+    }
+
+    #[test]
+    fn test_keeps_at_most_capacity_images() {
+        let mut viewer = viewer(&["a"]);
+        for i in 0..CAPACITY + 3 {
+            viewer.insert(format!("url{}", i), Handle::from_bytes(Vec::new()));
+        }
+        assert_eq!(viewer.full.len(), CAPACITY);
+        assert!(!viewer.full.contains_key("url0"));
+    }
+}
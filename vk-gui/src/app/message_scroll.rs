@@ -0,0 +1,195 @@
+//! Where the message list is scrolled, and how it moves as messages load.
+//!
+//! The list follows new messages while it is at the bottom. Scrolled up,
+//! it stays put and counts the messages that arrived below, shown on the
+//! "new messages" button. Reaching the top asks for older history.
+
+use vk_core::ChatMessage;
+
+/// Distance (px) from an end of the list still counted as being at it.
+pub const EDGE: f32 = 24.0;
+
+/// Scroll state of the message list of the open chat.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageScroll {
+    /// The list shows its last message and follows new ones
+    pub at_bottom: bool,
+    /// Messages that arrived below the view since it left the bottom
+    pub unseen: usize,
+}
+
+impl Default for MessageScroll {
+    fn default() -> Self {
+        Self {
+            at_bottom: true,
+            unseen: 0,
+        }
+    }
+}
+
+impl MessageScroll {
+    /// The user scrolled; distances are in pixels from each end. Returns
+    /// whether the top was reached, so older messages should be loaded.
+    pub fn scrolled(&mut self, to_top: f32, to_bottom: f32) -> bool {
+        self.at_bottom = to_bottom <= EDGE;
+        if self.at_bottom {
+            self.unseen = 0;
+        }
+        to_top <= EDGE
+    }
+
+    /// `count` messages were added below; returns whether to scroll down
+    /// to them.
+    pub fn appended(&mut self, count: usize) -> bool {
+        if !self.at_bottom {
+            self.unseen += count;
+        }
+        self.at_bottom
+    }
+
+    /// Jump to the latest message.
+    pub fn to_bottom(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Ends of the loaded history, to tell how a change moved the list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ends {
+    pub first: Option<i64>,
+    pub last: Option<i64>,
+}
+
+impl Ends {
+    pub fn of(messages: &[ChatMessage]) -> Self {
+        Self {
+            first: messages.first().map(|m| m.id),
+            last: messages.last().map(|m| m.id),
+        }
+    }
+}
+
+/// How the list changed between two [`Ends`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    /// A chat was loaded into an empty list
+    Loaded,
+    /// Older messages were put above; the count of them
+    Prepended(usize),
+    /// Messages were added below; the count of them
+    Appended(usize),
+    Unchanged,
+}
+
+impl Change {
+    pub fn between(before: Ends, messages: &[ChatMessage]) -> Self {
+        let after = Ends::of(messages);
+        if before.last.is_none() {
+            return if after.last.is_some() {
+                Change::Loaded
+            } else {
+                Change::Unchanged
+            };
+        }
+        if after.first != before.first
+            && let Some(idx) = messages.iter().position(|m| Some(m.id) == before.first)
+        {
+            return Change::Prepended(idx);
+        }
+        if after.last != before.last {
+            let below = match messages.iter().rposition(|m| Some(m.id) == before.last) {
+                Some(idx) => messages.len() - idx - 1,
+                None => 0,
+            };
+            return Change::Appended(below);
+        }
+        Change::Unchanged
+    }
+}
+
+/// Put a page of history into `messages`. A page older than the loaded
+/// messages goes above them. Any other page is the newest history: it
+/// replaces what it covers, and the older messages loaded stay above it.
+pub fn merge_page(messages: &mut Vec<ChatMessage>, page: Vec<ChatMessage>) {
+    let (Some(page_first), Some(page_last)) = (page.first(), page.last()) else {
+        return;
+    };
+    let (page_first, page_last) = (page_first.id, page_last.id);
+    // Messages still being sent have no id yet
+    let loaded_first = messages.iter().find(|m| m.id != 0).map(|m| m.id);
+
+    match loaded_first {
+        Some(first) if page_last <= first => {
+            let mut older: Vec<_> = page.into_iter().filter(|m| m.id < first).collect();
+            older.append(messages);
+            *messages = older;
+        }
+        _ => {
+            messages.retain(|m| m.id != 0 && m.id < page_first);
+            messages.extend(page);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vk_core::fixtures::message;
+
+    fn msg(id: i64) -> ChatMessage {
+        message(id, "")
+    }
+
+    fn ids(messages: &[ChatMessage]) -> Vec<i64> {
+        messages.iter().map(|m| m.id).collect()
+    }
+
+    #[test]
+    fn test_follows_only_at_bottom() {
+        let mut scroll = MessageScroll::default();
+        assert!(scroll.appended(1));
+
+        assert!(!scroll.scrolled(300.0, 200.0));
+        assert!(!scroll.appended(2));
+        assert!(!scroll.appended(1));
+        assert_eq!(scroll.unseen, 3);
+
+        // Reaching the bottom clears the count
+        scroll.scrolled(500.0, 0.0);
+        assert_eq!(scroll, MessageScroll::default());
+
+        assert!(scroll.scrolled(10.0, 400.0));
+    }
+
+    #[test]
+    fn test_merge_older_page() {
+        let mut messages = vec![msg(5), msg(6)];
+        merge_page(&mut messages, vec![msg(3), msg(4), msg(5)]);
+        assert_eq!(ids(&messages), vec![3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn test_merge_newest_page_keeps_history() {
+        let mut messages = vec![msg(1), msg(2), msg(3), msg(0)];
+        merge_page(&mut messages, vec![msg(2), msg(3), msg(4)]);
+        assert_eq!(ids(&messages), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_change_between() {
+        let before = Ends::of(&[msg(5), msg(6)]);
+        assert_eq!(
+            Change::between(before, &[msg(3), msg(4), msg(5), msg(6)]),
+            Change::Prepended(2)
+        );
+        assert_eq!(
+            Change::between(before, &[msg(5), msg(6), msg(7)]),
+            Change::Appended(1)
+        );
+        assert_eq!(
+            Change::between(before, &[msg(5), msg(6)]),
+            Change::Unchanged
+        );
+        assert_eq!(Change::between(Ends::of(&[]), &[msg(1)]), Change::Loaded);
+    }
+}
@@ -0,0 +1,192 @@
+//! Notification sounds for new messages.
+//!
+//! Playback runs on a dedicated thread that owns the audio output, so the UI
+//! never waits on the device. At most one sound plays per [`MIN_INTERVAL`],
+//! however many messages arrive. Without an audio device sounds are dropped
+//! silently and only the visual notifications remain.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rodio::source::{SineWave, Source};
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink};
+
+/// Shortest time between two sounds.
+pub const MIN_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Volume of a fresh install, from 0.0 to 1.0.
+pub const DEFAULT_VOLUME: f32 = 0.6;
+
+/// Length of one note of the built-in chime.
+const NOTE: Duration = Duration::from_millis(90);
+
+/// What the sound announces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SoundKind {
+    Message,
+    /// The message mentions the current user
+    Mention,
+}
+
+impl SoundKind {
+    /// Notes of the built-in chime; mentions ring higher.
+    fn notes(self) -> [f32; 2] {
+        match self {
+            SoundKind::Message => [880.0, 1174.7],
+            SoundKind::Mention => [1318.5, 1760.0],
+        }
+    }
+
+    /// Playback speed of a custom sound, so mentions still sound different.
+    fn speed(self) -> f32 {
+        match self {
+            SoundKind::Message => 1.0,
+            SoundKind::Mention => 1.15,
+        }
+    }
+}
+
+/// User settings for notification sounds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoundSettings {
+    pub enabled: bool,
+    /// From 0.0 to 1.0
+    pub volume: f32,
+    /// Sound file played instead of the built-in chime
+    pub custom_path: Option<PathBuf>,
+}
+
+impl Default for SoundSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            volume: DEFAULT_VOLUME,
+            custom_path: None,
+        }
+    }
+}
+
+struct Request {
+    kind: SoundKind,
+    volume: f32,
+    custom_path: Option<PathBuf>,
+}
+
+/// Plays notification sounds on a background thread, rate-limited.
+#[derive(Debug, Default)]
+pub struct Notifier {
+    /// Audio thread, started on the first sound
+    tx: Option<mpsc::Sender<Request>>,
+    /// The audio thread could not start or lost the device
+    unavailable: bool,
+    last_played: Option<Instant>,
+}
+
+impl Notifier {
+    /// Play a sound unless sounds are off or one played less than
+    /// [`MIN_INTERVAL`] ago. Never blocks.
+    pub fn play(&mut self, kind: SoundKind, settings: &SoundSettings) {
+        if !settings.enabled || settings.volume <= 0.0 || self.unavailable {
+            return;
+        }
+        let now = Instant::now();
+        if self
+            .last_played
+            .is_some_and(|last| now.duration_since(last) < MIN_INTERVAL)
+        {
+            return;
+        }
+        self.last_played = Some(now);
+
+        if self.tx.is_none() {
+            self.tx = spawn_player();
+        }
+        let request = Request {
+            kind,
+            volume: settings.volume.clamp(0.0, 1.0),
+            custom_path: settings.custom_path.clone(),
+        };
+        let sent = self.tx.as_ref().is_some_and(|tx| tx.send(request).is_ok());
+        if !sent {
+            self.tx = None;
+            self.unavailable = true;
+        }
+    }
+}
+
+/// Start the audio thread. It exits when no output device is available,
+/// which makes later sends fail.
+fn spawn_player() -> Option<mpsc::Sender<Request>> {
+    let (tx, rx) = mpsc::channel::<Request>();
+    let spawned = thread::Builder::new()
+        .name("notification-sound".into())
+        .spawn(move || {
+            // The stream must outlive every sink played on it
+            let Ok((_stream, handle)) = OutputStream::try_default() else {
+                tracing::debug!("No audio output, notification sounds disabled");
+                return;
+            };
+            for request in rx {
+                if let Err(e) = play_request(&handle, &request) {
+                    tracing::debug!("Failed to play notification sound: {}", e);
+                }
+            }
+        });
+
+    match spawned {
+        Ok(_) => Some(tx),
+        Err(e) => {
+            tracing::debug!("Failed to start audio thread: {}", e);
+            None
+        }
+    }
+}
+
+fn play_request(handle: &OutputStreamHandle, request: &Request) -> Result<(), String> {
+    let sink = Sink::try_new(handle).map_err(|e| e.to_string())?;
+    sink.set_volume(request.volume);
+
+    let custom = request
+        .custom_path
+        .as_deref()
+        .and_then(|path| match decode(path) {
+            Ok(source) => Some(source),
+            Err(e) => {
+                tracing::debug!("Cannot play {}: {}", path.display(), e);
+                None
+            }
+        });
+    match custom {
+        Some(source) => sink.append(source.speed(request.kind.speed())),
+        None => {
+            for freq in request.kind.notes() {
+                sink.append(
+                    SineWave::new(freq)
+                        .take_duration(NOTE)
+                        .fade_in(Duration::from_millis(5))
+                        .amplify(0.25),
+                );
+            }
+        }
+    }
+
+    sink.sleep_until_end();
+    Ok(())
+}
+
+fn decode(path: &Path) -> Result<Decoder<BufReader<File>>, String> {
+    let file = File::open(path).map_err(|e| e.to_string())?;
+    Decoder::new(BufReader::new(file)).map_err(|e| e.to_string())
+}
+
+/// Whether `text` mentions `user_id`: `[id1|Ann]` markup, `@all` or `@online`.
+pub fn is_mention(text: &str, user_id: i64) -> bool {
+    text.contains(&format!("[id{}|", user_id))
+        || text
+            .split(|c: char| !(c.is_alphanumeric() || c == '@'))
+            .any(|word| word == "@all" || word == "@online")
+}
@@ -0,0 +1,576 @@
+//! Theme-aware widget styles.
+//!
+//! [`Styles`] is built from the active [`Palette`] (dark or light) and the
+//! user-chosen accent color. It is `Copy`, so view code can move it into
+//! `.style(...)` closures.
+
+use iced::widget::{
+    button as button_widget, container as container_widget, text_input as input_widget,
+};
+use iced::{Border, Color, Shadow, Theme, Vector};
+
+/// Minimum contrast ratio for text (WCAG AA for normal text).
+pub const MIN_TEXT_CONTRAST: f32 = 4.5;
+
+/// Accent presets offered in settings.
+pub const ACCENT_PRESETS: [Color; 6] = [
+    rgb8(88, 170, 255),
+    rgb8(92, 209, 147),
+    rgb8(186, 134, 252),
+    rgb8(255, 167, 38),
+    rgb8(255, 105, 150),
+    rgb8(64, 196, 214),
+];
+
+/// Light or dark base palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ThemeMode {
+    #[default]
+    Dark,
+    Light,
+}
+
+impl ThemeMode {
+    pub fn toggled(self) -> Self {
+        match self {
+            Self::Dark => Self::Light,
+            Self::Light => Self::Dark,
+        }
+    }
+}
+
+/// Base colors of a theme, independent of the accent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub bg: Color,
+    pub surface: Color,
+    pub surface_alt: Color,
+    pub surface_hover: Color,
+    pub surface_pressed: Color,
+    pub outgoing: Color,
+    pub outgoing_hover: Color,
+    pub outgoing_pressed: Color,
+    pub border: Color,
+    pub border_hover: Color,
+    pub text: Color,
+    pub muted: Color,
+    pub success: Color,
+    pub danger: Color,
+    pub selection: Color,
+    pub shadow: Color,
+}
+
+impl Palette {
+    pub const DARK: Self = Self {
+        bg: rgb8(12, 14, 20),
+        surface: rgb8(18, 22, 32),
+        surface_alt: rgb8(26, 31, 44),
+        surface_hover: rgb8(32, 38, 54),
+        surface_pressed: rgb8(24, 29, 41),
+        outgoing: rgb8(20, 32, 46),
+        outgoing_hover: rgb8(26, 38, 56),
+        outgoing_pressed: rgb8(18, 26, 38),
+        border: rgb8(42, 50, 67),
+        border_hover: rgb8(72, 82, 104),
+        text: rgb8(231, 235, 242),
+        muted: rgb8(151, 160, 178),
+        success: rgb8(92, 209, 147),
+        danger: rgb8(255, 122, 122),
+        selection: rgb8(65, 92, 140),
+        shadow: Color::from_rgba(0.0, 0.0, 0.0, 0.35),
+    };
+
+    pub const LIGHT: Self = Self {
+        bg: rgb8(244, 246, 250),
+        surface: rgb8(255, 255, 255),
+        surface_alt: rgb8(236, 239, 245),
+        surface_hover: rgb8(228, 232, 240),
+        surface_pressed: rgb8(218, 223, 232),
+        outgoing: rgb8(226, 238, 255),
+        outgoing_hover: rgb8(214, 230, 252),
+        outgoing_pressed: rgb8(204, 222, 248),
+        border: rgb8(208, 214, 225),
+        border_hover: rgb8(170, 178, 194),
+        text: rgb8(24, 28, 38),
+        muted: rgb8(88, 96, 112),
+        success: rgb8(22, 128, 76),
+        danger: rgb8(196, 43, 43),
+        selection: rgb8(190, 210, 245),
+        shadow: Color::from_rgba(0.0, 0.0, 0.0, 0.12),
+    };
+
+    pub fn for_mode(mode: ThemeMode) -> Self {
+        match mode {
+            ThemeMode::Dark => Self::DARK,
+            ThemeMode::Light => Self::LIGHT,
+        }
+    }
+}
+
+/// Styles for the active palette and accent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Styles {
+    pub mode: ThemeMode,
+    pub palette: Palette,
+    /// Accent, adjusted so that `on_accent` text stays readable on it
+    pub accent: Color,
+    /// Text color used on accent/danger buttons
+    pub on_accent: Color,
+}
+
+impl Default for Styles {
+    fn default() -> Self {
+        Self::new(ThemeMode::default(), ACCENT_PRESETS[0])
+    }
+}
+
+impl Styles {
+    pub fn new(mode: ThemeMode, accent: Color) -> Self {
+        let palette = Palette::for_mode(mode);
+        let (accent, on_accent) = readable_fill(accent, &palette);
+        Self {
+            mode,
+            palette,
+            accent,
+            on_accent,
+        }
+    }
+
+    /// iced theme matching these styles.
+    pub fn theme(&self) -> Theme {
+        let name = match self.mode {
+            ThemeMode::Dark => "Cosmic Dark",
+            ThemeMode::Light => "Cosmic Light",
+        };
+        Theme::custom(
+            name.to_string(),
+            iced::theme::Palette {
+                background: self.palette.bg,
+                text: self.palette.text,
+                primary: self.accent,
+                success: self.palette.success,
+                danger: self.palette.danger,
+            },
+        )
+    }
+
+    pub fn root(&self, _theme: &Theme) -> container_widget::Style {
+        container_widget::Style {
+            text_color: Some(self.palette.text),
+            background: Some(self.palette.bg.into()),
+            ..container_widget::Style::default()
+        }
+    }
+
+    pub fn header(&self, _theme: &Theme) -> container_widget::Style {
+        container_widget::Style {
+            text_color: Some(self.palette.text),
+            background: Some(self.palette.surface_alt.into()),
+            border: Border {
+                width: 1.0,
+                radius: 10.0.into(),
+                color: self.palette.border,
+            },
+            shadow: Shadow {
+                color: self.palette.shadow,
+                offset: Vector::new(0.0, 4.0),
+                blur_radius: 12.0,
+            },
+        }
+    }
+
+    pub fn panel(&self, _theme: &Theme) -> container_widget::Style {
+        container_widget::Style {
+            text_color: Some(self.palette.text),
+            background: Some(self.palette.surface.into()),
+            border: Border {
+                width: 1.0,
+                radius: 12.0.into(),
+                color: self.palette.border,
+            },
+            ..container_widget::Style::default()
+        }
+    }
+
+    pub fn sidebar(&self, theme: &Theme) -> container_widget::Style {
+        self.panel(theme)
+    }
+
+    /// Dark backdrop of the photo viewer, the same in both themes.
+    pub fn overlay(&self, _theme: &Theme) -> container_widget::Style {
+        container_widget::Style {
+            text_color: Some(Color::WHITE),
+            background: Some(Color::from_rgba(0.0, 0.0, 0.0, 0.92).into()),
+            ..container_widget::Style::default()
+        }
+    }
+
+    /// Quoted reply or forwarded message inside a message bubble.
+    pub fn quote(&self, _theme: &Theme) -> container_widget::Style {
+        container_widget::Style {
+            text_color: Some(self.palette.text),
+            background: Some(self.palette.surface.into()),
+            border: Border {
+                width: 1.0,
+                radius: 6.0.into(),
+                color: self.accent,
+            },
+            ..container_widget::Style::default()
+        }
+    }
+
+    /// Placeholder shown while an avatar is not loaded.
+    pub fn avatar(&self, _theme: &Theme) -> container_widget::Style {
+        container_widget::Style {
+            text_color: Some(self.palette.muted),
+            background: Some(self.palette.surface_alt.into()),
+            border: Border {
+                width: 1.0,
+                radius: 16.0.into(),
+                color: self.palette.border,
+            },
+            ..container_widget::Style::default()
+        }
+    }
+
+    pub fn button_primary(
+        &self,
+        _theme: &Theme,
+        status: button_widget::Status,
+    ) -> button_widget::Style {
+        self.filled_button(self.accent, status, true)
+    }
+
+    pub fn button_secondary(
+        &self,
+        _theme: &Theme,
+        status: button_widget::Status,
+    ) -> button_widget::Style {
+        let bg = match status {
+            button_widget::Status::Hovered => self.palette.surface_alt,
+            button_widget::Status::Pressed => self.palette.surface_hover,
+            _ => self.palette.surface,
+        };
+
+        button_widget::Style {
+            background: Some(bg.into()),
+            text_color: self.palette.text,
+            border: Border {
+                width: 1.0,
+                radius: 10.0.into(),
+                color: self.palette.border,
+            },
+            shadow: Shadow::default(),
+        }
+    }
+
+    pub fn button_danger(
+        &self,
+        _theme: &Theme,
+        status: button_widget::Status,
+    ) -> button_widget::Style {
+        let (danger, _) = readable_fill(self.palette.danger, &self.palette);
+        self.filled_button(danger, status, false)
+    }
+
+    /// Accent swatch in the settings preset row.
+    pub fn swatch(
+        &self,
+        _theme: &Theme,
+        status: button_widget::Status,
+        color: Color,
+        selected: bool,
+    ) -> button_widget::Style {
+        button_widget::Style {
+            background: Some(color.into()),
+            text_color: self.on_accent,
+            border: Border {
+                width: if selected || status == button_widget::Status::Hovered {
+                    2.0
+                } else {
+                    0.0
+                },
+                radius: 10.0.into(),
+                color: self.palette.text,
+            },
+            shadow: Shadow::default(),
+        }
+    }
+
+    pub fn chat_button(
+        &self,
+        _theme: &Theme,
+        status: button_widget::Status,
+        selected: bool,
+    ) -> button_widget::Style {
+        let bg = if selected {
+            self.palette.surface_alt
+        } else {
+            self.palette.surface
+        };
+        let hover = if selected {
+            self.palette.surface_alt
+        } else {
+            self.palette.surface_hover
+        };
+
+        button_widget::Style {
+            background: Some(
+                match status {
+                    button_widget::Status::Hovered => hover,
+                    button_widget::Status::Pressed => self.palette.surface_pressed,
+                    _ => bg,
+                }
+                .into(),
+            ),
+            text_color: self.palette.text,
+            border: Border {
+                width: if selected { 1.0 } else { 0.0 },
+                radius: 10.0.into(),
+                color: if selected {
+                    self.accent
+                } else {
+                    Color::TRANSPARENT
+                },
+            },
+            shadow: Shadow::default(),
+        }
+    }
+
+    pub fn message_button(
+        &self,
+        _theme: &Theme,
+        status: button_widget::Status,
+        selected: bool,
+        outgoing: bool,
+    ) -> button_widget::Style {
+        let (base, hover, pressed) = if outgoing {
+            (
+                self.palette.outgoing,
+                self.palette.outgoing_hover,
+                self.palette.outgoing_pressed,
+            )
+        } else {
+            (
+                self.palette.surface_alt,
+                self.palette.surface_hover,
+                self.palette.surface_pressed,
+            )
+        };
+        let border = if selected {
+            self.accent
+        } else {
+            self.palette.border
+        };
+
+        button_widget::Style {
+            background: Some(
+                match status {
+                    button_widget::Status::Hovered => hover,
+                    button_widget::Status::Pressed => pressed,
+                    _ => base,
+                }
+                .into(),
+            ),
+            text_color: self.palette.text,
+            border: Border {
+                width: 1.0,
+                radius: 12.0.into(),
+                color: border,
+            },
+            shadow: Shadow::default(),
+        }
+    }
+
+    pub fn text_input(&self, _theme: &Theme, status: input_widget::Status) -> input_widget::Style {
+        let border = match status {
+            input_widget::Status::Focused => self.accent,
+            input_widget::Status::Hovered => self.palette.border_hover,
+            _ => self.palette.border,
+        };
+
+        input_widget::Style {
+            background: self.palette.surface.into(),
+            border: Border {
+                radius: 10.0.into(),
+                width: 1.0,
+                color: border,
+            },
+            icon: self.palette.muted,
+            placeholder: self.palette.muted,
+            value: self.palette.text,
+            selection: self.palette.selection,
+        }
+    }
+
+    fn filled_button(
+        &self,
+        fill: Color,
+        status: button_widget::Status,
+        raised: bool,
+    ) -> button_widget::Style {
+        let (_, on_fill) = readable_fill(fill, &self.palette);
+        let bg = match status {
+            button_widget::Status::Hovered => mix(fill, on_fill, 0.12),
+            button_widget::Status::Pressed => mix(fill, on_fill, -0.12),
+            _ => fill,
+        };
+
+        button_widget::Style {
+            background: Some(bg.into()),
+            text_color: on_fill,
+            border: Border {
+                width: 0.0,
+                radius: 10.0.into(),
+                color: Color::TRANSPARENT,
+            },
+            shadow: if raised {
+                Shadow {
+                    color: self.palette.shadow,
+                    offset: Vector::new(0.0, 2.0),
+                    blur_radius: 8.0,
+                }
+            } else {
+                Shadow::default()
+            },
+        }
+    }
+}
+
+/// Parse `#rrggbb` / `rrggbb` into a color.
+pub fn parse_hex(input: &str) -> Option<Color> {
+    let hex = input.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some(Color::from_rgb8(channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// WCAG contrast ratio between two opaque colors (1.0..=21.0).
+pub fn contrast_ratio(a: Color, b: Color) -> f32 {
+    let (la, lb) = (relative_luminance(a), relative_luminance(b));
+    let (hi, lo) = if la > lb { (la, lb) } else { (lb, la) };
+    (hi + 0.05) / (lo + 0.05)
+}
+
+fn relative_luminance(color: Color) -> f32 {
+    let linear = |c: f32| {
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(color.r) + 0.7152 * linear(color.g) + 0.0722 * linear(color.b)
+}
+
+/// Pick the palette text color (`bg` or `text`) that reads best on `fill`,
+/// shifting `fill` away from it until the contrast is sufficient.
+fn readable_fill(fill: Color, palette: &Palette) -> (Color, Color) {
+    let on_fill = if contrast_ratio(palette.bg, fill) >= contrast_ratio(palette.text, fill) {
+        palette.bg
+    } else {
+        palette.text
+    };
+    let away = if relative_luminance(on_fill) > 0.5 {
+        Color::BLACK
+    } else {
+        Color::WHITE
+    };
+
+    let mut fill = Color { a: 1.0, ..fill };
+    for _ in 0..32 {
+        if contrast_ratio(on_fill, fill) >= MIN_TEXT_CONTRAST {
+            break;
+        }
+        fill = mix(fill, away, 0.1);
+    }
+    (fill, on_fill)
+}
+
+/// Move `from` towards `to` by `amount` (negative moves away).
+fn mix(from: Color, to: Color, amount: f32) -> Color {
+    let channel = |a: f32, b: f32| (a + (b - a) * amount).clamp(0.0, 1.0);
+    Color::from_rgb(
+        channel(from.r, to.r),
+        channel(from.g, to.g),
+        channel(from.b, to.b),
+    )
+}
+
+const fn rgb8(r: u8, g: u8, b: u8) -> Color {
+    Color::from_rgb(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PALETTES: [Palette; 2] = [Palette::DARK, Palette::LIGHT];
+
+    #[test]
+    fn test_text_is_readable_on_all_backgrounds() {
+        for palette in PALETTES {
+            let backgrounds = [
+                palette.bg,
+                palette.surface,
+                palette.surface_alt,
+                palette.surface_hover,
+                palette.outgoing,
+            ];
+            for bg in backgrounds {
+                for fg in [palette.text, palette.muted] {
+                    let ratio = contrast_ratio(fg, bg);
+                    assert!(
+                        ratio > MIN_TEXT_CONTRAST,
+                        "{:?} on {:?}: {:.2}",
+                        fg,
+                        bg,
+                        ratio
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_any_accent_keeps_button_text_readable() {
+        let extremes = [
+            Color::WHITE,
+            Color::BLACK,
+            rgb8(255, 255, 0),
+            rgb8(128, 128, 128),
+            rgb8(0, 0, 255),
+        ];
+        for mode in [ThemeMode::Dark, ThemeMode::Light] {
+            for accent in ACCENT_PRESETS.into_iter().chain(extremes) {
+                let styles = Styles::new(mode, accent);
+                let ratio = contrast_ratio(styles.on_accent, styles.accent);
+                assert!(
+                    ratio >= MIN_TEXT_CONTRAST,
+                    "{:?} accent {:?}: {:.2}",
+                    mode,
+                    accent,
+                    ratio
+                );
+
+                let theme = Theme::Dark;
+                let danger = styles.button_danger(&theme, button_widget::Status::Active);
+                let bg = match danger.background {
+                    Some(iced::Background::Color(c)) => c,
+                    _ => unreachable!(),
+                };
+                assert!(contrast_ratio(danger.text_color, bg) >= MIN_TEXT_CONTRAST);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        assert_eq!(parse_hex("#58aaff"), Some(Color::from_rgb8(88, 170, 255)));
+        assert_eq!(parse_hex("58AAFF"), Some(Color::from_rgb8(88, 170, 255)));
+        assert_eq!(parse_hex("#58aa"), None);
+        assert_eq!(parse_hex("zzzzzz"), None);
+    }
+}
@@ -0,0 +1,116 @@
+//! Picking files to send, and previews of them.
+//!
+//! The native dialog chooses the file; it is checked against VK's limits
+//! here, so a wrong file is refused before anything is uploaded.
+
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use image::{ImageFormat, ImageReader};
+use serde::Serialize;
+use tauri::AppHandle;
+use tauri_plugin_dialog::DialogExt;
+use tokio::sync::oneshot;
+use vk_core::upload::{self, PHOTO_EXTENSIONS, Rejected, UploadKind};
+
+/// Error returned to the frontend, tagged by `kind` for toasts.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AttachError {
+    /// The dialog was closed without choosing a file
+    Cancelled,
+    /// Not a photo VK accepts (jpg, png, gif)
+    Unsupported { message: String },
+    /// Over VK's size limit for the kind
+    TooLarge { message: String },
+    /// The file cannot be read
+    NotFound { message: String },
+    /// No session to send with
+    NotLoggedIn,
+    /// The image could not be decoded for a thumbnail
+    Thumbnail { message: String },
+}
+
+impl AttachError {
+    fn rejected(path: &Path, rejected: Rejected) -> Self {
+        let message = format!("{}: {}", upload::file_title(path), rejected);
+        match rejected {
+            Rejected::NotAPhoto => AttachError::Unsupported { message },
+            Rejected::TooLarge { .. } => AttachError::TooLarge { message },
+        }
+    }
+}
+
+/// File chosen in the dialog, for the optimistic bubble.
+#[derive(Debug, Clone, Serialize)]
+pub struct PickedFile {
+    pub name: String,
+    pub size: u64,
+    pub path: String,
+}
+
+/// Let the user choose a file to send as `kind`, and check it.
+pub async fn pick(app: &AppHandle, kind: UploadKind) -> Result<PickedFile, AttachError> {
+    let (tx, rx) = oneshot::channel();
+    let dialog = app.dialog().file();
+    let dialog = match kind {
+        UploadKind::Photo => dialog
+            .set_title("Send photo")
+            .add_filter("Images", PHOTO_EXTENSIONS),
+        UploadKind::Doc => dialog.set_title("Send file"),
+    };
+    dialog.pick_file(move |file| {
+        let _ = tx.send(file);
+    });
+
+    let path = rx
+        .await
+        .ok()
+        .flatten()
+        .and_then(|file| file.into_path().ok())
+        .ok_or(AttachError::Cancelled)?;
+    checked(path, kind)
+}
+
+/// `path` with its size, if VK takes it as `kind`.
+fn checked(path: PathBuf, kind: UploadKind) -> Result<PickedFile, AttachError> {
+    let size = std::fs::metadata(&path)
+        .map_err(|e| AttachError::NotFound {
+            message: format!("{}: {}", path.display(), e),
+        })?
+        .len();
+    upload::check_file(&path, size, kind).map_err(|e| AttachError::rejected(&path, e))?;
+
+    Ok(PickedFile {
+        name: upload::file_title(&path),
+        size,
+        path: path.to_string_lossy().into_owned(),
+    })
+}
+
+/// PNG data URL of the image at `path`, at most `max_px` on each side.
+///
+/// Only photos VK accepts are read, so the webview cannot use this to
+/// look at arbitrary files.
+pub async fn thumbnail(path: &str, max_px: u32) -> Result<String, AttachError> {
+    let picked = checked(PathBuf::from(path), UploadKind::Photo)?;
+    tokio::task::spawn_blocking(move || encode_thumbnail(Path::new(&picked.path), max_px))
+        .await
+        .map_err(|e| AttachError::Thumbnail {
+            message: e.to_string(),
+        })?
+        .map_err(|e| AttachError::Thumbnail {
+            message: format!("{}: {}", path, e),
+        })
+}
+
+fn encode_thumbnail(path: &Path, max_px: u32) -> Result<String, image::ImageError> {
+    let image = ImageReader::open(path)?.with_guessed_format()?.decode()?;
+    let mut png = Cursor::new(Vec::new());
+    image
+        .thumbnail(max_px, max_px)
+        .write_to(&mut png, ImageFormat::Png)?;
+    let data = base64::engine::general_purpose::STANDARD.encode(png.into_inner());
+    Ok(format!("data:image/png;base64,{}", data))
+}
@@ -1,10 +1,24 @@
 //! Tauri commands callable from frontend.
 
-use tauri::{AppHandle, State};
-use vk_api::auth::AuthManager;
-use vk_core::AsyncCommand;
-
-use crate::state::AppState;
+use std::time::Duration;
+
+use tauri::{AppHandle, Emitter, State};
+use tokio::sync::watch;
+use vk_api::auth::{self, AuthManager};
+use vk_core::config::Frontend;
+use vk_core::download;
+use vk_core::export::{self, ExportFormat};
+use vk_core::outgoing::{MAX_MESSAGE_CHARS, split_message, too_long};
+use vk_core::upload::UploadKind;
+use vk_core::{AsyncCommand, AttachmentInfo, CoreEvent};
+
+use crate::attach::{self, AttachError, PickedFile};
+use crate::files::{self, FileError};
+use crate::settings::AppSettings;
+use crate::state::{AppState, HealthReport, Visibility};
+
+/// How long the browser gets to log in through the redirect catcher.
+const REDIRECT_TIMEOUT: Duration = Duration::from_secs(300);
 
 /// Get VK OAuth URL.
 #[tauri::command]
@@ -22,6 +36,44 @@ pub async fn login(
     state.login_from_redirect(app, &redirect_url).await
 }
 
+/// Start the local catcher of the OAuth redirect and return the auth URL
+/// to open; `wait_for_redirect_login` then completes the login.
+#[tauri::command]
+pub async fn start_redirect_login(
+    state: State<'_, AppState>,
+    port: Option<u16>,
+) -> Result<String, String> {
+    let mut slot = state.redirect_listener.lock().await;
+    // A previous attempt still holds the port
+    slot.take();
+    let listener =
+        AuthManager::start_redirect_listener(port.unwrap_or(auth::DEFAULT_REDIRECT_PORT))
+            .await
+            .map_err(|e| e.to_string())?;
+    let url = listener.auth_url();
+    *slot = Some(listener);
+    Ok(url)
+}
+
+/// Wait for the browser to bring the token to the catcher, then log in.
+#[tauri::command]
+pub async fn wait_for_redirect_login(
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let listener = state
+        .redirect_listener
+        .lock()
+        .await
+        .take()
+        .ok_or("Redirect catcher is not running")?;
+    let redirect_url = listener
+        .wait(REDIRECT_TIMEOUT)
+        .await
+        .map_err(|e| e.to_string())?;
+    state.login_from_redirect(app, &redirect_url).await
+}
+
 /// Check if authenticated.
 #[tauri::command]
 pub async fn is_authenticated(state: State<'_, AppState>) -> Result<bool, String> {
@@ -65,6 +117,16 @@ pub async fn load_conversations(
     Ok(())
 }
 
+/// Load the profile of the logged-in account.
+#[tauri::command]
+pub async fn load_profile(state: State<'_, AppState>) -> Result<(), String> {
+    let tx = state.command_tx.lock().await;
+    if let Some(tx) = tx.as_ref() {
+        tx.send(AsyncCommand::LoadProfile).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
 /// Load messages for a chat.
 #[tauri::command]
 pub async fn load_messages(
@@ -139,22 +201,58 @@ pub async fn load_messages_with_start_message_id(
     Ok(())
 }
 
-/// Send a message.
+/// Cut `text` to VK's length limit, or refuse it when splitting is off.
+async fn message_parts(state: &AppState, text: &str) -> Result<Vec<String>, String> {
+    if too_long(text) && !state.settings.lock().await.split_long_messages {
+        return Err(format!(
+            "message is {} characters, VK takes {}",
+            text.chars().count(),
+            MAX_MESSAGE_CHARS
+        ));
+    }
+    Ok(split_message(text, MAX_MESSAGE_CHARS))
+}
+
+/// Send a message; a long one goes in parts, in order.
 #[tauri::command]
 pub async fn send_message(
     state: State<'_, AppState>,
     peer_id: i64,
     text: String,
 ) -> Result<(), String> {
+    let parts = message_parts(&state, &text).await?;
     let tx = state.command_tx.lock().await;
     if let Some(tx) = tx.as_ref() {
-        tx.send(AsyncCommand::SendMessage { peer_id, text })
-            .map_err(|e| e.to_string())?;
+        for text in parts {
+            tx.send(AsyncCommand::SendMessage { peer_id, text })
+                .map_err(|e| e.to_string())?;
+        }
     }
     Ok(())
 }
 
-/// Send a reply.
+/// Answer the captcha of a `CaptchaRequired` event and repeat the
+/// command it came with.
+#[tauri::command]
+pub async fn answer_captcha(
+    state: State<'_, AppState>,
+    sid: String,
+    key: String,
+    retry: AsyncCommand,
+) -> Result<(), String> {
+    let tx = state.command_tx.lock().await;
+    if let Some(tx) = tx.as_ref() {
+        tx.send(AsyncCommand::AnswerCaptcha {
+            sid,
+            key,
+            retry: Box::new(retry),
+        })
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Send a reply; of a long one only the first part quotes `reply_to`.
 #[tauri::command]
 pub async fn send_reply(
     state: State<'_, AppState>,
@@ -162,10 +260,17 @@ pub async fn send_reply(
     reply_to: i64,
     text: String,
 ) -> Result<(), String> {
+    let parts = message_parts(&state, &text).await?;
     let tx = state.command_tx.lock().await;
     if let Some(tx) = tx.as_ref() {
-        tx.send(AsyncCommand::SendReply { peer_id, reply_to, text })
-            .map_err(|e| e.to_string())?;
+        let mut reply_to = Some(reply_to);
+        for text in parts {
+            let cmd = match reply_to.take() {
+                Some(reply_to) => AsyncCommand::SendReply { peer_id, reply_to, text },
+                None => AsyncCommand::SendMessage { peer_id, text },
+            };
+            tx.send(cmd).map_err(|e| e.to_string())?;
+        }
     }
     Ok(())
 }
@@ -198,6 +303,7 @@ pub async fn edit_message(
     message_id: i64,
     cmid: Option<i64>,
     text: String,
+    base_text: Option<String>,
 ) -> Result<(), String> {
     let tx = state.command_tx.lock().await;
     if let Some(tx) = tx.as_ref() {
@@ -206,6 +312,7 @@ pub async fn edit_message(
             message_id,
             cmid,
             text,
+            base_hash: base_text.as_deref().map(vk_core::edit::content_hash),
         })
         .map_err(|e| e.to_string())?;
     }
@@ -232,6 +339,28 @@ pub async fn delete_message(
     Ok(())
 }
 
+/// Put a reaction on a message, or remove yours when `reaction_id` is null.
+#[tauri::command]
+pub async fn set_reaction(
+    state: State<'_, AppState>,
+    peer_id: i64,
+    message_id: i64,
+    cmid: i64,
+    reaction_id: Option<i64>,
+) -> Result<(), String> {
+    let tx = state.command_tx.lock().await;
+    if let Some(tx) = tx.as_ref() {
+        tx.send(AsyncCommand::SetReaction {
+            peer_id,
+            message_id,
+            cmid,
+            reaction_id,
+        })
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
 /// Fetch message details (cmid, attachments, reply, forwards).
 #[tauri::command]
 pub async fn fetch_message_by_id(
@@ -275,16 +404,47 @@ pub async fn mark_as_read(
     Ok(())
 }
 
-/// Send a photo attachment.
+/// Create a group chat; the result arrives as a `ChatCreated` event.
 #[tauri::command]
-pub async fn send_photo(
+pub async fn create_chat(
+    state: State<'_, AppState>,
+    user_ids: Vec<i64>,
+    title: String,
+) -> Result<(), String> {
+    let tx = state.command_tx.lock().await;
+    if let Some(tx) = tx.as_ref() {
+        tx.send(AsyncCommand::CreateChat { user_ids, title })
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Rename a group chat; the result arrives as a `ChatSettingsChanged` event.
+#[tauri::command]
+pub async fn rename_chat(
+    state: State<'_, AppState>,
+    peer_id: i64,
+    title: String,
+) -> Result<(), String> {
+    let tx = state.command_tx.lock().await;
+    if let Some(tx) = tx.as_ref() {
+        tx.send(AsyncCommand::RenameChat { peer_id, title })
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Set the photo of a group chat from a local image; the result arrives as a
+/// `ChatSettingsChanged` event.
+#[tauri::command]
+pub async fn set_chat_photo(
     state: State<'_, AppState>,
     peer_id: i64,
     path: String,
 ) -> Result<(), String> {
     let tx = state.command_tx.lock().await;
     if let Some(tx) = tx.as_ref() {
-        tx.send(AsyncCommand::SendPhoto {
+        tx.send(AsyncCommand::SetChatPhoto {
             peer_id,
             path: std::path::PathBuf::from(path),
         })
@@ -293,58 +453,362 @@ pub async fn send_photo(
     Ok(())
 }
 
-/// Send a document attachment.
+/// Turn chat notifications off for `duration` (`1h`, `8h`, `forever`...), or
+/// back on when it is missing; the result arrives as a `ChatMuteChanged` event.
+#[tauri::command]
+pub async fn set_chat_muted(
+    state: State<'_, AppState>,
+    peer_id: i64,
+    duration: Option<String>,
+) -> Result<(), String> {
+    let duration = duration
+        .map(|d| vk_core::MuteDuration::parse(&d))
+        .transpose()?;
+    let tx = state.command_tx.lock().await;
+    if let Some(tx) = tx.as_ref() {
+        tx.send(AsyncCommand::SetChatMuted { peer_id, duration })
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Delete a conversation; the result arrives as a `ConversationDeleted` event.
+#[tauri::command]
+pub async fn delete_conversation(state: State<'_, AppState>, peer_id: i64) -> Result<(), String> {
+    let tx = state.command_tx.lock().await;
+    if let Some(tx) = tx.as_ref() {
+        tx.send(AsyncCommand::DeleteConversation { peer_id })
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Count chat media; the result arrives as a `ChatInfoLoaded` event.
+#[tauri::command]
+pub async fn load_chat_info(state: State<'_, AppState>, peer_id: i64) -> Result<(), String> {
+    let tx = state.command_tx.lock().await;
+    if let Some(tx) = tx.as_ref() {
+        tx.send(AsyncCommand::LoadChatInfo { peer_id })
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Load who read a message in a group chat; the result arrives as a
+/// `ReadPeersLoaded` event.
+#[tauri::command]
+pub async fn fetch_read_peers(
+    state: State<'_, AppState>,
+    peer_id: i64,
+    cmid: i64,
+) -> Result<(), String> {
+    let tx = state.command_tx.lock().await;
+    if let Some(tx) = tx.as_ref() {
+        tx.send(AsyncCommand::FetchReadPeers { peer_id, cmid })
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Send photos in one message; upload progress events carry `local_id`.
+#[tauri::command]
+pub async fn send_photo(
+    state: State<'_, AppState>,
+    peer_id: i64,
+    paths: Vec<String>,
+    local_id: u64,
+) -> Result<(), String> {
+    let tx = state.command_tx.lock().await;
+    if let Some(tx) = tx.as_ref() {
+        tx.send(AsyncCommand::SendPhoto {
+            peer_id,
+            paths: paths.into_iter().map(std::path::PathBuf::from).collect(),
+            local_id,
+        })
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Send documents in one message; upload progress events carry `local_id`.
 #[tauri::command]
 pub async fn send_doc(
     state: State<'_, AppState>,
     peer_id: i64,
-    path: String,
+    paths: Vec<String>,
+    local_id: u64,
 ) -> Result<(), String> {
     let tx = state.command_tx.lock().await;
     if let Some(tx) = tx.as_ref() {
         tx.send(AsyncCommand::SendDoc {
             peer_id,
-            path: std::path::PathBuf::from(path),
+            paths: paths.into_iter().map(std::path::PathBuf::from).collect(),
+            local_id,
         })
         .map_err(|e| e.to_string())?;
     }
     Ok(())
 }
 
+/// Choose a photo in the native dialog and send it; upload progress events
+/// carry `local_id`. Returns the file for the optimistic bubble.
+#[tauri::command]
+pub async fn pick_and_send_photo(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    peer_id: i64,
+    local_id: u64,
+) -> Result<PickedFile, AttachError> {
+    pick_and_send(&app, &state, peer_id, local_id, UploadKind::Photo).await
+}
+
+/// Choose a file in the native dialog and send it as a document; upload
+/// progress events carry `local_id`. Returns the file for the optimistic
+/// bubble.
+#[tauri::command]
+pub async fn pick_and_send_doc(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    peer_id: i64,
+    local_id: u64,
+) -> Result<PickedFile, AttachError> {
+    pick_and_send(&app, &state, peer_id, local_id, UploadKind::Doc).await
+}
+
+async fn pick_and_send(
+    app: &AppHandle,
+    state: &AppState,
+    peer_id: i64,
+    local_id: u64,
+    kind: UploadKind,
+) -> Result<PickedFile, AttachError> {
+    if state.command_tx.lock().await.is_none() {
+        return Err(AttachError::NotLoggedIn);
+    }
+    let file = attach::pick(app, kind).await?;
+
+    let paths = vec![std::path::PathBuf::from(&file.path)];
+    let command = match kind {
+        UploadKind::Photo => AsyncCommand::SendPhoto {
+            peer_id,
+            paths,
+            local_id,
+        },
+        UploadKind::Doc => AsyncCommand::SendDoc {
+            peer_id,
+            paths,
+            local_id,
+        },
+    };
+    let tx = state.command_tx.lock().await;
+    tx.as_ref()
+        .and_then(|tx| tx.send(command).ok())
+        .ok_or(AttachError::NotLoggedIn)?;
+    Ok(file)
+}
+
+/// Base64 PNG preview of a photo before sending, at most `max_px` a side.
+#[tauri::command]
+pub async fn get_image_thumbnail(path: String, max_px: u32) -> Result<String, AttachError> {
+    attach::thumbnail(&path, max_px).await
+}
+
 /// Download an attachment to the Downloads folder.
+///
+/// Progress is emitted as `core:event` `DownloadProgress`/`DownloadFinished`,
+/// like downloads started by the core. An existing file is not overwritten.
 #[tauri::command]
 pub async fn download_attachment(
+    app: AppHandle,
+    state: State<'_, AppState>,
     url: String,
     filename: String,
 ) -> Result<String, String> {
-    use std::path::PathBuf;
-
-    // Get Downloads directory
-    let download_dir = directories::UserDirs::new()
-        .and_then(|dirs| dirs.download_dir().map(|p| p.to_path_buf()))
-        .unwrap_or_else(|| PathBuf::from("."));
-
-    // Create full path
-    let file_path = download_dir.join(&filename);
-
-    // Download file
     let client = reqwest::Client::new();
-    let response = client
-        .get(&url)
-        .send()
+    let progress = |received, total| {
+        let _ = app.emit("core:event", CoreEvent::DownloadProgress {
+            title: filename.clone(),
+            received,
+            total,
+        });
+    };
+    let dir = state.settings.lock().await.download_dir();
+    let file_path = download::download(&client, &url, &dir, &filename, progress)
         .await
         .map_err(|e| format!("Download failed: {}", e))?;
 
-    let bytes = response
-        .bytes()
+    let _ = app.emit("core:event", CoreEvent::DownloadFinished {
+        title: filename,
+        path: file_path.clone(),
+    });
+    Ok(file_path.display().to_string())
+}
+
+/// Ask where to save `attachment` and download it there through the core.
+///
+/// Returns the chosen path, or `None` if the dialog was closed. The file is
+/// written as named, replacing one the user agreed to overwrite; progress
+/// and the end come as `core:event` `DownloadProgress`/`DownloadFinished`.
+#[tauri::command]
+pub async fn save_attachment_as(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    mut attachment: AttachmentInfo,
+) -> Result<Option<String>, String> {
+    let dir = state.settings.lock().await.download_dir();
+    let Some(path) = files::choose_save_path(&app, &dir, &attachment.title).await else {
+        return Ok(None);
+    };
+    let shown = path.display().to_string();
+    attachment.target_path = Some(path);
+
+    let tx = state.command_tx.lock().await;
+    let tx = tx.as_ref().ok_or("Not logged in")?;
+    tx.send(AsyncCommand::DownloadAttachments {
+        attachments: vec![attachment],
+    })
+    .map_err(|e| e.to_string())?;
+    Ok(Some(shown))
+}
+
+/// Export the history of a chat to a new file in the Downloads folder.
+///
+/// Progress is emitted as `core:event` `ExportProgress`; `cancel_export`
+/// stops it. Returns the path written, or `None` if cancelled.
+#[tauri::command]
+pub async fn export_history(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    peer_id: i64,
+    title: String,
+    format: String,
+) -> Result<Option<String>, String> {
+    let format = ExportFormat::parse(&format)
+        .ok_or_else(|| format!("Unknown export format: {}", format))?;
+    let client = state
+        .vk_client
+        .lock()
         .await
-        .map_err(|e| format!("Failed to read response: {}", e))?;
+        .clone()
+        .ok_or("Not logged in")?;
+    let cancel = {
+        let mut slot = state.history_export.lock().await;
+        if slot.is_some() {
+            return Err("An export is already running".into());
+        }
+        let (tx, rx) = watch::channel(false);
+        *slot = Some(tx);
+        rx
+    };
+
+    let dir = state.settings.lock().await.download_dir();
+    let path = export::default_path(&dir, &title, format);
+    let progress = |done, total| {
+        let _ = app.emit("core:event", CoreEvent::ExportProgress {
+            peer_id,
+            done,
+            total,
+        });
+    };
+    let result =
+        export::export_history(&client, peer_id, &title, format, &path, &cancel, progress).await;
+    state.history_export.lock().await.take();
+
+    match result {
+        Ok(Some(_)) => Ok(Some(path.display().to_string())),
+        Ok(None) => Ok(None),
+        Err(e) => Err(format!("Export failed: {:#}", e)),
+    }
+}
 
-    // Save to file
-    std::fs::write(&file_path, &bytes)
-        .map_err(|e| format!("Failed to save file: {}", e))?;
+/// Stop the running history export.
+#[tauri::command]
+pub async fn cancel_export(state: State<'_, AppState>) -> Result<(), String> {
+    if let Some(cancel) = state.history_export.lock().await.take() {
+        let _ = cancel.send(true);
+    }
+    Ok(())
+}
 
-    Ok(file_path.display().to_string())
+/// Show a downloaded file in the platform file manager.
+#[tauri::command]
+pub async fn show_in_folder(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<(), FileError> {
+    let download_dir = files::download_dir(&*state.settings.lock().await)?;
+    let path = files::allowed_path(&app, &download_dir, &path)?;
+    files::reveal(&path).await
+}
+
+/// Open a downloaded file in its default application.
+#[tauri::command]
+pub async fn open_path(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    path: String,
+) -> Result<(), FileError> {
+    let download_dir = files::download_dir(&*state.settings.lock().await)?;
+    let path = files::allowed_path(&app, &download_dir, &path)?;
+    files::open(&path).await
+}
+
+/// Start receiving `core:event`s, including those emitted before the
+/// listener was registered. Safe to call more than once.
+#[tauri::command]
+pub async fn subscribe_events(app: AppHandle, state: State<'_, AppState>) -> Result<(), String> {
+    state.subscribe_events(&app).await;
+    Ok(())
+}
+
+/// Report session health; revalidates the token and restarts a stale LongPoll.
+///
+/// Called by the frontend when the window becomes visible again.
+#[tauri::command]
+pub async fn health_check(state: State<'_, AppState>) -> Result<HealthReport, String> {
+    let report = state.health_report().await;
+    if report.authenticated && state.is_stale().await {
+        state.revalidate(false).await;
+        return Ok(state.health_report().await);
+    }
+    Ok(report)
+}
+
+/// Tell the backend whether the window is on screen; hidden switches to
+/// the battery-saving background mode.
+#[tauri::command]
+pub async fn set_window_visible(state: State<'_, AppState>, visible: bool) -> Result<(), String> {
+    let visibility = if visible {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    };
+    state.set_visibility(visibility).await;
+    Ok(())
+}
+
+/// Options of this app, the `[tauri]` section of the settings file.
+#[tauri::command]
+pub async fn get_settings(state: State<'_, AppState>) -> Result<AppSettings, String> {
+    let settings = state.settings.lock().await;
+    Ok(AppSettings::from_settings(&settings, &mut Vec::new()))
+}
+
+/// Replace the options of this app and save them for the next launch.
+#[tauri::command]
+pub async fn set_settings(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    settings: AppSettings,
+) -> Result<(), String> {
+    let mut shared = state.settings.lock().await;
+    let mut changed = shared.clone();
+    changed.set_section(Frontend::Tauri, &settings)?;
+    changed.save().map_err(|e| e.to_string())?;
+    *shared = changed.clone();
+    let _ = app.emit("core:event", CoreEvent::SettingsChanged(Box::new(changed)));
+    Ok(())
 }
 
 /// Logout.
@@ -352,9 +816,13 @@ pub async fn download_attachment(
 pub async fn logout(state: State<'_, AppState>) -> Result<(), String> {
     let mut auth = state.auth.lock().await;
     auth.logout().map_err(|e| e.to_string())?;
+    drop(auth);
+    // The next account must not see this one's messages
+    if let Some(cache) = &state.cache {
+        cache.clear();
+    }
 
-    *state.vk_client.lock().await = None;
-    *state.command_tx.lock().await = None;
+    state.stop_session().await;
 
     Ok(())
 }
@@ -0,0 +1,166 @@
+//! Choosing where downloads go, and opening downloaded files from the
+//! webview.
+//!
+//! The webview may only point at files inside the download directory or
+//! the app cache, so a compromised page cannot launch arbitrary programs.
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_dialog::DialogExt;
+use tokio::process::Command;
+use tokio::sync::oneshot;
+use vk_core::config::Settings;
+use vk_core::download;
+
+/// Error returned to the frontend, tagged by `kind` for toasts.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FileError {
+    /// The file no longer exists (moved or deleted)
+    NotFound { message: String },
+    /// The path is outside the download directory and the app cache
+    NotAllowed { message: String },
+    /// No file manager or default application could be launched
+    NoHandler { message: String },
+}
+
+/// The directory downloads may be opened from: the configured one or the
+/// user's download folder.
+///
+/// Unlike [`Settings::download_dir`] this does not fall back to the temp
+/// dir, which would let the webview open any file there.
+pub fn download_dir(settings: &Settings) -> Result<PathBuf, FileError> {
+    settings
+        .download_dir
+        .clone()
+        .or_else(|| {
+            directories::UserDirs::new().and_then(|dirs| dirs.download_dir().map(Path::to_path_buf))
+        })
+        .ok_or_else(|| FileError::NotAllowed {
+            message: "No download directory; set download_dir in the settings".into(),
+        })
+}
+
+/// Resolve `path` and check that it lies inside `download_dir` or the app
+/// cache.
+pub fn allowed_path(
+    app: &AppHandle,
+    download_dir: &Path,
+    path: &str,
+) -> Result<PathBuf, FileError> {
+    let resolved = Path::new(path)
+        .canonicalize()
+        .map_err(|e| FileError::NotFound {
+            message: format!("{}: {}", path, e),
+        })?;
+
+    let allowed = [
+        Some(download_dir.to_path_buf()),
+        app.path().app_cache_dir().ok(),
+    ];
+    let inside = allowed
+        .into_iter()
+        .flatten()
+        .filter_map(|dir| dir.canonicalize().ok())
+        .any(|dir| resolved.starts_with(dir));
+
+    if inside {
+        Ok(resolved)
+    } else {
+        Err(FileError::NotAllowed {
+            message: format!("{} is outside the download directory", path),
+        })
+    }
+}
+
+/// Ask where to save an attachment titled `title`, starting in `dir` with
+/// the title as a valid file name. `None` if the dialog was closed.
+pub async fn choose_save_path(app: &AppHandle, dir: &Path, title: &str) -> Option<PathBuf> {
+    let (tx, rx) = oneshot::channel();
+    app.dialog()
+        .file()
+        .set_title("Save attachment")
+        .set_directory(dir)
+        .set_file_name(download::sanitize_file_name(title))
+        .save_file(move |file| {
+            let _ = tx.send(file);
+        });
+    rx.await
+        .ok()
+        .flatten()
+        .and_then(|file| file.into_path().ok())
+}
+
+/// Open the file manager with `path` selected (its folder on Linux).
+pub async fn reveal(path: &Path) -> Result<(), FileError> {
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let command = {
+        let mut command = Command::new("xdg-open");
+        command.arg(path.parent().unwrap_or(path));
+        command
+    };
+    #[cfg(target_os = "windows")]
+    let command = {
+        let mut command = Command::new("explorer");
+        // explorer parses its own command line and does not understand
+        // the quotes `arg` would add around a path with spaces
+        command.raw_arg(format!("/select,\"{}\"", path.display()));
+        command
+    };
+    #[cfg(target_os = "macos")]
+    let command = {
+        let mut command = Command::new("open");
+        command.arg("-R").arg(path);
+        command
+    };
+
+    run(command, cfg!(not(target_os = "windows"))).await
+}
+
+/// Open `path` in its default application.
+pub async fn open(path: &Path) -> Result<(), FileError> {
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    let command = {
+        let mut command = Command::new("xdg-open");
+        command.arg(path);
+        command
+    };
+    #[cfg(target_os = "windows")]
+    let command = {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]).arg(path);
+        command
+    };
+    #[cfg(target_os = "macos")]
+    let command = {
+        let mut command = Command::new("open");
+        command.arg(path);
+        command
+    };
+
+    run(command, true).await
+}
+
+/// Run a launcher and map its failure to [`FileError::NoHandler`].
+///
+/// `explorer /select,` exits with 1 even on success, so its status is not
+/// checked there.
+async fn run(mut command: Command, check_status: bool) -> Result<(), FileError> {
+    let program = command
+        .as_std()
+        .get_program()
+        .to_string_lossy()
+        .into_owned();
+    let status = command.status().await.map_err(|e| FileError::NoHandler {
+        message: format!("Failed to run {}: {}", program, e),
+    })?;
+
+    if check_status && !status.success() {
+        return Err(FileError::NoHandler {
+            message: format!("{} exited with {}", program, status),
+        });
+    }
+    Ok(())
+}
@@ -1,6 +1,9 @@
 //! VK Tauri - Tauri GUI client library.
 
+pub mod attach;
 pub mod commands;
+pub mod files;
+pub mod settings;
 pub mod state;
 
 pub use commands::*;
@@ -8,8 +11,27 @@ pub use state::*;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    tracing_subscriber::fmt()
-        .with_env_filter("vk_tauri=debug,vk_core=debug,vk_api=debug")
+    use tracing_subscriber::prelude::*;
+
+    // Console as before, and the log file shared with the TUI
+    let file = vk_core::logging::log_dir().map(|dir| vk_core::logging::file_writer(&dir));
+    let (file_layer, _log_guard) = match file {
+        Some(Ok((writer, guard))) => (
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .with_writer(writer)
+                    .with_ansi(false),
+            ),
+            Some(guard),
+        ),
+        _ => (None, None),
+    };
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::EnvFilter::new(
+            "vk_tauri=debug,vk_core=debug,vk_api=debug",
+        ))
+        .with(tracing_subscriber::fmt::layer())
+        .with(file_layer)
         .init();
 
     let app_state = state::AppState::new();
@@ -43,8 +65,12 @@ pub fn run() {
                             let _ = window.show();
                             let _ = window.set_focus();
                         }
+                        set_visibility(app, state::Visibility::Visible);
                     }
                     "quit" => {
+                        if let Some(window) = app.get_webview_window("main") {
+                            settings::save_geometry(&window);
+                        }
                         app.exit(0);
                     }
                     _ => {}
@@ -59,6 +85,7 @@ pub fn run() {
                             let _ = window.show();
                             let _ = window.set_focus();
                         }
+                        set_visibility(tray.app_handle(), state::Visibility::Visible);
                     }
                     _ => {}
                 })
@@ -67,16 +94,30 @@ pub fn run() {
             // Store tray icon in app state
             let state: State<state::AppState> = app.state();
             let tray_clone = tray.clone();
-            tauri::async_runtime::block_on(async move {
+            let start_minimized = tauri::async_runtime::block_on(async move {
                 *state.tray_icon.lock().await = Some(tray_clone);
+                let settings = state.settings.lock().await;
+                settings::AppSettings::from_settings(&settings, &mut Vec::new()).start_minimized
             });
 
+            // The window starts hidden, so it appears where it was left
+            if let Some(window) = app.get_webview_window("main") {
+                settings::restore_geometry(&window);
+                if start_minimized {
+                    set_visibility(app.handle(), state::Visibility::Hidden);
+                } else {
+                    let _ = window.show();
+                }
+            }
+
             // Handle window close event - minimize to tray instead of exit
             if let Some(window) = app.get_webview_window("main") {
                 let window_clone = window.clone();
+                let app_handle = app.handle().clone();
                 window.on_window_event(move |event| {
                     if let tauri::WindowEvent::CloseRequested { api, .. } = event {
                         api.prevent_close();
+                        settings::save_geometry(&window_clone);
                         // Hide window instead of closing
                         #[cfg(target_os = "linux")]
                         {
@@ -86,6 +127,7 @@ pub fn run() {
                         {
                             let _ = window_clone.minimize();
                         }
+                        set_visibility(&app_handle, state::Visibility::Hidden);
                     }
                 });
             }
@@ -95,9 +137,12 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             commands::get_auth_url,
             commands::login,
+            commands::start_redirect_login,
+            commands::wait_for_redirect_login,
             commands::is_authenticated,
             commands::validate_session,
             commands::load_conversations,
+            commands::load_profile,
             commands::load_messages,
             commands::load_messages_around,
             commands::load_messages_with_offset,
@@ -105,16 +150,49 @@ pub fn run() {
             commands::send_message,
             commands::send_reply,
             commands::send_forward,
+            commands::answer_captcha,
             commands::edit_message,
             commands::delete_message,
+            commands::set_reaction,
             commands::fetch_message_by_id,
             commands::search_messages,
             commands::mark_as_read,
+            commands::create_chat,
+            commands::rename_chat,
+            commands::set_chat_photo,
+            commands::load_chat_info,
+            commands::fetch_read_peers,
+            commands::delete_conversation,
+            commands::set_chat_muted,
             commands::send_photo,
             commands::send_doc,
+            commands::pick_and_send_photo,
+            commands::pick_and_send_doc,
+            commands::get_image_thumbnail,
             commands::download_attachment,
+            commands::save_attachment_as,
+            commands::export_history,
+            commands::cancel_export,
+            commands::show_in_folder,
+            commands::open_path,
+            commands::subscribe_events,
+            commands::health_check,
+            commands::set_window_visible,
+            commands::get_settings,
+            commands::set_settings,
             commands::logout,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
+
+/// Switch the backend between normal and background mode from a window or
+/// tray handler.
+fn set_visibility(app: &tauri::AppHandle, visibility: state::Visibility) {
+    use tauri::Manager;
+
+    let state = app.state::<state::AppState>().inner().clone();
+    tauri::async_runtime::spawn(async move {
+        state.set_visibility(visibility).await;
+    });
+}
@@ -0,0 +1,116 @@
+//! The `[tauri]` section of the shared settings, and the main window's
+//! geometry, kept between launches.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use tauri::{PhysicalPosition, PhysicalSize, WebviewWindow};
+use vk_core::config::{Frontend, Settings};
+use vk_core::persist::PersistedFile;
+
+const GEOMETRY_VERSION: u32 = 1;
+
+/// Options of this app, changed from the frontend with `set_settings`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    /// Start with only the tray icon; the window opens from the tray
+    pub start_minimized: bool,
+}
+
+impl AppSettings {
+    /// The `[tauri]` section of `settings`; a broken one is added to
+    /// `problems` and the defaults are used.
+    pub fn from_settings(settings: &Settings, problems: &mut Vec<String>) -> Self {
+        match settings.section(Frontend::Tauri) {
+            Some(Ok(options)) => options,
+            Some(Err(e)) => {
+                problems.push(e);
+                Self::default()
+            }
+            None => Self::default(),
+        }
+    }
+}
+
+/// Size and place of the main window when it was last closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowGeometry {
+    pub width: u32,
+    pub height: u32,
+    pub x: i32,
+    pub y: i32,
+    /// The size and place above are those to restore when unmaximized
+    pub maximized: bool,
+}
+
+/// Remember where `window` is. A maximized or minimized window keeps the
+/// size and place it had before.
+pub fn save_geometry(window: &WebviewWindow) {
+    let Some(file) = geometry_file() else {
+        return;
+    };
+    let maximized = window.is_maximized().unwrap_or(false);
+    let minimized = window.is_minimized().unwrap_or(false);
+
+    let current = match (window.inner_size(), window.outer_position()) {
+        (Ok(size), Ok(position)) if !maximized && !minimized => Some(WindowGeometry {
+            width: size.width,
+            height: size.height,
+            x: position.x,
+            y: position.y,
+            maximized,
+        }),
+        _ => file.load().map(|previous| WindowGeometry {
+            maximized,
+            ..previous
+        }),
+    };
+
+    if let Some(geometry) = current
+        && let Err(e) = file.save(&Some(geometry))
+    {
+        tracing::warn!("Failed to save window geometry: {}", e);
+    }
+}
+
+/// Put `window` back where it was last closed, unless that place is on a
+/// monitor that is gone.
+pub fn restore_geometry(window: &WebviewWindow) {
+    let Some(geometry) = geometry_file().and_then(|file| file.load()) else {
+        return;
+    };
+
+    let _ = window.set_size(PhysicalSize::new(geometry.width, geometry.height));
+    if on_a_monitor(window, geometry.x, geometry.y) {
+        let _ = window.set_position(PhysicalPosition::new(geometry.x, geometry.y));
+    }
+    if geometry.maximized {
+        let _ = window.maximize();
+    }
+}
+
+/// Whether the point lies on one of the connected monitors.
+fn on_a_monitor(window: &WebviewWindow, x: i32, y: i32) -> bool {
+    window
+        .available_monitors()
+        .unwrap_or_default()
+        .iter()
+        .any(|monitor| {
+            let origin = monitor.position();
+            let size = monitor.size();
+            (origin.x..origin.x + size.width as i32).contains(&x)
+                && (origin.y..origin.y + size.height as i32).contains(&y)
+        })
+}
+
+fn geometry_file() -> Option<PersistedFile<Option<WindowGeometry>>> {
+    Some(PersistedFile::new(
+        config_dir()?.join("tauri_window.json"),
+        GEOMETRY_VERSION,
+    ))
+}
+
+fn config_dir() -> Option<PathBuf> {
+    directories::ProjectDirs::from("", "", "vk_tui").map(|dirs| dirs.config_dir().to_path_buf())
+}
@@ -1,28 +1,238 @@
 //! Application state management.
 
+use std::collections::{HashSet, VecDeque};
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::Serialize;
 use tauri::{AppHandle, Emitter, tray::TrayIcon};
-use tokio::sync::{mpsc, Mutex};
-use vk_api::{VkClient, auth::AuthManager};
-use vk_core::{AsyncCommand, CommandExecutor, CoreEvent};
+use tokio::sync::{mpsc, watch, Mutex};
+use tokio::task::JoinHandle;
+use vk_api::{
+    ConversationsResponse, LONG_POLL_MAX_WAIT, LONG_POLL_WAIT, LongPollResponse, LongPollServer,
+    VkClient,
+    auth::{AuthManager, RedirectListener},
+};
+use vk_core::cache::CacheLayer;
+use vk_core::config::Settings;
+use vk_core::longpoll::{LongPollSource, RetryPolicy};
+use vk_core::{AsyncCommand, CommandExecutor, CoreEvent, VkEvent};
+
+use crate::settings::AppSettings;
+
+/// LongPoll answers at least once per `wait`; silence for this much longer
+/// means the connection is dead (typically after suspend/resume).
+const LONG_POLL_STALE_MARGIN: Duration = Duration::from_secs(35);
+
+/// LongPoll wait while the window is hidden. Events still arrive at once,
+/// the longer wait only cuts the requests made while nothing happens.
+const HIDDEN_LONG_POLL_WAIT: Duration = LONG_POLL_MAX_WAIT;
+
+/// How often the resume detector compares wall-clock and monotonic time.
+const RESUME_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Wall-clock jump (beyond the check interval) treated as a system resume.
+const RESUME_JUMP_THRESHOLD: Duration = Duration::from_secs(30);
+
+/// Core events held while the frontend has not subscribed; older ones are
+/// dropped past this.
+const EVENT_BUFFER_CAP: usize = 256;
+
+/// LongPoll connection state as seen by the frontend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    Disconnected,
+    Connecting,
+    Connected,
+}
+
+/// Whether the main window is on screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Visibility {
+    Visible,
+    /// Hidden to the tray or minimized: background mode
+    Hidden,
+}
+
+impl Visibility {
+    fn long_poll_wait(self) -> Duration {
+        match self {
+            Visibility::Visible => LONG_POLL_WAIT,
+            Visibility::Hidden => HIDDEN_LONG_POLL_WAIT,
+        }
+    }
+}
+
+/// Result of the `health_check` command.
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    pub authenticated: bool,
+    pub connection: ConnectionState,
+    /// Seconds since the last LongPoll response (`None` before the first one)
+    pub last_event_age_secs: Option<u64>,
+    /// Seconds until the token expires (`None` for non-expiring tokens)
+    pub token_expires_in: Option<i64>,
+    pub visibility: Visibility,
+    /// Wait of the current LongPoll request
+    pub long_poll_wait_secs: u64,
+}
+
+/// Liveness of the current LongPoll connection.
+#[derive(Debug)]
+pub struct SessionHealth {
+    pub connection: ConnectionState,
+    pub last_event_at: Option<Instant>,
+    /// Wait the current poll was sent with
+    pub poll_wait: Duration,
+}
+
+impl SessionHealth {
+    fn new() -> Self {
+        Self {
+            connection: ConnectionState::Disconnected,
+            last_event_at: None,
+            poll_wait: LONG_POLL_WAIT,
+        }
+    }
+
+    fn is_stale(&self) -> bool {
+        self.connection != ConnectionState::Connected
+            || self
+                .last_event_at
+                .is_none_or(|at| at.elapsed() > self.poll_wait + LONG_POLL_STALE_MARGIN)
+    }
+}
+
+/// LongPoll source that records every server response as a sign of life.
+/// Polls with a longer wait while the window is hidden.
+struct TrackedSource {
+    client: Arc<VkClient>,
+    health: Arc<Mutex<SessionHealth>>,
+    visibility: Arc<Mutex<Visibility>>,
+}
+
+impl TrackedSource {
+    async fn touch(&self) {
+        self.health.lock().await.last_event_at = Some(Instant::now());
+    }
+}
+
+impl LongPollSource for TrackedSource {
+    async fn get_server(&self) -> vk_api::Result<LongPollServer> {
+        let server = self.client.get_server().await;
+        if server.is_ok() {
+            self.touch().await;
+        }
+        server
+    }
+
+    async fn poll(&self, server: &LongPollServer) -> vk_api::Result<LongPollResponse> {
+        let wait = self.visibility.lock().await.long_poll_wait();
+        self.health.lock().await.poll_wait = wait;
+        let response = self.client.longpoll().poll_with_wait(server, wait).await;
+        if response.is_ok() {
+            self.touch().await;
+        }
+        response
+    }
+
+    async fn missed_events(&self, server: &LongPollServer) -> vk_api::Result<Vec<VkEvent>> {
+        self.client.missed_events(server).await
+    }
+
+    async fn recent_conversations(&self, count: u32) -> vk_api::Result<ConversationsResponse> {
+        let response = self.client.recent_conversations(count).await;
+        if response.is_ok() {
+            self.touch().await;
+        }
+        response
+    }
+}
+
+/// Core events waiting for the frontend to subscribe.
+///
+/// The session starts, and loads chats, before the main view has its
+/// `core:event` listener; Tauri drops events nobody listens to.
+#[derive(Debug, Default)]
+pub struct EventBuffer {
+    subscribed: bool,
+    pending: VecDeque<CoreEvent>,
+}
+
+impl EventBuffer {
+    /// Keep `event` until the frontend subscribes, or hand it back to be
+    /// emitted now.
+    fn hold(&mut self, event: CoreEvent) -> Option<CoreEvent> {
+        if self.subscribed {
+            return Some(event);
+        }
+        if self.pending.len() == EVENT_BUFFER_CAP {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(event);
+        None
+    }
+
+    /// Mark the frontend subscribed and take the held events, oldest first.
+    fn subscribe(&mut self) -> Vec<CoreEvent> {
+        self.subscribed = true;
+        self.pending.drain(..).collect()
+    }
+}
 
 /// Global application state shared across Tauri.
+#[derive(Clone)]
 pub struct AppState {
     pub auth: Arc<Mutex<AuthManager>>,
     pub vk_client: Arc<Mutex<Option<Arc<VkClient>>>>,
     pub command_tx: Arc<Mutex<Option<mpsc::UnboundedSender<AsyncCommand>>>>,
+    pub event_tx: Arc<Mutex<Option<mpsc::UnboundedSender<CoreEvent>>>>,
     pub tray_icon: Arc<Mutex<Option<TrayIcon<tauri::Wry>>>>,
     pub unread_count: Arc<Mutex<u32>>,
+    pub health: Arc<Mutex<SessionHealth>>,
+    pub visibility: Arc<Mutex<Visibility>>,
+    /// Local catcher of the OAuth redirect, between starting and waiting
+    pub redirect_listener: Arc<Mutex<Option<RedirectListener>>>,
+    /// Stops the running history export
+    pub history_export: Arc<Mutex<Option<watch::Sender<bool>>>>,
+    /// Settings shared with the other frontends, `[tauri]` section included
+    pub settings: Arc<Mutex<Settings>>,
+    /// Core events held until `subscribe_events`
+    pub event_buffer: Arc<Mutex<EventBuffer>>,
+    /// Chats and messages kept between runs, if the cache is built in
+    pub cache: Option<Arc<dyn CacheLayer>>,
+    long_poll_shutdown: Arc<Mutex<Option<watch::Sender<bool>>>>,
+    resume_task: Arc<Mutex<Option<JoinHandle<()>>>>,
 }
 
 impl AppState {
     pub fn new() -> Self {
+        let (settings, mut problems) = Settings::load();
+        AppSettings::from_settings(&settings, &mut problems);
+        // Shown once the frontend subscribes; the defaults are used meanwhile
+        let mut event_buffer = EventBuffer::default();
+        for problem in problems {
+            tracing::warn!("Settings: {}", problem);
+            event_buffer.hold(CoreEvent::error(format!("Settings: {}", problem)));
+        }
+
         Self {
             auth: Arc::new(Mutex::new(AuthManager::default())),
             vk_client: Arc::new(Mutex::new(None)),
             command_tx: Arc::new(Mutex::new(None)),
+            event_tx: Arc::new(Mutex::new(None)),
             tray_icon: Arc::new(Mutex::new(None)),
             unread_count: Arc::new(Mutex::new(0)),
+            health: Arc::new(Mutex::new(SessionHealth::new())),
+            visibility: Arc::new(Mutex::new(Visibility::Visible)),
+            redirect_listener: Arc::new(Mutex::new(None)),
+            history_export: Arc::new(Mutex::new(None)),
+            settings: Arc::new(Mutex::new(settings)),
+            event_buffer: Arc::new(Mutex::new(event_buffer)),
+            cache: vk_core::cache::open_default(),
+            long_poll_shutdown: Arc::new(Mutex::new(None)),
+            resume_task: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -32,7 +242,16 @@ impl AppState {
         app_handle: AppHandle,
         token: String,
     ) -> Result<(), String> {
-        let client = Arc::new(VkClient::new(token));
+        let client = self
+            .settings
+            .lock()
+            .await
+            .client(token)
+            .map_err(|e| e.to_string())?;
+        let client = Arc::new(client);
+
+        // "Cannot reach api.vk.com via proxy ..." rather than a failed call
+        client.ping().await.map_err(|e| e.to_string())?;
 
         // Validate session
         client
@@ -48,14 +267,34 @@ impl AppState {
         // Store in state
         *self.vk_client.lock().await = Some(client.clone());
         *self.command_tx.lock().await = Some(cmd_tx);
+        *self.event_tx.lock().await = Some(event_tx.clone());
         let emit_handle = app_handle.clone();
         let notification_handle = app_handle.clone();
         let tray_icon = self.tray_icon.clone();
         let unread_count = self.unread_count.clone();
+        let health = self.health.clone();
+        let event_buffer = self.event_buffer.clone();
+        let settings = self.settings.clone();
+        let cache = self.cache.clone();
         tokio::spawn(async move {
+            // Chats with notifications off
+            let mut muted: HashSet<i64> = HashSet::new();
             while let Some(event) = event_rx.recv().await {
+                if let (Some(cache), CoreEvent::VkEvent(vk_event)) = (&cache, &event) {
+                    cache.apply_event(vk_event);
+                }
+
+                if let CoreEvent::VkEvent(VkEvent::ConnectionStatus(connected)) = &event {
+                    health.lock().await.connection = if *connected {
+                        ConnectionState::Connected
+                    } else {
+                        ConnectionState::Disconnected
+                    };
+                }
+
                 // Send notification for new incoming messages
                 if let CoreEvent::VkEvent(vk_core::VkEvent::NewMessage {
+                    peer_id,
                     text,
                     from_id,
                     is_outgoing,
@@ -64,8 +303,8 @@ impl AppState {
                 {
                     use tauri_plugin_notification::NotificationExt;
 
-                    // Only notify for incoming messages
-                    if !is_outgoing {
+                    // Only notify for incoming messages in chats that are not muted
+                    if !is_outgoing && !muted.contains(peer_id) {
                         let title = if *from_id > 0 {
                             format!("Новое сообщение от пользователя {}", from_id)
                         } else {
@@ -86,9 +325,29 @@ impl AppState {
                     }
                 }
 
+                if let CoreEvent::SettingsChanged(changed) = &event {
+                    *settings.lock().await = (**changed).clone();
+                }
+
+                // Keep track of muted chats so notifications skip them
+                if let CoreEvent::ChatMuteChanged { peer_id, muted: is_muted } = &event {
+                    if *is_muted {
+                        muted.insert(*peer_id);
+                    } else {
+                        muted.remove(peer_id);
+                    }
+                }
+
                 // Update tray tooltip when conversations are loaded
                 if let CoreEvent::ConversationsLoaded { chats, .. } = &event {
-                    let total_unread: u32 = chats.iter().map(|c| c.unread_count).sum();
+                    for chat in chats {
+                        if chat.is_muted {
+                            muted.insert(chat.id);
+                        } else {
+                            muted.remove(&chat.id);
+                        }
+                    }
+                    let total_unread = vk_core::total_unread(chats);
                     if let Some(tray) = tray_icon.lock().await.as_ref() {
                         let tooltip = if total_unread > 0 {
                             format!("VK Messenger ({} непрочитанных)", total_unread)
@@ -100,26 +359,192 @@ impl AppState {
                     *unread_count.lock().await = total_unread;
                 }
 
-                let _ = emit_handle.emit("core:event", event);
+                // Emitted under the lock, so a replay in `subscribe_events`
+                // cannot overtake it
+                let mut buffer = event_buffer.lock().await;
+                if let Some(event) = buffer.hold(event) {
+                    let _ = emit_handle.emit("core:event", event);
+                }
             }
         });
 
         // Spawn command executor
-        let executor = CommandExecutor::new(client.clone(), event_tx.clone());
+        let executor = CommandExecutor::new(client.clone(), event_tx.clone(), self.cache.clone())
+            .with_settings(self.settings.lock().await.clone());
         tokio::spawn(async move {
             while let Some(cmd) = cmd_rx.recv().await {
                 executor.execute(cmd).await;
             }
         });
 
-        // Spawn LongPoll
-        tokio::spawn(async move {
-            Self::run_long_poll(client, event_tx).await;
-        });
+        self.start_long_poll(client, event_tx).await;
+        self.start_resume_detector().await;
 
         Ok(())
     }
 
+    /// (Re)start the LongPoll listener, stopping the previous one.
+    async fn start_long_poll(&self, client: Arc<VkClient>, event_tx: mpsc::UnboundedSender<CoreEvent>) {
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        // Replacing the sender stops the old loop
+        *self.long_poll_shutdown.lock().await = Some(shutdown_tx);
+
+        *self.health.lock().await = SessionHealth {
+            connection: ConnectionState::Connecting,
+            ..SessionHealth::new()
+        };
+        let source = TrackedSource {
+            client,
+            health: self.health.clone(),
+            visibility: self.visibility.clone(),
+        };
+        tokio::spawn(vk_core::longpoll::run_with(
+            source,
+            RetryPolicy::default(),
+            event_tx,
+            shutdown_rx,
+            Arc::default(),
+        ));
+    }
+
+    /// Watch for wall-clock jumps (suspend/resume) and revalidate the session.
+    ///
+    /// The monotonic clock stops while the system sleeps, the wall clock
+    /// does not, so a gap between them means we were suspended.
+    async fn start_resume_detector(&self) {
+        let mut task = self.resume_task.lock().await;
+        if let Some(old) = task.take() {
+            old.abort();
+        }
+
+        let state = self.clone();
+        *task = Some(tokio::spawn(async move {
+            loop {
+                let wall_start = SystemTime::now();
+                let mono_start = Instant::now();
+                tokio::time::sleep(RESUME_CHECK_INTERVAL).await;
+
+                let wall_elapsed = wall_start.elapsed().unwrap_or_default();
+                if wall_elapsed > mono_start.elapsed() + RESUME_JUMP_THRESHOLD {
+                    tracing::info!("Resume detected ({}s wall-clock jump)", wall_elapsed.as_secs());
+                    state.revalidate(true).await;
+                }
+            }
+        }));
+    }
+
+    /// Drop the session and stop its background tasks.
+    pub async fn stop_session(&self) {
+        *self.vk_client.lock().await = None;
+        *self.command_tx.lock().await = None;
+        *self.event_tx.lock().await = None;
+        *self.health.lock().await = SessionHealth::new();
+        *self.long_poll_shutdown.lock().await = None;
+        *self.history_export.lock().await = None;
+        // The next main view subscribes again
+        *self.event_buffer.lock().await = EventBuffer::default();
+        // Last: this may be called from the resume detector itself
+        if let Some(task) = self.resume_task.lock().await.take() {
+            task.abort();
+        }
+    }
+
+    /// Start emitting core events to the webview, first the ones held since
+    /// the session started. Calling it again only keeps emitting.
+    pub async fn subscribe_events(&self, app_handle: &AppHandle) {
+        let mut buffer = self.event_buffer.lock().await;
+        let held = buffer.subscribe();
+        if !held.is_empty() {
+            tracing::debug!("Replaying {} core events", held.len());
+        }
+        for event in held {
+            let _ = app_handle.emit("core:event", event);
+        }
+    }
+
+    /// Current session health snapshot.
+    pub async fn health_report(&self) -> HealthReport {
+        let auth = self.auth.lock().await;
+        let has_session = self.vk_client.lock().await.is_some();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+        let health = self.health.lock().await;
+
+        HealthReport {
+            authenticated: has_session && auth.is_authenticated() && !auth.is_token_expired(),
+            connection: health.connection,
+            last_event_age_secs: health.last_event_at.map(|at| at.elapsed().as_secs()),
+            token_expires_in: auth.expires_at().map(|at| at - now),
+            visibility: *self.visibility.lock().await,
+            long_poll_wait_secs: health.poll_wait.as_secs(),
+        }
+    }
+
+    /// Switch between the normal and the background mode.
+    ///
+    /// Hidden, the LongPoll waits longer between idle answers; notifications
+    /// are unaffected. Shown again, a stale connection is restarted and the
+    /// chat list reloaded, so the window is current once the user sees it.
+    pub async fn set_visibility(&self, visibility: Visibility) {
+        let previous = std::mem::replace(&mut *self.visibility.lock().await, visibility);
+        if previous == visibility {
+            return;
+        }
+        tracing::info!("Window {:?}", visibility);
+
+        if visibility == Visibility::Visible {
+            if self.is_stale().await {
+                self.revalidate(false).await;
+            }
+            if let Some(tx) = self.command_tx.lock().await.as_ref() {
+                let _ = tx.send(AsyncCommand::LoadConversations { offset: 0 });
+            }
+        }
+    }
+
+    /// Whether the LongPoll connection looks dead.
+    pub async fn is_stale(&self) -> bool {
+        self.health.lock().await.is_stale()
+    }
+
+    /// Check the token with `users.get` and restart the LongPoll if needed.
+    ///
+    /// On an auth failure the session is torn down and `AuthExpired` is
+    /// emitted so the frontend asks the user to log in again.
+    pub async fn revalidate(&self, force_restart: bool) {
+        let Some(client) = self.vk_client.lock().await.clone() else {
+            return;
+        };
+        let Some(event_tx) = self.event_tx.lock().await.clone() else {
+            return;
+        };
+
+        let auth_failed = match client.users().get(&[], &[]).await {
+            Ok(_) => false,
+            Err(e) if e.is_auth() => true,
+            Err(e) => {
+                // Network may still be coming up; the LongPoll backoff handles it
+                tracing::warn!("Session revalidation failed: {}", e);
+                false
+            }
+        };
+
+        if auth_failed {
+            tracing::warn!("Session expired, asking to log in again");
+            let _ = event_tx.send(CoreEvent::AuthExpired);
+            self.stop_session().await;
+            return;
+        }
+
+        if force_restart || self.is_stale().await {
+            tracing::info!("Restarting stale LongPoll");
+            let _ = event_tx.send(CoreEvent::VkEvent(VkEvent::ConnectionStatus(false)));
+            self.start_long_poll(client, event_tx).await;
+        }
+    }
+
     /// Persist token from OAuth redirect and initialize session.
     pub async fn login_from_redirect(
         &self,
@@ -134,83 +559,25 @@ impl AppState {
             .access_token()
             .ok_or_else(|| "Token not found after save".to_string())?
             .to_string();
+
+        // Refuse a token without message access now rather than with
+        // "Access denied" on every chat
+        let client = self
+            .settings
+            .lock()
+            .await
+            .client(token.clone())
+            .map_err(|e| e.to_string())?;
+        if let Err(e) = vk_api::auth::validate_scopes(&client).await {
+            let _ = auth.logout();
+            return Err(e.to_string());
+        }
         drop(auth);
 
         self.initialize_session(app_handle, token).await
     }
 
 
-    /// Run VK LongPoll listener.
-    async fn run_long_poll(client: Arc<VkClient>, event_tx: mpsc::UnboundedSender<CoreEvent>) {
-        tracing::info!("Starting LongPoll...");
-        let mut backoff = std::time::Duration::from_secs(1);
-
-        let mut server = match client.longpoll().get_server().await {
-            Ok(s) => {
-                tracing::info!("Got LongPoll server: {}", s.server);
-                let _ = event_tx.send(CoreEvent::VkEvent(vk_core::VkEvent::ConnectionStatus(true)));
-                s
-            }
-            Err(e) => {
-                let _ = event_tx.send(CoreEvent::Error(format!("LongPoll error: {}", e)));
-                return;
-            }
-        };
-
-        loop {
-            match client.longpoll().poll(&server).await {
-                Ok(response) => {
-                    if let Some(failed) = response.failed {
-                        match failed {
-                            1 => {
-                                if let Some(ts) = response.ts {
-                                    server.ts = ts;
-                                }
-                            }
-                            2..=4 => match client.longpoll().get_server().await {
-                                Ok(new_server) => server = new_server,
-                                Err(e) => {
-                                    let _ = event_tx.send(CoreEvent::Error(format!("LongPoll error: {}", e)));
-                                    tokio::time::sleep(std::time::Duration::from_secs(5)).await;
-                                }
-                            },
-                            _ => {}
-                        }
-                        continue;
-                    }
-
-                    if let Some(ts) = response.ts {
-                        server.ts = ts;
-                    }
-
-                    if let Some(updates) = response.updates {
-                        for update in updates {
-                            if let Some(event) = vk_core::longpoll::handle_update(&update) {
-                                let _ = event_tx.send(CoreEvent::VkEvent(event));
-                            }
-                        }
-                    }
-                    backoff = std::time::Duration::from_secs(1);
-                }
-                Err(e) => {
-                    let _ = event_tx.send(CoreEvent::VkEvent(vk_core::VkEvent::ConnectionStatus(false)));
-                    let _ = event_tx.send(CoreEvent::Error(format!("LongPoll error: {}", e)));
-                    tokio::time::sleep(backoff).await;
-                    backoff = (backoff * 2).min(std::time::Duration::from_secs(30));
-
-                    match client.longpoll().get_server().await {
-                        Ok(new_server) => {
-                            server = new_server;
-                            let _ = event_tx.send(CoreEvent::VkEvent(vk_core::VkEvent::ConnectionStatus(true)));
-                            backoff = std::time::Duration::from_secs(1);
-                        }
-                        Err(_) => continue,
-                    }
-                }
-            }
-        }
-    }
-
     /// Update tray icon tooltip with unread count
     pub async fn update_tray_tooltip(&self, unread: u32) {
         if let Some(tray) = self.tray_icon.lock().await.as_ref() {
@@ -0,0 +1,199 @@
+//! Mock VK API server for the integration tests of the workspace crates.
+//!
+//! [`MockVk`] listens on localhost and answers each API method, and the
+//! Long Poll path [`LONG_POLL_PATH`], with canned JSON: the bodies queued
+//! for the method in order, the last one repeating. Fixtures with
+//! realistic responses live in `fixtures` next to this crate.
+//!
+//! ```ignore
+//! use vk_test_support::{MockVk, fixture};
+//!
+//! let mock = MockVk::start().await;
+//! mock.respond("messages.getHistory", fixture("get_history"));
+//! let history = mock.client().messages().get_history(1, 0, 20).await?;
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use vk_api::{SchemaMode, VkClient};
+
+/// Path the mock serves Long Poll on, given out by [`MockVk::long_poll_server`].
+pub const LONG_POLL_PATH: &str = "lp";
+
+/// A request the mock received.
+#[derive(Debug, Clone)]
+pub struct Call {
+    /// API method, or [`LONG_POLL_PATH`]
+    pub method: String,
+    /// Query string and form body, as sent
+    pub params: String,
+}
+
+impl Call {
+    /// Value of parameter `name`, still URL-encoded
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .split(['&', '?'])
+            .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+    }
+}
+
+/// Mock VK API on a localhost port.
+pub struct MockVk {
+    addr: std::net::SocketAddr,
+    responses: Arc<Mutex<HashMap<String, VecDeque<String>>>>,
+    calls: Arc<Mutex<Vec<Call>>>,
+}
+
+impl MockVk {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let responses: Arc<Mutex<HashMap<String, VecDeque<String>>>> = Arc::default();
+        let calls: Arc<Mutex<Vec<Call>>> = Arc::default();
+
+        let (routes, recorded) = (responses.clone(), calls.clone());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let call = read_call(&mut socket).await;
+                let body = {
+                    let mut routes = routes.lock().unwrap();
+                    match routes.get_mut(&call.method) {
+                        Some(queue) if queue.len() > 1 => queue.pop_front().unwrap(),
+                        Some(queue) => queue[0].clone(),
+                        None => unknown_method(&call.method),
+                    }
+                };
+                recorded.lock().unwrap().push(call);
+
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+
+        Self {
+            addr,
+            responses,
+            calls,
+        }
+    }
+
+    /// Queue `body` as the next answer to `method`
+    pub fn respond(&self, method: &str, body: impl Into<String>) -> &Self {
+        self.responses
+            .lock()
+            .unwrap()
+            .entry(method.to_string())
+            .or_default()
+            .push_back(body.into());
+        self
+    }
+
+    /// Queue a VK `error` object as the next answer to `method`
+    pub fn respond_error(&self, method: &str, code: i64, message: &str) -> &Self {
+        self.respond(method, error_body(code, message))
+    }
+
+    /// Base URL to give [`VkClientBuilder::api_url`](vk_api::VkClientBuilder::api_url)
+    pub fn api_url(&self) -> String {
+        format!("http://{}/method", self.addr)
+    }
+
+    /// Long Poll server address, for the `server` of a
+    /// `messages.getLongPollServer` answer
+    pub fn long_poll_server(&self) -> String {
+        format!("http://{}/{}", self.addr, LONG_POLL_PATH)
+    }
+
+    /// Client of the mock: no throttling, quick retries and strict schema
+    /// checks, so a fixture field the types miss fails the test
+    pub fn client(&self) -> VkClient {
+        VkClient::builder("test-token")
+            .api_url(self.api_url())
+            .no_proxy()
+            .max_requests_per_second(0)
+            .retry_backoff(Duration::from_millis(1))
+            .schema_mode(SchemaMode::Strict)
+            .build()
+    }
+
+    /// Requests received so far
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Requests received so far for `method`
+    pub fn calls_to(&self, method: &str) -> Vec<Call> {
+        self.calls()
+            .into_iter()
+            .filter(|call| call.method == method)
+            .collect()
+    }
+}
+
+/// Contents of `fixtures/<name>.json`
+pub fn fixture(name: &str) -> String {
+    let path = format!("{}/fixtures/{}.json", env!("CARGO_MANIFEST_DIR"), name);
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("Cannot read {}: {}", path, e))
+}
+
+/// A VK `error` response
+pub fn error_body(code: i64, message: &str) -> String {
+    serde_json::json!({
+        "error": {
+            "error_code": code,
+            "error_msg": message,
+            "request_params": [{ "key": "v", "value": vk_api::API_VERSION }],
+        }
+    })
+    .to_string()
+}
+
+fn unknown_method(method: &str) -> String {
+    error_body(3, &format!("Unknown method passed: {}", method))
+}
+
+/// Read a whole request: method from the path, then query and form body
+async fn read_call(socket: &mut tokio::net::TcpStream) -> Call {
+    let mut buf = Vec::new();
+    let mut body_start = 0;
+    let mut chunk = [0u8; 1024];
+    loop {
+        let n = socket.read(&mut chunk).await.unwrap_or(0);
+        if n == 0 {
+            break;
+        }
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            let headers = String::from_utf8_lossy(&buf[..pos]).to_lowercase();
+            let content_length = headers
+                .lines()
+                .find_map(|l| l.strip_prefix("content-length:"))
+                .and_then(|v| v.trim().parse::<usize>().ok())
+                .unwrap_or(0);
+            if buf.len() >= pos + 4 + content_length {
+                body_start = pos + 4;
+                break;
+            }
+        }
+    }
+
+    let request = String::from_utf8_lossy(&buf);
+    let target = request.split_whitespace().nth(1).unwrap_or_default();
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let method = path.rsplit('/').next().unwrap_or_default().to_string();
+    let body = String::from_utf8_lossy(&buf[body_start..]);
+    Call {
+        method,
+        params: [query, &body].join("&"),
+    }
+}
@@ -1,22 +1,43 @@
 //! Async action runners (VK API calls) extracted from main.rs for clarity.
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
 
-use tokio::sync::mpsc;
-use vk_api::VkClient;
+use tokio::sync::{mpsc, watch};
+use vk_api::auth::AuthManager;
+use vk_api::{BASIC_USER_FIELDS, CHAT_PEER_OFFSET, UploadProgress, VkClient};
+use vk_core::batch::demux;
+use vk_core::cache::CacheLayer;
+use vk_core::download;
+use vk_core::edit::{EditCheck, check_edit};
+use vk_core::export::{self, ExportFormat};
+use vk_core::grep::{self, GrepPattern};
+use vk_core::profiles::{DETAIL_FIELDS, GROUP_DETAIL_FIELDS};
+use vk_core::upload::{self, UploadKind};
+use vk_core::{CHAT_MEMBERS_PAGE, MuteDuration};
 
 use crate::mapper::map_forward_tree;
-use crate::mapper::{map_attachment, map_history_message, map_reply};
+use crate::mapper::{
+    conversation_kind, conversation_last_seen, conversation_muted, conversation_photo,
+    conversation_write_access, map_attachment, map_chat_members, map_group_profile,
+    map_history_message, map_reactions, map_read_peers, map_reply, map_user_profile, message_kind,
+    message_preview, message_sender,
+};
 use crate::message::Message;
-use crate::state::AttachmentInfo;
+use crate::state::{AttachmentInfo, CaptchaRetry, ChatMessage};
 
 pub async fn validate_session(client: Arc<VkClient>, tx: mpsc::UnboundedSender<Message>) {
     match client.account().get_profile_info().await {
-        Ok(_) => {
+        Ok(profile) => {
             let _ = tx.send(Message::SessionValidated {
                 valid: true,
                 error: None,
             });
+            let _ = tx.send(Message::ProfileLoaded(profile));
+        }
+        Err(e) if e.is_auth() => {
+            let _ = tx.send(Message::AuthExpired);
         }
         Err(e) => {
             let _ = tx.send(Message::SessionValidated {
@@ -27,38 +48,110 @@ pub async fn validate_session(client: Arc<VkClient>, tx: mpsc::UnboundedSender<M
     }
 }
 
+/// Check that VK is reachable and a pasted token has the scopes the client
+/// needs
+pub async fn validate_token(client: Arc<VkClient>, tx: mpsc::UnboundedSender<Message>) {
+    let result = match client.ping().await {
+        Ok(()) => vk_api::auth::validate_scopes(&client)
+            .await
+            .map_err(|e| match e {
+                vk_api::Error::MissingScopes(_) => e.to_string(),
+                e => format!("Token check failed: {}", e),
+            }),
+        Err(e) => Err(e.to_string()),
+    };
+    let _ = tx.send(Message::TokenChecked(result));
+}
+
+/// Catch the OAuth redirect on `port`, opening the auth URL in the browser
+pub async fn wait_for_redirect(
+    port: u16,
+    mut cancel: watch::Receiver<bool>,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    /// How long the browser gets to log in
+    const TIMEOUT: Duration = Duration::from_secs(300);
+
+    let listener = match AuthManager::start_redirect_listener(port).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            let _ = tx.send(Message::RedirectCaught(Err(e.to_string())));
+            return;
+        }
+    };
+    if let Err(e) = open::that(listener.auth_url()) {
+        let _ = tx.send(Message::RedirectCaught(Err(format!(
+            "Failed to open browser: {}",
+            e
+        ))));
+        return;
+    }
+
+    tokio::select! {
+        result = listener.wait(TIMEOUT) => {
+            let _ = tx.send(Message::RedirectCaught(result.map_err(|e| e.to_string())));
+        }
+        // Sent on Esc; the sender is dropped on logout
+        _ = cancel.changed() => {}
+    }
+}
+
+/// Load the profile of the logged-in account
+pub async fn load_profile(client: Arc<VkClient>, tx: mpsc::UnboundedSender<Message>) {
+    match client.account().get_profile_info().await {
+        Ok(profile) => {
+            let _ = tx.send(Message::ProfileLoaded(profile));
+        }
+        Err(e) => {
+            let _ = tx.send(api_error("Failed to load account profile", e));
+        }
+    }
+}
+
+/// Build an error message for a failed API call; auth failures end the session.
+pub fn api_error(context: &str, e: vk_api::Error) -> Message {
+    if e.is_auth() {
+        Message::AuthExpired
+    } else {
+        Message::Error(format!("{}: {}", context, e))
+    }
+}
+
+/// Same as [`api_error`], but for sending/editing/deleting messages.
+fn send_failed(context: &str, e: vk_api::Error) -> Message {
+    if e.is_auth() {
+        Message::AuthExpired
+    } else {
+        Message::SendFailed(format!("{}: {}", context, e))
+    }
+}
+
+/// Like [`send_failed`], but a captcha asks the user to solve it and then
+/// sends again with `retry`
+fn send_failed_or_captcha(context: &str, e: vk_api::Error, retry: CaptchaRetry) -> Message {
+    match e {
+        vk_api::Error::Captcha { sid, img_url } => Message::CaptchaRequired {
+            sid,
+            img_url,
+            retry,
+        },
+        e => send_failed(context, e),
+    }
+}
+
 pub async fn load_conversations(
     client: Arc<VkClient>,
     offset: u32,
+    count: u32,
     tx: mpsc::UnboundedSender<Message>,
 ) {
-    const COUNT: u32 = 50;
-
-    match client.messages().get_conversations(offset, COUNT).await {
+    match client.messages().get_conversations(offset, count).await {
         Ok(response) => {
-            let total_count = response.count as u32;
+            let total_count = response.count;
             let loaded_count = response.items.len() as u32;
             let has_more = offset + loaded_count < total_count;
 
-            let chats: Vec<crate::state::Chat> = response
-                .items
-                .into_iter()
-                .map(|item| {
-                    let title = super::get_conversation_title(&item, &response.profiles);
-                    let is_online =
-                        super::get_user_online(&item.conversation.peer.id, &response.profiles);
-
-                    crate::state::Chat {
-                        id: item.conversation.peer.id,
-                        title,
-                        last_message: item.last_message.text.clone(),
-                        last_message_time: item.last_message.date,
-                        unread_count: item.conversation.unread_count.unwrap_or(0),
-                        is_online,
-                    }
-                })
-                .collect();
-
+            let chats = conversation_chats(&response);
             let _ = tx.send(Message::ConversationsLoaded {
                 chats,
                 profiles: response.profiles,
@@ -67,22 +160,62 @@ pub async fn load_conversations(
             });
         }
         Err(e) => {
-            let _ = tx.send(Message::Error(format!("Failed to load chats: {}", e)));
+            let _ = tx.send(api_error("Failed to load chats", e));
         }
     }
 }
 
+/// Chat list entries of a getConversations page
+pub fn conversation_chats(response: &vk_api::ConversationsResponse) -> Vec<crate::state::Chat> {
+    let mut chats: Vec<crate::state::Chat> = response
+        .items
+        .iter()
+        .map(|item| {
+            let title = super::get_conversation_title(item, &response.profiles);
+            let is_online = super::get_user_online(&item.conversation.peer.id, &response.profiles);
+            let (can_write, cannot_write_reason) = conversation_write_access(item);
+
+            crate::state::Chat {
+                id: item.conversation.peer.id,
+                title,
+                last_message: message_preview(
+                    &item.last_message,
+                    &response.profiles,
+                    &response.groups,
+                ),
+                last_message_from: message_sender(
+                    &item.last_message,
+                    &response.profiles,
+                    &response.groups,
+                ),
+                last_message_kind: message_kind(&item.last_message),
+                last_message_time: item.last_message.date,
+                unread_count: item.conversation.unread_count.unwrap_or(0),
+                is_online,
+                photo_url: conversation_photo(item, &response.profiles, &response.groups),
+                is_muted: conversation_muted(item),
+                is_pinned: item.conversation.is_pinned(),
+                can_write,
+                cannot_write_reason,
+                kind: conversation_kind(item),
+                last_seen: conversation_last_seen(item, &response.profiles),
+            }
+        })
+        .collect();
+    vk_core::fill_missing_times(&mut chats);
+    chats
+}
+
 pub async fn load_messages(
     client: Arc<VkClient>,
     peer_id: i64,
     offset: u32,
+    count: u32,
     tx: mpsc::UnboundedSender<Message>,
 ) {
-    const COUNT: u32 = 50;
-
-    match client.messages().get_history(peer_id, offset, COUNT).await {
+    match client.messages().get_history(peer_id, offset, count).await {
         Ok(response) => {
-            let total_count = response.count as u32;
+            let total_count = response.count;
             let loaded_count = response.items.len() as u32;
             let has_more = offset + loaded_count < total_count;
 
@@ -108,7 +241,7 @@ pub async fn load_messages(
             });
         }
         Err(e) => {
-            let _ = tx.send(Message::Error(format!("Failed to load messages: {}", e)));
+            let _ = tx.send(api_error("Failed to load messages", e));
         }
     }
 }
@@ -118,17 +251,16 @@ pub async fn load_messages_around(
     client: Arc<VkClient>,
     peer_id: i64,
     message_id: i64,
+    count: u32,
     tx: mpsc::UnboundedSender<Message>,
 ) {
-    const COUNT: u32 = 50;
-
     match client
         .messages()
-        .get_history_around(peer_id, message_id, COUNT)
+        .get_history_around(peer_id, message_id, count)
         .await
     {
         Ok(response) => {
-            let total_count = response.count as u32;
+            let total_count = response.count;
             let has_more = true; // Always has more when loading around a message
 
             let out_read = response
@@ -153,10 +285,7 @@ pub async fn load_messages_around(
             });
         }
         Err(e) => {
-            let _ = tx.send(Message::Error(format!(
-                "Failed to load messages around target: {}",
-                e
-            )));
+            let _ = tx.send(api_error("Failed to load messages around target", e));
         }
     }
 }
@@ -177,7 +306,7 @@ pub async fn load_messages_with_offset(
         .await
     {
         Ok(response) => {
-            let total_count = response.count as u32;
+            let total_count = response.count;
             let loaded_count = response.items.len() as u32;
             let has_more = loaded_count == count; // Has more if we got full page
 
@@ -203,7 +332,7 @@ pub async fn load_messages_with_offset(
             });
         }
         Err(e) => {
-            let _ = tx.send(Message::Error(format!("Failed to load messages: {}", e)));
+            let _ = tx.send(api_error("Failed to load messages", e));
         }
     }
 }
@@ -219,13 +348,60 @@ pub async fn send_message(
             let _ = tx.send(Message::MessageSent(
                 sent.message_id,
                 sent.conversation_message_id,
+                sent.random_id,
             ));
         }
         Err(e) => {
-            let _ = tx.send(Message::SendFailed(format!(
-                "Failed to send message: {}",
-                e
-            )));
+            let _ = tx.send(send_failed_or_captcha(
+                "Failed to send message",
+                e,
+                CaptchaRetry::Message(peer_id, text),
+            ));
+        }
+    }
+}
+
+/// Send the parts of a long text in order, the first one answering
+/// `reply_to` with the `random_id` of its pending entry. Stops at the first
+/// part that fails.
+pub async fn send_parts(
+    client: Arc<VkClient>,
+    peer_id: i64,
+    reply_to: Option<(i64, i64)>,
+    parts: Vec<String>,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    let count = parts.len();
+    for (idx, text) in parts.into_iter().enumerate() {
+        let reply_to = reply_to.filter(|_| idx == 0);
+        let result = match reply_to {
+            Some((reply_to, random_id)) => {
+                client
+                    .messages()
+                    .send_with_random_id(peer_id, random_id, &text, Some(reply_to), &[])
+                    .await
+            }
+            None => client.messages().send(peer_id, &text).await,
+        };
+        match result {
+            Ok(sent) => {
+                let _ = tx.send(Message::MessageSent(
+                    sent.message_id,
+                    sent.conversation_message_id,
+                    sent.random_id,
+                ));
+            }
+            Err(e) => {
+                let retry = match reply_to {
+                    Some((reply_to, random_id)) => {
+                        CaptchaRetry::Reply(peer_id, reply_to, text, random_id)
+                    }
+                    None => CaptchaRetry::Message(peer_id, text),
+                };
+                let context = format!("Failed to send part {} of {}", idx + 1, count);
+                let _ = tx.send(send_failed_or_captcha(&context, e, retry));
+                return;
+            }
         }
     }
 }
@@ -246,13 +422,15 @@ pub async fn send_forward(
             let _ = tx.send(Message::MessageSent(
                 sent.message_id,
                 sent.conversation_message_id,
+                sent.random_id,
             ));
         }
         Err(e) => {
-            let _ = tx.send(Message::SendFailed(format!(
-                "Failed to forward message: {}",
-                e
-            )));
+            let _ = tx.send(send_failed_or_captcha(
+                "Failed to forward message",
+                e,
+                CaptchaRetry::Forward(peer_id, message_ids, comment),
+            ));
         }
     }
 }
@@ -262,65 +440,92 @@ pub async fn send_reply(
     peer_id: i64,
     reply_to: i64,
     text: String,
+    random_id: i64,
     tx: mpsc::UnboundedSender<Message>,
 ) {
     match client
         .messages()
-        .send_with_reply(peer_id, &text, reply_to)
+        .send_with_random_id(peer_id, random_id, &text, Some(reply_to), &[])
         .await
     {
         Ok(sent) => {
             let _ = tx.send(Message::MessageSent(
                 sent.message_id,
                 sent.conversation_message_id,
+                sent.random_id,
             ));
         }
         Err(e) => {
-            let _ = tx.send(Message::SendFailed(format!("Failed to send reply: {}", e)));
+            let _ = tx.send(send_failed_or_captcha(
+                "Failed to send reply",
+                e,
+                CaptchaRetry::Reply(peer_id, reply_to, text, random_id),
+            ));
         }
     }
 }
 
-pub async fn send_photo_attachment(
+/// Upload `paths` and send whatever uploaded in one message
+pub async fn send_files(
     client: Arc<VkClient>,
     peer_id: i64,
-    path: String,
+    kind: UploadKind,
+    paths: Vec<String>,
+    local_id: u64,
+    random_id: i64,
     tx: mpsc::UnboundedSender<Message>,
 ) {
+    let paths: Vec<PathBuf> = paths.into_iter().map(PathBuf::from).collect();
+    let progress = upload_progress(local_id, tx.clone());
+    let batch = upload::upload_files(&client, peer_id, kind, &paths, progress).await;
+
+    if batch.auth_failed() {
+        let _ = tx.send(Message::AuthExpired);
+        return;
+    }
+    if batch.attachments.is_empty() {
+        let _ = tx.send(Message::UploadFailed(local_id, batch.failure_text()));
+        return;
+    }
+    if !batch.failed.is_empty() {
+        let _ = tx.send(Message::UploadPartlyFailed(
+            local_id,
+            batch.failed_names(),
+            batch.failure_text(),
+        ));
+    }
+
+    let context = match kind {
+        UploadKind::Photo => "Failed to send photo",
+        UploadKind::Doc => "Failed to send file",
+    };
     match client
         .messages()
-        .send_photo(peer_id, Path::new(&path))
+        .send_with_random_id(peer_id, random_id, "", None, &batch.attachments)
         .await
     {
         Ok(sent) => {
             let _ = tx.send(Message::MessageSent(
                 sent.message_id,
                 sent.conversation_message_id,
+                sent.random_id,
             ));
         }
         Err(e) => {
-            let _ = tx.send(Message::SendFailed(format!("Failed to send photo: {}", e)));
+            let _ = tx.send(send_failed(context, e));
         }
     }
 }
 
-pub async fn send_doc_attachment(
-    client: Arc<VkClient>,
-    peer_id: i64,
-    path: String,
-    tx: mpsc::UnboundedSender<Message>,
-) {
-    match client.messages().send_doc(peer_id, Path::new(&path)).await {
-        Ok(sent) => {
-            let _ = tx.send(Message::MessageSent(
-                sent.message_id,
-                sent.conversation_message_id,
-            ));
+/// Progress callback sending [`Message::UploadProgress`] once per percent
+fn upload_progress(local_id: u64, tx: mpsc::UnboundedSender<Message>) -> UploadProgress {
+    let last_percent = AtomicU64::new(u64::MAX);
+    Arc::new(move |bytes_sent, total| {
+        let percent = bytes_sent * 100 / total.max(1);
+        if last_percent.swap(percent, Ordering::Relaxed) != percent {
+            let _ = tx.send(Message::UploadProgress(local_id, bytes_sent, total));
         }
-        Err(e) => {
-            let _ = tx.send(Message::SendFailed(format!("Failed to send file: {}", e)));
-        }
-    }
+    })
 }
 
 pub async fn edit_message(
@@ -329,21 +534,34 @@ pub async fn edit_message(
     message_id: i64,
     cmid: Option<i64>,
     text: String,
+    base_hash: Option<u64>,
     tx: mpsc::UnboundedSender<Message>,
 ) {
+    let attachments = match check_edit(&client, message_id, base_hash).await {
+        Ok(EditCheck::Ready { attachments }) => attachments,
+        Ok(EditCheck::Conflict(server_text)) => {
+            let _ = tx.send(Message::EditConflict {
+                message_id,
+                server_text,
+            });
+            return;
+        }
+        Err(e) => {
+            let _ = tx.send(send_failed("Failed to edit message", e));
+            return;
+        }
+    };
+
     match client
         .messages()
-        .edit(peer_id, message_id, cmid, &text)
+        .edit(peer_id, message_id, cmid, &text, &attachments)
         .await
     {
         Ok(()) => {
             let _ = tx.send(Message::MessageEdited(message_id));
         }
         Err(e) => {
-            let _ = tx.send(Message::SendFailed(format!(
-                "Failed to edit message: {}",
-                e
-            )));
+            let _ = tx.send(send_failed("Failed to edit message", e));
         }
     }
 }
@@ -363,102 +581,239 @@ pub async fn delete_message(
             let _ = tx.send(Message::MessageDeleted(message_id));
         }
         Err(e) => {
-            let _ = tx.send(Message::SendFailed(format!(
-                "Failed to delete message: {}",
-                e
-            )));
+            let _ = tx.send(send_failed("Failed to delete message", e));
         }
     }
 }
 
-pub async fn fetch_message_by_id(
+pub async fn delete_messages(
     client: Arc<VkClient>,
-    msg_id: i64,
-    tx: mpsc::UnboundedSender<Message>,
-) {
-    match client.messages().get_by_id(&[msg_id]).await {
-        Ok(messages) => {
-            if let Some(msg) = messages.first() {
-                let attachments = msg
-                    .attachments
-                    .clone()
-                    .into_iter()
-                    .map(map_attachment)
-                    .collect::<Vec<_>>();
-                let reply = msg.reply_message.as_ref().map(|r| map_reply(&[], r));
-                let forwards = msg
-                    .fwd_messages
-                    .iter()
-                    .map(|m| map_forward_tree(&[], m))
-                    .collect::<Vec<_>>();
-                let fwd_count = forwards.len();
-
-                let _ = tx.send(Message::MessageDetailsFetched {
-                    message_id: msg.id,
-                    cmid: msg.conversation_message_id,
-                    text: Some(msg.text.clone()),
-                    is_edited: msg.update_time.is_some(),
-                    attachments: Some(attachments),
-                    reply,
-                    fwd_count: Some(fwd_count),
-                    forwards: Some(forwards),
-                });
-            }
+    message_ids: Vec<i64>,
+    delete_for_all: bool,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    match client
+        .messages()
+        .delete_each(&message_ids, delete_for_all)
+        .await
+    {
+        Ok(deleted) => {
+            let failed = message_ids
+                .into_iter()
+                .filter(|id| !deleted.contains(id))
+                .collect();
+            let _ = tx.send(Message::MessagesDeleted { deleted, failed });
         }
         Err(e) => {
-            tracing::warn!("Failed to fetch message details: {}", e);
+            let _ = tx.send(send_failed("Failed to delete messages", e));
         }
     }
 }
 
-pub async fn download_attachments(atts: Vec<AttachmentInfo>, tx: mpsc::UnboundedSender<Message>) {
-    let Some(base_dir) = directories::UserDirs::new()
-        .and_then(|u| u.download_dir().map(|p| p.to_path_buf()))
-        .or_else(|| Some(std::env::temp_dir()))
-    else {
-        let _ = tx.send(Message::Error("No download directory available".into()));
-        return;
+pub async fn set_reaction(
+    client: Arc<VkClient>,
+    peer_id: i64,
+    message_id: i64,
+    cmid: i64,
+    reaction_id: Option<i64>,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    let messages = client.messages();
+    let result = match reaction_id {
+        Some(reaction_id) => messages.send_reaction(peer_id, cmid, reaction_id).await,
+        None => messages.delete_reaction(peer_id, cmid).await,
     };
+    if let Err(e) = result {
+        let _ = tx.send(send_failed("Failed to set reaction", e));
+        // Undo the optimistic counters
+        fetch_message_details(client, vec![message_id], tx).await;
+    }
+}
 
-    if std::fs::create_dir_all(&base_dir).is_err() {
-        let _ = tx.send(Message::Error("Failed to create download directory".into()));
-        return;
+/// Fetch the details of `message_ids` in one request. The action handler
+/// gathers ids asked for close together with a
+/// [`Coalescer`](vk_core::batch::Coalescer).
+pub async fn fetch_message_details(
+    client: Arc<VkClient>,
+    message_ids: Vec<i64>,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    let messages = match client.messages().get_by_id_batched(&message_ids).await {
+        Ok(messages) => messages,
+        Err(e) => {
+            tracing::warn!("Failed to fetch message details: {}", e);
+            return;
+        }
+    };
+
+    for (message_id, msg) in demux(&message_ids, messages, |m| m.id) {
+        let Some(msg) = msg else {
+            tracing::debug!("No details returned for message {}", message_id);
+            continue;
+        };
+        let attachments = msg
+            .attachments
+            .clone()
+            .into_iter()
+            .map(map_attachment)
+            .collect::<Vec<_>>();
+        let reply = msg.reply_message.as_ref().map(|r| map_reply(&[], r));
+        let forwards = msg
+            .fwd_messages
+            .iter()
+            .map(|m| map_forward_tree(&[], m))
+            .collect::<Vec<_>>();
+        let fwd_count = forwards.len();
+
+        let _ = tx.send(Message::MessageDetailsFetched {
+            message_id: msg.id,
+            cmid: msg.conversation_message_id,
+            text: Some(msg.text.clone()),
+            is_edited: msg.update_time.is_some(),
+            attachments: Some(attachments),
+            reply,
+            fwd_count: Some(fwd_count),
+            forwards: Some(forwards),
+            reactions: Some(map_reactions(&msg)),
+        });
     }
+}
 
+/// Find the id of a replied message VK gave only the cmid of
+pub async fn resolve_reply(
+    client: Arc<VkClient>,
+    peer_id: i64,
+    cmid: i64,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    let result = match client
+        .messages()
+        .get_by_conversation_message_id(peer_id, &[cmid])
+        .await
+    {
+        Ok(messages) => messages
+            .first()
+            .map(|m| m.id)
+            .filter(|&id| id > 0)
+            .ok_or_else(|| "Replied message not found (deleted?)".to_string()),
+        Err(e) if e.is_auth() => {
+            let _ = tx.send(Message::AuthExpired);
+            return;
+        }
+        Err(e) => Err(format!("Failed to find replied message: {}", e)),
+    };
+    let _ = tx.send(Message::ReplyTargetResolved(result));
+}
+
+pub async fn download_attachments(
+    atts: Vec<AttachmentInfo>,
+    dir: PathBuf,
+    tx: mpsc::UnboundedSender<Message>,
+) {
     let client = reqwest::Client::new();
 
     for (idx, att) in atts.into_iter().enumerate() {
-        let Some(url) = att.url.clone() else {
+        let Some(url) = att.url else {
             continue;
         };
 
-        let name = if !att.title.is_empty() {
-            att.title.clone()
+        let title = if !att.title.is_empty() {
+            att.title
         } else {
             format!("attachment_{}", idx)
         };
 
-        let path = base_dir.join(name);
-
-        match client.get(&url).send().await {
-            Ok(resp) => match resp.bytes().await {
-                Ok(bytes) => {
-                    if let Err(e) = std::fs::write(&path, &bytes) {
-                        let _ = tx.send(Message::Error(format!(
-                            "Failed to save {}: {}",
-                            path.display(),
-                            e
-                        )));
-                    }
+        let progress = |received, total| {
+            let _ = tx.send(Message::DownloadProgress {
+                title: title.clone(),
+                received,
+                total,
+            });
+        };
+        match download::download(&client, &url, &dir, &title, progress).await {
+            Ok(path) => {
+                let _ = tx.send(Message::DownloadFinished { path });
+            }
+            Err(e) => {
+                let _ = tx.send(Message::Error(format!(
+                    "Download of {} failed: {}",
+                    title, e
+                )));
+            }
+        }
+    }
+}
+
+/// Download the photo at `url` to a temp file, then decode it for the
+/// preview popup (`inline`) or open it in the default image viewer
+pub async fn preview_photo(url: String, inline: bool, tx: mpsc::UnboundedSender<Message>) {
+    // One file per process, replaced by the next preview
+    let path = std::env::temp_dir().join(format!("vk_tui_preview_{}.jpg", std::process::id()));
+    let client = reqwest::Client::new();
+    if let Err(e) = download::download_to(&client, &url, &path, |_, _| {}).await {
+        let _ = tx.send(Message::PhotoPreviewLoaded(Err(format!(
+            "Failed to load photo: {}",
+            e
+        ))));
+        return;
+    }
+
+    if !inline {
+        if let Err(e) = open::that(&path) {
+            let _ = tx.send(Message::PhotoPreviewLoaded(Err(format!(
+                "Failed to open photo: {}",
+                e
+            ))));
+        }
+        return;
+    }
+
+    let decoded = tokio::task::spawn_blocking(move || {
+        image::ImageReader::open(&path)?
+            .with_guessed_format()?
+            .decode()
+            .map(|image| Arc::new(image.to_rgba8()))
+            .map_err(std::io::Error::other)
+    })
+    .await
+    .map_err(std::io::Error::other)
+    .and_then(|result| result);
+    let _ = tx.send(Message::PhotoPreviewLoaded(
+        decoded.map_err(|e| format!("Cannot show photo: {}", e)),
+    ));
+}
+
+/// Write the history of `peer_id` to `path`, reporting progress per page
+pub async fn export_history(
+    client: Arc<VkClient>,
+    peer_id: i64,
+    title: String,
+    format: ExportFormat,
+    path: PathBuf,
+    mut cancel: watch::Receiver<bool>,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    let progress = |done, total| {
+        let _ = tx.send(Message::ExportProgress(done, total));
+    };
+    let check = cancel.clone();
+    tokio::select! {
+        result = export::export_history(&client, peer_id, &title, format, &path, &check, progress) => {
+            match result {
+                Ok(Some(count)) => {
+                    let _ = tx.send(Message::ExportFinished(Ok(count)));
+                }
+                Ok(None) => {}
+                Err(e) if e.downcast_ref::<vk_api::Error>().is_some_and(|e| e.is_auth()) => {
+                    let _ = tx.send(Message::AuthExpired);
                 }
                 Err(e) => {
-                    let _ = tx.send(Message::Error(format!("Download failed: {}", e)));
+                    let _ = tx.send(Message::ExportFinished(Err(format!("Export failed: {:#}", e))));
                 }
-            },
-            Err(e) => {
-                let _ = tx.send(Message::Error(format!("Download failed: {}", e)));
             }
         }
+        // Sent on Esc; stops a page request in flight too
+        _ = cancel.changed() => {}
     }
 }
 
@@ -518,6 +873,7 @@ pub async fn search_messages(
                     chat_title,
                     text: msg.text,
                     timestamp: msg.date,
+                    highlight: None,
                 });
             }
 
@@ -527,7 +883,400 @@ pub async fn search_messages(
             });
         }
         Err(e) => {
-            let _ = tx.send(Message::Error(format!("Search failed: {}", e)));
+            let _ = tx.send(api_error("Search failed", e));
+        }
+    }
+}
+
+/// Search the history of `peer_id` on the device, streaming results
+pub async fn grep_history(
+    peer_id: i64,
+    title: String,
+    query: String,
+    loaded: Vec<ChatMessage>,
+    cache: Option<Arc<dyn CacheLayer>>,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    // Checked before the action is sent
+    let Ok(pattern) = GrepPattern::parse(&query) else {
+        return;
+    };
+    // Tens of thousands of cached messages: keep the runtime free
+    let scan = tokio::task::spawn_blocking(move || {
+        let messages = grep::searchable_history(peer_id, loaded, cache.as_deref());
+        grep::grep_messages(&messages, &pattern, peer_id, &title, |results, done| {
+            tx.send(Message::GrepResults {
+                query: query.clone(),
+                results,
+                done,
+            })
+            .is_ok()
+        });
+    });
+    if let Err(e) = scan.await {
+        tracing::warn!("Offline search failed: {}", e);
+    }
+}
+
+/// Load profiles missing for a picker in one users.get call
+pub async fn load_users(
+    client: Arc<VkClient>,
+    user_ids: Vec<i64>,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    let users = match client.users().get(&user_ids, BASIC_USER_FIELDS).await {
+        Ok(users) => users,
+        Err(e) => {
+            tracing::warn!("Failed to load users: {}", e);
+            Vec::new()
+        }
+    };
+    let _ = tx.send(Message::UsersLoaded {
+        requested: user_ids,
+        users,
+    });
+}
+
+/// Load profile details for the whois popup
+pub async fn fetch_user_profile(
+    client: Arc<VkClient>,
+    user_id: i64,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    let profile = if user_id < 0 {
+        client
+            .groups()
+            .get_by_id(&[-user_id], GROUP_DETAIL_FIELDS)
+            .await
+            .map(|groups| groups.first().map(map_group_profile))
+    } else {
+        client
+            .users()
+            .get(&[user_id], DETAIL_FIELDS)
+            .await
+            .map(|users| users.first().map(map_user_profile))
+    };
+
+    match profile {
+        Ok(Some(profile)) => {
+            let _ = tx.send(Message::UserProfileLoaded(profile));
+        }
+        Ok(None) => {
+            let _ = tx.send(Message::UserProfileFailed(format!(
+                "Profile {} not found",
+                user_id
+            )));
+        }
+        Err(e) if e.is_auth() => {
+            let _ = tx.send(Message::AuthExpired);
+        }
+        Err(e) => {
+            let _ = tx.send(Message::UserProfileFailed(format!(
+                "Failed to load profile: {}",
+                e
+            )));
+        }
+    }
+}
+
+/// Load a page of group chat members for the members popup
+pub async fn load_chat_members(
+    client: Arc<VkClient>,
+    peer_id: i64,
+    offset: u32,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    match client
+        .messages()
+        .get_conversation_members(peer_id, offset, CHAT_MEMBERS_PAGE)
+        .await
+    {
+        Ok(response) => {
+            let _ = tx.send(Message::MembersLoaded {
+                peer_id,
+                offset,
+                members: map_chat_members(&response),
+                total_count: response.count,
+            });
+        }
+        Err(e) if e.is_auth() => {
+            let _ = tx.send(Message::AuthExpired);
+        }
+        Err(e) => {
+            let _ = tx.send(Message::MembersFailed(format!(
+                "Failed to load members: {}",
+                e
+            )));
+        }
+    }
+}
+
+/// Leave a group chat by removing the own user from it
+pub async fn leave_chat(
+    client: Arc<VkClient>,
+    peer_id: i64,
+    user_id: i64,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    match client
+        .messages()
+        .remove_chat_user(peer_id - CHAT_PEER_OFFSET, user_id)
+        .await
+    {
+        Ok(()) => {
+            let _ = tx.send(Message::ChatLeft(peer_id));
+        }
+        Err(e) => match vk_core::leave_chat_error(&e) {
+            Some(reason) => {
+                let _ = tx.send(Message::Error(reason.to_string()));
+            }
+            None => {
+                let _ = tx.send(api_error("Failed to leave chat", e));
+            }
+        },
+    }
+}
+
+/// Turn notifications of a chat off for `duration`, or back on for `None`
+pub async fn set_chat_muted(
+    client: Arc<VkClient>,
+    peer_id: i64,
+    duration: Option<MuteDuration>,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    let time = duration.map_or(0, MuteDuration::api_time);
+    match client.account().set_silence_mode(peer_id, time).await {
+        Ok(()) => {
+            let _ = tx.send(Message::ChatMuteChanged {
+                peer_id,
+                muted: duration.is_some(),
+            });
+        }
+        Err(e) => {
+            let _ = tx.send(api_error("Failed to change notifications", e));
+        }
+    }
+}
+
+/// Pin a chat to the top of the conversation list, or unpin it
+pub async fn set_chat_pinned(
+    client: Arc<VkClient>,
+    peer_id: i64,
+    pinned: bool,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    let messages = client.messages();
+    let result = if pinned {
+        messages.pin_conversation(peer_id).await
+    } else {
+        messages.unpin_conversation(peer_id).await
+    };
+    match result {
+        Ok(()) => {
+            let _ = tx.send(Message::ChatPinChanged { peer_id, pinned });
+        }
+        Err(e) => {
+            let _ = tx.send(api_error("Failed to pin chat", e));
+        }
+    }
+}
+
+/// Delete a conversation with its history
+pub async fn delete_conversation(
+    client: Arc<VkClient>,
+    peer_id: i64,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    match client.messages().delete_conversation(peer_id).await {
+        Ok(()) => {
+            let _ = tx.send(Message::ConversationDeleted(peer_id));
+        }
+        Err(e) => {
+            let _ = tx.send(send_failed("Failed to delete conversation", e));
+        }
+    }
+}
+
+/// Load friends for the `:newchat` picker
+pub async fn load_friends(client: Arc<VkClient>, tx: mpsc::UnboundedSender<Message>) {
+    match client.friends().get(None).await {
+        Ok(friends) => {
+            let people = friends.iter().map(|u| (u.id, u.full_name())).collect();
+            let _ = tx.send(Message::FriendsLoaded(people));
+        }
+        Err(e) => {
+            // The picker still offers dialog peers
+            let _ = tx.send(Message::FriendsLoaded(Vec::new()));
+            let _ = tx.send(api_error("Failed to load friends", e));
+        }
+    }
+}
+
+/// Create a group chat with the picked users
+pub async fn create_chat(
+    client: Arc<VkClient>,
+    user_ids: Vec<i64>,
+    title: String,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    match client.messages().create_chat(&user_ids, &title).await {
+        Ok(chat_id) => match vk_api::chat_peer_id(chat_id) {
+            Some(peer_id) => {
+                let _ = tx.send(Message::ChatCreated { peer_id, title });
+            }
+            None => {
+                let _ = tx.send(Message::Error(format!("VK returned chat id {}", chat_id)));
+            }
+        },
+        Err(e) => match vk_core::create_chat_error(&e) {
+            Some(reason) => {
+                let _ = tx.send(Message::Error(reason.to_string()));
+            }
+            None => {
+                let _ = tx.send(api_error("Failed to create chat", e));
+            }
+        },
+    }
+}
+
+/// Rename a group chat
+/// Check the chat settings acl before changing the title or photo, sending
+/// the reason when the user may not. Without the settings the API decides.
+async fn may_change_chat_info(
+    client: &VkClient,
+    peer_id: i64,
+    tx: &mpsc::UnboundedSender<Message>,
+) -> bool {
+    match client.messages().get_conversation_by_id(peer_id).await {
+        Ok(conversation) => match vk_core::change_chat_info_denied(&conversation) {
+            Some(reason) => {
+                let _ = tx.send(Message::Error(reason.to_string()));
+                false
+            }
+            None => true,
+        },
+        Err(e) => {
+            tracing::debug!("Failed to load chat settings of {}: {}", peer_id, e);
+            true
+        }
+    }
+}
+
+pub async fn rename_chat(
+    client: Arc<VkClient>,
+    peer_id: i64,
+    title: String,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    if !may_change_chat_info(&client, peer_id, &tx).await {
+        return;
+    }
+
+    match client
+        .messages()
+        .edit_chat(peer_id - CHAT_PEER_OFFSET, &title)
+        .await
+    {
+        Ok(()) => {
+            let _ = tx.send(Message::ChatSettingsChanged {
+                peer_id,
+                title: Some(title),
+                photo_url: None,
+            });
+        }
+        Err(e) => match vk_core::rename_chat_error(&e) {
+            Some(reason) => {
+                let _ = tx.send(Message::Error(reason.to_string()));
+            }
+            None => {
+                let _ = tx.send(api_error("Failed to rename chat", e));
+            }
+        },
+    }
+}
+
+/// Upload a local image and make it the photo of a group chat
+pub async fn set_chat_photo(
+    client: Arc<VkClient>,
+    peer_id: i64,
+    path: String,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    if !may_change_chat_info(&client, peer_id, &tx).await {
+        return;
+    }
+
+    let _ = tx.send(Message::ChatPhotoUploading(path.clone()));
+    match client
+        .messages()
+        .set_chat_photo(peer_id - CHAT_PEER_OFFSET, Path::new(&path))
+        .await
+    {
+        Ok(photo_url) => {
+            let _ = tx.send(Message::ChatSettingsChanged {
+                peer_id,
+                title: None,
+                photo_url,
+            });
+        }
+        Err(e) => match vk_core::rename_chat_error(&e) {
+            Some(reason) => {
+                let _ = tx.send(Message::Error(reason.to_string()));
+            }
+            None => {
+                let _ = tx.send(api_error("Failed to set chat photo", e));
+            }
+        },
+    }
+}
+
+/// Load who read one of our messages for the read receipts popup
+pub async fn fetch_read_peers(
+    client: Arc<VkClient>,
+    peer_id: i64,
+    cmid: i64,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    match client
+        .messages()
+        .get_message_read_peers(peer_id, cmid)
+        .await
+    {
+        Ok(response) => {
+            let _ = tx.send(Message::ReadPeersLoaded(map_read_peers(
+                peer_id, cmid, &response,
+            )));
+        }
+        Err(e) if e.is_auth() => {
+            let _ = tx.send(Message::AuthExpired);
+        }
+        Err(e) => {
+            let reason = vk_core::read_peers_error(&e)
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("Failed to load read receipts: {}", e));
+            let _ = tx.send(Message::ReadPeersFailed(reason));
+        }
+    }
+}
+
+/// Count the media of a chat for the `:info` popup
+pub async fn load_chat_info(
+    client: Arc<VkClient>,
+    peer_id: i64,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    match vk_core::media::load_chat_info(&client, peer_id).await {
+        Ok(info) => {
+            let _ = tx.send(Message::ChatInfoLoaded(info));
+        }
+        Err(e) if e.is_auth() => {
+            let _ = tx.send(Message::AuthExpired);
+        }
+        Err(e) => {
+            let _ = tx.send(Message::ChatInfoFailed(format!(
+                "Failed to load chat info: {}",
+                e
+            )));
         }
     }
 }
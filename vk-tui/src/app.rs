@@ -1,14 +1,36 @@
+use std::ops::RangeInclusive;
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
 
-use crate::state::{App, AsyncAction, Chat, ChatMessage, RunningState, Screen};
+use crate::config::Config;
+use crate::history::{CommandHistory, history_path};
+use crate::state::{
+    App, AsyncAction, Chat, ChatMessage, Focus, MembersView, MessagesPagination, Mode, NewChatView,
+    ReadersView, RunningState, Screen, Whois,
+};
 use vk_api::VkClient;
 use vk_api::auth::AuthManager;
+use vk_core::MessageKind;
+use vk_core::config::Settings;
+use vk_core::profiles::LOADING_NAME;
+
+/// Terminals narrower than this, in columns, show only one panel
+pub const SIDEBAR_COLLAPSE_BELOW: u16 = 60;
 
 impl App {
     /// Create new application state
     pub fn new() -> Self {
-        let mut app = Self::default();
+        let (settings, mut problems) = Settings::load();
+        let config = Config::load(&settings, &mut problems);
+        let mut app = Self {
+            config,
+            settings,
+            cache: vk_core::cache::open_default(),
+            command_history: history_path().map(CommandHistory::load).unwrap_or_default(),
+            ..Self::default()
+        };
+        app.sidebar_width = app.config.sidebar_width();
 
         // Restore token if present
         if app.auth.is_authenticated()
@@ -19,12 +41,29 @@ impl App {
                 app.screen = Screen::Auth;
                 app.status = Some("Session expired. Please authorize again.".into());
             } else {
-                app.vk_client = Some(Arc::new(VkClient::new(token.to_string())));
-                app.screen = Screen::Main;
-                app.status = Some("Restoring session...".into());
+                match app.settings.client(token.to_string()) {
+                    Ok(client) => {
+                        app.set_client(Some(Arc::new(client)));
+                        app.screen = Screen::Main;
+                        app.status = Some("Restoring session...".into());
+                        if let Some(cache) = &app.cache {
+                            app.chats = cache.chats();
+                            app.stats.record_cache_read(!app.chats.is_empty());
+                        }
+                    }
+                    Err(e) => app.status = Some(e.to_string()),
+                }
             }
         }
 
+        // Broken settings fall back to defaults, but the user should know
+        if !problems.is_empty() {
+            for problem in &problems {
+                tracing::warn!("Settings: {}", problem);
+            }
+            app.status = Some(format!("Settings: {}", problems.join("; ")));
+        }
+
         app
     }
 
@@ -33,6 +72,56 @@ impl App {
         AuthManager::get_auth_url()
     }
 
+    /// Set the VK client, for the action handler too. Actions sent while
+    /// there is none wait for the next one.
+    pub fn set_client(&mut self, client: Option<Arc<VkClient>>) {
+        self.vk_client = client.clone();
+        self.shared_client.send_replace(client);
+    }
+
+    /// Whether the chat list is drawn next to the messages. Below
+    /// [`SIDEBAR_COLLAPSE_BELOW`] columns it is hidden unless toggled on.
+    pub fn sidebar_visible(&self) -> bool {
+        self.sidebar_toggle
+            .unwrap_or(self.terminal_size.width >= SIDEBAR_COLLAPSE_BELOW)
+    }
+
+    /// Messages moved by `Ctrl+U`/`Ctrl+D`: half of those on screen
+    pub fn half_page(&self) -> usize {
+        (self.layout.borrow().message_rows.len() / 2).max(1)
+    }
+
+    /// Whether a popup covers the panels, so clicks on them are ignored
+    pub fn popup_open(&self) -> bool {
+        self.forward.is_some()
+            || self.forward_view.is_some()
+            || self.edit_conflict.is_some()
+            || self.captcha.is_some()
+            || self.cross_chat_send.is_some()
+            || self.whois.is_some()
+            || self.members.is_some()
+            || self.leave_chat.is_some()
+            || self.delete_chat.is_some()
+            || self.delete_prompt.is_some()
+            || self.message_menu.is_some()
+            || self.link_picker.is_some()
+            || self.new_chat.is_some()
+            || self.chat_info.is_some()
+            || self.read_by.is_some()
+            || self.show_registers
+            || self.show_stats
+            || self.log_view.is_some()
+            || self.photo_preview.is_some()
+            || self.show_help
+            || self.global_search.is_some()
+    }
+
+    /// Whether typing goes to the token input: on the auth screen and in
+    /// the re-auth prompt
+    pub fn entering_token(&self) -> bool {
+        self.screen == Screen::Auth || self.session_expired
+    }
+
     /// Check if app should quit
     pub fn is_running(&self) -> bool {
         self.running_state == RunningState::Running
@@ -43,6 +132,36 @@ impl App {
         self.action_tx = Some(tx);
     }
 
+    /// Set the task running the actions, aborting the previous one
+    pub fn set_action_handler(&mut self, handler: JoinHandle<()>) {
+        if let Some(previous) = self.action_handler.replace(handler) {
+            previous.abort();
+        }
+    }
+
+    /// Log out: stop Long Poll and the action handler with the requests
+    /// it has in flight, and forget everything loaded for the account.
+    /// Config and registers are kept.
+    pub fn end_session(&mut self) {
+        if let Some(handler) = self.action_handler.take() {
+            handler.abort();
+        }
+        let _ = self.auth.logout();
+        // The next account must not see this one's messages
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+        *self = Self {
+            screen: Screen::Auth,
+            mode: Mode::Insert,
+            auth: std::mem::take(&mut self.auth),
+            registers: std::mem::take(&mut self.registers),
+            config: std::mem::take(&mut self.config),
+            cache: self.cache.take(),
+            ..Self::default()
+        };
+    }
+
     /// Send async action
     pub fn send_action(&self, action: AsyncAction) {
         if let Some(tx) = &self.action_tx {
@@ -50,6 +169,150 @@ impl App {
         }
     }
 
+    /// Id tying upload progress of an attachment to its pending message
+    pub fn next_upload_id(&mut self) -> u64 {
+        self.last_upload_id += 1;
+        self.last_upload_id
+    }
+
+    /// Open the profile popup and load the profile
+    pub fn open_whois(&mut self, user_id: i64) {
+        self.whois = Some(Whois {
+            user_id,
+            profile: None,
+        });
+        self.send_action(AsyncAction::FetchUserProfile(user_id));
+    }
+
+    /// Open the read receipts popup of an own message and load its readers
+    pub fn open_readers(&mut self, peer_id: i64, cmid: i64) {
+        self.read_by = Some(ReadersView {
+            peer_id,
+            cmid,
+            readers: None,
+        });
+        self.send_action(AsyncAction::FetchReadPeers(peer_id, cmid));
+    }
+
+    /// Toggle a reaction on the selected message, updating the counters
+    /// before VK confirms
+    pub fn react(&mut self, reaction_id: i64) {
+        let Some(peer_id) = self.current_peer_id else {
+            self.status = Some("No chat selected".into());
+            return;
+        };
+        let scroll = self.messages_scroll;
+        let Some(msg) = self.messages.get_mut(scroll) else {
+            return;
+        };
+        let Some(cmid) = msg.cmid else {
+            self.status = Some("Message is not sent yet".into());
+            return;
+        };
+        let reaction = vk_core::toggle_reaction(&mut msg.reactions, reaction_id);
+        let message_id = msg.id;
+        self.send_action(AsyncAction::SetReaction(
+            peer_id, message_id, cmid, reaction,
+        ));
+    }
+
+    /// Open the member list of a group chat and load the first page
+    pub fn open_members(&mut self, peer_id: i64) {
+        self.members = Some(MembersView {
+            peer_id,
+            members: Vec::new(),
+            total: None,
+            selected: 0,
+            is_loading: true,
+        });
+        self.send_action(AsyncAction::LoadChatMembers(peer_id, 0));
+    }
+
+    /// Load the next page of members once the selection nears the end
+    pub fn load_more_members(&mut self) {
+        const PRELOAD: usize = 20;
+
+        let Some(view) = self.members.as_mut() else {
+            return;
+        };
+        if view.is_loading || !view.has_more() || view.selected + PRELOAD < view.members.len() {
+            return;
+        }
+        view.is_loading = true;
+        let action = AsyncAction::LoadChatMembers(view.peer_id, view.members.len() as u32);
+        self.send_action(action);
+    }
+
+    /// Open a chat and load its latest messages
+    pub fn open_chat(&mut self, peer_id: i64, title: &str) {
+        // Clear chat filter if active
+        self.chat_filter = None;
+
+        self.current_peer_id = Some(peer_id);
+        self.messages.clear();
+        self.visual_anchor = None;
+        self.unseen_below = 0;
+        // A reply only makes sense in the chat it was started in
+        self.reply_to = None;
+        self.is_loading = true;
+        // Initialize messages pagination and load first page
+        self.messages_pagination = Some(MessagesPagination::new(peer_id));
+        // Show what is cached until it arrives
+        if let Some(cache) = &self.cache {
+            self.messages = cache.messages(peer_id, 50);
+            self.messages_scroll = self.messages.len().saturating_sub(1);
+            self.stats.record_cache_read(!self.messages.is_empty());
+        }
+        if let Some(pagination) = &mut self.messages_pagination {
+            pagination.is_loading = true;
+            pagination.from_cache = !self.messages.is_empty();
+        }
+        self.send_action(AsyncAction::LoadMessages(peer_id, 0));
+        self.send_action(AsyncAction::MarkAsRead(peer_id));
+        self.status = Some(format!("Loading chat: {}", title));
+        self.focus = Focus::Messages;
+    }
+
+    /// Open the chat info popup, counting media unless cached or `refresh`
+    pub fn open_chat_info(&mut self, peer_id: i64, refresh: bool) {
+        self.chat_info = Some(peer_id);
+        if refresh {
+            self.chat_infos.remove(&peer_id);
+        }
+        if !self.chat_infos.contains_key(&peer_id) {
+            self.send_action(AsyncAction::LoadChatInfo(peer_id));
+        }
+    }
+
+    /// Open the user picker for a new group chat, starting with dialog
+    /// peers and adding friends once they load
+    pub fn open_new_chat(&mut self, title: String) {
+        let people = self
+            .chats
+            .iter()
+            .filter(|c| c.id > 0 && !vk_api::is_chat_peer(c.id))
+            .map(|c| (c.id, c.title.clone()))
+            .collect();
+        self.new_chat = Some(NewChatView {
+            title,
+            people,
+            picked: Vec::new(),
+            selected: 0,
+            is_loading: true,
+        });
+        self.send_action(AsyncAction::LoadFriends);
+    }
+
+    /// Start the Long Poll loop, stopping the previous one
+    pub fn start_long_poll(&mut self) {
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        self.long_poll_shutdown = Some(shutdown_tx);
+        self.send_action(AsyncAction::StartLongPoll(
+            shutdown_rx,
+            self.long_poll_reconnect.clone(),
+        ));
+    }
+
     /// Get current chat peer_id
     pub fn current_chat(&self) -> Option<&Chat> {
         if let Some(filter) = &self.chat_filter {
@@ -63,10 +326,118 @@ impl App {
         }
     }
 
+    /// Why nothing can be sent to the open chat, if it is read-only
+    pub fn write_denied(&self) -> Option<String> {
+        let peer_id = self.current_peer_id?;
+        self.chats.iter().find(|c| c.id == peer_id)?.write_denied()
+    }
+
+    /// Re-sort chats by last activity, keeping the same chat selected
+    pub fn sort_chats(&mut self) {
+        let selected_id = self.current_chat().map(|c| c.id);
+        vk_core::sort_chats(&mut self.chats);
+        self.refresh_chat_selection(selected_id);
+    }
+
+    /// Show a new message in the chat list, moving its chat up
+    pub fn record_chat_activity(
+        &mut self,
+        peer_id: i64,
+        text: &str,
+        from: Option<String>,
+        kind: MessageKind,
+        timestamp: i64,
+        unread: bool,
+    ) {
+        let selected_id = self.current_chat().map(|c| c.id);
+        if let Some(chat) = self.deleted_chats.remove(&peer_id) {
+            vk_core::restore_chat(&mut self.chats, chat);
+        }
+        if vk_core::record_new_message(
+            &mut self.chats,
+            peer_id,
+            text,
+            from,
+            kind,
+            timestamp,
+            unread,
+        ) {
+            self.refresh_chat_selection(selected_id);
+        }
+    }
+
+    /// Pin or unpin a chat, moving it to its place and keeping the same
+    /// chat selected
+    pub fn set_chat_pinned(&mut self, peer_id: i64, pinned: bool) {
+        let selected_id = self.current_chat().map(|c| c.id);
+        if vk_core::set_chat_pinned(&mut self.chats, peer_id, pinned) {
+            self.refresh_chat_selection(selected_id);
+        }
+    }
+
+    /// Drop a chat from the list (left or deleted), closing it if open and
+    /// keeping the selection in range
+    pub fn remove_chat(&mut self, peer_id: i64) -> Option<Chat> {
+        let removed = vk_core::remove_chat(&mut self.chats, peer_id)?;
+        if let Some(filter) = &mut self.chat_filter {
+            filter.filtered_indices = vk_core::fuzzy::filter_chats(&self.chats, &filter.query);
+        }
+        let visible = self
+            .chat_filter
+            .as_ref()
+            .map_or(self.chats.len(), |f| f.filtered_indices.len());
+        self.selected_chat = self.selected_chat.min(visible.saturating_sub(1));
+
+        if self.members.as_ref().is_some_and(|v| v.peer_id == peer_id) {
+            self.members = None;
+        }
+        if self.chat_info == Some(peer_id) {
+            self.chat_info = None;
+        }
+        if self.read_by.as_ref().is_some_and(|v| v.peer_id == peer_id) {
+            self.read_by = None;
+        }
+        if self.current_peer_id == Some(peer_id) {
+            self.current_peer_id = None;
+            self.messages.clear();
+            self.messages_pagination = None;
+            self.focus = Focus::ChatList;
+        }
+        Some(removed)
+    }
+
+    /// Recompute filter results after reordering and point the selection
+    /// back at `selected_id`
+    fn refresh_chat_selection(&mut self, selected_id: Option<i64>) {
+        if let Some(filter) = &mut self.chat_filter {
+            filter.filtered_indices = vk_core::fuzzy::filter_chats(&self.chats, &filter.query);
+        }
+        let Some(selected_id) = selected_id else {
+            return;
+        };
+        let position = match &self.chat_filter {
+            Some(filter) => filter
+                .filtered_indices
+                .iter()
+                .position(|&idx| self.chats[idx].id == selected_id),
+            None => self.chats.iter().position(|c| c.id == selected_id),
+        };
+        if let Some(position) = position {
+            self.selected_chat = position;
+        }
+    }
+
+    /// Id of the logged-in account, if known
+    pub fn own_user_id(&self) -> Option<i64> {
+        vk_core::profiles::own_user_id(self.current_user.as_ref(), self.auth.user_id())
+    }
+
     /// Get user name by id
     pub fn get_user_name(&self, user_id: i64) -> String {
         if let Some(user) = self.users.get(&user_id) {
             user.full_name()
+        } else if self.profile_warmup.is_pending(user_id) {
+            LOADING_NAME.to_string()
         } else if user_id < 0 {
             format!("Group {}", -user_id)
         } else {
@@ -78,4 +449,20 @@ impl App {
     pub fn current_message(&self) -> Option<&ChatMessage> {
         self.messages.get(self.messages_scroll)
     }
+
+    /// Indices of the messages selected with `V`, from the anchor to the
+    /// cursor
+    pub fn visual_selection(&self) -> Option<RangeInclusive<usize>> {
+        let anchor_id = self.visual_anchor?;
+        let cursor = self
+            .messages_scroll
+            .min(self.messages.len().checked_sub(1)?);
+        // The anchor may have left the window: select from the cursor
+        let anchor = self
+            .messages
+            .iter()
+            .position(|m| m.id == anchor_id)
+            .unwrap_or(cursor);
+        Some(anchor.min(cursor)..=anchor.max(cursor))
+    }
 }
@@ -0,0 +1,211 @@
+//! Command-line flags and subcommands
+
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::terminal::CapsOverrides;
+
+/// Terminal client for VKontakte
+#[derive(Debug, Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Start with the conversation matching NAME open
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["send", "unread"])]
+    pub chat: Option<String>,
+
+    /// Send TEXT to the conversation matching NAME, print the message id and exit
+    #[arg(long, num_args = 2, value_names = ["NAME", "TEXT"], conflicts_with = "unread")]
+    pub send: Option<Vec<String>>,
+
+    /// Print conversations with unread messages and exit
+    #[arg(long)]
+    pub unread: bool,
+
+    /// Draw in the main screen instead of the alternate one
+    #[arg(long)]
+    pub no_alt_screen: bool,
+
+    /// Disable colors
+    #[arg(long)]
+    pub no_color: bool,
+
+    /// Leave the mouse to the terminal (`disable_mouse` in the config)
+    #[arg(long)]
+    pub no_mouse: bool,
+
+    /// Log filter, e.g. `debug` or `info,vk_api=trace` (default: RUST_LOG, else `info`)
+    #[arg(long, value_name = "LEVEL")]
+    pub log_level: Option<String>,
+}
+
+/// Scripting commands; they print JSON (or plain lines) and exit
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Send a message to a peer id and print `{"message_id": N}`
+    Send {
+        /// Peer id of the conversation (user, 2000000000 + chat, -group)
+        #[arg(long, value_name = "ID", allow_negative_numbers = true)]
+        peer: i64,
+
+        /// Message text
+        #[arg(long, required_unless_present = "file")]
+        text: Option<String>,
+
+        /// Attach a file as a document; repeat for several
+        #[arg(long, value_name = "PATH")]
+        file: Vec<PathBuf>,
+    },
+    /// List recent conversations
+    Chats {
+        /// Print a JSON array instead of one line per conversation
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// What to do instead of starting the TUI
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Headless {
+    Send {
+        chat: String,
+        text: String,
+    },
+    Unread,
+    SendTo {
+        peer_id: i64,
+        text: String,
+        files: Vec<PathBuf>,
+    },
+    Chats {
+        json: bool,
+    },
+}
+
+impl Args {
+    pub fn caps_overrides(&self) -> CapsOverrides {
+        CapsOverrides {
+            no_alt_screen: self.no_alt_screen,
+            no_color: self.no_color,
+            no_mouse: self.no_mouse,
+        }
+    }
+
+    /// The mode that runs without the terminal UI, if one was requested
+    pub fn headless(&self) -> Option<Headless> {
+        match &self.command {
+            Some(Command::Send { peer, text, file }) => {
+                return Some(Headless::SendTo {
+                    peer_id: *peer,
+                    text: text.clone().unwrap_or_default(),
+                    files: file.clone(),
+                });
+            }
+            Some(Command::Chats { json }) => return Some(Headless::Chats { json: *json }),
+            None => {}
+        }
+        if let Some([chat, text]) = self.send.as_deref() {
+            return Some(Headless::Send {
+                chat: chat.clone(),
+                text: text.clone(),
+            });
+        }
+        self.unread.then_some(Headless::Unread)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, clap::Error> {
+        Args::try_parse_from(std::iter::once("vk-tui").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn test_send_takes_name_and_text() {
+        let args = parse(&["--send", "Alice", "on my way"]).unwrap();
+        assert_eq!(
+            args.headless(),
+            Some(Headless::Send {
+                chat: "Alice".into(),
+                text: "on my way".into()
+            })
+        );
+        assert!(parse(&["--send", "Alice"]).is_err());
+    }
+
+    #[test]
+    fn test_modes_conflict() {
+        assert!(parse(&["--chat", "Alice", "--unread"]).is_err());
+        assert!(parse(&["--send", "Alice", "hi", "--unread"]).is_err());
+
+        let args = parse(&["--chat", "Alice"]).unwrap();
+        assert_eq!(args.chat.as_deref(), Some("Alice"));
+        assert_eq!(args.headless(), None);
+        assert_eq!(
+            parse(&["--unread"]).unwrap().headless(),
+            Some(Headless::Unread)
+        );
+    }
+
+    #[test]
+    fn test_send_subcommand() {
+        let args = parse(&["send", "--peer", "12345", "--text", "hi"]).unwrap();
+        assert_eq!(
+            args.headless(),
+            Some(Headless::SendTo {
+                peer_id: 12345,
+                text: "hi".into(),
+                files: Vec::new()
+            })
+        );
+
+        let args = parse(&["send", "--peer", "-7", "--file", "a.pdf", "--file", "b.pdf"]).unwrap();
+        assert_eq!(
+            args.headless(),
+            Some(Headless::SendTo {
+                peer_id: -7,
+                text: String::new(),
+                files: vec!["a.pdf".into(), "b.pdf".into()]
+            })
+        );
+
+        // Something to send, and a numeric peer
+        assert!(parse(&["send", "--peer", "12345"]).is_err());
+        assert!(parse(&["send", "--peer", "Alice", "--text", "hi"]).is_err());
+        assert!(parse(&["--unread", "send", "--peer", "1", "--text", "hi"]).is_err());
+    }
+
+    #[test]
+    fn test_chats_subcommand() {
+        assert_eq!(
+            parse(&["chats", "--json"]).unwrap().headless(),
+            Some(Headless::Chats { json: true })
+        );
+        assert_eq!(
+            parse(&["chats"]).unwrap().headless(),
+            Some(Headless::Chats { json: false })
+        );
+    }
+
+    #[test]
+    fn test_caps_overrides() {
+        let args = parse(&["--no-alt-screen", "--no-color", "--no-mouse"]).unwrap();
+        assert_eq!(
+            args.caps_overrides(),
+            CapsOverrides {
+                no_alt_screen: true,
+                no_color: true,
+                no_mouse: true,
+            }
+        );
+        assert_eq!(
+            parse(&[]).unwrap().caps_overrides(),
+            CapsOverrides::default()
+        );
+    }
+}
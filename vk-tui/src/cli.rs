@@ -0,0 +1,235 @@
+//! Headless modes (`--send`, `--unread`, the `send` and `chats`
+//! subcommands) and resolving `--chat` names.
+//!
+//! These run before the terminal is set up and never touch raw mode or
+//! the alternate screen, so they work from scripts and pipes. The
+//! subcommands print JSON for scripts; errors go to stderr with a
+//! non-zero exit code.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use anyhow::{Context, Result, bail};
+use vk_api::VkClient;
+use vk_api::auth::AuthManager;
+use vk_core::config::Settings;
+use vk_core::fuzzy::fuzzy_match;
+use vk_core::upload::{self, UploadKind};
+
+use crate::args::Headless;
+use crate::state::Chat;
+
+/// Conversations searched by name: one page, the most VK returns at once
+const CHATS_SEARCHED: u32 = 200;
+
+/// Candidates listed when a name is ambiguous
+const CANDIDATES_SHOWN: usize = 5;
+
+/// Run a headless mode, printing errors to stderr
+pub async fn run(mode: Headless) -> ExitCode {
+    let result = match stored_client() {
+        Ok(client) => match mode {
+            Headless::Send { chat, text } => send(&client, &chat, &text).await,
+            Headless::Unread => print_unread(&client).await,
+            Headless::SendTo {
+                peer_id,
+                text,
+                files,
+            } => send_to(&client, peer_id, &text, &files).await,
+            Headless::Chats { json } => print_chats(&client, json).await,
+        },
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("vk-tui: {:#}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Client for the saved session
+fn stored_client() -> Result<Arc<VkClient>> {
+    let auth = AuthManager::default();
+    match auth.access_token() {
+        Some(token) if !auth.is_token_expired() => {
+            let (settings, _) = Settings::load();
+            Ok(Arc::new(settings.client(token.to_string())?))
+        }
+        Some(_) => bail!("session expired, run vk-tui to authorize again"),
+        None => bail!("not logged in, run vk-tui to authorize"),
+    }
+}
+
+/// Recent conversations, most recent first
+async fn recent_chats(client: &VkClient) -> Result<Vec<Chat>> {
+    let response = client
+        .messages()
+        .get_conversations(0, CHATS_SEARCHED)
+        .await
+        .context("failed to load conversations")?;
+    Ok(crate::actions::conversation_chats(&response))
+}
+
+/// Look up the conversation `--chat`/`--send` refer to
+pub async fn find_chat(client: &VkClient, name: &str) -> Result<Chat> {
+    let chats = recent_chats(client).await?;
+    resolve_chat(&chats, name).cloned()
+}
+
+async fn send(client: &VkClient, name: &str, text: &str) -> Result<()> {
+    let chat = find_chat(client, name).await?;
+    let sent = client
+        .messages()
+        .send(chat.id, text)
+        .await
+        .with_context(|| format!("failed to send to {}", chat.title))?;
+    println!("{}", sent.message_id);
+    Ok(())
+}
+
+/// `send`: upload `files` as documents and send them with `text` in one
+/// message. Nothing is sent unless every file uploaded.
+async fn send_to(client: &VkClient, peer_id: i64, text: &str, files: &[PathBuf]) -> Result<()> {
+    if let Some(missing) = files.iter().find(|f| !f.is_file()) {
+        bail!("no such file: {}", missing.display());
+    }
+
+    let messages = client.messages();
+    let sent = if files.is_empty() {
+        messages.send(peer_id, text).await
+    } else {
+        let batch =
+            upload::upload_files(client, peer_id, UploadKind::Doc, files, Arc::new(|_, _| {}))
+                .await;
+        if !batch.failed.is_empty() {
+            bail!("{}", batch.failure_text());
+        }
+        messages
+            .send_with_attachments(peer_id, text, &batch.attachments)
+            .await
+    }
+    .with_context(|| format!("failed to send to {}", peer_id))?;
+    println!("{}", serde_json::json!({ "message_id": sent.message_id }));
+    Ok(())
+}
+
+/// `chats`: recent conversations as a JSON array, or `id<TAB>title<TAB>unread`
+async fn print_chats(client: &VkClient, json: bool) -> Result<()> {
+    let chats = recent_chats(client).await?;
+    if json {
+        println!("{}", serde_json::to_string(&chats)?);
+        return Ok(());
+    }
+    for chat in &chats {
+        println!("{}\t{}\t{}", chat.id, chat.title, chat.unread_count);
+    }
+    Ok(())
+}
+
+async fn print_unread(client: &VkClient) -> Result<()> {
+    let chats = recent_chats(client).await?;
+    let unread: Vec<&Chat> = chats.iter().filter(|c| c.unread_count > 0).collect();
+    if unread.is_empty() {
+        println!("No unread messages");
+    }
+    for chat in unread {
+        println!("{}: {}", chat.title, chat.unread_count);
+    }
+    Ok(())
+}
+
+/// Pick the chat `query` names: an exact title (ignoring case) wins, then
+/// a title containing it, then a fuzzy match. More than one chat in the
+/// first tier that matches anything is an error listing them.
+pub fn resolve_chat<'a>(chats: &'a [Chat], query: &str) -> Result<&'a Chat> {
+    let query = query.trim();
+    let lower = query.to_lowercase();
+    let tiers: [&dyn Fn(&Chat) -> bool; 3] = [
+        &|c| c.title.to_lowercase() == lower,
+        &|c| c.title.to_lowercase().contains(&lower),
+        &|c| fuzzy_match(&c.title, query).is_some(),
+    ];
+
+    for matches_tier in tiers {
+        let found: Vec<&Chat> = chats.iter().filter(|c| matches_tier(c)).collect();
+        match found.as_slice() {
+            [] => continue,
+            [chat] => return Ok(chat),
+            _ => {
+                let mut names: Vec<&str> = found
+                    .iter()
+                    .take(CANDIDATES_SHOWN)
+                    .map(|c| c.title.as_str())
+                    .collect();
+                if found.len() > CANDIDATES_SHOWN {
+                    names.push("...");
+                }
+                bail!(
+                    "\"{}\" is ambiguous, it matches: {}",
+                    query,
+                    names.join(", ")
+                );
+            }
+        }
+    }
+    bail!("no conversation matches \"{}\"", query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vk_core::{ChatKind, MessageKind};
+
+    fn chats(titles: &[&str]) -> Vec<Chat> {
+        titles
+            .iter()
+            .enumerate()
+            .map(|(i, title)| Chat {
+                id: i as i64 + 1,
+                title: title.to_string(),
+                last_message: String::new(),
+                last_message_from: None,
+                last_message_kind: MessageKind::Text,
+                last_message_time: 0,
+                unread_count: 0,
+                is_online: false,
+                photo_url: None,
+                is_muted: false,
+                is_pinned: false,
+                can_write: true,
+                cannot_write_reason: None,
+                kind: ChatKind::User,
+                last_seen: None,
+            })
+            .collect()
+    }
+
+    fn resolve(titles: &[&str], query: &str) -> Result<i64, String> {
+        let chats = chats(titles);
+        resolve_chat(&chats, query)
+            .map(|c| c.id)
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_exact_title_wins() {
+        assert_eq!(resolve(&["Alice Smith", "Alice"], "alice"), Ok(2));
+        assert_eq!(resolve(&["Bob", "Alice Smith"], "smith"), Ok(2));
+        // Fuzzy only when nothing contains the query
+        assert_eq!(resolve(&["Bob", "Alice Smith"], "asmth"), Ok(2));
+    }
+
+    #[test]
+    fn test_ambiguous_and_missing() {
+        let err = resolve(&["Alice Smith", "Alice Jones", "Bob"], "Alice").unwrap_err();
+        assert!(err.contains("ambiguous"));
+        assert!(err.contains("Alice Smith, Alice Jones"));
+
+        let err = resolve(&["Bob"], "Carol").unwrap_err();
+        assert!(err.contains("no conversation"));
+    }
+}
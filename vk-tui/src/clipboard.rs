@@ -0,0 +1,186 @@
+//! Copying yanks to and pasting from the system clipboard.
+//!
+//! `wl-copy` and `xclip` are tried first. Without them (typically over
+//! SSH) the text is handed to the terminal as an OSC 52 escape sequence,
+//! which most modern terminals put on the local clipboard. Pasting needs
+//! `wl-paste` or `xclip`; over SSH the terminal's own paste still works.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Largest OSC 52 payload sent; many terminals drop longer ones silently.
+pub const OSC52_MAX_LEN: usize = 100_000;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// How a copy reached the clipboard, for the status bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    WlCopy,
+    Xclip,
+    Osc52,
+}
+
+impl Method {
+    pub fn label(self) -> &'static str {
+        match self {
+            Method::WlCopy => "wl-copy",
+            Method::Xclip => "xclip",
+            Method::Osc52 => "terminal",
+        }
+    }
+}
+
+/// Put `text` on the system clipboard.
+pub fn copy(text: &str) -> anyhow::Result<Method> {
+    let mut errors = Vec::new();
+
+    match pipe_to("wl-copy", &[], text) {
+        Ok(()) => return Ok(Method::WlCopy),
+        Err(e) => errors.push(format!("wl-copy {}", e)),
+    }
+
+    match pipe_to("xclip", &["-selection", "clipboard"], text) {
+        Ok(()) => return Ok(Method::Xclip),
+        Err(e) => errors.push(format!("xclip {}", e)),
+    }
+
+    match osc52(text) {
+        Some(sequence) => {
+            let mut stdout = std::io::stdout();
+            stdout.write_all(sequence.as_bytes())?;
+            stdout.flush()?;
+            Ok(Method::Osc52)
+        }
+        None => {
+            errors.push("too long for OSC 52".into());
+            anyhow::bail!("Clipboard unavailable ({})", errors.join("; "))
+        }
+    }
+}
+
+/// Text on the system clipboard.
+pub fn paste() -> anyhow::Result<String> {
+    let mut errors = Vec::new();
+
+    match Command::new("wl-paste").arg("--no-newline").output() {
+        Ok(output) if output.status.success() => {
+            return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+        }
+        Ok(output) => errors.push(format!("wl-paste status {}", output.status)),
+        Err(e) => errors.push(format!("wl-paste missing: {}", e)),
+    }
+
+    match Command::new("xclip")
+        .args(["-selection", "clipboard", "-o"])
+        .output()
+    {
+        Ok(output) if output.status.success() => {
+            return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+        }
+        Ok(output) => errors.push(format!("xclip status {}", output.status)),
+        Err(e) => errors.push(format!("xclip missing: {}", e)),
+    }
+
+    anyhow::bail!("Clipboard unavailable ({})", errors.join("; "))
+}
+
+/// Run `program` with `text` on its stdin.
+fn pipe_to(program: &str, args: &[&str], text: &str) -> Result<(), String> {
+    // Both tools keep a background process owning the selection; with
+    // stdout inherited it would hold the terminal, so it is discarded.
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("missing: {}", e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(text.as_bytes())
+            .map_err(|e| format!("write failed: {}", e))?;
+    }
+    let status = child.wait().map_err(|e| e.to_string())?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("status {}", status))
+    }
+}
+
+/// OSC 52 sequence setting the clipboard to `text`, `None` past
+/// [`OSC52_MAX_LEN`]. Inside tmux the sequence is wrapped so tmux passes
+/// it on to the outer terminal.
+pub fn osc52(text: &str) -> Option<String> {
+    let payload = base64(text.as_bytes());
+    if payload.len() > OSC52_MAX_LEN {
+        return None;
+    }
+    let sequence = format!("\x1b]52;c;{}\x07", payload);
+    if std::env::var_os("TMUX").is_some() {
+        Some(tmux_passthrough(&sequence))
+    } else {
+        Some(sequence)
+    }
+}
+
+/// `sequence` in a tmux DCS passthrough, with its escapes doubled.
+fn tmux_passthrough(sequence: &str) -> String {
+    format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+}
+
+/// Standard padded base64.
+fn base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (u32::from(b[0]) << 16) | (u32::from(b[1]) << 8) | u32::from(b[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64("привет".as_bytes()), "0L/RgNC40LLQtdGC");
+    }
+
+    #[test]
+    fn test_osc52_sequence() {
+        if std::env::var_os("TMUX").is_some() {
+            return;
+        }
+        assert_eq!(osc52("hi").as_deref(), Some("\x1b]52;c;aGk=\x07"));
+        assert!(osc52(&"x".repeat(OSC52_MAX_LEN)).is_none());
+    }
+
+    #[test]
+    fn test_tmux_passthrough_doubles_escapes() {
+        assert_eq!(
+            tmux_passthrough("\x1b]52;c;aGk=\x07"),
+            "\x1bPtmux;\x1b\x1b]52;c;aGk=\x07\x1b\\"
+        );
+    }
+}
@@ -1,8 +1,56 @@
 //! Parser for command mode (colon-commands).
+use crate::config::clamp_sidebar_width;
 use crate::state::{
-    App, AsyncAction, AttachmentInfo, CommandSuggestion, CompletionState, Focus, PathEntry,
+    App, AsyncAction, AttachmentInfo, Chat, ChatFilter, ChatNameOption, CommandSuggestion,
+    CompletionState, Focus, ForwardStage, ForwardState, HistoryExport, LogView, PathEntry,
     SubcommandOption,
 };
+use vk_core::MuteDuration;
+use vk_core::export;
+use vk_core::logging;
+use vk_core::upload;
+
+/// Lines shown by `:log tail`
+const LOG_TAIL_LINES: usize = 200;
+
+/// Conversations offered at most when completing a chat argument
+const CHAT_COMPLETIONS: usize = 10;
+
+/// What a command takes after its name
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgumentKind {
+    None,
+    /// Free text, e.g. a message or a title
+    Text,
+    /// One of the options of [`generate_subcommand_completions`]
+    Choice,
+    /// A conversation, by title or peer id
+    Chat,
+}
+
+/// Argument of `command`, by any of its names. Drives completion after
+/// the command name.
+pub fn argument_kind(command: &str) -> ArgumentKind {
+    match command {
+        "s" | "search" | "dm" | "f" | "forward" | "fwd" => ArgumentKind::Chat,
+        "attach" | "export" | "log" | "set" | "cache" | "chat" | "react" => ArgumentKind::Choice,
+        "m" | "msg" | "grep" | "rename" | "newchat" | "mute" | "w" | "whois" | "stats" => {
+            ArgumentKind::Text
+        }
+        _ => ArgumentKind::None,
+    }
+}
+
+/// The chat a command argument names: a peer id of a listed chat, or a
+/// title as [`crate::cli::resolve_chat`] matches it.
+fn chat_argument<'a>(chats: &'a [Chat], arg: &str) -> Result<&'a Chat, String> {
+    if let Ok(peer_id) = arg.trim().parse::<i64>()
+        && let Some(chat) = chats.iter().find(|c| c.id == peer_id)
+    {
+        return Ok(chat);
+    }
+    crate::cli::resolve_chat(chats, arg).map_err(|e| e.to_string())
+}
 
 pub fn handle_command(app: &mut App, cmd: &str) -> Option<crate::message::Message> {
     // Remove leading ':' if present
@@ -16,22 +64,47 @@ pub fn handle_command(app: &mut App, cmd: &str) -> Option<crate::message::Messag
         "q" | "quit" | "qa" | "quitall" => {
             app.running_state = crate::state::RunningState::Done;
         }
+        "logout" => {
+            return Some(crate::message::Message::Logout);
+        }
         "b" | "back" => {
             app.focus = Focus::ChatList;
             app.current_peer_id = None;
         }
         "s" | "search" => {
             if parts.len() > 1 {
-                let query = parts[1..].join(" ");
-                app.status = Some(format!("Search: {} (not yet implemented)", query));
+                // Spaces in the query are kept as typed
+                let query = cmd.trim_start()[parts[0].len()..].trim().to_string();
+                let mut filter = ChatFilter::new();
+                filter.filtered_indices = vk_core::fuzzy::filter_chats(&app.chats, &query);
+                filter.cursor = query.chars().count();
+                filter.query = query;
+                app.chat_filter = Some(filter);
+                app.selected_chat = 0;
+                app.focus = Focus::ChatList;
             } else {
                 app.status = Some("Usage: :search <query>".into());
             }
         }
+        "dm" => {
+            if parts.len() > 1 {
+                match chat_argument(&app.chats, &parts[1..].join(" ")) {
+                    Ok(chat) => {
+                        let (peer_id, title) = (chat.id, chat.title.clone());
+                        app.open_chat(peer_id, &title);
+                    }
+                    Err(e) => app.status = Some(e),
+                }
+            } else {
+                app.status = Some("Usage: :dm <chat>".into());
+            }
+        }
         "m" | "msg" => {
             if parts.len() > 1 {
                 let text = parts[1..].join(" ");
-                if let Some(peer_id) = app.current_peer_id {
+                if let Some(denied) = app.write_denied() {
+                    app.status = Some(denied);
+                } else if let Some(peer_id) = app.current_peer_id {
                     app.send_action(AsyncAction::SendMessage(peer_id, text));
                 } else {
                     app.status = Some("No chat selected".into());
@@ -41,22 +114,36 @@ pub fn handle_command(app: &mut App, cmd: &str) -> Option<crate::message::Messag
             }
         }
         "ap" | "attach" => {
-            if parts.len() > 2 && parts[1] == "photo" {
-                let path = parts[2..].join(" ");
+            if let Some(denied) = app.write_denied() {
+                app.status = Some(denied);
+            } else if parts.len() > 2 && parts[1] == "photo" {
+                let paths = upload::split_paths(&parts[2..].join(" "));
                 if let Some(peer_id) = app.current_peer_id {
-                    app.send_action(AsyncAction::SendPhoto(peer_id, path));
+                    let local_id = app.next_upload_id();
+                    app.send_action(AsyncAction::SendPhoto(
+                        peer_id,
+                        paths,
+                        local_id,
+                        vk_api::new_random_id(),
+                    ));
                 } else {
                     app.status = Some("No chat selected".into());
                 }
             } else if parts.len() > 2 && parts[1] == "doc" {
-                let path = parts[2..].join(" ");
+                let paths = upload::split_paths(&parts[2..].join(" "));
                 if let Some(peer_id) = app.current_peer_id {
-                    app.send_action(AsyncAction::SendDoc(peer_id, path));
+                    let local_id = app.next_upload_id();
+                    app.send_action(AsyncAction::SendDoc(
+                        peer_id,
+                        paths,
+                        local_id,
+                        vk_api::new_random_id(),
+                    ));
                 } else {
                     app.status = Some("No chat selected".into());
                 }
             } else {
-                app.status = Some("Usage: :attach photo|doc <path>".into());
+                app.status = Some("Usage: :attach photo|doc <path>...".into());
             }
         }
         "dl" | "download" => {
@@ -64,7 +151,7 @@ pub fn handle_command(app: &mut App, cmd: &str) -> Option<crate::message::Messag
                 let downloadable: Vec<AttachmentInfo> = msg
                     .attachments
                     .iter()
-                    .filter(|a| a.url.is_some())
+                    .filter(|a| a.is_downloadable())
                     .cloned()
                     .collect();
                 if downloadable.is_empty() {
@@ -75,14 +162,247 @@ pub fn handle_command(app: &mut App, cmd: &str) -> Option<crate::message::Messag
                 }
             }
         }
+        "export" => {
+            let format = parts.get(1).and_then(|f| export::ExportFormat::parse(f));
+            let path_start = if format.is_some() { 2 } else { 1 };
+            let format = format.unwrap_or_default();
+            if app.history_export.is_some() {
+                app.status = Some("An export is already running".into());
+            } else if let Some(peer_id) = app.current_peer_id {
+                let title = app
+                    .chats
+                    .iter()
+                    .find(|c| c.id == peer_id)
+                    .map_or_else(|| peer_id.to_string(), |c| c.title.clone());
+                let path = match parts.get(path_start..).filter(|p| !p.is_empty()) {
+                    Some(path) => std::path::PathBuf::from(path.join(" ")),
+                    None => export::default_path(&app.settings.download_dir(), &title, format),
+                };
+                let (cancel, cancel_rx) = tokio::sync::watch::channel(false);
+                app.send_action(AsyncAction::ExportHistory(
+                    peer_id,
+                    title,
+                    format,
+                    path.clone(),
+                    cancel_rx,
+                ));
+                app.history_export = Some(HistoryExport { path, cancel });
+                app.status = Some("Exporting history... (Esc to cancel)".into());
+            } else {
+                app.status = Some("No chat selected".into());
+            }
+        }
+        "grep" => {
+            // Spaces in the pattern are kept as typed
+            let query = cmd.trim_start()["grep".len()..].trim();
+            if app.current_peer_id.is_none() {
+                app.status = Some("No chat selected".into());
+            } else if query.is_empty() {
+                app.status = Some("Usage: :grep <text> or :grep /regex/".into());
+            } else {
+                return Some(crate::message::Message::StartGrep(query.to_string()));
+            }
+        }
+        "cache" => match (parts.get(1), &app.cache) {
+            (Some(&"clear"), Some(cache)) => {
+                cache.clear();
+                app.status = Some("Message cache cleared".into());
+            }
+            (Some(&"clear"), None) => {
+                app.status = Some("Message cache is off".into());
+            }
+            _ => {
+                app.status = Some("Usage: :cache clear".into());
+            }
+        },
         "h" | "help" => {
             app.show_help = true;
         }
+        "w" | "whois" => {
+            let target = match parts.get(1) {
+                Some(id) => id.parse::<i64>().ok(),
+                None if app.focus == Focus::Messages => app.current_message().map(|m| m.from_id),
+                None => app.current_peer_id.filter(|id| !vk_api::is_chat_peer(*id)),
+            };
+            match target {
+                Some(user_id) => app.open_whois(user_id),
+                None => app.status = Some("Usage: :whois [user_id]".into()),
+            }
+        }
+        "mem" | "members" => match app.current_peer_id.filter(|id| vk_api::is_chat_peer(*id)) {
+            Some(peer_id) => app.open_members(peer_id),
+            None => app.status = Some("Not a group chat".into()),
+        },
+        "info" => match app.current_peer_id {
+            Some(peer_id) => app.open_chat_info(peer_id, false),
+            None => app.status = Some("No chat selected".into()),
+        },
+        "rename" => match app.current_peer_id.filter(|id| vk_api::is_chat_peer(*id)) {
+            Some(peer_id) if parts.len() > 1 => {
+                app.send_action(AsyncAction::RenameChat(peer_id, parts[1..].join(" ")));
+            }
+            Some(_) => app.status = Some("Usage: :rename <title>".into()),
+            None => app.status = Some("Not a group chat".into()),
+        },
+        "chat" => match app.current_peer_id.filter(|id| vk_api::is_chat_peer(*id)) {
+            Some(peer_id) if parts.get(1) == Some(&"rename") && parts.len() > 2 => {
+                app.send_action(AsyncAction::RenameChat(peer_id, parts[2..].join(" ")));
+            }
+            Some(peer_id) if parts.get(1) == Some(&"rename") => {
+                // Inline prompt: stay in command mode with the title to edit
+                let title = app
+                    .chats
+                    .iter()
+                    .find(|c| c.id == peer_id)
+                    .map(|c| c.title.clone())
+                    .unwrap_or_default();
+                app.command_input = format!("chat rename {}", title);
+                app.command_cursor = app.command_input.chars().count();
+                app.completion_state = CompletionState::Inactive;
+                app.status = Some("Edit the title and press Enter".into());
+                return Some(crate::message::Message::Noop);
+            }
+            Some(peer_id) if parts.get(1) == Some(&"photo") && parts.len() > 2 => {
+                app.send_action(AsyncAction::SetChatPhoto(peer_id, parts[2..].join(" ")));
+                app.status = Some("Checking chat permissions...".into());
+            }
+            Some(_) => app.status = Some("Usage: :chat rename [title] | :chat photo <path>".into()),
+            None => app.status = Some("Not a group chat".into()),
+        },
+        "react" => match parts.get(1).map(|e| vk_core::reaction_id(e)) {
+            Some(Some(reaction_id)) => app.react(reaction_id),
+            Some(None) => app.status = Some(format!("Unknown reaction: {}", parts[1])),
+            None => app.status = Some("Usage: :react <emoji>".into()),
+        },
+        "newchat" => {
+            if parts.len() > 1 {
+                app.open_new_chat(parts[1..].join(" "));
+            } else {
+                app.status = Some("Usage: :newchat <title>".into());
+            }
+        }
+        "leave" => match app.current_peer_id.filter(|id| vk_api::is_chat_peer(*id)) {
+            Some(peer_id) => app.leave_chat = Some(peer_id),
+            None => app.status = Some("Not a group chat".into()),
+        },
+        "mute" => {
+            let peer_id = app.current_peer_id.or(app.current_chat().map(|c| c.id));
+            let duration = MuteDuration::parse(parts.get(1).copied().unwrap_or("forever"));
+            match (peer_id, duration) {
+                (Some(peer_id), Ok(duration)) => {
+                    app.send_action(AsyncAction::SetChatMuted(peer_id, Some(duration)));
+                }
+                (None, _) => app.status = Some("No chat selected".into()),
+                (_, Err(e)) => app.status = Some(e),
+            }
+        }
+        "unmute" => match app.current_peer_id.or(app.current_chat().map(|c| c.id)) {
+            Some(peer_id) => app.send_action(AsyncAction::SetChatMuted(peer_id, None)),
+            None => app.status = Some("No chat selected".into()),
+        },
+        "pinchat" | "unpinchat" => match app.current_peer_id.or(app.current_chat().map(|c| c.id)) {
+            Some(peer_id) => {
+                app.send_action(AsyncAction::SetChatPinned(peer_id, parts[0] == "pinchat"));
+            }
+            None => app.status = Some("No chat selected".into()),
+        },
+        "delchat" => match app.current_peer_id.or(app.current_chat().map(|c| c.id)) {
+            Some(peer_id) => app.delete_chat = Some(peer_id),
+            None => app.status = Some("No chat selected".into()),
+        },
+        "reg" | "registers" => {
+            if app.registers.list().is_empty() {
+                app.status = Some("No registers yet".into());
+            } else {
+                app.show_registers = true;
+            }
+        }
+        "set" => match (
+            parts.get(1).copied(),
+            parts.get(2).map(|v| v.parse::<u16>()),
+        ) {
+            (Some("sidebar"), Some(Ok(percent))) => {
+                app.sidebar_width = clamp_sidebar_width(percent);
+                app.status = Some(format!("Chat list width: {}%", app.sidebar_width));
+            }
+            _ => app.status = Some("Usage: :set sidebar <percent>".into()),
+        },
+        "reconnect" => return Some(crate::message::Message::Reconnect),
+        "log" => {
+            let dir = logging::log_dir();
+            let file = dir.as_deref().and_then(logging::current_log_file);
+            match (parts.get(1).copied(), file) {
+                (Some("tail"), Some(path)) => match logging::tail(&path, LOG_TAIL_LINES) {
+                    Ok(lines) => {
+                        app.log_view = Some(LogView {
+                            path,
+                            lines,
+                            from_bottom: 0,
+                        });
+                    }
+                    Err(e) => {
+                        app.status = Some(format!("Cannot read {}: {}", path.display(), e));
+                    }
+                },
+                (Some("path"), Some(path)) => {
+                    app.status = Some(format!("Log file: {}", path.display()));
+                }
+                (Some("tail" | "path"), None) => {
+                    app.status = Some(match dir {
+                        Some(dir) => format!("No log file in {}", dir.display()),
+                        None => "No log directory on this system".into(),
+                    });
+                }
+                _ => app.status = Some("Usage: :log tail|path".into()),
+            }
+        }
+        "stats" => match parts.get(1).copied() {
+            Some("reset") => {
+                app.stats.reset();
+                app.status = Some("Stats reset".into());
+            }
+            Some(_) => app.status = Some("Usage: :stats [reset]".into()),
+            None => app.show_stats = true,
+        },
         "r" | "reply" => {
-            app.status = Some("Reply not yet implemented".into());
+            if app.current_peer_id.is_some() && app.current_message().is_some() {
+                // Command mode moved focus to the input; reply to the selection
+                app.focus = Focus::Messages;
+                return Some(crate::message::Message::ReplyToMessage);
+            }
+            app.status = Some("No message selected".into());
         }
         "f" | "forward" | "fwd" => {
-            app.status = Some("Forward not yet implemented".into());
+            let Some(msg) = app
+                .current_message()
+                .filter(|_| app.current_peer_id.is_some())
+            else {
+                app.status = Some("No message selected".into());
+                return None;
+            };
+            if parts.len() == 1 || msg.id == 0 || msg.is_service() {
+                // The key of the message checks it and asks for the chat
+                app.focus = Focus::Messages;
+                return Some(crate::message::Message::ForwardMessage);
+            }
+            let source_message_id = msg.id;
+            match chat_argument(&app.chats, &parts[1..].join(" ")) {
+                Ok(chat) => {
+                    app.status = Some(format!("Forward to {}: add comment or Enter", chat.title));
+                    app.forward = Some(ForwardState {
+                        source_message_ids: vec![source_message_id],
+                        query: String::new(),
+                        filtered: Vec::new(),
+                        selected: 0,
+                        comment: String::new(),
+                        stage: ForwardStage::EnterComment {
+                            peer_id: chat.id,
+                            title: chat.title.clone(),
+                        },
+                    });
+                }
+                Err(e) => app.status = Some(e),
+            }
         }
         "p" | "pin" => {
             app.status = Some("Pin/unpin not yet implemented".into());
@@ -103,6 +423,11 @@ pub fn generate_suggestions(input: &str) -> Vec<CommandSuggestion> {
             description: "Quit application".to_string(),
             usage: Some(":q, :quit, :qa, :quitall".to_string()),
         },
+        CommandSuggestion {
+            command: "logout".to_string(),
+            description: "Log out and return to authorization".to_string(),
+            usage: Some(":logout".to_string()),
+        },
         CommandSuggestion {
             command: "back".to_string(),
             description: "Return to chat list".to_string(),
@@ -113,6 +438,11 @@ pub fn generate_suggestions(input: &str) -> Vec<CommandSuggestion> {
             description: "Search conversations".to_string(),
             usage: Some(":search <query>, :s <query>".to_string()),
         },
+        CommandSuggestion {
+            command: "dm".to_string(),
+            description: "Open a conversation".to_string(),
+            usage: Some(":dm <chat>".to_string()),
+        },
         CommandSuggestion {
             command: "msg".to_string(),
             description: "Quick send message".to_string(),
@@ -133,11 +463,121 @@ pub fn generate_suggestions(input: &str) -> Vec<CommandSuggestion> {
             description: "Download attachments from selected message".to_string(),
             usage: Some(":download, :dl".to_string()),
         },
+        CommandSuggestion {
+            command: "export".to_string(),
+            description: "Save the chat history to a file".to_string(),
+            usage: Some(":export [json|html] [path]".to_string()),
+        },
+        CommandSuggestion {
+            command: "grep".to_string(),
+            description: "Search this chat offline".to_string(),
+            usage: Some(":grep <text> | :grep /regex/".to_string()),
+        },
+        CommandSuggestion {
+            command: "cache clear".to_string(),
+            description: "Delete the cached chats and messages".to_string(),
+            usage: Some(":cache clear".to_string()),
+        },
         CommandSuggestion {
             command: "help".to_string(),
             description: "Show help popup".to_string(),
             usage: Some(":help, :h".to_string()),
         },
+        CommandSuggestion {
+            command: "whois".to_string(),
+            description: "Show profile of the sender or chat peer".to_string(),
+            usage: Some(":whois [user_id], :w".to_string()),
+        },
+        CommandSuggestion {
+            command: "members".to_string(),
+            description: "List members of the group chat".to_string(),
+            usage: Some(":members, :mem".to_string()),
+        },
+        CommandSuggestion {
+            command: "info".to_string(),
+            description: "Show media counts of the chat".to_string(),
+            usage: Some(":info".to_string()),
+        },
+        CommandSuggestion {
+            command: "rename".to_string(),
+            description: "Rename the group chat".to_string(),
+            usage: Some(":rename <title>".to_string()),
+        },
+        CommandSuggestion {
+            command: "chat rename".to_string(),
+            description: "Edit the title of the group chat".to_string(),
+            usage: Some(":chat rename [title]".to_string()),
+        },
+        CommandSuggestion {
+            command: "chat photo".to_string(),
+            description: "Set the group chat photo from a file".to_string(),
+            usage: Some(":chat photo <path>".to_string()),
+        },
+        CommandSuggestion {
+            command: "react".to_string(),
+            description: "Toggle a reaction on the selected message".to_string(),
+            usage: Some(":react <emoji>".to_string()),
+        },
+        CommandSuggestion {
+            command: "newchat".to_string(),
+            description: "Create a group chat with picked users".to_string(),
+            usage: Some(":newchat <title>".to_string()),
+        },
+        CommandSuggestion {
+            command: "leave".to_string(),
+            description: "Leave the group chat".to_string(),
+            usage: Some(":leave".to_string()),
+        },
+        CommandSuggestion {
+            command: "mute".to_string(),
+            description: "Turn off notifications of the chat".to_string(),
+            usage: Some(":mute [1h|8h|forever]".to_string()),
+        },
+        CommandSuggestion {
+            command: "unmute".to_string(),
+            description: "Turn notifications of the chat back on".to_string(),
+            usage: Some(":unmute".to_string()),
+        },
+        CommandSuggestion {
+            command: "pinchat".to_string(),
+            description: "Pin the chat to the top of the chat list".to_string(),
+            usage: Some(":pinchat".to_string()),
+        },
+        CommandSuggestion {
+            command: "unpinchat".to_string(),
+            description: "Unpin the chat from the top of the chat list".to_string(),
+            usage: Some(":unpinchat".to_string()),
+        },
+        CommandSuggestion {
+            command: "delchat".to_string(),
+            description: "Delete the conversation and its history".to_string(),
+            usage: Some(":delchat".to_string()),
+        },
+        CommandSuggestion {
+            command: "registers".to_string(),
+            description: "Show yank registers".to_string(),
+            usage: Some(":registers, :reg".to_string()),
+        },
+        CommandSuggestion {
+            command: "set sidebar".to_string(),
+            description: "Width of the chat list, percent of the screen".to_string(),
+            usage: Some(":set sidebar <percent>".to_string()),
+        },
+        CommandSuggestion {
+            command: "reconnect".to_string(),
+            description: "Reconnect to VK now (Ctrl+R)".to_string(),
+            usage: None,
+        },
+        CommandSuggestion {
+            command: "log".to_string(),
+            description: "Show the end of the log file, or its path".to_string(),
+            usage: Some(":log tail|path".to_string()),
+        },
+        CommandSuggestion {
+            command: "stats".to_string(),
+            description: "Show session statistics".to_string(),
+            usage: Some(":stats [reset]".to_string()),
+        },
         CommandSuggestion {
             command: "reply".to_string(),
             description: "Reply to selected message".to_string(),
@@ -146,7 +586,7 @@ pub fn generate_suggestions(input: &str) -> Vec<CommandSuggestion> {
         CommandSuggestion {
             command: "forward".to_string(),
             description: "Forward selected message".to_string(),
-            usage: Some(":forward, :f, :fwd".to_string()),
+            usage: Some(":forward [chat], :f, :fwd".to_string()),
         },
         CommandSuggestion {
             command: "pin".to_string(),
@@ -188,6 +628,50 @@ fn generate_subcommand_completions(command: &str, input: &str) -> CompletionStat
                 description: "Attach document".to_string(),
             },
         ],
+        "export" => vec![
+            SubcommandOption {
+                name: "json".to_string(),
+                description: "JSON array of the messages".to_string(),
+            },
+            SubcommandOption {
+                name: "html".to_string(),
+                description: "Standalone HTML transcript".to_string(),
+            },
+        ],
+        "log" => vec![
+            SubcommandOption {
+                name: "tail".to_string(),
+                description: "Last log lines in a popup".to_string(),
+            },
+            SubcommandOption {
+                name: "path".to_string(),
+                description: "Where the log file is".to_string(),
+            },
+        ],
+        "set" => vec![SubcommandOption {
+            name: "sidebar".to_string(),
+            description: "Chat list width, percent".to_string(),
+        }],
+        "cache" => vec![SubcommandOption {
+            name: "clear".to_string(),
+            description: "Delete the cached chats and messages".to_string(),
+        }],
+        "chat" => vec![
+            SubcommandOption {
+                name: "rename".to_string(),
+                description: "Edit the chat title".to_string(),
+            },
+            SubcommandOption {
+                name: "photo".to_string(),
+                description: "Set the chat photo from a file".to_string(),
+            },
+        ],
+        "react" => vk_core::reaction_emojis()
+            .map(|emoji| SubcommandOption {
+                name: emoji.to_string(),
+                description: "Toggle this reaction".to_string(),
+            })
+            .collect(),
         _ => vec![],
     };
 
@@ -209,6 +693,49 @@ fn generate_subcommand_completions(command: &str, input: &str) -> CompletionStat
     }
 }
 
+/// Conversations matching `query` for the chat argument of `command`,
+/// best first
+fn generate_chat_completions(command: &str, query: &str, chats: &[Chat]) -> CompletionState {
+    let query = query.trim();
+    let options: Vec<ChatNameOption> = vk_core::fuzzy::filter_chats(chats, query)
+        .into_iter()
+        .take(CHAT_COMPLETIONS)
+        .map(|idx| {
+            let chat = &chats[idx];
+            let lower = chat.title.to_lowercase();
+            let ambiguous = chats
+                .iter()
+                .any(|c| c.id != chat.id && c.title.to_lowercase() == lower);
+            ChatNameOption {
+                peer_id: chat.id,
+                title: chat.title.clone(),
+                insert: if ambiguous {
+                    chat.id.to_string()
+                } else {
+                    chat.title.clone()
+                },
+            }
+        })
+        .collect();
+
+    // The argument is complete already: Enter runs the command
+    if let [only] = options.as_slice()
+        && only.insert == query
+    {
+        return CompletionState::Inactive;
+    }
+
+    if options.is_empty() {
+        CompletionState::Inactive
+    } else {
+        CompletionState::ChatNames {
+            command: command.to_string(),
+            options,
+            selected: 0,
+        }
+    }
+}
+
 /// Generate file path completions
 fn generate_filepath_completions(input: &str, base: &str) -> CompletionState {
     use std::fs;
@@ -288,8 +815,9 @@ fn generate_filepath_completions(input: &str, base: &str) -> CompletionState {
 }
 
 /// Determine completion state based on input
-/// This is the FSM transition logic with context-aware parsing
-pub fn determine_completion_state(input: &str) -> CompletionState {
+/// This is the FSM transition logic with context-aware parsing; `chats`
+/// complete chat arguments
+pub fn determine_completion_state(input: &str, chats: &[Chat]) -> CompletionState {
     // Remove leading ':' if present
     let trimmed = input.trim_start_matches(':');
     let parts: Vec<&str> = trimmed.split_whitespace().collect();
@@ -319,22 +847,33 @@ pub fn determine_completion_state(input: &str) -> CompletionState {
             }
         }
 
-        // Stage 2: Subcommand completion for "attach"
-        // Examples: ":attach " or ":attach ph"
-        (["attach"], true) => generate_subcommand_completions("attach", ""),
-        (["attach", sub], false) => generate_subcommand_completions("attach", sub),
+        // Stage 2: Subcommand completion, e.g. ":attach " or ":export ht"
+        ([command], true) if argument_kind(command) == ArgumentKind::Choice => {
+            generate_subcommand_completions(command, "")
+        }
+        ([command, sub], false) if argument_kind(command) == ArgumentKind::Choice => {
+            generate_subcommand_completions(command, sub)
+        }
 
-        // Stage 3: File path completion for "attach photo|doc"
+        // Stage 3: File path completion for "attach photo|doc" and "chat photo"
         // Examples: ":attach photo " or ":attach photo /home/user/fi"
-        (["attach", "photo" | "doc"], true) => generate_filepath_completions("", "."),
-        (["attach", "photo" | "doc", path @ ..], _) => {
+        (["attach", "photo" | "doc"] | ["chat", "photo"], true) => {
+            generate_filepath_completions("", ".")
+        }
+        (["attach", "photo" | "doc", path @ ..] | ["chat", "photo", path @ ..], _) => {
             let path_str = path.join(" ");
             generate_filepath_completions(&path_str, ".")
         }
 
-        // Future extensions:
-        // (["search"], true) => generate_search_scope_completions(),
-        // (["forward"], true) => generate_chat_completions(),
+        // Conversation for commands taking a chat; titles have spaces, so
+        // the whole rest of the input is the query
+        ([command, ..], _) if argument_kind(command) == ArgumentKind::Chat && parts.len() > 1 => {
+            let query = trimmed.trim_start()[command.len()..].trim_start();
+            generate_chat_completions(command, query, chats)
+        }
+        ([command], true) if argument_kind(command) == ArgumentKind::Chat => {
+            generate_chat_completions(command, "", chats)
+        }
 
         // Default: no completion
         _ => CompletionState::Inactive,
@@ -0,0 +1,214 @@
+//! TUI options: the `[tui]` section of the shared `settings.toml`, or
+//! `config.toml` in the app config directory if there is no such section
+
+use std::path::PathBuf;
+
+use serde::Deserialize;
+use vk_core::config::{Frontend, Settings};
+use vk_core::timeline::{Clock, HourFormat, parse_utc_offset};
+use vk_core::window::DEFAULT_MESSAGE_WINDOW;
+
+/// Chat list width, percent of the screen, unless configured otherwise
+pub const DEFAULT_SIDEBAR_WIDTH: u16 = 30;
+
+/// Chat list widths accepted, percent of the screen
+pub const SIDEBAR_WIDTH_RANGE: std::ops::RangeInclusive<u16> = 15..=70;
+
+/// User-tunable behaviour. Missing keys keep their defaults.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Ask before sending text that was typed while another chat was open
+    pub confirm_cross_chat_send: bool,
+    /// Local port catching the OAuth redirect (`Ctrl+L` on the auth
+    /// screen); `None` uses the default
+    pub redirect_port: Option<u16>,
+    /// Messages of the open chat kept in memory; `None` uses the default
+    pub message_window: Option<usize>,
+    /// `"24h"` or `"12h"` message times
+    pub time_format: HourFormat,
+    /// Timezone of message times, like `"+03:00"`; `None` is UTC
+    pub utc_offset: Option<String>,
+    /// Leave the mouse to the terminal, so its text selection works
+    pub disable_mouse: bool,
+    /// Chat list width, percent of the screen; `None` uses the default
+    pub sidebar_width: Option<u16>,
+}
+
+impl Config {
+    /// Load the options from `settings`, falling back to `config.toml`.
+    /// Anything invalid keeps its default and is added to `problems`.
+    pub fn load(settings: &Settings, problems: &mut Vec<String>) -> Self {
+        let config = match settings.section::<Self>(Frontend::Tui) {
+            Some(Ok(config)) => config,
+            Some(Err(e)) => {
+                problems.push(e);
+                Self::default()
+            }
+            None => Self::load_legacy(problems),
+        };
+        if let Some(text) = &config.utc_offset
+            && parse_utc_offset(text).is_none()
+        {
+            problems.push(format!("invalid utc_offset {:?}, using UTC", text));
+        }
+        config
+    }
+
+    /// `config.toml`, from before the shared settings file
+    fn load_legacy(problems: &mut Vec<String>) -> Self {
+        let Some(path) = Self::path() else {
+            return Self::default();
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(data) => Self::parse(&data).unwrap_or_else(|e| {
+                problems.push(format!("{}: {}", path.display(), e.message()));
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Messages of the open chat kept in memory while scrolling
+    pub fn message_window(&self) -> usize {
+        self.message_window.unwrap_or(DEFAULT_MESSAGE_WINDOW)
+    }
+
+    /// Chat list width, percent of the screen, kept within
+    /// [`SIDEBAR_WIDTH_RANGE`]
+    pub fn sidebar_width(&self) -> u16 {
+        clamp_sidebar_width(self.sidebar_width.unwrap_or(DEFAULT_SIDEBAR_WIDTH))
+    }
+
+    /// How message times are shown; an invalid offset falls back to UTC
+    pub fn clock(&self) -> Clock {
+        let mut clock = Clock {
+            hours: self.time_format,
+            ..Clock::default()
+        };
+        if let Some(offset) = self.utc_offset.as_deref().and_then(parse_utc_offset) {
+            clock.offset = offset;
+        }
+        clock
+    }
+
+    fn parse(data: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(data)
+    }
+
+    fn path() -> Option<PathBuf> {
+        directories::ProjectDirs::from("", "", "vk_tui")
+            .map(|dirs| dirs.config_dir().join("config.toml"))
+    }
+}
+
+/// `percent` moved into [`SIDEBAR_WIDTH_RANGE`]
+pub fn clamp_sidebar_width(percent: u16) -> u16 {
+    percent.clamp(*SIDEBAR_WIDTH_RANGE.start(), *SIDEBAR_WIDTH_RANGE.end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_when_empty() {
+        let config = Config::parse("").unwrap();
+        assert!(!config.confirm_cross_chat_send);
+    }
+
+    #[test]
+    fn test_confirm_cross_chat_send() {
+        let config = Config::parse("confirm_cross_chat_send = true\n").unwrap();
+        assert!(config.confirm_cross_chat_send);
+    }
+
+    #[test]
+    fn test_redirect_port() {
+        assert_eq!(Config::parse("").unwrap().redirect_port, None);
+        let config = Config::parse("redirect_port = 9000\n").unwrap();
+        assert_eq!(config.redirect_port, Some(9000));
+    }
+
+    #[test]
+    fn test_message_window() {
+        assert_eq!(
+            Config::parse("").unwrap().message_window(),
+            DEFAULT_MESSAGE_WINDOW
+        );
+        let config = Config::parse("message_window = 2000\n").unwrap();
+        assert_eq!(config.message_window(), 2000);
+    }
+
+    #[test]
+    fn test_clock() {
+        assert_eq!(Config::parse("").unwrap().clock(), Clock::default());
+        let clock = Config::parse("time_format = \"12h\"\nutc_offset = \"+03:00\"\n")
+            .unwrap()
+            .clock();
+        assert_eq!(clock.hours, HourFormat::H12);
+        assert_eq!(clock.offset.whole_hours(), 3);
+        // A bad offset keeps UTC rather than rejecting the whole file
+        let config = Config::parse("utc_offset = \"Moscow\"\n").unwrap();
+        assert_eq!(config.clock(), Clock::default());
+    }
+
+    #[test]
+    fn test_disable_mouse() {
+        assert!(!Config::parse("").unwrap().disable_mouse);
+        assert!(
+            Config::parse("disable_mouse = true\n")
+                .unwrap()
+                .disable_mouse
+        );
+    }
+
+    #[test]
+    fn test_sidebar_width() {
+        assert_eq!(
+            Config::parse("").unwrap().sidebar_width(),
+            DEFAULT_SIDEBAR_WIDTH
+        );
+        assert_eq!(
+            Config::parse("sidebar_width = 40\n")
+                .unwrap()
+                .sidebar_width(),
+            40
+        );
+        // Too narrow to read a chat title, or too wide to read messages
+        assert_eq!(
+            Config::parse("sidebar_width = 5\n")
+                .unwrap()
+                .sidebar_width(),
+            15
+        );
+        assert_eq!(
+            Config::parse("sidebar_width = 95\n")
+                .unwrap()
+                .sidebar_width(),
+            70
+        );
+    }
+
+    #[test]
+    fn test_tui_section_of_settings() {
+        let (settings, _) = Settings::parse("[tui]\ndisable_mouse = true\nsidebar_width = 40\n");
+        let mut problems = Vec::new();
+        let config = Config::load(&settings, &mut problems);
+        assert!(problems.is_empty());
+        assert!(config.disable_mouse);
+        assert_eq!(config.sidebar_width(), 40);
+
+        // A wrong section is reported and the defaults are used
+        let (settings, _) = Settings::parse("[tui]\ndisable_mouse = \"yes\"\n");
+        let config = Config::load(&settings, &mut problems);
+        assert!(!config.disable_mouse);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].starts_with("[tui]: "));
+    }
+
+    #[test]
+    fn test_invalid_value_is_error() {
+        assert!(Config::parse("confirm_cross_chat_send = \"yes\"").is_err());
+    }
+}
@@ -20,6 +20,8 @@ pub enum Event {
     Mouse(MouseEvent),
     /// Terminal resize
     Resize(u16, u16),
+    /// Bracketed paste, delivered whole
+    Paste(String),
     /// VK event (new message, etc.)
     Vk(VkEvent),
 }
@@ -56,6 +58,7 @@ impl EventHandler {
                             CrosstermEvent::Key(key) => Event::Key(key),
                             CrosstermEvent::Mouse(mouse) => Event::Mouse(mouse),
                             CrosstermEvent::Resize(w, h) => Event::Resize(w, h),
+                            CrosstermEvent::Paste(text) => Event::Paste(text),
                             _ => continue,
                         };
                         if tx_clone.send(event).is_err() {
@@ -0,0 +1,402 @@
+//! Images drawn inside the terminal, for the photo preview popup
+//!
+//! Terminals with the kitty graphics protocol (kitty, WezTerm, Ghostty) get
+//! the raw pixels; those with sixel (foot, mlterm, contour) get a sixel
+//! image on a 216-colour palette. The image is written straight to stdout
+//! after ratatui has drawn the popup under it, and removed with a full
+//! redraw when the popup closes, since ratatui does not know it is there.
+
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+use base64::Engine;
+use crossterm::{cursor::MoveTo, queue};
+use image::RgbaImage;
+use image::imageops::{self, FilterType};
+use ratatui::{Terminal, backend::Backend, layout::Rect};
+
+/// Cell size assumed when the terminal does not report its pixels
+const DEFAULT_CELL: (u32, u32) = (8, 16);
+
+/// Largest payload of one kitty graphics escape, in bytes
+const KITTY_CHUNK: usize = 4096;
+
+/// Levels per channel of the sixel palette: 6×6×6 colours
+const SIXEL_LEVELS: u32 = 6;
+
+/// Pixels with less alpha are left transparent
+const OPAQUE_ALPHA: u8 = 128;
+
+/// How images are sent to the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphicsProtocol {
+    Kitty,
+    Sixel,
+}
+
+impl GraphicsProtocol {
+    /// Protocol of the terminal we run in, if it has one we know.
+    /// `VK_TUI_GRAPHICS=kitty|sixel|none` overrides the guess.
+    pub fn detect() -> Option<Self> {
+        let var = |name| std::env::var(name).ok();
+        Self::from_env(
+            var("VK_TUI_GRAPHICS").as_deref(),
+            var("TERM").as_deref(),
+            var("TERM_PROGRAM").as_deref(),
+            std::env::var_os("KITTY_WINDOW_ID").is_some(),
+        )
+    }
+
+    fn from_env(
+        forced: Option<&str>,
+        term: Option<&str>,
+        term_program: Option<&str>,
+        kitty_window: bool,
+    ) -> Option<Self> {
+        match forced.map(str::to_ascii_lowercase).as_deref() {
+            Some("kitty") => return Some(Self::Kitty),
+            Some("sixel") => return Some(Self::Sixel),
+            Some("none" | "off") => return None,
+            _ => {}
+        }
+
+        let term = term.unwrap_or("").to_ascii_lowercase();
+        // tmux and screen swallow the escapes unless told to pass them on
+        if term.starts_with("screen") || term.starts_with("tmux") {
+            return None;
+        }
+        let program = term_program.unwrap_or("");
+        if kitty_window
+            || term.contains("kitty")
+            || term.contains("ghostty")
+            || matches!(program, "WezTerm" | "ghostty")
+        {
+            Some(Self::Kitty)
+        } else if ["foot", "mlterm", "contour", "yaft"]
+            .iter()
+            .any(|name| term.starts_with(name))
+        {
+            Some(Self::Sixel)
+        } else {
+            None
+        }
+    }
+
+    /// Escape sequence drawing `image` at the cursor
+    pub fn encode(self, image: &RgbaImage) -> String {
+        match self {
+            Self::Kitty => encode_kitty(image),
+            Self::Sixel => encode_sixel(image),
+        }
+    }
+}
+
+/// The image drawn over the screen, kept in step with the popup
+pub struct ImageOverlay {
+    protocol: GraphicsProtocol,
+    /// Image and area last drawn
+    shown: Option<(Arc<RgbaImage>, Rect)>,
+}
+
+impl ImageOverlay {
+    pub fn new(protocol: GraphicsProtocol) -> Self {
+        Self {
+            protocol,
+            shown: None,
+        }
+    }
+
+    /// Draw `image` centered in `area` unless it is already there, or
+    /// remove the drawn one when `image` is `None`. Removing clears the
+    /// screen, so `redraw` is called to paint the interface again before
+    /// anything new is drawn over it.
+    pub fn sync<B: Backend + Write>(
+        &mut self,
+        terminal: &mut Terminal<B>,
+        image: Option<(&Arc<RgbaImage>, Rect)>,
+        redraw: impl FnOnce(&mut Terminal<B>) -> io::Result<()>,
+    ) -> io::Result<()> {
+        let unchanged = match (&self.shown, image) {
+            (Some((shown, shown_area)), Some((image, area))) => {
+                Arc::ptr_eq(shown, image) && *shown_area == area
+            }
+            (None, None) => true,
+            _ => false,
+        };
+        if unchanged {
+            return Ok(());
+        }
+
+        if self.remove(terminal)? {
+            redraw(terminal)?;
+        }
+        if let Some((image, area)) = image {
+            let cell = cell_size();
+            let fitted = fit(image, area, cell);
+            let (cols, rows) = cells_of(&fitted, cell);
+            let x = area.x + area.width.saturating_sub(cols) / 2;
+            let y = area.y + area.height.saturating_sub(rows) / 2;
+
+            let backend = terminal.backend_mut();
+            queue!(backend, MoveTo(x, y))?;
+            backend.write_all(self.protocol.encode(&fitted).as_bytes())?;
+            Write::flush(backend)?;
+            self.shown = Some((Arc::clone(image), area));
+        }
+        Ok(())
+    }
+
+    /// Remove the drawn image, clearing the screen so ratatui paints the
+    /// cells under it again. Returns whether there was one.
+    pub fn remove<B: Backend + Write>(&mut self, terminal: &mut Terminal<B>) -> io::Result<bool> {
+        if self.shown.take().is_none() {
+            return Ok(false);
+        }
+        if self.protocol == GraphicsProtocol::Kitty {
+            // Delete all placements, and the image data with them
+            terminal
+                .backend_mut()
+                .write_all(b"\x1b_Ga=d,d=A,q=2\x1b\\")?;
+        }
+        terminal.clear()?;
+        Ok(true)
+    }
+}
+
+/// Pixels of one terminal cell
+fn cell_size() -> (u32, u32) {
+    match crossterm::terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => (
+            u32::from(size.width) / u32::from(size.columns),
+            u32::from(size.height) / u32::from(size.rows),
+        ),
+        _ => DEFAULT_CELL,
+    }
+}
+
+/// `image` scaled down to fit `area`, keeping its aspect ratio. Small
+/// images are not enlarged.
+fn fit(image: &RgbaImage, area: Rect, cell: (u32, u32)) -> RgbaImage {
+    let max_w = u32::from(area.width) * cell.0;
+    let max_h = u32::from(area.height) * cell.1;
+    let (w, h) = image.dimensions();
+    if w <= max_w && h <= max_h {
+        return image.clone();
+    }
+    let scale = f64::min(
+        f64::from(max_w) / f64::from(w),
+        f64::from(max_h) / f64::from(h),
+    );
+    let new_w = ((f64::from(w) * scale) as u32).max(1);
+    let new_h = ((f64::from(h) * scale) as u32).max(1);
+    imageops::resize(image, new_w, new_h, FilterType::Triangle)
+}
+
+/// Cells covered by `image`
+fn cells_of(image: &RgbaImage, cell: (u32, u32)) -> (u16, u16) {
+    let cols = image.width().div_ceil(cell.0.max(1));
+    let rows = image.height().div_ceil(cell.1.max(1));
+    (
+        u16::try_from(cols).unwrap_or(u16::MAX),
+        u16::try_from(rows).unwrap_or(u16::MAX),
+    )
+}
+
+/// Kitty graphics escapes transmitting and showing `image` as RGBA,
+/// split into chunks the terminal accepts. The cursor is not moved.
+fn encode_kitty(image: &RgbaImage) -> String {
+    let data = base64::engine::general_purpose::STANDARD.encode(image.as_raw());
+    let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut out = String::with_capacity(data.len() + chunks.len() * 16);
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        if i == 0 {
+            let _ = write!(
+                out,
+                "\x1b_Ga=T,f=32,s={},v={},C=1,q=2,m={};",
+                image.width(),
+                image.height(),
+                more
+            );
+        } else {
+            let _ = write!(out, "\x1b_Gm={};", more);
+        }
+        // Base64 is ASCII
+        out.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        out.push_str("\x1b\\");
+    }
+    out
+}
+
+/// Sixel image of `image` on a 6×6×6 colour cube. Mostly transparent
+/// pixels are left out, showing the popup under them.
+fn encode_sixel(image: &RgbaImage) -> String {
+    let (w, h) = image.dimensions();
+    let colours = (SIXEL_LEVELS * SIXEL_LEVELS * SIXEL_LEVELS) as usize;
+
+    // P2=1: pixels not drawn keep the background
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", w, h);
+    for index in 0..colours as u32 {
+        let level = |n: u32| n * 100 / (SIXEL_LEVELS - 1);
+        let _ = write!(
+            out,
+            "#{};2;{};{};{}",
+            index,
+            level(index / (SIXEL_LEVELS * SIXEL_LEVELS)),
+            level(index / SIXEL_LEVELS % SIXEL_LEVELS),
+            level(index % SIXEL_LEVELS)
+        );
+    }
+
+    // One band of six pixel rows at a time: for every colour in it, the
+    // bits of each column lit in that colour
+    let mut planes: Vec<Option<Vec<u8>>> = vec![None; colours];
+    for band in (0..h).step_by(6) {
+        for dy in 0..(h - band).min(6) {
+            for x in 0..w {
+                let pixel = image.get_pixel(x, band + dy);
+                if pixel[3] < OPAQUE_ALPHA {
+                    continue;
+                }
+                let plane =
+                    planes[palette_index(pixel.0)].get_or_insert_with(|| vec![0; w as usize]);
+                plane[x as usize] |= 1 << dy;
+            }
+        }
+
+        let mut first = true;
+        for (index, plane) in planes.iter_mut().enumerate() {
+            let Some(bits) = plane.take() else {
+                continue;
+            };
+            // `$` goes back to the start of the band for the next colour
+            if !first {
+                out.push('$');
+            }
+            first = false;
+            let _ = write!(out, "#{}", index);
+            push_sixel_runs(&mut out, &bits);
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+/// Palette entry nearest to an RGBA pixel
+fn palette_index([r, g, b, _]: [u8; 4]) -> usize {
+    let level = |v: u8| (u32::from(v) * (SIXEL_LEVELS - 1) + 127) / 255;
+    (level(r) * SIXEL_LEVELS * SIXEL_LEVELS + level(g) * SIXEL_LEVELS + level(b)) as usize
+}
+
+/// Sixel characters for `bits`, with `!n` repeats for runs
+fn push_sixel_runs(out: &mut String, bits: &[u8]) {
+    let mut runs = bits.chunk_by(|a, b| a == b).peekable();
+    while let Some(run) = runs.next() {
+        // Blank columns at the end need not be sent
+        if runs.peek().is_none() && run[0] == 0 {
+            break;
+        }
+        let c = char::from(0x3f + run[0]);
+        if run.len() > 3 {
+            let _ = write!(out, "!{}{}", run.len(), c);
+        } else {
+            out.extend(std::iter::repeat_n(c, run.len()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn test_detect_protocol() {
+        let detect = GraphicsProtocol::from_env;
+        assert_eq!(
+            detect(None, Some("xterm-kitty"), None, false),
+            Some(GraphicsProtocol::Kitty)
+        );
+        assert_eq!(
+            detect(None, Some("xterm-256color"), Some("WezTerm"), false),
+            Some(GraphicsProtocol::Kitty)
+        );
+        assert_eq!(
+            detect(None, Some("xterm-256color"), None, true),
+            Some(GraphicsProtocol::Kitty)
+        );
+        assert_eq!(
+            detect(None, Some("foot-extra"), None, false),
+            Some(GraphicsProtocol::Sixel)
+        );
+        assert_eq!(detect(None, Some("xterm-256color"), None, false), None);
+        assert_eq!(detect(None, Some("tmux-256color"), None, true), None);
+
+        // The variable wins over the guess
+        assert_eq!(
+            detect(Some("sixel"), Some("xterm-256color"), None, false),
+            Some(GraphicsProtocol::Sixel)
+        );
+        assert_eq!(detect(Some("none"), Some("xterm-kitty"), None, true), None);
+    }
+
+    #[test]
+    fn test_kitty_chunks() {
+        let small = RgbaImage::from_pixel(2, 1, Rgba([255, 0, 0, 255]));
+        assert_eq!(
+            encode_kitty(&small),
+            "\x1b_Ga=T,f=32,s=2,v=1,C=1,q=2,m=0;/wAA//8AAP8=\x1b\\"
+        );
+
+        // 64×64 RGBA is 16384 bytes, 21848 in base64: six chunks
+        let large = RgbaImage::new(64, 64);
+        let out = encode_kitty(&large);
+        assert_eq!(out.matches("\x1b_G").count(), 6);
+        assert_eq!(out.matches("m=1;").count(), 5);
+        assert!(out.ends_with("\x1b\\") && out.contains("\x1b_Gm=0;"));
+    }
+
+    #[test]
+    fn test_sixel_encoding() {
+        // Red column over blue, two pixels wide and seven high: two bands
+        let mut image = RgbaImage::from_pixel(2, 7, Rgba([0, 0, 255, 255]));
+        for y in 0..3 {
+            image.put_pixel(0, y, Rgba([255, 0, 0, 255]));
+        }
+        let out = encode_sixel(&image);
+        assert!(out.starts_with("\x1bP0;1;0q\"1;1;2;7#0;2;0;0;0"));
+        assert!(out.contains("#180;2;100;0;0#"));
+        assert!(out.ends_with("\x1b\\"));
+
+        let body = &out[out.find("#215;2;100;100;100").unwrap() + 18..out.len() - 2];
+        // Band 1: blue (5) in rows 3..6 of column 0 and all of column 1;
+        // red (180) in rows 0..3 of column 0. Band 2: blue in row 0.
+        assert_eq!(body, "#5w~$#180F-#5@@-");
+    }
+
+    #[test]
+    fn test_sixel_runs() {
+        let mut out = String::new();
+        push_sixel_runs(&mut out, &[63, 63, 63, 63, 63, 1, 0, 0]);
+        assert_eq!(out, "!5~@");
+
+        let mut out = String::new();
+        push_sixel_runs(&mut out, &[0, 0, 1]);
+        assert_eq!(out, "??@");
+    }
+
+    #[test]
+    fn test_fit_keeps_aspect() {
+        let area = Rect::new(0, 0, 10, 5);
+        let wide = RgbaImage::new(400, 100);
+        let fitted = fit(&wide, area, (8, 16));
+        assert_eq!(fitted.dimensions(), (80, 20));
+        assert_eq!(cells_of(&fitted, (8, 16)), (10, 2));
+
+        // Small images stay as they are
+        let small = RgbaImage::new(16, 16);
+        assert_eq!(fit(&small, area, (8, 16)).dimensions(), (16, 16));
+    }
+}
@@ -0,0 +1,180 @@
+//! History of `:` commands, kept across sessions.
+//!
+//! Commands go to `command_history.json` in the state directory, oldest
+//! first. Up/Down in command mode walk back through the ones starting with
+//! what is typed; Ctrl+R searches them like a shell does.
+
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use vk_core::persist::PersistedFile;
+
+/// Commands kept; older ones are dropped.
+pub const HISTORY_LIMIT: usize = 200;
+
+/// Schema version of `command_history.json`.
+const HISTORY_VERSION: u32 = 1;
+
+/// Executed commands, oldest first, without the leading `:`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CommandHistory {
+    entries: Vec<String>,
+    /// File the history is saved to; none keeps it in memory
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl CommandHistory {
+    /// History saved at `path`, empty if there is none yet.
+    pub fn load(path: PathBuf) -> Self {
+        let mut history: Self = PersistedFile::new(&path, HISTORY_VERSION).load();
+        history.path = Some(path);
+        history
+    }
+
+    /// Write the history to its file, if it has one.
+    pub fn save(&self) -> io::Result<()> {
+        match &self.path {
+            Some(path) => PersistedFile::new(path, HISTORY_VERSION).save(self),
+            None => Ok(()),
+        }
+    }
+
+    /// Record `command` and save. Blank commands and a repeat of the last
+    /// one are skipped.
+    pub fn append(&mut self, command: &str) {
+        let command = command.trim().trim_start_matches(':').trim();
+        if command.is_empty() || self.entries.last().is_some_and(|last| last == command) {
+            return;
+        }
+        self.entries.push(command.to_string());
+        let excess = self.entries.len().saturating_sub(HISTORY_LIMIT);
+        self.entries.drain(..excess);
+        if let Err(e) = self.save() {
+            tracing::debug!("Failed to save command history: {}", e);
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
+    /// The newest entry starting with `prefix` older than `before`, or
+    /// than all of them for `None`.
+    pub fn older(&self, prefix: &str, before: Option<usize>) -> Option<usize> {
+        let end = before.unwrap_or(self.entries.len()).min(self.entries.len());
+        self.entries[..end]
+            .iter()
+            .rposition(|entry| entry.starts_with(prefix))
+    }
+
+    /// The oldest entry starting with `prefix` newer than `after`.
+    pub fn newer(&self, prefix: &str, after: usize) -> Option<usize> {
+        self.entries
+            .iter()
+            .enumerate()
+            .skip(after + 1)
+            .find(|(_, entry)| entry.starts_with(prefix))
+            .map(|(idx, _)| idx)
+    }
+
+    /// The newest entry containing `query` from `from` back, like Ctrl+R
+    /// in a shell; `None` starts at the newest entry.
+    pub fn search(&self, query: &str, from: Option<usize>) -> Option<usize> {
+        let end = from.map_or(self.entries.len(), |idx| idx + 1);
+        self.entries[..end.min(self.entries.len())]
+            .iter()
+            .rposition(|entry| entry.contains(query))
+    }
+}
+
+/// Where the history is kept.
+pub fn history_path() -> Option<PathBuf> {
+    let dirs = directories::ProjectDirs::from("", "", "vk_tui")?;
+    // Only Linux has a state directory
+    let base = dirs.state_dir().unwrap_or_else(|| dirs.data_local_dir());
+    Some(base.join("command_history.json"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(entries: &[&str]) -> CommandHistory {
+        let mut history = CommandHistory::default();
+        for entry in entries {
+            history.append(entry);
+        }
+        history
+    }
+
+    fn entries(history: &CommandHistory) -> Vec<&str> {
+        history.entries.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn test_append_skips_repeats_and_blanks() {
+        let history = history(&[
+            ":set sidebar 30",
+            "set sidebar 30",
+            "  ",
+            "q",
+            "set sidebar 30",
+        ]);
+        assert_eq!(
+            entries(&history),
+            vec!["set sidebar 30", "q", "set sidebar 30"]
+        );
+    }
+
+    #[test]
+    fn test_append_keeps_the_limit() {
+        let mut history = CommandHistory::default();
+        for i in 0..HISTORY_LIMIT + 5 {
+            history.append(&format!("msg {}", i));
+        }
+        assert_eq!(history.entries.len(), HISTORY_LIMIT);
+        assert_eq!(history.get(0), Some("msg 5"));
+    }
+
+    #[test]
+    fn test_prefix_recall() {
+        let history = history(&["search ann", "q", "set sidebar 30", "search bob"]);
+
+        let newest = history.older("se", None);
+        assert_eq!(newest, Some(3));
+        assert_eq!(history.older("se", newest), Some(2));
+        assert_eq!(history.older("se", Some(2)), Some(0));
+        assert_eq!(history.older("se", Some(0)), None);
+
+        assert_eq!(history.newer("se", 0), Some(2));
+        assert_eq!(history.newer("se", 3), None);
+        assert_eq!(history.older("", None), Some(3));
+    }
+
+    #[test]
+    fn test_reverse_search() {
+        let history = history(&["dm Ann Lee", "q", "forward Ann Lee", "search bob"]);
+
+        let hit = history.search("Ann", None);
+        assert_eq!(hit, Some(2));
+        // Searching again goes on past the current hit
+        assert_eq!(history.search("Ann", Some(1)), Some(0));
+        assert_eq!(history.search("nobody", None), None);
+    }
+
+    #[test]
+    fn test_load_and_save() {
+        let dir = std::env::temp_dir().join(format!("vk_tui_history_{}", std::process::id()));
+        let path = dir.join("command_history.json");
+
+        let mut history = CommandHistory::load(path.clone());
+        history.append("dm Ann Lee");
+        history.append("q");
+
+        let loaded = CommandHistory::load(path);
+        assert_eq!(entries(&loaded), vec!["dm Ann Lee", "q"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -14,6 +14,23 @@ pub fn insert_char_at(s: &mut String, char_pos: usize, c: char) {
     s.insert(byte_idx, c);
 }
 
+/// Insert a string at character position, returning the position after it
+pub fn insert_str_at(s: &mut String, char_pos: usize, text: &str) -> usize {
+    let byte_idx = char_to_byte_index(s, char_pos);
+    s.insert_str(byte_idx, text);
+    char_pos + text.chars().count()
+}
+
+/// Pasted text as one line: the input has no newlines, and Enter in a
+/// paste must not send the message
+pub fn single_line(text: &str) -> String {
+    text.lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 /// Remove char at character position (not byte position)
 pub fn remove_char_at(s: &mut String, char_pos: usize) -> Option<char> {
     let byte_idx = char_to_byte_index(s, char_pos);
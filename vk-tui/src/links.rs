@@ -0,0 +1,134 @@
+//! Links in a message, for `o` and `yl`.
+
+use crate::state::ChatMessage;
+
+/// Characters that end a sentence rather than a URL when they come last.
+const TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?', '\'', '"'];
+
+/// Closing brackets kept at the end of a URL only if it opened them too,
+/// as in `https://en.wikipedia.org/wiki/Rust_(language)`.
+const BRACKETS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}')];
+
+/// URLs in `text`, in order and without repeats. A URL may start inside a
+/// word, as in `(https://vk.com)` or `[site](https://vk.com)`; punctuation
+/// and unmatched brackets after it are left out.
+pub fn extract_urls(text: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    let mut rest = text;
+    while let Some(start) = next_scheme(rest) {
+        let candidate = &rest[start..];
+        let end = candidate
+            .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '`'))
+            .unwrap_or(candidate.len());
+        let url = trim_url(&candidate[..end]);
+        // Just the scheme is not a link
+        if url.len() > url.find("://").map_or(0, |i| i + 3) && !urls.iter().any(|u| u == url) {
+            urls.push(url.to_string());
+        }
+        rest = &candidate[end..];
+    }
+    urls
+}
+
+/// Links of `msg`: those in its text, then those of its attachments.
+pub fn message_urls(msg: &ChatMessage) -> Vec<String> {
+    let mut urls = extract_urls(&msg.text);
+    for url in msg.attachments.iter().filter_map(|a| a.url.as_ref()) {
+        if !urls.contains(url) {
+            urls.push(url.clone());
+        }
+    }
+    urls
+}
+
+/// Byte offset of the next `http://` or `https://`.
+fn next_scheme(text: &str) -> Option<usize> {
+    let http = text.find("http://");
+    let https = text.find("https://");
+    match (http, https) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Drop sentence punctuation and closing brackets the URL did not open.
+fn trim_url(mut url: &str) -> &str {
+    loop {
+        let Some(last) = url.chars().last() else {
+            return url;
+        };
+        let unmatched = BRACKETS
+            .iter()
+            .find(|(_, close)| *close == last)
+            .is_some_and(|&(open, close)| url.matches(close).count() > url.matches(open).count());
+        if TRAILING_PUNCTUATION.contains(&last) || unmatched {
+            url = &url[..url.len() - last.len_utf8()];
+        } else {
+            return url;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_urls() {
+        assert_eq!(
+            extract_urls("see https://vk.com/dev and http://example.org/a?b=1&c=2"),
+            vec!["https://vk.com/dev", "http://example.org/a?b=1&c=2"]
+        );
+        assert!(extract_urls("no links here, just http:// and text").is_empty());
+    }
+
+    #[test]
+    fn test_trailing_slash_is_kept() {
+        assert_eq!(
+            extract_urls("docs: https://dev.vk.com/ref/, then"),
+            vec!["https://dev.vk.com/ref/"]
+        );
+        assert_eq!(extract_urls("https://vk.com/"), vec!["https://vk.com/"]);
+    }
+
+    #[test]
+    fn test_sentence_punctuation_is_dropped() {
+        assert_eq!(
+            extract_urls("Look: https://vk.com/feed. Or https://vk.com/im?!"),
+            vec!["https://vk.com/feed", "https://vk.com/im"]
+        );
+        assert_eq!(
+            extract_urls("\"https://vk.com/quoted\""),
+            vec!["https://vk.com/quoted"]
+        );
+    }
+
+    #[test]
+    fn test_brackets() {
+        assert_eq!(
+            extract_urls("(see https://vk.com/about)"),
+            vec!["https://vk.com/about"]
+        );
+        assert_eq!(
+            extract_urls(
+                "[site](https://vk.com/page), [wiki](https://en.wikipedia.org/wiki/Rust_(language))."
+            ),
+            vec![
+                "https://vk.com/page",
+                "https://en.wikipedia.org/wiki/Rust_(language)"
+            ]
+        );
+        assert_eq!(
+            extract_urls("<https://vk.com/angle>"),
+            vec!["https://vk.com/angle"]
+        );
+    }
+
+    #[test]
+    fn test_repeats_are_dropped() {
+        assert_eq!(
+            extract_urls("https://vk.com https://vk.com, https://vk.com/a"),
+            vec!["https://vk.com", "https://vk.com/a"]
+        );
+    }
+}
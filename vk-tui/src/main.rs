@@ -1,64 +1,53 @@
 mod actions;
 mod app;
+mod args;
+mod cli;
+mod clipboard;
 mod commands;
+mod config;
 mod event;
+mod graphics;
+mod history;
 mod input;
-mod longpoll;
+mod links;
 mod mapper;
 mod message;
-mod search;
+mod registers;
 mod state;
+mod terminal;
 mod ui;
 mod update;
 
-use std::io;
+use std::collections::VecDeque;
+use std::process::ExitCode;
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::Result;
-use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture},
-    execute,
-    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
-};
-use ratatui::{Terminal, backend::CrosstermBackend};
-use tokio::sync::mpsc;
-
-use event::{Event, VkEvent};
-use longpoll::handle_update;
+use clap::Parser;
+use tokio::sync::{Notify, mpsc, watch};
+use tokio::task::{JoinHandle, JoinSet};
+use tracing_subscriber::EnvFilter;
+
+use event::Event;
+use graphics::{GraphicsProtocol, ImageOverlay};
 use message::Message;
-use state::{App, AsyncAction, Screen};
+use state::{App, AsyncAction, Mode, Screen};
+use terminal::TerminalCaps;
 use update::update;
 use vk_api::{User, VkClient};
-
-/// Initialize terminal
-fn init_terminal() -> Result<Terminal<CrosstermBackend<io::Stdout>>> {
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let terminal = Terminal::new(backend)?;
-    Ok(terminal)
-}
-
-/// Restore terminal to original state
-fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
-    Ok(())
-}
+use vk_core::CoreEvent;
+use vk_core::batch::{Coalescer, FETCH_WINDOW};
+use vk_core::config::Settings;
+use vk_core::errors::error_text;
+use vk_core::logging;
+use vk_core::upload::UploadKind;
 
 /// Setup panic hook to restore terminal on panic
-fn setup_panic_hook() {
+fn setup_panic_hook(caps: TerminalCaps) {
     let original_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |panic_info| {
-        let _ = disable_raw_mode();
-        let _ = execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture);
+        caps.restore_raw();
         original_hook(panic_info);
     }));
 }
@@ -67,37 +56,53 @@ fn setup_panic_hook() {
 fn spawn_action_handler(
     mut action_rx: mpsc::UnboundedReceiver<AsyncAction>,
     message_tx: mpsc::UnboundedSender<Message>,
-    vk_client: Option<Arc<VkClient>>,
-) {
+    mut vk_client: watch::Receiver<Option<Arc<VkClient>>>,
+    settings: Settings,
+) -> JoinHandle<()> {
+    let page_size = settings.messages_page_size;
     tokio::spawn(async move {
-        while let Some(action) = action_rx.recv().await {
-            let client = match &vk_client {
-                Some(c) => c.clone(),
-                None => {
-                    let _ = message_tx.send(Message::Error("Not authenticated".into()));
-                    continue;
-                }
-            };
+        // Aborting the handler drops the set, cancelling what is in flight
+        let mut tasks = JoinSet::new();
+        let mut pending = VecDeque::new();
+        // Message ids waiting for their details to be fetched
+        let details = Coalescer::new(FETCH_WINDOW);
+        while let Some((client, action)) = next_action(
+            &mut action_rx,
+            &mut vk_client,
+            &mut pending,
+            &mut tasks,
+            &message_tx,
+        )
+        .await
+        {
+            while tasks.try_join_next().is_some() {}
 
             let tx = message_tx.clone();
 
             match action {
                 AsyncAction::ValidateSession => {
-                    tokio::spawn(actions::validate_session(client, tx));
+                    tasks.spawn(actions::validate_session(client, tx));
                 }
+                // Started by next_action, without waiting for a session
+                AsyncAction::ValidateToken(_) | AsyncAction::WaitForRedirect(..) => {}
                 AsyncAction::LoadConversations(offset) => {
-                    tokio::spawn(actions::load_conversations(client, offset, tx));
+                    tasks.spawn(actions::load_conversations(client, offset, page_size, tx));
                 }
                 AsyncAction::LoadMessages(peer_id, offset) => {
-                    tokio::spawn(actions::load_messages(client, peer_id, offset, tx));
+                    tasks.spawn(actions::load_messages(
+                        client, peer_id, offset, page_size, tx,
+                    ));
                 }
                 AsyncAction::LoadMessagesAround(peer_id, message_id) => {
-                    tokio::spawn(actions::load_messages_around(
-                        client, peer_id, message_id, tx,
+                    tasks.spawn(actions::load_messages_around(
+                        client, peer_id, message_id, page_size, tx,
                     ));
                 }
+                AsyncAction::ResolveReply(peer_id, cmid) => {
+                    tasks.spawn(actions::resolve_reply(client, peer_id, cmid, tx));
+                }
                 AsyncAction::LoadMessagesWithOffset(peer_id, start_message_id, offset, count) => {
-                    tokio::spawn(actions::load_messages_with_offset(
+                    tasks.spawn(actions::load_messages_with_offset(
                         client,
                         peer_id,
                         start_message_id,
@@ -107,46 +112,150 @@ fn spawn_action_handler(
                     ));
                 }
                 AsyncAction::SendMessage(peer_id, text) => {
-                    tokio::spawn(actions::send_message(client, peer_id, text, tx));
+                    tasks.spawn(actions::send_message(client, peer_id, text, tx));
+                }
+                AsyncAction::SendReply(peer_id, reply_to, text, random_id) => {
+                    tasks.spawn(actions::send_reply(
+                        client, peer_id, reply_to, text, random_id, tx,
+                    ));
                 }
-                AsyncAction::SendReply(peer_id, reply_to, text) => {
-                    tokio::spawn(actions::send_reply(client, peer_id, reply_to, text, tx));
+                AsyncAction::SendParts(peer_id, reply_to, parts) => {
+                    tasks.spawn(actions::send_parts(client, peer_id, reply_to, parts, tx));
                 }
                 AsyncAction::SendForward(peer_id, ids, comment) => {
-                    tokio::spawn(actions::send_forward(client, peer_id, ids, comment, tx));
+                    tasks.spawn(actions::send_forward(client, peer_id, ids, comment, tx));
                 }
-                AsyncAction::StartLongPoll => {
-                    tokio::spawn(run_long_poll(client, tx));
+                AsyncAction::StartLongPoll(shutdown, reconnect) => {
+                    tasks.spawn(run_long_poll(client, shutdown, reconnect, tx));
                 }
                 AsyncAction::MarkAsRead(peer_id) => {
-                    tokio::spawn(mark_as_read(client, peer_id, tx));
+                    tasks.spawn(mark_as_read(client, peer_id, tx));
                 }
-                AsyncAction::SendPhoto(peer_id, path) => {
-                    tokio::spawn(actions::send_photo_attachment(client, peer_id, path, tx));
+                AsyncAction::SendPhoto(peer_id, paths, local_id, random_id) => {
+                    tasks.spawn(actions::send_files(
+                        client,
+                        peer_id,
+                        UploadKind::Photo,
+                        paths,
+                        local_id,
+                        random_id,
+                        tx,
+                    ));
                 }
-                AsyncAction::SendDoc(peer_id, path) => {
-                    tokio::spawn(actions::send_doc_attachment(client, peer_id, path, tx));
+                AsyncAction::SendDoc(peer_id, paths, local_id, random_id) => {
+                    tasks.spawn(actions::send_files(
+                        client,
+                        peer_id,
+                        UploadKind::Doc,
+                        paths,
+                        local_id,
+                        random_id,
+                        tx,
+                    ));
                 }
                 AsyncAction::DownloadAttachments(atts) => {
-                    tokio::spawn(actions::download_attachments(atts, tx));
+                    tasks.spawn(actions::download_attachments(
+                        atts,
+                        settings.download_dir(),
+                        tx,
+                    ));
+                }
+                AsyncAction::PreviewPhoto(url, inline) => {
+                    tasks.spawn(actions::preview_photo(url, inline, tx));
+                }
+                AsyncAction::ExportHistory(peer_id, title, format, path, cancel) => {
+                    tasks.spawn(actions::export_history(
+                        client, peer_id, title, format, path, cancel, tx,
+                    ));
                 }
-                AsyncAction::EditMessage(peer_id, message_id, cmid, text) => {
-                    tokio::spawn(actions::edit_message(
-                        client, peer_id, message_id, cmid, text, tx,
+                AsyncAction::EditMessage(peer_id, message_id, cmid, text, base_hash) => {
+                    tasks.spawn(actions::edit_message(
+                        client, peer_id, message_id, cmid, text, base_hash, tx,
                     ));
                 }
                 AsyncAction::DeleteMessage(_peer_id, msg_id, delete_for_all) => {
-                    tokio::spawn(actions::delete_message(client, msg_id, delete_for_all, tx));
+                    tasks.spawn(actions::delete_message(client, msg_id, delete_for_all, tx));
+                }
+                AsyncAction::DeleteMessages(msg_ids, delete_for_all) => {
+                    tasks.spawn(actions::delete_messages(
+                        client,
+                        msg_ids,
+                        delete_for_all,
+                        tx,
+                    ));
                 }
                 AsyncAction::FetchMessageById(msg_id) => {
-                    tokio::spawn(actions::fetch_message_by_id(client, msg_id, tx));
+                    details.push(msg_id, move |msg_ids| {
+                        actions::fetch_message_details(client, msg_ids, tx)
+                    });
+                }
+                // Before the send it answers is started
+                AsyncAction::AnswerCaptcha(sid, key) => {
+                    client.answer_captcha(&sid, &key);
+                }
+                AsyncAction::SetReaction(peer_id, msg_id, cmid, reaction_id) => {
+                    tasks.spawn(actions::set_reaction(
+                        client,
+                        peer_id,
+                        msg_id,
+                        cmid,
+                        reaction_id,
+                        tx,
+                    ));
+                }
+                AsyncAction::GrepHistory(peer_id, title, query, loaded, cache) => {
+                    tasks.spawn(actions::grep_history(
+                        peer_id, title, query, loaded, cache, tx,
+                    ));
                 }
                 AsyncAction::SearchMessages(query) => {
-                    tokio::spawn(actions::search_messages(client, query, tx));
+                    tasks.spawn(actions::search_messages(client, query, tx));
+                }
+                AsyncAction::LoadUsers(user_ids) => {
+                    tasks.spawn(actions::load_users(client, user_ids, tx));
+                }
+                AsyncAction::LoadProfile => {
+                    tasks.spawn(actions::load_profile(client, tx));
+                }
+                AsyncAction::FetchUserProfile(user_id) => {
+                    tasks.spawn(actions::fetch_user_profile(client, user_id, tx));
+                }
+                AsyncAction::LoadChatMembers(peer_id, offset) => {
+                    tasks.spawn(actions::load_chat_members(client, peer_id, offset, tx));
+                }
+                AsyncAction::LeaveChat(peer_id, user_id) => {
+                    tasks.spawn(actions::leave_chat(client, peer_id, user_id, tx));
+                }
+                AsyncAction::LoadFriends => {
+                    tasks.spawn(actions::load_friends(client, tx));
+                }
+                AsyncAction::CreateChat(user_ids, title) => {
+                    tasks.spawn(actions::create_chat(client, user_ids, title, tx));
+                }
+                AsyncAction::FetchReadPeers(peer_id, cmid) => {
+                    tasks.spawn(actions::fetch_read_peers(client, peer_id, cmid, tx));
+                }
+                AsyncAction::LoadChatInfo(peer_id) => {
+                    tasks.spawn(actions::load_chat_info(client, peer_id, tx));
+                }
+                AsyncAction::RenameChat(peer_id, title) => {
+                    tasks.spawn(actions::rename_chat(client, peer_id, title, tx));
+                }
+                AsyncAction::SetChatPhoto(peer_id, path) => {
+                    tasks.spawn(actions::set_chat_photo(client, peer_id, path, tx));
+                }
+                AsyncAction::SetChatMuted(peer_id, duration) => {
+                    tasks.spawn(actions::set_chat_muted(client, peer_id, duration, tx));
+                }
+                AsyncAction::SetChatPinned(peer_id, pinned) => {
+                    tasks.spawn(actions::set_chat_pinned(client, peer_id, pinned, tx));
+                }
+                AsyncAction::DeleteConversation(peer_id) => {
+                    tasks.spawn(actions::delete_conversation(client, peer_id, tx));
                 }
             }
         }
-    });
+    })
 }
 
 /// Load conversations from VK API
@@ -187,90 +296,79 @@ fn get_user_online(peer_id: &i64, profiles: &[User]) -> bool {
 
 // mapping helpers moved to mapper.rs
 
-/// Load messages from VK API
-/// Run Long Poll loop for real-time updates
-async fn run_long_poll(client: Arc<VkClient>, tx: mpsc::UnboundedSender<Message>) {
-    tracing::info!("Starting Long Poll...");
-    let mut backoff = Duration::from_secs(1);
-
-    // Get Long Poll server
-    let mut server = match client.longpoll().get_server().await {
-        Ok(s) => {
-            tracing::info!("Got Long Poll server: {}", s.server);
-            s
-        }
-        Err(e) => {
-            tracing::error!("Failed to get Long Poll server: {}", e);
-            let _ = tx.send(Message::Error(format!("Long Poll error: {}", e)));
-            return;
-        }
-    };
-
-    let _ = tx.send(Message::VkEvent(VkEvent::ConnectionStatus(true)));
-
+/// Next action to run and the client to run it with, `None` once the app
+/// is gone. Without a client (the session expired) actions wait in
+/// `pending` and run in order once a new one is set. Actions of the
+/// login itself need no session; they are started in `tasks` right away.
+async fn next_action(
+    action_rx: &mut mpsc::UnboundedReceiver<AsyncAction>,
+    vk_client: &mut watch::Receiver<Option<Arc<VkClient>>>,
+    pending: &mut VecDeque<AsyncAction>,
+    tasks: &mut JoinSet<()>,
+    message_tx: &mpsc::UnboundedSender<Message>,
+) -> Option<(Arc<VkClient>, AsyncAction)> {
     loop {
-        match client.longpoll().poll(&server).await {
-            Ok(response) => {
-                // Handle failed responses
-                if let Some(failed) = response.failed {
-                    match failed {
-                        1 => {
-                            // Update ts
-                            if let Some(ts) = response.ts {
-                                server.ts = ts;
-                            }
-                        }
-                        2..=4 => {
-                            // Need to get new server
-                            match client.longpoll().get_server().await {
-                                Ok(new_server) => server = new_server,
-                                Err(e) => {
-                                    let _ = tx.send(Message::Error(format!(
-                                        "Long Poll reconnect error: {}",
-                                        e
-                                    )));
-                                    tokio::time::sleep(Duration::from_secs(5)).await;
-                                }
-                            }
-                        }
-                        _ => {}
-                    }
-                    continue;
+        let client = vk_client.borrow_and_update().clone();
+        if let Some(client) = client
+            && let Some(action) = pending.pop_front()
+        {
+            return Some((client, action));
+        }
+        tokio::select! {
+            action = action_rx.recv() => match action? {
+                AsyncAction::ValidateToken(client) => {
+                    tasks.spawn(actions::validate_token(client, message_tx.clone()));
                 }
-
-                // Update ts
-                if let Some(ts) = response.ts {
-                    server.ts = ts;
+                AsyncAction::WaitForRedirect(port, cancel) => {
+                    tasks.spawn(actions::wait_for_redirect(port, cancel, message_tx.clone()));
                 }
+                action => pending.push_back(action),
+            },
+            Ok(()) = vk_client.changed() => {}
+        }
+    }
+}
 
-                // Process updates
-                if let Some(updates) = response.updates {
-                    tracing::debug!("Got {} updates", updates.len());
-                    for update in updates {
-                        tracing::trace!("Update: {:?}", update);
-                        if let Some(event) = handle_update(&update) {
-                            let _ = tx.send(Message::VkEvent(event));
-                        }
-                    }
-                }
-                backoff = Duration::from_secs(1);
-            }
-            Err(e) => {
-                let _ = tx.send(Message::VkEvent(VkEvent::ConnectionStatus(false)));
-                let _ = tx.send(Message::Error(format!("Long Poll error: {}", e)));
-                tokio::time::sleep(backoff).await;
-                backoff = (backoff * 2).min(Duration::from_secs(30));
-
-                // Try to reconnect
-                match client.longpoll().get_server().await {
-                    Ok(new_server) => {
-                        server = new_server;
-                        let _ = tx.send(Message::VkEvent(VkEvent::ConnectionStatus(true)));
-                        backoff = Duration::from_secs(1);
-                    }
-                    Err(_) => continue,
-                }
-            }
+/// Start the action handler, replacing the previous one
+fn start_action_handler(app: &mut App, message_tx: &mpsc::UnboundedSender<Message>) {
+    let (action_tx, action_rx) = mpsc::unbounded_channel();
+    app.set_action_tx(action_tx);
+    app.set_action_handler(spawn_action_handler(
+        action_rx,
+        message_tx.clone(),
+        app.shared_client.subscribe(),
+        app.settings.clone(),
+    ));
+}
+
+/// Run the shared Long Poll loop, forwarding its events as messages
+async fn run_long_poll(
+    client: Arc<VkClient>,
+    shutdown: watch::Receiver<bool>,
+    reconnect: Arc<Notify>,
+    tx: mpsc::UnboundedSender<Message>,
+) {
+    let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+    tokio::spawn(vk_core::longpoll::run(
+        client, event_tx, shutdown, reconnect,
+    ));
+
+    // Ends when the loop stops and drops its sender
+    while let Some(event) = event_rx.recv().await {
+        let message = match event {
+            CoreEvent::VkEvent(event) => Message::VkEvent(event),
+            CoreEvent::LongPollKeyExpired => Message::LongPollKeyExpired,
+            CoreEvent::LongPollReconnecting { attempt } => Message::LongPollReconnecting(attempt),
+            CoreEvent::LongPollDegraded => Message::LongPollDegraded,
+            CoreEvent::PossibleConcurrentSession { .. } => Message::PossibleConcurrentSession,
+            CoreEvent::AuthExpired => Message::AuthExpired,
+            CoreEvent::Error {
+                text, occurrence, ..
+            } => Message::Error(error_text(&text, occurrence)),
+            _ => continue,
+        };
+        if tx.send(message).is_err() {
+            break;
         }
     }
 }
@@ -278,45 +376,85 @@ async fn run_long_poll(client: Arc<VkClient>, tx: mpsc::UnboundedSender<Message>
 /// Mark messages as read for a peer
 async fn mark_as_read(client: Arc<VkClient>, peer_id: i64, tx: mpsc::UnboundedSender<Message>) {
     if let Err(e) = client.messages().mark_as_read(peer_id).await {
-        let _ = tx.send(Message::Error(format!("Failed to mark as read: {}", e)));
+        let _ = tx.send(actions::api_error("Failed to mark as read", e));
     }
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    // Initialize tracing to write to file
-    let log_file = std::fs::File::create("vk_tui.log")?;
-    tracing_subscriber::fmt()
-        .with_writer(log_file)
-        .with_ansi(false)
-        .with_max_level(tracing::Level::DEBUG)
-        .init();
+async fn main() -> Result<ExitCode> {
+    let args = args::Args::parse();
+    if let Some(mode) = args.headless() {
+        return Ok(cli::run(mode).await);
+    }
+
+    // Log to a file only: anything on stderr would corrupt the screen
+    let filter = match &args.log_level {
+        Some(level) => EnvFilter::try_new(level)?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
+    let _log_guard = match logging::log_dir().map(|dir| logging::file_writer(&dir)) {
+        Some(Ok((writer, guard))) => {
+            tracing_subscriber::fmt()
+                .with_writer(writer)
+                .with_ansi(false)
+                .with_env_filter(filter)
+                .init();
+            Some(guard)
+        }
+        _ => None,
+    };
 
     tracing::info!("Starting vk-tui application");
 
+    // Create application state
+    let mut app = App::new();
+
+    // Resolve --chat before touching the terminal, so errors stay readable
+    let start_chat = match (&args.chat, &app.vk_client) {
+        (Some(name), Some(client)) => match cli::find_chat(client, name).await {
+            Ok(chat) => Some(chat),
+            Err(e) => {
+                eprintln!("vk-tui: {:#}", e);
+                return Ok(ExitCode::FAILURE);
+            }
+        },
+        (Some(_), None) => {
+            eprintln!("vk-tui: not logged in, --chat needs a saved session");
+            return Ok(ExitCode::FAILURE);
+        }
+        (None, _) => None,
+    };
+
+    // Detect what the terminal supports
+    let mut overrides = args.caps_overrides();
+    overrides.no_mouse |= app.config.disable_mouse;
+    let caps = TerminalCaps::detect(overrides);
+    tracing::info!("Terminal capabilities: {:?}", caps);
+
     // Setup panic hook
-    setup_panic_hook();
+    setup_panic_hook(caps);
 
     // Initialize terminal
-    let mut terminal = init_terminal()?;
+    let mut terminal = caps.init()?;
+    app.terminal_size = terminal.size()?;
 
-    // Create application state
-    let mut app = App::new();
+    // Photos are drawn in the terminal if it can, else opened in a viewer
+    app.graphics = GraphicsProtocol::detect().filter(|_| caps.alt_screen);
+    tracing::info!("Terminal graphics: {:?}", app.graphics);
+    let mut overlay = app.graphics.map(ImageOverlay::new);
 
-    // Create channels for async actions
-    let (action_tx, action_rx) = mpsc::unbounded_channel::<AsyncAction>();
     let (message_tx, mut message_rx) = mpsc::unbounded_channel::<Message>();
 
-    app.set_action_tx(action_tx);
-
-    // Spawn action handler with current VK client
-    spawn_action_handler(action_rx, message_tx.clone(), app.vk_client.clone());
+    start_action_handler(&mut app, &message_tx);
 
     // If already authenticated, validate session before loading
     if app.vk_client.is_some() {
         app.is_loading = true;
         app.send_action(AsyncAction::ValidateSession);
     }
+    if let Some(chat) = start_chat {
+        app.open_chat(chat.id, &chat.title);
+    }
 
     // Create event handler
     let mut events = event::EventHandler::new(Duration::from_millis(100));
@@ -324,7 +462,25 @@ async fn main() -> Result<()> {
     // Main loop
     while app.is_running() {
         // Draw UI
-        terminal.draw(|frame| ui::view(&app, frame))?;
+        let draw = |terminal: &mut ratatui::DefaultTerminal| {
+            terminal
+                .draw(|frame| {
+                    ui::view(&app, frame);
+                    caps.apply_colors(frame.buffer_mut());
+                })
+                .map(|_| ())
+        };
+        draw(&mut terminal)?;
+
+        // The photo goes over the popup drawn for it
+        if let Some(overlay) = &mut overlay {
+            let image = app
+                .photo_preview
+                .as_ref()
+                .and_then(|preview| preview.image.as_ref())
+                .zip(app.layout.borrow().photo);
+            overlay.sync(&mut terminal, image, draw)?;
+        }
 
         // Handle events
         tokio::select! {
@@ -343,6 +499,31 @@ async fn main() -> Result<()> {
                             } else {
                                 Message::Noop
                             }
+                        // Ctrl+R gets a new Long Poll server at once
+                        } else if key.code == KeyCode::Char('r')
+                            && key.modifiers.contains(KeyModifiers::CONTROL)
+                            && app.screen == Screen::Main
+                            && app.global_search.is_none()
+                        {
+                            Message::Reconnect
+                        // Ctrl+O goes back along the jump list; it opens the
+                        // OAuth URL only before logging in
+                        } else if key.code == KeyCode::Char('o')
+                            && key.modifiers.contains(KeyModifiers::CONTROL)
+                            && app.screen == Screen::Main
+                            && app.mode == Mode::Normal
+                            && !app.entering_token()
+                            && !app.popup_open()
+                        {
+                            Message::JumpBack
+                        // Ctrl+C drops the reply being written instead of quitting
+                        } else if key.code == KeyCode::Char('c')
+                            && key.modifiers.contains(KeyModifiers::CONTROL)
+                            && app.screen == Screen::Main
+                            && app.reply_to.is_some()
+                            && app.global_search.is_none()
+                        {
+                            Message::CancelReply
                         // Check if global search is active and handle its input
                         } else if app.global_search.is_some() {
                             match key.code {
@@ -365,12 +546,58 @@ async fn main() -> Result<()> {
                                 KeyCode::Enter => Message::Select,
                                 _ => Message::Noop,
                             }
-                        } else if app.screen == Screen::Auth {
+                        } else if app.entering_token() {
                             Message::from_auth_key_event(key)
+                        } else if let Some(captcha) = &app.captcha {
+                            Message::from_captcha_key_event(key, captcha.key.is_empty())
                         } else if let Some(fwd) = &app.forward {
                             Message::from_forward_key_event(key, fwd.stage.clone())
+                        } else if app.edit_conflict.is_some() {
+                            Message::from_edit_conflict_key_event(key)
+                        } else if app.cross_chat_send.is_some() {
+                            Message::from_cross_chat_send_key_event(key)
                         } else if app.forward_view.is_some() {
                             Message::from_forward_view_key_event(key)
+                        } else if app.photo_preview.is_some() {
+                            Message::from_photo_preview_key_event(key)
+                        } else if app.whois.is_some() {
+                            Message::from_whois_key_event(key)
+                        } else if app.read_by.is_some() {
+                            Message::from_readers_key_event(key)
+                        } else if app.leave_chat.is_some() {
+                            Message::from_leave_chat_key_event(key)
+                        } else if app.delete_chat.is_some() {
+                            Message::from_delete_chat_key_event(key)
+                        } else if app.delete_prompt.is_some() {
+                            Message::from_delete_prompt_key_event(key)
+                        } else if app.message_menu.is_some() {
+                            Message::from_message_menu_key_event(key)
+                        } else if app.link_picker.is_some() {
+                            Message::from_link_picker_key_event(key)
+                        } else if app.new_chat.is_some() {
+                            Message::from_new_chat_key_event(key)
+                        } else if app.chat_info.is_some() {
+                            Message::from_chat_info_key_event(key)
+                        } else if app.members.is_some() {
+                            Message::from_members_key_event(key)
+                        } else if app.show_stats {
+                            Message::from_stats_key_event(key)
+                        } else if app.log_view.is_some() {
+                            Message::from_log_view_key_event(key)
+                        } else if app.show_registers {
+                            Message::from_registers_key_event(key)
+                        } else if app.awaiting_register {
+                            Message::from_register_name_key_event(key)
+                        } else if app.awaiting_yank {
+                            Message::from_yank_key_event(key)
+                        } else if app.awaiting_go {
+                            Message::from_go_key_event(key)
+                        } else if app.visual_anchor.is_some() {
+                            Message::from_visual_key_event(key)
+                        } else if app.history_search.is_some() {
+                            Message::from_history_search_key_event(key)
+                        } else if app.history_export.is_some() && key.code == KeyCode::Esc {
+                            Message::CancelExport
                         } else {
                             Message::from_key_event(key, app.mode, app.focus, app.show_help)
                         };
@@ -381,19 +608,26 @@ async fn main() -> Result<()> {
                             current_msg = update(&mut app, msg);
                         }
 
-                        // If we just authenticated, restart action handler with new client
-                        if app.vk_client.is_some() && !app.is_loading && app.chats.is_empty() {
-                            let (new_action_tx, new_action_rx) = mpsc::unbounded_channel();
-                            app.set_action_tx(new_action_tx);
-                            spawn_action_handler(new_action_rx, message_tx.clone(), app.vk_client.clone());
-                            app.is_loading = true;
-                            app.chats_pagination.is_loading = true;
-                            app.send_action(AsyncAction::LoadConversations(0));
-                            app.send_action(AsyncAction::StartLongPoll);
+                        // Logging out stops the action handler; the next login needs a new one
+                        if app.action_handler.is_none() {
+                            start_action_handler(&mut app, &message_tx);
                         }
                     }
-                    Event::Mouse(_) => {}
-                    Event::Resize(_, _) => {}
+                    Event::Mouse(mouse) => {
+                        if app.screen == Screen::Main && !app.popup_open() && !app.session_expired {
+                            let msg = Message::from_mouse_event(mouse, &app.layout.borrow(), app.focus);
+                            let mut current_msg = Some(msg);
+                            while let Some(msg) = current_msg {
+                                current_msg = update(&mut app, msg);
+                            }
+                        }
+                    }
+                    Event::Resize(width, height) => {
+                        update(&mut app, Message::Resize(width, height));
+                    }
+                    Event::Paste(text) => {
+                        update(&mut app, Message::InputPaste(text));
+                    }
                     Event::Vk(vk_event) => {
                         update(&mut app, Message::VkEvent(vk_event));
                     }
@@ -405,8 +639,11 @@ async fn main() -> Result<()> {
         }
     }
 
-    // Restore terminal
-    restore_terminal(&mut terminal)?;
+    // Restore terminal, without an image left on it
+    if let Some(overlay) = &mut overlay {
+        overlay.remove(&mut terminal)?;
+    }
+    caps.restore(&mut terminal)?;
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
@@ -3,4 +3,9 @@
 //! This module exists for backward compatibility during the transition
 //! to the vk-core crate.
 
-pub use vk_core::mapper::{map_attachment, map_forward_tree, map_history_message, map_reply};
+pub use vk_core::mapper::{
+    conversation_kind, conversation_last_seen, conversation_muted, conversation_photo,
+    conversation_write_access, map_attachment, map_chat_members, map_forward_tree,
+    map_group_profile, map_history_message, map_reactions, map_read_peers, map_reply,
+    map_user_profile, message_kind, message_preview, message_sender,
+};
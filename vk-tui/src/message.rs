@@ -1,7 +1,11 @@
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::Position;
 
 use crate::event::VkEvent;
-use crate::state::{AttachmentInfo, Chat, ChatMessage, Focus, ForwardStage, Mode, ReplyPreview};
+use crate::state::{
+    AttachmentInfo, CaptchaRetry, Chat, ChatMessage, Focus, ForwardStage, LayoutMap, Mode,
+    ReplyPreview,
+};
 use vk_api::User;
 
 /// Messages for the TEA update loop
@@ -13,12 +17,18 @@ pub enum Message {
     Noop,
     /// Quit the application
     Quit,
+    /// End the session and return to authorization
+    Logout,
     /// Open auth URL in browser
     OpenAuthUrl,
     /// Switch focus to next panel
     FocusNext,
     /// Switch focus to previous panel
     FocusPrev,
+    /// Show or hide the chat list (`Ctrl+B`)
+    ToggleSidebar,
+    /// Terminal resized to (columns, rows)
+    Resize(u16, u16),
     /// Navigate up in current list
     NavigateUp,
     /// Navigate down in current list
@@ -35,6 +45,10 @@ pub enum Message {
     // Insert mode - text input
     /// Input character
     InputChar(char),
+    /// Pasted text, inserted at the cursor in one go
+    InputPaste(String),
+    /// Paste the system clipboard (Ctrl+V)
+    PasteClipboard,
     /// Delete character (backspace)
     InputBackspace,
     /// Delete word
@@ -57,6 +71,19 @@ pub enum Message {
     CompletionDown,
     /// Select item from completion
     CompletionSelect,
+    /// Recall the previous command starting with what is typed
+    CommandHistoryUp,
+    /// Back towards what was typed before the recall
+    CommandHistoryDown,
+    /// Ctrl+R: search the command history
+    HistorySearchStart,
+    HistorySearchChar(char),
+    HistorySearchBackspace,
+    /// Ctrl+R again: the next older match
+    HistorySearchNext,
+    /// Run the matched command
+    HistorySearchAccept,
+    HistorySearchCancel,
 
     // Mode transitions
     /// Enter Normal mode
@@ -71,18 +98,77 @@ pub enum Message {
     GoToTop,
     /// Go to bottom of list
     GoToBottom,
+    /// Jump to the messages that arrived while reading history
+    ShowUnseen,
+    /// `g` pressed in the message list; waits for `g` or `r`
+    StartGo,
+    /// Key after `g` was not a place to go
+    CancelGo,
+    /// Go to the message the selected one replies to
+    GoToReply,
+    /// Id of a replied message found by its cmid, or why it was not
+    ReplyTargetResolved(Result<i64, String>),
+    /// Go back to where the last `gr` jumped from
+    JumpBack,
+
+    // Mouse
+    /// Chat row clicked (index among the chats shown)
+    ChatClicked(usize),
+    /// Message clicked
+    MessageClicked(usize),
+    InputClicked,
+    /// Wheel turned over a panel
+    Scroll {
+        panel: Focus,
+        down: bool,
+    },
 
     // Message actions (vi-like)
     /// Reply to selected message
     ReplyToMessage,
     /// Forward selected message
     ForwardMessage,
-    /// Delete selected message
+    /// Ask how to delete the selected message
     DeleteMessage,
+    /// Move through the `dd` prompt
+    DeletePromptUp,
+    DeletePromptDown,
+    /// Act on the highlighted `dd` choice
+    DeletePromptConfirm,
+    DeletePromptCancel,
+    /// Move through the links of the link picker
+    LinkPickerUp,
+    LinkPickerDown,
+    /// Open the highlighted link, or the one with this number (from 1)
+    LinkPickerOpen(Option<usize>),
+    LinkPickerClose,
+    /// Open the menu of actions for the selected message
+    OpenMessageMenu,
+    /// Move through the message menu
+    MessageMenuUp,
+    MessageMenuDown,
+    /// Run the highlighted action of the message menu
+    MessageMenuSelect,
+    MessageMenuClose,
     /// Edit selected message
     EditMessage,
+    /// `y` pressed; waits for `y`, `l` or `A`
+    StartYank,
     /// Copy message text (yank)
     YankMessage,
+    /// Copy the first link of the selected message
+    YankLink,
+    /// Copy all loaded messages of the chat
+    YankConversation,
+    /// Key after `y` was not a yank target
+    CancelYank,
+    /// `V`: start selecting messages from the cursor
+    StartVisual,
+    CancelVisual,
+    /// Forward, delete or copy the selected messages
+    ForwardSelection,
+    DeleteSelection,
+    YankSelection,
     /// Pin/unpin message
     PinMessage,
     /// View forwarded content
@@ -124,11 +210,31 @@ pub enum Message {
     SendFailed(String),
     /// VK API event
     VkEvent(VkEvent),
+    /// Long Poll key had to be renewed
+    LongPollKeyExpired,
+    /// Long Poll is getting a new server (attempt since the connection was lost)
+    LongPollReconnecting(u32),
+    /// Long Poll keeps failing; recent conversations are polled instead
+    LongPollDegraded,
+    /// Make Long Poll get a new server now (`:reconnect`, Ctrl+R)
+    Reconnect,
+    /// Long Poll keys expire so often another client may use the account
+    PossibleConcurrentSession,
     /// Session validation result
     SessionValidated {
         valid: bool,
         error: Option<String>,
     },
+    /// Start catching the OAuth redirect on a local port
+    WaitForRedirect,
+    /// Stop catching the OAuth redirect
+    CancelRedirectWait,
+    /// Redirect URL caught by the local catcher, or why it failed
+    RedirectCaught(Result<String, String>),
+    /// Scope check of a pasted token, with the error to show
+    TokenChecked(Result<(), String>),
+    /// Profile of the logged-in account loaded
+    ProfileLoaded(vk_api::ProfileInfo),
     /// Conversations loaded from API
     ConversationsLoaded {
         chats: Vec<Chat>,
@@ -144,12 +250,46 @@ pub enum Message {
         total_count: u32,
         has_more: bool,
     },
-    /// Message sent successfully (message_id, cmid)
-    MessageSent(i64, i64),
+    /// Message sent successfully (message_id, cmid, random_id)
+    MessageSent(i64, i64, i64),
+    /// Bytes of an attachment saved so far
+    DownloadProgress {
+        title: String,
+        received: u64,
+        total: Option<u64>,
+    },
+    /// Show the first photo of the selected message
+    PreviewPhoto,
+    /// Photo for the preview popup decoded, or why it could not be
+    PhotoPreviewLoaded(Result<std::sync::Arc<image::RgbaImage>, String>),
+    /// Close the photo preview popup
+    PhotoPreviewClose,
+    /// Attachment saved to disk
+    DownloadFinished {
+        path: std::path::PathBuf,
+    },
+    /// History export progress (messages fetched, total)
+    ExportProgress(usize, usize),
+    /// History export written (messages), or why it failed
+    ExportFinished(Result<usize, String>),
+    /// Stop the history export
+    CancelExport,
+    /// Attachment upload progress (local_id, bytes_sent, total)
+    UploadProgress(u64, u64, u64),
+    /// No attachment could be uploaded, so nothing was sent (local_id, error)
+    UploadFailed(u64, String),
+    /// Some attachments could not be uploaded; the rest is sent
+    /// (local_id, failed file names, error)
+    UploadPartlyFailed(u64, Vec<String>, String),
     /// Message edited successfully
     MessageEdited(i64),
     /// Message deleted successfully
     MessageDeleted(i64), // message_id
+    /// Selected messages deleted; `failed` were not
+    MessagesDeleted {
+        deleted: Vec<i64>,
+        failed: Vec<i64>,
+    },
     /// Message details fetched (update cmid/text/attachments)
     MessageDetailsFetched {
         message_id: i64,
@@ -160,9 +300,41 @@ pub enum Message {
         reply: Option<ReplyPreview>,
         fwd_count: Option<usize>,
         forwards: Option<Vec<crate::state::ForwardItem>>,
+        reactions: Option<Vec<vk_core::ReactionCount>>,
     },
     /// Error occurred
     Error(String),
+    /// Edit not submitted: message was changed elsewhere
+    EditConflict {
+        message_id: i64,
+        server_text: String,
+    },
+    /// Resolve edit conflict by submitting local text anyway
+    EditConflictOverwrite,
+    /// Resolve edit conflict by dropping local text
+    EditConflictTakeTheirs,
+    /// Resolve edit conflict by continuing to edit on top of server text
+    EditConflictMerge,
+    /// Send input typed in another chat to the current one
+    CrossChatSendConfirm,
+    /// Keep the input instead of sending it
+    CrossChatSendCancel,
+    /// Access token rejected by VK, user has to log in again
+    AuthExpired,
+    /// VK wants a captcha solved before it takes the send
+    CaptchaRequired {
+        sid: String,
+        img_url: String,
+        retry: CaptchaRetry,
+    },
+    CaptchaChar(char),
+    CaptchaBackspace,
+    /// Open the captcha image in the browser
+    CaptchaOpenImage,
+    /// Answer the captcha and send again
+    CaptchaSubmit,
+    /// Give up on the send
+    CaptchaCancel,
 
     // Chat filter
     /// Start chat filter mode
@@ -177,6 +349,8 @@ pub enum Message {
     // Global search
     /// Start global search mode
     StartGlobalSearch,
+    /// Search the open chat on the device (`:grep`)
+    StartGrep(String),
     /// Input character in global search
     GlobalSearchChar(char),
     /// Delete character in global search
@@ -189,14 +363,148 @@ pub enum Message {
     GlobalSearchDown,
     /// Select search result and navigate to message
     GlobalSearchSelect,
+    /// Open the next hit of the last global search
+    NextSearchHit,
+    /// Open the previous hit of the last global search
+    PrevSearchHit,
     /// Search results loaded
     SearchResultsLoaded {
         results: Vec<crate::state::SearchResult>,
-        total_count: i32,
+        total_count: u32,
+    },
+    /// A batch of `:grep` results for `query`; `done` on the last one
+    GrepResults {
+        query: String,
+        results: Vec<crate::state::SearchResult>,
+        done: bool,
+    },
+    /// Show the profile of the highlighted message's sender
+    ShowSenderProfile,
+    /// Profile for the whois popup loaded
+    UserProfileLoaded(crate::state::ProfileDetails),
+    /// Loading the whois profile failed
+    UserProfileFailed(String),
+    /// Close the whois popup
+    WhoisClose,
+    /// Show who read the highlighted own message in a group chat
+    ShowReaders,
+    /// Open the command line at `:react ` for the selected message
+    StartReaction,
+    /// Readers for the read receipts popup loaded
+    ReadPeersLoaded(vk_core::MessageReaders),
+    /// Loading the readers failed
+    ReadPeersFailed(String),
+    ReadersClose,
+    /// Close the stats popup
+    StatsClose,
+    /// Close the `:log tail` popup
+    LogViewClose,
+    /// Scroll the log popup by lines, negative towards older ones
+    LogViewScroll(isize),
+    /// `"` pressed: the next key names a register
+    StartRegister,
+    /// Register named for the next yank or paste (`None` cancels)
+    SelectRegister(Option<char>),
+    /// Paste a register into the input at the cursor
+    PasteRegister,
+    RegistersClose,
+    /// A page of the members popup loaded
+    MembersLoaded {
+        peer_id: i64,
+        offset: u32,
+        members: Vec<crate::state::ChatMember>,
+        total_count: u32,
+    },
+    /// Loading members failed
+    MembersFailed(String),
+    MembersUp,
+    MembersDown,
+    MembersClose,
+    /// Leave the chat asked about by `:leave`
+    LeaveChatConfirm,
+    LeaveChatCancel,
+    /// Left a group chat
+    ChatLeft(i64),
+    /// Ask to delete the selected conversation
+    DeleteChat,
+    /// Delete the conversation asked about by `dd` or `:delchat`
+    DeleteChatConfirm,
+    DeleteChatCancel,
+    /// Conversation deleted
+    ConversationDeleted(i64),
+    /// Notifications of a chat turned off or on
+    ChatMuteChanged {
+        peer_id: i64,
+        muted: bool,
+    },
+    /// Pin the selected chat to the top, or unpin it
+    TogglePinChat,
+    /// Chat pinned or unpinned
+    ChatPinChanged {
+        peer_id: i64,
+        pinned: bool,
+    },
+    /// Friends for the `:newchat` picker loaded: (user_id, name)
+    FriendsLoaded(Vec<(i64, String)>),
+    NewChatUp,
+    NewChatDown,
+    NewChatToggle,
+    NewChatConfirm,
+    NewChatClose,
+    /// Media counts for the `:info` popup loaded
+    ChatInfoLoaded(vk_core::media::ChatInfo),
+    /// Counting media failed
+    ChatInfoFailed(String),
+    ChatInfoRefresh,
+    ChatInfoClose,
+    /// Chat photo passed the permission check and is uploading: path
+    ChatPhotoUploading(String),
+    /// Title or photo of a group chat changed with `:chat`; unchanged
+    /// fields are `None`
+    ChatSettingsChanged {
+        peer_id: i64,
+        title: Option<String>,
+        photo_url: Option<String>,
+    },
+    /// Group chat created
+    ChatCreated {
+        peer_id: i64,
+        title: String,
+    },
+    /// Profiles requested for a picker loaded (`users` is empty on failure)
+    UsersLoaded {
+        requested: Vec<i64>,
+        users: Vec<User>,
     },
 }
 
 impl Message {
+    /// Convert a mouse event to a message using where the last frame drew
+    /// things; the wheel scrolls the panel under it, or the focused one
+    pub fn from_mouse_event(mouse: MouseEvent, layout: &LayoutMap, focus: Focus) -> Self {
+        let pos = Position::new(mouse.column, mouse.row);
+        match mouse.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                if layout.unseen_hint.is_some_and(|area| area.contains(pos)) {
+                    Message::ShowUnseen
+                } else if let Some(idx) = layout.chat_at(pos) {
+                    Message::ChatClicked(idx)
+                } else if let Some(idx) = layout.message_at(pos) {
+                    Message::MessageClicked(idx)
+                } else if layout.input.contains(pos) {
+                    Message::InputClicked
+                } else {
+                    Message::Noop
+                }
+            }
+            MouseEventKind::ScrollDown | MouseEventKind::ScrollUp => Message::Scroll {
+                panel: layout.panel_at(pos).unwrap_or(focus),
+                down: mouse.kind == MouseEventKind::ScrollDown,
+            },
+            _ => Message::Noop,
+        }
+    }
+
     /// Convert key event to message based on current mode and focus
     pub fn from_key_event(key: KeyEvent, mode: Mode, focus: Focus, show_help: bool) -> Self {
         // Help popup takes precedence
@@ -238,17 +546,24 @@ impl Message {
             KeyCode::Char('o') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                 Some(Message::OpenAuthUrl)
             }
+            KeyCode::Char('l') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                Some(Message::WaitForRedirect)
+            }
             _ => None,
         } {
             return global;
         }
 
         match key.code {
+            KeyCode::Esc => Message::CancelRedirectWait,
             KeyCode::Enter => Message::InputSubmit,
             KeyCode::Backspace => Message::InputBackspace,
             KeyCode::Char('w') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                 Message::InputDeleteWord
             }
+            KeyCode::Char('v') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                Message::PasteClipboard
+            }
             KeyCode::Char(c) => Message::InputChar(c),
             _ => Message::Noop,
         }
@@ -293,6 +608,241 @@ impl Message {
         }
     }
 
+    /// Handle keys when edit-conflict prompt is open
+    pub fn from_edit_conflict_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Char('o') => Message::EditConflictOverwrite,
+            KeyCode::Char('t') | KeyCode::Esc => Message::EditConflictTakeTheirs,
+            KeyCode::Char('m') | KeyCode::Enter => Message::EditConflictMerge,
+            _ => Message::Noop,
+        }
+    }
+
+    /// Handle keys when the captcha prompt is open. `o` opens the image
+    /// until typing starts; Ctrl+O always does.
+    pub fn from_captcha_key_event(key: KeyEvent, key_empty: bool) -> Self {
+        match key.code {
+            KeyCode::Esc => Message::CaptchaCancel,
+            KeyCode::Enter => Message::CaptchaSubmit,
+            KeyCode::Backspace => Message::CaptchaBackspace,
+            KeyCode::Char('o') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                Message::CaptchaOpenImage
+            }
+            KeyCode::Char('o') if key_empty => Message::CaptchaOpenImage,
+            KeyCode::Char(c) => Message::CaptchaChar(c),
+            _ => Message::Noop,
+        }
+    }
+
+    /// Handle keys when cross-chat send confirmation is open
+    pub fn from_cross_chat_send_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Char('y') | KeyCode::Enter => Message::CrossChatSendConfirm,
+            KeyCode::Char('n') | KeyCode::Esc => Message::CrossChatSendCancel,
+            _ => Message::Noop,
+        }
+    }
+
+    /// Handle keys when the whois popup is open
+    pub fn from_whois_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('u') => Message::WhoisClose,
+            _ => Message::Noop,
+        }
+    }
+
+    /// Handle keys when the read receipts popup is open
+    pub fn from_readers_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('R') => Message::ReadersClose,
+            _ => Message::Noop,
+        }
+    }
+
+    /// Handle keys when the members popup is open
+    pub fn from_members_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => Message::MembersClose,
+            KeyCode::Up | KeyCode::Char('k') => Message::MembersUp,
+            KeyCode::Down | KeyCode::Char('j') => Message::MembersDown,
+            _ => Message::Noop,
+        }
+    }
+
+    /// Handle keys when the `:info` popup is open
+    pub fn from_chat_info_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => Message::ChatInfoClose,
+            KeyCode::Char('r') => Message::ChatInfoRefresh,
+            _ => Message::Noop,
+        }
+    }
+
+    /// Handle keys when the `:newchat` picker is open
+    pub fn from_new_chat_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Esc => Message::NewChatClose,
+            KeyCode::Up | KeyCode::Char('k') => Message::NewChatUp,
+            KeyCode::Down | KeyCode::Char('j') => Message::NewChatDown,
+            KeyCode::Char(' ') => Message::NewChatToggle,
+            KeyCode::Enter => Message::NewChatConfirm,
+            _ => Message::Noop,
+        }
+    }
+
+    /// Handle keys when the `:leave` confirmation is open
+    pub fn from_leave_chat_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Char('y') => Message::LeaveChatConfirm,
+            KeyCode::Char('n') | KeyCode::Esc => Message::LeaveChatCancel,
+            _ => Message::Noop,
+        }
+    }
+
+    /// Handle keys when the delete conversation confirmation is open
+    pub fn from_delete_chat_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Char('y') => Message::DeleteChatConfirm,
+            KeyCode::Char('n') | KeyCode::Esc => Message::DeleteChatCancel,
+            _ => Message::Noop,
+        }
+    }
+
+    /// Handle keys when the `dd` prompt is open
+    pub fn from_delete_prompt_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => Message::DeletePromptUp,
+            KeyCode::Down | KeyCode::Char('j') => Message::DeletePromptDown,
+            KeyCode::Enter => Message::DeletePromptConfirm,
+            KeyCode::Esc | KeyCode::Char('q') => Message::DeletePromptCancel,
+            _ => Message::Noop,
+        }
+    }
+
+    /// Handle keys when the link picker is open; a digit opens that link
+    pub fn from_link_picker_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => Message::LinkPickerUp,
+            KeyCode::Down | KeyCode::Char('j') => Message::LinkPickerDown,
+            KeyCode::Enter | KeyCode::Char('o') => Message::LinkPickerOpen(None),
+            KeyCode::Char(c @ '1'..='9') => {
+                Message::LinkPickerOpen(c.to_digit(10).map(|n| n as usize))
+            }
+            KeyCode::Esc | KeyCode::Char('q') => Message::LinkPickerClose,
+            _ => Message::Noop,
+        }
+    }
+
+    /// Handle keys when the message menu is open
+    pub fn from_message_menu_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => Message::MessageMenuUp,
+            KeyCode::Down | KeyCode::Char('j') => Message::MessageMenuDown,
+            KeyCode::Enter => Message::MessageMenuSelect,
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char(' ') | KeyCode::Char('m') => {
+                Message::MessageMenuClose
+            }
+            _ => Message::Noop,
+        }
+    }
+
+    /// Handle keys while Ctrl+R searches the command history
+    pub fn from_history_search_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Esc => Message::HistorySearchCancel,
+            KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                Message::HistorySearchCancel
+            }
+            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                Message::HistorySearchNext
+            }
+            KeyCode::Enter => Message::HistorySearchAccept,
+            KeyCode::Backspace => Message::HistorySearchBackspace,
+            KeyCode::Char(c) => Message::HistorySearchChar(c),
+            _ => Message::Noop,
+        }
+    }
+
+    /// Handle the key after `"`, which names a register
+    pub fn from_register_name_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Char(c) => Message::SelectRegister(Some(c)),
+            _ => Message::SelectRegister(None),
+        }
+    }
+
+    /// Handle the key after `y`
+    pub fn from_yank_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Char('y') => Message::YankMessage,
+            KeyCode::Char('l') => Message::YankLink,
+            KeyCode::Char('A') => Message::YankConversation,
+            _ => Message::CancelYank,
+        }
+    }
+
+    /// Handle the key after `g` in the message list
+    pub fn from_go_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Char('g') => Message::GoToTop,
+            KeyCode::Char('r') => Message::GoToReply,
+            _ => Message::CancelGo,
+        }
+    }
+
+    /// Handle keys while messages are selected with `V`
+    pub fn from_visual_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Char('j') | KeyCode::Down => Message::NavigateDown,
+            KeyCode::Char('k') | KeyCode::Up => Message::NavigateUp,
+            KeyCode::Char('g') => Message::GoToTop,
+            KeyCode::Char('G') => Message::GoToBottom,
+            KeyCode::Char('f') => Message::ForwardSelection,
+            KeyCode::Char('d') => Message::DeleteSelection,
+            KeyCode::Char('y') => Message::YankSelection,
+            KeyCode::Esc | KeyCode::Char('V') => Message::CancelVisual,
+            _ => Message::Noop,
+        }
+    }
+
+    /// Handle keys when the `:registers` popup is open
+    pub fn from_registers_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => Message::RegistersClose,
+            _ => Message::Noop,
+        }
+    }
+
+    /// Handle keys when the stats popup is open
+    pub fn from_stats_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => Message::StatsClose,
+            _ => Message::Noop,
+        }
+    }
+
+    /// Handle keys when the photo preview is open
+    pub fn from_photo_preview_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => Message::PhotoPreviewClose,
+            _ => Message::Noop,
+        }
+    }
+
+    /// Handle keys when the log popup is open
+    pub fn from_log_view_key_event(key: KeyEvent) -> Self {
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => Message::LogViewClose,
+            KeyCode::Up | KeyCode::Char('k') => Message::LogViewScroll(-1),
+            KeyCode::Down | KeyCode::Char('j') => Message::LogViewScroll(1),
+            KeyCode::PageUp => Message::LogViewScroll(-20),
+            KeyCode::PageDown => Message::LogViewScroll(20),
+            KeyCode::Char('g') | KeyCode::Home => Message::LogViewScroll(isize::MIN),
+            KeyCode::Char('G') | KeyCode::End => Message::LogViewScroll(isize::MAX),
+            _ => Message::Noop,
+        }
+    }
+
     /// Handle keys when forward-view popup is open
     pub fn from_forward_view_key_event(key: KeyEvent) -> Self {
         match key.code {
@@ -310,6 +860,9 @@ impl Message {
             KeyCode::Esc => return Message::Back,
             KeyCode::Tab => return Message::FocusNext,
             KeyCode::BackTab => return Message::FocusPrev,
+            KeyCode::Char('b') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Message::ToggleSidebar;
+            }
             KeyCode::Char(':') => return Message::EnterCommandMode,
             KeyCode::Char('?') => return Message::ToggleHelp,
             _ => {}
@@ -324,6 +877,8 @@ impl Message {
                 // Allow entering Insert mode
                 match key.code {
                     KeyCode::Char('i') | KeyCode::Enter => Message::EnterInsertMode,
+                    KeyCode::Char('p') => Message::PasteRegister,
+                    KeyCode::Char('"') => Message::StartRegister,
                     _ => Message::Noop,
                 }
             }
@@ -342,6 +897,8 @@ impl Message {
             // Actions
             KeyCode::Char('l') | KeyCode::Enter => Message::Select,
             KeyCode::Char('/') => Message::StartChatFilter,
+            KeyCode::Char('d') => Message::DeleteChat,
+            KeyCode::Char('P') => Message::TogglePinChat,
 
             _ => Message::Noop,
         }
@@ -362,7 +919,7 @@ impl Message {
             // Navigation
             KeyCode::Char('j') | KeyCode::Down => Message::NavigateDown,
             KeyCode::Char('k') | KeyCode::Up => Message::NavigateUp,
-            KeyCode::Char('g') => Message::GoToTop,
+            KeyCode::Char('g') => Message::StartGo,
             KeyCode::Char('G') => Message::GoToBottom,
 
             // Enter Insert mode
@@ -374,17 +931,26 @@ impl Message {
             KeyCode::Char('F') => Message::ViewForwarded,
             KeyCode::Char('e') => Message::EditMessage,
             KeyCode::Char('p') => Message::PinMessage,
+            KeyCode::Char('u') => Message::ShowSenderProfile,
+            KeyCode::Char('R') => Message::ShowReaders,
+            KeyCode::Char('x') => Message::StartReaction,
+            KeyCode::Char(' ') | KeyCode::Char('m') => Message::OpenMessageMenu,
 
             // Double-char commands (dd, yy)
             KeyCode::Char('d') => Message::DeleteMessage, // Will need state for 'dd'
-            KeyCode::Char('y') => Message::YankMessage,   // Will need state for 'yy'
+            KeyCode::Char('y') => Message::StartYank,
+            KeyCode::Char('"') => Message::StartRegister,
+            KeyCode::Char('V') => Message::StartVisual,
 
             // Attachments and links
             KeyCode::Char('o') => Message::OpenLink,
             KeyCode::Char('a') => Message::DownloadAttachment,
+            KeyCode::Char('P') => Message::PreviewPhoto,
 
             // Search
             KeyCode::Char('/') => Message::StartSearch,
+            KeyCode::Char('n') => Message::NextSearchHit,
+            KeyCode::Char('N') => Message::PrevSearchHit,
 
             // Back to ChatList
             KeyCode::Char('h') => Message::FocusPrev,
@@ -410,6 +976,12 @@ impl Message {
             KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                 Message::InputDeleteWord // Clear line
             }
+            KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                Message::PasteRegister
+            }
+            KeyCode::Char('v') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                Message::PasteClipboard
+            }
 
             // Regular character
             KeyCode::Char(c) => Message::InputChar(c),
@@ -430,8 +1002,13 @@ impl Message {
             // Completion navigation
             KeyCode::Tab => Message::CompletionDown,
             KeyCode::BackTab => Message::CompletionUp,
-            KeyCode::Down => Message::CompletionDown,
-            KeyCode::Up => Message::CompletionUp,
+
+            // Command history
+            KeyCode::Up => Message::CommandHistoryUp,
+            KeyCode::Down => Message::CommandHistoryDown,
+            KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                Message::HistorySearchStart
+            }
 
             // Editing
             KeyCode::Backspace => Message::CommandBackspace,
@@ -441,6 +1018,9 @@ impl Message {
             KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
                 Message::CommandDeleteWord // Clear command line
             }
+            KeyCode::Char('v') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                Message::PasteClipboard
+            }
 
             // Regular character
             KeyCode::Char(c) => Message::CommandChar(c),
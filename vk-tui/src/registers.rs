@@ -0,0 +1,158 @@
+//! Vim-style yank registers (session-only).
+
+use std::collections::BTreeMap;
+
+use crate::input::char_to_byte_index;
+
+/// Largest text kept in one register, in bytes.
+pub const REGISTER_CAP: usize = 64 * 1024;
+
+/// Name `:registers` shows for the unnamed register.
+pub const UNNAMED: char = '"';
+
+/// The unnamed register holding the last yank, plus named registers a–z.
+#[derive(Debug, Clone, Default)]
+pub struct Registers {
+    unnamed: Option<String>,
+    named: BTreeMap<char, String>,
+}
+
+impl Registers {
+    /// Whether `name` is a register `"x` can select.
+    pub fn is_valid_name(name: char) -> bool {
+        name.is_ascii_lowercase()
+    }
+
+    /// Store a yank. The unnamed register always gets it, like in vim;
+    /// `name` also stores it in that named register.
+    pub fn yank(&mut self, name: Option<char>, text: &str) {
+        let text = truncate_to_cap(text);
+        if let Some(name) = name.filter(|n| Self::is_valid_name(*n)) {
+            self.named.insert(name, text.clone());
+        }
+        self.unnamed = Some(text);
+    }
+
+    /// Contents of a register; `None` is the unnamed one.
+    pub fn get(&self, name: Option<char>) -> Option<&str> {
+        match name {
+            None | Some(UNNAMED) => self.unnamed.as_deref(),
+            Some(name) => self.named.get(&name).map(String::as_str),
+        }
+    }
+
+    /// Non-empty registers for `:registers`, unnamed first.
+    pub fn list(&self) -> Vec<(char, &str)> {
+        self.unnamed
+            .as_deref()
+            .map(|text| (UNNAMED, text))
+            .into_iter()
+            .chain(self.named.iter().map(|(name, text)| (*name, text.as_str())))
+            .collect()
+    }
+
+    /// Insert a register into `input` at the character position `cursor`,
+    /// moving the cursor past it. Returns false if the register is empty.
+    pub fn paste(&self, name: Option<char>, input: &mut String, cursor: &mut usize) -> bool {
+        let Some(text) = self.get(name).filter(|t| !t.is_empty()) else {
+            return false;
+        };
+        let pos = (*cursor).min(input.chars().count());
+        input.insert_str(char_to_byte_index(input, pos), text);
+        *cursor = pos + text.chars().count();
+        true
+    }
+}
+
+/// Cut `text` to at most [`REGISTER_CAP`] bytes on a char boundary.
+fn truncate_to_cap(text: &str) -> String {
+    if text.len() <= REGISTER_CAP {
+        return text.to_string();
+    }
+    let mut end = REGISTER_CAP;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text[..end].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_yank_fills_unnamed_and_named() {
+        let mut registers = Registers::default();
+        registers.yank(None, "first");
+        registers.yank(Some('a'), "second");
+
+        assert_eq!(registers.get(None), Some("second"));
+        assert_eq!(registers.get(Some('a')), Some("second"));
+        assert_eq!(registers.get(Some('b')), None);
+
+        registers.yank(None, "third");
+        assert_eq!(registers.get(Some('a')), Some("second"));
+        assert_eq!(registers.list(), vec![(UNNAMED, "third"), ('a', "second")]);
+    }
+
+    #[test]
+    fn test_invalid_name_only_fills_unnamed() {
+        let mut registers = Registers::default();
+        registers.yank(Some('A'), "text");
+
+        assert_eq!(registers.get(None), Some("text"));
+        assert_eq!(registers.list().len(), 1);
+    }
+
+    #[test]
+    fn test_yank_is_capped_on_char_boundary() {
+        let mut registers = Registers::default();
+        let text = "я".repeat(REGISTER_CAP);
+        registers.yank(None, &text);
+
+        let stored = registers.get(None).unwrap();
+        assert_eq!(stored.len(), REGISTER_CAP);
+        assert!(stored.chars().all(|c| c == 'я'));
+    }
+
+    #[test]
+    fn test_paste_at_cursor_positions() {
+        let mut registers = Registers::default();
+        registers.yank(None, "привет ");
+
+        let mut input = String::new();
+        let mut cursor = 0;
+        assert!(registers.paste(None, &mut input, &mut cursor));
+        assert_eq!(input, "привет ");
+        assert_eq!(cursor, 7);
+
+        let mut input = "мир".to_string();
+        let mut cursor = 0;
+        registers.paste(None, &mut input, &mut cursor);
+        assert_eq!(input, "привет мир");
+        assert_eq!(cursor, 7);
+
+        let mut input = "ab".to_string();
+        let mut cursor = 1;
+        registers.paste(None, &mut input, &mut cursor);
+        assert_eq!(input, "aпривет b");
+        assert_eq!(cursor, 8);
+
+        let mut input = "end ".to_string();
+        let mut cursor = 4;
+        registers.paste(None, &mut input, &mut cursor);
+        assert_eq!(input, "end привет ");
+        assert_eq!(cursor, 11);
+    }
+
+    #[test]
+    fn test_paste_empty_register() {
+        let registers = Registers::default();
+        let mut input = "keep".to_string();
+        let mut cursor = 2;
+
+        assert!(!registers.paste(Some('q'), &mut input, &mut cursor));
+        assert_eq!(input, "keep");
+        assert_eq!(cursor, 2);
+    }
+}
@@ -3,16 +3,36 @@
 //! This module re-exports core types from vk-core and defines
 //! TUI-specific state types.
 
+use image::RgbaImage;
+use ratatui::layout::{Position, Rect, Size};
+use std::cell::RefCell;
 use std::collections::HashMap;
-use tokio::sync::mpsc;
+use std::time::Instant;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
 
-use vk_api::User;
+use crate::config::{Config, DEFAULT_SIDEBAR_WIDTH};
 use vk_api::auth::AuthManager;
+use vk_api::{ProfileInfo, User};
+use vk_core::cache::CacheLayer;
+use vk_core::config::Settings;
+use vk_core::export::ExportFormat;
+use vk_core::longpoll::ConnectionState;
+use vk_core::media::ChatInfo;
+use vk_core::profiles::ProfileWarmup;
+use vk_core::stats::Stats;
+use vk_core::{MessageReaders, MuteDuration};
+
+use crate::graphics::GraphicsProtocol;
+use crate::history::CommandHistory;
+use crate::registers::Registers;
+use crate::ui::MessageLineCache;
 
 // Re-export core types
 pub use vk_core::{
-    AttachmentInfo, AttachmentKind, Chat, ChatMessage, ChatsPagination, DeliveryStatus,
-    ForwardItem, MessagesPagination, ReplyPreview, SearchResult,
+    AttachmentInfo, AttachmentKind, Chat, ChatKind, ChatMember, ChatMessage, ChatsPagination,
+    DeliveryStatus, ForwardItem, MessagesPagination, ProfileDetails, ReplyPreview, SearchResult,
+    UploadState,
 };
 
 /// Current screen
@@ -70,23 +90,101 @@ pub enum RunningState {
 /// Async actions to be performed in background
 pub enum AsyncAction {
     ValidateSession,
+    /// Check the scopes of a pasted token; runs without a session
+    ValidateToken(std::sync::Arc<vk_api::VkClient>),
+    /// Catch the OAuth redirect on a local port; runs without a session
+    WaitForRedirect(u16, watch::Receiver<bool>),
     LoadConversations(u32),                     // offset
     LoadMessages(i64, u32),                     // peer_id, offset
     LoadMessagesAround(i64, i64),               // peer_id, message_id
     LoadMessagesWithOffset(i64, i64, i32, u32), // peer_id, start_message_id, offset, count
     SendMessage(i64, String),                   // peer_id, text
     SendForward(i64, Vec<i64>, String),         // peer_id, message_ids, comment
-    SendReply(i64, i64, String),                // peer_id, reply_to_msg_id, text
-    StartLongPoll,
+    SendReply(i64, i64, String, i64),           // peer_id, reply_to_msg_id, text, random_id
+    /// Text over VK's length limit, in parts sent one after another:
+    /// peer_id, reply_to and random_id for the first part, parts
+    SendParts(i64, Option<(i64, i64)>, Vec<String>),
+    /// Shutdown signal, reconnect trigger
+    StartLongPoll(watch::Receiver<bool>, std::sync::Arc<tokio::sync::Notify>),
     MarkAsRead(i64),
-    SendPhoto(i64, String), // peer_id, path
-    SendDoc(i64, String),   // peer_id, path
+    /// Find the id of a replied message known only by cmid: peer_id, cmid
+    ResolveReply(i64, i64),
+    SendPhoto(i64, Vec<String>, u64, i64), // peer_id, paths, local_id, random_id
+    SendDoc(i64, Vec<String>, u64, i64),   // peer_id, paths, local_id, random_id
     DownloadAttachments(Vec<AttachmentInfo>),
-    EditMessage(i64, i64, Option<i64>, String), // peer_id, message_id, cmid, text
+    /// url; decoded for the popup if true, else opened in a viewer
+    PreviewPhoto(String, bool),
+    /// peer_id, chat title, format, path, cancel signal
+    ExportHistory(
+        i64,
+        String,
+        ExportFormat,
+        std::path::PathBuf,
+        watch::Receiver<bool>,
+    ),
+    /// peer_id, chat title, query, loaded messages, cache; no request made
+    GrepHistory(
+        i64,
+        String,
+        String,
+        Vec<ChatMessage>,
+        Option<std::sync::Arc<dyn CacheLayer>>,
+    ),
+    EditMessage(i64, i64, Option<i64>, String, Option<u64>), // peer_id, message_id, cmid, text, base_hash
     #[allow(dead_code)]
-    DeleteMessage(i64, i64, bool), // peer_id, message_id, delete_for_all
-    FetchMessageById(i64),                      // message_id - to get cmid after sending
-    SearchMessages(String),                     // query
+    DeleteMessage(i64, i64, bool),    // peer_id, message_id, delete_for_all
+    DeleteMessages(Vec<i64>, bool),                          // message_ids, delete_for_all
+    FetchMessageById(i64),     // message_id - to get cmid after sending
+    SearchMessages(String),    // query
+    LoadUsers(Vec<i64>),       // user_ids
+    FetchUserProfile(i64),     // user_id, negative for communities
+    LoadChatMembers(i64, u32), // peer_id, offset
+    LeaveChat(i64, i64),       // peer_id, own user_id
+    LoadFriends,
+    LoadProfile,
+    CreateChat(Vec<i64>, String),            // user_ids, title
+    RenameChat(i64, String),                 // peer_id, title
+    SetChatPhoto(i64, String),               // peer_id, path
+    LoadChatInfo(i64),                       // peer_id
+    FetchReadPeers(i64, i64),                // peer_id, cmid
+    DeleteConversation(i64),                 // peer_id
+    SetChatMuted(i64, Option<MuteDuration>), // peer_id, None unmutes
+    SetChatPinned(i64, bool),                // peer_id, pinned
+    SetReaction(i64, i64, i64, Option<i64>), // peer_id, message_id, cmid, None removes ours
+    /// Send `key` with the next call that asked for captcha `sid`
+    AnswerCaptcha(String, String), // sid, key
+}
+
+/// A send that VK answered with a captcha, made again once it is solved
+#[derive(Debug, Clone)]
+pub enum CaptchaRetry {
+    Message(i64, String),           // peer_id, text
+    Reply(i64, i64, String, i64),   // peer_id, reply_to_msg_id, text, random_id
+    Forward(i64, Vec<i64>, String), // peer_id, message_ids, comment
+}
+
+impl CaptchaRetry {
+    pub fn action(self) -> AsyncAction {
+        match self {
+            CaptchaRetry::Message(peer_id, text) => AsyncAction::SendMessage(peer_id, text),
+            CaptchaRetry::Reply(peer_id, reply_to, text, random_id) => {
+                AsyncAction::SendReply(peer_id, reply_to, text, random_id)
+            }
+            CaptchaRetry::Forward(peer_id, message_ids, comment) => {
+                AsyncAction::SendForward(peer_id, message_ids, comment)
+            }
+        }
+    }
+}
+
+/// Captcha prompt shown when VK asks for one
+#[derive(Debug, Clone)]
+pub struct CaptchaPrompt {
+    pub sid: String,
+    pub img_url: String,
+    pub retry: CaptchaRetry,
+    /// Text typed from the image
+    pub key: String,
 }
 
 /// Chat filter state for local fuzzy search
@@ -115,7 +213,16 @@ pub struct GlobalSearch {
     pub results: Vec<SearchResult>,
     pub selected: usize,
     pub is_loading: bool,
-    pub total_count: i32,
+    pub total_count: u32,
+    /// `:grep` in the open chat: typing searches on the device only
+    pub offline: bool,
+}
+
+/// Global search results kept after opening one of them
+#[derive(Debug, Clone)]
+pub struct SearchHits {
+    pub results: Vec<SearchResult>,
+    pub current: usize,
 }
 
 impl GlobalSearch {
@@ -127,6 +234,7 @@ impl GlobalSearch {
             selected: 0,
             is_loading: false,
             total_count: 0,
+            offline: false,
         }
     }
 }
@@ -148,9 +256,18 @@ pub struct App {
 
     // VK state
     pub vk_client: Option<std::sync::Arc<vk_api::VkClient>>,
+    /// Client the action handler runs with, replaced on re-auth; set
+    /// both with [`App::set_client`]
+    pub shared_client: watch::Sender<Option<std::sync::Arc<vk_api::VkClient>>>,
+    /// Token expired; the re-auth prompt is shown over the main screen
+    pub session_expired: bool,
+    /// Waiting for the browser to bring the token to the local catcher
+    pub redirect_wait: Option<RedirectWait>,
     pub users: HashMap<i64, User>,
-    #[allow(dead_code)]
-    pub current_user: Option<User>,
+    /// Profiles requested for pickers and not loaded yet
+    pub profile_warmup: ProfileWarmup,
+    /// Logged-in account, once its profile is loaded
+    pub current_user: Option<ProfileInfo>,
 
     // Chat state
     pub chats: Vec<Chat>,
@@ -159,11 +276,24 @@ pub struct App {
     pub messages: Vec<ChatMessage>,
     pub messages_scroll: usize,
     pub target_message_id: Option<i64>,
+    /// Search hit or reply target shown inverted until the deadline or the
+    /// next navigation
+    pub highlighted_message: Option<(i64, Instant)>,
     pub reply_to: Option<(i64, ReplyPreview)>,
+    /// Last id handed out by [`App::next_upload_id`]
+    pub last_upload_id: u64,
+    /// File just downloaded, opened by `o` until the selection moves
+    pub last_download: Option<std::path::PathBuf>,
+    /// History export started with `:export`, cancelled with Esc
+    pub history_export: Option<HistoryExport>,
+    /// Local message cache, with the `cache` feature
+    pub cache: Option<std::sync::Arc<dyn CacheLayer>>,
 
     // Search and filter state
     pub chat_filter: Option<ChatFilter>,
     pub global_search: Option<GlobalSearch>,
+    /// Results of the last global search, for `n`/`N`
+    pub search_hits: Option<SearchHits>,
 
     // Pagination state
     pub chats_pagination: ChatsPagination,
@@ -172,21 +302,102 @@ pub struct App {
     // Input state
     pub input: String,
     pub input_cursor: usize,
+    /// Chat that was open when the current input was started
+    pub input_peer_id: Option<i64>,
 
     // Command mode state
     pub command_input: String,
     pub command_cursor: usize,
+    /// Executed `:` commands, saved across sessions
+    pub command_history: CommandHistory,
+    /// Up/Down are walking through the command history
+    pub history_recall: Option<HistoryRecall>,
+    /// Ctrl+R search through the command history
+    pub history_search: Option<HistorySearch>,
 
     // UI state
     pub status: Option<String>,
     pub is_loading: bool,
     pub editing_message: Option<usize>,
+    pub edit_base_hash: Option<u64>,
+    pub edit_conflict: Option<EditConflict>,
+    pub captcha: Option<CaptchaPrompt>,
+    pub cross_chat_send: Option<CrossChatSend>,
+    pub whois: Option<Whois>,
+    pub read_by: Option<ReadersView>,
+    pub members: Option<MembersView>,
+    /// Chat whose `:info` popup is open
+    pub chat_info: Option<i64>,
+    /// Media counts per chat, kept for the session
+    pub chat_infos: HashMap<i64, ChatInfo>,
+    /// Group chat waiting for the y/n confirmation of `:leave`
+    pub leave_chat: Option<i64>,
+    /// Chat waiting for the y/n confirmation of `dd` or `:delchat`
+    pub delete_chat: Option<i64>,
+    /// Messages waiting for the `dd` prompt
+    pub delete_prompt: Option<DeletePrompt>,
+    /// Actions offered for the selected message (`Space` / `m`)
+    pub message_menu: Option<MessageMenu>,
+    /// Links of the selected message to pick from, when it has several
+    pub link_picker: Option<LinkPicker>,
+    /// Chats deleted this session, listed again if a message arrives
+    pub deleted_chats: HashMap<i64, Chat>,
+    pub new_chat: Option<NewChatView>,
     pub show_help: bool,
+    pub show_stats: bool,
+    /// Log lines shown with `:log tail`
+    pub log_view: Option<LogView>,
+    /// Photo shown with `P`
+    pub photo_preview: Option<PhotoPreview>,
+    /// How this terminal draws images; `None` opens photos in a viewer
+    pub graphics: Option<GraphicsProtocol>,
+    /// Rendered message lines, filled while drawing
+    pub message_lines: RefCell<MessageLineCache>,
+    /// Session counters for `:stats`
+    pub stats: Stats,
+    pub registers: Registers,
+    /// `"` was pressed; the next key names a register
+    pub awaiting_register: bool,
+    /// `y` was pressed; the next key picks what to yank
+    pub awaiting_yank: bool,
+    /// `g` was pressed in the message list; the next key picks where to go
+    pub awaiting_go: bool,
+    /// Where `gr` jumped from, newest last; Ctrl+O goes back
+    pub jump_list: Vec<JumpPoint>,
+    /// `V` was pressed on this message; messages from it to the cursor
+    /// are selected
+    pub visual_anchor: Option<i64>,
+    /// Messages that arrived below the cursor while reading history
+    pub unseen_below: usize,
+    /// Where the last frame drew what the mouse can click
+    pub layout: RefCell<LayoutMap>,
+    /// Terminal size in cells, kept up to date on resize
+    pub terminal_size: Size,
+    /// Chat list width, percent of the screen (`:set sidebar`)
+    pub sidebar_width: u16,
+    /// Chat list shown or hidden with `Ctrl+B`; `None` hides it only on
+    /// narrow terminals
+    pub sidebar_toggle: Option<bool>,
+    /// Register named with `"x` for the next yank or paste
+    pub pending_register: Option<char>,
+    pub show_registers: bool,
     pub forward_view: Option<ForwardView>,
     pub completion_state: CompletionState,
 
+    pub config: Config,
+    /// Settings shared with the other frontends
+    pub settings: Settings,
+
     // Async action sender
     pub action_tx: Option<mpsc::UnboundedSender<AsyncAction>>,
+    /// Task running the actions; aborting it cancels the requests in flight
+    pub action_handler: Option<JoinHandle<()>>,
+    /// Stops the running Long Poll loop when dropped
+    pub long_poll_shutdown: Option<watch::Sender<bool>>,
+    /// Makes the Long Poll loop get a new server at once (`:reconnect`)
+    pub long_poll_reconnect: std::sync::Arc<tokio::sync::Notify>,
+    /// Shown in the status bar
+    pub connection: ConnectionState,
 }
 
 impl Default for App {
@@ -200,7 +411,11 @@ impl Default for App {
             token_input: String::new(),
             token_cursor: 0,
             vk_client: None,
+            shared_client: watch::Sender::new(None),
+            session_expired: false,
+            redirect_wait: None,
             users: HashMap::new(),
+            profile_warmup: ProfileWarmup::default(),
             current_user: None,
             chats: Vec::new(),
             selected_chat: 0,
@@ -208,23 +423,74 @@ impl Default for App {
             messages: Vec::new(),
             messages_scroll: 0,
             target_message_id: None,
+            highlighted_message: None,
             reply_to: None,
+            last_upload_id: 0,
+            last_download: None,
+            history_export: None,
+            cache: None,
             chat_filter: None,
             global_search: None,
+            search_hits: None,
             chats_pagination: ChatsPagination::default(),
             messages_pagination: None,
             input: String::new(),
             input_cursor: 0,
+            input_peer_id: None,
             command_input: String::new(),
             command_cursor: 0,
+            command_history: CommandHistory::default(),
+            history_recall: None,
+            history_search: None,
             status: None,
             is_loading: false,
             editing_message: None,
+            edit_base_hash: None,
+            edit_conflict: None,
+            captcha: None,
+            cross_chat_send: None,
+            whois: None,
+            read_by: None,
+            members: None,
+            chat_info: None,
+            chat_infos: HashMap::new(),
+            leave_chat: None,
+            delete_chat: None,
+            delete_prompt: None,
+            message_menu: None,
+            link_picker: None,
+            deleted_chats: HashMap::new(),
+            new_chat: None,
             show_help: false,
+            show_stats: false,
+            log_view: None,
+            photo_preview: None,
+            graphics: None,
+            message_lines: RefCell::default(),
+            stats: Stats::default(),
+            registers: Registers::default(),
+            awaiting_register: false,
+            awaiting_yank: false,
+            awaiting_go: false,
+            jump_list: Vec::new(),
+            visual_anchor: None,
+            unseen_below: 0,
+            layout: RefCell::default(),
+            terminal_size: Size::default(),
+            sidebar_width: DEFAULT_SIDEBAR_WIDTH,
+            sidebar_toggle: None,
+            pending_register: None,
+            show_registers: false,
             forward_view: None,
             completion_state: CompletionState::default(),
             forward: None,
             action_tx: None,
+            action_handler: None,
+            long_poll_shutdown: None,
+            long_poll_reconnect: std::sync::Arc::default(),
+            connection: ConnectionState::default(),
+            config: Config::default(),
+            settings: Settings::default(),
         }
     }
 }
@@ -237,7 +503,8 @@ pub enum ForwardStage {
 
 #[derive(Debug, Clone)]
 pub struct ForwardState {
-    pub source_message_id: i64,
+    /// Messages to forward, oldest first
+    pub source_message_ids: Vec<i64>,
     pub query: String,
     pub filtered: Vec<Chat>,
     pub selected: usize,
@@ -245,6 +512,335 @@ pub struct ForwardState {
     pub stage: ForwardStage,
 }
 
+/// Message was edited elsewhere while the user was editing it
+#[derive(Debug, Clone)]
+pub struct EditConflict {
+    pub peer_id: i64,
+    pub message_id: i64,
+    pub cmid: Option<i64>,
+    pub local_text: String,
+    pub server_text: String,
+}
+
+/// Local OAuth redirect catcher started with `Ctrl+L`
+#[derive(Debug)]
+pub struct RedirectWait {
+    pub started: Instant,
+    /// Stops the catcher when sent to or dropped
+    pub cancel: watch::Sender<bool>,
+}
+
+/// History export in progress
+#[derive(Debug)]
+pub struct HistoryExport {
+    pub path: std::path::PathBuf,
+    /// Stops the export when sent to or dropped
+    pub cancel: watch::Sender<bool>,
+}
+
+/// Profile popup opened with `u` or `:whois`
+#[derive(Debug, Clone)]
+pub struct Whois {
+    pub user_id: i64,
+    /// `None` while loading
+    pub profile: Option<ProfileDetails>,
+}
+
+/// Read receipts popup opened with `R` on an own message in a group chat
+#[derive(Debug, Clone)]
+pub struct ReadersView {
+    pub peer_id: i64,
+    pub cmid: i64,
+    /// `None` while loading
+    pub readers: Option<MessageReaders>,
+}
+
+/// Member list popup opened with `:members`
+#[derive(Debug, Clone)]
+pub struct MembersView {
+    pub peer_id: i64,
+    pub members: Vec<ChatMember>,
+    /// Member count reported by VK, `None` until the first page arrives
+    pub total: Option<u32>,
+    pub selected: usize,
+    pub is_loading: bool,
+}
+
+impl MembersView {
+    /// Whether VK has members that are not loaded yet
+    pub fn has_more(&self) -> bool {
+        self.total
+            .is_some_and(|total| self.members.len() < total as usize)
+    }
+}
+
+/// User picker opened with `:newchat <title>`
+#[derive(Debug, Clone)]
+pub struct NewChatView {
+    pub title: String,
+    /// (user_id, name) of dialog peers, then friends not among them
+    pub people: Vec<(i64, String)>,
+    /// Picked user ids in the order they were picked
+    pub picked: Vec<i64>,
+    pub selected: usize,
+    /// Friends are still loading
+    pub is_loading: bool,
+}
+
+impl NewChatView {
+    /// Pick the highlighted user, or unpick them if already picked
+    pub fn toggle(&mut self) {
+        let Some((id, _)) = self.people.get(self.selected) else {
+            return;
+        };
+        if let Some(pos) = self.picked.iter().position(|p| p == id) {
+            self.picked.remove(pos);
+        } else {
+            self.picked.push(*id);
+        }
+    }
+}
+
+/// Where the last frame drew the main panels, for mouse clicks, and the
+/// inside of the photo popup, for the image drawn over it
+#[derive(Debug, Clone, Default)]
+pub struct LayoutMap {
+    pub chat_list: Rect,
+    /// Index of the topmost chat shown; each chat takes two rows
+    pub first_chat: usize,
+    pub messages: Rect,
+    /// Visible messages as (first screen row, rows, message index)
+    pub message_rows: Vec<(u16, u16, usize)>,
+    pub input: Rect,
+    /// The "↓ N new messages" hint, while shown
+    pub unseen_hint: Option<Rect>,
+    pub photo: Option<Rect>,
+}
+
+impl LayoutMap {
+    /// Index of the chat drawn at `pos`, which may be past the last chat
+    pub fn chat_at(&self, pos: Position) -> Option<usize> {
+        let inner = inner(self.chat_list);
+        inner
+            .contains(pos)
+            .then(|| self.first_chat + usize::from((pos.y - inner.y) / 2))
+    }
+
+    /// Index of the message drawn at `pos`
+    pub fn message_at(&self, pos: Position) -> Option<usize> {
+        if !inner(self.messages).contains(pos) {
+            return None;
+        }
+        self.message_rows
+            .iter()
+            .find(|(top, rows, _)| (*top..top + rows).contains(&pos.y))
+            .map(|(_, _, idx)| *idx)
+    }
+
+    /// Panel under `pos`
+    pub fn panel_at(&self, pos: Position) -> Option<Focus> {
+        [
+            (self.chat_list, Focus::ChatList),
+            (self.messages, Focus::Messages),
+            (self.input, Focus::Input),
+        ]
+        .into_iter()
+        .find(|(area, _)| area.contains(pos))
+        .map(|(_, panel)| panel)
+    }
+}
+
+/// `area` without its border
+fn inner(area: Rect) -> Rect {
+    area.inner(ratatui::layout::Margin::new(1, 1))
+}
+
+/// A choice in the `dd` prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteChoice {
+    ForMe,
+    ForAll,
+    Cancel,
+}
+
+/// Messages waiting for the `dd` prompt to say how to delete them
+#[derive(Debug, Clone)]
+pub struct DeletePrompt {
+    pub message_ids: Vec<i64>,
+    /// All of them are ours and younger than 24 hours
+    pub for_all_allowed: bool,
+    pub selected: DeleteChoice,
+}
+
+impl DeletePrompt {
+    pub const CHOICES: [DeleteChoice; 3] = [
+        DeleteChoice::ForMe,
+        DeleteChoice::ForAll,
+        DeleteChoice::Cancel,
+    ];
+
+    pub fn new(message_ids: Vec<i64>, for_all_allowed: bool) -> Self {
+        Self {
+            message_ids,
+            for_all_allowed,
+            selected: DeleteChoice::ForMe,
+        }
+    }
+
+    pub fn is_enabled(&self, choice: DeleteChoice) -> bool {
+        choice != DeleteChoice::ForAll || self.for_all_allowed
+    }
+
+    /// Move the highlight by one, skipping a disabled choice. Only the
+    /// middle choice can be disabled, so the next one never is.
+    pub fn move_selection(&mut self, down: bool) {
+        let last = Self::CHOICES.len() - 1;
+        let step = |pos: usize| {
+            if down {
+                (pos + 1).min(last)
+            } else {
+                pos.saturating_sub(1)
+            }
+        };
+        let current = Self::CHOICES
+            .iter()
+            .position(|c| *c == self.selected)
+            .unwrap_or(0);
+        let mut pos = step(current);
+        if !self.is_enabled(Self::CHOICES[pos]) {
+            pos = step(pos);
+        }
+        self.selected = Self::CHOICES[pos];
+    }
+}
+
+/// An entry of the message menu; each stands for a key of its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuAction {
+    Reply,
+    Forward,
+    Edit,
+    Delete,
+    Copy,
+    Pin,
+    React,
+    Download,
+    OpenLink,
+}
+
+impl MenuAction {
+    pub const ALL: [MenuAction; 9] = [
+        MenuAction::Reply,
+        MenuAction::Forward,
+        MenuAction::Edit,
+        MenuAction::Delete,
+        MenuAction::Copy,
+        MenuAction::Pin,
+        MenuAction::React,
+        MenuAction::Download,
+        MenuAction::OpenLink,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            MenuAction::Reply => "Reply",
+            MenuAction::Forward => "Forward",
+            MenuAction::Edit => "Edit",
+            MenuAction::Delete => "Delete",
+            MenuAction::Copy => "Copy text",
+            MenuAction::Pin => "Pin",
+            MenuAction::React => "React",
+            MenuAction::Download => "Download attachments",
+            MenuAction::OpenLink => "Open link",
+        }
+    }
+
+    /// The key doing the same without the menu
+    pub fn key(self) -> &'static str {
+        match self {
+            MenuAction::Reply => "r",
+            MenuAction::Forward => "f",
+            MenuAction::Edit => "e",
+            MenuAction::Delete => "dd",
+            MenuAction::Copy => "yy",
+            MenuAction::Pin => "p",
+            MenuAction::React => "x",
+            MenuAction::Download => "a",
+            MenuAction::OpenLink => "o",
+        }
+    }
+}
+
+/// Popup listing what can be done with the selected message
+#[derive(Debug, Clone)]
+pub struct MessageMenu {
+    /// Only the actions that apply to the message, in [`MenuAction::ALL`] order
+    pub actions: Vec<MenuAction>,
+    pub selected: usize,
+}
+
+impl MessageMenu {
+    pub fn new(actions: Vec<MenuAction>) -> Self {
+        Self {
+            actions,
+            selected: 0,
+        }
+    }
+
+    pub fn move_selection(&mut self, down: bool) {
+        self.selected = if down {
+            (self.selected + 1).min(self.actions.len().saturating_sub(1))
+        } else {
+            self.selected.saturating_sub(1)
+        };
+    }
+
+    pub fn current(&self) -> Option<MenuAction> {
+        self.actions.get(self.selected).copied()
+    }
+}
+
+/// Popup asking which link of a message to open
+#[derive(Debug, Clone)]
+pub struct LinkPicker {
+    pub urls: Vec<String>,
+    pub selected: usize,
+}
+
+/// Entries kept in [`App::jump_list`]
+pub const JUMP_LIST_LIMIT: usize = 50;
+
+/// A message the cursor was on before jumping to a replied message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JumpPoint {
+    pub peer_id: i64,
+    pub message_id: i64,
+}
+
+/// Input typed in another chat is about to be sent to this one
+#[derive(Debug, Clone)]
+pub struct CrossChatSend {
+    pub peer_id: i64,
+    pub title: String,
+}
+
+/// Photo preview popup; the image is drawn over it by the main loop
+#[derive(Debug, Clone)]
+pub struct PhotoPreview {
+    pub title: String,
+    /// Decoded photo, `None` while it is downloading
+    pub image: Option<std::sync::Arc<RgbaImage>>,
+}
+
+/// The end of the log file, newest line last
+#[derive(Debug, Clone)]
+pub struct LogView {
+    pub path: std::path::PathBuf,
+    pub lines: Vec<String>,
+    /// Lines scrolled up from the newest one
+    pub from_bottom: usize,
+}
+
 #[derive(Debug, Clone)]
 pub struct ForwardView {
     pub items: Vec<ForwardItem>,
@@ -274,6 +870,35 @@ pub struct PathEntry {
     pub is_dir: bool,
 }
 
+/// Position of Up/Down in the command history
+#[derive(Debug, Clone)]
+pub struct HistoryRecall {
+    /// What was typed before the first Up; only entries starting with it
+    /// are recalled
+    pub prefix: String,
+    pub index: usize,
+}
+
+/// Ctrl+R search through the command history
+#[derive(Debug, Clone, Default)]
+pub struct HistorySearch {
+    pub query: String,
+    /// Entry matching the query, if any
+    pub hit: Option<usize>,
+    /// Command input to restore when the search is cancelled
+    pub draft: String,
+}
+
+/// Conversation offered for a chat argument
+#[derive(Debug, Clone)]
+pub struct ChatNameOption {
+    pub peer_id: i64,
+    pub title: String,
+    /// What selecting it puts in the command: the title, or the peer id
+    /// when another chat has the same title
+    pub insert: String,
+}
+
 /// Completion state machine
 #[derive(Debug, Clone, Default)]
 #[allow(dead_code)]
@@ -302,4 +927,11 @@ pub enum CompletionState {
         entries: Vec<PathEntry>,
         selected: usize,
     },
+
+    /// Completing conversation titles
+    ChatNames {
+        command: String,
+        options: Vec<ChatNameOption>,
+        selected: usize,
+    },
 }
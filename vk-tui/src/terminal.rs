@@ -0,0 +1,306 @@
+//! Terminal capability detection and setup/teardown
+//!
+//! Minimal terminals (`TERM=dumb`, serial/CI consoles, screen readers) don't
+//! understand the alternate screen, mouse reporting or bright colors. We
+//! detect what is available at startup and only enable that, so restoring
+//! the terminal undoes exactly what was done.
+
+use std::io::{self, IsTerminal};
+
+use anyhow::Result;
+use crossterm::{
+    event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture},
+    execute,
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
+};
+use ratatui::{
+    Terminal,
+    backend::CrosstermBackend,
+    buffer::Buffer,
+    style::{Color, Modifier},
+};
+
+/// How colors are rendered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    /// Render styles as-is
+    Full,
+    /// Only the basic 8 ANSI colors
+    Basic,
+    /// No colors, highlights are shown with reverse video
+    None,
+}
+
+/// Terminal features enabled for this session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerminalCaps {
+    pub alt_screen: bool,
+    pub mouse: bool,
+    /// Pastes arrive as one event instead of a key per character
+    pub bracketed_paste: bool,
+    pub color: ColorMode,
+}
+
+/// Command-line overrides for capability detection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CapsOverrides {
+    pub no_alt_screen: bool,
+    pub no_color: bool,
+    /// Leave the mouse to the terminal, for its own text selection
+    pub no_mouse: bool,
+}
+
+impl TerminalCaps {
+    /// Detect capabilities from the environment and stdout
+    pub fn detect(overrides: CapsOverrides) -> Self {
+        let term = std::env::var("TERM").ok();
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        Self::from_env(
+            term.as_deref(),
+            no_color,
+            io::stdout().is_terminal(),
+            overrides,
+        )
+    }
+
+    fn from_env(
+        term: Option<&str>,
+        no_color: bool,
+        is_tty: bool,
+        overrides: CapsOverrides,
+    ) -> Self {
+        let term = term.unwrap_or("").to_ascii_lowercase();
+
+        // Hardware VT terminals and their emulations are monochrome without mouse
+        let mut caps = if !is_tty || term.is_empty() || term == "dumb" || term.starts_with("vt") {
+            Self {
+                alt_screen: false,
+                mouse: false,
+                bracketed_paste: false,
+                color: ColorMode::None,
+            }
+        } else if term == "linux" || term == "ansi" || term == "cons25" {
+            // Kernel/BSD consoles: 8 colors, no xterm mouse reporting
+            Self {
+                alt_screen: true,
+                mouse: false,
+                bracketed_paste: false,
+                color: ColorMode::Basic,
+            }
+        } else {
+            Self {
+                alt_screen: true,
+                mouse: true,
+                bracketed_paste: true,
+                color: ColorMode::Full,
+            }
+        };
+
+        if no_color || overrides.no_color {
+            caps.color = ColorMode::None;
+        }
+        if overrides.no_alt_screen {
+            caps.alt_screen = false;
+        }
+        if overrides.no_mouse {
+            caps.mouse = false;
+        }
+
+        caps
+    }
+
+    /// Enter raw mode and enable the detected features
+    pub fn init(&self) -> Result<Terminal<CrosstermBackend<io::Stdout>>> {
+        enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        if self.alt_screen {
+            execute!(stdout, EnterAlternateScreen)?;
+        }
+        if self.mouse {
+            execute!(stdout, EnableMouseCapture)?;
+        }
+        if self.bracketed_paste {
+            execute!(stdout, EnableBracketedPaste)?;
+        }
+        let backend = CrosstermBackend::new(stdout);
+        let mut terminal = Terminal::new(backend)?;
+        if !self.alt_screen {
+            // Drawing in-place: start from a clean screen
+            terminal.clear()?;
+        }
+        Ok(terminal)
+    }
+
+    /// Undo exactly what [`TerminalCaps::init`] enabled
+    pub fn restore(&self, terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> Result<()> {
+        disable_raw_mode()?;
+        if self.mouse {
+            execute!(terminal.backend_mut(), DisableMouseCapture)?;
+        }
+        if self.bracketed_paste {
+            execute!(terminal.backend_mut(), DisableBracketedPaste)?;
+        }
+        if self.alt_screen {
+            execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+        } else {
+            terminal.clear()?;
+        }
+        terminal.show_cursor()?;
+        Ok(())
+    }
+
+    /// Best-effort restore without a `Terminal` (used from the panic hook)
+    pub fn restore_raw(&self) {
+        let _ = disable_raw_mode();
+        let mut stdout = io::stdout();
+        if self.mouse {
+            let _ = execute!(stdout, DisableMouseCapture);
+        }
+        if self.bracketed_paste {
+            let _ = execute!(stdout, DisableBracketedPaste);
+        }
+        if self.alt_screen {
+            let _ = execute!(stdout, LeaveAlternateScreen);
+        }
+        let _ = execute!(stdout, crossterm::cursor::Show);
+    }
+
+    /// Downgrade colors of a rendered frame to the supported palette
+    pub fn apply_colors(&self, buf: &mut Buffer) {
+        if self.color == ColorMode::Full {
+            return;
+        }
+
+        for cell in buf.content.iter_mut() {
+            let fg = cell.fg;
+            let bg = cell.bg;
+
+            let bg_supported = self.color == ColorMode::Basic && to_basic(bg).is_some();
+            if bg != Color::Reset && !bg_supported {
+                // Background highlight that can't be shown: use reverse video
+                cell.modifier.insert(Modifier::REVERSED);
+                cell.bg = Color::Reset;
+                cell.fg = Color::Reset;
+                continue;
+            }
+
+            match self.color {
+                ColorMode::Basic => {
+                    cell.fg = to_basic(fg).unwrap_or(Color::Reset);
+                    cell.bg = to_basic(bg).unwrap_or(Color::Reset);
+                }
+                ColorMode::None => {
+                    cell.fg = Color::Reset;
+                    cell.bg = Color::Reset;
+                }
+                ColorMode::Full => {}
+            }
+        }
+    }
+}
+
+/// Map a color onto the 8 basic ANSI colors (`None` if there is no sensible match)
+fn to_basic(color: Color) -> Option<Color> {
+    match color {
+        Color::Reset => Some(Color::Reset),
+        Color::Black
+        | Color::Red
+        | Color::Green
+        | Color::Yellow
+        | Color::Blue
+        | Color::Magenta
+        | Color::Cyan
+        | Color::Gray => Some(color),
+        // ANSI 7 is the brightest of the 8; bright white is not among them
+        Color::White => Some(Color::Gray),
+        Color::LightRed => Some(Color::Red),
+        Color::LightGreen => Some(Color::Green),
+        Color::LightYellow => Some(Color::Yellow),
+        Color::LightBlue => Some(Color::Blue),
+        Color::LightMagenta => Some(Color::Magenta),
+        Color::LightCyan => Some(Color::Cyan),
+        // Dark gray (bright black) and arbitrary colors fall back to the
+        // terminal default
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect(term: Option<&str>, no_color: bool, is_tty: bool) -> TerminalCaps {
+        TerminalCaps::from_env(term, no_color, is_tty, CapsOverrides::default())
+    }
+
+    #[test]
+    fn test_full_terminal() {
+        let caps = detect(Some("xterm-256color"), false, true);
+        assert!(caps.alt_screen && caps.mouse && caps.bracketed_paste);
+        assert_eq!(caps.color, ColorMode::Full);
+    }
+
+    #[test]
+    fn test_dumb_or_not_tty() {
+        for caps in [detect(Some("dumb"), false, true), detect(None, false, true)] {
+            assert!(!caps.alt_screen && !caps.mouse && !caps.bracketed_paste);
+            assert_eq!(caps.color, ColorMode::None);
+        }
+        assert!(!detect(Some("xterm-256color"), false, false).alt_screen);
+    }
+
+    #[test]
+    fn test_no_color_and_overrides() {
+        assert_eq!(detect(Some("xterm"), true, true).color, ColorMode::None);
+        assert_eq!(detect(Some("linux"), false, true).color, ColorMode::Basic);
+
+        let overrides = CapsOverrides {
+            no_alt_screen: true,
+            no_color: true,
+            no_mouse: false,
+        };
+        let caps = TerminalCaps::from_env(Some("xterm-256color"), false, true, overrides);
+        assert!(!caps.alt_screen);
+        assert!(caps.mouse);
+        assert_eq!(caps.color, ColorMode::None);
+
+        let overrides = CapsOverrides {
+            no_mouse: true,
+            ..CapsOverrides::default()
+        };
+        let caps = TerminalCaps::from_env(Some("xterm-256color"), false, true, overrides);
+        assert!(!caps.mouse);
+        assert!(caps.alt_screen);
+    }
+
+    #[test]
+    fn test_apply_colors_reverses_highlight() {
+        let caps = detect(Some("xterm"), true, true);
+        let mut buf = Buffer::empty(ratatui::layout::Rect::new(0, 0, 2, 1));
+        buf.content[0].set_fg(Color::Yellow).set_bg(Color::DarkGray);
+        buf.content[1].set_fg(Color::Cyan);
+
+        caps.apply_colors(&mut buf);
+
+        assert!(buf.content[0].modifier.contains(Modifier::REVERSED));
+        assert_eq!(buf.content[0].bg, Color::Reset);
+        assert_eq!(buf.content[1].fg, Color::Reset);
+    }
+
+    #[test]
+    fn test_basic_colors_keep_gray_and_white() {
+        let caps = detect(Some("linux"), false, true);
+        let mut buf = Buffer::empty(ratatui::layout::Rect::new(0, 0, 3, 1));
+        buf.content[0].set_fg(Color::Gray);
+        buf.content[1].set_fg(Color::White).set_bg(Color::Blue);
+        buf.content[2].set_fg(Color::Black).set_bg(Color::White);
+
+        caps.apply_colors(&mut buf);
+
+        assert_eq!(buf.content[0].fg, Color::Gray);
+        assert_eq!(buf.content[1].fg, Color::Gray);
+        assert_eq!(buf.content[1].bg, Color::Blue);
+        assert_eq!(buf.content[2].bg, Color::Gray);
+        assert!(!buf.content[2].modifier.contains(Modifier::REVERSED));
+    }
+}
@@ -0,0 +1,269 @@
+//! Cache of the rendered lines of messages.
+//!
+//! Building the lines of a message (spans, timestamps, attachment
+//! details) is most of the cost of drawing the message list, and every
+//! key press redraws. Lines are kept per message and rebuilt only when
+//! what they show changes; with [`visible_window`] a frame touches only
+//! the messages on screen, so typing in a long chat stays fast.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+
+use ratatui::text::Line;
+
+use crate::state::ChatMessage;
+
+/// Rendered lines by message id.
+#[derive(Debug, Default)]
+pub struct MessageLineCache {
+    /// Chat and list width the entries were built for
+    peer_id: Option<i64>,
+    width: u16,
+    entries: HashMap<i64, (u64, Vec<Line<'static>>)>,
+    /// Lines built so far, cache misses
+    #[cfg(test)]
+    builds: usize,
+}
+
+impl MessageLineCache {
+    /// Drop everything when another chat is open or the panel was resized.
+    pub fn reset_if_changed(&mut self, peer_id: Option<i64>, width: u16) {
+        if self.peer_id != peer_id || self.width != width {
+            self.peer_id = peer_id;
+            self.width = width;
+            self.entries.clear();
+        }
+    }
+
+    /// Lines of `msg`, built with `build` unless cached for the same
+    /// content.
+    pub fn lines(
+        &mut self,
+        msg: &ChatMessage,
+        name_loading: bool,
+        build: impl Fn(&ChatMessage, bool) -> Vec<Line<'static>>,
+    ) -> &[Line<'static>] {
+        let key = fingerprint(msg, name_loading);
+        let fresh = matches!(self.entries.get(&msg.id), Some((cached, _)) if *cached == key);
+        if !fresh {
+            self.entries.insert(msg.id, (key, build(msg, name_loading)));
+            #[cfg(test)]
+            {
+                self.builds += 1;
+            }
+        }
+        &self.entries[&msg.id].1
+    }
+
+    /// Forget messages no longer in `messages` (deleted ones, mostly).
+    pub fn retain(&mut self, messages: &[ChatMessage]) {
+        if self.entries.len() <= messages.len() {
+            return;
+        }
+        let ids: HashSet<i64> = messages.iter().map(|m| m.id).collect();
+        self.entries.retain(|id, _| ids.contains(id));
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[cfg(test)]
+    fn builds(&self) -> usize {
+        self.builds
+    }
+}
+
+/// Hash of everything the lines of a message show.
+fn fingerprint(msg: &ChatMessage, name_loading: bool) -> u64 {
+    let mut h = DefaultHasher::new();
+    msg.text.hash(&mut h);
+    msg.kind.hash(&mut h);
+    msg.timestamp.hash(&mut h);
+    msg.from_name.hash(&mut h);
+    name_loading.hash(&mut h);
+    (msg.is_outgoing, msg.is_read, msg.is_edited, msg.is_pinned).hash(&mut h);
+    (msg.delivery as u8).hash(&mut h);
+    msg.upload.map(|u| u.percent()).hash(&mut h);
+    msg.fwd_count.hash(&mut h);
+    if let Some(reply) = &msg.reply {
+        (&reply.from, &reply.text).hash(&mut h);
+    }
+    for r in &msg.reactions {
+        (r.reaction_id, r.count, r.mine).hash(&mut h);
+    }
+    for att in &msg.attachments {
+        std::mem::discriminant(&att.kind).hash(&mut h);
+        (
+            &att.title,
+            &att.url,
+            &att.subtitle,
+            &att.description,
+            att.size,
+        )
+            .hash(&mut h);
+    }
+    h.finish()
+}
+
+/// Messages to hand to the list widget so it shows exactly what it would
+/// with all `len` of them: the selected one, and enough around it to fill
+/// `height` rows on either side. `rows` is the height of a message.
+pub fn visible_window(
+    len: usize,
+    selected: usize,
+    height: usize,
+    mut rows: impl FnMut(usize) -> usize,
+) -> Range<usize> {
+    if len == 0 {
+        return 0..0;
+    }
+    let selected = selected.min(len - 1);
+
+    let mut start = selected;
+    let mut above = rows(selected);
+    while start > 0 && above < height {
+        start -= 1;
+        above += rows(start);
+    }
+
+    let mut end = selected + 1;
+    let mut filled = above;
+    while end < len && filled < height {
+        filled += rows(end);
+        end += 1;
+    }
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use ratatui::layout::Rect;
+    use ratatui::widgets::ListItem;
+
+    use super::*;
+    use crate::state::{App, Screen};
+    use crate::ui::{message_items, message_lines};
+    use vk_core::fixtures;
+    use vk_core::timeline::Clock;
+
+    fn message(id: i64) -> ChatMessage {
+        let text = format!("message number {} with some text in it", id);
+        ChatMessage {
+            from_id: 1,
+            is_outgoing: id % 2 == 0,
+            ..fixtures::message(id, &text)
+        }
+    }
+
+    fn build(msg: &ChatMessage, _: bool) -> Vec<Line<'static>> {
+        vec![Line::from(msg.text.clone())]
+    }
+
+    #[test]
+    fn test_rebuilds_only_changed_messages() {
+        let mut cache = MessageLineCache::default();
+        let mut msg = message(1);
+        assert_eq!(cache.lines(&msg, false, build)[0].to_string(), msg.text);
+
+        msg.text = "edited".into();
+        assert_eq!(cache.lines(&msg, false, build)[0].to_string(), "edited");
+
+        cache.reset_if_changed(Some(5), 80);
+        assert_eq!(cache.len(), 0);
+        cache.lines(&msg, false, build);
+        cache.lines(&message(2), false, build);
+        cache.retain(&[message(2)]);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn test_visible_window() {
+        let one_row = |_| 1;
+        // Top of the list: everything that fits below the selection
+        assert_eq!(visible_window(100, 0, 10, one_row), 0..10);
+        // Bottom: the selection and what fits above it
+        assert_eq!(visible_window(100, 99, 10, one_row), 90..100);
+        assert_eq!(visible_window(100, 50, 10, one_row), 41..51);
+        assert_eq!(visible_window(3, 1, 10, one_row), 0..3);
+        assert_eq!(visible_window(0, 0, 10, one_row), 0..0);
+        // Taller messages take more of the height
+        assert_eq!(visible_window(100, 99, 10, |_| 3), 96..100);
+    }
+
+    /// A redraw with nothing changed builds no lines, and a frame builds
+    /// only the messages on screen, not all 2000.
+    #[test]
+    fn test_unchanged_frame_builds_nothing() {
+        let mut app = App {
+            screen: Screen::Main,
+            current_peer_id: Some(1),
+            messages: (0..2000).map(message).collect(),
+            messages_scroll: 1999,
+            ..App::default()
+        };
+        let area = Rect::new(0, 0, 120, 40);
+        let builds = |app: &App| app.message_lines.borrow().builds();
+
+        message_items(&app, area);
+        let first = builds(&app);
+        assert!(first > 0 && first < 100, "first frame built {}", first);
+
+        message_items(&app, area);
+        assert_eq!(builds(&app), first);
+
+        app.messages[1999].text = "edited".into();
+        message_items(&app, area);
+        assert_eq!(builds(&app), first + 1);
+    }
+
+    /// Per-frame cost of the message list items with 2000 unchanged
+    /// messages, against building an item for every message as each
+    /// frame used to. Drawing the visible rows is the same for both.
+    #[test]
+    fn test_cached_frame_is_faster() {
+        let app = App {
+            screen: Screen::Main,
+            current_peer_id: Some(1),
+            messages: (0..2000).map(message).collect(),
+            messages_scroll: 1999,
+            ..App::default()
+        };
+        let area = Rect::new(0, 0, 120, 40);
+
+        // Fastest of a few runs, to keep other tests' load out of it
+        let fastest = |frame: &dyn Fn() -> usize| {
+            (0..10)
+                .map(|_| {
+                    let start = Instant::now();
+                    std::hint::black_box(frame());
+                    start.elapsed()
+                })
+                .min()
+                .unwrap_or(Duration::MAX)
+        };
+
+        let uncached = fastest(&|| {
+            let items: Vec<ListItem> = app
+                .messages
+                .iter()
+                .map(|m| ListItem::new(message_lines(m, false, &Clock::default())))
+                .collect();
+            items.len()
+        });
+        message_items(&app, area);
+        let cached = fastest(&|| message_items(&app, area).0.len());
+
+        assert!(
+            cached * 5 < uncached,
+            "cached frame {:?}, uncached {:?}",
+            cached,
+            uncached
+        );
+    }
+}
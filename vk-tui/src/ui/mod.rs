@@ -7,7 +7,30 @@ use ratatui::{
     widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
 };
 
-use crate::state::{App, AttachmentKind, DeliveryStatus, Focus, ForwardStage, Mode, Screen};
+use crate::state::{
+    App, AttachmentInfo, AttachmentKind, ChatMessage, DeleteChoice, DeletePrompt, DeliveryStatus,
+    Focus, ForwardStage, LayoutMap, Mode, Screen,
+};
+use unicode_width::UnicodeWidthStr;
+use vk_core::format_reactions;
+use vk_core::longpoll::ConnectionState;
+use vk_core::media::MediaKind;
+use vk_core::outgoing::{length_counter, too_long};
+use vk_core::profiles::LOADING_NAME;
+use vk_core::timeline::{Clock, Row, day_rows, starts_day};
+
+mod message_lines;
+mod width;
+
+pub use message_lines::MessageLineCache;
+use message_lines::visible_window;
+use width::{cursor_display_col, truncate_to_width};
+
+/// Start of the chat filter and search inputs
+const SEARCH_PROMPT: &str = "🔍 ";
+
+/// Narrowest chat list drawn, in columns, whatever its configured percent
+const MIN_SIDEBAR_COLUMNS: u16 = 20;
 
 /// Main view function - renders the entire UI
 pub fn view(app: &App, frame: &mut Frame) {
@@ -26,6 +49,77 @@ pub fn view(app: &App, frame: &mut Frame) {
         render_forward_view_popup(app, frame);
     }
 
+    // Edit-conflict prompt on top
+    if app.edit_conflict.is_some() {
+        render_edit_conflict_popup(app, frame);
+    }
+
+    if app.cross_chat_send.is_some() {
+        render_cross_chat_send_popup(app, frame);
+    }
+
+    if app.whois.is_some() {
+        render_whois_popup(app, frame);
+    }
+
+    if app.members.is_some() {
+        render_members_popup(app, frame);
+    }
+
+    if let Some(peer_id) = app.leave_chat {
+        let question = format!("Leave '{}'?", chat_title(app, peer_id));
+        render_confirm_popup(frame, " Leave chat ", question, "leave");
+    }
+
+    if let Some(peer_id) = app.delete_chat {
+        let question = format!(
+            "Delete conversation '{}'? Its history is deleted for you.",
+            chat_title(app, peer_id)
+        );
+        render_confirm_popup(frame, " Delete conversation ", question, "delete");
+    }
+
+    if app.delete_prompt.is_some() {
+        render_delete_prompt_popup(app, frame);
+    }
+
+    if app.message_menu.is_some() {
+        render_message_menu_popup(app, frame);
+    }
+
+    if app.link_picker.is_some() {
+        render_link_picker_popup(app, frame);
+    }
+
+    if app.new_chat.is_some() {
+        render_new_chat_popup(app, frame);
+    }
+
+    if app.chat_info.is_some() {
+        render_chat_info_popup(app, frame);
+    }
+
+    if app.read_by.is_some() {
+        render_readers_popup(app, frame);
+    }
+
+    if app.show_registers {
+        render_registers_popup(app, frame);
+    }
+
+    // Redrawn every tick, so uptime and counters stay current
+    if app.show_stats {
+        render_stats_popup(app, frame);
+    }
+
+    if app.log_view.is_some() {
+        render_log_popup(app, frame);
+    }
+
+    if app.photo_preview.is_some() {
+        render_photo_popup(app, frame);
+    }
+
     // Render help popup on top if visible
     if app.show_help {
         render_help_popup(app, frame);
@@ -44,6 +138,98 @@ pub fn view(app: &App, frame: &mut Frame) {
     if app.global_search.is_some() {
         render_global_search_popup(app, frame);
     }
+
+    if app.captcha.is_some() {
+        render_captcha_popup(app, frame);
+    }
+
+    // Takes all keys until the session is renewed
+    if app.session_expired {
+        render_reauth_popup(app, frame);
+    }
+}
+
+/// Spinner while the redirect catcher waits, the `Ctrl+L` hint otherwise
+fn redirect_wait_line(app: &App) -> Line<'static> {
+    const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+    match &app.redirect_wait {
+        Some(wait) => {
+            let frame = wait.started.elapsed().as_millis() / 100 % SPINNER.len() as u128;
+            Line::from(Span::styled(
+                format!(
+                    "{} Waiting for browser redirect... (Esc to stop)",
+                    SPINNER[frame as usize]
+                ),
+                Style::default().fg(Color::Yellow),
+            ))
+        }
+        None => Line::from(vec![
+            Span::raw("Or press "),
+            Span::styled(
+                "Ctrl+L",
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" to wait for browser redirect automatically"),
+        ]),
+    }
+}
+
+/// Prompt for a new token over the main screen
+fn render_reauth_popup(app: &App, frame: &mut Frame) {
+    let area = frame.area();
+    let width = 70.min(area.width.saturating_sub(4));
+    let popup_area = centered_rect(width, 9, area);
+
+    frame.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(" Session expired ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Red));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3), // Instructions
+            Constraint::Length(3), // Input field
+            Constraint::Length(1), // Redirect catcher
+        ])
+        .split(inner);
+
+    let instructions = Paragraph::new(vec![
+        Line::from("Session expired — paste new redirect URL and press Enter."),
+        Line::from(vec![
+            Span::styled(
+                "Ctrl+O",
+                Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" opens the auth URL in browser"),
+        ]),
+        Line::from(Span::styled(
+            "Chats and drafts are kept.",
+            Style::default().fg(Color::DarkGray),
+        )),
+    ]);
+    frame.render_widget(instructions, chunks[0]);
+
+    let input = Paragraph::new(app.token_input.as_str()).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Cyan)),
+    );
+    frame.render_widget(input, chunks[1]);
+
+    frame.render_widget(Paragraph::new(redirect_wait_line(app)), chunks[2]);
+
+    let cursor_x = cursor_display_col(&app.token_input, app.token_cursor);
+    frame.set_cursor_position((chunks[1].x + cursor_x + 1, chunks[1].y + 1));
 }
 
 /// Render authentication screen
@@ -99,20 +285,19 @@ fn render_auth_screen(app: &App, frame: &mut Frame) {
     frame.render_widget(instructions, chunks[0]);
 
     // Auth URL (truncated if needed)
-    let auth_url = app.auth_url();
-    let url_display = if auth_url.len() > chunks[1].width as usize - 2 {
-        format!("{}...", &auth_url[..chunks[1].width as usize - 5])
-    } else {
-        auth_url
-    };
+    let url_display =
+        truncate_to_width(&app.auth_url(), chunks[1].width.saturating_sub(2) as usize);
     let url = Paragraph::new(url_display)
         .style(Style::default().fg(Color::Yellow))
         .wrap(Wrap { trim: false });
     frame.render_widget(url, chunks[1]);
 
     // Input label
-    let label = Paragraph::new("Paste redirect URL here and press Enter:")
-        .style(Style::default().fg(Color::Gray));
+    let label = Paragraph::new(vec![
+        Line::from("Paste redirect URL here and press Enter:"),
+        redirect_wait_line(app),
+    ])
+    .style(Style::default().fg(Color::Gray));
     frame.render_widget(label, chunks[3]);
 
     // Input field
@@ -126,8 +311,8 @@ fn render_auth_screen(app: &App, frame: &mut Frame) {
     frame.render_widget(input, chunks[4]);
 
     // Show cursor - calculate visual width for UTF-8
-    let cursor_x = visual_width(&app.token_input, app.token_cursor);
-    frame.set_cursor_position((chunks[4].x + cursor_x as u16 + 1, chunks[4].y + 1));
+    let cursor_x = cursor_display_col(&app.token_input, app.token_cursor);
+    frame.set_cursor_position((chunks[4].x + cursor_x + 1, chunks[4].y + 1));
 
     // Status
     if let Some(status) = &app.status {
@@ -145,10 +330,28 @@ fn render_auth_screen(app: &App, frame: &mut Frame) {
 
 /// Render main chat screen
 fn render_main_screen(app: &App, frame: &mut Frame) {
+    // Panels not drawn this frame must not catch clicks
+    *app.layout.borrow_mut() = LayoutMap::default();
+    let area = frame.area();
+
+    if !app.sidebar_visible() {
+        // One panel: the chat list until a chat is open and focused
+        if app.focus == Focus::ChatList || app.current_peer_id.is_none() {
+            render_chat_list(app, frame, area);
+        } else {
+            render_chat_area(app, frame, area);
+        }
+        return;
+    }
+
+    let sidebar = u32::from(area.width) * u32::from(app.sidebar_width) / 100;
+    let sidebar = u16::try_from(sidebar)
+        .unwrap_or(u16::MAX)
+        .max(MIN_SIDEBAR_COLUMNS);
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(30), Constraint::Percentage(70)])
-        .split(frame.area());
+        .constraints([Constraint::Length(sidebar), Constraint::Min(0)])
+        .split(area);
 
     render_chat_list(app, frame, chunks[0]);
     render_chat_area(app, frame, chunks[1]);
@@ -199,10 +402,12 @@ fn render_chat_list(app: &App, frame: &mut Frame, area: Rect) {
                     }),
                 ),
                 Span::styled(unread, Style::default().fg(Color::Cyan)),
+                Span::raw(if chat.is_muted { " 🔕" } else { "" }),
+                Span::raw(if chat.is_pinned { " 📌" } else { "" }),
             ]);
 
             let preview = Line::from(vec![Span::styled(
-                truncate_str(&chat.last_message, area.width.saturating_sub(4) as usize),
+                truncate_to_width(&chat.preview(), area.width.saturating_sub(4) as usize),
                 Style::default().fg(Color::DarkGray),
             )]);
 
@@ -216,11 +421,18 @@ fn render_chat_list(app: &App, frame: &mut Frame, area: Rect) {
         Style::default().fg(Color::DarkGray)
     };
 
-    let title = if app.is_loading {
-        " Chats (loading...) "
+    let unread_total = vk_core::total_unread(&app.chats);
+    let mut title = if app.is_loading {
+        " Chats (loading...) ".to_string()
+    } else if unread_total > 0 {
+        format!(" Chats ({} unread) ", unread_total)
     } else {
-        " Chats "
+        " Chats ".to_string()
     };
+    // Which account this is
+    if let Some(user) = &app.current_user {
+        title.push_str(&format!("— {} ", user.full_name()));
+    }
 
     let list = List::new(items)
         .block(
@@ -240,6 +452,9 @@ fn render_chat_list(app: &App, frame: &mut Frame, area: Rect) {
     state.select(Some(app.selected_chat));
 
     frame.render_stateful_widget(list, area, &mut state);
+    let mut layout = app.layout.borrow_mut();
+    layout.chat_list = area;
+    layout.first_chat = state.offset();
 
     // Render filter input if active
     if let Some(filter) = &app.chat_filter {
@@ -250,7 +465,7 @@ fn render_chat_list(app: &App, frame: &mut Frame, area: Rect) {
             height: 3,
         };
 
-        let filter_text = format!("🔍 {}", filter.query);
+        let filter_text = format!("{}{}", SEARCH_PROMPT, filter.query);
         let filter_widget = Paragraph::new(filter_text)
             .block(
                 Block::default()
@@ -264,7 +479,10 @@ fn render_chat_list(app: &App, frame: &mut Frame, area: Rect) {
         frame.render_widget(filter_widget, filter_area);
 
         // Render cursor
-        let cursor_x = filter_area.x + 3 + filter.cursor as u16; // +3 for "🔍 "
+        let cursor_x = filter_area.x
+            + 1
+            + cursor_display_col(SEARCH_PROMPT, usize::MAX)
+            + cursor_display_col(&filter.query, filter.cursor);
         let cursor_y = filter_area.y + 1;
         frame.set_cursor_position(Position::new(cursor_x, cursor_y));
     }
@@ -272,125 +490,50 @@ fn render_chat_list(app: &App, frame: &mut Frame, area: Rect) {
 
 /// Render the chat area (messages + input)
 fn render_chat_area(app: &App, frame: &mut Frame, area: Rect) {
+    let banner_height = u16::from(app.reply_to.is_some());
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Min(3),    // Messages
-            Constraint::Length(3), // Input
-            Constraint::Length(1), // Status
+            Constraint::Min(3),                // Messages
+            Constraint::Length(banner_height), // Reply banner
+            Constraint::Length(3),             // Input
+            Constraint::Length(1),             // Status
         ])
         .split(area);
 
     render_messages(app, frame, chunks[0]);
-    render_input(app, frame, chunks[1]);
-    render_status(app, frame, chunks[2]);
+    render_reply_banner(app, frame, chunks[1]);
+    render_input(app, frame, chunks[2]);
+    render_status(app, frame, chunks[3]);
+}
+
+/// "↩ Replying to Name: text" above the input while a reply is composed
+fn render_reply_banner(app: &App, frame: &mut Frame, area: Rect) {
+    let Some((_, preview)) = &app.reply_to else {
+        return;
+    };
+    const HINT: &str = "  (Ctrl+C to cancel)";
+
+    let snippet = match preview.text.lines().next() {
+        Some(line) if !line.is_empty() => line.to_string(),
+        _ if !preview.attachments.is_empty() => "[attachment]".to_string(),
+        _ => String::new(),
+    };
+    let prefix = format!("↩ Replying to {}: ", preview.from);
+    let room =
+        (area.width as usize).saturating_sub(UnicodeWidthStr::width(prefix.as_str()) + HINT.len());
+    let banner = Line::from(vec![
+        Span::styled(prefix, Style::default().fg(Color::Yellow)),
+        Span::raw(truncate_to_width(&snippet, room)),
+        Span::styled(HINT, Style::default().fg(Color::DarkGray)),
+    ]);
+    frame.render_widget(Paragraph::new(banner), area);
 }
 
 /// Render messages panel
 fn render_messages(app: &App, frame: &mut Frame, area: Rect) {
     let is_focused = app.focus == Focus::Messages;
-
-    let render_lines = |msg: &crate::state::ChatMessage| -> Vec<Line<'static>> {
-        let name_style = if msg.is_outgoing {
-            Style::default().fg(Color::Green)
-        } else {
-            Style::default().fg(Color::Cyan)
-        };
-
-        let read_indicator = match msg.delivery {
-            DeliveryStatus::Pending => "...",
-            DeliveryStatus::Failed => "!",
-            DeliveryStatus::Sent => {
-                if msg.is_outgoing {
-                    if msg.is_read { "✓✓" } else { "✓" }
-                } else {
-                    ""
-                }
-            }
-        };
-
-        // Format timestamp
-        let time = format_timestamp(msg.timestamp);
-
-        let mut first_line = vec![
-            Span::styled(time, Style::default().fg(Color::DarkGray)),
-            Span::raw(" "),
-            if msg.is_pinned {
-                Span::styled("📌 ", Style::default().fg(Color::Yellow))
-            } else {
-                Span::raw("")
-            },
-            Span::styled(msg.from_name.clone(), name_style),
-            Span::raw(": "),
-            Span::raw(msg.text.clone()),
-        ];
-
-        // Add edited indicator
-        if msg.is_edited {
-            first_line.push(Span::styled(" (e)", Style::default().fg(Color::Yellow)));
-        }
-
-        // Add delivery status indicator
-        if !read_indicator.is_empty() {
-            first_line.push(Span::styled(
-                format!(" {}", read_indicator),
-                Style::default().fg(Color::DarkGray),
-            ));
-        }
-
-        let mut lines = vec![Line::from(first_line)];
-
-        if let Some(reply) = &msg.reply {
-            lines.insert(
-                0,
-                Line::from(vec![
-                    Span::styled("↩ ", Style::default().fg(Color::Gray)),
-                    Span::styled(reply.from.clone(), Style::default().fg(Color::Gray)),
-                    Span::raw(": "),
-                    Span::styled(
-                        truncate_str(&reply.text, 60),
-                        Style::default().fg(Color::Gray),
-                    ),
-                ]),
-            );
-        }
-
-        if msg.fwd_count > 0 {
-            lines.push(Line::from(vec![Span::styled(
-                format!("↪ forwarded {}", msg.fwd_count),
-                Style::default().fg(Color::Gray),
-            )]));
-        }
-
-        for att in &msg.attachments {
-            let label = match &att.kind {
-                AttachmentKind::Photo => "[photo]".to_string(),
-                AttachmentKind::Doc => "[file]".to_string(),
-                AttachmentKind::Link => "[link]".to_string(),
-                AttachmentKind::Audio => "[audio]".to_string(),
-                AttachmentKind::Sticker => "[sticker]".to_string(),
-                AttachmentKind::Other(k) => format!("[{}]", k),
-            };
-            let mut detail = format!("{} {}", label, att.title);
-            if let Some(sub) = &att.subtitle {
-                detail.push_str(&format!(" — {}", sub));
-            }
-            if let Some(size) = att.size {
-                let kb = size as f64 / 1024.0;
-                detail.push_str(&format!(" ({:.1} KB)", kb));
-            }
-            if let Some(url) = &att.url {
-                detail.push(' ');
-                detail.push_str(url);
-            }
-            lines.push(Line::from(Span::styled(
-                detail,
-                Style::default().fg(Color::Gray),
-            )));
-        }
-
-        lines
-    };
+    let name_loading = |msg: &ChatMessage| app.profile_warmup.is_pending(msg.from_id);
 
     // Reserve top area for pinned message if available
     let pinned_message = app.messages.iter().find(|m| m.is_pinned);
@@ -405,7 +548,8 @@ fn render_messages(app: &App, frame: &mut Frame, area: Rect) {
     };
 
     if let (Some(msg), Some(p_area)) = (pinned_message, pinned_area) {
-        let height = render_lines(msg).len() as u16 + 2;
+        let lines = message_lines(msg, name_loading(msg), &app.config.clock());
+        let height = lines.len() as u16 + 2;
         let adj_height = height.min(p_area.height);
         let pin_block = Block::default()
             .title(" Pinned ")
@@ -414,7 +558,7 @@ fn render_messages(app: &App, frame: &mut Frame, area: Rect) {
         let inner_height = adj_height.saturating_sub(2).max(1);
         let inner_area = Rect::new(p_area.x, p_area.y, p_area.width, adj_height);
         frame.render_widget(pin_block, inner_area);
-        let content = Paragraph::new(render_lines(msg))
+        let content = Paragraph::new(lines)
             .style(Style::default().fg(Color::White))
             .wrap(Wrap { trim: false });
         frame.render_widget(
@@ -428,11 +572,8 @@ fn render_messages(app: &App, frame: &mut Frame, area: Rect) {
         );
     }
 
-    let messages: Vec<ListItem> = app
-        .messages
-        .iter()
-        .map(|msg| ListItem::new(render_lines(msg)))
-        .collect();
+    let (messages, selected_row, indices) = message_items(app, list_area);
+    let heights: Vec<u16> = messages.iter().map(|item| item.height() as u16).collect();
 
     let border_style = if is_focused {
         Style::default().fg(Color::Cyan)
@@ -440,11 +581,12 @@ fn render_messages(app: &App, frame: &mut Frame, area: Rect) {
         Style::default().fg(Color::DarkGray)
     };
 
+    // "Weekend trip — 23 members", "Ann Lee — last seen 5 min ago"
     let chat_title = app
         .current_peer_id
         .and_then(|peer_id| app.chats.iter().find(|c| c.id == peer_id))
-        .map(|c| c.title.as_str())
-        .unwrap_or("Messages");
+        .map(|c| c.header(now_timestamp()))
+        .unwrap_or_else(|| "Messages".to_string());
 
     let title = if app.is_loading && app.current_peer_id.is_some() {
         format!(" {} (loading...) ", chat_title)
@@ -452,23 +594,305 @@ fn render_messages(app: &App, frame: &mut Frame, area: Rect) {
         format!(" {} ", chat_title)
     };
 
+    let mut block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(border_style);
+    let mut layout = app.layout.borrow_mut();
+    layout.messages = list_area;
+    layout.unseen_hint = None;
+    if app.unseen_below > 0 {
+        let hint = match app.unseen_below {
+            1 => " ↓ 1 new message ".to_string(),
+            n => format!(" ↓ {} new messages ", n),
+        };
+        // Right-aligned on the bottom border, inside the corner
+        let width = (hint.width() as u16).min(list_area.width.saturating_sub(2));
+        layout.unseen_hint = Some(Rect::new(
+            list_area.right().saturating_sub(width + 1),
+            list_area.bottom().saturating_sub(1),
+            width,
+            1,
+        ));
+        block = block.title_bottom(
+            Line::from(Span::styled(hint, Style::default().fg(Color::Yellow))).right_aligned(),
+        );
+    }
+
     let list = List::new(messages)
-        .block(
-            Block::default()
-                .title(title)
-                .borders(Borders::ALL)
-                .border_style(border_style),
-        )
+        .block(block)
         .highlight_style(Style::default().bg(Color::DarkGray));
 
     let mut state = ListState::default();
-    state.select(Some(app.messages_scroll));
+    state.select(Some(selected_row));
 
     frame.render_stateful_widget(list, list_area, &mut state);
+
+    // Rows the list ended up showing, for mouse clicks
+    layout.message_rows.clear();
+    let bottom = list_area.bottom().saturating_sub(1);
+    let mut y = list_area.y + 1;
+    for (height, idx) in heights.iter().zip(&indices).skip(state.offset()) {
+        if y >= bottom {
+            break;
+        }
+        if let Some(idx) = idx {
+            layout
+                .message_rows
+                .push((y, (*height).min(bottom - y), *idx));
+        }
+        y += height;
+    }
+}
+
+/// Lines of one message in the message list. `name_loading` shows the
+/// placeholder while the sender's profile is still being fetched.
+fn message_lines(msg: &ChatMessage, name_loading: bool, clock: &Clock) -> Vec<Line<'static>> {
+    // Joins, leaves, renames: a dimmed line of its own, without a sender
+    if msg.is_service() {
+        return vec![
+            Line::from(Span::styled(
+                msg.text.clone(),
+                Style::default()
+                    .fg(Color::DarkGray)
+                    .add_modifier(Modifier::ITALIC),
+            ))
+            .centered(),
+        ];
+    }
+
+    let name_style = if msg.is_outgoing {
+        Style::default().fg(Color::Green)
+    } else {
+        Style::default().fg(Color::Cyan)
+    };
+
+    let read_indicator = match msg.delivery {
+        DeliveryStatus::Pending => "...",
+        DeliveryStatus::Failed => "!",
+        DeliveryStatus::Sent => {
+            if msg.is_outgoing {
+                if msg.is_read { "✓✓" } else { "✓" }
+            } else {
+                ""
+            }
+        }
+    };
+
+    let time = clock.message_time(msg.timestamp, now_timestamp());
+
+    let mut first_line = vec![
+        Span::styled(time, Style::default().fg(Color::DarkGray)),
+        Span::raw(" "),
+        if msg.is_pinned {
+            Span::styled("📌 ", Style::default().fg(Color::Yellow))
+        } else {
+            Span::raw("")
+        },
+        Span::styled(
+            if name_loading {
+                LOADING_NAME.to_string()
+            } else {
+                msg.from_name.clone()
+            },
+            name_style,
+        ),
+        Span::raw(": "),
+        Span::raw(msg.display_text().to_string()),
+    ];
+
+    // Add edited indicator
+    if msg.is_edited {
+        first_line.push(Span::styled(" (e)", Style::default().fg(Color::Yellow)));
+    }
+
+    // Add delivery status indicator
+    if !read_indicator.is_empty() {
+        first_line.push(Span::styled(
+            format!(" {}", read_indicator),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    if let Some(upload) = msg.upload
+        && msg.delivery == DeliveryStatus::Pending
+    {
+        first_line.push(Span::styled(
+            format!(" {}%", upload.percent()),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+
+    let mut lines = vec![Line::from(first_line)];
+
+    if let Some(reply) = &msg.reply {
+        lines.insert(
+            0,
+            Line::from(vec![
+                Span::styled("↩ ", Style::default().fg(Color::Gray)),
+                Span::styled(reply.from.clone(), Style::default().fg(Color::Gray)),
+                Span::raw(": "),
+                Span::styled(
+                    truncate_to_width(&reply.text, 60),
+                    Style::default().fg(Color::Gray),
+                ),
+            ]),
+        );
+    }
+
+    if msg.fwd_count > 0 {
+        lines.push(Line::from(vec![Span::styled(
+            format!("↪ forwarded {}", msg.fwd_count),
+            Style::default().fg(Color::Gray),
+        )]));
+    }
+
+    for att in &msg.attachments {
+        if matches!(att.kind, AttachmentKind::Link) {
+            lines.extend(link_preview_lines(att, &msg.text));
+            continue;
+        }
+        let label = match &att.kind {
+            AttachmentKind::Photo => "[photo]".to_string(),
+            AttachmentKind::Doc => "[file]".to_string(),
+            AttachmentKind::Link => "[link]".to_string(),
+            AttachmentKind::Audio => "[audio]".to_string(),
+            AttachmentKind::Sticker => "[sticker]".to_string(),
+            AttachmentKind::Other(k) => format!("[{}]", k),
+        };
+        let mut detail = format!("{} {}", label, att.title);
+        if let Some(sub) = &att.subtitle {
+            detail.push_str(&format!(" — {}", sub));
+        }
+        if let Some(size) = att.size {
+            let kb = size as f64 / 1024.0;
+            detail.push_str(&format!(" ({:.1} KB)", kb));
+        }
+        if let Some(url) = &att.url {
+            detail.push(' ');
+            detail.push_str(url);
+        }
+        lines.push(Line::from(Span::styled(
+            detail,
+            Style::default().fg(Color::Gray),
+        )));
+    }
+
+    if !msg.reactions.is_empty() {
+        // Own reaction highlighted, like the ✓✓ of own messages
+        let spans: Vec<Span> = msg
+            .reactions
+            .iter()
+            .enumerate()
+            .flat_map(|(i, r)| {
+                let style = if r.mine {
+                    Style::default().fg(Color::Yellow)
+                } else {
+                    Style::default().fg(Color::Gray)
+                };
+                [
+                    Span::raw(if i == 0 { "" } else { "  " }),
+                    Span::styled(format_reactions(std::slice::from_ref(r)), style),
+                ]
+            })
+            .collect();
+        lines.push(Line::from(spans));
+    }
+
+    lines
+}
+
+/// List items for the messages around the selection, with the index of
+/// the first. Only these reach the list, with their lines from the cache,
+/// so a frame costs the visible rows rather than the whole history.
+/// Items of the visible part of the message list, the item to select and
+/// the message of each item (`None` for day separators).
+fn message_items(app: &App, area: Rect) -> (Vec<ListItem<'static>>, usize, Vec<Option<usize>>) {
+    let name_loading = |msg: &ChatMessage| app.profile_warmup.is_pending(msg.from_id);
+    let highlighted = app
+        .highlighted_message
+        .filter(|(_, until)| *until > std::time::Instant::now())
+        .map(|(id, _)| id);
+    let selection = app.visual_selection();
+    let clock = app.config.clock();
+    let build = |msg: &ChatMessage, loading: bool| message_lines(msg, loading, &clock);
+
+    let mut cache = app.message_lines.borrow_mut();
+    cache.reset_if_changed(app.current_peer_id, area.width);
+    let selected = app
+        .messages_scroll
+        .min(app.messages.len().saturating_sub(1));
+    let window = visible_window(
+        app.messages.len(),
+        selected,
+        area.height.saturating_sub(2) as usize,
+        |i| {
+            let msg = &app.messages[i];
+            let separator = usize::from(starts_day(&app.messages, i, &clock));
+            cache.lines(msg, name_loading(msg), build).len() + separator
+        },
+    );
+    let rows = day_rows(&app.messages, window, &clock);
+    let indices = rows
+        .iter()
+        .map(|row| match row {
+            Row::Day(_) => None,
+            Row::Message(i) => Some(*i),
+        })
+        .collect();
+    // Separators are list items too, but j/k only moves between messages
+    let selected_row = rows
+        .iter()
+        .position(|row| *row == Row::Message(selected))
+        .unwrap_or(0);
+    let messages: Vec<ListItem> = rows
+        .into_iter()
+        .map(|row| match row {
+            Row::Day(label) => ListItem::new(
+                Line::from(Span::styled(
+                    format!("— {} —", label),
+                    Style::default().fg(Color::DarkGray),
+                ))
+                .centered(),
+            ),
+            Row::Message(i) => {
+                let msg = &app.messages[i];
+                let item = ListItem::new(cache.lines(msg, name_loading(msg), build).to_vec());
+                if highlighted == Some(msg.id) {
+                    item.style(Style::default().add_modifier(Modifier::REVERSED))
+                } else if selection.as_ref().is_some_and(|range| range.contains(&i)) {
+                    item.style(Style::default().bg(Color::Blue))
+                } else {
+                    item
+                }
+            }
+        })
+        .collect();
+    cache.retain(&app.messages);
+    (messages, selected_row, indices)
 }
 
 /// Render input field
 fn render_input(app: &App, frame: &mut Frame, area: Rect) {
+    app.layout.borrow_mut().input = area;
+
+    // Read-only chat: say why instead of offering an input
+    if let Some(denied) = app.write_denied() {
+        let notice = Paragraph::new(denied)
+            .style(
+                Style::default()
+                    .fg(Color::DarkGray)
+                    .add_modifier(Modifier::ITALIC),
+            )
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(Color::DarkGray)),
+            )
+            .wrap(Wrap { trim: false });
+        frame.render_widget(notice, area);
+        return;
+    }
+
     let is_focused = app.focus == Focus::Input;
 
     let border_style = if is_focused {
@@ -477,10 +901,21 @@ fn render_input(app: &App, frame: &mut Frame, area: Rect) {
         Style::default().fg(Color::DarkGray)
     };
 
+    // Counter once the text nears VK's limit, red past it
+    let mut title = vec![Span::raw(" Message (Enter to send) ")];
+    if let Some(counter) = length_counter(&app.input) {
+        let style = if too_long(&app.input) {
+            Style::default().fg(Color::Red)
+        } else {
+            Style::default().fg(Color::Yellow)
+        };
+        title.push(Span::styled(format!("{} ", counter), style));
+    }
+
     let input = Paragraph::new(app.input.as_str())
         .block(
             Block::default()
-                .title(" Message (Enter to send) ")
+                .title(Line::from(title))
                 .borders(Borders::ALL)
                 .border_style(border_style),
         )
@@ -490,22 +925,35 @@ fn render_input(app: &App, frame: &mut Frame, area: Rect) {
 
     // Show cursor when focused - calculate visual width for UTF-8
     if is_focused {
-        let cursor_x = visual_width(&app.input, app.input_cursor);
-        frame.set_cursor_position((area.x + cursor_x as u16 + 1, area.y + 1));
+        let cursor_x = cursor_display_col(&app.input, app.input_cursor);
+        frame.set_cursor_position((area.x + cursor_x + 1, area.y + 1));
     }
 }
 
-/// Calculate visual width of string up to char_pos
-fn visual_width(s: &str, char_pos: usize) -> usize {
-    use unicode_width::UnicodeWidthChar;
-    s.chars()
-        .take(char_pos)
-        .map(|c| c.width().unwrap_or(0))
-        .sum()
-}
-
 /// Render status bar
 fn render_status(app: &App, frame: &mut Frame, area: Rect) {
+    // Ctrl+R in Command mode: the search and its match, like a shell
+    if let Some(search) = &app.history_search {
+        let hit = search
+            .hit
+            .and_then(|hit| app.command_history.get(hit))
+            .unwrap_or("");
+        let label = if search.hit.is_some() || search.query.is_empty() {
+            "reverse-i-search"
+        } else {
+            "failing reverse-i-search"
+        };
+        let prompt = format!("({})`{}': ", label, search.query);
+        let cursor_x = prompt.width() as u16 - 3;
+        let line = Line::from(vec![
+            Span::styled(prompt, Style::default().fg(Color::Yellow)),
+            Span::raw(hit.to_string()),
+        ]);
+        frame.render_widget(Paragraph::new(line), area);
+        frame.set_cursor_position((area.x + cursor_x, area.y));
+        return;
+    }
+
     // In Command mode, show command prompt
     if app.mode == Mode::Command {
         let cmd_text = format!(":{}", app.command_input);
@@ -513,8 +961,8 @@ fn render_status(app: &App, frame: &mut Frame, area: Rect) {
         frame.render_widget(cmd_prompt, area);
 
         // Show cursor at command position
-        let cursor_x = visual_width(&app.command_input, app.command_cursor);
-        frame.set_cursor_position((area.x + cursor_x as u16 + 1, area.y)); // +1 for ':'
+        let cursor_x = cursor_display_col(&app.command_input, app.command_cursor);
+        frame.set_cursor_position((area.x + cursor_x + 1, area.y)); // +1 for ':'
         return;
     }
 
@@ -528,7 +976,17 @@ fn render_status(app: &App, frame: &mut Frame, area: Rect) {
         (Mode::Normal, Focus::Input) => "i insert mode | Esc back",
         (Mode::Command, _) => "Enter execute | Esc cancel",
     };
-    let status_text = app.status.as_deref().unwrap_or(default_help);
+    let visual_help;
+    let status_text = match app.visual_selection() {
+        Some(range) => {
+            visual_help = format!(
+                "-- VISUAL -- {} selected | j/k extend | f forward | d delete | y copy | Esc cancel",
+                range.count()
+            );
+            visual_help.as_str()
+        }
+        None => app.status.as_deref().unwrap_or(default_help),
+    };
 
     let style = if app.status.as_ref().is_some_and(|s| s.contains("Error")) {
         Style::default().fg(Color::Red)
@@ -538,38 +996,61 @@ fn render_status(app: &App, frame: &mut Frame, area: Rect) {
 
     let help = Paragraph::new(status_text).style(style);
 
-    frame.render_widget(help, area);
-}
-
-/// Truncate string to max length with ellipsis
-fn truncate_str(s: &str, max_len: usize) -> String {
-    if s.chars().count() <= max_len {
-        s.to_string()
-    } else {
-        format!(
-            "{}...",
-            s.chars()
-                .take(max_len.saturating_sub(3))
-                .collect::<String>()
-        )
-    }
+    // Connection state on the right, always visible
+    let label = app.connection.label();
+    let color = match app.connection {
+        ConnectionState::Connected => Color::Green,
+        ConnectionState::Reconnecting { .. } | ConnectionState::Polling => Color::Yellow,
+        ConnectionState::Offline => Color::Red,
+    };
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(UnicodeWidthStr::width(label.as_str()) as u16 + 1),
+        ])
+        .split(area);
+    frame.render_widget(help, chunks[0]);
+    frame.render_widget(
+        Paragraph::new(label)
+            .style(Style::default().fg(color))
+            .alignment(Alignment::Right),
+        chunks[1],
+    );
 }
 
-/// Format unix timestamp to HH:MM
-fn format_timestamp(ts: i64) -> String {
-    use time::macros::format_description;
-    use time::{Duration, OffsetDateTime};
-
-    let now = OffsetDateTime::now_utc();
-    let dt = OffsetDateTime::from_unix_timestamp(ts).unwrap_or(OffsetDateTime::UNIX_EPOCH);
+/// Link attachment as a preview: title and site in the accent color, the
+/// description dimmed under it. The URL is left out when the message text
+/// already shows it.
+fn link_preview_lines(att: &AttachmentInfo, text: &str) -> Vec<Line<'static>> {
+    let mut title = format!("[link] {}", att.title);
+    if let Some(site) = &att.subtitle {
+        title.push_str(&format!(" — {}", site));
+    }
+    if let Some(url) = &att.url
+        && !att.is_link_in(text)
+    {
+        title.push(' ');
+        title.push_str(url);
+    }
 
-    if (now - dt) < Duration::days(1) {
-        dt.format(&format_description!("[hour]:[minute]"))
-            .unwrap_or_else(|_| "--:--".into())
-    } else {
-        dt.format(&format_description!("[day].[month].[year]"))
-            .unwrap_or_else(|_| "--.--.----".into())
+    let mut lines = vec![Line::from(Span::styled(
+        title,
+        Style::default().fg(Color::Cyan),
+    ))];
+    if let Some(description) = &att.description {
+        let description = description.split_whitespace().collect::<Vec<_>>().join(" ");
+        lines.push(Line::from(Span::styled(
+            format!("  {}", truncate_to_width(&description, 120)),
+            Style::default().fg(Color::DarkGray),
+        )));
     }
+    lines
+}
+
+/// Current unix time, for showing when messages were sent
+fn now_timestamp() -> i64 {
+    time::OffsetDateTime::now_utc().unix_timestamp()
 }
 
 /// Create a centered rectangle
@@ -579,166 +1060,1065 @@ fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
     Rect::new(x, y, width, height)
 }
 
-fn render_forward_popup(app: &App, frame: &mut Frame) {
-    let Some(fwd) = &app.forward else {
-        return;
-    };
+fn render_forward_popup(app: &App, frame: &mut Frame) {
+    let Some(fwd) = &app.forward else {
+        return;
+    };
+
+    let area = frame.area();
+    let width = (area.width as f32 * 0.7).clamp(40.0, 100.0) as u16;
+    let height = (area.height as f32 * 0.7).clamp(12.0, 30.0) as u16;
+    let popup_area = centered_rect(width, height, area);
+
+    frame.render_widget(Clear, popup_area);
+
+    let action = match fwd.source_message_ids.len() {
+        1 => "Forward".to_string(),
+        count => format!("Forward {} messages", count),
+    };
+    match &fwd.stage {
+        ForwardStage::SelectTarget => {
+            let block = Block::default()
+                .title(format!(" {} to... ", action))
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow));
+            let inner = block.inner(popup_area);
+            frame.render_widget(block, popup_area);
+
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([
+                    Constraint::Length(1),
+                    Constraint::Length(2),
+                    Constraint::Min(3),
+                    Constraint::Length(1),
+                ])
+                .split(inner);
+
+            let query = Paragraph::new(format!("Search: {}", fwd.query))
+                .style(Style::default().fg(Color::White));
+            frame.render_widget(query, chunks[0]);
+
+            let hint =
+                Paragraph::new("Type to search, j/k to move, Enter to select, Esc to cancel")
+                    .style(Style::default().fg(Color::DarkGray));
+            frame.render_widget(hint, chunks[1]);
+
+            let items: Vec<ListItem> = fwd
+                .filtered
+                .iter()
+                .map(|chat| {
+                    let unread = if chat.unread_count > 0 {
+                        format!(" ({})", chat.unread_count)
+                    } else {
+                        String::new()
+                    };
+                    let title = if app.profile_warmup.is_pending(chat.id) {
+                        LOADING_NAME
+                    } else {
+                        chat.title.as_str()
+                    };
+                    ListItem::new(Line::from(vec![
+                        Span::styled(title, Style::default().add_modifier(Modifier::BOLD)),
+                        Span::raw(unread),
+                        Span::styled(
+                            format!("  [{}]", chat.id),
+                            Style::default().fg(Color::DarkGray),
+                        ),
+                    ]))
+                })
+                .collect();
+
+            let list = List::new(items)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .title(" Conversations "),
+                )
+                .highlight_style(
+                    Style::default()
+                        .bg(Color::Blue)
+                        .fg(Color::White)
+                        .add_modifier(Modifier::BOLD),
+                )
+                .highlight_symbol("▶ ");
+
+            let mut state = ListState::default();
+            if !fwd.filtered.is_empty() {
+                state.select(Some(fwd.selected));
+            }
+
+            frame.render_stateful_widget(list, chunks[2], &mut state);
+        }
+        ForwardStage::EnterComment { title, .. } => {
+            let block = Block::default()
+                .title(format!(" {} to {} ", action, title))
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow));
+            let inner = block.inner(popup_area);
+            frame.render_widget(block, popup_area);
+
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([
+                    Constraint::Length(2),
+                    Constraint::Min(2),
+                    Constraint::Length(1),
+                ])
+                .split(inner);
+
+            let info = Paragraph::new("Enter comment (optional), Enter to send, Esc to cancel")
+                .style(Style::default().fg(Color::DarkGray));
+            frame.render_widget(info, chunks[0]);
+
+            let input_block = Block::default().borders(Borders::ALL);
+            let input = Paragraph::new(fwd.comment.as_str())
+                .block(input_block)
+                .wrap(Wrap { trim: false });
+            frame.render_widget(input, chunks[1]);
+
+            let cursor_x = cursor_display_col(&fwd.comment, fwd.comment.chars().count());
+            frame.set_cursor_position((chunks[1].x + cursor_x + 1, chunks[1].y + 1));
+        }
+    }
+}
+
+fn render_forward_view_popup(app: &App, frame: &mut Frame) {
+    let Some(view) = &app.forward_view else {
+        return;
+    };
+
+    let area = frame.area();
+    let width = (area.width as f32 * 0.7).clamp(50.0, 110.0) as u16;
+    let height = (area.height as f32 * 0.7).clamp(12.0, 30.0) as u16;
+    let popup_area = centered_rect(width, height, area);
+
+    frame.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(" Forwarded messages (Esc to close) ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Yellow));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    if view.items.is_empty() {
+        let empty = Paragraph::new("No forwarded messages")
+            .style(Style::default().fg(Color::DarkGray))
+            .alignment(Alignment::Center);
+        frame.render_widget(empty, inner);
+        return;
+    }
+
+    let flattened = super::update::flatten_forwards(&view.items, 0);
+    let items: Vec<ListItem> = flattened
+        .iter()
+        .map(|(indent, text)| {
+            let pad = "  ".repeat(*indent);
+            ListItem::new(Line::from(vec![Span::raw(format!("{}{}", pad, text))]))
+        })
+        .collect();
+
+    let list = List::new(items).highlight_style(
+        Style::default()
+            .bg(Color::Blue)
+            .fg(Color::White)
+            .add_modifier(Modifier::BOLD),
+    );
+
+    let mut state = ListState::default();
+    state.select(Some(view.selected));
+    frame.render_stateful_widget(list, inner, &mut state);
+}
+
+/// Render prompt for a message that was edited elsewhere during local editing
+fn render_edit_conflict_popup(app: &App, frame: &mut Frame) {
+    let Some(conflict) = &app.edit_conflict else {
+        return;
+    };
+
+    let area = frame.area();
+    let width = (area.width as f32 * 0.6).clamp(40.0, 90.0) as u16;
+    let height = (area.height as f32 * 0.5).clamp(10.0, 20.0) as u16;
+    let popup_area = centered_rect(width, height, area);
+
+    frame.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(" Message changed elsewhere ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Red));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    let label = Style::default().fg(Color::DarkGray);
+    let lines = vec![
+        Line::from(Span::styled("Theirs:", label)),
+        Line::from(conflict.server_text.as_str()),
+        Line::from(""),
+        Line::from(Span::styled("Yours:", label)),
+        Line::from(conflict.local_text.as_str()),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("o", Style::default().fg(Color::Yellow)),
+            Span::raw(" overwrite  "),
+            Span::styled("t", Style::default().fg(Color::Yellow)),
+            Span::raw(" take theirs  "),
+            Span::styled("m", Style::default().fg(Color::Yellow)),
+            Span::raw(" merge manually"),
+        ]),
+    ];
+
+    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, inner);
+}
+
+/// Render the captcha prompt: the image link and the typed answer
+fn render_captcha_popup(app: &App, frame: &mut Frame) {
+    let Some(captcha) = &app.captcha else {
+        return;
+    };
+
+    let area = frame.area();
+    let width = (area.width as f32 * 0.6).clamp(40.0, 90.0) as u16;
+    let popup_area = centered_rect(width, 9, area);
+
+    frame.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(" Captcha ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Yellow));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    let key = Style::default().fg(Color::Yellow);
+    let lines = vec![
+        Line::from("VK wants the text from this image before sending:"),
+        Line::from(Span::styled(
+            captcha.img_url.as_str(),
+            Style::default().fg(Color::Cyan),
+        )),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("> ", key),
+            Span::raw(captcha.key.as_str()),
+            Span::styled("█", Style::default().fg(Color::DarkGray)),
+        ]),
+        Line::from(""),
+        Line::from(vec![
+            Span::styled("o", key),
+            Span::raw("/"),
+            Span::styled("Ctrl+O", key),
+            Span::raw(" open image  "),
+            Span::styled("Enter", key),
+            Span::raw(" send  "),
+            Span::styled("Esc", key),
+            Span::raw(" cancel"),
+        ]),
+    ];
+
+    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, inner);
+}
+
+/// Render y/n prompt before sending input that was typed in another chat
+fn render_cross_chat_send_popup(app: &App, frame: &mut Frame) {
+    let Some(pending) = &app.cross_chat_send else {
+        return;
+    };
+
+    let area = frame.area();
+    let width = (area.width as f32 * 0.5).clamp(30.0, 70.0) as u16;
+    let popup_area = centered_rect(width, 5, area);
+
+    frame.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(" Typed in another chat ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Yellow));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    let lines = vec![
+        Line::from(format!("Send to '{}'?", pending.title)),
+        Line::from(vec![
+            Span::styled("y", Style::default().fg(Color::Yellow)),
+            Span::raw(" send  "),
+            Span::styled("n", Style::default().fg(Color::Yellow)),
+            Span::raw(" keep editing"),
+        ]),
+    ];
+
+    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, inner);
+}
+
+fn render_whois_popup(app: &App, frame: &mut Frame) {
+    let Some(whois) = &app.whois else {
+        return;
+    };
+
+    let area = frame.area();
+    let width = (area.width as f32 * 0.5).clamp(30.0, 70.0) as u16;
+    let popup_area = centered_rect(width, 10, area);
+
+    frame.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(" Profile (Esc to close) ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    let Some(profile) = &whois.profile else {
+        let loading = Paragraph::new(format!("Loading {}...", app.get_user_name(whois.user_id)))
+            .style(Style::default().fg(Color::DarkGray));
+        frame.render_widget(loading, inner);
+        return;
+    };
+
+    let field = |label: &str, value: String| {
+        Line::from(vec![
+            Span::styled(
+                format!("{:<10}", label),
+                Style::default().fg(Color::DarkGray),
+            ),
+            Span::raw(value),
+        ])
+    };
+
+    let mut lines = vec![Line::from(vec![
+        Span::styled(
+            profile.name.clone(),
+            Style::default().add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(
+            profile
+                .screen_name
+                .as_ref()
+                .map(|s| format!("  @{}", s))
+                .unwrap_or_default(),
+            Style::default().fg(Color::DarkGray),
+        ),
+    ])];
+    let now = time::OffsetDateTime::now_utc().unix_timestamp();
+    if let Some(presence) = profile.presence(now) {
+        let color = if profile.is_online {
+            Color::Green
+        } else {
+            Color::Gray
+        };
+        lines.push(Line::from(Span::styled(
+            presence,
+            Style::default().fg(color),
+        )));
+    }
+    lines.push(Line::from(""));
+    if let Some(city) = &profile.city {
+        lines.push(field("City", city.clone()));
+    }
+    if let Some(bdate) = &profile.bdate {
+        lines.push(field("Birthday", bdate.clone()));
+    }
+    if let Some(count) = profile.members_count {
+        lines.push(field("Members", count.to_string()));
+    }
+    if let Some(status) = &profile.status {
+        lines.push(field("Status", status.clone()));
+    }
+
+    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, inner);
+}
+
+fn render_members_popup(app: &App, frame: &mut Frame) {
+    let Some(view) = &app.members else {
+        return;
+    };
+
+    let area = frame.area();
+    let width = (area.width as f32 * 0.5).clamp(40.0, 70.0) as u16;
+    let height = (area.height as f32 * 0.7).clamp(10.0, 30.0) as u16;
+    let popup_area = centered_rect(width, height, area);
+
+    frame.render_widget(Clear, popup_area);
+
+    let count = match view.total {
+        Some(total) => format!("{}/{}", view.members.len(), total),
+        None => "...".to_string(),
+    };
+    let block = Block::default()
+        .title(format!(" Members {} (Esc to close) ", count))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    if view.members.is_empty() {
+        let text = if view.is_loading {
+            "Loading..."
+        } else {
+            "No members"
+        };
+        let empty = Paragraph::new(text)
+            .style(Style::default().fg(Color::DarkGray))
+            .alignment(Alignment::Center);
+        frame.render_widget(empty, inner);
+        return;
+    }
+
+    let mut items: Vec<ListItem> = view
+        .members
+        .iter()
+        .map(|member| {
+            let role = if member.is_owner {
+                " ★ owner"
+            } else if member.is_admin {
+                " ★ admin"
+            } else {
+                ""
+            };
+            ListItem::new(Line::from(vec![
+                Span::raw(member.name.clone()),
+                Span::styled(role, Style::default().fg(Color::Yellow)),
+            ]))
+        })
+        .collect();
+    if view.is_loading {
+        items.push(ListItem::new(Span::styled(
+            "Loading more...",
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    let list = List::new(items).highlight_style(
+        Style::default()
+            .bg(Color::Blue)
+            .fg(Color::White)
+            .add_modifier(Modifier::BOLD),
+    );
+
+    let mut state = ListState::default();
+    state.select(Some(view.selected));
+    frame.render_stateful_widget(list, inner, &mut state);
+}
+
+fn render_chat_info_popup(app: &App, frame: &mut Frame) {
+    let Some(peer_id) = app.chat_info else {
+        return;
+    };
+    let title = app
+        .chats
+        .iter()
+        .find(|c| c.id == peer_id)
+        .map(|c| c.title.clone())
+        .unwrap_or_else(|| peer_id.to_string());
+
+    let area = frame.area();
+    let width = (area.width as f32 * 0.4).clamp(30.0, 50.0) as u16;
+    let height = (MediaKind::ALL.len() as u16 + 4).min(area.height);
+    let popup_area = centered_rect(width, height, area);
+
+    frame.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(format!(" {} (r refresh, Esc close) ", title))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    let Some(info) = app.chat_infos.get(&peer_id) else {
+        let loading = Paragraph::new("Counting media...")
+            .style(Style::default().fg(Color::DarkGray))
+            .alignment(Alignment::Center);
+        frame.render_widget(loading, inner);
+        return;
+    };
+
+    let mut lines: Vec<Line> = info
+        .counts
+        .iter()
+        .map(|(kind, count)| {
+            Line::from(vec![
+                Span::styled(
+                    format!("{:<8}", kind.label()),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::raw(count.to_string()),
+            ])
+        })
+        .collect();
+    if info.counts.iter().any(|(_, count)| count.more) {
+        lines.push(Line::from(Span::styled(
+            "+ counting stopped early",
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    frame.render_widget(Paragraph::new(lines), inner);
+}
+
+fn render_readers_popup(app: &App, frame: &mut Frame) {
+    let Some(view) = &app.read_by else {
+        return;
+    };
+
+    // Summary, blank line, then one reader per line
+    const MAX_LISTED: usize = 10;
+    let listed = view
+        .readers
+        .as_ref()
+        .map_or(0, |r| r.readers.len().min(MAX_LISTED));
+
+    let area = frame.area();
+    let width = (area.width as f32 * 0.4).clamp(30.0, 50.0) as u16;
+    let height = (listed as u16 + 5).min(area.height);
+    let popup_area = centered_rect(width, height, area);
+
+    frame.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(" Read by (Esc to close) ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    let Some(readers) = &view.readers else {
+        let loading = Paragraph::new("Loading readers...")
+            .style(Style::default().fg(Color::DarkGray))
+            .alignment(Alignment::Center);
+        frame.render_widget(loading, inner);
+        return;
+    };
+
+    let mut lines = vec![
+        Line::from(Span::styled(
+            readers.summary(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+    ];
+    lines.extend(
+        readers
+            .readers
+            .iter()
+            .take(MAX_LISTED)
+            .map(|(_, name)| Line::from(format!("✓ {}", name))),
+    );
+    let hidden = (readers.count as usize).max(readers.readers.len()) - listed;
+    if hidden > 0 {
+        lines.push(Line::from(Span::styled(
+            format!("+ {} more", hidden),
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    frame.render_widget(Paragraph::new(lines).wrap(Wrap { trim: true }), inner);
+}
+
+fn render_new_chat_popup(app: &App, frame: &mut Frame) {
+    let Some(view) = &app.new_chat else {
+        return;
+    };
+
+    let area = frame.area();
+    let width = (area.width as f32 * 0.5).clamp(40.0, 70.0) as u16;
+    let height = (area.height as f32 * 0.7).clamp(10.0, 30.0) as u16;
+    let popup_area = centered_rect(width, height, area);
+
+    frame.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(format!(
+            " New chat '{}': {} picked (Space pick, Enter create) ",
+            view.title,
+            view.picked.len()
+        ))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    if view.people.is_empty() {
+        let text = if view.is_loading {
+            "Loading..."
+        } else {
+            "No friends or dialogs"
+        };
+        let empty = Paragraph::new(text)
+            .style(Style::default().fg(Color::DarkGray))
+            .alignment(Alignment::Center);
+        frame.render_widget(empty, inner);
+        return;
+    }
+
+    let mut items: Vec<ListItem> = view
+        .people
+        .iter()
+        .map(|(id, name)| {
+            let (mark, style) = if view.picked.contains(id) {
+                ("[x] ", Style::default().fg(Color::Green))
+            } else {
+                ("[ ] ", Style::default())
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(mark, style),
+                Span::raw(name.clone()),
+            ]))
+        })
+        .collect();
+    if view.is_loading {
+        items.push(ListItem::new(Span::styled(
+            "Loading friends...",
+            Style::default().fg(Color::DarkGray),
+        )));
+    }
+
+    let list = List::new(items).highlight_style(
+        Style::default()
+            .bg(Color::Blue)
+            .fg(Color::White)
+            .add_modifier(Modifier::BOLD),
+    );
+
+    let mut state = ListState::default();
+    state.select(Some(view.selected));
+    frame.render_stateful_widget(list, inner, &mut state);
+}
+
+fn chat_title(app: &App, peer_id: i64) -> String {
+    app.chats
+        .iter()
+        .find(|c| c.id == peer_id)
+        .map(|c| c.title.clone())
+        .unwrap_or_else(|| peer_id.to_string())
+}
+
+/// y/n confirmation for a destructive action; `verb` labels the `y` key
+fn render_confirm_popup(frame: &mut Frame, title: &str, question: String, verb: &str) {
+    let area = frame.area();
+    let width = (area.width as f32 * 0.5).clamp(30.0, 70.0) as u16;
+    let popup_area = centered_rect(width, 6, area);
+
+    frame.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Red));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    let lines = vec![
+        Line::from(question),
+        Line::from(vec![
+            Span::styled("y", Style::default().fg(Color::Yellow)),
+            Span::raw(format!(" {}  ", verb)),
+            Span::styled("n", Style::default().fg(Color::Yellow)),
+            Span::raw(" cancel"),
+        ]),
+    ];
+
+    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, inner);
+}
+
+/// Render the `dd` prompt: delete for me, for everyone, or cancel
+fn render_delete_prompt_popup(app: &App, frame: &mut Frame) {
+    let Some(prompt) = &app.delete_prompt else {
+        return;
+    };
+
+    let area = frame.area();
+    let width = (area.width as f32 * 0.5).clamp(36.0, 70.0) as u16;
+    let popup_area = centered_rect(width, 7, area);
+
+    frame.render_widget(Clear, popup_area);
+
+    let title = match prompt.message_ids.len() {
+        1 => " Delete message ".to_string(),
+        n => format!(" Delete {} messages ", n),
+    };
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Red));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    let mut lines: Vec<Line> = DeletePrompt::CHOICES
+        .iter()
+        .map(|&choice| {
+            let label = match choice {
+                DeleteChoice::ForMe => "Delete for me",
+                DeleteChoice::ForAll => "Delete for everyone",
+                DeleteChoice::Cancel => "Cancel",
+            };
+            let style = if !prompt.is_enabled(choice) {
+                Style::default().fg(Color::DarkGray)
+            } else if choice == prompt.selected {
+                Style::default()
+                    .bg(Color::Blue)
+                    .fg(Color::White)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            let mut spans = vec![Span::styled(format!(" {} ", label), style)];
+            if !prompt.is_enabled(choice) {
+                spans.push(Span::styled(
+                    " (only your messages from the last 24 hours)",
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            Line::from(spans)
+        })
+        .collect();
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        "j/k move, Enter choose, Esc cancel",
+        Style::default().fg(Color::DarkGray),
+    )));
+
+    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, inner);
+}
+
+fn render_message_menu_popup(app: &App, frame: &mut Frame) {
+    let Some(menu) = &app.message_menu else {
+        return;
+    };
+
+    let area = frame.area();
+    let height = (menu.actions.len() as u16 + 4).min(area.height);
+    let popup_area = centered_rect(34, height, area);
+
+    frame.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(" Message ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    let mut lines: Vec<Line> = menu
+        .actions
+        .iter()
+        .enumerate()
+        .map(|(i, action)| {
+            let style = if i == menu.selected {
+                Style::default()
+                    .bg(Color::Blue)
+                    .fg(Color::White)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            Line::from(vec![
+                Span::styled(format!(" {:<22}", action.label()), style),
+                Span::styled(
+                    format!("{:>3}", action.key()),
+                    Style::default().fg(Color::DarkGray),
+                ),
+            ])
+        })
+        .collect();
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        "j/k move, Enter run, Esc close",
+        Style::default().fg(Color::DarkGray),
+    )));
+
+    frame.render_widget(Paragraph::new(lines), inner);
+}
+
+fn render_link_picker_popup(app: &App, frame: &mut Frame) {
+    let Some(picker) = &app.link_picker else {
+        return;
+    };
+
+    let area = frame.area();
+    let width = (area.width as f32 * 0.7).clamp(40.0, 100.0) as u16;
+    let height = (picker.urls.len() as u16 + 4).min(area.height);
+    let popup_area = centered_rect(width, height, area);
+
+    frame.render_widget(Clear, popup_area);
+
+    let block = Block::default()
+        .title(" Open link ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
+
+    // "1. " before each link
+    let url_width = (inner.width as usize).saturating_sub(4);
+    let mut lines: Vec<Line> = picker
+        .urls
+        .iter()
+        .enumerate()
+        .map(|(i, url)| {
+            let number = if i < 9 {
+                format!("{}.", i + 1)
+            } else {
+                "  ".to_string()
+            };
+            let style = if i == picker.selected {
+                Style::default()
+                    .bg(Color::Blue)
+                    .fg(Color::White)
+                    .add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            Line::from(vec![
+                Span::styled(number, Style::default().fg(Color::DarkGray)),
+                Span::raw(" "),
+                Span::styled(truncate_to_width(url, url_width), style),
+            ])
+        })
+        .collect();
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        "1-9 or j/k and Enter open, Esc close",
+        Style::default().fg(Color::DarkGray),
+    )));
+
+    frame.render_widget(Paragraph::new(lines), inner);
+}
+
+fn render_registers_popup(app: &App, frame: &mut Frame) {
+    let registers = app.registers.list();
 
     let area = frame.area();
-    let width = (area.width as f32 * 0.7).clamp(40.0, 100.0) as u16;
-    let height = (area.height as f32 * 0.7).clamp(12.0, 30.0) as u16;
+    let width = (area.width as f32 * 0.6).clamp(40.0, 80.0) as u16;
+    let height = (registers.len() as u16 + 2).min(area.height);
     let popup_area = centered_rect(width, height, area);
 
     frame.render_widget(Clear, popup_area);
 
-    match &fwd.stage {
-        ForwardStage::SelectTarget => {
-            let block = Block::default()
-                .title(" Forward to... ")
-                .borders(Borders::ALL)
-                .border_style(Style::default().fg(Color::Yellow));
-            let inner = block.inner(popup_area);
-            frame.render_widget(block, popup_area);
-
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Length(1),
-                    Constraint::Length(2),
-                    Constraint::Min(3),
-                    Constraint::Length(1),
-                ])
-                .split(inner);
+    let block = Block::default()
+        .title(" Registers (Esc to close) ")
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
 
-            let query = Paragraph::new(format!("Search: {}", fwd.query))
-                .style(Style::default().fg(Color::White));
-            frame.render_widget(query, chunks[0]);
+    let text_width = (inner.width as usize).saturating_sub(4);
+    let lines: Vec<Line> = registers
+        .into_iter()
+        .map(|(name, text)| {
+            let text = text.replace('\n', "⏎");
+            Line::from(vec![
+                Span::styled(format!("\"{}  ", name), Style::default().fg(Color::Yellow)),
+                Span::raw(truncate_to_width(&text, text_width)),
+            ])
+        })
+        .collect();
 
-            let hint =
-                Paragraph::new("Type to search, j/k to move, Enter to select, Esc to cancel")
-                    .style(Style::default().fg(Color::DarkGray));
-            frame.render_widget(hint, chunks[1]);
+    frame.render_widget(Paragraph::new(lines), inner);
+}
 
-            let items: Vec<ListItem> = fwd
-                .filtered
-                .iter()
-                .map(|chat| {
-                    let unread = if chat.unread_count > 0 {
-                        format!(" ({})", chat.unread_count)
-                    } else {
-                        String::new()
-                    };
-                    ListItem::new(Line::from(vec![
-                        Span::styled(&chat.title, Style::default().add_modifier(Modifier::BOLD)),
-                        Span::raw(unread),
-                        Span::styled(
-                            format!("  [{}]", chat.id),
-                            Style::default().fg(Color::DarkGray),
-                        ),
-                    ]))
-                })
-                .collect();
+fn render_log_popup(app: &App, frame: &mut Frame) {
+    let Some(view) = &app.log_view else {
+        return;
+    };
 
-            let list = List::new(items)
-                .block(
-                    Block::default()
-                        .borders(Borders::ALL)
-                        .title(" Conversations "),
-                )
-                .highlight_style(
-                    Style::default()
-                        .bg(Color::Blue)
-                        .fg(Color::White)
-                        .add_modifier(Modifier::BOLD),
-                )
-                .highlight_symbol("▶ ");
+    let area = frame.area();
+    let popup_area = centered_rect(area.width * 9 / 10, area.height * 8 / 10, area);
+    frame.render_widget(Clear, popup_area);
 
-            let mut state = ListState::default();
-            if !fwd.filtered.is_empty() {
-                state.select(Some(fwd.selected));
-            }
+    let name = view
+        .path
+        .file_name()
+        .map_or_else(String::new, |n| n.to_string_lossy().into_owned());
+    let block = Block::default()
+        .title(format!(" {} (Esc to close, j/k/g/G to scroll) ", name))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
 
-            frame.render_stateful_widget(list, chunks[2], &mut state);
-        }
-        ForwardStage::EnterComment { title, .. } => {
-            let block = Block::default()
-                .title(format!(" Forward to {} ", title))
-                .borders(Borders::ALL)
-                .border_style(Style::default().fg(Color::Yellow));
-            let inner = block.inner(popup_area);
-            frame.render_widget(block, popup_area);
+    let end = view.lines.len() - view.from_bottom.min(view.lines.len());
+    let start = end.saturating_sub(inner.height as usize);
+    let lines: Vec<Line> = view.lines[start..end]
+        .iter()
+        .map(|line| {
+            let color = if line.contains(" ERROR ") {
+                Color::Red
+            } else if line.contains(" WARN ") {
+                Color::Yellow
+            } else {
+                Color::Gray
+            };
+            Line::from(Span::styled(line.as_str(), Style::default().fg(color)))
+        })
+        .collect();
+    if lines.is_empty() {
+        frame.render_widget(Paragraph::new("Log is empty"), inner);
+    } else {
+        frame.render_widget(Paragraph::new(lines), inner);
+    }
+}
 
-            let chunks = Layout::default()
-                .direction(Direction::Vertical)
-                .constraints([
-                    Constraint::Length(2),
-                    Constraint::Min(2),
-                    Constraint::Length(1),
-                ])
-                .split(inner);
+/// Frame of the photo preview. The inside is left blank for the image,
+/// which the main loop draws with terminal graphics.
+fn render_photo_popup(app: &App, frame: &mut Frame) {
+    let Some(preview) = &app.photo_preview else {
+        return;
+    };
 
-            let info = Paragraph::new("Enter comment (optional), Enter to send, Esc to cancel")
-                .style(Style::default().fg(Color::DarkGray));
-            frame.render_widget(info, chunks[0]);
+    let area = frame.area();
+    let popup_area = centered_rect(area.width * 8 / 10, area.height * 8 / 10, area);
+    frame.render_widget(Clear, popup_area);
 
-            let input_block = Block::default().borders(Borders::ALL);
-            let input = Paragraph::new(fwd.comment.as_str())
-                .block(input_block)
-                .wrap(Wrap { trim: false });
-            frame.render_widget(input, chunks[1]);
+    let title = truncate_to_width(&preview.title, usize::from(popup_area.width / 2));
+    let block = Block::default()
+        .title(format!(" {} (Esc to close) ", title))
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan));
+    let inner = block.inner(popup_area);
+    frame.render_widget(block, popup_area);
 
-            let cursor_x = visual_width(&fwd.comment, fwd.comment.chars().count());
-            frame.set_cursor_position((chunks[1].x + cursor_x as u16 + 1, chunks[1].y + 1));
-        }
+    if preview.image.is_none() {
+        frame.render_widget(
+            Paragraph::new("Loading photo...").alignment(Alignment::Center),
+            inner,
+        );
     }
+    app.layout.borrow_mut().photo = Some(inner);
 }
 
-fn render_forward_view_popup(app: &App, frame: &mut Frame) {
-    let Some(view) = &app.forward_view else {
-        return;
-    };
+fn render_stats_popup(app: &App, frame: &mut Frame) {
+    /// Chats listed by message count
+    const TOP_CHATS: usize = 5;
 
     let area = frame.area();
-    let width = (area.width as f32 * 0.7).clamp(50.0, 110.0) as u16;
-    let height = (area.height as f32 * 0.7).clamp(12.0, 30.0) as u16;
+    let width = (area.width as f32 * 0.6).clamp(40.0, 80.0) as u16;
+    let height = (area.height as f32 * 0.7).min(30.0) as u16;
     let popup_area = centered_rect(width, height, area);
 
     frame.render_widget(Clear, popup_area);
 
     let block = Block::default()
-        .title(" Forwarded messages (Esc to close) ")
+        .title(" Stats (Esc to close, :stats reset) ")
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(Color::Yellow));
+        .border_style(Style::default().fg(Color::Cyan));
     let inner = block.inner(popup_area);
     frame.render_widget(block, popup_area);
 
-    if view.items.is_empty() {
-        let empty = Paragraph::new("No forwarded messages")
-            .style(Style::default().fg(Color::DarkGray))
-            .alignment(Alignment::Center);
-        frame.render_widget(empty, inner);
-        return;
+    let stats = &app.stats;
+    let heading = |text: &str| {
+        Line::from(Span::styled(
+            text.to_string(),
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        ))
+    };
+    let field = |label: &str, value: String| {
+        Line::from(vec![
+            Span::styled(
+                format!("{:<14}", label),
+                Style::default().fg(Color::DarkGray),
+            ),
+            Span::raw(value),
+        ])
+    };
+
+    let uptime = stats.uptime().as_secs();
+    let total = stats.total();
+    let mut lines = vec![
+        field(
+            "Uptime",
+            format!(
+                "{:02}:{:02}:{:02}",
+                uptime / 3600,
+                uptime / 60 % 60,
+                uptime % 60
+            ),
+        ),
+        field("Reconnects", stats.reconnects().to_string()),
+        field(
+            "Key failures",
+            format!(
+                "{} ({} concurrent session warnings)",
+                stats.key_failures(),
+                stats.concurrent_session_warnings()
+            ),
+        ),
+        field(
+            "Messages",
+            format!("{} received, {} sent", total.received, total.sent),
+        ),
+    ];
+    if app.cache.is_some() {
+        let cache = stats.cache();
+        lines.push(field(
+            "Cache",
+            format!("{} hits, {} misses", cache.hits, cache.misses),
+        ));
     }
 
-    let flattened = super::update::flatten_forwards(&view.items, 0);
-    let items: Vec<ListItem> = flattened
-        .iter()
-        .map(|(indent, text)| {
-            let pad = "  ".repeat(*indent);
-            ListItem::new(Line::from(vec![Span::raw(format!("{}{}", pad, text))]))
-        })
-        .collect();
+    let by_chat = stats.by_chat();
+    if !by_chat.is_empty() {
+        lines.push(Line::from(""));
+        lines.push(heading("Chats"));
+        for (peer_id, counts) in by_chat.iter().take(TOP_CHATS) {
+            let title = app
+                .chats
+                .iter()
+                .find(|c| c.id == *peer_id)
+                .map(|c| c.title.clone())
+                .unwrap_or_else(|| peer_id.to_string());
+            lines.push(field(
+                &truncate_to_width(&title, 13),
+                format!("{} received, {} sent", counts.received, counts.sent),
+            ));
+        }
+        if by_chat.len() > TOP_CHATS {
+            lines.push(Line::from(Span::styled(
+                format!("...and {} more", by_chat.len() - TOP_CHATS),
+                Style::default().fg(Color::DarkGray),
+            )));
+        }
+    }
 
-    let list = List::new(items).highlight_style(
-        Style::default()
-            .bg(Color::Blue)
-            .fg(Color::White)
-            .add_modifier(Modifier::BOLD),
-    );
+    if let Some(client) = &app.vk_client {
+        let mut metrics: Vec<_> = client.metrics().into_iter().collect();
+        metrics.sort_by(|a, b| a.0.cmp(&b.0));
+        if !metrics.is_empty() {
+            lines.push(Line::from(""));
+            lines.push(heading("API calls"));
+            for (method, m) in metrics {
+                let errors = if m.errors > 0 {
+                    Span::styled(
+                        format!(", {} errors", m.errors),
+                        Style::default().fg(Color::Red),
+                    )
+                } else {
+                    Span::raw("")
+                };
+                lines.push(Line::from(vec![
+                    Span::styled(
+                        format!("{:<30}", method),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                    Span::raw(m.calls.to_string()),
+                    errors,
+                ]));
+            }
+        }
+    }
 
-    let mut state = ListState::default();
-    state.select(Some(view.selected));
-    frame.render_stateful_widget(list, inner, &mut state);
+    let paragraph = Paragraph::new(lines).wrap(Wrap { trim: false });
+    frame.render_widget(paragraph, inner);
 }
 
 /// Render help popup
@@ -777,8 +2157,11 @@ fn render_help_popup(app: &App, frame: &mut Frame) {
             Line::from("G                - Go to last chat"),
             Line::from("l, Enter         - Open selected chat"),
             Line::from("/                - Search conversations"),
+            Line::from("dd               - Delete conversation"),
+            Line::from("P                - Pin/unpin chat to the top"),
             Line::from("h                - Switch to left panel"),
             Line::from("Tab              - Next panel"),
+            Line::from("Ctrl+B           - Show/hide chat list"),
             Line::from(""),
             Line::from(Span::styled("Commands", Style::default().fg(Color::Yellow))),
             Line::from(""),
@@ -796,24 +2179,39 @@ fn render_help_popup(app: &App, frame: &mut Frame) {
             Line::from(""),
             Line::from("j, Down          - Scroll down"),
             Line::from("k, Up            - Scroll up"),
-            Line::from("g                - Go to first message"),
-            Line::from("G                - Go to last message"),
-            Line::from("Ctrl+U           - Page up"),
-            Line::from("Ctrl+D           - Page down"),
+            Line::from("gg               - Go to first message"),
+            Line::from("gr               - Go to the message this one replies to"),
+            Line::from("Ctrl+O           - Back to where gr jumped from"),
+            Line::from("G                - Go to last message, clearing ↓ new"),
+            Line::from("Ctrl+U           - Half a screen up"),
+            Line::from("Ctrl+D           - Half a screen down"),
+            Line::from("Ctrl+B           - Show/hide chat list"),
             Line::from(""),
             Line::from(Span::styled("Actions", Style::default().fg(Color::Yellow))),
             Line::from(""),
             Line::from("i, l, Enter      - Enter insert mode (write message)"),
+            Line::from("Space, m         - Menu of actions for the message"),
             Line::from("r                - Reply to message"),
             Line::from("f                - Forward message"),
             Line::from("F                - View forwarded (popup)"),
             Line::from("e                - Edit message"),
-            Line::from("dd               - Delete message"),
+            Line::from("dd               - Delete message (for me or everyone)"),
+            Line::from("V                - Select messages (then f, d or y)"),
             Line::from("yy               - Copy message text"),
+            Line::from("yl               - Copy first link in message"),
+            Line::from("yA               - Copy the loaded conversation"),
+            Line::from("\"ayy             - Copy into register a"),
             Line::from("p                - Pin/unpin message (coming soon)"),
-            Line::from("o, Ctrl+L        - Open link in message"),
+            Line::from("u                - Show sender profile"),
+            Line::from("R                - Who read my message (group chats)"),
+            Line::from("x                - React to message"),
+            Line::from(
+                "o, Ctrl+L        - Open link in message (pick one if several), or the file just downloaded",
+            ),
             Line::from("a                - Download attachments"),
+            Line::from("P                - Preview photo in the terminal"),
             Line::from("/                - Search in chat (coming soon)"),
+            Line::from("n, N             - Next/previous global search result"),
             Line::from("h, Esc           - Back to chat list"),
         ],
         Focus::Input => vec![
@@ -827,8 +2225,11 @@ fn render_help_popup(app: &App, frame: &mut Frame) {
             Line::from("Type normally to write message"),
             Line::from("Enter            - Send message"),
             Line::from("Esc              - Exit to normal mode"),
+            Line::from("Ctrl+C           - Cancel the reply being written"),
             Line::from("Ctrl+W           - Delete word"),
             Line::from("Ctrl+U           - Clear line"),
+            Line::from("Ctrl+P           - Paste last yank"),
+            Line::from("Ctrl+V           - Paste from clipboard"),
             Line::from("Backspace        - Delete character"),
             Line::from(""),
             Line::from(Span::styled(
@@ -836,8 +2237,8 @@ fn render_help_popup(app: &App, frame: &mut Frame) {
                 Style::default().fg(Color::Yellow),
             )),
             Line::from(""),
-            Line::from("/sendfile <path> - Send file attachment"),
-            Line::from("/sendimg <path>  - Send image"),
+            Line::from("/sendfile <path>... - Send files (up to 10)"),
+            Line::from("/sendimg <path>...  - Send images; quote names with spaces"),
             Line::from("/sendimg --clipboard - Send from clipboard"),
         ],
     };
@@ -852,12 +2253,59 @@ fn render_help_popup(app: &App, frame: &mut Frame) {
             .add_modifier(Modifier::BOLD),
     )));
     all_lines.push(Line::from(""));
+    all_lines.push(Line::from(
+        "Up, Down         - Earlier commands starting with what is typed",
+    ));
+    all_lines.push(Line::from("Ctrl+R           - Search earlier commands"));
+    all_lines.push(Line::from("Tab, Shift+Tab   - Move through completions"));
     all_lines.push(Line::from(":q, :quit        - Quit application"));
+    all_lines.push(Line::from(":logout          - Log out"));
     all_lines.push(Line::from(":back, :b        - Return to chat list"));
     all_lines.push(Line::from(":search <q>, :s  - Search conversations"));
+    all_lines.push(Line::from(":dm <chat>       - Open a conversation"));
+    all_lines.push(Line::from(":forward [chat], :f - Forward selected message"));
+    all_lines.push(Line::from(
+        "  Tab completes chat names; a peer id tells same-titled chats apart",
+    ));
     all_lines.push(Line::from(":msg <text>, :m  - Quick send message"));
     all_lines.push(Line::from(":attach photo <path>, :ap - Send photo"));
     all_lines.push(Line::from(":attach doc <path>, :ad   - Send document"));
+    all_lines.push(Line::from(":whois [id], :w  - Show user profile"));
+    all_lines.push(Line::from(":members, :mem   - List group chat members"));
+    all_lines.push(Line::from(":leave           - Leave group chat"));
+    all_lines.push(Line::from(
+        ":mute [1h|8h|forever] - Turn off chat notifications",
+    ));
+    all_lines.push(Line::from(
+        ":unmute          - Turn chat notifications back on",
+    ));
+    all_lines.push(Line::from(
+        ":pinchat         - Pin chat to the top of the list",
+    ));
+    all_lines.push(Line::from(":unpinchat       - Unpin chat"));
+    all_lines.push(Line::from(":delchat         - Delete conversation"));
+    all_lines.push(Line::from(":info            - Show chat media counts"));
+    all_lines.push(Line::from(":rename <title>  - Rename group chat"));
+    all_lines.push(Line::from(
+        ":chat rename     - Edit group chat title in place",
+    ));
+    all_lines.push(Line::from(":chat photo <path> - Set group chat photo"));
+    all_lines.push(Line::from(":newchat <title> - Create group chat"));
+    all_lines.push(Line::from(":react <emoji>   - Toggle reaction on message"));
+    all_lines.push(Line::from(":registers, :reg - Show yank registers"));
+    all_lines.push(Line::from(":stats [reset]   - Show session statistics"));
+    all_lines.push(Line::from(
+        ":log tail|path   - Show recent log lines / the log file",
+    ));
+    all_lines.push(Line::from(
+        ":export [json|html] [path] - Save chat history to a file",
+    ));
+    all_lines.push(Line::from(
+        ":grep <text|/re/> - Search this chat offline (loaded and cached)",
+    ));
+    all_lines.push(Line::from(
+        ":cache clear     - Delete cached chats and messages",
+    ));
     all_lines.push(Line::from(":help, :h        - Show this help"));
 
     let paragraph = Paragraph::new(all_lines)
@@ -890,6 +2338,11 @@ fn render_command_completion(app: &App, frame: &mut Frame) {
         } => {
             render_filepath_suggestions(entries, *selected, frame);
         }
+        CompletionState::ChatNames {
+            options, selected, ..
+        } => {
+            render_chat_name_suggestions(options, *selected, frame);
+        }
     }
 }
 
@@ -962,7 +2415,7 @@ fn render_command_suggestions(
     let list = List::new(items)
         .block(
             Block::default()
-                .title(" Commands (Tab/↓↑ to navigate, Enter to select, Esc to cancel) ")
+                .title(" Commands (Tab/Shift+Tab to navigate, Enter to select, Esc to cancel) ")
                 .borders(Borders::ALL)
                 .border_style(Style::default().fg(Color::Yellow)),
         )
@@ -1034,7 +2487,75 @@ fn render_subcommand_suggestions(
     let list = List::new(items)
         .block(
             Block::default()
-                .title(" Options (Tab/↓↑ to navigate, Enter to select, Esc to cancel) ")
+                .title(" Options (Tab/Shift+Tab to navigate, Enter to select, Esc to cancel) ")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow)),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(Color::DarkGray)
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("▶ ");
+
+    let mut state = ListState::default();
+    state.select(Some(selected));
+
+    frame.render_stateful_widget(list, popup_area, &mut state);
+}
+
+/// Render conversation suggestions for a chat argument
+fn render_chat_name_suggestions(
+    options: &[crate::state::ChatNameOption],
+    selected: usize,
+    frame: &mut Frame,
+) {
+    let area = frame.area();
+
+    // Peer ids are shown for titles that need them
+    let label = |opt: &crate::state::ChatNameOption| {
+        if opt.insert == opt.title {
+            opt.title.clone()
+        } else {
+            format!("{} ({})", opt.title, opt.peer_id)
+        }
+    };
+    let max_len = options.iter().map(|o| label(o).width()).max().unwrap_or(10);
+
+    // Width: title + borders + highlight symbol
+    let width = (max_len + 6).clamp(30, 80) as u16;
+
+    // Height: one line per option + borders
+    let height = (options.len() as u16 + 2).min(12);
+
+    // Position: bottom-left, above status line
+    let popup_area = Rect {
+        x: area.x + 2,
+        y: area.height.saturating_sub(height + 2),
+        width,
+        height,
+    };
+
+    // Clear background
+    frame.render_widget(Clear, popup_area);
+
+    let items: Vec<ListItem> = options
+        .iter()
+        .map(|opt| {
+            ListItem::new(Line::from(Span::styled(
+                label(opt),
+                Style::default()
+                    .fg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+            )))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .title(" Chats (Tab/Shift+Tab to navigate, Enter to select, Esc to cancel) ")
                 .borders(Borders::ALL)
                 .border_style(Style::default().fg(Color::Yellow)),
         )
@@ -1111,7 +2632,7 @@ fn render_filepath_suggestions(
     let list = List::new(items)
         .block(
             Block::default()
-                .title(" Files (Tab/↓↑ to navigate, Enter to select, Esc to cancel) ")
+                .title(" Files (Tab/Shift+Tab to navigate, Enter to select, Esc to cancel) ")
                 .borders(Borders::ALL)
                 .border_style(Style::default().fg(Color::Yellow)),
         )
@@ -1128,6 +2649,44 @@ fn render_filepath_suggestions(
 
     frame.render_stateful_widget(list, popup_area, &mut state);
 }
+/// About `width` characters of `text` around the match at byte `range`,
+/// with the match highlighted and newlines flattened.
+fn highlighted_preview(
+    text: &str,
+    range: std::ops::Range<usize>,
+    width: usize,
+) -> Vec<Span<'static>> {
+    let flat = |s: &str| s.replace('\n', " ");
+    let (Some(before), Some(matched), Some(after)) = (
+        text.get(..range.start),
+        text.get(range.clone()),
+        text.get(range.end..),
+    ) else {
+        return vec![Span::raw(flat(text))];
+    };
+    // Keep some context before the match, the rest after it
+    let lead = width / 3;
+    let skip = before.chars().count().saturating_sub(lead);
+    let mut before: String = before.chars().skip(skip).collect();
+    if skip > 0 {
+        before.insert_str(0, "...");
+    }
+    let room = width.saturating_sub(before.width() + matched.width());
+    let after_shown = truncate_to_width(after, room);
+    let text_style = Style::default().fg(Color::White);
+    vec![
+        Span::styled(flat(&before), text_style),
+        Span::styled(
+            flat(matched),
+            Style::default()
+                .fg(Color::Black)
+                .bg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::styled(flat(&after_shown), text_style),
+    ]
+}
+
 /// Render global search popup
 fn render_global_search_popup(app: &App, frame: &mut Frame) {
     let Some(search) = &app.global_search else {
@@ -1159,9 +2718,12 @@ fn render_global_search_popup(app: &App, frame: &mut Frame) {
 
     // Render input field
     let input_text = if search.is_loading {
-        format!("🔍 {} (searching...)", search.query)
+        format!("{}{} (searching...)", SEARCH_PROMPT, search.query)
     } else {
-        format!("🔍 {} ({} results)", search.query, search.total_count)
+        format!(
+            "{}{} ({} results)",
+            SEARCH_PROMPT, search.query, search.total_count
+        )
     };
 
     let input_widget = Paragraph::new(input_text)
@@ -1169,7 +2731,11 @@ fn render_global_search_popup(app: &App, frame: &mut Frame) {
             Block::default()
                 .borders(Borders::ALL)
                 .border_style(Style::default().fg(Color::Cyan))
-                .title(" Global Search (Esc to cancel) "),
+                .title(if search.offline {
+                    " Search in Chat, Offline (Esc to cancel) "
+                } else {
+                    " Global Search (Esc to cancel) "
+                }),
         )
         .style(Style::default().fg(Color::White));
 
@@ -1177,21 +2743,27 @@ fn render_global_search_popup(app: &App, frame: &mut Frame) {
     frame.render_widget(input_widget, chunks[0]);
 
     // Render cursor in input field
-    let cursor_x = chunks[0].x + 3 + search.cursor as u16; // +3 for "🔍 "
+    let cursor_x = chunks[0].x
+        + 1
+        + cursor_display_col(SEARCH_PROMPT, usize::MAX)
+        + cursor_display_col(&search.query, search.cursor);
     let cursor_y = chunks[0].y + 1;
     frame.set_cursor_position(Position::new(cursor_x, cursor_y));
 
     // Render results list
+    let clock = app.config.clock();
+    let now = now_timestamp();
     let results: Vec<ListItem> = search
         .results
         .iter()
         .map(|result| {
-            let timestamp = format_timestamp(result.timestamp);
-            let preview = if result.text.chars().count() > 60 {
-                let truncated: String = result.text.chars().take(60).collect();
-                format!("{}...", truncated)
-            } else {
-                result.text.clone()
+            let timestamp = clock.message_time(result.timestamp, now);
+            let preview = match &result.highlight {
+                Some(range) => highlighted_preview(&result.text, range.clone(), 60),
+                None => vec![Span::styled(
+                    truncate_to_width(&result.text, 60),
+                    Style::default().fg(Color::White),
+                )],
             };
 
             let lines = vec![
@@ -1207,7 +2779,7 @@ fn render_global_search_popup(app: &App, frame: &mut Frame) {
                     Span::raw(" • "),
                     Span::styled(timestamp, Style::default().fg(Color::DarkGray)),
                 ]),
-                Line::from(Span::styled(preview, Style::default().fg(Color::White))),
+                Line::from(preview),
             ];
 
             ListItem::new(lines)
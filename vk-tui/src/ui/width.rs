@@ -0,0 +1,83 @@
+//! Text measured in terminal cells rather than chars or bytes: Cyrillic
+//! takes one cell per char, most emoji and CJK take two.
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// `s` cut to at most `max` cells, ending with "..." when anything was
+/// cut. Never splits a char.
+pub fn truncate_to_width(s: &str, max: usize) -> String {
+    if s.width() <= max {
+        return s.to_string();
+    }
+    let ellipsis = if max >= 3 { "..." } else { "" };
+    let room = max - ellipsis.len();
+    let mut out = String::new();
+    let mut used = 0;
+    for c in s.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w > room {
+            break;
+        }
+        used += w;
+        out.push(c);
+    }
+    out.push_str(ellipsis);
+    out
+}
+
+/// Cell column of a cursor standing after the first `char_pos` chars of
+/// `s`.
+pub fn cursor_display_col(s: &str, char_pos: usize) -> u16 {
+    let cells: usize = s
+        .chars()
+        .take(char_pos)
+        .map(|c| c.width().unwrap_or(0))
+        .sum();
+    u16::try_from(cells).unwrap_or(u16::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_ascii() {
+        assert_eq!(truncate_to_width("hello", 10), "hello");
+        assert_eq!(truncate_to_width("hello world", 8), "hello...");
+        assert_eq!(truncate_to_width("hello", 2), "he");
+        assert_eq!(truncate_to_width("hello", 0), "");
+    }
+
+    #[test]
+    fn test_truncate_cyrillic() {
+        // One cell per char, two bytes each
+        assert_eq!(truncate_to_width("Привет, мир", 11), "Привет, мир");
+        assert_eq!(truncate_to_width("Привет, мир", 9), "Привет...");
+    }
+
+    #[test]
+    fn test_truncate_emoji() {
+        // 🎉 takes two cells: it does not fit in the one left before "..."
+        assert_eq!(truncate_to_width("ab🎉cd", 5), "ab...");
+        assert_eq!(truncate_to_width("ab🎉cd", 6), "ab🎉cd");
+        assert_eq!(truncate_to_width("🎉🎉🎉", 5), "🎉...");
+    }
+
+    #[test]
+    fn test_truncate_long_url_in_narrow_area() {
+        let url = "https://oauth.vk.com/authorize?client_id=1&scope=messages,photos";
+        for width in 0..10 {
+            assert!(truncate_to_width(url, width).width() <= width);
+        }
+    }
+
+    #[test]
+    fn test_cursor_display_col() {
+        assert_eq!(cursor_display_col("abc", 2), 2);
+        assert_eq!(cursor_display_col("Привет", 3), 3);
+        assert_eq!(cursor_display_col("a🎉b", 2), 3);
+        assert_eq!(cursor_display_col("日本", 2), 4);
+        // Past the end stops at the end
+        assert_eq!(cursor_display_col("ab", 10), 2);
+    }
+}
@@ -1,25 +1,69 @@
 use std::process::Command;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::clipboard;
 use crate::commands::{determine_completion_state, handle_command};
 use crate::event::VkEvent;
-use crate::input::{delete_word, insert_char_at, remove_char_at};
+use crate::input::{delete_word, insert_char_at, insert_str_at, remove_char_at, single_line};
+use crate::links::message_urls;
 use crate::message::Message;
+use crate::registers::Registers;
 use crate::state::{
-    App, AsyncAction, AttachmentInfo, AttachmentKind, Chat, ChatMessage, ChatsPagination,
-    CompletionState, DeliveryStatus, Focus, ForwardStage, MessagesPagination, Mode, ReplyPreview,
-    RunningState, Screen,
+    App, AsyncAction, AttachmentInfo, AttachmentKind, CaptchaPrompt, Chat, ChatKind, ChatMessage,
+    ChatsPagination, CompletionState, CrossChatSend, DeleteChoice, DeletePrompt, DeliveryStatus,
+    EditConflict, Focus, ForwardStage, HistoryRecall, HistorySearch, JUMP_LIST_LIMIT, JumpPoint,
+    LinkPicker, MenuAction, MessageMenu, MessagesPagination, Mode, PhotoPreview, RedirectWait,
+    ReplyPreview, RunningState, Screen, SearchHits, SearchResult, UploadState,
 };
-use vk_api::VkClient;
+use ratatui::layout::Size;
+use tokio::sync::watch;
+use vk_api::MAX_ATTACHMENTS;
+use vk_core::MessageKind;
+use vk_core::download;
+use vk_core::edit::{EDIT_TOO_OLD, apply_remote_edit, content_hash, is_conflict, is_editable};
+use vk_core::export;
+use vk_core::grep::GrepPattern;
+use vk_core::longpoll::{CONCURRENT_SESSION_WARNING, ConnectionState, FLAG_DELETED};
+use vk_core::models::SERVICE_MESSAGE_DENIED;
+use vk_core::outgoing::{
+    MAX_MESSAGE_CHARS, confirm_sent, drop_failed_uploads, fail_upload, merge_incoming,
+    set_upload_progress, split_message, too_long,
+};
+use vk_core::profiles::{refresh_names, warmup_candidates};
+use vk_core::upload;
+use vk_core::window::{Grown, anchor_insert, trim_window};
+
+/// How long a message jumped to (search hit, replied message) stays
+/// highlighted
+const JUMP_HIGHLIGHT: Duration = Duration::from_secs(5);
 
 pub fn update(app: &mut App, msg: Message) -> Option<Message> {
+    if matches!(
+        msg,
+        Message::NavigateUp
+            | Message::NavigateDown
+            | Message::PageUp
+            | Message::PageDown
+            | Message::GoToTop
+            | Message::GoToBottom
+            | Message::ShowUnseen
+    ) {
+        app.highlighted_message = None;
+        app.last_download = None;
+    }
+
     match msg {
         Message::Noop => {}
         Message::Quit => {
             app.running_state = RunningState::Done;
         }
+        Message::Logout => {
+            app.end_session();
+            app.status = Some("Logged out. Authorize again to log in.".into());
+        }
         Message::OpenAuthUrl => {
-            if app.screen == Screen::Auth {
+            if app.entering_token() {
                 let url = app.auth_url();
                 if let Err(e) = open::that(&url) {
                     app.status = Some(format!("Failed to open browser: {}", e));
@@ -39,6 +83,19 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                 app.focus = app.focus.prev();
             }
         }
+        Message::ToggleSidebar => {
+            if app.screen == Screen::Main {
+                let visible = !app.sidebar_visible();
+                app.sidebar_toggle = Some(visible);
+                // A hidden chat list cannot keep the focus while a chat is open
+                if !visible && app.focus == Focus::ChatList && app.current_peer_id.is_some() {
+                    app.focus = Focus::Messages;
+                }
+            }
+        }
+        Message::Resize(width, height) => {
+            app.terminal_size = Size::new(width, height);
+        }
         Message::NavigateUp => {
             if app.screen == Screen::Main {
                 match app.focus {
@@ -138,27 +195,31 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                                         pagination.is_loading = true;
                                     }
 
-                                    const COUNT: u32 = 50;
-                                    const OFFSET: i32 = -49; // -(COUNT - 1) for overlap
+                                    let count = app.settings.messages_page_size;
+                                    // -(count - 1) for overlap
+                                    let offset = 1 - count as i32;
 
                                     app.status = Some("Loading newer messages...".into());
                                     tracing::debug!(
                                         "Sending LoadMessagesWithOffset: peer_id={}, last_cmid={}, offset={}, count={}",
                                         peer_id,
                                         last_cmid,
-                                        OFFSET,
-                                        COUNT
+                                        offset,
+                                        count
                                     );
 
-                                    // Load with fixed offset=-49 and count=50 to get overlap + new messages
+                                    // Load from the last message on to get overlap + new messages
                                     // Deduplication will filter out already loaded messages
                                     app.send_action(AsyncAction::LoadMessagesWithOffset(
-                                        peer_id, last_cmid, OFFSET, COUNT,
+                                        peer_id, last_cmid, offset, count,
                                     ));
                                 }
                             }
                         } else {
                             app.messages_scroll += 1;
+                            if app.messages_scroll + 1 == app.messages.len() {
+                                app.unseen_below = 0;
+                            }
                         }
                     }
                     Focus::Input => {}
@@ -166,6 +227,8 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
             }
         }
         Message::GoToTop => {
+            app.awaiting_go = false;
+            app.status = None;
             if app.screen == Screen::Main {
                 match app.focus {
                     Focus::ChatList => app.selected_chat = 0,
@@ -178,24 +241,145 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
             if app.screen == Screen::Main {
                 match app.focus {
                     Focus::ChatList => app.selected_chat = app.chats.len().saturating_sub(1),
-                    Focus::Messages => app.messages_scroll = app.messages.len().saturating_sub(1),
+                    Focus::Messages => {
+                        app.messages_scroll = app.messages.len().saturating_sub(1);
+                        app.unseen_below = 0;
+                    }
                     Focus::Input => {}
                 }
             }
         }
+        Message::StartGo => {
+            app.awaiting_go = true;
+            app.status = Some("g".into());
+        }
+        Message::CancelGo => {
+            app.awaiting_go = false;
+            app.status = None;
+        }
+        Message::GoToReply => {
+            app.awaiting_go = false;
+            app.status = None;
+            if app.screen != Screen::Main || app.focus != Focus::Messages {
+                return None;
+            }
+            let (Some(peer_id), Some(msg)) = (app.current_peer_id, app.current_message()) else {
+                return None;
+            };
+            let Some(reply) = &msg.reply else {
+                app.status = Some("Not a reply".into());
+                return None;
+            };
+            let from = JumpPoint {
+                peer_id,
+                message_id: msg.id,
+            };
+            let (message_id, cmid) = (reply.message_id, reply.cmid);
+
+            let loaded = app.messages.iter().position(|m| {
+                message_id.is_some_and(|id| m.id == id) || (cmid.is_some() && m.cmid == cmid)
+            });
+            if let Some(pos) = loaded {
+                push_jump(app, from);
+                show_message(app, pos);
+            } else if let Some(message_id) = message_id {
+                push_jump(app, from);
+                load_around(app, peer_id, message_id);
+            } else if let Some(cmid) = cmid {
+                push_jump(app, from);
+                app.send_action(AsyncAction::ResolveReply(peer_id, cmid));
+                app.status = Some("Finding replied message...".into());
+            } else {
+                app.status = Some("Replied message is not known".into());
+            }
+        }
+        Message::ReplyTargetResolved(result) => match result {
+            Ok(message_id) => {
+                if let Some(peer_id) = app.current_peer_id {
+                    load_around(app, peer_id, message_id);
+                }
+            }
+            Err(e) => {
+                // Nothing was jumped to
+                app.jump_list.pop();
+                app.status = Some(e);
+            }
+        },
+        Message::JumpBack => {
+            let peer_id = app.current_peer_id?;
+            app.jump_list.retain(|p| p.peer_id == peer_id);
+            let Some(point) = app.jump_list.pop() else {
+                app.status = Some("Jump list is empty".into());
+                return None;
+            };
+            app.focus = Focus::Messages;
+            match app.messages.iter().position(|m| m.id == point.message_id) {
+                Some(pos) => show_message(app, pos),
+                None => load_around(app, peer_id, point.message_id),
+            }
+        }
+        Message::ShowUnseen => {
+            if app.screen == Screen::Main {
+                app.focus = Focus::Messages;
+                app.messages_scroll = app.messages.len().saturating_sub(1);
+                app.unseen_below = 0;
+            }
+        }
+        Message::ChatClicked(idx) => {
+            let selected = std::mem::replace(&mut app.selected_chat, idx);
+            if app.current_chat().is_none() {
+                app.selected_chat = selected;
+                return None;
+            }
+            app.focus = Focus::ChatList;
+            if app.mode == Mode::Insert {
+                app.mode = Mode::Normal;
+            }
+            return Some(Message::Select);
+        }
+        Message::MessageClicked(idx) => {
+            if idx < app.messages.len() {
+                app.focus = Focus::Messages;
+                if app.mode == Mode::Insert {
+                    app.mode = Mode::Normal;
+                }
+                app.messages_scroll = idx;
+                if idx + 1 == app.messages.len() {
+                    app.unseen_below = 0;
+                }
+            }
+        }
+        Message::InputClicked => {
+            if app.current_peer_id.is_some() {
+                return Some(Message::EnterInsertMode);
+            }
+        }
+        Message::Scroll { panel, down } => {
+            // Scroll the panel under the mouse without focusing it
+            let focus = std::mem::replace(&mut app.focus, panel);
+            let next = update(
+                app,
+                if down {
+                    Message::NavigateDown
+                } else {
+                    Message::NavigateUp
+                },
+            );
+            app.focus = focus;
+            return next;
+        }
         Message::Select => {
-            if app.screen == Screen::Auth {
+            if app.entering_token() {
                 if app.auth.save_token_from_url(&app.token_input).is_ok()
                     && let Some(token) = app.auth.access_token()
                 {
-                    app.vk_client = Some(Arc::new(VkClient::new(token.to_string())));
-                    app.screen = Screen::Main;
-                    app.status = Some("Authenticated successfully".into());
-                    // Initialize chats pagination and load first page
-                    app.chats_pagination = ChatsPagination::default();
-                    app.chats_pagination.is_loading = true;
-                    app.send_action(AsyncAction::LoadConversations(0));
-                    app.send_action(AsyncAction::StartLongPoll);
+                    match app.settings.client(token.to_string()) {
+                        Ok(client) => {
+                            app.send_action(AsyncAction::ValidateToken(Arc::new(client)));
+                            app.status = Some("Checking token permissions...".into());
+                        }
+                        Err(e) => app.status = Some(e.to_string()),
+                    }
                 } else {
                     app.status = Some("Failed to parse token from URL".into());
                 }
@@ -204,21 +388,68 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                 && let Some((peer_id, title)) =
                     app.current_chat().map(|chat| (chat.id, chat.title.clone()))
             {
-                // Clear chat filter if active
-                app.chat_filter = None;
-
-                app.current_peer_id = Some(peer_id);
-                app.messages.clear();
-                app.is_loading = true;
-                // Initialize messages pagination and load first page
-                app.messages_pagination = Some(MessagesPagination::new(peer_id));
-                if let Some(pagination) = &mut app.messages_pagination {
-                    pagination.is_loading = true;
+                app.open_chat(peer_id, &title);
+            }
+        }
+        Message::WaitForRedirect => {
+            if app.entering_token() && app.redirect_wait.is_none() {
+                let (cancel, cancel_rx) = watch::channel(false);
+                let port = app
+                    .config
+                    .redirect_port
+                    .unwrap_or(vk_api::auth::DEFAULT_REDIRECT_PORT);
+                app.send_action(AsyncAction::WaitForRedirect(port, cancel_rx));
+                app.redirect_wait = Some(RedirectWait {
+                    started: Instant::now(),
+                    cancel,
+                });
+                app.status = Some("Log in in the browser; the token comes back by itself".into());
+            }
+        }
+        Message::CancelRedirectWait => {
+            if let Some(wait) = app.redirect_wait.take() {
+                let _ = wait.cancel.send(true);
+                app.status = Some("Stopped waiting for the browser".into());
+            }
+        }
+        Message::RedirectCaught(result) => {
+            if app.redirect_wait.take().is_some() {
+                match result {
+                    Ok(url) => {
+                        app.token_cursor = url.chars().count();
+                        app.token_input = url;
+                        return Some(Message::Select);
+                    }
+                    Err(err) => app.status = Some(err),
                 }
-                app.send_action(AsyncAction::LoadMessages(peer_id, 0));
-                app.send_action(AsyncAction::MarkAsRead(peer_id));
-                app.status = Some(format!("Loading chat: {}", title));
-                app.focus = Focus::Messages;
+            }
+        }
+        Message::TokenChecked(result) => {
+            if let Err(err) = result {
+                // Keep the pasted URL, but not a token that cannot work
+                let _ = app.auth.logout();
+                app.status = Some(err);
+            } else if let Some(token) = app.auth.access_token()
+                // Built the same way for the check a moment ago
+                && let Ok(client) = app.settings.client(token.to_string())
+            {
+                app.set_client(Some(Arc::new(client)));
+                app.token_input.clear();
+                app.token_cursor = 0;
+                if app.session_expired {
+                    // Everything loaded stays; queued actions now run
+                    app.session_expired = false;
+                    app.status = Some("Session renewed".into());
+                } else {
+                    app.screen = Screen::Main;
+                    app.status = Some("Authenticated successfully".into());
+                    app.is_loading = true;
+                    app.chats_pagination = ChatsPagination::default();
+                    app.chats_pagination.is_loading = true;
+                    app.send_action(AsyncAction::LoadProfile);
+                    app.send_action(AsyncAction::LoadConversations(0));
+                }
+                app.start_long_poll();
             }
         }
         Message::Back => {
@@ -228,61 +459,115 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
             }
         }
         Message::OpenLink => {
-            if app.screen == Screen::Main
+            if let Some(path) = app.last_download.take() {
+                app.status = Some(match open::that(&path) {
+                    Ok(()) => format!("Opened {}", download::display_path(&path)),
+                    Err(e) => format!("Failed to open file: {}", e),
+                });
+            } else if app.screen == Screen::Main
                 && app.focus == Focus::Messages
                 && let Some(msg) = app.current_message()
             {
-                if let Some(url) = first_url(msg) {
-                    if let Err(e) = open::that(&url) {
-                        app.status = Some(format!("Failed to open link: {}", e));
-                    } else {
-                        app.status = Some(format!("Opened {}", url));
-                    }
-                } else {
-                    app.status = Some("No link in message".into());
+                let urls = message_urls(msg);
+                match urls.as_slice() {
+                    [] => app.status = Some("No link in message".into()),
+                    [url] => open_url(app, url),
+                    _ => app.link_picker = Some(LinkPicker { urls, selected: 0 }),
                 }
             }
         }
+        Message::LinkPickerUp => {
+            if let Some(picker) = &mut app.link_picker {
+                picker.selected = picker.selected.saturating_sub(1);
+            }
+        }
+        Message::LinkPickerDown => {
+            if let Some(picker) = &mut app.link_picker {
+                picker.selected = (picker.selected + 1).min(picker.urls.len() - 1);
+            }
+        }
+        Message::LinkPickerOpen(number) => {
+            let picker = app.link_picker.as_ref()?;
+            let index = number.map_or(picker.selected, |n| n - 1);
+            // A number past the list leaves the picker open
+            let url = picker.urls.get(index)?.clone();
+            app.link_picker = None;
+            open_url(app, &url);
+        }
+        Message::LinkPickerClose => {
+            app.link_picker = None;
+        }
         Message::PageUp => {
             if app.screen == Screen::Main && app.focus == Focus::Messages {
-                app.messages_scroll = app.messages_scroll.saturating_sub(10);
+                app.messages_scroll = app.messages_scroll.saturating_sub(app.half_page());
             }
         }
         Message::PageDown => {
             if app.screen == Screen::Main && app.focus == Focus::Messages {
-                app.messages_scroll =
-                    (app.messages_scroll + 10).min(app.messages.len().saturating_sub(1));
+                app.messages_scroll = (app.messages_scroll + app.half_page())
+                    .min(app.messages.len().saturating_sub(1));
+                if app.messages_scroll + 1 >= app.messages.len() {
+                    app.unseen_below = 0;
+                }
             }
         }
         Message::InputChar(c) => match app.screen {
-            Screen::Auth => {
+            _ if app.entering_token() => {
                 insert_char_at(&mut app.token_input, app.token_cursor, c);
                 app.token_cursor += 1;
             }
             Screen::Main if app.focus == Focus::Input => {
+                if app.input.is_empty() {
+                    app.input_peer_id = app.current_peer_id;
+                }
                 insert_char_at(&mut app.input, app.input_cursor, c);
                 app.input_cursor += 1;
             }
             _ => {}
         },
-        Message::InputBackspace => match app.screen {
-            Screen::Auth => {
-                if app.token_cursor > 0 {
-                    app.token_cursor -= 1;
-                    remove_char_at(&mut app.token_input, app.token_cursor);
+        Message::InputPaste(text) => {
+            let text = single_line(&text);
+            match app.screen {
+                _ if app.entering_token() => {
+                    app.token_cursor =
+                        insert_str_at(&mut app.token_input, app.token_cursor, text.trim());
                 }
-            }
-            Screen::Main if app.focus == Focus::Input => {
-                if app.input_cursor > 0 {
-                    app.input_cursor -= 1;
-                    remove_char_at(&mut app.input, app.input_cursor);
+                Screen::Main if app.mode == Mode::Command => {
+                    app.command_cursor =
+                        insert_str_at(&mut app.command_input, app.command_cursor, &text);
+                    app.history_recall = None;
+                    app.completion_state =
+                        determine_completion_state(&app.command_input, &app.chats);
+                }
+                Screen::Main if app.focus == Focus::Input => {
+                    if app.input.is_empty() {
+                        app.input_peer_id = app.current_peer_id;
+                    }
+                    app.input_cursor = insert_str_at(&mut app.input, app.input_cursor, &text);
                 }
+                _ => {}
+            }
+        }
+        Message::PasteClipboard => match clipboard::paste() {
+            Ok(text) if !text.is_empty() => return Some(Message::InputPaste(text)),
+            Ok(_) => app.status = Some("Clipboard is empty".into()),
+            Err(e) => app.status = Some(e.to_string()),
+        },
+        Message::InputBackspace => match app.screen {
+            _ if app.entering_token() && app.token_cursor > 0 => {
+                app.token_cursor -= 1;
+                remove_char_at(&mut app.token_input, app.token_cursor);
+            }
+            _ if app.entering_token() => {}
+            Screen::Main if app.focus == Focus::Input && app.input_cursor > 0 => {
+                app.input_cursor -= 1;
+                remove_char_at(&mut app.input, app.input_cursor);
             }
             _ => {}
         },
         Message::InputDeleteWord => {
             let (input, cursor) = match app.screen {
-                Screen::Auth => (&mut app.token_input, &mut app.token_cursor),
+                _ if app.entering_token() => (&mut app.token_input, &mut app.token_cursor),
                 Screen::Main if app.focus == Focus::Input => {
                     (&mut app.input, &mut app.input_cursor)
                 }
@@ -291,7 +576,7 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
             delete_word(input, cursor);
         }
         Message::InputSubmit => match app.screen {
-            Screen::Auth => return Some(Message::Select),
+            _ if app.entering_token() => return Some(Message::Select),
             Screen::Main if app.focus == Focus::Input => {
                 if app.input.is_empty() {
                     return None;
@@ -303,6 +588,10 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                         return None;
                     }
                 };
+                if let Some(denied) = app.write_denied() {
+                    app.status = Some(denied);
+                    return None;
+                }
                 if let Some(edit_idx) = app.editing_message {
                     let (message_id, cmid) = if let Some(msg) = app.messages.get(edit_idx) {
                         if msg.id == 0 {
@@ -320,30 +609,66 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                     app.input_cursor = 0;
                     app.mode = Mode::Normal;
                     app.editing_message = None;
+                    let base_hash = app.edit_base_hash.take();
                     app.status = Some("Editing...".into());
                     if let Some(m) = app.messages.get_mut(edit_idx) {
                         m.text = text.clone();
                     }
-                    app.send_action(AsyncAction::EditMessage(peer_id, message_id, cmid, text));
+                    app.send_action(AsyncAction::EditMessage(
+                        peer_id, message_id, cmid, text, base_hash,
+                    ));
+                    return None;
+                }
+
+                // Typed in another chat: make sure it is meant for this one
+                if app.config.confirm_cross_chat_send
+                    && app.input_peer_id.is_some_and(|origin| origin != peer_id)
+                {
+                    let title = app
+                        .chats
+                        .iter()
+                        .find(|c| c.id == peer_id)
+                        .map(|c| c.title.clone())
+                        .unwrap_or_else(|| peer_id.to_string());
+                    app.cross_chat_send = Some(CrossChatSend { peer_id, title });
                     return None;
                 }
+                app.input_peer_id = None;
 
                 if let Some(cmd) = parse_send_command(&app.input) {
                     return handle_send_command(app, peer_id, cmd);
                 }
 
+                if too_long(&app.input) && !app.settings.split_long_messages {
+                    app.status = Some(format!(
+                        "Message is {} characters, VK takes {}; set split_long_messages to send it in parts",
+                        app.input.chars().count(),
+                        MAX_MESSAGE_CHARS
+                    ));
+                    return None;
+                }
+
                 let text = std::mem::take(&mut app.input);
                 app.input_cursor = 0;
                 app.mode = Mode::Normal;
-                app.status = Some("Sending...".into());
+                let parts = split_message(&text, MAX_MESSAGE_CHARS);
+                app.status = Some(if parts.len() > 1 {
+                    format!("Sending in {} parts...", parts.len())
+                } else {
+                    "Sending...".into()
+                });
 
                 if let Some((reply_id, preview)) = app.reply_to.take() {
+                    let random_id = vk_api::new_random_id();
                     app.messages.push(ChatMessage {
                         id: 0,
                         cmid: None,
-                        from_id: app.auth.user_id().unwrap_or(0),
+                        random_id: Some(random_id),
+                        from_id: app.own_user_id().unwrap_or(0),
                         from_name: "You".into(),
-                        text: text.clone(),
+                        from_photo: None,
+                        text: parts.first().cloned().unwrap_or_default(),
+                        kind: MessageKind::Text,
                         timestamp: chrono_timestamp(),
                         is_outgoing: true,
                         is_read: false,
@@ -354,9 +679,21 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                         reply: Some(preview),
                         fwd_count: 0,
                         forwards: Vec::new(),
+                        reactions: Vec::new(),
+                        upload: None,
                     });
                     app.messages_scroll = app.messages.len().saturating_sub(1);
-                    app.send_action(AsyncAction::SendReply(peer_id, reply_id, text));
+                    if parts.len() > 1 {
+                        app.send_action(AsyncAction::SendParts(
+                            peer_id,
+                            Some((reply_id, random_id)),
+                            parts,
+                        ));
+                    } else {
+                        app.send_action(AsyncAction::SendReply(peer_id, reply_id, text, random_id));
+                    }
+                } else if parts.len() > 1 {
+                    app.send_action(AsyncAction::SendParts(peer_id, None, parts));
                 } else {
                     app.send_action(AsyncAction::SendMessage(peer_id, text));
                 }
@@ -368,21 +705,24 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
         Message::CommandChar(c) => {
             insert_char_at(&mut app.command_input, app.command_cursor, c);
             app.command_cursor += 1;
+            app.history_recall = None;
 
             // FSM state transition based on new input
-            app.completion_state = determine_completion_state(&app.command_input);
+            app.completion_state = determine_completion_state(&app.command_input, &app.chats);
         }
         Message::CommandBackspace => {
             if app.command_cursor > 0 {
                 app.command_cursor -= 1;
                 remove_char_at(&mut app.command_input, app.command_cursor);
+                app.history_recall = None;
 
                 // FSM state transition based on new input
-                app.completion_state = determine_completion_state(&app.command_input);
+                app.completion_state = determine_completion_state(&app.command_input, &app.chats);
             }
         }
         Message::CommandDeleteWord => {
             delete_word(&mut app.command_input, &mut app.command_cursor);
+            app.history_recall = None;
         }
         Message::CommandSubmit => {
             // FSM state transition on submit (Tab key behavior)
@@ -403,7 +743,8 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                     app.command_cursor = app.command_input.len();
 
                     // Re-evaluate completion state for next stage
-                    app.completion_state = determine_completion_state(&app.command_input);
+                    app.completion_state =
+                        determine_completion_state(&app.command_input, &app.chats);
                     return None;
                 }
                 CompletionState::Subcommands {
@@ -423,7 +764,8 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                     app.command_cursor = app.command_input.len();
 
                     // Re-evaluate completion state for next stage
-                    app.completion_state = determine_completion_state(&app.command_input);
+                    app.completion_state =
+                        determine_completion_state(&app.command_input, &app.chats);
                     return None;
                 }
                 CompletionState::FilePaths {
@@ -446,7 +788,8 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                         app.command_cursor = app.command_input.len();
 
                         // Re-evaluate completion state to show directory contents
-                        app.completion_state = determine_completion_state(&app.command_input);
+                        app.completion_state =
+                            determine_completion_state(&app.command_input, &app.chats);
                     } else {
                         // File: insert with space and close completion
                         app.command_input = format!("{} {} ", cmd_part, entry.full_path);
@@ -455,9 +798,28 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                     }
                     return None;
                 }
+                CompletionState::ChatNames {
+                    command,
+                    options,
+                    selected,
+                } => {
+                    // Chat argument: insert it, the next Enter runs the command
+                    let prefix = if app.command_input.starts_with(':') {
+                        ":"
+                    } else {
+                        ""
+                    };
+                    app.command_input =
+                        format!("{}{} {}", prefix, command, options[selected].insert);
+                    app.command_cursor = app.command_input.chars().count();
+                    app.completion_state = CompletionState::Inactive;
+                    return None;
+                }
                 CompletionState::Inactive => {
                     // No completion active - execute the command
                     let cmd = app.command_input.clone();
+                    app.history_recall = None;
+                    app.command_history.append(&cmd);
                     if let Some(res) = handle_command(app, &cmd) {
                         return Some(res);
                     }
@@ -496,6 +858,11 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                         *selected -= 1;
                     }
                 }
+                CompletionState::ChatNames { selected, .. } => {
+                    if *selected > 0 {
+                        *selected -= 1;
+                    }
+                }
                 CompletionState::Inactive => {}
             }
         }
@@ -524,15 +891,102 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                         *selected += 1;
                     }
                 }
+                CompletionState::ChatNames {
+                    selected, options, ..
+                } => {
+                    // Tab cycles through the conversations
+                    *selected = (*selected + 1) % options.len();
+                }
                 CompletionState::Inactive => {}
             }
         }
         Message::CompletionSelect => {
             // Same as Enter - handled by CommandSubmit
         }
+        Message::CommandHistoryUp => {
+            let (prefix, before) = match &app.history_recall {
+                Some(recall) => (recall.prefix.clone(), Some(recall.index)),
+                None => (app.command_input.trim_start_matches(':').to_string(), None),
+            };
+            if let Some(index) = app.command_history.older(&prefix, before) {
+                let command = app.command_history.get(index)?.to_string();
+                recall_command(app, command);
+                app.history_recall = Some(HistoryRecall { prefix, index });
+            }
+        }
+        Message::CommandHistoryDown => {
+            let recall = app.history_recall.take()?;
+            match app.command_history.newer(&recall.prefix, recall.index) {
+                Some(index) => {
+                    let command = app.command_history.get(index)?.to_string();
+                    recall_command(app, command);
+                    app.history_recall = Some(HistoryRecall { index, ..recall });
+                }
+                // Past the newest: back to what was typed
+                None => {
+                    recall_command(app, recall.prefix);
+                    app.completion_state =
+                        determine_completion_state(&app.command_input, &app.chats);
+                }
+            }
+        }
+        Message::HistorySearchStart => {
+            app.history_recall = None;
+            app.history_search = Some(HistorySearch {
+                draft: app.command_input.clone(),
+                ..HistorySearch::default()
+            });
+        }
+        Message::HistorySearchChar(c) => {
+            let search = app.history_search.as_mut()?;
+            search.query.push(c);
+            // The current hit still counts, like in a shell
+            search.hit = app.command_history.search(&search.query, search.hit);
+        }
+        Message::HistorySearchBackspace => {
+            let search = app.history_search.as_mut()?;
+            search.query.pop();
+            search.hit = app.command_history.search(&search.query, None);
+        }
+        Message::HistorySearchNext => {
+            let search = app.history_search.as_mut()?;
+            let from = match search.hit {
+                Some(0) => return None,
+                Some(hit) => Some(hit - 1),
+                None => None,
+            };
+            if let Some(hit) = app.command_history.search(&search.query, from) {
+                search.hit = Some(hit);
+            }
+        }
+        Message::HistorySearchAccept => {
+            let search = app.history_search.take()?;
+            match search.hit.and_then(|hit| app.command_history.get(hit)) {
+                Some(command) => {
+                    recall_command(app, command.to_string());
+                    return Some(Message::CommandSubmit);
+                }
+                None => recall_command(app, search.draft),
+            }
+        }
+        Message::HistorySearchCancel => {
+            let search = app.history_search.take()?;
+            recall_command(app, search.draft);
+            app.completion_state = determine_completion_state(&app.command_input, &app.chats);
+        }
 
         // Mode switches
         Message::EnterNormalMode => {
+            // Esc from an empty input gives up the reply too
+            if app.mode == Mode::Insert && app.input.is_empty() && app.reply_to.is_some() {
+                app.reply_to = None;
+                app.mode = Mode::Normal;
+                if app.focus == Focus::Input {
+                    app.focus = Focus::Messages;
+                }
+                app.status = Some("Reply cancelled".into());
+                return None;
+            }
             app.mode = Mode::Normal;
             if app.focus == Focus::Input {
                 app.focus = Focus::Messages;
@@ -540,21 +994,37 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
             app.command_input.clear();
             app.command_cursor = 0;
             app.completion_state = CompletionState::Inactive; // FSM reset
+            app.history_recall = None;
             app.status = Some("Normal mode".into());
         }
         Message::EnterInsertMode => {
+            if let Some(denied) = app.write_denied() {
+                app.status = Some(denied);
+                return None;
+            }
             app.mode = Mode::Insert;
             app.focus = Focus::Input;
             app.status = Some("Insert mode".into());
         }
+        Message::StartReaction => {
+            if app.focus == Focus::Messages && app.current_message().is_some() {
+                app.mode = Mode::Command;
+                app.focus = Focus::Input;
+                app.command_input = "react ".into();
+                app.command_cursor = app.command_input.chars().count();
+                app.completion_state = determine_completion_state(&app.command_input, &app.chats);
+                app.status = Some("Pick a reaction; the same one again removes it".into());
+            }
+        }
         Message::EnterCommandMode => {
             app.mode = Mode::Command;
             app.focus = Focus::Input;
             app.command_input.clear();
             app.command_cursor = 0;
+            app.history_recall = None;
 
             // FSM initial state - show all commands
-            app.completion_state = determine_completion_state("");
+            app.completion_state = determine_completion_state("", &app.chats);
 
             app.status = Some("Command mode".into());
         }
@@ -568,7 +1038,7 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                 let downloadable: Vec<AttachmentInfo> = msg
                     .attachments
                     .iter()
-                    .filter(|a| a.url.is_some())
+                    .filter(|a| a.is_downloadable())
                     .cloned()
                     .collect();
                 if downloadable.is_empty() {
@@ -580,6 +1050,47 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
             }
         }
 
+        Message::PreviewPhoto => {
+            if app.screen == Screen::Main
+                && app.focus == Focus::Messages
+                && let Some(msg) = app.current_message()
+            {
+                let photo = msg
+                    .attachments
+                    .iter()
+                    .find(|a| matches!(a.kind, AttachmentKind::Photo) && a.url.is_some());
+                match photo {
+                    None => app.status = Some("No photo in this message".into()),
+                    Some(photo) => {
+                        let url = photo.url.clone().unwrap_or_default();
+                        let inline = app.graphics.is_some();
+                        if inline {
+                            app.photo_preview = Some(PhotoPreview {
+                                title: photo.title.clone(),
+                                image: None,
+                            });
+                        } else {
+                            app.status = Some("Opening photo...".into());
+                        }
+                        app.send_action(AsyncAction::PreviewPhoto(url, inline));
+                    }
+                }
+            }
+        }
+        Message::PhotoPreviewLoaded(Ok(image)) => {
+            // Nothing to do if closed while loading
+            if let Some(preview) = &mut app.photo_preview {
+                preview.image = Some(image);
+            }
+        }
+        Message::PhotoPreviewLoaded(Err(e)) => {
+            app.photo_preview = None;
+            app.status = Some(e);
+        }
+        Message::PhotoPreviewClose => {
+            app.photo_preview = None;
+        }
+
         // Message actions
         Message::ReplyToMessage => {
             if app.screen == Screen::Main
@@ -588,63 +1099,239 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
             {
                 if msg.id == 0 {
                     app.status = Some("Cannot reply to unsent message".into());
+                } else if let Some(denied) = app.write_denied() {
+                    app.status = Some(denied);
+                } else if msg.is_service() {
+                    app.status = Some(SERVICE_MESSAGE_DENIED.into());
                 } else {
                     let preview = ReplyPreview {
                         from: msg.from_name.clone(),
                         text: truncate_str(&msg.text, 120),
                         attachments: msg.attachments.clone(),
+                        message_id: Some(msg.id),
+                        cmid: msg.cmid,
                     };
                     app.reply_to = Some((msg.id, preview));
                     app.mode = Mode::Insert;
                     app.focus = Focus::Input;
-                    app.status = Some(format!("Replying to {} (Esc to cancel)", msg.from_name));
+                    app.status = Some(format!("Replying to {}", msg.from_name));
                 }
             }
         }
         Message::DeleteMessage => {
             if app.screen == Screen::Main
                 && app.focus == Focus::Messages
-                && let Some(msg) = app.current_message().cloned()
+                && let Some(msg) = app.current_message()
             {
-                if !msg.is_outgoing {
-                    app.status = Some("Can only delete your own messages".into());
-                    return None;
-                }
                 if msg.id == 0 {
                     app.status = Some("Cannot delete message that is not sent yet".into());
                     return None;
                 }
-                if let Some(peer_id) = app.current_peer_id {
-                    app.status = Some("Deleting message...".into());
-                    app.send_action(AsyncAction::DeleteMessage(peer_id, msg.id, false));
+                if msg.is_service() {
+                    app.status = Some(SERVICE_MESSAGE_DENIED.into());
+                    return None;
                 }
+                let for_all = msg.can_delete_for_all(chrono_timestamp());
+                app.delete_prompt = Some(DeletePrompt::new(vec![msg.id], for_all));
             }
         }
+        Message::DeletePromptUp => {
+            if let Some(prompt) = &mut app.delete_prompt {
+                prompt.move_selection(false);
+            }
+        }
+        Message::DeletePromptDown => {
+            if let Some(prompt) = &mut app.delete_prompt {
+                prompt.move_selection(true);
+            }
+        }
+        Message::DeletePromptConfirm => {
+            let prompt = app.delete_prompt.take()?;
+            let for_all = match prompt.selected {
+                DeleteChoice::ForMe => false,
+                DeleteChoice::ForAll => true,
+                DeleteChoice::Cancel => return None,
+            };
+            if let [msg_id] = prompt.message_ids[..] {
+                let peer_id = app.current_peer_id?;
+                app.status = Some("Deleting message...".into());
+                app.send_action(AsyncAction::DeleteMessage(peer_id, msg_id, for_all));
+            } else {
+                app.status = Some(format!("Deleting {} messages...", prompt.message_ids.len()));
+                app.send_action(AsyncAction::DeleteMessages(prompt.message_ids, for_all));
+            }
+        }
+        Message::DeletePromptCancel => {
+            app.delete_prompt = None;
+        }
+        Message::OpenMessageMenu => {
+            if app.screen == Screen::Main
+                && app.focus == Focus::Messages
+                && let Some(msg) = app.current_message()
+            {
+                let actions = menu_actions(msg, chrono_timestamp());
+                if actions.is_empty() {
+                    app.status = Some("Nothing to do with this message".into());
+                } else {
+                    app.message_menu = Some(MessageMenu::new(actions));
+                }
+            }
+        }
+        Message::MessageMenuUp => {
+            if let Some(menu) = &mut app.message_menu {
+                menu.move_selection(false);
+            }
+        }
+        Message::MessageMenuDown => {
+            if let Some(menu) = &mut app.message_menu {
+                menu.move_selection(true);
+            }
+        }
+        Message::MessageMenuSelect => {
+            // The key of the action does the rest
+            let action = app.message_menu.take()?.current()?;
+            return Some(match action {
+                MenuAction::Reply => Message::ReplyToMessage,
+                MenuAction::Forward => Message::ForwardMessage,
+                MenuAction::Edit => Message::EditMessage,
+                MenuAction::Delete => Message::DeleteMessage,
+                MenuAction::Copy => Message::YankMessage,
+                MenuAction::Pin => Message::PinMessage,
+                MenuAction::React => Message::StartReaction,
+                MenuAction::Download => Message::DownloadAttachment,
+                MenuAction::OpenLink => Message::OpenLink,
+            });
+        }
+        Message::MessageMenuClose => {
+            app.message_menu = None;
+        }
         Message::EditMessage => {
             if app.screen == Screen::Main
                 && app.focus == Focus::Messages
                 && let Some(msg) = app.current_message()
             {
+                if msg.is_service() {
+                    app.status = Some(SERVICE_MESSAGE_DENIED.into());
+                    return None;
+                }
                 if !msg.is_outgoing {
                     app.status = Some("Can only edit your own messages".into());
                     return None;
                 }
-                app.input = msg.text.clone();
-                app.input_cursor = app.input.chars().count();
+                if msg.id != 0 && !is_editable(msg.timestamp, chrono_timestamp()) {
+                    app.status = Some(EDIT_TOO_OLD.into());
+                    return None;
+                }
+                let text = msg.text.clone();
+                app.edit_base_hash = Some(content_hash(&text));
+                app.input_cursor = text.chars().count();
+                app.input = text;
                 app.editing_message = Some(app.messages_scroll);
                 app.mode = Mode::Insert;
                 app.focus = Focus::Input;
                 app.status = Some("Editing message (not yet saved)".into());
             }
         }
+        Message::StartYank => {
+            app.awaiting_yank = true;
+            app.status = Some("y".into());
+        }
+        Message::CancelYank => {
+            app.awaiting_yank = false;
+            app.pending_register = None;
+            app.status = None;
+        }
         Message::YankMessage => {
+            app.awaiting_yank = false;
             if app.screen == Screen::Main
                 && app.focus == Focus::Messages
                 && let Some(msg) = app.current_message()
             {
-                app.status = Some(format!("Copied: {}", truncate_str(&msg.text, 50)));
+                let text = msg.text.clone();
+                let preview = truncate_str(&text, 50);
+                yank(app, &text, preview);
             }
         }
+        Message::YankLink => {
+            app.awaiting_yank = false;
+            if app.screen == Screen::Main
+                && app.focus == Focus::Messages
+                && let Some(msg) = app.current_message()
+            {
+                match message_urls(msg).into_iter().next() {
+                    Some(url) => {
+                        let preview = truncate_str(&url, 50);
+                        yank(app, &url, preview);
+                    }
+                    None => {
+                        app.pending_register = None;
+                        app.status = Some("No link in message".into());
+                    }
+                }
+            }
+        }
+        Message::YankConversation => {
+            app.awaiting_yank = false;
+            if app.screen == Screen::Main && app.focus == Focus::Messages {
+                if app.messages.is_empty() {
+                    app.pending_register = None;
+                    app.status = Some("No messages to copy".into());
+                    return None;
+                }
+                let text = conversation_text(&app.messages);
+                let preview = format!("{} messages", app.messages.len());
+                yank(app, &text, preview);
+            }
+        }
+        Message::StartVisual => {
+            if app.screen == Screen::Main
+                && app.focus == Focus::Messages
+                && let Some(msg) = app.current_message()
+            {
+                app.visual_anchor = Some(msg.id);
+                app.status = None;
+            }
+        }
+        Message::CancelVisual => {
+            app.visual_anchor = None;
+        }
+        Message::ForwardSelection => {
+            let range = app.visual_selection()?;
+            app.visual_anchor = None;
+            let ids: Vec<i64> = app.messages[range]
+                .iter()
+                .filter(|m| !m.is_service())
+                .map(|m| m.id)
+                .filter(|&id| id != 0)
+                .collect();
+            if ids.is_empty() {
+                app.status = Some("Cannot forward messages that are not sent yet".into());
+            } else {
+                open_forward(app, ids);
+            }
+        }
+        Message::DeleteSelection => {
+            let range = app.visual_selection()?;
+            app.visual_anchor = None;
+            let now = chrono_timestamp();
+            let sent: Vec<&ChatMessage> =
+                app.messages[range].iter().filter(|m| m.id != 0).collect();
+            if sent.is_empty() {
+                app.status = Some("Cannot delete messages that are not sent yet".into());
+                return None;
+            }
+            let for_all = sent.iter().all(|m| m.can_delete_for_all(now));
+            let ids = sent.iter().map(|m| m.id).collect();
+            app.delete_prompt = Some(DeletePrompt::new(ids, for_all));
+        }
+        Message::YankSelection => {
+            let range = app.visual_selection()?;
+            app.visual_anchor = None;
+            let selected = &app.messages[range];
+            let text = conversation_text(selected);
+            let preview = format!("{} messages", selected.len());
+            yank(app, &text, preview);
+        }
         Message::PinMessage => {
             if app.screen == Screen::Main
                 && app.focus == Focus::Messages
@@ -696,17 +1383,11 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
             {
                 if msg.id == 0 {
                     app.status = Some("Cannot forward message that is not sent yet".into());
+                } else if msg.is_service() {
+                    app.status = Some(SERVICE_MESSAGE_DENIED.into());
                 } else {
-                    let filtered = forward_filter(&app.chats, "");
-                    app.forward = Some(crate::state::ForwardState {
-                        source_message_id: msg.id,
-                        query: String::new(),
-                        filtered,
-                        selected: 0,
-                        comment: String::new(),
-                        stage: ForwardStage::SelectTarget,
-                    });
-                    app.status = Some("Select chat to forward (j/k, type to search)".into());
+                    let source_message_id = msg.id;
+                    open_forward(app, vec![source_message_id]);
                 }
             }
         }
@@ -782,10 +1463,10 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                         }
                     }
                     ForwardStage::EnterComment { peer_id, .. } => {
-                        let (comment, source_id) = if let Some(state) = app.forward.take() {
-                            (state.comment, state.source_message_id)
+                        let (comment, source_ids) = if let Some(state) = app.forward.take() {
+                            (state.comment, state.source_message_ids)
                         } else {
-                            (String::new(), fwd.source_message_id)
+                            (String::new(), fwd.source_message_ids)
                         };
 
                         // Optimistic placeholder
@@ -797,9 +1478,12 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                         app.messages.push(ChatMessage {
                             id: 0,
                             cmid: None,
-                            from_id: app.auth.user_id().unwrap_or(0),
+                            random_id: None,
+                            from_id: app.own_user_id().unwrap_or(0),
                             from_name: "You".into(),
+                            from_photo: None,
                             text,
+                            kind: MessageKind::Text,
                             timestamp: chrono_timestamp(),
                             is_outgoing: true,
                             is_read: false,
@@ -808,17 +1492,15 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                             delivery: DeliveryStatus::Pending,
                             attachments: Vec::new(),
                             reply: None,
-                            fwd_count: 1,
+                            fwd_count: source_ids.len(),
                             forwards: Vec::new(),
+                            reactions: Vec::new(),
+                            upload: None,
                         });
                         app.messages_scroll = app.messages.len().saturating_sub(1);
 
                         app.status = Some("Forwarding...".into());
-                        app.send_action(AsyncAction::SendForward(
-                            peer_id,
-                            vec![source_id],
-                            comment,
-                        ));
+                        app.send_action(AsyncAction::SendForward(peer_id, source_ids, comment));
                     }
                 }
             }
@@ -847,26 +1529,28 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
         }
 
         // Messages from VK events and async actions
-        Message::VkEvent(event) => return handle_vk_event(app, event),
+        Message::VkEvent(event) => {
+            if let Some(cache) = &app.cache {
+                cache.apply_event(&event);
+            }
+            return handle_vk_event(app, event);
+        }
         Message::SessionValidated { valid, error } => {
             if valid {
                 app.status = Some("Session validated".into());
                 app.is_loading = true;
                 app.chats_pagination.is_loading = true;
                 app.send_action(AsyncAction::LoadConversations(0));
-                app.send_action(AsyncAction::StartLongPoll);
+                app.start_long_poll();
             } else if let Some(err) = error {
-                if is_auth_error(&err) {
-                    let _ = app.auth.logout();
-                    app.vk_client = None;
-                    app.screen = Screen::Auth;
-                    app.status = Some("Session expired. Please authorize again.".into());
-                } else {
-                    app.status = Some(err);
-                }
+                app.status = Some(err);
                 app.is_loading = false;
             }
         }
+        Message::ProfileLoaded(profile) => {
+            app.status = Some(format!("Logged in as {}", profile.full_name()));
+            app.current_user = Some(profile);
+        }
         Message::ConversationsLoaded {
             chats,
             profiles,
@@ -874,6 +1558,9 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
             has_more,
         } => {
             app.is_loading = false;
+            if let Some(cache) = &app.cache {
+                cache.store_chats(&chats);
+            }
 
             // Append or replace chats based on offset
             if app.chats_pagination.offset == 0 {
@@ -883,6 +1570,7 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                 // Pagination - append
                 app.chats.extend(chats);
             }
+            app.sort_chats();
 
             // Update pagination state
             app.chats_pagination.offset = app.chats.len() as u32;
@@ -910,7 +1598,20 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
         } => {
             app.is_loading = false;
 
-            // Append or replace messages based on offset and overlap
+            // The first page replaces the cached messages shown meanwhile
+            let newest = app
+                .messages_pagination
+                .as_mut()
+                .is_some_and(|p| p.peer_id == peer_id && std::mem::take(&mut p.from_cache));
+            if newest {
+                app.messages.clear();
+            }
+            if let Some(cache) = &app.cache {
+                cache.reconcile(peer_id, &messages, newest);
+            }
+
+            // Append or replace messages based on offset and overlap
+            let mut grown = None;
             if let Some(pagination) = &app.messages_pagination {
                 // Always check for overlap first if we have existing messages
                 if !app.messages.is_empty() {
@@ -933,6 +1634,7 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                                 }
                             }
                             app.messages_scroll = current_last_scroll;
+                            grown = Some(Grown::Newer);
                         } else {
                             // Loading older messages - prepend only new ones
                             let _loaded_count = messages.len();
@@ -941,9 +1643,13 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                                 .filter(|m| !existing_ids.contains(&m.id))
                                 .collect();
                             let prepend_count = new_messages.len();
+                            let old_len = app.messages.len();
                             new_messages.append(&mut app.messages);
                             app.messages = new_messages;
-                            app.messages_scroll = app.messages_scroll.saturating_add(prepend_count);
+                            app.messages_scroll =
+                                anchor_insert(app.messages_scroll, old_len, 0, prepend_count)
+                                    .scroll;
+                            grown = Some(Grown::Older);
                         }
                     } else if pagination.offset == 0 {
                         // No overlap and offset=0 - replace all (first load)
@@ -952,10 +1658,13 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                     } else {
                         // No overlap - prepend older messages
                         let loaded_count = messages.len();
+                        let old_len = app.messages.len();
                         let mut new_messages = messages;
                         new_messages.append(&mut app.messages);
                         app.messages = new_messages;
-                        app.messages_scroll = app.messages_scroll.saturating_add(loaded_count);
+                        app.messages_scroll =
+                            anchor_insert(app.messages_scroll, old_len, 0, loaded_count).scroll;
+                        grown = Some(Grown::Older);
                     }
                 } else {
                     // Empty - first load
@@ -967,7 +1676,12 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                 if let Some(pagination) = &mut app.messages_pagination {
                     pagination.offset = app.messages.len() as u32;
                     pagination.total_count = Some(total_count);
-                    pagination.has_more = has_more;
+                    // A full page of newer messages may not reach the latest
+                    if grown == Some(Grown::Newer) {
+                        pagination.has_newer = has_more;
+                    } else {
+                        pagination.has_more = has_more;
+                    }
                     pagination.is_loading = false;
 
                     // Update first and last cmid from current messages
@@ -996,6 +1710,19 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                             );
                         }
                     }
+
+                    if let Some(grown) = grown {
+                        let dropped = trim_window(
+                            &mut app.messages,
+                            &mut app.messages_scroll,
+                            pagination,
+                            app.config.message_window(),
+                            grown,
+                        );
+                        if dropped > 0 {
+                            tracing::debug!("Dropped {} messages out of the window", dropped);
+                        }
+                    }
                 }
             } else {
                 // No pagination state - first load
@@ -1008,6 +1735,22 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                 if let Some(chat) = app.chats.iter_mut().find(|c| c.id == peer_id) {
                     chat.unread_count = 0;
                 }
+                // History may be newer than the chat list (e.g. sent elsewhere)
+                if let Some(newest) = app.messages.iter().max_by_key(|m| m.timestamp) {
+                    let kind = MessageKind::of_loaded(&newest.attachments, newest.fwd_count > 0);
+                    let text = match kind.placeholder() {
+                        Some(placeholder) if newest.text.is_empty() => placeholder.to_string(),
+                        _ => newest.text.clone(),
+                    };
+                    let from = vk_core::preview_sender(
+                        peer_id,
+                        newest.is_outgoing,
+                        newest.from_id,
+                        |_| newest.from_name.clone(),
+                    );
+                    let timestamp = newest.timestamp;
+                    app.record_chat_activity(peer_id, &text, from, kind, timestamp, false);
+                }
                 for msg in app.messages.iter_mut() {
                     if !msg.is_outgoing {
                         msg.is_read = true;
@@ -1021,22 +1764,97 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
             }
 
             // If we have a target message, scroll to it
-            if let Some(target_id) = app.target_message_id
-                && let Some(pos) = app.messages.iter().position(|m| m.id == target_id)
-            {
-                app.messages_scroll = pos;
-                app.target_message_id = None;
+            if let Some(target_id) = app.target_message_id.take() {
+                if let Some(pos) = app.messages.iter().position(|m| m.id == target_id) {
+                    app.messages_scroll = pos;
+                    app.highlighted_message = Some((target_id, Instant::now() + JUMP_HIGHLIGHT));
+                    app.status = app.search_hits.as_ref().map(|hits| {
+                        format!(
+                            "Result {} of {} (n/N: next/previous)",
+                            hits.current + 1,
+                            hits.results.len()
+                        )
+                    });
+                } else {
+                    // Deleted since it was found: show the latest history instead
+                    app.messages.clear();
+                    app.messages_pagination = Some(MessagesPagination::new(peer_id));
+                    app.send_action(AsyncAction::LoadMessages(peer_id, 0));
+                    app.status = Some("Message not found (deleted?), showing latest".into());
+                }
             }
         }
-        Message::MessageSent(msg_id, cmid) => {
-            if let Some(msg) = app.messages.last_mut()
-                && msg.id == 0
+        Message::MessageSent(msg_id, cmid, random_id) => {
+            confirm_sent(&mut app.messages, msg_id, cmid, random_id);
+            app.messages_scroll = app
+                .messages_scroll
+                .min(app.messages.len().saturating_sub(1));
+            app.send_action(AsyncAction::FetchMessageById(msg_id));
+        }
+        Message::DownloadProgress {
+            title,
+            received,
+            total,
+        } => {
+            app.status = Some(format!(
+                "Downloading {}",
+                download::progress_text(&title, received, total)
+            ));
+        }
+        Message::ExportProgress(done, total) => {
+            if app.history_export.is_some() {
+                app.status = Some(format!(
+                    "{} (Esc to cancel)",
+                    export::progress_text(done, total)
+                ));
+            }
+        }
+        Message::ExportFinished(result) => {
+            if let Some(job) = app.history_export.take() {
+                app.status = Some(match result {
+                    Ok(count) => format!(
+                        "Exported {} messages to {}",
+                        count,
+                        download::display_path(&job.path)
+                    ),
+                    Err(e) => e,
+                });
+            }
+        }
+        Message::CancelExport => {
+            if let Some(job) = app.history_export.take() {
+                let _ = job.cancel.send(true);
+                app.status = Some("Export cancelled".into());
+            }
+        }
+        Message::DownloadFinished { path } => {
+            app.status = Some(format!(
+                "Saved to {} (o to open)",
+                download::display_path(&path)
+            ));
+            app.last_download = Some(path);
+        }
+        Message::UploadProgress(local_id, bytes_sent, total) => {
+            set_upload_progress(&mut app.messages, local_id, bytes_sent, total);
+        }
+        Message::UploadFailed(local_id, err) => {
+            app.is_loading = false;
+            fail_upload(&mut app.messages, local_id);
+            app.status = Some(format!("Upload failed: {}", err));
+        }
+        Message::UploadPartlyFailed(local_id, failed, err) => {
+            drop_failed_uploads(&mut app.messages, local_id, &failed);
+            if let Some(msg) = app
+                .messages
+                .iter_mut()
+                .rev()
+                .find(|m| m.upload.is_some_and(|u| u.local_id == local_id))
+                && let Some(kind) = msg.attachments.first().map(|a| a.kind.clone())
             {
-                msg.id = msg_id;
-                msg.cmid = Some(cmid);
-                msg.delivery = DeliveryStatus::Sent;
+                let titles: Vec<String> = msg.attachments.iter().map(|a| a.title.clone()).collect();
+                msg.text = upload_text(&kind, &titles);
             }
-            app.send_action(AsyncAction::FetchMessageById(msg_id));
+            app.status = Some(format!("{}; sending the rest", err));
         }
         Message::MessageEdited(msg_id) => {
             app.status = Some("Message edited".into());
@@ -1049,6 +1867,9 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
         }
         Message::MessageDeleted(msg_id) => {
             app.status = Some("Message deleted".into());
+            if let Some(cache) = &app.cache {
+                cache.delete_message(msg_id);
+            }
             if let Some(pos) = app.messages.iter().position(|m| m.id == msg_id) {
                 app.messages.remove(pos);
                 if app.messages_scroll >= app.messages.len() && app.messages_scroll > 0 {
@@ -1056,6 +1877,27 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                 }
             }
         }
+        Message::MessagesDeleted { deleted, failed } => {
+            app.status = Some(if failed.is_empty() {
+                format!("Deleted {} messages", deleted.len())
+            } else {
+                format!(
+                    "Deleted {} of {} messages, {} failed",
+                    deleted.len(),
+                    deleted.len() + failed.len(),
+                    failed.len()
+                )
+            });
+            if let Some(cache) = &app.cache {
+                for &msg_id in &deleted {
+                    cache.delete_message(msg_id);
+                }
+            }
+            app.messages.retain(|m| !deleted.contains(&m.id));
+            app.messages_scroll = app
+                .messages_scroll
+                .min(app.messages.len().saturating_sub(1));
+        }
         Message::MessageDetailsFetched {
             message_id,
             cmid,
@@ -1065,7 +1907,21 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
             reply,
             fwd_count,
             forwards,
+            reactions,
         } => {
+            // Message we're editing was changed elsewhere: ask before going on
+            if let Some(server_text) = &text
+                && let Some(base_hash) = app.edit_base_hash
+                && app
+                    .editing_message
+                    .and_then(|idx| app.messages.get(idx))
+                    .is_some_and(|m| m.id == message_id)
+                && is_conflict(base_hash, server_text)
+            {
+                let local_text = app.input.clone();
+                open_edit_conflict(app, message_id, local_text, server_text.clone());
+            }
+
             if let Some(msg) = app.messages.iter_mut().find(|m| m.id == message_id) {
                 if let Some(cmid) = cmid {
                     msg.cmid = Some(cmid);
@@ -1088,24 +1944,113 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                 if let Some(fwds) = forwards {
                     msg.forwards = fwds;
                 }
+                if let Some(reactions) = reactions {
+                    msg.reactions = reactions;
+                }
+                if let Some(cache) = &app.cache
+                    && let Some(peer_id) = app.current_peer_id
+                {
+                    cache.upsert_message(peer_id, msg);
+                }
             }
         }
         Message::Error(err) => {
             app.is_loading = false;
-            if is_auth_error(&err) {
-                let _ = app.auth.logout();
-                app.vk_client = None;
-                app.screen = Screen::Auth;
-                app.focus = Focus::ChatList;
+            app.status = Some(format!("Error: {}", err));
+        }
+        Message::EditConflict {
+            message_id,
+            server_text,
+        } => {
+            // Local text was applied optimistically on submit
+            let local_text = app
+                .messages
+                .iter()
+                .find(|m| m.id == message_id)
+                .map(|m| m.text.clone())
+                .unwrap_or_default();
+            open_edit_conflict(app, message_id, local_text, server_text);
+        }
+        Message::EditConflictOverwrite => {
+            if let Some(conflict) = app.edit_conflict.take() {
+                finish_editing(app);
+                if let Some(m) = app
+                    .messages
+                    .iter_mut()
+                    .find(|m| m.id == conflict.message_id)
+                {
+                    m.text = conflict.local_text.clone();
+                }
+                app.status = Some("Editing...".into());
+                app.send_action(AsyncAction::EditMessage(
+                    conflict.peer_id,
+                    conflict.message_id,
+                    conflict.cmid,
+                    conflict.local_text,
+                    None,
+                ));
+            }
+        }
+        Message::CrossChatSendConfirm => {
+            if let Some(pending) = app.cross_chat_send.take()
+                && app.current_peer_id == Some(pending.peer_id)
+            {
+                app.input_peer_id = Some(pending.peer_id);
+                return Some(Message::InputSubmit);
+            }
+        }
+        Message::CrossChatSendCancel => {
+            app.cross_chat_send = None;
+            app.status = Some("Not sent".into());
+        }
+        Message::EditConflictTakeTheirs => {
+            if let Some(conflict) = app.edit_conflict.take() {
+                finish_editing(app);
+                if let Some(m) = app
+                    .messages
+                    .iter_mut()
+                    .find(|m| m.id == conflict.message_id)
+                {
+                    m.text = conflict.server_text;
+                }
+                app.status = Some("Kept the version edited elsewhere".into());
+            }
+        }
+        Message::EditConflictMerge => {
+            if let Some(conflict) = app.edit_conflict.take() {
+                let Some(idx) = app
+                    .messages
+                    .iter()
+                    .position(|m| m.id == conflict.message_id)
+                else {
+                    finish_editing(app);
+                    return None;
+                };
+                app.messages[idx].text = conflict.server_text.clone();
+                app.edit_base_hash = Some(content_hash(&conflict.server_text));
+                app.editing_message = Some(idx);
+                app.input = conflict.local_text;
+                app.input_cursor = app.input.chars().count();
                 app.mode = Mode::Insert;
-                app.chats.clear();
-                app.messages.clear();
-                app.current_peer_id = None;
-                app.status = Some("Authorization failed. Please re-authenticate.".into());
-            } else {
-                app.status = Some(format!("Error: {}", err));
+                app.focus = Focus::Input;
+                app.status =
+                    Some("Message shows the new server text; edit yours and resend".into());
             }
         }
+        Message::AuthExpired => {
+            // Chats, messages and drafts stay; actions wait for a new token
+            app.is_loading = false;
+            let _ = app.auth.logout();
+            app.set_client(None);
+            app.long_poll_shutdown = None;
+            app.connection = ConnectionState::Offline;
+            // Dropping the sender stops an export; its pages would fail anyway
+            app.history_export = None;
+            app.token_input.clear();
+            app.token_cursor = 0;
+            app.session_expired = app.screen == Screen::Main;
+            app.status = Some("Session expired. Please authorize again.".into());
+        }
         Message::SendFailed(err) => {
             app.is_loading = false;
             if let Some(last) = app.messages.last_mut()
@@ -1115,6 +2060,72 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
             }
             app.status = Some(format!("Failed to send: {}", err));
         }
+        Message::CaptchaRequired {
+            sid,
+            img_url,
+            retry,
+        } => {
+            app.captcha = Some(CaptchaPrompt {
+                sid,
+                img_url,
+                retry,
+                key: String::new(),
+            });
+            app.status = Some("VK asks to solve a captcha before sending".into());
+        }
+        Message::CaptchaChar(c) => {
+            if let Some(captcha) = &mut app.captcha {
+                captcha.key.push(c);
+            }
+        }
+        Message::CaptchaBackspace => {
+            if let Some(captcha) = &mut app.captcha {
+                captcha.key.pop();
+            }
+        }
+        Message::CaptchaOpenImage => {
+            if let Some(captcha) = &app.captcha
+                && let Err(e) = open::that(&captcha.img_url)
+            {
+                app.status = Some(format!("Failed to open browser: {}", e));
+            }
+        }
+        Message::CaptchaSubmit => {
+            if let Some(captcha) = app.captcha.take_if(|c| !c.key.trim().is_empty()) {
+                app.send_action(AsyncAction::AnswerCaptcha(
+                    captcha.sid,
+                    captcha.key.trim().to_string(),
+                ));
+                app.send_action(captcha.retry.action());
+                app.status = Some("Sending...".into());
+            }
+        }
+        Message::CaptchaCancel => {
+            if app.captcha.take().is_some() {
+                return Some(Message::SendFailed("captcha not solved".into()));
+            }
+        }
+        Message::LongPollKeyExpired => {
+            app.stats.record_key_failure();
+        }
+        Message::LongPollReconnecting(attempt) => {
+            app.connection = ConnectionState::Reconnecting { attempt };
+        }
+        Message::LongPollDegraded => {
+            app.connection = ConnectionState::Polling;
+        }
+        Message::Reconnect => {
+            if app.long_poll_shutdown.is_some() {
+                app.long_poll_reconnect.notify_one();
+                app.status = Some("Reconnecting...".into());
+            } else {
+                app.status = Some("Not logged in".into());
+            }
+        }
+        Message::PossibleConcurrentSession => {
+            app.stats.record_concurrent_session();
+            app.status = Some(CONCURRENT_SESSION_WARNING.into());
+        }
         // Search / UI
         Message::StartSearch => {
             if app.screen == Screen::Main {
@@ -1133,7 +2144,7 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
             if app.screen == Screen::Main && app.focus == Focus::ChatList {
                 let mut filter = crate::state::ChatFilter::new();
                 // Initialize with all chats
-                filter.filtered_indices = crate::search::filter_chats(&app.chats, "");
+                filter.filtered_indices = vk_core::fuzzy::filter_chats(&app.chats, "");
                 app.chat_filter = Some(filter);
                 // Reset selection to first chat
                 app.selected_chat = 0;
@@ -1145,7 +2156,7 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                 crate::input::insert_char_at(&mut filter.query, filter.cursor, c);
                 filter.cursor += 1;
                 // Update filtered indices
-                filter.filtered_indices = crate::search::filter_chats(&app.chats, &filter.query);
+                filter.filtered_indices = vk_core::fuzzy::filter_chats(&app.chats, &filter.query);
                 // Reset selection to first result
                 app.selected_chat = 0;
                 app.status = Some(format!(
@@ -1162,7 +2173,7 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                 filter.cursor -= 1;
                 crate::input::remove_char_at(&mut filter.query, filter.cursor);
                 // Update filtered indices
-                filter.filtered_indices = crate::search::filter_chats(&app.chats, &filter.query);
+                filter.filtered_indices = vk_core::fuzzy::filter_chats(&app.chats, &filter.query);
                 // Reset selection to first result
                 app.selected_chat = 0;
                 app.status = Some(format!(
@@ -1183,10 +2194,22 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
             app.global_search = Some(search);
             app.status = Some("Global search: (type to search, Esc to cancel)".into());
         }
+        Message::StartGrep(query) => {
+            let mut search = crate::state::GlobalSearch::new();
+            search.cursor = query.chars().count();
+            search.query = query;
+            search.offline = true;
+            app.global_search = Some(search);
+            start_grep(app);
+        }
         Message::GlobalSearchChar(c) => {
             if let Some(search) = &mut app.global_search {
                 crate::input::insert_char_at(&mut search.query, search.cursor, c);
                 search.cursor += 1;
+                if search.offline {
+                    start_grep(app);
+                    return None;
+                }
                 // Trigger search with debounce
                 search.is_loading = true;
                 let query = search.query.clone();
@@ -1201,7 +2224,9 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
             {
                 search.cursor -= 1;
                 crate::input::remove_char_at(&mut search.query, search.cursor);
-                if search.query.is_empty() {
+                if search.offline {
+                    start_grep(app);
+                } else if search.query.is_empty() {
                     search.results.clear();
                     search.total_count = 0;
                     search.selected = 0;
@@ -1232,30 +2257,58 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
             }
         }
         Message::GlobalSearchSelect => {
-            if let Some(search) = &app.global_search
-                && let Some(result) = search.results.get(search.selected)
+            if let Some(search) = app.global_search.take()
+                && let Some(result) = search.results.get(search.selected).cloned()
             {
-                let peer_id = result.peer_id;
-                let message_id = result.message_id;
-
-                // Close search
-                app.global_search = None;
-
-                // Open chat and load messages around the found message
-                app.current_peer_id = Some(peer_id);
-                app.messages.clear();
-                app.target_message_id = Some(message_id);
-                app.is_loading = true;
-                app.messages_pagination = Some(crate::state::MessagesPagination::new(peer_id));
-                if let Some(pagination) = &mut app.messages_pagination {
-                    pagination.is_loading = true;
+                app.search_hits = Some(SearchHits {
+                    results: search.results,
+                    current: search.selected,
+                });
+                open_search_hit(app, &result);
+            }
+        }
+        Message::GrepResults {
+            query,
+            results,
+            done,
+        } => {
+            if let Some(search) = &mut app.global_search
+                && search.offline
+                && search.query == query
+            {
+                search.results.extend(results);
+                search.total_count = search.results.len() as u32;
+                search.is_loading = !done;
+                if done {
+                    app.status = Some(format!(
+                        "Found {} messages for '{}' (offline)",
+                        search.total_count, search.query
+                    ));
                 }
-                app.send_action(AsyncAction::LoadMessagesAround(peer_id, message_id));
-                app.send_action(AsyncAction::MarkAsRead(peer_id));
-                app.status = Some("Loading chat...".to_string());
-                app.focus = Focus::Messages;
             }
         }
+        Message::NextSearchHit | Message::PrevSearchHit => {
+            let forward = matches!(msg, Message::NextSearchHit);
+            let Some(hits) = &mut app.search_hits else {
+                app.status = Some("No search results (Ctrl+F to search)".into());
+                return None;
+            };
+            let next = if forward {
+                hits.current + 1
+            } else {
+                hits.current.wrapping_sub(1)
+            };
+            let Some(result) = hits.results.get(next).cloned() else {
+                app.status = Some(if forward {
+                    "Last search result".into()
+                } else {
+                    "First search result".into()
+                });
+                return None;
+            };
+            hits.current = next;
+            open_search_hit(app, &result);
+        }
         Message::SearchResultsLoaded {
             results,
             total_count,
@@ -1271,6 +2324,340 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
                 ));
             }
         }
+        Message::ShowSenderProfile => {
+            if app.focus == Focus::Messages
+                && let Some(msg) = app.current_message()
+            {
+                let from_id = msg.from_id;
+                app.open_whois(from_id);
+            }
+        }
+        Message::UserProfileLoaded(profile) => {
+            if let Some(whois) = &mut app.whois
+                && whois.user_id == profile.id
+            {
+                whois.profile = Some(profile);
+            }
+        }
+        Message::UserProfileFailed(error) => {
+            app.whois = None;
+            app.status = Some(error);
+        }
+        Message::WhoisClose => {
+            app.whois = None;
+        }
+        Message::ShowReaders => {
+            if app.focus == Focus::Messages
+                && let Some(peer_id) = app.current_peer_id
+                && let Some(msg) = app.current_message()
+            {
+                let (is_outgoing, cmid) = (msg.is_outgoing, msg.cmid);
+                if !vk_api::is_chat_peer(peer_id) {
+                    // DMs keep the plain ✓✓ mark
+                    app.status = Some("Readers are listed in group chats only".into());
+                } else if !is_outgoing {
+                    app.status = Some("Only your own messages have read receipts".into());
+                } else if let Some(cmid) = cmid {
+                    app.open_readers(peer_id, cmid);
+                } else {
+                    app.status = Some("Message is not sent yet".into());
+                }
+            }
+        }
+        Message::ReadPeersLoaded(readers) => {
+            if let Some(view) = &mut app.read_by
+                && view.peer_id == readers.peer_id
+                && view.cmid == readers.cmid
+            {
+                view.readers = Some(readers);
+            }
+        }
+        Message::ReadPeersFailed(error) => {
+            app.read_by = None;
+            app.status = Some(error);
+        }
+        Message::ReadersClose => {
+            app.read_by = None;
+        }
+        Message::StatsClose => {
+            app.show_stats = false;
+        }
+        Message::LogViewClose => {
+            app.log_view = None;
+        }
+        Message::LogViewScroll(delta) => {
+            if let Some(view) = &mut app.log_view {
+                let oldest = view.lines.len().saturating_sub(1);
+                view.from_bottom = if delta < 0 {
+                    view.from_bottom
+                        .saturating_add(delta.unsigned_abs())
+                        .min(oldest)
+                } else {
+                    view.from_bottom.saturating_sub(delta as usize)
+                };
+            }
+        }
+        Message::StartRegister => {
+            app.awaiting_register = true;
+            app.status = Some("\"".into());
+        }
+        Message::SelectRegister(name) => {
+            app.awaiting_register = false;
+            match name {
+                Some(name) if Registers::is_valid_name(name) => {
+                    app.pending_register = Some(name);
+                    app.status = Some(format!("\"{}", name));
+                }
+                Some(name) => {
+                    app.pending_register = None;
+                    app.status = Some(format!("Invalid register: {}", name));
+                }
+                None => {
+                    app.pending_register = None;
+                    app.status = None;
+                }
+            }
+        }
+        Message::PasteRegister => {
+            if app.screen == Screen::Main && app.focus == Focus::Input {
+                let register = app.pending_register.take();
+                if app.input.is_empty() {
+                    app.input_peer_id = app.current_peer_id;
+                }
+                if !app
+                    .registers
+                    .paste(register, &mut app.input, &mut app.input_cursor)
+                {
+                    app.status = Some(match register {
+                        Some(name) => format!("Register \"{} is empty", name),
+                        None => "Nothing yanked yet".into(),
+                    });
+                }
+            }
+        }
+        Message::RegistersClose => {
+            app.show_registers = false;
+        }
+        Message::MembersLoaded {
+            peer_id,
+            offset,
+            members,
+            total_count,
+        } => {
+            if let Some(view) = &mut app.members
+                && view.peer_id == peer_id
+                && view.members.len() == offset as usize
+            {
+                view.members.extend(members);
+                view.total = Some(total_count);
+                view.is_loading = false;
+            }
+        }
+        Message::MembersFailed(error) => {
+            app.members = None;
+            app.status = Some(error);
+        }
+        Message::MembersUp => {
+            if let Some(view) = &mut app.members {
+                view.selected = view.selected.saturating_sub(1);
+            }
+        }
+        Message::MembersDown => {
+            if let Some(view) = &mut app.members
+                && view.selected + 1 < view.members.len()
+            {
+                view.selected += 1;
+            }
+            app.load_more_members();
+        }
+        Message::MembersClose => {
+            app.members = None;
+        }
+        Message::LeaveChatConfirm => {
+            if let Some(peer_id) = app.leave_chat.take() {
+                match app.own_user_id() {
+                    Some(user_id) => {
+                        app.send_action(AsyncAction::LeaveChat(peer_id, user_id));
+                        app.status = Some("Leaving chat...".into());
+                    }
+                    None => app.status = Some("Unknown own user id".into()),
+                }
+            }
+        }
+        Message::LeaveChatCancel => {
+            app.leave_chat = None;
+        }
+        Message::ChatLeft(peer_id) => {
+            app.remove_chat(peer_id);
+            app.status = Some("Left the chat".into());
+        }
+        Message::DeleteChat => {
+            if app.screen == Screen::Main
+                && app.focus == Focus::ChatList
+                && let Some(chat) = app.current_chat()
+            {
+                app.delete_chat = Some(chat.id);
+            }
+        }
+        Message::DeleteChatConfirm => {
+            if let Some(peer_id) = app.delete_chat.take() {
+                app.send_action(AsyncAction::DeleteConversation(peer_id));
+                app.status = Some("Deleting conversation...".into());
+            }
+        }
+        Message::DeleteChatCancel => {
+            app.delete_chat = None;
+        }
+        Message::ChatMuteChanged { peer_id, muted } => {
+            vk_core::set_chat_muted(&mut app.chats, peer_id, muted);
+            app.status = Some(if muted {
+                "Notifications off".into()
+            } else {
+                "Notifications on".into()
+            });
+        }
+        Message::TogglePinChat => {
+            if app.screen == Screen::Main
+                && app.focus == Focus::ChatList
+                && let Some(chat) = app.current_chat()
+            {
+                let action = AsyncAction::SetChatPinned(chat.id, !chat.is_pinned);
+                app.send_action(action);
+            }
+        }
+        Message::ChatPinChanged { peer_id, pinned } => {
+            app.set_chat_pinned(peer_id, pinned);
+            app.status = Some(if pinned {
+                "Chat pinned".into()
+            } else {
+                "Chat unpinned".into()
+            });
+        }
+        Message::ConversationDeleted(peer_id) => {
+            if let Some(cache) = &app.cache {
+                cache.delete_chat(peer_id);
+            }
+            if let Some(chat) = app.remove_chat(peer_id) {
+                app.deleted_chats.insert(peer_id, chat);
+            }
+            app.status = Some("Conversation deleted".into());
+        }
+        Message::FriendsLoaded(friends) => {
+            if let Some(view) = &mut app.new_chat {
+                for (id, name) in friends {
+                    if !view.people.iter().any(|(known, _)| *known == id) {
+                        view.people.push((id, name));
+                    }
+                }
+                view.is_loading = false;
+            }
+        }
+        Message::NewChatUp => {
+            if let Some(view) = &mut app.new_chat {
+                view.selected = view.selected.saturating_sub(1);
+            }
+        }
+        Message::NewChatDown => {
+            if let Some(view) = &mut app.new_chat
+                && view.selected + 1 < view.people.len()
+            {
+                view.selected += 1;
+            }
+        }
+        Message::NewChatToggle => {
+            if let Some(view) = &mut app.new_chat {
+                view.toggle();
+            }
+        }
+        Message::NewChatConfirm => {
+            if let Some(view) = &app.new_chat {
+                if view.picked.is_empty() {
+                    app.status = Some("Pick at least one user with Space".into());
+                } else {
+                    let action = AsyncAction::CreateChat(view.picked.clone(), view.title.clone());
+                    app.send_action(action);
+                    app.new_chat = None;
+                    app.status = Some("Creating chat...".into());
+                }
+            }
+        }
+        Message::NewChatClose => {
+            app.new_chat = None;
+        }
+        Message::ChatInfoLoaded(info) => {
+            app.chat_infos.insert(info.peer_id, info);
+        }
+        Message::ChatInfoFailed(error) => {
+            app.chat_info = None;
+            app.status = Some(error);
+        }
+        Message::ChatInfoRefresh => {
+            if let Some(peer_id) = app.chat_info {
+                app.open_chat_info(peer_id, true);
+            }
+        }
+        Message::ChatInfoClose => {
+            app.chat_info = None;
+        }
+        Message::ChatPhotoUploading(path) => {
+            app.status = Some(format!("Uploading chat photo {}...", path));
+        }
+        Message::ChatSettingsChanged {
+            peer_id,
+            title,
+            photo_url,
+        } => {
+            if let Some(url) = &photo_url {
+                vk_core::set_chat_photo(&mut app.chats, peer_id, url);
+            }
+            app.status = Some(match title {
+                Some(title) => {
+                    vk_core::rename_chat(&mut app.chats, peer_id, &title);
+                    format!("Chat renamed to '{}'", title)
+                }
+                None => "Chat photo updated".into(),
+            });
+        }
+        Message::ChatCreated { peer_id, title } => {
+            if !app.chats.iter().any(|c| c.id == peer_id) {
+                app.chats.insert(
+                    0,
+                    Chat {
+                        id: peer_id,
+                        title: title.clone(),
+                        last_message: String::new(),
+                        last_message_from: None,
+                        last_message_kind: MessageKind::Text,
+                        last_message_time: chrono_timestamp(),
+                        unread_count: 0,
+                        is_online: false,
+                        photo_url: None,
+                        is_muted: false,
+                        is_pinned: false,
+                        can_write: true,
+                        cannot_write_reason: None,
+                        kind: ChatKind::of_peer(peer_id),
+                        last_seen: None,
+                    },
+                );
+            }
+            app.chat_filter = None;
+            app.selected_chat = app.chats.iter().position(|c| c.id == peer_id).unwrap_or(0);
+            app.open_chat(peer_id, &title);
+        }
+        Message::UsersLoaded { requested, users } => {
+            app.profile_warmup.finish(&requested);
+            refresh_names(&mut app.chats, &mut app.messages, &users);
+            for user in users {
+                app.users.insert(user.id, user);
+            }
+            if let Some(fwd) = &mut app.forward
+                && matches!(fwd.stage, ForwardStage::SelectTarget)
+            {
+                fwd.filtered = forward_filter(&app.chats, &fwd.query);
+                fwd.selected = fwd.selected.min(fwd.filtered.len().saturating_sub(1));
+            }
+        }
     }
 
     None
@@ -1278,116 +2665,27 @@ pub fn update(app: &mut App, msg: Message) -> Option<Message> {
 
 fn handle_send_command(app: &mut App, peer_id: i64, cmd: SendCommand) -> Option<Message> {
     match cmd {
-        SendCommand::File(path) => {
-            let title = std::path::Path::new(&path)
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("file")
-                .to_string();
-
-            app.messages.push(ChatMessage {
-                id: 0,
-                cmid: None,
-                from_id: app.auth.user_id().unwrap_or(0),
-                from_name: "You".into(),
-                text: format!("[file] {}", title),
-                timestamp: chrono_timestamp(),
-                is_outgoing: true,
-                is_read: false,
-                is_edited: false,
-                is_pinned: false,
-                delivery: DeliveryStatus::Pending,
-                attachments: vec![AttachmentInfo {
-                    kind: AttachmentKind::Doc,
-                    title: title.clone(),
-                    url: None,
-                    thumbnail_url: None,
-                    size: None,
-                    subtitle: None,
-                }],
-                reply: None,
-                fwd_count: 0,
-                forwards: Vec::new(),
-            });
-            app.messages_scroll = app.messages.len().saturating_sub(1);
-            app.input.clear();
-            app.input_cursor = 0;
-            app.send_action(AsyncAction::SendDoc(peer_id, path));
+        SendCommand::File(paths) | SendCommand::Image(paths) if paths.len() > MAX_ATTACHMENTS => {
+            app.status = Some(format!("At most {} files per message", MAX_ATTACHMENTS));
             None
         }
-        SendCommand::Image(path) => {
-            let title = std::path::Path::new(&path)
-                .file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("image")
-                .to_string();
-
-            app.messages.push(ChatMessage {
-                id: 0,
-                cmid: None,
-                from_id: app.auth.user_id().unwrap_or(0),
-                from_name: "You".into(),
-                text: format!("[image] {}", title),
-                timestamp: chrono_timestamp(),
-                is_outgoing: true,
-                is_read: false,
-                is_edited: false,
-                is_pinned: false,
-                delivery: DeliveryStatus::Pending,
-                attachments: vec![AttachmentInfo {
-                    kind: AttachmentKind::Photo,
-                    title: title.clone(),
-                    url: None,
-                    thumbnail_url: None,
-                    size: None,
-                    subtitle: None,
-                }],
-                reply: None,
-                fwd_count: 0,
-                forwards: Vec::new(),
-            });
-            app.messages_scroll = app.messages.len().saturating_sub(1);
-            app.input.clear();
-            app.input_cursor = 0;
-            app.send_action(AsyncAction::SendPhoto(peer_id, path));
+        SendCommand::File(paths) => {
+            let (local_id, random_id) = push_pending_upload(app, AttachmentKind::Doc, &paths);
+            app.send_action(AsyncAction::SendDoc(peer_id, paths, local_id, random_id));
+            None
+        }
+        SendCommand::Image(paths) => {
+            let (local_id, random_id) = push_pending_upload(app, AttachmentKind::Photo, &paths);
+            app.send_action(AsyncAction::SendPhoto(peer_id, paths, local_id, random_id));
             None
         }
         SendCommand::ImageClipboard => match read_clipboard_image() {
             Ok(path) => {
-                let title = path
-                    .file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("clipboard.png")
-                    .to_string();
-                app.messages.push(ChatMessage {
-                    id: 0,
-                    cmid: None,
-                    from_id: app.auth.user_id().unwrap_or(0),
-                    from_name: "You".into(),
-                    text: format!("[image] {}", title),
-                    timestamp: chrono_timestamp(),
-                    is_outgoing: true,
-                    is_read: false,
-                    is_edited: false,
-                    is_pinned: false,
-                    delivery: DeliveryStatus::Pending,
-                    attachments: vec![AttachmentInfo {
-                        kind: AttachmentKind::Photo,
-                        title: title.clone(),
-                        url: None,
-                        thumbnail_url: None,
-                        size: None,
-                        subtitle: None,
-                    }],
-                    reply: None,
-                    fwd_count: 0,
-                    forwards: Vec::new(),
-                });
-                app.messages_scroll = app.messages.len().saturating_sub(1);
-                app.input.clear();
-                app.input_cursor = 0;
                 if let Some(path_str) = path.to_str() {
-                    app.send_action(AsyncAction::SendPhoto(peer_id, path_str.to_string()));
+                    let paths = vec![path_str.to_string()];
+                    let (local_id, random_id) =
+                        push_pending_upload(app, AttachmentKind::Photo, &paths);
+                    app.send_action(AsyncAction::SendPhoto(peer_id, paths, local_id, random_id));
                 }
                 None
             }
@@ -1399,6 +2697,66 @@ fn handle_send_command(app: &mut App, peer_id: i64, cmd: SendCommand) -> Option<
     }
 }
 
+/// Show files being sent as a pending message listing all of them;
+/// returns the upload id their progress is reported with and the
+/// `random_id` the message is sent with
+fn push_pending_upload(app: &mut App, kind: AttachmentKind, paths: &[String]) -> (u64, i64) {
+    let titles: Vec<String> = paths
+        .iter()
+        .map(|p| upload::file_title(std::path::Path::new(p)))
+        .collect();
+    let local_id = app.next_upload_id();
+    let random_id = vk_api::new_random_id();
+
+    app.messages.push(ChatMessage {
+        id: 0,
+        cmid: None,
+        random_id: Some(random_id),
+        from_id: app.own_user_id().unwrap_or(0),
+        from_name: "You".into(),
+        from_photo: None,
+        text: upload_text(&kind, &titles),
+        kind: MessageKind::Text,
+        timestamp: chrono_timestamp(),
+        is_outgoing: true,
+        is_read: false,
+        is_edited: false,
+        is_pinned: false,
+        delivery: DeliveryStatus::Pending,
+        attachments: titles
+            .into_iter()
+            .map(|title| AttachmentInfo {
+                kind: kind.clone(),
+                title,
+                url: None,
+                thumbnail_url: None,
+                size: None,
+                subtitle: None,
+                description: None,
+                target_path: None,
+            })
+            .collect(),
+        reply: None,
+        fwd_count: 0,
+        forwards: Vec::new(),
+        reactions: Vec::new(),
+        upload: Some(UploadState::new(local_id)),
+    });
+    app.messages_scroll = app.messages.len().saturating_sub(1);
+    app.input.clear();
+    app.input_cursor = 0;
+    (local_id, random_id)
+}
+
+/// Text of a pending upload: "[file] a.pdf, b.pdf"
+fn upload_text(kind: &AttachmentKind, titles: &[String]) -> String {
+    let label = match kind {
+        AttachmentKind::Photo => "[image]",
+        _ => "[file]",
+    };
+    format!("{} {}", label, titles.join(", "))
+}
+
 fn handle_vk_event(app: &mut App, event: VkEvent) -> Option<Message> {
     match event {
         VkEvent::NewMessage {
@@ -1408,14 +2766,36 @@ fn handle_vk_event(app: &mut App, event: VkEvent) -> Option<Message> {
             text,
             from_id,
             is_outgoing,
+            has_attachments,
+            attachment_types,
+            action,
+            random_id,
         } => {
+            app.stats.record_message(peer_id, is_outgoing);
+            let unread = !is_outgoing && app.current_peer_id != Some(peer_id);
+            let preview =
+                vk_core::preview_text(&text, action.as_ref(), &attachment_types, from_id, |id| {
+                    app.get_user_name(id)
+                });
+            let from =
+                vk_core::preview_sender(peer_id, is_outgoing, from_id, |id| app.get_user_name(id));
+            let kind = MessageKind::of(action.as_ref(), &attachment_types);
+            app.record_chat_activity(peer_id, &preview, from, kind, timestamp, unread);
+            if let Some(action) = &action {
+                vk_core::apply_chat_action(&mut app.chats, peer_id, action);
+            }
+
             if app.current_peer_id == Some(peer_id) {
-                app.messages.push(ChatMessage {
+                let message = ChatMessage {
                     id: message_id,
                     cmid: None,
+                    random_id,
                     from_id,
                     from_name: app.get_user_name(from_id),
-                    text,
+                    from_photo: None,
+                    // A service message has no text of its own
+                    text: if action.is_some() { preview } else { text },
+                    kind,
                     timestamp,
                     is_outgoing,
                     is_read: true,
@@ -1426,11 +2806,53 @@ fn handle_vk_event(app: &mut App, event: VkEvent) -> Option<Message> {
                     reply: None,
                     fwd_count: 0,
                     forwards: Vec::new(),
-                });
-                app.messages_scroll = app.messages.len().saturating_sub(1);
+                    reactions: Vec::new(),
+                    upload: None,
+                };
+                // Not shown while the latest messages are out of the window;
+                // they are loaded again when scrolling down
+                let has_newer = app
+                    .messages_pagination
+                    .as_ref()
+                    .is_some_and(|p| p.has_newer);
+                if !has_newer {
+                    // Our own message may already be shown as a pending entry
+                    let old_len = app.messages.len();
+                    let idx = merge_incoming(&mut app.messages, message);
+                    if app.messages.len() > old_len {
+                        let anchored = anchor_insert(app.messages_scroll, old_len, idx, 1);
+                        app.messages_scroll = anchored.scroll;
+                        app.unseen_below += anchored.unseen;
+                    }
+                    if let Some(pagination) = &mut app.messages_pagination {
+                        trim_window(
+                            &mut app.messages,
+                            &mut app.messages_scroll,
+                            pagination,
+                            app.config.message_window(),
+                            Grown::Newer,
+                        );
+                    }
+                } else if !is_outgoing {
+                    // Below the window, but still counted on the jump hint
+                    app.unseen_below += 1;
+                }
+                if has_attachments {
+                    app.send_action(AsyncAction::FetchMessageById(message_id));
+                }
                 app.send_action(AsyncAction::MarkAsRead(peer_id));
-            } else if let Some(chat) = app.chats.iter_mut().find(|c| c.id == peer_id) {
-                chat.unread_count += 1;
+            }
+        }
+        VkEvent::MessageFlagsChanged {
+            peer_id,
+            flags,
+            set,
+            ..
+        } => {
+            // Restored after deletion: it is no longer in the list, reload
+            if !set && flags & FLAG_DELETED != 0 && app.current_peer_id == Some(peer_id) {
+                app.send_action(AsyncAction::LoadMessages(peer_id, 0));
+                app.status = Some("Message restored".into());
             }
         }
         VkEvent::MessageRead {
@@ -1456,12 +2878,31 @@ fn handle_vk_event(app: &mut App, event: VkEvent) -> Option<Message> {
                 }
             }
         }
+        VkEvent::ReactionsChanged { peer_id, cmid } => {
+            if app.current_peer_id == Some(peer_id)
+                && let Some(msg) = app.messages.iter().find(|m| m.cmid == Some(cmid))
+            {
+                app.send_action(AsyncAction::FetchMessageById(msg.id));
+            }
+        }
         VkEvent::MessageEditedFromLongPoll {
             peer_id,
             message_id,
+            new_text,
+            has_attachments,
         } => {
             if app.current_peer_id == Some(peer_id) {
-                app.send_action(AsyncAction::FetchMessageById(message_id));
+                // Text-only edits carry the new text; fetch the rest
+                let applied = app
+                    .messages
+                    .iter_mut()
+                    .find(|m| m.id == message_id)
+                    .is_some_and(|msg| {
+                        apply_remote_edit(msg, new_text.as_deref(), has_attachments)
+                    });
+                if !applied {
+                    app.send_action(AsyncAction::FetchMessageById(message_id));
+                }
                 app.status = Some("Message updated from web".into());
             }
         }
@@ -1486,6 +2927,12 @@ fn handle_vk_event(app: &mut App, event: VkEvent) -> Option<Message> {
             }
         }
         VkEvent::ConnectionStatus(connected) => {
+            app.stats.record_connection(connected);
+            app.connection = if connected {
+                ConnectionState::Connected
+            } else {
+                ConnectionState::Offline
+            };
             app.status = Some(if connected {
                 "Connected to VK".into()
             } else {
@@ -1496,9 +2943,136 @@ fn handle_vk_event(app: &mut App, event: VkEvent) -> Option<Message> {
     None
 }
 
-// command handling moved to commands.rs
+/// Run the `:grep` query of the search popup anew on the open chat
+fn start_grep(app: &mut App) {
+    let (Some(search), Some(peer_id)) = (&mut app.global_search, app.current_peer_id) else {
+        return;
+    };
+    search.results.clear();
+    search.selected = 0;
+    search.total_count = 0;
+    search.is_loading = false;
+    if search.query.is_empty() {
+        app.status = Some("Search in chat: (type to search, Esc to cancel)".into());
+        return;
+    }
+    if let Err(e) = GrepPattern::parse(&search.query) {
+        app.status = Some(e);
+        return;
+    }
+    search.is_loading = true;
+    let query = search.query.clone();
+    let title = app
+        .chats
+        .iter()
+        .find(|c| c.id == peer_id)
+        .map_or_else(|| peer_id.to_string(), |c| c.title.clone());
+    app.status = Some(format!("Searching offline: {}", query));
+    app.send_action(AsyncAction::GrepHistory(
+        peer_id,
+        title,
+        query,
+        app.messages.clone(),
+        app.cache.clone(),
+    ));
+}
+
+/// Open the chat of a search result and load history around the message
+fn open_search_hit(app: &mut App, result: &SearchResult) {
+    let peer_id = result.peer_id;
+    let message_id = result.message_id;
+
+    // Already loaded (`:grep` of the open chat): no need to refetch
+    if app.current_peer_id == Some(peer_id)
+        && let Some(pos) = app.messages.iter().position(|m| m.id == message_id)
+    {
+        app.messages_scroll = pos;
+        app.highlighted_message = Some((message_id, Instant::now() + JUMP_HIGHLIGHT));
+        app.status = app.search_hits.as_ref().map(|hits| {
+            format!(
+                "Result {} of {} (n/N: next/previous)",
+                hits.current + 1,
+                hits.results.len()
+            )
+        });
+        app.focus = Focus::Messages;
+        return;
+    }
+
+    app.current_peer_id = Some(peer_id);
+    if app.chat_filter.is_none()
+        && let Some(idx) = app.chats.iter().position(|c| c.id == peer_id)
+    {
+        app.selected_chat = idx;
+    }
+    load_around(app, peer_id, message_id);
+    app.send_action(AsyncAction::MarkAsRead(peer_id));
+    app.status = Some("Loading chat...".to_string());
+    app.focus = Focus::Messages;
+}
+
+/// Replace the messages of the open chat with those around `message_id`;
+/// it is selected and highlighted once they arrive
+fn load_around(app: &mut App, peer_id: i64, message_id: i64) {
+    app.messages.clear();
+    app.messages_scroll = 0;
+    app.unseen_below = 0;
+    app.highlighted_message = None;
+    app.target_message_id = Some(message_id);
+    app.is_loading = true;
+    let mut pagination = MessagesPagination::new(peer_id);
+    pagination.is_loading = true;
+    app.messages_pagination = Some(pagination);
+    app.send_action(AsyncAction::LoadMessagesAround(peer_id, message_id));
+}
+
+/// Select the loaded message at `pos` and highlight it
+fn show_message(app: &mut App, pos: usize) {
+    app.messages_scroll = pos;
+    app.highlighted_message = app
+        .messages
+        .get(pos)
+        .map(|m| (m.id, Instant::now() + JUMP_HIGHLIGHT));
+}
+
+/// Remember `point` for Ctrl+O, forgetting the oldest past the limit
+fn push_jump(app: &mut App, point: JumpPoint) {
+    if app.jump_list.len() == JUMP_LIST_LIMIT {
+        app.jump_list.remove(0);
+    }
+    app.jump_list.push(point);
+}
+
+/// Show the edit-conflict prompt for a message
+fn open_edit_conflict(app: &mut App, message_id: i64, local_text: String, server_text: String) {
+    let Some(peer_id) = app.current_peer_id else {
+        return;
+    };
+    let cmid = app
+        .messages
+        .iter()
+        .find(|m| m.id == message_id)
+        .and_then(|m| m.cmid);
+    app.edit_conflict = Some(EditConflict {
+        peer_id,
+        message_id,
+        cmid,
+        local_text,
+        server_text,
+    });
+    app.status = Some("Message changed elsewhere".into());
+}
+
+/// Leave edit mode and drop the pending edit text
+fn finish_editing(app: &mut App) {
+    if app.editing_message.take().is_some() {
+        app.input.clear();
+        app.input_cursor = 0;
+        app.mode = Mode::Normal;
+    }
+    app.edit_base_hash = None;
+}
 
-// Helpers moved from app.rs
 fn chrono_timestamp() -> i64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
@@ -1506,27 +3080,20 @@ fn chrono_timestamp() -> i64 {
         .as_secs() as i64
 }
 
-fn is_auth_error(msg: &str) -> bool {
-    msg.contains("VK API error 5")
-        || msg.contains("VK API error 7")
-        || msg.contains("VK API error 179")
-        || msg.to_lowercase().contains("authorization failed")
-}
-
 // Command parsing helpers for slash-commands
 #[derive(Debug, Clone)]
 enum SendCommand {
-    File(String),
-    Image(String),
+    File(Vec<String>),
+    Image(Vec<String>),
     ImageClipboard,
 }
 
 fn parse_send_command(input: &str) -> Option<SendCommand> {
     let trimmed = input.trim();
     if let Some(rest) = trimmed.strip_prefix("/sendfile ") {
-        let path = rest.trim().to_string();
-        if !path.is_empty() {
-            return Some(SendCommand::File(path));
+        let paths = upload::split_paths(rest);
+        if !paths.is_empty() {
+            return Some(SendCommand::File(paths));
         }
     }
     if let Some(rest) = trimmed.strip_prefix("/sendimg ") {
@@ -1534,8 +3101,9 @@ fn parse_send_command(input: &str) -> Option<SendCommand> {
         if arg == "--clipboard" {
             return Some(SendCommand::ImageClipboard);
         }
-        if !arg.is_empty() {
-            return Some(SendCommand::Image(arg.to_string()));
+        let paths = upload::split_paths(arg);
+        if !paths.is_empty() {
+            return Some(SendCommand::Image(paths));
         }
     }
     None
@@ -1577,17 +3145,63 @@ fn read_clipboard_image() -> anyhow::Result<std::path::PathBuf> {
     Ok(path)
 }
 
-fn first_url(msg: &ChatMessage) -> Option<String> {
-    extract_first_url(&msg.text).or_else(|| msg.attachments.iter().find_map(|a| a.url.clone()))
+/// Store a yank in the registers and, unless a register was named, on the
+/// system clipboard. `preview` describes it in the status bar.
+fn yank(app: &mut App, text: &str, preview: String) {
+    let register = app.pending_register.take();
+    app.registers.yank(register, text);
+    app.status = Some(match register {
+        Some(name) => format!("Yanked into \"{}: {}", name, preview),
+        None => match clipboard::copy(text) {
+            Ok(method) => format!("Copied ({}): {}", method.label(), preview),
+            Err(e) => format!("Yanked, but not copied: {}", e),
+        },
+    });
+}
+
+/// Loaded messages as "Name: text" lines, oldest first.
+fn conversation_text(messages: &[ChatMessage]) -> String {
+    messages
+        .iter()
+        .map(|msg| format!("{}: {}", msg.from_name, msg.text))
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
-fn extract_first_url(text: &str) -> Option<String> {
-    text.split_whitespace()
-        .find(|token| token.starts_with("http://") || token.starts_with("https://"))
-        .map(|s| {
-            s.trim_matches(|c: char| c.is_ascii_punctuation())
-                .to_string()
+/// Put a command in the command line with the cursor at its end, ready
+/// to run
+fn recall_command(app: &mut App, command: String) {
+    app.command_cursor = command.chars().count();
+    app.command_input = command;
+    app.completion_state = CompletionState::Inactive;
+}
+
+/// Actions of the message menu that apply to `msg`, the way their keys
+/// would check it.
+fn menu_actions(msg: &ChatMessage, now: i64) -> Vec<MenuAction> {
+    let sent = msg.id != 0 && !msg.is_service();
+    MenuAction::ALL
+        .into_iter()
+        .filter(|action| match action {
+            MenuAction::Reply | MenuAction::Forward | MenuAction::Delete | MenuAction::Pin => sent,
+            MenuAction::Edit => {
+                msg.is_outgoing
+                    && !msg.is_service()
+                    && (msg.id == 0 || is_editable(msg.timestamp, now))
+            }
+            MenuAction::Copy => !msg.text.is_empty(),
+            MenuAction::React => sent && msg.cmid.is_some(),
+            MenuAction::Download => msg.attachments.iter().any(|a| a.is_downloadable()),
+            MenuAction::OpenLink => !message_urls(msg).is_empty(),
         })
+        .collect()
+}
+
+fn open_url(app: &mut App, url: &str) {
+    app.status = Some(match open::that(url) {
+        Ok(()) => format!("Opened {}", url),
+        Err(e) => format!("Failed to open link: {}", e),
+    });
 }
 
 fn truncate_str(s: &str, max_len: usize) -> String {
@@ -1603,19 +3217,45 @@ fn truncate_str(s: &str, max_len: usize) -> String {
     }
 }
 
+/// Request profiles a picker is about to show but that are not loaded yet.
+fn warm_up_profiles(app: &mut App) {
+    let candidates = warmup_candidates(&app.chats, &app.messages, app.current_peer_id);
+    let missing = app
+        .profile_warmup
+        .request(candidates, |id| app.users.contains_key(&id));
+    if !missing.is_empty() {
+        app.send_action(AsyncAction::LoadUsers(missing));
+    }
+}
+
+/// Open the forward popup for `source_message_ids`, oldest first
+fn open_forward(app: &mut App, source_message_ids: Vec<i64>) {
+    let filtered = forward_filter(&app.chats, "");
+    warm_up_profiles(app);
+    app.forward = Some(crate::state::ForwardState {
+        source_message_ids,
+        query: String::new(),
+        filtered,
+        selected: 0,
+        comment: String::new(),
+        stage: ForwardStage::SelectTarget,
+    });
+    app.status = Some("Select chat to forward (j/k, type to search)".into());
+}
+
+/// Forward targets matching `query` like the chat filter, then chats
+/// whose peer id contains it.
 fn forward_filter(chats: &[Chat], query: &str) -> Vec<Chat> {
-    let q = query.trim().to_lowercase();
-    if q.is_empty() {
-        return chats.to_vec();
+    let q = query.trim();
+    let mut indices = vk_core::fuzzy::filter_chats(chats, q);
+    if !q.is_empty() {
+        for (idx, chat) in chats.iter().enumerate() {
+            if chat.id.to_string().contains(q) && !indices.contains(&idx) {
+                indices.push(idx);
+            }
+        }
     }
-    chats
-        .iter()
-        .filter(|c| {
-            let title = c.title.to_lowercase();
-            title.contains(&q) || c.id.to_string().contains(&q)
-        })
-        .cloned()
-        .collect()
+    indices.into_iter().map(|idx| chats[idx].clone()).collect()
 }
 
 pub fn flatten_forwards(